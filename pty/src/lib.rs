@@ -103,6 +103,12 @@ pub trait MasterPty: std::io::Write {
     /// slave end.  This is equivalent to the Write impl on MasterPty
     /// itself, but allows splitting it off into a separate object.
     fn try_clone_writer(&self) -> Result<Box<dyn std::io::Write + Send>, Error>;
+    /// If applicable to the type of the tty, returns the id of the
+    /// process group that is currently in the foreground of the tty.
+    /// This changes as the shell runs programs and as they exit.
+    fn process_group_leader(&self) -> Option<u32> {
+        None
+    }
 }
 
 /// Represents a child process spawned into the pty.
@@ -118,6 +124,12 @@ pub trait Child: std::fmt::Debug {
     /// Blocks execution until the child process has completed,
     /// yielding its exit status.
     fn wait(&mut self) -> IoResult<ExitStatus>;
+    /// If the child is a local process, returns its process id.
+    /// This allows the embedding application to learn of the exit
+    /// of the process asynchronously, rather than polling `try_wait`.
+    fn process_id(&self) -> Option<u32> {
+        None
+    }
 }
 
 /// Represents the slave side of a pty.
@@ -128,31 +140,49 @@ pub trait SlavePty {
 }
 
 /// Represents the exit status of a child process.
-/// This is rather anemic in the current version of this crate,
-/// holding only an indicator of success or failure.
 #[derive(Debug, Clone)]
 pub struct ExitStatus {
-    successful: bool,
+    code: u32,
 }
 
 impl ExitStatus {
     /// Construct an ExitStatus from a process return code
     pub fn with_exit_code(code: u32) -> Self {
-        Self {
-            successful: code == 0,
-        }
+        Self { code }
     }
 
     pub fn success(&self) -> bool {
-        self.successful
+        self.code == 0
+    }
+
+    /// Returns the exit code of the process.  A process that was
+    /// terminated by a signal is reported as 128 plus the signal
+    /// number, in the same way that the shell reports it.
+    pub fn exit_code(&self) -> u32 {
+        self.code
     }
 }
 
 impl From<std::process::ExitStatus> for ExitStatus {
     fn from(status: std::process::ExitStatus) -> ExitStatus {
-        ExitStatus {
-            successful: status.success(),
-        }
+        let code = match status.code() {
+            Some(code) => code as u32,
+            None => {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::process::ExitStatusExt;
+                    status
+                        .signal()
+                        .map(|signal| 128 + signal as u32)
+                        .unwrap_or(1)
+                }
+                #[cfg(not(unix))]
+                {
+                    1
+                }
+            }
+        };
+        ExitStatus { code }
     }
 }
 
@@ -188,6 +218,10 @@ impl Child for std::process::Child {
     fn wait(&mut self) -> IoResult<ExitStatus> {
         std::process::Child::wait(self).map(Into::into)
     }
+
+    fn process_id(&self) -> Option<u32> {
+        Some(self.id())
+    }
 }
 
 pub fn native_pty_system() -> Box<dyn PtySystem> {
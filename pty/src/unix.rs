@@ -248,6 +248,13 @@ impl MasterPty for UnixMasterPty {
         let fd = PtyFd(self.fd.try_clone()?);
         Ok(Box::new(UnixMasterPty { fd }))
     }
+
+    fn process_group_leader(&self) -> Option<u32> {
+        match unsafe { libc::tcgetpgrp(self.fd.as_raw_fd()) } {
+            pid if pid > 0 => Some(pid as u32),
+            _ => None,
+        }
+    }
 }
 
 impl Write for UnixMasterPty {
@@ -73,6 +73,15 @@ impl Child for WinChild {
             Err(IoError::last_os_error())
         }
     }
+
+    fn process_id(&self) -> Option<u32> {
+        let pid = unsafe { GetProcessId(self.proc.as_raw_handle()) };
+        if pid > 0 {
+            Some(pid)
+        } else {
+            None
+        }
+    }
 }
 
 impl std::future::Future for WinChild {
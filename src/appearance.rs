@@ -0,0 +1,370 @@
+//! Tracks whether the system prefers a dark or a light appearance, so
+//! that the `color_scheme_dark` and `color_scheme_light` settings can
+//! be followed.  Changes are subscribed to rather than polled for: the
+//! desktop portal's SettingChanged signal on unix systems, the
+//! appearance notification on macOS and the registry key on Windows.
+//! Outside of macOS the preference is only followed while one of those
+//! settings is configured, so systems that have no way to express it
+//! (such as servers, or X11 setups without the desktop portal) don't
+//! pay for, or log about, detection that can never succeed.
+//! The CycleAppearance key assignment overrides the system preference.
+#[cfg(not(target_os = "macos"))]
+use crate::config::configuration;
+#[cfg(not(target_os = "macos"))]
+use std::process::Command;
+use std::sync::atomic::{AtomicU8, Ordering};
+#[cfg(not(target_os = "macos"))]
+use std::time::{Duration, Instant};
+
+/// How often the configuration is checked for a setting that
+/// needs the system preference
+#[cfg(not(target_os = "macos"))]
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How long to wait before subscribing again after a failure; this
+/// doubles with each consecutive failure, up to MAX_RETRY_INTERVAL
+#[cfg(not(target_os = "macos"))]
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+#[cfg(not(target_os = "macos"))]
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Appearance {
+    Light,
+    Dark,
+}
+
+impl Appearance {
+    fn to_u8(appearance: Option<Appearance>) -> u8 {
+        match appearance {
+            None => 0,
+            Some(Appearance::Light) => 1,
+            Some(Appearance::Dark) => 2,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Appearance> {
+        match value {
+            1 => Some(Appearance::Light),
+            2 => Some(Appearance::Dark),
+            _ => None,
+        }
+    }
+}
+
+/// The most recently detected system preference
+static DETECTED: AtomicU8 = AtomicU8::new(0);
+/// Set by CycleAppearance; takes precedence over DETECTED
+static OVERRIDE: AtomicU8 = AtomicU8::new(0);
+
+/// Returns the appearance that the color scheme should follow, or
+/// None if neither the system nor the user has expressed a preference
+pub fn current() -> Option<Appearance> {
+    Appearance::from_u8(OVERRIDE.load(Ordering::Relaxed))
+        .or_else(|| Appearance::from_u8(DETECTED.load(Ordering::Relaxed)))
+}
+
+/// Cycles the override from following the system to dark, to light
+/// and back to following the system, and returns the new override
+pub fn cycle_override() -> Option<Appearance> {
+    let next = match Appearance::from_u8(OVERRIDE.load(Ordering::Relaxed)) {
+        None => Some(Appearance::Dark),
+        Some(Appearance::Dark) => Some(Appearance::Light),
+        Some(Appearance::Light) => None,
+    };
+    OVERRIDE.store(Appearance::to_u8(next), Ordering::Relaxed);
+    crate::config::notify_appearance_changed();
+    next
+}
+
+/// Records the system preference, causing the color scheme to
+/// be derived again if it changed
+fn set_detected(appearance: Option<Appearance>) {
+    let value = Appearance::to_u8(appearance);
+    if DETECTED.swap(value, Ordering::Relaxed) != value {
+        log::debug!("system appearance is now {:?}", appearance);
+        crate::config::notify_appearance_changed();
+    }
+}
+
+/// Follows the appearance notification that the application
+/// receives; it is cheap enough that there's no need to wait
+/// for the configuration to ask for it
+#[cfg(target_os = "macos")]
+pub fn start_watching() {
+    fn appearance(dark: bool) -> Option<Appearance> {
+        Some(if dark {
+            Appearance::Dark
+        } else {
+            Appearance::Light
+        })
+    }
+    set_detected(appearance(::window::os::macos::is_dark_appearance()));
+    ::window::os::macos::set_appearance_handler(|dark| set_detected(appearance(dark)));
+}
+
+/// Starts a thread that follows the system preference while the
+/// configuration asks for that.  Should the subscription fail, such
+/// as when the desktop portal isn't running yet, it is retried later.
+#[cfg(not(target_os = "macos"))]
+pub fn start_watching() {
+    std::thread::spawn(|| {
+        let mut retry_interval = RETRY_INTERVAL;
+        loop {
+            loop {
+                let config = configuration();
+                if config.color_scheme_dark.is_some() || config.color_scheme_light.is_some() {
+                    break;
+                }
+                std::thread::sleep(CONFIG_CHECK_INTERVAL);
+            }
+
+            let started = Instant::now();
+            if let Err(err) = watch() {
+                log::debug!(
+                    "unable to follow the system appearance, \
+                     trying again in {:?}: {:#}",
+                    retry_interval,
+                    err
+                );
+            }
+            if started.elapsed() > MAX_RETRY_INTERVAL {
+                // It was working for a while; this is a fresh failure
+                retry_interval = RETRY_INTERVAL;
+            }
+            std::thread::sleep(retry_interval);
+            retry_interval = (retry_interval * 2).min(MAX_RETRY_INTERVAL);
+        }
+    });
+}
+
+#[cfg(not(target_os = "macos"))]
+fn run(command: &mut Command) -> anyhow::Result<std::process::Output> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // Don't flash a console window each time we look
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    Ok(command.output()?)
+}
+
+/// Asks the desktop portal for the `color-scheme` setting
+#[cfg(all(unix, not(target_os = "macos")))]
+fn detect() -> anyhow::Result<Option<Appearance>> {
+    let output = run(Command::new("dbus-send").args(&[
+        "--session",
+        "--print-reply=literal",
+        "--reply-timeout=1000",
+        "--dest=org.freedesktop.portal.Desktop",
+        "/org/freedesktop/portal/desktop",
+        "org.freedesktop.portal.Settings.Read",
+        "string:org.freedesktop.appearance",
+        "string:color-scheme",
+    ]))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "the desktop portal didn't report the color-scheme: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(parse_portal_reply(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the reply printed by `dbus-send --print-reply=literal`,
+/// eg: `variant variant uint32 1`.  The value is 1 if dark is
+/// preferred, 2 if light is preferred and 0 if there's no preference.
+#[cfg(any(test, all(unix, not(target_os = "macos"))))]
+fn parse_portal_reply(reply: &str) -> Option<Appearance> {
+    match reply.split_whitespace().last()? {
+        "1" => Some(Appearance::Dark),
+        "2" => Some(Appearance::Light),
+        _ => None,
+    }
+}
+
+/// Reads the current preference and then follows the portal's
+/// SettingChanged signal; only returns once dbus-monitor, which
+/// subscribes to the signal on our behalf, can't be run or exits
+#[cfg(all(unix, not(target_os = "macos")))]
+fn watch() -> anyhow::Result<()> {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    set_detected(detect()?);
+
+    let mut child = Command::new("dbus-monitor")
+        .args(&[
+            "--session",
+            "type='signal',interface='org.freedesktop.portal.Settings',member='SettingChanged'",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("dbus-monitor has no stdout"))?;
+
+    let mut signals = SettingChangedParser::default();
+    for line in BufReader::new(stdout).lines() {
+        if let Some(appearance) = signals.feed(&line?) {
+            set_detected(appearance);
+        }
+    }
+    let status = child.wait()?;
+    anyhow::bail!("dbus-monitor exited: {}", status);
+}
+
+/// Picks the color-scheme changes out of the signals that
+/// `dbus-monitor` prints, which look like:
+///
+/// ```text
+/// signal time=1.2 sender=:1.30 -> destination=(null destination) serial=240 ...
+///    string "org.freedesktop.appearance"
+///    string "color-scheme"
+///    variant       uint32 1
+/// ```
+#[cfg(any(test, all(unix, not(target_os = "macos"))))]
+#[derive(Default)]
+struct SettingChangedParser {
+    /// The string arguments of the signal being printed
+    strings: Vec<String>,
+}
+
+#[cfg(any(test, all(unix, not(target_os = "macos"))))]
+impl SettingChangedParser {
+    /// Consumes a line of output, returning the new preference
+    /// when the line completes a change to the color-scheme
+    fn feed(&mut self, line: &str) -> Option<Option<Appearance>> {
+        let line = line.trim();
+        if line.starts_with("signal ") {
+            self.strings.clear();
+        } else if line.starts_with("string ") {
+            self.strings
+                .push(line["string ".len()..].trim_matches('"').to_string());
+        } else if line.starts_with("variant ")
+            && self.strings == ["org.freedesktop.appearance", "color-scheme"]
+        {
+            return Some(parse_portal_reply(line));
+        }
+        None
+    }
+}
+
+#[cfg(windows)]
+const PERSONALIZE_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Themes\Personalize";
+
+/// Reads the current preference each time a value of the
+/// Personalize registry key is set; only returns on error
+#[cfg(windows)]
+fn watch() -> anyhow::Result<()> {
+    use winapi::shared::minwindef::HKEY;
+    use winapi::um::winnt::{KEY_NOTIFY, REG_NOTIFY_CHANGE_LAST_SET};
+    use winapi::um::winreg::{
+        RegCloseKey, RegNotifyChangeKeyValue, RegOpenKeyExW, HKEY_CURRENT_USER,
+    };
+
+    struct Key(HKEY);
+    impl Drop for Key {
+        fn drop(&mut self) {
+            unsafe {
+                RegCloseKey(self.0);
+            }
+        }
+    }
+
+    let path: Vec<u16> = PERSONALIZE_KEY.encode_utf16().chain(Some(0)).collect();
+    let mut key = std::ptr::null_mut();
+    let res = unsafe { RegOpenKeyExW(HKEY_CURRENT_USER, path.as_ptr(), 0, KEY_NOTIFY, &mut key) };
+    if res != 0 {
+        anyhow::bail!("unable to open {}: error {}", PERSONALIZE_KEY, res);
+    }
+    let key = Key(key);
+
+    loop {
+        set_detected(detect()?);
+        // Blocks until a value of the key is set
+        let res = unsafe {
+            RegNotifyChangeKeyValue(
+                key.0,
+                0,
+                REG_NOTIFY_CHANGE_LAST_SET,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if res != 0 {
+            anyhow::bail!("unable to watch {}: error {}", PERSONALIZE_KEY, res);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn detect() -> anyhow::Result<Option<Appearance>> {
+    let key = format!(r"HKCU\{}", PERSONALIZE_KEY);
+    let output =
+        run(Command::new("reg").args(&["query", key.as_str(), "/v", "AppsUseLightTheme"]))?;
+    if !output.status.success() {
+        anyhow::bail!("AppsUseLightTheme is not set");
+    }
+    Ok(parse_reg_query(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the output of `reg query`, which includes a line such as
+/// `AppsUseLightTheme    REG_DWORD    0x0`
+#[cfg(any(test, windows))]
+fn parse_reg_query(output: &str) -> Option<Appearance> {
+    let line = output
+        .lines()
+        .find(|line| line.trim_start().starts_with("AppsUseLightTheme"))?;
+    match line.split_whitespace().last()? {
+        "0x0" => Some(Appearance::Dark),
+        _ => Some(Appearance::Light),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn portal_reply() {
+        assert_eq!(
+            parse_portal_reply("   variant       variant          uint32 1\n"),
+            Some(Appearance::Dark)
+        );
+        assert_eq!(
+            parse_portal_reply("variant variant uint32 2"),
+            Some(Appearance::Light)
+        );
+        assert_eq!(parse_portal_reply("variant variant uint32 0"), None);
+        assert_eq!(parse_portal_reply(""), None);
+    }
+
+    #[test]
+    fn reg_query() {
+        let output = "\r\nHKEY_CURRENT_USER\\Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize\r\n    AppsUseLightTheme    REG_DWORD    0x0\r\n\r\n";
+        assert_eq!(parse_reg_query(output), Some(Appearance::Dark));
+        assert_eq!(
+            parse_reg_query(&output.replace("0x0", "0x1")),
+            Some(Appearance::Light)
+        );
+        assert_eq!(parse_reg_query("nothing here"), None);
+    }
+
+    #[test]
+    fn setting_changed() {
+        let output = "signal time=1.1 sender=org.freedesktop.DBus -> destination=:1.9 serial=2 path=/org/freedesktop/DBus; interface=org.freedesktop.DBus; member=NameAcquired\n   string \":1.9\"\n\
+                      signal time=1.2 sender=:1.30 -> destination=(null destination) serial=240 path=/org/freedesktop/portal/desktop; interface=org.freedesktop.portal.Settings; member=SettingChanged\n   string \"org.gnome.desktop.interface\"\n   string \"gtk-theme\"\n   variant       string \"Adwaita\"\n\
+                      signal time=1.3 sender=:1.30 -> destination=(null destination) serial=241 path=/org/freedesktop/portal/desktop; interface=org.freedesktop.portal.Settings; member=SettingChanged\n   string \"org.freedesktop.appearance\"\n   string \"color-scheme\"\n   variant       uint32 1\n\
+                      signal time=1.4 sender=:1.30 -> destination=(null destination) serial=242 path=/org/freedesktop/portal/desktop; interface=org.freedesktop.portal.Settings; member=SettingChanged\n   string \"org.freedesktop.appearance\"\n   string \"color-scheme\"\n   variant       uint32 0\n";
+        let mut parser = SettingChangedParser::default();
+        let changes: Vec<Option<Appearance>> = output
+            .lines()
+            .filter_map(|line| parser.feed(line))
+            .collect();
+        assert_eq!(changes, vec![Some(Appearance::Dark), None]);
+    }
+}
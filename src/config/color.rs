@@ -1,8 +1,92 @@
 use crate::config::*;
+use serde::{Deserializer, Serializer};
 use termwiz::cell::CellAttributes;
 use termwiz::color::{ColorSpec, RgbColor};
+use wezterm_term::color::ColorPalette;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// The color of selected text: either a color, or "auto", in which
+/// case the text keeps its own color unless that would be hard to
+/// read over the selection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionForeground {
+    Auto,
+    Color(RgbColor),
+}
+
+impl Serialize for SelectionForeground {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            SelectionForeground::Auto => serializer.serialize_str("auto"),
+            SelectionForeground::Color(color) => color.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SelectionForeground {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(SelectionForeground::Auto);
+        }
+        RgbColor::from_named_or_rgb_string(&s)
+            .map(SelectionForeground::Color)
+            .ok_or_else(|| format!("unknown color name: {}", s))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A color with an opacity, which is specified as `#RRGGBBAA`.
+/// Any of the forms accepted for other colors may also be used, and
+/// produce an opaque color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RgbaColor {
+    pub color: RgbColor,
+    pub alpha: u8,
+}
+
+impl RgbaColor {
+    pub fn from_rgba_str(s: &str) -> Option<Self> {
+        if s.len() == 9 && s.starts_with('#') {
+            let alpha = u8::from_str_radix(s.get(7..)?, 16).ok()?;
+            let color = RgbColor::from_rgb_str(s.get(..7)?)?;
+            return Some(Self { color, alpha });
+        }
+        RgbColor::from_named_or_rgb_string(s).map(|color| Self { color, alpha: 0xff })
+    }
+}
+
+impl Serialize for RgbaColor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = format!(
+            "#{:02x}{:02x}{:02x}{:02x}",
+            self.color.red, self.color.green, self.color.blue, self.alpha
+        );
+        s.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RgbaColor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        RgbaColor::from_rgba_str(&s)
+            .ok_or_else(|| format!("unknown color name: {}", s))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Palette {
     /// The text color to use when the attributes are reset to default
     pub foreground: Option<RgbColor>,
@@ -12,9 +96,12 @@ pub struct Palette {
     pub cursor_fg: Option<RgbColor>,
     pub cursor_bg: Option<RgbColor>,
     pub cursor_border: Option<RgbColor>,
-    /// The color of selected text
-    pub selection_fg: Option<RgbColor>,
-    pub selection_bg: Option<RgbColor>,
+    /// The color of selected text, or "auto" to keep the color of
+    /// the text where it contrasts well enough with the selection
+    pub selection_fg: Option<SelectionForeground>,
+    /// The color of the selection.  If it is partially transparent,
+    /// it is blended over the background of the selected text.
+    pub selection_bg: Option<RgbaColor>,
     /// A list of 8 colors corresponding to the basic ANSI palette
     pub ansi: Option<[RgbColor; 8]>,
     /// A list of 8 colors corresponding to bright versions of the
@@ -43,9 +130,16 @@ impl From<Palette> for wezterm_term::color::ColorPalette {
         apply_color!(cursor_fg);
         apply_color!(cursor_bg);
         apply_color!(cursor_border);
-        apply_color!(selection_fg);
-        apply_color!(selection_bg);
         apply_color!(scrollbar_thumb);
+        match cfg.selection_fg {
+            Some(SelectionForeground::Auto) => p.selection_fg_auto = true,
+            Some(SelectionForeground::Color(color)) => p.selection_fg = color,
+            None => {}
+        }
+        if let Some(RgbaColor { color, alpha }) = cfg.selection_bg {
+            p.selection_bg = color;
+            p.selection_bg_alpha = alpha;
+        }
 
         if let Some(ansi) = cfg.ansi {
             for (idx, col) in ansi.iter().enumerate() {
@@ -116,9 +210,48 @@ pub struct TabBarColors {
     /// Styling for an inactive tab with a mouse hovering
     #[serde(default = "default_inactive_tab_hover")]
     pub inactive_tab_hover: TabBarColor,
+
+    /// The color of the strip along the bottom of a tab that shows
+    /// the progress reported by the application via OSC 9;4
+    #[serde(default = "default_progress")]
+    pub progress: RgbColor,
+
+    /// The color of the progress strip when the application reports
+    /// that the operation failed
+    #[serde(default = "default_progress_error")]
+    pub progress_error: RgbColor,
+
+    /// The color of the progress strip when the application reports
+    /// that the operation is paused
+    #[serde(default = "default_progress_paused")]
+    pub progress_paused: RgbColor,
+
+    /// Styling for the status text at the right of the tab bar
+    #[serde(default = "default_status")]
+    pub status: TabBarColor,
 }
 impl_lua_conversion!(TabBarColors);
 
+fn default_progress() -> RgbColor {
+    RgbColor::new(0x4e, 0x9a, 0x06)
+}
+
+fn default_progress_error() -> RgbColor {
+    RgbColor::new(0xcc, 0x00, 0x00)
+}
+
+fn default_progress_paused() -> RgbColor {
+    RgbColor::new(0xc4, 0xa0, 0x00)
+}
+
+fn default_status() -> TabBarColor {
+    TabBarColor {
+        bg_color: default_background(),
+        fg_color: RgbColor::new(0xc0, 0xc0, 0xc0),
+        ..TabBarColor::default()
+    }
+}
+
 fn default_background() -> RgbColor {
     RgbColor::new(0x0b, 0x00, 0x22)
 }
@@ -153,7 +286,61 @@ impl Default for TabBarColors {
             inactive_tab: default_inactive_tab(),
             inactive_tab_hover: default_inactive_tab_hover(),
             active_tab: default_active_tab(),
+            progress: default_progress(),
+            progress_error: default_progress_error(),
+            progress_paused: default_progress_paused(),
+            status: default_status(),
+        }
+    }
+}
+
+/// Colors that apply to an individual tab, taking precedence over
+/// the colors from the configuration.  This is useful for making
+/// tabs that are connected to sensitive remote hosts stand out.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct TabColorOverrides {
+    /// The name of the color scheme to use in place of `color_scheme`
+    #[serde(default)]
+    pub color_scheme: Option<String>,
+    /// The background color to use in place of that from the color
+    /// scheme.  This is also used for the tab's entry in the tab bar.
+    #[serde(default)]
+    pub background: Option<RgbColor>,
+}
+impl_lua_conversion!(TabColorOverrides);
+
+impl TabColorOverrides {
+    /// Replace the values in self with any that are set in `other`
+    pub fn merge_from(&mut self, other: &TabColorOverrides) {
+        if let Some(scheme) = other.color_scheme.as_ref() {
+            self.color_scheme.replace(scheme.clone());
+        }
+        if let Some(background) = other.background {
+            self.background.replace(background);
+        }
+    }
+
+    /// Returns the palette of a tab with these overrides applied.
+    /// `scheme_palette` resolves the name of a color scheme to its
+    /// palette, and `tab_palette` returns the palette of the tab, which
+    /// is only needed when no color scheme applies.
+    pub fn apply_to_palette(
+        &self,
+        scheme_palette: impl FnOnce(&str) -> Option<ColorPalette>,
+        tab_palette: impl FnOnce() -> ColorPalette,
+    ) -> ColorPalette {
+        let mut palette = match self
+            .color_scheme
+            .as_ref()
+            .and_then(|name| scheme_palette(name))
+        {
+            Some(palette) => palette,
+            None => tab_palette(),
+        };
+        if let Some(background) = self.background {
+            palette.background = background;
         }
+        palette
     }
 }
 
@@ -163,3 +350,30 @@ pub struct ColorSchemeFile {
     pub colors: Palette,
 }
 impl_lua_conversion!(ColorSchemeFile);
+
+/// Adjusts the hue, saturation and brightness of the background image
+/// by multiplying each of them by the corresponding value
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct HsbTransform {
+    #[serde(default = "default_hsb_multiplier")]
+    pub hue: f32,
+    #[serde(default = "default_hsb_multiplier")]
+    pub saturation: f32,
+    #[serde(default = "default_hsb_multiplier")]
+    pub brightness: f32,
+}
+impl_lua_conversion!(HsbTransform);
+
+fn default_hsb_multiplier() -> f32 {
+    1.0
+}
+
+impl Default for HsbTransform {
+    fn default() -> Self {
+        Self {
+            hue: 1.0,
+            saturation: 1.0,
+            brightness: 1.0,
+        }
+    }
+}
@@ -8,6 +8,13 @@ const FONT_FAMILY: &str = "Consolas";
 #[cfg(all(not(target_os = "macos"), not(windows)))]
 const FONT_FAMILY: &str = "monospace";
 
+/// The smallest font size, in points, that the font size controls and
+/// the applications running in a tab are able to select
+pub const MIN_FONT_SIZE: f64 = 2.0;
+/// The largest font size, in points, that the font size controls and
+/// the applications running in a tab are able to select
+pub const MAX_FONT_SIZE: f64 = 256.0;
+
 #[derive(Debug, Copy, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
 pub enum FontHinting {
     /// No hinting is performed
@@ -43,9 +50,29 @@ impl Default for FontAntiAliasing {
     }
 }
 
+/// Controls how characters that are not present in any of the
+/// configured fonts are rendered
+#[derive(Debug, Copy, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
+pub enum NotdefGlyphStyle {
+    /// A hollow box containing the hexadecimal codepoint
+    HexBox,
+    /// A hollow box
+    PlainBox,
+    /// Nothing is rendered
+    Blank,
+}
+impl_lua_conversion!(NotdefGlyphStyle);
+
+impl Default for NotdefGlyphStyle {
+    fn default() -> Self {
+        Self::HexBox
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct FontAttributes {
-    /// The font family name
+    /// The font family name.  This is ignored when `file` is set.
+    #[serde(default)]
     pub family: String,
     /// Whether the font should be a bold variant
     #[serde(default)]
@@ -53,6 +80,20 @@ pub struct FontAttributes {
     /// Whether the font should be an italic variant
     #[serde(default)]
     pub italic: bool,
+    /// Load the font from this file rather than locating it by its
+    /// family name.  Relative paths are resolved relative to the
+    /// directory containing the configuration file.
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// When `file` is a font collection (eg: a `.ttc` file), the 0-based
+    /// index of the font within that collection
+    #[serde(default)]
+    pub index: Option<u32>,
+    /// Whether glyphs that are too large for their cells should be
+    /// scaled down to fit, rather than clipped.  If unspecified,
+    /// this is true for fonts with color glyphs, such as emoji fonts.
+    #[serde(default)]
+    pub scale_to_cell: Option<bool>,
 }
 impl_lua_conversion!(FontAttributes);
 
@@ -62,6 +103,9 @@ impl FontAttributes {
             family: family.into(),
             bold: false,
             italic: false,
+            file: None,
+            index: None,
+            scale_to_cell: None,
         }
     }
 }
@@ -72,6 +116,9 @@ impl Default for FontAttributes {
             family: FONT_FAMILY.into(),
             bold: false,
             italic: false,
+            file: None,
+            index: None,
+            scale_to_cell: None,
         }
     }
 }
@@ -87,6 +134,14 @@ pub struct TextStyle {
     /// useful in a `[[font_rules]]` section to implement changing
     /// the text color for eg: bold text.
     pub foreground: Option<RgbColor>,
+
+    /// Additional harfbuzz features to apply when shaping text in this
+    /// style, using the same syntax as the global `harfbuzz_features`
+    /// option.  These are applied after the global features, and the
+    /// features of a `font_rules` style are applied after those of the
+    /// `font` style, so that a rule can override the base style.
+    #[serde(default)]
+    pub harfbuzz_features: Vec<String>,
 }
 impl_lua_conversion!(TextStyle);
 
@@ -95,15 +150,29 @@ impl Default for TextStyle {
         Self {
             foreground: None,
             font: vec![FontAttributes::default()],
+            harfbuzz_features: vec![],
         }
     }
 }
 
 impl TextStyle {
+    /// Make a version of this style that doesn't form ligatures.
+    /// Its features differ from those of this style, so the fonts that
+    /// are loaded for it and the text that is shaped with it are cached
+    /// separately.
+    pub fn make_without_ligatures(&self) -> Self {
+        let mut style = self.clone();
+        style
+            .harfbuzz_features
+            .extend(["calt=0", "clig=0", "liga=0"].iter().map(|s| s.to_string()));
+        style
+    }
+
     /// Make a version of this style with bold enabled.
     pub fn make_bold(&self) -> Self {
         Self {
             foreground: self.foreground,
+            harfbuzz_features: self.harfbuzz_features.clone(),
             font: self
                 .font
                 .iter()
@@ -120,6 +189,7 @@ impl TextStyle {
     pub fn make_italic(&self) -> Self {
         Self {
             foreground: self.foreground,
+            harfbuzz_features: self.harfbuzz_features.clone(),
             font: self
                 .font
                 .iter()
@@ -168,6 +238,67 @@ impl TextStyle {
     }
 }
 
+/// A font that applies to an individual tab, taking precedence over
+/// the font from the configuration.  This is useful for tabs that are
+/// used for presentations, or that display logs.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct TabFontOverride {
+    /// The font to use in place of `font`.  It also replaces the
+    /// families of the `font_rules` styles that were derived from
+    /// `font`, such as the default bold and italic styles.
+    #[serde(default)]
+    pub font: Option<TextStyle>,
+    /// The size to use in place of `font_size`
+    #[serde(default)]
+    pub font_size: Option<f64>,
+}
+impl_lua_conversion!(TabFontOverride);
+
+impl TabFontOverride {
+    /// Replace the values in self with any that are set in `other`
+    pub fn merge_from(&mut self, other: &TabFontOverride) {
+        if let Some(font) = other.font.as_ref() {
+            self.font.replace(font.clone());
+        }
+        if let Some(font_size) = other.font_size {
+            self.font_size.replace(font_size);
+        }
+    }
+
+    /// Applies a user variable set by the application in the tab with
+    /// OSC 1337 SetUserVar.  `WEZTERM_FONT` sets the font family and
+    /// `WEZTERM_FONT_SIZE` sets the size; an empty value reverts to the
+    /// configured font or size.  The size is clamped to the range
+    /// `MIN_FONT_SIZE..=MAX_FONT_SIZE`.  Returns false if `name` is not
+    /// one of those variables, or the size is not a positive number.
+    pub fn apply_user_var(&mut self, name: &str, value: &str) -> bool {
+        let value = value.trim();
+        match name {
+            "WEZTERM_FONT" if value.is_empty() => {
+                self.font.take();
+            }
+            "WEZTERM_FONT" => {
+                self.font.replace(TextStyle {
+                    font: vec![FontAttributes::new(value)],
+                    ..TextStyle::default()
+                });
+            }
+            "WEZTERM_FONT_SIZE" if value.is_empty() => {
+                self.font_size.take();
+            }
+            "WEZTERM_FONT_SIZE" => match value.parse::<f64>() {
+                Ok(size) if size > 0. && size.is_finite() => {
+                    self.font_size
+                        .replace(size.max(MIN_FONT_SIZE).min(MAX_FONT_SIZE));
+                }
+                _ => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
 /// Defines a rule that can be used to select a `TextStyle` given
 /// an input `CellAttributes` value.  The logic that applies the
 /// matching can be found in src/font/mod.rs.  The concept is that
@@ -209,6 +340,45 @@ pub struct StyleRule {
     pub invisible: Option<bool>,
 
     /// When this rule matches, `font` specifies the styling to be used.
+    /// Any parts of the style that are not specified are inherited
+    /// from the base `font` configuration; see `inherit_base_style`.
+    #[serde(default = "default_rule_style")]
     pub font: TextStyle,
 }
 impl_lua_conversion!(StyleRule);
+
+/// The style of a rule that doesn't specify one; everything is
+/// inherited from the base font configuration
+fn default_rule_style() -> TextStyle {
+    TextStyle {
+        font: vec![],
+        foreground: None,
+        harfbuzz_features: vec![],
+    }
+}
+
+impl StyleRule {
+    /// Fills in the parts of the style of this rule that were left
+    /// unspecified using the `base` style.  If the rule doesn't list
+    /// any fonts then it uses those of the `base` style, made bold
+    /// and/or italic when the rule matches on those attributes,
+    /// so that a rule that only changes eg: the foreground color
+    /// doesn't lose the bold or italic styling of the text.
+    /// The harfbuzz features are merged when the font is resolved,
+    /// so they are not copied here.
+    pub fn inherit_base_style(&mut self, base: &TextStyle) {
+        if self.font.font.is_empty() {
+            let mut font = base.clone();
+            if self.intensity == Some(wezterm_term::Intensity::Bold) {
+                font = font.make_bold();
+            }
+            if self.italic == Some(true) {
+                font = font.make_italic();
+            }
+            self.font.font = font.font;
+        }
+        if self.font.foreground.is_none() {
+            self.font.foreground = base.foreground;
+        }
+    }
+}
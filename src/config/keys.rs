@@ -12,6 +12,42 @@ pub struct Key {
 }
 impl_lua_conversion!(Key);
 
+/// A key that arms the bindings whose mods include `LEADER`.
+/// The key pressed after it, within the timeout, is looked up in
+/// those bindings.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LeaderKey {
+    #[serde(deserialize_with = "de_keycode")]
+    pub key: KeyCode,
+    #[serde(deserialize_with = "de_modifiers", default)]
+    pub mods: Modifiers,
+    #[serde(default = "default_leader_timeout", alias = "timeout_ms")]
+    pub timeout_milliseconds: u64,
+    /// If true, the leader key is sent to the tab when the key that
+    /// follows it doesn't match a LEADER binding, or when the timeout
+    /// expires.  Otherwise it is swallowed.
+    #[serde(default)]
+    pub send_leader_when_unmatched: bool,
+}
+impl_lua_conversion!(LeaderKey);
+
+/// A named set of key assignments that is activated by the
+/// ActivateKeyTable assignment, for modal keymaps
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct KeyTable {
+    #[serde(default)]
+    pub keys: Vec<Key>,
+    /// If true, keys that aren't in the table are handled as usual
+    /// while it is active.  Otherwise they are swallowed.
+    #[serde(default)]
+    pub pass_through_unmatched: bool,
+}
+impl_lua_conversion!(KeyTable);
+
+fn default_leader_timeout() -> u64 {
+    1000
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Mouse {
     pub event: MouseEventTrigger,
@@ -139,7 +175,7 @@ where
     }
 }
 
-fn de_modifiers<'de, D>(deserializer: D) -> Result<Modifiers, D::Error>
+pub(crate) fn de_modifiers<'de, D>(deserializer: D) -> Result<Modifiers, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -154,6 +190,8 @@ where
             mods |= Modifiers::CTRL;
         } else if ele == "SUPER" || ele == "CMD" || ele == "WIN" {
             mods |= Modifiers::SUPER;
+        } else if ele == "LEADER" {
+            mods |= Modifiers::LEADER;
         } else if ele == "NONE" || ele == "" {
             mods |= Modifiers::NONE;
         } else {
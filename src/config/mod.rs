@@ -1,5 +1,6 @@
 //! Configuration for the gui portion of the terminal
 
+use crate::appearance::Appearance;
 use crate::create_user_owned_dirs;
 use crate::font::locator::FontLocatorSelection;
 use crate::font::rasterizer::FontRasterizerSelection;
@@ -11,7 +12,7 @@ use lazy_static::lazy_static;
 use portable_pty::{CommandBuilder, PtySize};
 use serde::{Deserialize, Serialize};
 use std;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io::prelude::*;
@@ -45,6 +46,86 @@ lazy_static! {
     pub static ref CONFIG_DIR: PathBuf = xdg_config_home();
     pub static ref RUNTIME_DIR: PathBuf = compute_runtime_dir().unwrap();
     static ref CONFIG: Configuration = Configuration::new();
+    static ref SOURCES: Mutex<ConfigSources> = Mutex::new(ConfigSources::default());
+}
+
+/// Where the configuration is loaded from, as directed by the
+/// command line.  These are kept so that they are applied again
+/// each time that the configuration is reloaded.
+#[derive(Default, Clone)]
+struct ConfigSources {
+    /// The file named by `--config-file`, which is used instead of
+    /// searching for one
+    file: Option<PathBuf>,
+    /// Use the defaults rather than loading a file
+    skip_file: bool,
+    /// The `--config name=value` overrides, in the order given
+    overrides: Vec<(String, String)>,
+}
+
+/// Directs the configuration to be loaded from `file` rather than
+/// from the usual locations, or from the defaults if `skip_file` is
+/// true, and to have `overrides` applied over it.  This must be
+/// called before the configuration is first loaded.
+pub fn set_config_sources(
+    file: Option<PathBuf>,
+    skip_file: bool,
+    overrides: Vec<(String, String)>,
+) {
+    *SOURCES.lock().unwrap() = ConfigSources {
+        file,
+        skip_file,
+        overrides,
+    };
+}
+
+/// Parses a `--config name=value` override.  The value is a lua
+/// expression, so strings must be quoted: `color_scheme="Batman"`.
+pub fn parse_config_override(s: &str) -> anyhow::Result<(String, String)> {
+    let eq = s
+        .find('=')
+        .ok_or_else(|| anyhow!("expected name=value, but `{}` has no `=`", s))?;
+    let name = s[..eq].trim();
+    let value = s[eq + 1..].trim();
+    let valid_name = name
+        .chars()
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        bail!("`{}` is not the name of a config option", name);
+    }
+    if value.is_empty() {
+        bail!("no value was given for `{}`", name);
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Evaluates the `--config` overrides and assigns them to the
+/// `config` table, which was returned by the config file or is nil
+/// when there is no file
+fn apply_overrides<'lua>(
+    lua: &'lua mlua::Lua,
+    config: mlua::Value<'lua>,
+    overrides: &[(String, String)],
+) -> anyhow::Result<mlua::Value<'lua>> {
+    if overrides.is_empty() {
+        return Ok(config);
+    }
+    let table = match config {
+        mlua::Value::Table(table) => table,
+        mlua::Value::Nil => lua.create_table()?,
+        _ => bail!("--config overrides can only be applied to a config that is a table"),
+    };
+    for (name, value) in overrides {
+        let value: mlua::Value = lua
+            .load(value)
+            .set_name(format!("--config {}", name).as_bytes())?
+            .eval()
+            .with_context(|| format!("Error evaluating --config {}={}", name, value))?;
+        table.set(name.as_str(), value)?;
+    }
+    Ok(mlua::Value::Table(table))
 }
 
 fn xdg_config_home() -> PathBuf {
@@ -70,6 +151,82 @@ pub fn reload() {
     CONFIG.reload();
 }
 
+/// The config file that is created when there isn't one to edit
+const SKELETON: &str = include_str!("skeleton.lua");
+const TOML_SKELETON: &str = "# This is your wezterm configuration file.  wezterm watches it and\n\
+                             # applies your changes as soon as you save them.\n";
+
+/// Returns the path of the config file, for opening it in an editor.
+/// When there is no config file yet, a skeleton that consists of
+/// commented out examples is created first, and the configuration is
+/// reloaded so that the new file is watched for changes.
+pub fn config_file_for_editing() -> anyhow::Result<PathBuf> {
+    let explicit = SOURCES.lock().unwrap().file.clone();
+    let path = match explicit {
+        Some(path) => path,
+        None => match Config::config_file_paths().into_iter().find(|p| p.exists()) {
+            Some(path) => path,
+            None => std::env::var_os("WEZTERM_CONFIG_FILE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| HOME_DIR.join(".wezterm.lua")),
+        },
+    };
+    if path.exists() {
+        return Ok(path);
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("creating the directory {}", parent.display()))?;
+    }
+    let skeleton = if path.extension() == Some(OsStr::new("toml")) {
+        TOML_SKELETON
+    } else {
+        SKELETON
+    };
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(skeleton.as_bytes()))
+        .with_context(|| format!("creating the config file {}", path.display()))?;
+    log::info!("created the config file {}", path.display());
+    reload();
+    Ok(path)
+}
+
+/// Returns the command line of the editor in which to open the config
+/// file: the `config_file_editor` option, else `$VISUAL`, else `$EDITOR`,
+/// else a basic editor that is likely to be installed
+fn editor_command(
+    configured: Option<&Vec<String>>,
+    visual: Option<String>,
+    editor: Option<String>,
+) -> Vec<String> {
+    if let Some(args) = configured.filter(|args| !args.is_empty()) {
+        return args.clone();
+    }
+    // Like git, allow the variables to include arguments,
+    // such as "code --wait"
+    let from_env = visual
+        .into_iter()
+        .chain(editor)
+        .map(|value| {
+            value
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .find(|args| !args.is_empty());
+    from_env.unwrap_or_else(|| vec![if cfg!(windows) { "notepad.exe" } else { "vi" }.to_string()])
+}
+
+/// Called when the appearance that the color scheme follows has
+/// changed, so that the configuration is applied again
+pub fn notify_appearance_changed() {
+    CONFIG.bump_generation();
+}
+
 /// If there was an error loading the preferred configuration,
 /// return it, otherwise return the current configuration
 pub fn configuration_result() -> Result<ConfigHandle, Error> {
@@ -154,10 +311,19 @@ impl ConfigInner {
     fn reload(&mut self) {
         match Config::load() {
             Ok((config, path)) => {
+                let changes = ConfigChanges::between(&self.config, &config);
                 self.config = Arc::new(config);
                 self.error.take();
                 self.generation += 1;
-                log::debug!("Reloaded configuration! generation={}", self.generation);
+                if self.generation > 1 {
+                    log::info!(
+                        "config reloaded: generation={} changed={}",
+                        self.generation,
+                        changes
+                    );
+                } else {
+                    log::debug!("Loaded configuration! generation={}", self.generation);
+                }
                 if let Some(path) = path {
                     self.watch_path(path);
                 }
@@ -215,6 +381,13 @@ impl Configuration {
         inner.reload();
     }
 
+    /// Causes consumers of the configuration to derive their state
+    /// from it again, without reloading it
+    pub fn bump_generation(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.generation += 1;
+    }
+
     /// Returns a copy of any captured error message.
     /// The error message is not cleared.
     pub fn get_error(&self) -> Option<String> {
@@ -254,12 +427,137 @@ impl std::ops::Deref for ConfigHandle {
     }
 }
 
+/// The groups of options that differ between two configurations, so
+/// that a reload only redoes the work that depends on what changed;
+/// in particular, changing only the colors mustn't discard the fonts
+/// and the glyphs rendered from them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigChanges {
+    pub colors: bool,
+    /// Options that affect how glyphs are rendered or the size of
+    /// the cells
+    pub fonts: bool,
+    /// Options that the key and mouse assignments are built from
+    pub keys: bool,
+    pub other: bool,
+}
+
+/// Evaluates to true if any of the fields differ.  Not all of the
+/// types within the config can be compared, but they all implement
+/// Debug, and the maps are ordered, so the output is deterministic.
+macro_rules! fields_differ {
+    ($old:expr, $new:expr, $($field:ident),+) => {
+        false $(|| format!("{:?}", $old.$field) != format!("{:?}", $new.$field))+
+    };
+}
+
+/// Copies the fields from `$from` to `$to`
+macro_rules! copy_fields {
+    ($from:expr, $to:expr, $($field:ident),+) => {
+        $($to.$field = $from.$field.clone();)+
+    };
+}
+
+macro_rules! config_groups {
+    ($($group:ident: [$($field:ident),+ $(,)?],)+) => {
+        impl ConfigChanges {
+            pub fn between(old: &Config, new: &Config) -> Self {
+                // Everything outside of the groups is compared at once,
+                // by comparing the configs after making the fields of
+                // the groups the same
+                let mut rest = new.clone();
+                $(copy_fields!(old, rest, $($field),+);)+
+                Self {
+                    $($group: fields_differ!(old, new, $($field),+),)+
+                    other: format!("{:?}", old) != format!("{:?}", rest),
+                }
+            }
+        }
+    };
+}
+
+config_groups! {
+    colors: [
+        colors,
+        color_scheme,
+        color_schemes,
+        color_scheme_dirs,
+        color_scheme_dark,
+        color_scheme_light,
+    ],
+    fonts: [
+        font_size,
+        line_height,
+        cell_width,
+        dpi,
+        font_dirs,
+        font,
+        font_rules,
+        font_locator,
+        font_rasterizer,
+        font_shaper,
+        font_hinting,
+        font_antialias,
+        notdef_glyph_style,
+        harfbuzz_features,
+        allow_square_glyphs_to_overflow,
+        snap_powerline_glyphs_to_cell,
+        clip_glyphs_to_cells,
+        cursor_breaks_ligatures,
+    ],
+    keys: [
+        keys,
+        disable_default_key_bindings,
+        mouse_bindings,
+        disable_default_mouse_bindings,
+        leader,
+        key_tables,
+    ],
+}
+
+impl ConfigChanges {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl std::fmt::Display for ConfigChanges {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let groups = [
+            ("colors", self.colors),
+            ("fonts", self.fonts),
+            ("keys", self.keys),
+            ("other", self.other),
+        ];
+        let changed: Vec<&str> = groups
+            .iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| *name)
+            .collect();
+        if changed.is_empty() {
+            write!(f, "nothing")
+        } else {
+            write!(f, "{}", changed.join(","))
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     /// The font size, measured in points
     #[serde(default = "default_font_size")]
     pub font_size: f64,
 
+    /// Scales the height of the character cell computed from the
+    /// font metrics; 1.2 adds 20% of additional space between lines
+    #[serde(default = "default_cell_multiplier")]
+    pub line_height: f64,
+
+    /// Scales the width of the character cell computed from the
+    /// font metrics; glyphs are centered within the adjusted cell
+    #[serde(default = "default_cell_multiplier")]
+    pub cell_width: f64,
+
     /// When using FontKitXXX font systems, a set of directories to
     /// search ahead of the standard font locations for fonts.
     /// Relative paths are taken to be relative to the directory
@@ -292,7 +590,7 @@ pub struct Config {
 
     /// Named color schemes
     #[serde(default)]
-    pub color_schemes: HashMap<String, Palette>,
+    pub color_schemes: BTreeMap<String, Palette>,
 
     /// How many lines of scrollback you want to retain
     #[serde(default = "default_scrollback_lines")]
@@ -316,7 +614,7 @@ pub struct Config {
     /// when spawning commands in the local domain.
     /// This is not used when working with remote domains.
     #[serde(default)]
-    pub set_environment_variables: HashMap<String, String>,
+    pub set_environment_variables: BTreeMap<String, String>,
 
     /// Specifies the height of a new window, expressed in character cells.
     #[serde(default = "default_initial_rows")]
@@ -329,6 +627,21 @@ pub struct Config {
     #[serde(default = "default_hyperlink_rules")]
     pub hyperlink_rules: Vec<hyperlink::Rule>,
 
+    /// Specifies the program and arguments used to open a URI, such
+    /// as when clicking on a hyperlink.  Any `{}` in the arguments is
+    /// replaced by the URI; if none of them contain `{}` then the URI
+    /// is appended as the final argument.  The program is run directly
+    /// rather than via the shell, so the URI cannot inject commands.
+    /// If unset, the default opener for the system is used.
+    #[serde(default)]
+    pub open_uri_command: Option<Vec<String>>,
+
+    /// Maps a URI scheme (eg: `file` or `https`) to the command used
+    /// to open URIs with that scheme.  The commands take the same form
+    /// as `open_uri_command`, which is used for unlisted schemes.
+    #[serde(default)]
+    pub open_uri_handlers: BTreeMap<String, Vec<String>>,
+
     /// What to set the TERM variable to
     #[serde(default = "default_term")]
     pub term: String,
@@ -344,6 +657,11 @@ pub struct Config {
     #[serde(default)]
     pub font_antialias: FontAntiAliasing,
 
+    /// How to render characters that are not present in any of the
+    /// configured fonts
+    #[serde(default)]
+    pub notdef_glyph_style: NotdefGlyphStyle,
+
     /// Specify the features to enable when using harfbuzz for font shaping.
     /// There is some light documentation here:
     /// <https://harfbuzz.github.io/shaping-opentype-features.html>
@@ -494,6 +812,13 @@ pub struct Config {
     #[serde(default)]
     pub default_cursor_style: DefaultCursorStyle,
 
+    /// Controls how the text under a block cursor is colored.
+    /// `Palette` (the default) draws the glyph in `cursor_fg` over a
+    /// `cursor_bg` block, while `Invert` swaps the foreground and
+    /// background colors of the cell under the cursor.
+    #[serde(default)]
+    pub cursor_color_mode: CursorColorMode,
+
     /// If non-zero, specifies the period (in seconds) at which various
     /// statistics are logged.  Note that there is a minimum period of
     /// 10 seconds.
@@ -507,6 +832,25 @@ pub struct Config {
     #[serde(default = "default_true")]
     pub scroll_to_bottom_on_input: bool,
 
+    /// If true (the default), trailing whitespace is removed from
+    /// each line of copied text.  If false, only the blank cells that
+    /// pad a line beyond the text written by the application are
+    /// removed, so that spaces printed by the application are kept.
+    #[serde(default = "default_true")]
+    pub selection_trim_trailing_whitespace: bool,
+
+    /// If true (the default), a TAB that the application used to move
+    /// over blank cells is copied as a TAB rather than as spaces.
+    #[serde(default = "default_true")]
+    pub selection_preserve_tabs: bool,
+
+    /// Completing a selection with the mouse copies the text to the
+    /// primary selection.  If this is true, it is also copied to the
+    /// clipboard.  On systems without a primary selection, which are
+    /// Windows, macOS and Wayland, it is always copied to the clipboard.
+    #[serde(default)]
+    pub copy_selection_to_clipboard: bool,
+
     #[serde(default)]
     pub use_ime: bool,
 
@@ -531,12 +875,257 @@ pub struct Config {
     /// mapping annoying in vim :-p
     #[serde(default)]
     pub enable_csi_u_key_encoding: bool,
+
+    /// If true (the default), wezterm will exit once the last of its
+    /// windows has been closed.  If false, wezterm keeps running with
+    /// no windows, which is how applications conventionally behave
+    /// on macOS, and clicking its Dock icon opens a new window.  This
+    /// is only considered on macOS.
+    #[serde(default = "default_true")]
+    pub quit_when_all_windows_are_closed: bool,
+
+    /// The minimum interval, in milliseconds, between desktop
+    /// notifications requested by the same tab using OSC 9 or
+    /// OSC 777.  Notifications that arrive sooner are discarded.
+    #[serde(default = "default_notification_cooldown_ms")]
+    pub notification_cooldown_ms: u64,
+
+    /// The characters that delimit words for double-click selection,
+    /// word motions in copy mode and word editing in the search overlay.
+    /// Whitespace is always treated as a delimiter.
+    #[serde(default = "default_selection_word_boundary")]
+    pub selection_word_boundary: String,
+
+    /// Glyphs that are too large for their cells are clipped to the
+    /// cells, unless their font is scaled to fit.  When this is true,
+    /// glyphs that are roughly square, such as the icons in some patched
+    /// fonts, are instead allowed to extend into the following cell.
+    #[serde(default)]
+    pub allow_square_glyphs_to_overflow: bool,
+
+    /// When true, the Powerline separator glyphs are stretched to
+    /// exactly fill their cells, so that no seams are visible between
+    /// the segments of a powerline prompt.  Set this to false to render
+    /// them using the natural metrics of the font.
+    #[serde(default = "default_true")]
+    pub snap_powerline_glyphs_to_cell: bool,
+
+    /// If true, a full reset of the terminal (RIS, or the
+    /// ResetTerminal key assignment) also discards the scrollback
+    /// rather than just clearing the visible screen.
+    #[serde(default)]
+    pub clear_scrollback_on_reset: bool,
+
+    /// If true, applications may resize the window using the
+    /// CSI 8 t and CSI 4 t escape sequences.  This is off by default
+    /// so that untrusted programs can't change the size of the window.
+    #[serde(default)]
+    pub allow_window_resize_requests: bool,
+
+    /// The maximum amount of output from a child command that may be
+    /// read from its pty before the terminal model has caught up with
+    /// it.  Once that much is pending, wezterm stops reading so that
+    /// the kernel pty buffer pushes back on the command, rather than
+    /// the backlog building up in memory and delaying the display of
+    /// the output that follows, such as the prompt after CTRL-C.
+    /// The default value is 128K.
+    #[serde(default = "default_max_pending_output_bytes")]
+    pub max_pending_output_bytes: usize,
+
+    /// How many lines to scroll for each tick of the mouse wheel.
+    /// This multiplies the ticks reported by the system, which on
+    /// Windows already accounts for the system wheel settings.
+    /// An application that has grabbed the mouse is sent one wheel
+    /// event for every `scroll_lines_per_tick` lines.
+    #[serde(default = "default_scroll_lines_per_tick")]
+    pub scroll_lines_per_tick: usize,
+
+    /// How many pixels of pixel-precise scrolling, such as from a
+    /// trackpad, correspond to one line.  Defaults to the height of
+    /// a cell.
+    #[serde(default)]
+    pub scroll_pixels_per_line: Option<f64>,
+
+    /// The directory in which the QuickSave key assignment saves the
+    /// text of the tab.  Defaults to the home directory.
+    #[serde(default)]
+    pub quick_save_directory: Option<PathBuf>,
+
+    /// When true, glyphs that extend past the cells that they occupy,
+    /// as glyphs from proportional fallback fonts often do, are clipped
+    /// to those cells.  Italic glyphs keep their overhang into the
+    /// following cell.  Set this to false to let them overlap their
+    /// neighbors instead.
+    #[serde(default = "default_true")]
+    pub clip_glyphs_to_cells: bool,
+
+    /// A key that arms the key bindings whose mods include `LEADER`,
+    /// in the manner of the tmux prefix key
+    #[serde(default)]
+    pub leader: Option<LeaderKey>,
+
+    /// Named tables of key assignments that the ActivateKeyTable
+    /// assignment can make active
+    #[serde(default)]
+    pub key_tables: BTreeMap<String, KeyTable>,
+
+    /// A command whose output is shown in the status area at the right
+    /// of the tab bar, or in the window title when the tab bar is hidden.
+    /// It is run every `status_update_interval` milliseconds.
+    /// Text set by the application in the active tab, using OSC 7777,
+    /// takes precedence over it.
+    #[serde(default)]
+    pub status_command: Option<Vec<String>>,
+    #[serde(default = "default_status_update_interval")]
+    pub status_update_interval: u64,
+
+    /// The major version of Unicode whose character widths are used
+    /// to lay out text.  Versions before 9 treat most emoji as single
+    /// width.  Applications can change it at runtime using the iTerm2
+    /// `UnicodeVersion` escape sequence.
+    #[serde(default = "default_unicode_version")]
+    pub unicode_version: u8,
+
+    /// An image to draw behind the text, stretched to fill the window.
+    /// It shows through the cells whose background is the default
+    /// background color.
+    #[serde(default)]
+    pub window_background_image: Option<PathBuf>,
+
+    /// Adjusts the background image while the window has the focus
+    #[serde(default)]
+    pub window_background_image_hsb: HsbTransform,
+
+    /// Adjusts the background image while the window doesn't have the
+    /// focus; defaults to `window_background_image_hsb`
+    #[serde(default)]
+    pub inactive_window_background_image_hsb: Option<HsbTransform>,
+
+    /// When true, a ligature that the cursor is over is drawn as its
+    /// individual characters, so that it is clear which one of them
+    /// the cursor is on
+    #[serde(default)]
+    pub cursor_breaks_ligatures: bool,
+
+    /// Specifies the program and arguments used to open a file at a
+    /// particular line, such as when clicking on a `src/main.rs:42:7`
+    /// location in compiler output.  `{file}`, `{line}` and `{col}`
+    /// in the arguments are replaced by the location; the column is 1
+    /// if the location doesn't include one.  If unset, such files are
+    /// opened in the same way as other files.
+    #[serde(default)]
+    pub editor_command: Option<Vec<String>>,
+
+    /// When input is being broadcast to several tabs, whether pasted
+    /// text is sent to all of them rather than just the active tab
+    #[serde(default)]
+    pub broadcast_paste: bool,
+
+    /// If true, a tab that has been scrolled back is scrolled to the
+    /// bottom when it produces output.  The default is to leave it
+    /// scrolled back, with each tab keeping its own position.
+    #[serde(default)]
+    pub scroll_to_bottom_on_output: bool,
+
+    /// If non-zero, the output of tabs that aren't visible is applied
+    /// to the terminal in batches, at most this many milliseconds
+    /// apart, rather than as it arrives.  This reduces the CPU that is
+    /// used by programs that produce a lot of output in the background.
+    /// The output is applied in full when the tab becomes visible.
+    #[serde(default)]
+    pub inactive_tab_output_batch_milliseconds: u64,
+
+    /// When the output of tabs that aren't visible is applied in
+    /// batches, the most output that is held before it is applied.
+    /// The default is 1MB.
+    #[serde(default = "default_inactive_tab_output_buffer_bytes")]
+    pub inactive_tab_output_buffer_bytes: usize,
+
+    /// The color schemes to use in place of `color_scheme` while the
+    /// system prefers a dark or a light appearance
+    #[serde(default)]
+    pub color_scheme_dark: Option<String>,
+    #[serde(default)]
+    pub color_scheme_light: Option<String>,
+
+    /// The format of the title of the window, in which `{title}` is
+    /// replaced by the title of the active tab, `{workspace}` by the
+    /// name of the workspace of the window, and `{tab_index}` and
+    /// `{tab_count}` by the number of the active tab and the number
+    /// of tabs.  When not set, the title is that of the active tab,
+    /// preceded by the workspace name and, when there are several
+    /// tabs, the number of the active tab.
+    #[serde(default)]
+    pub window_title_format: Option<String>,
+
+    /// What to do when an application rings the bell
+    #[serde(default)]
+    pub audible_bell: AudibleBell,
+    /// A sound file to play when the bell rings, in place of the
+    /// system beep
+    #[serde(default)]
+    pub bell_sound: Option<PathBuf>,
+    /// The volume of the bell, from 0.0 to 1.0
+    #[serde(default = "default_bell_volume")]
+    pub bell_volume: f32,
+    /// The minimum interval, in milliseconds, between bells rung by
+    /// the same tab.  Bells that arrive sooner are ignored, so that a
+    /// program that rings the bell repeatedly doesn't stack sounds.
+    #[serde(default = "default_bell_cooldown_ms")]
+    pub bell_cooldown_ms: u64,
+    /// When false, the audible bell is silent while the window has
+    /// the focus, leaving just the visual bell
+    #[serde(default = "default_true")]
+    pub audible_bell_when_focused: bool,
+    /// How long, in milliseconds, the window flashes when the bell
+    /// rings.  0 disables the visual bell, except when the audible
+    /// bell is enabled but there is no way to play it.
+    #[serde(default)]
+    pub visual_bell_duration_ms: u64,
+
+    /// The path to a GLSL fragment shader that is run over each frame
+    /// before it is shown.  Only takes effect with the OpenGL renderer.
+    #[serde(default)]
+    pub custom_shader: Option<PathBuf>,
+
+    /// What happens when a single directory is dragged and dropped
+    /// onto the window; files are always pasted as quoted paths
+    #[serde(default)]
+    pub dropped_directory_action: DroppedDirectoryAction,
+
+    /// The command line of the editor that `OpenConfigFile` runs, to
+    /// which the path of the config file is appended.  When not set,
+    /// `$VISUAL` or `$EDITOR` is used.
+    #[serde(default)]
+    pub config_file_editor: Option<Vec<String>>,
+
+    /// The modifiers that, while held with the mouse over a link, show
+    /// the target of the link at the bottom of the window
+    #[serde(
+        deserialize_with = "keys::de_modifiers",
+        default = "default_hyperlink_preview_mods"
+    )]
+    pub hyperlink_preview_mods: Modifiers,
+
+    /// Whether bytes from 0x80 to 0x9f in the output, that aren't part
+    /// of a UTF-8 sequence, are taken to be 8-bit C1 controls, as sent
+    /// by some legacy systems
+    #[serde(default)]
+    pub enable_8bit_c1_controls: bool,
 }
 
 fn default_update_interval() -> u64 {
     86400
 }
 
+fn default_status_update_interval() -> u64 {
+    1000
+}
+
+fn default_unicode_version() -> u8 {
+    termwiz::cell::UnicodeVersion::LATEST.0
+}
+
 #[derive(Deserialize, Serialize, Clone, Copy, Debug)]
 pub enum DefaultCursorStyle {
     BlinkingBlock,
@@ -554,6 +1143,99 @@ impl Default for DefaultCursorStyle {
     }
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudibleBell {
+    /// The system beep, or `bell_sound` if it is set
+    SystemBeep,
+    Disabled,
+}
+impl_lua_conversion!(AudibleBell);
+
+impl Default for AudibleBell {
+    fn default() -> Self {
+        AudibleBell::SystemBeep
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DroppedDirectoryAction {
+    /// Paste the quoted path of the directory, as for a file
+    PastePath,
+    /// Run `cd` with the path of the directory
+    ChangeDirectory,
+}
+impl_lua_conversion!(DroppedDirectoryAction);
+
+impl Default for DroppedDirectoryAction {
+    fn default() -> Self {
+        DroppedDirectoryAction::PastePath
+    }
+}
+
+/// How the bytes exchanged with a tab are translated to and from
+/// characters, for talking to legacy systems that don't use UTF-8
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TabEncoding {
+    Utf8,
+    Latin1,
+    /// The character set of the original IBM PC, with its line drawing
+    /// characters, as used by BBSes
+    Cp437,
+}
+impl_lua_conversion!(TabEncoding);
+
+impl Default for TabEncoding {
+    fn default() -> Self {
+        TabEncoding::Utf8
+    }
+}
+
+impl TabEncoding {
+    pub fn variants() -> Vec<&'static str> {
+        vec!["Utf8", "Latin1", "Cp437"]
+    }
+}
+
+impl std::str::FromStr for TabEncoding {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "utf8" => Ok(TabEncoding::Utf8),
+            "latin1" => Ok(TabEncoding::Latin1),
+            "cp437" => Ok(TabEncoding::Cp437),
+            _ => Err(anyhow!(
+                "{} is not a valid TabEncoding variant, possible values are {:?}",
+                s,
+                TabEncoding::variants()
+            )),
+        }
+    }
+}
+
+impl From<TabEncoding> for termwiz::escape::parser::Encoding {
+    fn from(encoding: TabEncoding) -> Self {
+        use termwiz::escape::parser::Encoding;
+        match encoding {
+            TabEncoding::Utf8 => Encoding::Utf8,
+            TabEncoding::Latin1 => Encoding::Latin1,
+            TabEncoding::Cp437 => Encoding::Cp437,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorColorMode {
+    Palette,
+    Invert,
+}
+impl_lua_conversion!(CursorColorMode);
+
+impl Default for CursorColorMode {
+    fn default() -> Self {
+        CursorColorMode::Palette
+    }
+}
+
 impl DefaultCursorStyle {
     pub fn effective_shape(self, shape: CursorShape) -> CursorShape {
         match shape {
@@ -583,6 +1265,24 @@ pub struct WindowPadding {
 }
 impl_lua_conversion!(WindowPadding);
 
+/// Configuration that applies to a single window, taking precedence
+/// over the corresponding values in the main configuration.
+/// These are specified when the window is spawned, either via
+/// the command line or a `SpawnCommandInNewWindow` key assignment.
+#[derive(Default, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct WindowConfigOverrides {
+    /// Overrides `font_size` for the window
+    #[serde(default)]
+    pub font_size: Option<f64>,
+    /// Overrides `color_scheme` for the window
+    #[serde(default)]
+    pub color_scheme: Option<String>,
+    /// Names the workspace of the window
+    #[serde(default)]
+    pub workspace: Option<String>,
+}
+impl_lua_conversion!(WindowConfigOverrides);
+
 impl Default for Config {
     fn default() -> Self {
         // Ask serde to provide the defaults based on the attributes
@@ -594,6 +1294,49 @@ impl Default for Config {
 
 impl Config {
     pub fn load() -> Result<(Self, Option<PathBuf>), Error> {
+        let sources = SOURCES.lock().unwrap().clone();
+
+        if !sources.skip_file {
+            if let Some(path) = sources.file.as_ref() {
+                return match Self::load_file(path, &sources.overrides)? {
+                    Some(cfg) => Ok((cfg, Some(path.to_path_buf()))),
+                    None => bail!("--config-file {} does not exist", path.display()),
+                };
+            }
+            for p in &Self::config_file_paths() {
+                if let Some(cfg) = Self::load_file(p, &sources.overrides)? {
+                    return Ok((cfg, Some(p.to_path_buf())));
+                }
+            }
+        }
+
+        if sources.overrides.is_empty() {
+            return Ok((Self::default().compute_extra_defaults(None), None));
+        }
+        let lua = crate::scripting::make_lua_context(&CONFIG_DIR)?;
+        let config = apply_overrides(&lua, mlua::Value::Nil, &sources.overrides)?;
+        let cfg: Self = crate::scripting::from_lua_value(config)
+            .context("Error converting the --config overrides to Config struct")?;
+        let _ = cfg.key_bindings()?;
+        let cfg = cfg.compute_extra_defaults(None);
+        cfg.check_font_files()?;
+        Ok((cfg, None))
+    }
+
+    /// Returns the command line that opens `path` in the editor
+    pub fn config_file_editor_command(&self, path: &Path) -> Vec<String> {
+        let mut args = editor_command(
+            self.config_file_editor.as_ref(),
+            std::env::var("VISUAL").ok(),
+            std::env::var("EDITOR").ok(),
+        );
+        args.push(path.to_string_lossy().into_owned());
+        args
+    }
+
+    /// Returns the locations in which to look for the config file,
+    /// in order of preference
+    fn config_file_paths() -> Vec<PathBuf> {
         // Note that the directories crate has methods for locating project
         // specific config directories, but only returns one of them, not
         // multiple.  In addition, it spawns a lot of subprocesses,
@@ -601,7 +1344,9 @@ impl Config {
 
         let mut paths = vec![
             CONFIG_DIR.join("wezterm.lua"),
+            CONFIG_DIR.join("wezterm.toml"),
             HOME_DIR.join(".wezterm.lua"),
+            HOME_DIR.join(".wezterm.toml"),
         ];
         if cfg!(windows) {
             // On Windows, a common use case is to maintain a thumb drive
@@ -622,46 +1367,84 @@ impl Config {
             log::trace!("Note: WEZTERM_CONFIG_FILE is set in the environment");
             paths.insert(0, path.into());
         }
+        paths
+    }
 
-        for p in &paths {
-            log::trace!("consider config: {}", p.display());
-            let mut file = match fs::File::open(p) {
-                Ok(file) => file,
-                Err(err) => match err.kind() {
-                    std::io::ErrorKind::NotFound => continue,
-                    _ => bail!("Error opening {}: {}", p.display(), err),
-                },
-            };
-
-            let mut s = String::new();
-            file.read_to_string(&mut s)?;
-
-            let cfg: Self;
+    /// Loads the config file `p`, which is TOML if its extension is
+    /// `toml` and lua otherwise, and applies `overrides` over it.
+    /// Returns None if the file doesn't exist.
+    fn load_file(p: &Path, overrides: &[(String, String)]) -> anyhow::Result<Option<Self>> {
+        log::trace!("consider config: {}", p.display());
+        let mut file = match fs::File::open(p) {
+            Ok(file) => file,
+            Err(err) => match err.kind() {
+                std::io::ErrorKind::NotFound => return Ok(None),
+                _ => bail!("Error opening {}: {}", p.display(), err),
+            },
+        };
 
-            let lua = crate::scripting::make_lua_context(p)?;
-            let config: mlua::Value = lua
-                .load(&s)
+        let mut s = String::new();
+        file.read_to_string(&mut s)
+            .with_context(|| format!("Error reading {}", p.display()))?;
+
+        let lua = crate::scripting::make_lua_context(p)?;
+        let config: mlua::Value = if p.extension() == Some(OsStr::new("toml")) {
+            // The error includes the line on which parsing failed
+            let value: toml::Value = toml::from_str(&s)
+                .with_context(|| format!("Error parsing TOML from {}", p.display()))?;
+            crate::scripting::to_lua_value(&lua, value)?
+        } else {
+            // The chunk name is included in lua errors along with the line
+            lua.load(&s)
                 .set_name(p.to_string_lossy().as_bytes())?
-                .eval()?;
-            cfg = crate::scripting::from_lua_value(config).with_context(|| {
-                format!(
-                    "Error converting lua value returned by script {} to Config struct",
-                    p.display()
-                )
-            })?;
-
-            // Compute but discard the key bindings here so that we raise any
-            // problems earlier than we use them.
-            let _ = cfg.key_bindings()?;
+                .eval()
+                .with_context(|| format!("Error evaluating {}", p.display()))?
+        };
+        let config = apply_overrides(&lua, config, overrides)?;
+        let cfg: Self = crate::scripting::from_lua_value(config).with_context(|| {
+            format!(
+                "Error converting lua value returned by script {} to Config struct",
+                p.display()
+            )
+        })?;
+
+        // Compute but discard the key bindings here so that we raise any
+        // problems earlier than we use them.
+        let _ = cfg.key_bindings()?;
+
+        std::env::set_var("WEZTERM_CONFIG_FILE", p);
+        if let Some(dir) = p.parent() {
+            std::env::set_var("WEZTERM_CONFIG_DIR", dir);
+        }
+        let cfg = cfg.compute_extra_defaults(Some(p));
+        cfg.check_font_files()?;
+        Ok(Some(cfg))
+    }
 
-            std::env::set_var("WEZTERM_CONFIG_FILE", p);
-            if let Some(dir) = p.parent() {
-                std::env::set_var("WEZTERM_CONFIG_DIR", dir);
+    /// Verify that the fonts that are specified by file exist and
+    /// that their collection indices are valid, so that such problems
+    /// are reported along with other configuration errors
+    fn check_font_files(&self) -> anyhow::Result<()> {
+        let styles = std::iter::once(&self.font).chain(self.font_rules.iter().map(|r| &r.font));
+        for style in styles {
+            for attr in &style.font {
+                if let Some(file) = attr.file.as_ref() {
+                    let num_faces = crate::font::parser::font_file_face_count(file)
+                        .with_context(|| format!("Unable to load font {}", file.display()))?;
+                    let index = attr.index.unwrap_or(0) as usize;
+                    if index >= num_faces {
+                        bail!(
+                            "font index {} is out of range for {}, which contains {} font{}",
+                            index,
+                            file.display(),
+                            num_faces,
+                            if num_faces == 1 { "" } else { "s" }
+                        );
+                    }
+                }
             }
-            return Ok((cfg.compute_extra_defaults(Some(p)), Some(p.to_path_buf())));
         }
-
-        Ok((Self::default().compute_extra_defaults(None), None))
+        Ok(())
     }
 
     pub fn default_config() -> Self {
@@ -703,12 +1486,30 @@ impl Config {
                     *font_dir = dir;
                 }
             }
+
+            // Likewise for fonts that are specified by file
+            let styles = std::iter::once(&mut cfg.font)
+                .chain(cfg.font_rules.iter_mut().map(|rule| &mut rule.font));
+            for style in styles {
+                for attr in &mut style.font {
+                    if let Some(file) = attr.file.as_mut() {
+                        if !file.is_absolute() {
+                            *file = config_dir.join(&file);
+                        }
+                    }
+                }
+            }
+        }
+
+        let base_font = cfg.font.clone();
+        for rule in &mut cfg.font_rules {
+            rule.inherit_base_style(&base_font);
         }
 
         if cfg.font_rules.is_empty() {
             // Expand out some reasonable default font rules
-            let bold = self.font.make_bold();
-            let italic = self.font.make_italic();
+            let bold = cfg.font.make_bold();
+            let italic = cfg.font.make_italic();
             let bold_italic = bold.make_italic();
 
             cfg.font_rules.push(StyleRule {
@@ -735,20 +1536,46 @@ impl Config {
         cfg.load_color_schemes(&cfg.compute_color_scheme_dirs())
             .ok();
 
-        if let Some(scheme) = cfg.color_scheme.as_ref() {
-            if !cfg.color_schemes.contains_key(scheme) {
-                log::error!(
-                    "Your configuration specifies \
-                     color_scheme=\"{}\" but that scheme \
-                     was not found",
-                    scheme
-                );
+        let schemes = [
+            ("color_scheme", &cfg.color_scheme),
+            ("color_scheme_dark", &cfg.color_scheme_dark),
+            ("color_scheme_light", &cfg.color_scheme_light),
+        ];
+        for (option, scheme) in schemes.iter() {
+            if let Some(scheme) = scheme.as_ref() {
+                if !cfg.color_schemes.contains_key(scheme) {
+                    log::error!(
+                        "Your configuration specifies \
+                         {}=\"{}\" but that scheme \
+                         was not found",
+                        option,
+                        scheme
+                    );
+                }
             }
         }
 
         cfg
     }
 
+    /// Returns the name of the color scheme to use: that for the
+    /// current appearance, if it is configured, or else `color_scheme`
+    pub fn effective_color_scheme(&self) -> Option<&String> {
+        let scheme = match crate::appearance::current() {
+            Some(Appearance::Dark) => self.color_scheme_dark.as_ref(),
+            Some(Appearance::Light) => self.color_scheme_light.as_ref(),
+            None => None,
+        };
+        scheme.or_else(|| self.color_scheme.as_ref())
+    }
+
+    /// Returns the name of the color scheme whose palette is used,
+    /// which is None if the effective scheme was not found
+    pub fn applied_color_scheme(&self) -> Option<&String> {
+        self.effective_color_scheme()
+            .filter(|scheme| self.color_schemes.contains_key(*scheme))
+    }
+
     fn compute_color_scheme_dirs(&self) -> Vec<PathBuf> {
         let mut paths = self.color_scheme_dirs.clone();
         paths.push(CONFIG_DIR.join("colors"));
@@ -850,6 +1677,37 @@ impl Config {
         Ok(())
     }
 
+    /// Returns the argument vector for the command that should be used
+    /// to open `uri`, or None if the system default opener should be used
+    pub fn open_uri_argv(&self, uri: &str) -> Option<Vec<String>> {
+        let scheme = url::Url::parse(uri)
+            .ok()
+            .map(|url| url.scheme().to_string());
+        let template = scheme
+            .and_then(|scheme| self.open_uri_handlers.get(&scheme))
+            .or_else(|| self.open_uri_command.as_ref())?;
+        if template.is_empty() {
+            return None;
+        }
+
+        let mut has_placeholder = false;
+        let mut argv: Vec<String> = template
+            .iter()
+            .map(|arg| {
+                if arg.contains("{}") {
+                    has_placeholder = true;
+                    arg.replace("{}", uri)
+                } else {
+                    arg.clone()
+                }
+            })
+            .collect();
+        if !has_placeholder {
+            argv.push(uri.to_string());
+        }
+        Some(argv)
+    }
+
     pub fn initial_size(&self) -> PtySize {
         PtySize {
             rows: self.initial_rows,
@@ -904,6 +1762,18 @@ fn default_ratelimit_output_bytes_per_second() -> u32 {
     400_000
 }
 
+fn default_max_pending_output_bytes() -> usize {
+    128 * 1024
+}
+
+fn default_inactive_tab_output_buffer_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_scroll_lines_per_tick() -> usize {
+    1
+}
+
 fn default_true() -> bool {
     true
 }
@@ -912,6 +1782,22 @@ fn default_cursor_blink_rate() -> u64 {
     800
 }
 
+fn default_notification_cooldown_ms() -> u64 {
+    3000
+}
+
+fn default_bell_volume() -> f32 {
+    1.0
+}
+
+fn default_bell_cooldown_ms() -> u64 {
+    250
+}
+
+fn default_selection_word_boundary() -> String {
+    " \t\n{}[]()\"'`".to_string()
+}
+
 fn default_swap_backspace_and_delete() -> bool {
     // cfg!(target_os = "macos")
     // See: https://github.com/wez/wezterm/issues/88
@@ -930,12 +1816,32 @@ fn default_initial_cols() -> u16 {
     80
 }
 
+/// Matches the file of a file:line location: either a path that has
+/// a directory component, or a file name with the extension of a
+/// source file.  Requiring one of these keeps host:port pairs, such
+/// as `example.com:8080`, from being taken for locations.
+const FILE_LINK_PATH: &str = concat!(
+    r"((?:\.\.?|\b[\w.-]+)?(?:/[\w.-]+)+",
+    r"|\b[\w.-]+\.(?:rs|c|h|cc|cpp|cxx|hh|hpp|m|mm|go|py|rb|js|jsx|ts|tsx|java|kt|scala",
+    r"|swift|cs|fs|lua|sh|pl|php|hs|ml|ex|exs|erl|el|zig|nim|dart|sql|proto|toml|ya?ml",
+    r"|json|xml|html?|css|scss|md|txt))",
+);
+
 fn default_hyperlink_rules() -> Vec<hyperlink::Rule> {
     vec![
         // URL with a protocol
         hyperlink::Rule::new(r"\b\w+://(?:[\w.-]+)\.[a-z]{2,15}\S*\b", "$0").unwrap(),
         // implicit mailto link
         hyperlink::Rule::new(r"\b\w+@[\w-]+(\.[\w-]+)+\b", "mailto:$0").unwrap(),
+        // file:line:col locations, such as those in compiler output,
+        // relative to the working directory of the tab
+        hyperlink::Rule::new(
+            &format!(r"{}:(\d+):(\d+)\b", FILE_LINK_PATH),
+            "file://$CWD/$1#$2:$3",
+        )
+        .unwrap(),
+        // file:line locations
+        hyperlink::Rule::new(&format!(r"{}:(\d+)\b", FILE_LINK_PATH), "file://$CWD/$1#$2").unwrap(),
     ]
 }
 
@@ -954,6 +1860,10 @@ fn default_font_size() -> f64 {
     10.0
 }
 
+fn default_cell_multiplier() -> f64 {
+    1.0
+}
+
 fn default_dpi() -> f64 {
     96.0
 }
@@ -970,6 +1880,14 @@ pub fn pki_dir() -> anyhow::Result<PathBuf> {
     compute_runtime_dir().map(|d| d.join("pki"))
 }
 
+fn default_hyperlink_preview_mods() -> Modifiers {
+    if cfg!(target_os = "macos") {
+        Modifiers::SUPER
+    } else {
+        Modifiers::CTRL
+    }
+}
+
 fn default_read_timeout() -> Duration {
     Duration::from_secs(60)
 }
@@ -977,3 +1895,200 @@ fn default_read_timeout() -> Duration {
 fn default_write_timeout() -> Duration {
     Duration::from_secs(60)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config_override() {
+        assert_eq!(
+            parse_config_override("font_size=16").unwrap(),
+            ("font_size".to_string(), "16".to_string())
+        );
+        // Only the first `=` separates the name from the value
+        assert_eq!(
+            parse_config_override("color_scheme = \"a=b\"").unwrap(),
+            ("color_scheme".to_string(), "\"a=b\"".to_string())
+        );
+        assert!(parse_config_override("font_size").is_err());
+        assert!(parse_config_override("font_size=").is_err());
+        assert!(parse_config_override("=16").is_err());
+        assert!(parse_config_override("font.size=16").is_err());
+    }
+
+    #[test]
+    fn file_link_rules() {
+        let rules = default_hyperlink_rules();
+        let link = |line: &str| {
+            hyperlink::Rule::match_hyperlinks(line, &rules)
+                .get(0)
+                .map(|m| m.link.uri().to_string())
+        };
+        assert_eq!(
+            link("error at src/main.rs:42:7"),
+            Some("file://$CWD/src/main.rs#42:7".to_string())
+        );
+        assert_eq!(
+            link("main.rs:42"),
+            Some("file://$CWD/main.rs#42".to_string())
+        );
+        assert_eq!(
+            link("./build/out:3"),
+            Some("file://$CWD/./build/out#3".to_string())
+        );
+        assert_eq!(
+            link("/etc/hosts:1"),
+            Some("file://$CWD//etc/hosts#1".to_string())
+        );
+        // host:port pairs aren't locations
+        assert_eq!(link("listening on example.com:8080"), None);
+        assert_eq!(link("localhost:3000"), None);
+    }
+
+    #[test]
+    fn tab_font_size_is_clamped() {
+        let mut font = TabFontOverride::default();
+        assert!(font.apply_user_var("WEZTERM_FONT_SIZE", "1e9"));
+        assert_eq!(font.font_size, Some(MAX_FONT_SIZE));
+        assert!(font.apply_user_var("WEZTERM_FONT_SIZE", "0.01"));
+        assert_eq!(font.font_size, Some(MIN_FONT_SIZE));
+        assert!(font.apply_user_var("WEZTERM_FONT_SIZE", " 14 "));
+        assert_eq!(font.font_size, Some(14.));
+        assert!(!font.apply_user_var("WEZTERM_FONT_SIZE", "-3"));
+        assert!(!font.apply_user_var("WEZTERM_FONT_SIZE", "inf"));
+        assert!(font.apply_user_var("WEZTERM_FONT_SIZE", ""));
+        assert_eq!(font.font_size, None);
+    }
+
+    #[test]
+    fn config_changes() {
+        let old = Config::default();
+        assert!(ConfigChanges::between(&old, &old.clone()).is_empty());
+
+        let mut new = old.clone();
+        new.color_scheme = Some("Batman".to_string());
+        assert_eq!(
+            ConfigChanges::between(&old, &new),
+            ConfigChanges {
+                colors: true,
+                ..Default::default()
+            }
+        );
+
+        new.font_size += 2.;
+        new.scrollback_lines += 1;
+        let changes = ConfigChanges::between(&old, &new);
+        assert_eq!(
+            changes,
+            ConfigChanges {
+                colors: true,
+                fonts: true,
+                keys: false,
+                other: true,
+            }
+        );
+        assert_eq!(changes.to_string(), "colors,fonts,other");
+    }
+
+    #[test]
+    fn open_uri_argv() {
+        let argv = |args: &[&str]| -> Vec<String> { args.iter().map(|s| s.to_string()).collect() };
+        let mut config = Config::default();
+        // The default opener for the system is used
+        assert_eq!(config.open_uri_argv("https://example.com"), None);
+
+        // Without a placeholder, the URI is appended
+        config.open_uri_command = Some(argv(&["firefox", "--new-tab"]));
+        assert_eq!(
+            config.open_uri_argv("https://example.com"),
+            Some(argv(&["firefox", "--new-tab", "https://example.com"]))
+        );
+
+        // Each placeholder is replaced, and the URI isn't appended
+        config.open_uri_command = Some(argv(&["opener", "--url={}", "--title={}"]));
+        assert_eq!(
+            config.open_uri_argv("https://example.com/a b"),
+            Some(argv(&[
+                "opener",
+                "--url=https://example.com/a b",
+                "--title=https://example.com/a b"
+            ]))
+        );
+        // A URI with shell syntax is passed as a single argument
+        assert_eq!(
+            config.open_uri_argv("https://example.com/;rm -rf ~"),
+            Some(argv(&[
+                "opener",
+                "--url=https://example.com/;rm -rf ~",
+                "--title=https://example.com/;rm -rf ~"
+            ]))
+        );
+
+        // A handler for the scheme takes precedence over the command,
+        // which is used for other schemes and for unparseable URIs
+        config
+            .open_uri_handlers
+            .insert("mailto".to_string(), argv(&["mail", "-s", "{}"]));
+        assert_eq!(
+            config.open_uri_argv("mailto:someone@example.com"),
+            Some(argv(&["mail", "-s", "mailto:someone@example.com"]))
+        );
+        assert_eq!(
+            config.open_uri_argv("not a uri"),
+            Some(argv(&["opener", "--url=not a uri", "--title=not a uri"]))
+        );
+
+        // An empty handler opens nothing for its scheme
+        config.open_uri_handlers.insert("file".to_string(), vec![]);
+        assert_eq!(config.open_uri_argv("file:///etc/passwd"), None);
+    }
+
+    #[test]
+    fn overrides_are_applied_last() {
+        let lua = mlua::Lua::new();
+        let config: mlua::Value = lua.load("{font_size=10, line_height=1.5}").eval().unwrap();
+        let overrides = vec![
+            ("font_size".to_string(), "16".to_string()),
+            ("font_size".to_string(), "18".to_string()),
+        ];
+        let config = apply_overrides(&lua, config, &overrides).unwrap();
+        let cfg: Config = crate::scripting::from_lua_value(config).unwrap();
+        assert!((cfg.font_size - 18.).abs() < f64::EPSILON);
+        assert!((cfg.line_height - 1.5).abs() < f64::EPSILON);
+
+        let err = apply_overrides(
+            &lua,
+            mlua::Value::Nil,
+            &[("font_size".to_string(), "1 +".to_string())],
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("--config font_size=1 +"));
+    }
+
+    #[test]
+    fn skeleton_is_a_valid_config() {
+        let lua = crate::scripting::make_lua_context(Path::new(".")).unwrap();
+        let config: mlua::Value = lua.load(SKELETON).eval().unwrap();
+        let cfg: Config = crate::scripting::from_lua_value(config).unwrap();
+        assert!((cfg.font_size - Config::default().font_size).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn editor() {
+        let configured = vec!["code".to_string(), "--wait".to_string()];
+        assert_eq!(
+            editor_command(Some(&configured), Some("emacs".into()), None),
+            configured
+        );
+        assert_eq!(
+            editor_command(None, Some("subl -w".into()), Some("nano".into())),
+            vec!["subl".to_string(), "-w".to_string()]
+        );
+        assert_eq!(
+            editor_command(None, Some("".into()), Some("nano".into())),
+            vec!["nano".to_string()]
+        );
+        assert!(!editor_command(None, None, None).is_empty());
+    }
+}
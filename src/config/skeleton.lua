@@ -0,0 +1,28 @@
+-- This is your wezterm configuration file.  wezterm watches it and
+-- applies your changes as soon as you save them.  Every option is
+-- described at https://wezfurlong.org/wezterm/config/files.html
+-- Uncomment a line by removing the leading "--" to try it out.
+local wezterm = require 'wezterm';
+
+return {
+  -- The font, and its size in points
+  -- font = wezterm.font("JetBrains Mono"),
+  -- font_size = 12.0,
+
+  -- A color scheme from https://wezfurlong.org/wezterm/colorschemes/
+  -- color_scheme = "Batman",
+
+  -- How many lines of scrollback to keep for each tab
+  -- scrollback_lines = 3500,
+
+  -- Hide the tab bar when there is only one tab
+  -- hide_tab_bar_if_only_one_tab = true,
+
+  -- The program that new tabs run, instead of your shell
+  -- default_prog = {"/bin/bash", "-l"},
+
+  -- Additional key assignments
+  -- keys = {
+  --   {key="e", mods="CTRL|SHIFT", action="OpenConfigFile"},
+  -- },
+}
@@ -25,5 +25,10 @@ pub struct SshDomain {
 
     /// The path to the wezterm binary on the remote host
     pub remote_wezterm_path: Option<String>,
+
+    /// Colors to use for tabs in this domain in preference to
+    /// those from the configuration
+    #[serde(default)]
+    pub colors: TabColorOverrides,
 }
 impl_lua_conversion!(SshDomain);
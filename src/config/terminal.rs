@@ -1,6 +1,7 @@
 //! Bridge our gui config into the terminal crate configuration
 
 use crate::config::configuration;
+use termwiz::cell::UnicodeVersion;
 use termwiz::hyperlink::Rule as HyperlinkRule;
 use wezterm_term::color::ColorPalette;
 
@@ -25,10 +26,22 @@ impl wezterm_term::TerminalConfiguration for TermConfig {
         configuration().enable_csi_u_key_encoding
     }
 
+    fn clear_scrollback_on_reset(&self) -> bool {
+        configuration().clear_scrollback_on_reset
+    }
+
+    fn enable_8bit_c1_controls(&self) -> bool {
+        configuration().enable_8bit_c1_controls
+    }
+
+    fn unicode_version(&self) -> UnicodeVersion {
+        UnicodeVersion(configuration().unicode_version)
+    }
+
     fn color_palette(&self) -> ColorPalette {
         let config = configuration();
 
-        if let Some(scheme_name) = config.color_scheme.as_ref() {
+        if let Some(scheme_name) = config.effective_color_scheme() {
             if let Some(palette) = config.color_schemes.get(scheme_name) {
                 return palette.clone().into();
             }
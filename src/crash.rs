@@ -0,0 +1,94 @@
+//! Reports panics.  A report including a backtrace is written to a
+//! file in the runtime directory and a notification tells the user
+//! where to find it.  A panic on a background thread, such as that of
+//! an overlay or of a pty reader, leaves the rest of wezterm running,
+//! but a panic on the gui thread unwinds out of the event loop and so
+//! terminates the process rather than leaving a window that no longer
+//! responds.
+use crate::config::RUNTIME_DIR;
+use std::io::Write;
+use std::panic::PanicInfo;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set while a report is being written, so that a panic while doing
+/// so can't recurse
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // The logger isn't used here: the panic may have happened
+        // while its lock was held
+        if !REPORTING.swap(true, Ordering::SeqCst) {
+            let report = format_report(info);
+            let message = match write_report(&report) {
+                Ok(path) => format!(
+                    "wezterm hit an internal error; details were saved to {}",
+                    path.display()
+                ),
+                Err(err) => {
+                    eprintln!("failed to save the panic report: {:#}\n{}", err, report);
+                    format!(
+                        "wezterm hit an internal error, and failed to save the details: {:#}",
+                        err
+                    )
+                }
+            };
+
+            if std::thread::current().name() == Some("main") {
+                crate::fatal_toast_notification("Wezterm panic", &message);
+            } else {
+                crate::toast_notification("Wezterm panic", &message);
+            }
+            REPORTING.store(false, Ordering::SeqCst);
+        }
+        // The panic then unwinds as usual; on the gui thread that
+        // ends the process, which closes the master side of the ptys
+        // so the programs running in them are sent SIGHUP, just as
+        // when a window is closed normally
+        default_hook(info);
+    }));
+}
+
+fn payload_message(info: &PanicInfo) -> String {
+    if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "(no message)".to_string()
+    }
+}
+
+fn format_report(info: &PanicInfo) -> String {
+    let thread = std::thread::current();
+    let location = info
+        .location()
+        .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+        .unwrap_or_else(|| "unknown".to_string());
+    let config = std::env::var("WEZTERM_CONFIG_FILE").unwrap_or_else(|_| "(none)".to_string());
+
+    format!(
+        "wezterm {} panicked\nthread: {}\nlocation: {}\nmessage: {}\nconfig: {}\n\n{:?}",
+        crate::wezterm_version(),
+        thread.name().unwrap_or("<unnamed>"),
+        location,
+        payload_message(info),
+        config,
+        backtrace::Backtrace::new()
+    )
+}
+
+/// Appends `report` to the report file of this process, returning
+/// the path to the file
+fn write_report(report: &str) -> anyhow::Result<PathBuf> {
+    std::fs::create_dir_all(&*RUNTIME_DIR)?;
+    let path = RUNTIME_DIR.join(format!("panic-{}.txt", std::process::id()));
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    writeln!(file, "{}\n", report)?;
+    Ok(path)
+}
@@ -2,6 +2,7 @@
 
 use crate::config::{configuration, FontAntiAliasing, FontHinting};
 use crate::font::locator::FontDataHandle;
+use crate::font::rasterizer::FontSynthesis;
 use anyhow::{anyhow, Context};
 pub use freetype::*;
 use std::ptr;
@@ -61,6 +62,22 @@ pub fn compute_load_flags_from_config() -> i32 {
     flags
 }
 
+/// Determines the render mode that FT_LOAD_RENDER would use for
+/// `load_flags`; the inverse of render_mode_to_load_target
+fn load_target_render_mode(load_flags: FT_Int32) -> FT_Render_Mode {
+    let load_flags = load_flags as u32;
+    if load_flags & FT_LOAD_MONOCHROME != 0 {
+        return FT_Render_Mode::FT_RENDER_MODE_MONO;
+    }
+    match (load_flags >> 16) & 15 {
+        1 => FT_Render_Mode::FT_RENDER_MODE_LIGHT,
+        2 => FT_Render_Mode::FT_RENDER_MODE_MONO,
+        3 => FT_Render_Mode::FT_RENDER_MODE_LCD,
+        4 => FT_Render_Mode::FT_RENDER_MODE_LCD_V,
+        _ => FT_Render_Mode::FT_RENDER_MODE_NORMAL,
+    }
+}
+
 pub struct Face {
     pub face: FT_Face,
     _bytes: Vec<u8>,
@@ -145,18 +162,67 @@ impl Face {
         ft_result(unsafe { FT_Select_Size(self.face, idx as i32) }, ())
     }
 
+    /// Returns whether the face is bold and whether it is italic,
+    /// according to its style flags
+    pub fn style(&self) -> (bool, bool) {
+        let flags = unsafe { (*self.face).style_flags } as u32;
+        (
+            flags & FT_STYLE_FLAG_BOLD != 0,
+            flags & FT_STYLE_FLAG_ITALIC != 0,
+        )
+    }
+
     pub fn load_and_render_glyph(
         &mut self,
         glyph_index: FT_UInt,
         load_flags: FT_Int32,
+        synthesis: FontSynthesis,
     ) -> anyhow::Result<&FT_GlyphSlotRec_> {
         unsafe {
-            let res = FT_Load_Glyph(
-                self.face,
-                glyph_index,
-                (FT_LOAD_DEFAULT | FT_LOAD_RENDER) as i32 | load_flags,
-            );
-            ft_result(res, &*(*self.face).glyph)
+            if synthesis == FontSynthesis::default() {
+                let res = FT_Load_Glyph(
+                    self.face,
+                    glyph_index,
+                    (FT_LOAD_DEFAULT | FT_LOAD_RENDER) as i32 | load_flags,
+                );
+                return ft_result(res, &*(*self.face).glyph);
+            }
+
+            // Load the outline so that we can adjust it before rendering
+            let res = FT_Load_Glyph(self.face, glyph_index, FT_LOAD_DEFAULT as i32 | load_flags);
+            ft_result(res, ())?;
+
+            let glyph = (*self.face).glyph;
+            if (*glyph).format != FT_Glyph_Format::FT_GLYPH_FORMAT_OUTLINE {
+                // Bitmap glyphs can't be adjusted; use them as they are
+                return ft_result(
+                    FT_Render_Glyph(glyph, load_target_render_mode(load_flags)),
+                    &*glyph,
+                );
+            }
+
+            if synthesis.bold {
+                // This is the strength used by FT_GlyphSlot_Embolden
+                let size_metrics = &(*(*self.face).size).metrics;
+                let strength = (FT_Pos::from((*self.face).units_per_EM) * size_metrics.y_scale
+                    / 0x1_0000)
+                    / 24;
+                ft_result(FT_Outline_Embolden(&mut (*glyph).outline, strength), ())?;
+            }
+
+            if synthesis.italic {
+                // Shear by about 12 degrees, as FT_GlyphSlot_Oblique does
+                let shear = FT_Matrix {
+                    xx: 0x1_0000,
+                    xy: 0x0366A,
+                    yx: 0,
+                    yy: 0x1_0000,
+                };
+                FT_Outline_Transform(&(*glyph).outline, &shear);
+            }
+
+            let res = FT_Render_Glyph(glyph, load_target_render_mode(load_flags));
+            ft_result(res, &*glyph)
         }
     }
 
@@ -4,9 +4,11 @@ mod hbwrap;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub mod ftwrap;
 pub mod locator;
+pub mod notdef;
 pub mod parser;
 pub mod rasterizer;
 pub mod shaper;
@@ -17,17 +19,26 @@ pub mod fcwrap;
 
 use crate::font::locator::{FontDataHandle, FontLocator, FontLocatorSelection};
 pub use crate::font::rasterizer::RasterizedGlyph;
-use crate::font::rasterizer::{FontRasterizer, FontRasterizerSelection};
-pub use crate::font::shaper::{FallbackIdx, FontMetrics, GlyphInfo};
+use crate::font::rasterizer::{FontRasterizer, FontRasterizerSelection, FontSynthesis};
+pub use crate::font::shaper::{FallbackIdx, FontMetrics, GlyphInfo, NOTDEF_FONT_IDX};
 use crate::font::shaper::{FontShaper, FontShaperSelection};
+use crate::font::units::PixelLength;
 
-use super::config::{configuration, ConfigHandle, TextStyle};
+use super::config::{
+    configuration, ConfigHandle, TabFontOverride, TextStyle, MAX_FONT_SIZE, MIN_FONT_SIZE,
+};
 use wezterm_term::CellAttributes;
 
 pub struct LoadedFont {
     rasterizers: Vec<RefCell<Option<Box<dyn FontRasterizer>>>>,
     handles: Vec<FontDataHandle>,
     shaper: Box<dyn FontShaper>,
+    /// The styles requested of the fonts, which are synthesized
+    /// for any that lack a face in that style
+    synthesis: FontSynthesis,
+    /// The `scale_to_cell` setting of the font entry that produced
+    /// each of the handles; None for fallbacks suggested by the locator
+    scale_to_cell: Vec<Option<bool>>,
     metrics: FontMetrics,
     font_size: f64,
     dpi: u32,
@@ -38,6 +49,13 @@ impl LoadedFont {
         self.metrics
     }
 
+    /// Returns whether glyphs from the `fallback` font were configured
+    /// to be scaled to fit their cells, or None if the font entry
+    /// doesn't say
+    pub fn scale_to_cell(&self, fallback: FallbackIdx) -> Option<bool> {
+        self.scale_to_cell.get(fallback).cloned().flatten()
+    }
+
     pub fn shape(&self, text: &str) -> anyhow::Result<Vec<GlyphInfo>> {
         self.shaper.shape(text, self.font_size, self.dpi)
     }
@@ -53,8 +71,8 @@ impl LoadedFont {
             .ok_or_else(|| anyhow!("no such fallback index: {}", fallback))?;
         let mut opt_raster = cell.borrow_mut();
         if opt_raster.is_none() {
-            let raster =
-                FontRasterizerSelection::get_default().new_rasterizer(&self.handles[fallback])?;
+            let raster = FontRasterizerSelection::get_default()
+                .new_rasterizer(&self.handles[fallback], self.synthesis)?;
             opt_raster.replace(raster);
         }
 
@@ -65,12 +83,21 @@ impl LoadedFont {
     }
 }
 
+static NEXT_FONTS_ID: AtomicUsize = AtomicUsize::new(0);
+
 /// Matches and loads fonts for a given input style
 pub struct FontConfiguration {
+    /// Distinguishes the glyphs of this configuration from those of
+    /// others that share the same glyph cache
+    id: usize,
     fonts: RefCell<HashMap<TextStyle, Rc<LoadedFont>>>,
     metrics: RefCell<Option<FontMetrics>>,
     dpi_scale: RefCell<f64>,
     font_scale: RefCell<f64>,
+    /// Overrides the font_size from the configuration
+    font_size: RefCell<Option<f64>>,
+    /// Replaces the font from the configuration; see `substitute_font`
+    font_override: Option<TextStyle>,
     config_generation: RefCell<usize>,
     locator: Box<dyn FontLocator>,
 }
@@ -80,15 +107,45 @@ impl FontConfiguration {
     pub fn new() -> Self {
         let locator = FontLocatorSelection::get_default().new_locator();
         Self {
+            id: NEXT_FONTS_ID.fetch_add(1, Ordering::Relaxed),
             fonts: RefCell::new(HashMap::new()),
             locator,
             metrics: RefCell::new(None),
             font_scale: RefCell::new(1.0),
+            font_size: RefCell::new(None),
             dpi_scale: RefCell::new(1.0),
+            font_override: None,
             config_generation: RefCell::new(configuration().generation()),
         }
     }
 
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Create a configuration for a tab that has a font override.
+    /// It has the same scaling and font size as this configuration,
+    /// except where `font_override` specifies the size.
+    pub fn with_override(&self, font_override: &TabFontOverride) -> Self {
+        let mut fonts = Self::new();
+        *fonts.dpi_scale.borrow_mut() = *self.dpi_scale.borrow();
+        *fonts.font_scale.borrow_mut() = *self.font_scale.borrow();
+        *fonts.font_size.borrow_mut() = font_override.font_size.or(*self.font_size.borrow());
+        fonts.font_override = font_override.font.clone();
+        fonts
+    }
+
+    /// Create a configuration whose fonts are `scale` times the size
+    /// of those of this configuration, for text that should stand out
+    pub fn scaled(&self, scale: f64) -> Self {
+        let mut fonts = Self::new();
+        *fonts.dpi_scale.borrow_mut() = *self.dpi_scale.borrow();
+        *fonts.font_scale.borrow_mut() = *self.font_scale.borrow() * scale;
+        *fonts.font_size.borrow_mut() = *self.font_size.borrow();
+        fonts.font_override = self.font_override.clone();
+        fonts
+    }
+
     /// Given a text style, load (with caching) the font that best
     /// matches according to the fontconfig pattern.
     pub fn resolve_font(&self, style: &TextStyle) -> anyhow::Result<Rc<LoadedFont>> {
@@ -107,30 +164,90 @@ impl FontConfiguration {
             return Ok(Rc::clone(entry));
         }
 
+        let requested = style;
+        let substituted = self
+            .font_override
+            .as_ref()
+            .and_then(|replacement| substitute_font(style, &config.font, replacement));
+        let style = substituted.as_ref().unwrap_or(style);
+
         let attributes = style.font_with_fallback();
-        let mut handles = parser::ParsedFont::load_fonts(&config, &attributes)?;
-        handles.append(&mut self.locator.load_fonts(&attributes)?);
+        let synthesis = attributes
+            .first()
+            .map(|attr| FontSynthesis {
+                bold: attr.bold,
+                italic: attr.italic,
+            })
+            .unwrap_or_default();
+        // Fonts that are specified by file bypass the font_dirs and
+        // system font matching entirely
+        let (files, attributes): (Vec<_>, Vec<_>) =
+            attributes.into_iter().partition(|attr| attr.file.is_some());
+        // Each handle is paired with the scale_to_cell setting of
+        // the font entry that produced it
+        let mut handles: Vec<(FontDataHandle, Option<bool>)> = files
+            .into_iter()
+            .filter_map(|attr| {
+                let index = attr.index.unwrap_or(0);
+                let scale_to_cell = attr.scale_to_cell;
+                attr.file
+                    .map(|path| (FontDataHandle::OnDisk { path, index }, scale_to_cell))
+            })
+            .collect();
+        for (handle, attr) in parser::ParsedFont::load_fonts(&config, &attributes)? {
+            handles.push((handle, attr.scale_to_cell));
+        }
+        // The locator may suggest additional fallbacks for each entry;
+        // those are lower precedence than any of the entries themselves
+        let mut fallbacks = vec![];
+        for attr in &attributes {
+            let mut located = self
+                .locator
+                .load_fonts(std::slice::from_ref(attr))?
+                .into_iter();
+            if let Some(handle) = located.next() {
+                handles.push((handle, attr.scale_to_cell));
+            }
+            fallbacks.extend(located.map(|handle| (handle, None)));
+        }
+        handles.append(&mut fallbacks);
+        let (handles, scale_to_cell): (Vec<_>, Vec<_>) = handles.into_iter().unzip();
         let mut rasterizers = vec![];
         for _ in &handles {
             rasterizers.push(RefCell::new(None));
         }
-        let shaper = FontShaperSelection::get_default().new_shaper(&handles)?;
+
+        // The global features come first, then those of the base font
+        // and then those of the style itself, so that a font_rules style
+        // can override the features of the base style.  Where the style
+        // is the base font, its features are harmlessly repeated.
+        let features: Vec<String> = config
+            .harfbuzz_features
+            .iter()
+            .chain(config.font.harfbuzz_features.iter())
+            .chain(style.harfbuzz_features.iter())
+            .cloned()
+            .collect();
+        let shaper = FontShaperSelection::get_default().new_shaper(&handles, &features)?;
 
         let config = configuration();
-        let font_size = config.font_size * *self.font_scale.borrow();
+        let font_size =
+            self.font_size.borrow().unwrap_or(config.font_size) * *self.font_scale.borrow();
         let dpi = *self.dpi_scale.borrow() as u32 * config.dpi as u32;
-        let metrics = shaper.metrics(font_size, dpi)?;
+        let metrics = adjust_cell_metrics(shaper.metrics(font_size, dpi)?, &config);
 
         let loaded = Rc::new(LoadedFont {
             rasterizers,
             handles,
             shaper,
+            synthesis,
+            scale_to_cell,
             metrics,
             font_size,
             dpi,
         });
 
-        fonts.insert(style.clone(), Rc::clone(&loaded));
+        fonts.insert(requested.clone(), Rc::clone(&loaded));
 
         Ok(loaded)
     }
@@ -142,6 +259,14 @@ impl FontConfiguration {
         self.metrics.borrow_mut().take();
     }
 
+    /// Use `font_size` in place of the font_size from the configuration,
+    /// or revert to the configured value if `font_size` is None
+    pub fn set_font_size(&self, font_size: Option<f64>) {
+        *self.font_size.borrow_mut() = font_size;
+        self.fonts.borrow_mut().clear();
+        self.metrics.borrow_mut().take();
+    }
+
     /// Returns the baseline font specified in the configuration
     pub fn default_font(&self) -> anyhow::Result<Rc<LoadedFont>> {
         self.resolve_font(&configuration().font)
@@ -151,6 +276,15 @@ impl FontConfiguration {
         *self.font_scale.borrow()
     }
 
+    /// Limits `font_scale` so that the scaled font size stays within
+    /// `MIN_FONT_SIZE..=MAX_FONT_SIZE`
+    pub fn clamp_font_scale(&self, font_scale: f64) -> f64 {
+        let font_size = self.font_size.borrow().unwrap_or(configuration().font_size);
+        font_scale
+            .max(MIN_FONT_SIZE / font_size)
+            .min(MAX_FONT_SIZE / font_size)
+    }
+
     pub fn default_font_metrics(&self) -> Result<FontMetrics, Error> {
         {
             let metrics = self.metrics.borrow();
@@ -207,3 +341,123 @@ impl FontConfiguration {
         &config.font
     }
 }
+
+/// Returns the style to load in place of `style` for a tab whose font
+/// is overridden by `replacement`, or None if `style` uses some other
+/// font than the `base` font of the configuration.  The families of the
+/// base font are replaced wherever they appear, as they do in the bold
+/// and italic styles derived from it, keeping the bold and italic
+/// attributes of `style`.
+fn substitute_font(
+    style: &TextStyle,
+    base: &TextStyle,
+    replacement: &TextStyle,
+) -> Option<TextStyle> {
+    let same_fonts = style.font.len() == base.font.len()
+        && style
+            .font
+            .iter()
+            .zip(base.font.iter())
+            .all(|(a, b)| a.family == b.family && a.file == b.file && a.index == b.index);
+    if !same_fonts {
+        return None;
+    }
+
+    let (bold, italic) = style
+        .font
+        .first()
+        .map_or((false, false), |attr| (attr.bold, attr.italic));
+    Some(TextStyle {
+        font: replacement
+            .font
+            .iter()
+            .map(|attr| {
+                let mut attr = attr.clone();
+                attr.bold |= bold;
+                attr.italic |= italic;
+                attr
+            })
+            .collect(),
+        foreground: style.foreground.or(replacement.foreground),
+        harfbuzz_features: replacement
+            .harfbuzz_features
+            .iter()
+            .chain(style.harfbuzz_features.iter())
+            .cloned()
+            .collect(),
+    })
+}
+
+/// The smallest `line_height` or `cell_width` multiplier that we
+/// will honor; anything smaller would leave no room for the glyphs
+const MIN_CELL_MULTIPLIER: f64 = 0.1;
+
+fn line_height_multiplier(config: &ConfigHandle) -> f64 {
+    config.line_height.max(MIN_CELL_MULTIPLIER)
+}
+
+fn cell_width_multiplier(config: &ConfigHandle) -> f64 {
+    config.cell_width.max(MIN_CELL_MULTIPLIER)
+}
+
+/// Scale the cell dimensions reported by the shaper by the
+/// `line_height` and `cell_width` multipliers from the configuration.
+/// The baseline is moved up by half of any additional height so that
+/// the glyphs remain vertically centered in the cell; the underline
+/// position is relative to the descender and so follows along.
+fn adjust_cell_metrics(metrics: FontMetrics, config: &ConfigHandle) -> FontMetrics {
+    let cell_height = (metrics.cell_height.get() * line_height_multiplier(config))
+        .round()
+        .max(1.);
+    let cell_width = (metrics.cell_width.get() * cell_width_multiplier(config))
+        .round()
+        .max(1.);
+    let extra_height = cell_height - metrics.cell_height.get();
+
+    FontMetrics {
+        cell_height: PixelLength::new(cell_height),
+        cell_width: PixelLength::new(cell_width),
+        descender: metrics.descender - PixelLength::new((extra_height / 2.).floor()),
+        ..metrics
+    }
+}
+
+/// Returns the horizontal space to add to the left of a glyph that
+/// spans `num_cells` cells, in order to center it within cells that
+/// have been widened (or narrowed) by the `cell_width` multiplier.
+pub fn cell_width_padding(metrics: &FontMetrics, num_cells: u8) -> PixelLength {
+    let multiplier = cell_width_multiplier(&configuration());
+    let natural_width = metrics.cell_width / multiplier;
+    (metrics.cell_width - natural_width) * num_cells.max(1) as f64 / 2.
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::FontAttributes;
+
+    #[test]
+    fn font_override_replaces_the_base_families() {
+        let base = TextStyle {
+            font: vec![FontAttributes::new("Fira Code")],
+            ..TextStyle::default()
+        };
+        let replacement = TextStyle {
+            font: vec![FontAttributes::new("Iosevka")],
+            ..TextStyle::default()
+        };
+
+        let bold = substitute_font(&base.make_bold(), &base, &replacement).unwrap();
+        assert_eq!(bold.font.len(), 1);
+        assert_eq!(bold.font[0].family, "Iosevka");
+        assert!(bold.font[0].bold);
+        assert!(!bold.font[0].italic);
+
+        // A rule that names a font of its own keeps it
+        let other = TextStyle {
+            font: vec![FontAttributes::new("Operator Mono")],
+            ..TextStyle::default()
+        };
+        assert_eq!(substitute_font(&other, &base, &replacement), None);
+    }
+}
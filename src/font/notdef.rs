@@ -0,0 +1,186 @@
+//! Procedurally rendered "notdef" glyphs.
+//! These are shown in place of characters that are not present in
+//! any of the configured fonts, so that the presence of those
+//! characters isn't silently hidden.  Depending on the configured
+//! `NotdefGlyphStyle` the glyph is a hollow box, optionally containing
+//! the hexadecimal codepoint drawn using a tiny embedded pixel font.
+use crate::config::NotdefGlyphStyle;
+use crate::font::rasterizer::RasterizedGlyph;
+use crate::font::shaper::FontMetrics;
+use crate::font::units::*;
+
+/// A 3x5 pixel font for the hex digits.  Each row is 3 bits wide,
+/// with the most significant bit being the leftmost pixel.
+const HEX_DIGITS: [[u8; 5]; 16] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111], // 0
+    [0b010, 0b110, 0b010, 0b010, 0b111], // 1
+    [0b111, 0b001, 0b111, 0b100, 0b111], // 2
+    [0b111, 0b001, 0b111, 0b001, 0b111], // 3
+    [0b101, 0b101, 0b111, 0b001, 0b001], // 4
+    [0b111, 0b100, 0b111, 0b001, 0b111], // 5
+    [0b111, 0b100, 0b111, 0b101, 0b111], // 6
+    [0b111, 0b001, 0b001, 0b001, 0b001], // 7
+    [0b111, 0b101, 0b111, 0b101, 0b111], // 8
+    [0b111, 0b101, 0b111, 0b001, 0b111], // 9
+    [0b010, 0b101, 0b111, 0b101, 0b101], // A
+    [0b110, 0b101, 0b110, 0b101, 0b110], // B
+    [0b111, 0b100, 0b100, 0b100, 0b111], // C
+    [0b110, 0b101, 0b101, 0b101, 0b110], // D
+    [0b111, 0b100, 0b111, 0b100, 0b111], // E
+    [0b111, 0b100, 0b111, 0b100, 0b100], // F
+];
+const DIGIT_WIDTH: usize = 3;
+const DIGIT_HEIGHT: usize = 5;
+
+struct Bitmap {
+    width: usize,
+    height: usize,
+    /// pre-multiplied RGBA 32bpp, to match RasterizedGlyph
+    data: Vec<u8>,
+}
+
+impl Bitmap {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0u8; width * height * 4],
+        }
+    }
+
+    fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        for y in y..(y + height).min(self.height) {
+            for x in x..(x + width).min(self.width) {
+                let idx = (y * self.width + x) * 4;
+                for b in &mut self.data[idx..idx + 4] {
+                    *b = 0xff;
+                }
+            }
+        }
+    }
+}
+
+/// Renders the notdef glyph for `codepoint`, sized to fill `num_cells`
+/// cells of the font described by `metrics`.
+pub fn rasterize_notdef(
+    codepoint: u32,
+    num_cells: usize,
+    metrics: &FontMetrics,
+    style: NotdefGlyphStyle,
+) -> RasterizedGlyph {
+    let cell_height = metrics.cell_height.get() as usize;
+    let width = metrics.cell_width.get() as usize * num_cells.max(1);
+
+    if style == NotdefGlyphStyle::Blank || width < 4 || cell_height < 4 {
+        return RasterizedGlyph {
+            data: vec![],
+            height: 0,
+            width: 0,
+            bearing_x: PixelLength::new(0.),
+            bearing_y: PixelLength::new(0.),
+            has_color: false,
+        };
+    }
+
+    let mut bitmap = Bitmap::new(width, cell_height);
+
+    // The box is inset by a pixel on each side so that adjacent
+    // notdef glyphs remain distinguishable
+    let (box_x, box_y) = (1, 1);
+    let (box_width, box_height) = (width - 2, cell_height - 2);
+    // A thick stroke in a short cell would otherwise overflow the box
+    let thickness = (metrics.underline_thickness.get().round() as usize)
+        .max(1)
+        .min(box_width.min(box_height));
+    bitmap.fill_rect(box_x, box_y, box_width, thickness);
+    bitmap.fill_rect(
+        box_x,
+        box_y + box_height.saturating_sub(thickness),
+        box_width,
+        thickness,
+    );
+    bitmap.fill_rect(box_x, box_y, thickness, box_height);
+    bitmap.fill_rect(
+        box_x + box_width.saturating_sub(thickness),
+        box_y,
+        thickness,
+        box_height,
+    );
+
+    if style == NotdefGlyphStyle::HexBox {
+        // The digits are laid out in two rows; BMP characters have
+        // 4 digits and the others have 6
+        let digits = if codepoint <= 0xffff {
+            format!("{:04X}", codepoint)
+        } else {
+            format!("{:06X}", codepoint)
+        };
+        let cols = digits.len() / 2;
+
+        // Leave a pixel of space between the box and the digits,
+        // and between adjacent digits
+        let inner_width = box_width.saturating_sub(2 * (thickness + 1));
+        let inner_height = box_height.saturating_sub(2 * (thickness + 1));
+        let units_wide = cols * (DIGIT_WIDTH + 1) - 1;
+        let units_high = 2 * (DIGIT_HEIGHT + 1) - 1;
+        let scale = (inner_width / units_wide).min(inner_height / units_high);
+
+        // If the cell is too small to fit the digits, we're left
+        // with a plain box
+        if scale > 0 {
+            let left = box_x + (box_width - units_wide * scale) / 2;
+            let top = box_y + (box_height - units_high * scale) / 2;
+
+            for (idx, digit) in digits.chars().enumerate() {
+                let glyph = &HEX_DIGITS[digit.to_digit(16).unwrap_or(0) as usize];
+                let digit_x = left + (idx % cols) * (DIGIT_WIDTH + 1) * scale;
+                let digit_y = top + (idx / cols) * (DIGIT_HEIGHT + 1) * scale;
+                for (row, bits) in glyph.iter().enumerate() {
+                    for col in 0..DIGIT_WIDTH {
+                        if bits & (1 << (DIGIT_WIDTH - 1 - col)) != 0 {
+                            bitmap.fill_rect(
+                                digit_x + col * scale,
+                                digit_y + row * scale,
+                                scale,
+                                scale,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    RasterizedGlyph {
+        data: bitmap.data,
+        height: bitmap.height,
+        width: bitmap.width,
+        bearing_x: PixelLength::new(0.),
+        // Position the top of the glyph at the top of the cell
+        bearing_y: metrics.cell_height + metrics.descender,
+        has_color: false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thick_stroke_in_short_cell() {
+        let metrics = FontMetrics {
+            cell_width: PixelLength::new(8.),
+            cell_height: PixelLength::new(5.),
+            descender: PixelLength::new(-1.),
+            underline_thickness: PixelLength::new(6.),
+            underline_position: PixelLength::new(-1.),
+        };
+        let glyph = rasterize_notdef(0x10ffff, 1, &metrics, NotdefGlyphStyle::HexBox);
+        assert_eq!((glyph.width, glyph.height), (8, 5));
+        // The box is solid, save for the inset around it
+        assert_eq!(
+            glyph.data.chunks(4).filter(|px| px[3] == 0xff).count(),
+            6 * 3
+        );
+    }
+}
@@ -4,7 +4,7 @@
 #![allow(dead_code)]
 use crate::config::{Config, FontAttributes};
 use crate::font::locator::{FontDataHandle, FontLocatorSelection};
-use crate::font::shaper::{FallbackIdx, FontMetrics, GlyphInfo};
+use crate::font::shaper::{snaps_to_cell, FallbackIdx, FontMetrics, GlyphInfo};
 use crate::font::units::*;
 use allsorts::binary::read::{ReadScope, ReadScopeOwned};
 use allsorts::font_data_impl::read_cmap_subtable;
@@ -75,10 +75,12 @@ impl Names {
 impl ParsedFont {
     /// Load FontDataHandle's for fonts that match the configuration
     /// and that are found in the config font_dirs list.
-    pub fn load_fonts(
+    /// Each handle is paired with the entry of `fonts_selection`
+    /// that it matched.
+    pub fn load_fonts<'a>(
         config: &Config,
-        fonts_selection: &[FontAttributes],
-    ) -> anyhow::Result<Vec<FontDataHandle>> {
+        fonts_selection: &'a [FontAttributes],
+    ) -> anyhow::Result<Vec<(FontDataHandle, &'a FontAttributes)>> {
         // First discover the available fonts
         let mut font_info = vec![];
         for path in &config.font_dirs {
@@ -103,26 +105,28 @@ impl ParsedFont {
         // fonts_selection is strictly ordered
         let mut handles = vec![];
         for attr in fonts_selection {
-            let mut found = false;
-            for (names, path, index) in &font_info {
-                if font_info_matches(attr, names) {
+            match best_font_match(attr, &font_info) {
+                Some(idx) => {
+                    let (names, path, index) = &font_info[idx];
                     log::warn!(
                         "Using {} from {} index {}",
                         names.full_name,
                         path.display(),
                         index
                     );
-                    handles.push(FontDataHandle::OnDisk {
-                        path: path.clone(),
-                        index: (*index).try_into()?,
-                    });
-                    found = true;
-                    break;
+                    handles.push((
+                        FontDataHandle::OnDisk {
+                            path: path.clone(),
+                            index: (*index).try_into()?,
+                        },
+                        attr,
+                    ));
+                }
+                None => {
+                    if FontLocatorSelection::get_default() == FontLocatorSelection::ConfigDirsOnly {
+                        log::error!("Did not locate a font match for {:?}", attr);
+                    }
                 }
-            }
-            if !found && FontLocatorSelection::get_default() == FontLocatorSelection::ConfigDirsOnly
-            {
-                log::error!("Did not locate a font match for {:?}", attr);
             }
         }
         Ok(handles)
@@ -418,6 +422,7 @@ impl ParsedFont {
             // let num_cells = glyph_info.glyph.unicodes.len();
             let num_cells = unicode_column_width(&text);
 
+            let snap_to_cell = snaps_to_cell(&text);
             let info = GlyphInfo {
                 #[cfg(debug_assertions)]
                 text,
@@ -429,6 +434,7 @@ impl ParsedFont {
                 y_advance,
                 x_offset: PixelLength::new(0.),
                 y_offset: PixelLength::new(0.),
+                snap_to_cell,
             };
 
             cluster += text_len;
@@ -451,25 +457,73 @@ fn collect_font_info(
     Ok(())
 }
 
-fn font_info_matches(attr: &FontAttributes, names: &Names) -> bool {
+/// Returns the `(bold, italic)` style described by a font sub-family
+/// name such as "Bold Italic" or "Oblique"
+fn sub_family_style(sub_family: &str) -> (bool, bool) {
+    let sub_family = sub_family.to_lowercase();
+    let bold = ["bold", "black", "heavy"]
+        .iter()
+        .any(|w| sub_family.contains(w));
+    let italic = sub_family.contains("italic") || sub_family.contains("oblique");
+    (bold, italic)
+}
+
+/// Rates how well the font described by `names` matches `attr`,
+/// returning None if it doesn't match at all.  Higher is better:
+/// an exact match on the full name beats a face of the family with
+/// the requested style, which in turn beats the regular face of the
+/// family; the latter is used only when the family has no face
+/// in the requested style, and the style is then synthesized.
+fn font_info_match_quality(attr: &FontAttributes, names: &Names) -> Option<u8> {
     if attr.family == names.full_name {
-        true
-    } else if let Some(fam) = names.family.as_ref() {
-        // TODO: correctly match using family and sub-family;
-        // this is a pretty rough approximation
-        if attr.family == *fam {
-            match names.sub_family.as_ref().map(String::as_str) {
-                Some("Italic") if attr.italic => true,
-                Some("Bold") if attr.bold => true,
-                Some("Regular") | None => true,
-                _ => false,
+        return Some(4);
+    }
+    if names.family.as_ref() != Some(&attr.family) {
+        return None;
+    }
+
+    let sub_family = names.sub_family.as_ref().map(String::as_str);
+    // Prefer the conventionally named faces over the likes of
+    // "Light Italic" or "Semibold", which otherwise have the same style
+    let conventional = match sub_family {
+        None | Some("Regular") | Some("Bold") | Some("Italic") | Some("Bold Italic") => 1,
+        _ => 0,
+    };
+    let style = sub_family.map(sub_family_style).unwrap_or((false, false));
+
+    if style == (attr.bold, attr.italic) {
+        Some(2 + conventional)
+    } else if style == (false, false) {
+        Some(conventional)
+    } else {
+        None
+    }
+}
+
+/// Returns the index of the best match for `attr` in `font_info`.
+/// When several fonts are equally good, the earliest is chosen.
+fn best_font_match(attr: &FontAttributes, font_info: &[(Names, PathBuf, usize)]) -> Option<usize> {
+    let mut best: Option<(usize, u8)> = None;
+    for (idx, (names, _, _)) in font_info.iter().enumerate() {
+        if let Some(quality) = font_info_match_quality(attr, names) {
+            if best.map(|(_, q)| quality > q).unwrap_or(true) {
+                best = Some((idx, quality));
             }
-        } else {
-            false
         }
-    } else {
-        false
     }
+    best.map(|(idx, _)| idx)
+}
+
+/// Returns the number of fonts in the file at `path`; 1 for a regular
+/// font file, or the number of fonts in a collection
+pub fn font_file_face_count(path: &Path) -> anyhow::Result<usize> {
+    let data = std::fs::read(path)?;
+    let scope = allsorts::binary::read::ReadScope::new(&data);
+    let file = scope.read::<OpenTypeFile>()?;
+    Ok(match &file.font {
+        OpenTypeFont::Single(_) => 1,
+        OpenTypeFont::Collection(ttc) => ttc.offset_tables.len(),
+    })
 }
 
 fn parse_and_collect_font_info(
@@ -536,3 +590,71 @@ fn get_name(name_table_data: &[u8], name_id: u16) -> anyhow::Result<String> {
     cstr.into_string()
         .map_err(|e| anyhow!("name_id {} is not representable as String: {}", name_id, e))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn face(family: &str, sub_family: &str) -> (Names, PathBuf, usize) {
+        (
+            Names {
+                full_name: format!("{} {}", family, sub_family),
+                unique: None,
+                family: Some(family.to_string()),
+                sub_family: Some(sub_family.to_string()),
+                postscript_name: None,
+            },
+            PathBuf::from(format!("{}-{}.ttf", family, sub_family)),
+            0,
+        )
+    }
+
+    fn attr(family: &str, bold: bool, italic: bool) -> FontAttributes {
+        FontAttributes {
+            family: family.to_string(),
+            bold,
+            italic,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn four_faces() {
+        // Sorted by full name, as load_fonts does
+        let font_info = vec![
+            face("Fira", "Bold"),
+            face("Fira", "Bold Italic"),
+            face("Fira", "Italic"),
+            face("Fira", "Light"),
+            face("Fira", "Regular"),
+        ];
+
+        let resolve = |bold, italic| {
+            best_font_match(&attr("Fira", bold, italic), &font_info)
+                .map(|idx| font_info[idx].0.sub_family.clone().unwrap())
+        };
+
+        assert_eq!(resolve(false, false), Some("Regular".to_string()));
+        assert_eq!(resolve(true, false), Some("Bold".to_string()));
+        assert_eq!(resolve(false, true), Some("Italic".to_string()));
+        assert_eq!(resolve(true, true), Some("Bold Italic".to_string()));
+    }
+
+    #[test]
+    fn missing_faces_use_regular() {
+        let font_info = vec![face("Mono", "Bold"), face("Mono", "Regular")];
+
+        let resolve = |bold, italic| {
+            best_font_match(&attr("Mono", bold, italic), &font_info)
+                .map(|idx| font_info[idx].0.sub_family.clone().unwrap())
+        };
+
+        assert_eq!(resolve(false, true), Some("Regular".to_string()));
+        assert_eq!(resolve(true, true), Some("Regular".to_string()));
+        assert_eq!(resolve(true, false), Some("Bold".to_string()));
+        assert_eq!(
+            best_font_match(&attr("Other", false, false), &font_info),
+            None
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use crate::font::locator::FontDataHandle;
-use crate::font::rasterizer::FontRasterizer;
+use crate::font::rasterizer::{FontRasterizer, FontSynthesis};
 use crate::font::units::*;
 use crate::font::{ftwrap, RasterizedGlyph};
 use ::freetype::FT_GlyphSlotRec_;
@@ -10,6 +10,8 @@ use std::slice;
 
 pub struct FreeTypeRasterizer {
     has_color: bool,
+    /// The styles that the face lacks and that we must synthesize
+    synthesis: FontSynthesis,
     face: RefCell<ftwrap::Face>,
     _lib: ftwrap::Library,
 }
@@ -27,7 +29,7 @@ impl FontRasterizer for FreeTypeRasterizer {
 
         let mut face = self.face.borrow_mut();
         let descender = unsafe { (*(*face.face).size).metrics.descender as f64 / 64.0 };
-        let ft_glyph = face.load_and_render_glyph(glyph_pos, load_flags)?;
+        let ft_glyph = face.load_and_render_glyph(glyph_pos, load_flags, self.synthesis)?;
 
         let mode: ftwrap::FT_Pixel_Mode =
             unsafe { mem::transmute(u32::from(ft_glyph.bitmap.pixel_mode)) };
@@ -271,17 +273,26 @@ impl FreeTypeRasterizer {
         }
     }
 
-    pub fn from_locator(handle: &FontDataHandle) -> anyhow::Result<Self> {
-        log::trace!("Rasterizier wants {:?}", handle);
+    pub fn from_locator(handle: &FontDataHandle, wanted: FontSynthesis) -> anyhow::Result<Self> {
+        log::trace!("Rasterizier wants {:?} {:?}", handle, wanted);
         let lib = ftwrap::Library::new()?;
         let face = lib.face_from_locator(handle)?;
         let has_color = unsafe {
             (((*face.face).face_flags as u32) & (ftwrap::FT_FACE_FLAG_COLOR as u32)) != 0
         };
+        let (is_bold, is_italic) = face.style();
+        let synthesis = FontSynthesis {
+            bold: wanted.bold && !is_bold,
+            italic: wanted.italic && !is_italic,
+        };
+        if synthesis != FontSynthesis::default() {
+            log::trace!("synthesizing {:?} for {:?}", synthesis, handle);
+        }
         Ok(Self {
             _lib: lib,
             face: RefCell::new(face),
             has_color,
+            synthesis,
         })
     }
 }
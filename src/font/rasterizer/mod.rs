@@ -17,6 +17,15 @@ pub struct RasterizedGlyph {
     pub has_color: bool,
 }
 
+/// The styles that were requested of a font.  If the font that was
+/// located doesn't provide them, the rasterizer synthesizes them
+/// by emboldening and/or slanting the glyph outlines.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FontSynthesis {
+    pub bold: bool,
+    pub italic: bool,
+}
+
 /// Rasterizes the specified glyph index in the associated font
 /// and returns the generated bitmap
 pub trait FontRasterizer {
@@ -62,10 +71,11 @@ impl FontRasterizerSelection {
     pub fn new_rasterizer(
         self,
         handle: &FontDataHandle,
+        synthesis: FontSynthesis,
     ) -> anyhow::Result<Box<dyn FontRasterizer>> {
         match self {
             Self::FreeType => Ok(Box::new(freetype::FreeTypeRasterizer::from_locator(
-                handle,
+                handle, synthesis,
             )?)),
             Self::FontKit => bail!("FontKit rasterizer not implemented yet"),
         }
@@ -1,7 +1,7 @@
 use crate::font::locator::FontDataHandle;
 use crate::font::parser::*;
-use crate::font::shaper::{FallbackIdx, FontMetrics, FontShaper, GlyphInfo};
-use anyhow::{anyhow, bail};
+use crate::font::shaper::{notdef_glyphs, FallbackIdx, FontMetrics, FontShaper, GlyphInfo};
+use anyhow::bail;
 
 pub struct AllsortsShaper {
     fonts: Vec<Option<ParsedFont>>,
@@ -56,27 +56,10 @@ impl AllsortsShaper {
                 );
             }
             None => {
-                // We ran out of fallback fonts, so use a replacement
-                // character that is likely to be in one of those fonts
-                let mut alt_text = String::new();
-                let num_chars = s.chars().count();
-                for _ in 0..num_chars {
-                    alt_text.push('?');
-                }
-                if alt_text == s {
-                    // We already tried to fallback to this and failed
-                    return Err(anyhow!("could not fallback to ? character"));
-                }
-                return self.shape_into(
-                    0,
-                    &alt_text,
-                    slice_index,
-                    script,
-                    lang,
-                    font_size,
-                    dpi,
-                    results,
-                );
+                // We ran out of fallback fonts, so render these
+                // characters as notdef glyphs
+                results.append(&mut notdef_glyphs(s, slice_index));
+                return Ok(());
             }
         };
         let first_pass =
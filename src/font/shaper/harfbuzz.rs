@@ -1,12 +1,14 @@
-use crate::config::configuration;
 use crate::font::ftwrap;
 use crate::font::hbwrap as harfbuzz;
 use crate::font::locator::FontDataHandle;
-use crate::font::shaper::{FallbackIdx, FontMetrics, FontShaper, GlyphInfo};
+use crate::font::shaper::{
+    notdef_glyphs, snaps_to_cell, FallbackIdx, FontMetrics, FontShaper, GlyphInfo,
+};
 use crate::font::units::*;
 use anyhow::{anyhow, bail};
 use log::{debug, error};
 use std::cell::{RefCell, RefMut};
+use unicode_segmentation::UnicodeSegmentation;
 
 fn make_glyphinfo(
     text: &str,
@@ -27,6 +29,7 @@ fn make_glyphinfo(
         y_advance: PixelLength::new(f64::from(pos.y_advance) / 64.0),
         x_offset: PixelLength::new(f64::from(pos.x_offset) / 64.0),
         y_offset: PixelLength::new(f64::from(pos.y_offset) / 64.0),
+        snap_to_cell: snaps_to_cell(text),
     }
 }
 
@@ -41,20 +44,34 @@ pub struct HarfbuzzShaper {
     handles: Vec<FontDataHandle>,
     fonts: Vec<RefCell<Option<FontPair>>>,
     lib: ftwrap::Library,
+    features: Vec<harfbuzz::hb_feature_t>,
 }
 
 impl HarfbuzzShaper {
-    pub fn new(handles: &[FontDataHandle]) -> anyhow::Result<Self> {
+    pub fn new(handles: &[FontDataHandle], features: &[String]) -> anyhow::Result<Self> {
         let lib = ftwrap::Library::new()?;
         let handles = handles.to_vec();
         let mut fonts = vec![];
         for _ in 0..handles.len() {
             fonts.push(RefCell::new(None));
         }
+        // Parse the features just once here, rather than on every call
+        // to shape, so that invalid features are reported just once
+        let features = features
+            .iter()
+            .filter_map(|s| match harfbuzz::feature_from_string(s) {
+                Ok(feature) => Some(feature),
+                Err(err) => {
+                    log::warn!("ignoring invalid harfbuzz feature `{}`: {:#}", s, err);
+                    None
+                }
+            })
+            .collect();
         Ok(Self {
             fonts,
             handles,
             lib,
+            features,
         })
     }
 
@@ -94,13 +111,6 @@ impl HarfbuzzShaper {
         font_size: f64,
         dpi: u32,
     ) -> anyhow::Result<Vec<GlyphInfo>> {
-        let config = configuration();
-        let features: Vec<harfbuzz::hb_feature_t> = config
-            .harfbuzz_features
-            .iter()
-            .filter_map(|s| harfbuzz::feature_from_string(s).ok())
-            .collect();
-
         let mut buf = harfbuzz::Buffer::new()?;
         buf.set_script(harfbuzz::hb_script_t::HB_SCRIPT_LATIN);
         buf.set_direction(harfbuzz::hb_direction_t::HB_DIRECTION_LTR);
@@ -116,7 +126,7 @@ impl HarfbuzzShaper {
                         pair.size = font_size;
                         pair.dpi = dpi;
                     }
-                    pair.font.shape(&mut buf, Some(features.as_slice()));
+                    pair.font.shape(&mut buf, Some(self.features.as_slice()));
                 }
                 None => {
                     let chars: Vec<u32> = s.chars().map(|c| c as u32).collect();
@@ -169,12 +179,39 @@ impl HarfbuzzShaper {
         }
         //debug!("sizes: {:?}", sizes);
 
+        // A grapheme cluster must be rendered using a single font,
+        // otherwise the components of eg: an emoji ZWJ sequence could
+        // be split across fonts and rendered as separate glyphs.
+        // If any glyph in a cluster is missing from this font then the
+        // whole cluster is shaped using the fallback.
+        let grapheme_starts: Vec<usize> = s.grapheme_indices(true).map(|(idx, _)| idx).collect();
+        let grapheme_of = |pos: usize| match grapheme_starts.binary_search(&pos) {
+            Ok(idx) => idx,
+            Err(idx) => idx.saturating_sub(1),
+        };
+        let mut grapheme_needs_fallback = vec![false; grapheme_starts.len()];
+        for info in infos.iter() {
+            if info.codepoint == 0 {
+                if let Some(flag) =
+                    grapheme_needs_fallback.get_mut(grapheme_of(info.cluster as usize))
+                {
+                    *flag = true;
+                }
+            }
+        }
+        let needs_fallback = |info: &harfbuzz::hb_glyph_info_t| {
+            grapheme_needs_fallback
+                .get(grapheme_of(info.cluster as usize))
+                .cloned()
+                .unwrap_or(info.codepoint == 0)
+        };
+
         // Now make a second pass to determine if we need
         // to perform fallback to a later font.
         // We can determine this by looking at the codepoint.
         for (i, info) in infos.iter().enumerate() {
             let pos = info.cluster as usize;
-            if info.codepoint == 0 {
+            if needs_fallback(info) {
                 if first_fallback_pos.is_none() {
                     // Start of a run that needs fallback
                     first_fallback_pos = Some(pos);
@@ -185,15 +222,12 @@ impl HarfbuzzShaper {
 
                 let substr = &s[start_pos..pos];
                 let mut shape = match self.do_shape(font_idx + 1, substr, font_size, dpi) {
-                    Ok(shape) => Ok(shape),
+                    Ok(shape) => shape,
                     Err(e) => {
                         error!("{:?} for {:?}", e, substr);
-                        if font_idx == 0 && s == "?" {
-                            bail!("unable to find any usable glyphs for `?` in font_idx 0");
-                        }
-                        self.do_shape(0, "?", font_size, dpi)
+                        notdef_glyphs(substr, 0)
                     }
-                }?;
+                };
 
                 // Fixup the cluster member to match our current offset
                 for mut info in &mut shape {
@@ -203,7 +237,7 @@ impl HarfbuzzShaper {
 
                 first_fallback_pos = None;
             }
-            if info.codepoint != 0 {
+            if !needs_fallback(info) {
                 if s.is_char_boundary(pos) && s.is_char_boundary(pos + sizes[i]) {
                     let text = &s[pos..pos + sizes[i]];
                     //debug!("glyph from `{}`", text);
@@ -227,15 +261,12 @@ impl HarfbuzzShaper {
                 );
             }
             let mut shape = match self.do_shape(font_idx + 1, substr, font_size, dpi) {
-                Ok(shape) => Ok(shape),
+                Ok(shape) => shape,
                 Err(e) => {
                     error!("{:?} for {:?}", e, substr);
-                    if font_idx == 0 && s == "?" {
-                        bail!("unable to find any usable glyphs for `?` in font_idx 0");
-                    }
-                    self.do_shape(0, "?", font_size, dpi)
+                    notdef_glyphs(substr, 0)
                 }
-            }?;
+            };
             // Fixup the cluster member to match our current offset
             for mut info in &mut shape {
                 info.cluster += start_pos as u32;
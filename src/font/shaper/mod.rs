@@ -29,6 +29,23 @@ pub struct GlyphInfo {
     pub x_offset: PixelLength,
     /// Destination render offset
     pub y_offset: PixelLength,
+    /// Whether this is a separator glyph that should be stretched to
+    /// exactly fill its cells; see `snaps_to_cell`
+    pub snap_to_cell: bool,
+}
+
+/// Returns true if `text` is one of the Powerline separators, or one
+/// of their Nerd Font extensions.  Adjacent segments of a powerline
+/// prompt are drawn with these, so unless they touch the edges of
+/// their cells exactly, seams are visible between the segments.
+pub fn snaps_to_cell(text: &str) -> bool {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some('\u{e0b0}'..='\u{e0c8}'), None)
+        | (Some('\u{e0ca}'), None)
+        | (Some('\u{e0cc}'..='\u{e0d4}'), None) => true,
+        _ => false,
+    }
 }
 
 /// Represents a numbered index in the fallback sequence for a `NamedFont`.
@@ -36,6 +53,40 @@ pub struct GlyphInfo {
 /// want to search for a fallback in later indices.
 pub type FallbackIdx = usize;
 
+/// The FallbackIdx assigned to glyphs for characters that are not
+/// present in any font.  The glyph_pos of such a glyph holds the
+/// codepoint of the character, and the glyph is rendered procedurally;
+/// see `crate::font::notdef`.
+pub const NOTDEF_FONT_IDX: FallbackIdx = FallbackIdx::max_value();
+
+/// Produces a notdef glyph for each of the characters in `s`, which
+/// has been found to be missing from all of the fonts.
+/// `cluster` is the offset of `s` within the shaped text.
+/// The advances are left at zero as the renderer positions glyphs
+/// by their cluster rather than by advancing a render cursor.
+pub fn notdef_glyphs(s: &str, cluster: usize) -> Vec<GlyphInfo> {
+    use termwiz::cell::unicode_column_width;
+    let mut buf = [0u8; 4];
+    s.char_indices()
+        .map(|(idx, c)| {
+            let text: &str = c.encode_utf8(&mut buf);
+            GlyphInfo {
+                #[cfg(debug_assertions)]
+                text: text.into(),
+                cluster: (cluster + idx) as u32,
+                num_cells: unicode_column_width(text).max(1) as u8,
+                font_idx: NOTDEF_FONT_IDX,
+                glyph_pos: c as u32,
+                x_advance: PixelLength::new(0.),
+                y_advance: PixelLength::new(0.),
+                x_offset: PixelLength::new(0.),
+                y_offset: PixelLength::new(0.),
+                snap_to_cell: false,
+            }
+        })
+        .collect()
+}
+
 /// Describes the key font metrics that we use in rendering
 #[derive(Copy, Clone, Debug)]
 pub struct FontMetrics {
@@ -95,9 +146,15 @@ impl FontShaperSelection {
         vec!["Harfbuzz", "AllSorts"]
     }
 
-    pub fn new_shaper(self, handles: &[FontDataHandle]) -> anyhow::Result<Box<dyn FontShaper>> {
+    /// Creates a shaper for the fonts in `handles`.  `features` lists
+    /// the font features to apply, for those shapers that support them.
+    pub fn new_shaper(
+        self,
+        handles: &[FontDataHandle],
+        features: &[String],
+    ) -> anyhow::Result<Box<dyn FontShaper>> {
         match self {
-            Self::Harfbuzz => Ok(Box::new(harfbuzz::HarfbuzzShaper::new(handles)?)),
+            Self::Harfbuzz => Ok(Box::new(harfbuzz::HarfbuzzShaper::new(handles, features)?)),
             Self::Allsorts => Ok(Box::new(allsorts::AllsortsShaper::new(handles)?)),
         }
     }
@@ -0,0 +1,27 @@
+precision mediump float;
+
+in vec2 o_tex;
+in vec4 o_color;
+in float o_textured;
+
+uniform sampler2D atlas_tex;
+
+out vec4 color;
+
+// The size of the squares of the checkerboard, in pixels
+const float checker_size = 8.0;
+
+void main() {
+  if (o_textured == 0.0) {
+    // The outline of an allocation
+    color = o_color;
+    return;
+  }
+
+  // Show the atlas over a checkerboard so that transparent texels can
+  // be told apart from opaque black ones
+  float check = mod(floor(gl_FragCoord.x / checker_size) + floor(gl_FragCoord.y / checker_size), 2.0);
+  vec3 checker = mix(vec3(0.4), vec3(0.6), check);
+  vec4 texel = texture(atlas_tex, o_tex);
+  color = vec4(mix(checker, texel.rgb, texel.a), 1.0);
+}
@@ -0,0 +1,18 @@
+precision mediump float;
+in vec2 position;
+in vec2 tex;
+in vec4 color;
+in float textured;
+
+uniform mat4 projection;
+
+out vec2 o_tex;
+out vec4 o_color;
+out float o_textured;
+
+void main() {
+    o_tex = tex;
+    o_color = color;
+    o_textured = textured;
+    gl_Position = projection * vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,251 @@
+//! A debugging view of the glyph texture atlas, which is toggled by the
+//! ToggleTextureAtlas key assignment.  The atlas is drawn scaled
+//! into the bottom right corner of the window, over a checkerboard so
+//! that transparent texels can be told apart from black ones, and each
+//! allocated sprite is outlined in a color that shows how recently it
+//! was allocated.  This is useful when diagnosing glyphs that are
+//! corrupted, evicted too early or that bleed into their neighbors.
+
+// Clippy hates the implement_vertex macro and won't let me scope
+// this warning to its use
+#![allow(clippy::unneeded_field_pattern)]
+
+use ::window::bitmaps::atlas::Atlas;
+use ::window::bitmaps::{ImageTexture, Texture2d};
+use ::window::*;
+use std::time::{Duration, Instant};
+
+/// The gap between the view and the edges of the window
+const MARGIN: isize = 8;
+/// The size of the squares of the checkerboard, in pixels; this
+/// matches the value in atlas-fragment.glsl
+const CHECKER_SIZE: isize = 8;
+
+/// Returns the square of the window in which the atlas is drawn: the
+/// bottom right corner, taking up to half of the shorter side of the
+/// window, but never enlarging the atlas
+pub fn view_rect(dimensions: &Dimensions, atlas_side: usize) -> Rect {
+    let width = dimensions.pixel_width as isize;
+    let height = dimensions.pixel_height as isize;
+    let side = (width.min(height) / 2).min(atlas_side as isize).max(1);
+    Rect::new(
+        Point::new(
+            (width - side - MARGIN).max(0),
+            (height - side - MARGIN).max(0),
+        ),
+        Size::new(side, side),
+    )
+}
+
+/// Returns the outline color for a sprite that was allocated `age`
+/// ago: red for the last second, yellow for the last ten seconds,
+/// green for the last minute and blue for anything older
+pub fn age_color(age: Duration) -> Color {
+    if age < Duration::from_secs(1) {
+        Color::rgb(0xff, 0x30, 0x30)
+    } else if age < Duration::from_secs(10) {
+        Color::rgb(0xff, 0xd0, 0x30)
+    } else if age < Duration::from_secs(60) {
+        Color::rgb(0x30, 0xd0, 0x30)
+    } else {
+        Color::rgb(0x40, 0x80, 0xff)
+    }
+}
+
+/// Maps a rectangle in atlas pixels to the equivalent rectangle
+/// within a view of `view_side` pixels
+fn scale_rect(rect: Rect, view_side: isize, atlas_side: usize) -> Rect {
+    let atlas_side = atlas_side as isize;
+    let scale = |v: isize| v * view_side / atlas_side;
+    let left = scale(rect.min_x());
+    let top = scale(rect.min_y());
+    // Keep tiny sprites visible when the atlas is scaled down
+    let right = scale(rect.max_x()).max(left + 1);
+    let bottom = scale(rect.max_y()).max(top + 1);
+    Rect::new(Point::new(left, top), Size::new(right - left, bottom - top))
+}
+
+/// Returns the outlines of the allocated sprites within a view of
+/// `view_side` pixels, along with their colors
+fn outlines<T: Texture2d>(atlas: &Atlas<T>, view_side: isize) -> Vec<(Rect, Color)> {
+    let now = Instant::now();
+    let mut outlines: Vec<(Rect, Color, Instant)> = atlas
+        .allocations()
+        .map(|alloc| {
+            (
+                scale_rect(alloc.coords, view_side, atlas.size()),
+                age_color(now.saturating_duration_since(alloc.allocated)),
+                alloc.allocated,
+            )
+        })
+        .collect();
+    // Draw the newest sprites last, so that they are on top
+    outlines.sort_by_key(|(_, _, allocated)| *allocated);
+    outlines
+        .into_iter()
+        .map(|(rect, color, _)| (rect, color))
+        .collect()
+}
+
+/// Paints the view using the software renderer
+pub fn paint_software(
+    ctx: &mut dyn PaintContext,
+    dimensions: &Dimensions,
+    atlas: &Atlas<ImageTexture>,
+) {
+    let view = view_rect(dimensions, atlas.size());
+    let side = view.size.width;
+    let mut image = Image::new(side as usize, side as usize);
+
+    for y in (0..side).step_by(CHECKER_SIZE as usize) {
+        for x in (0..side).step_by(CHECKER_SIZE as usize) {
+            let shade = if (x / CHECKER_SIZE + y / CHECKER_SIZE) % 2 == 0 {
+                0x66
+            } else {
+                0x99
+            };
+            image.clear_rect(
+                Rect::new(Point::new(x, y), Size::new(CHECKER_SIZE, CHECKER_SIZE)),
+                Color::rgb(shade, shade, shade),
+            );
+        }
+    }
+
+    // Scale using the nearest texel, so that corruption isn't smoothed
+    // away by interpolation
+    let texture = atlas.texture();
+    let source = texture.image.borrow();
+    let atlas_side = atlas.size();
+    let mut scaled = Image::new(side as usize, side as usize);
+    for y in 0..side as usize {
+        for x in 0..side as usize {
+            *scaled.pixel_mut(x, y) = *source.pixel(
+                x * atlas_side / side as usize,
+                y * atlas_side / side as usize,
+            );
+        }
+    }
+    image.draw_image(Point::new(0, 0), None, &scaled, Operator::Over);
+
+    for (rect, color) in outlines(atlas, side) {
+        image.draw_rect(rect, color, Operator::Source);
+    }
+
+    ctx.draw_image(view.origin, None, &image, Operator::Source);
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct AtlasVertex {
+    pub position: (f32, f32),
+    pub tex: (f32, f32),
+    pub color: (f32, f32, f32, f32),
+    // A bool can't be an input to the vertex shader, so this is
+    // 1.0 for the atlas and 0.0 for the outlines
+    pub textured: f32,
+}
+::window::glium::implement_vertex!(AtlasVertex, position, tex, color, textured);
+
+/// The vertices used to draw the view using OpenGL, along with the
+/// indices of the triangles that draw the atlas and of the lines
+/// that outline the allocated sprites
+pub struct AtlasViewVertices {
+    pub vertices: Vec<AtlasVertex>,
+    pub triangles: Vec<u32>,
+    pub lines: Vec<u32>,
+}
+
+/// Computes the vertices for the view.  The positions are relative to
+/// the center of the window, as they are for the cells.
+pub fn opengl_vertices<T: Texture2d>(
+    dimensions: &Dimensions,
+    atlas: &Atlas<T>,
+) -> AtlasViewVertices {
+    let view = view_rect(dimensions, atlas.size());
+    let x_offset = view.min_x() as f32 - dimensions.pixel_width as f32 / 2.;
+    let y_offset = view.min_y() as f32 - dimensions.pixel_height as f32 / 2.;
+    let side = view.size.width as f32;
+
+    let mut vertices = vec![];
+    for &(x, y) in &[(0., 0.), (1., 0.), (0., 1.), (1., 1.)] {
+        vertices.push(AtlasVertex {
+            position: (x_offset + x * side, y_offset + y * side),
+            tex: (x, y),
+            textured: 1.,
+            ..Default::default()
+        });
+    }
+    let triangles = vec![0, 1, 2, 1, 2, 3];
+
+    let mut lines = vec![];
+    for (rect, color) in outlines(atlas, view.size.width) {
+        let idx = vertices.len() as u32;
+        let left = x_offset + rect.min_x() as f32;
+        let top = y_offset + rect.min_y() as f32;
+        let right = x_offset + rect.max_x() as f32;
+        let bottom = y_offset + rect.max_y() as f32;
+        for &position in &[(left, top), (right, top), (right, bottom), (left, bottom)] {
+            vertices.push(AtlasVertex {
+                position,
+                color: color.to_tuple_rgba(),
+                ..Default::default()
+            });
+        }
+        for side in 0..4 {
+            lines.push(idx + side);
+            lines.push(idx + (side + 1) % 4);
+        }
+    }
+
+    AtlasViewVertices {
+        vertices,
+        triangles,
+        lines,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn view_is_in_the_bottom_right_corner() {
+        let dimensions = Dimensions {
+            pixel_width: 1000,
+            pixel_height: 600,
+            dpi: 96,
+        };
+        assert_eq!(
+            view_rect(&dimensions, 4096),
+            Rect::new(Point::new(692, 292), Size::new(300, 300))
+        );
+        // A small atlas is not enlarged
+        assert_eq!(view_rect(&dimensions, 128).size, Size::new(128, 128));
+    }
+
+    #[test]
+    fn sprites_are_scaled_into_the_view() {
+        let rect = Rect::new(Point::new(100, 200), Size::new(10, 20));
+        assert_eq!(
+            scale_rect(rect, 512, 1024),
+            Rect::new(Point::new(50, 100), Size::new(5, 10))
+        );
+        // Sprites remain visible when scaled down a lot
+        assert_eq!(scale_rect(rect, 16, 4096).size, Size::new(1, 1));
+    }
+
+    #[test]
+    fn colors_by_age() {
+        assert_eq!(
+            age_color(Duration::from_millis(10)),
+            Color::rgb(0xff, 0x30, 0x30)
+        );
+        assert_ne!(
+            age_color(Duration::from_secs(5)),
+            age_color(Duration::from_secs(30))
+        );
+        assert_eq!(
+            age_color(Duration::from_secs(3600)),
+            Color::rgb(0x40, 0x80, 0xff)
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Rings the bell when an application sends BEL.  The audible bell is
+//! either the system beep or the `bell_sound` file, which is played by
+//! a command line player on a thread of its own, so that the gui never
+//! waits for it.  When there's no way to make a sound, the window
+//! flashes instead.
+use crate::config::{AudibleBell, Config};
+use ::window::{Connection, ConnectionOps};
+use lazy_static::lazy_static;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// How long the window flashes when the audible bell can't be played
+/// and the visual bell isn't configured
+const FALLBACK_VISUAL_BELL_MS: u64 = 100;
+
+/// A command line program that can play a sound file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Player {
+    AfPlay,
+    PaPlay,
+    PwPlay,
+    APlay,
+    PowerShell,
+}
+
+impl Player {
+    fn candidates() -> &'static [Player] {
+        if cfg!(target_os = "macos") {
+            &[Player::AfPlay]
+        } else if cfg!(windows) {
+            &[Player::PowerShell]
+        } else {
+            &[Player::PaPlay, Player::PwPlay, Player::APlay]
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Player::AfPlay => "afplay",
+            Player::PaPlay => "paplay",
+            Player::PwPlay => "pw-play",
+            Player::APlay => "aplay",
+            Player::PowerShell => "powershell",
+        }
+    }
+
+    /// Returns the arguments that play `path` at `volume`; aplay and
+    /// PowerShell play at the volume of the system
+    fn args(self, path: &Path, volume: f32) -> Vec<OsString> {
+        let path = path.as_os_str().to_os_string();
+        match self {
+            Player::AfPlay => vec!["-v".into(), format!("{:.2}", volume).into(), path],
+            Player::PaPlay => vec![
+                format!("--volume={}", (volume * 65536.) as u32).into(),
+                path,
+            ],
+            Player::PwPlay => vec![format!("--volume={:.2}", volume).into(), path],
+            Player::APlay => vec!["-q".into(), path],
+            Player::PowerShell => {
+                let path = path.to_string_lossy().replace('\'', "''");
+                vec![
+                    "-NoProfile".into(),
+                    "-NonInteractive".into(),
+                    "-Command".into(),
+                    format!("(New-Object Media.SoundPlayer '{}').PlaySync()", path).into(),
+                ]
+            }
+        }
+    }
+}
+
+/// Returns true if `program` is an executable in the PATH
+fn in_path(program: &str) -> bool {
+    let path = match std::env::var_os("PATH") {
+        Some(path) => path,
+        None => return false,
+    };
+    std::env::split_paths(&path).any(|dir| {
+        let candidate: PathBuf = dir.join(program);
+        candidate.is_file() || (cfg!(windows) && candidate.with_extension("exe").is_file())
+    })
+}
+
+lazy_static! {
+    static ref PLAYER: Option<Player> = Player::candidates()
+        .iter()
+        .copied()
+        .find(|player| in_path(player.program()));
+}
+
+/// Plays the sound in `path` without waiting for it to finish.
+/// Returns false if there is no program with which to play it.
+fn play_sound(path: &Path, volume: f32) -> bool {
+    let player = match *PLAYER {
+        Some(player) => player,
+        None => return false,
+    };
+    let mut command = Command::new(player.program());
+    command
+        .args(player.args(path, volume))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // Don't flash a console window for each bell
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let path = path.to_path_buf();
+    std::thread::spawn(move || match command.status() {
+        Ok(status) if !status.success() => {
+            log::debug!("{} failed to play {}", player.program(), path.display());
+        }
+        Ok(_) => {}
+        Err(err) => log::debug!("failed to run {}: {}", player.program(), err),
+    });
+    true
+}
+
+/// Makes the bell sound, returning false if that wasn't possible
+fn play(config: &Config) -> bool {
+    let volume = config.bell_volume.max(0.).min(1.);
+    if let Some(path) = config.bell_sound.as_ref() {
+        if play_sound(path, volume) {
+            return true;
+        }
+    }
+    Connection::get().map_or(false, |conn| conn.beep(volume))
+}
+
+/// Returns how long the window should flash when the bell rings
+fn visual_bell_duration(config: &Config, audible: bool, played: bool) -> Option<Duration> {
+    let ms = if config.visual_bell_duration_ms == 0 && audible && !played {
+        FALLBACK_VISUAL_BELL_MS
+    } else {
+        config.visual_bell_duration_ms
+    };
+    if ms == 0 {
+        None
+    } else {
+        Some(Duration::from_millis(ms))
+    }
+}
+
+/// Rings the bell, for a window that has the focus if `focused` is
+/// true.  Returns how long the window should flash, if it should.
+pub fn ring(config: &Config, focused: bool) -> Option<Duration> {
+    let audible = config.audible_bell == AudibleBell::SystemBeep
+        && (config.audible_bell_when_focused || !focused);
+    let played = audible && play(config);
+    visual_bell_duration(config, audible, played)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn player_args() {
+        let path = Path::new("/tmp/it's.wav");
+        assert_eq!(
+            Player::PaPlay.args(path, 0.5),
+            vec![OsString::from("--volume=32768"), path.into()]
+        );
+        assert_eq!(
+            Player::AfPlay.args(path, 1.0),
+            vec![OsString::from("-v"), "1.00".into(), path.into()]
+        );
+        assert_eq!(
+            Player::PowerShell.args(path, 1.0).last().unwrap(),
+            "(New-Object Media.SoundPlayer '/tmp/it''s.wav').PlaySync()"
+        );
+    }
+
+    #[test]
+    fn visual_bell() {
+        let mut config = Config::default();
+        assert_eq!(visual_bell_duration(&config, true, true), None);
+        assert_eq!(visual_bell_duration(&config, false, false), None);
+        // The visual bell stands in for a bell that couldn't be played
+        assert_eq!(
+            visual_bell_duration(&config, true, false),
+            Some(Duration::from_millis(FALLBACK_VISUAL_BELL_MS))
+        );
+
+        config.visual_bell_duration_ms = 300;
+        assert_eq!(
+            visual_bell_duration(&config, false, false),
+            Some(Duration::from_millis(300))
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Turns files that are dropped onto the window into input for the
+//! active tab.  The paths are quoted so that the shell sees each one
+//! as a single word, whatever spaces or quotes they contain, and are
+//! separated by spaces.  They are sent as a paste, so that they are
+//! bracketed when the application has enabled bracketed paste mode,
+//! and are never run by themselves.  A lone directory can instead be
+//! made the current directory of the shell, when
+//! `dropped_directory_action` asks for that.
+//! Control characters in a name are escaped, as otherwise a crafted
+//! name could end a bracketed paste early or press Enter.
+use crate::config::DroppedDirectoryAction;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// What to send to the tab for a drop
+#[derive(Debug, PartialEq, Eq)]
+pub enum DropInput {
+    /// Text to paste at the cursor
+    Paste(String),
+    /// A command line to type and run
+    Command(String),
+}
+
+/// Returns true if `c` needn't be quoted for the shell
+fn is_safe_char(c: char) -> bool {
+    c.is_ascii_alphanumeric()
+        || match c {
+            '_' | '-' | '.' | '/' | ',' | ':' | '=' | '+' | '@' | '%' => true,
+            #[cfg(windows)]
+            '\\' => true,
+            _ => false,
+        }
+}
+
+/// Quotes `path` so that the shell treats it as a single word.  Paths
+/// that consist only of safe characters are left alone, so that the
+/// common case stays readable.  Returns None if the path contains
+/// control characters that can't be escaped for the shell.
+pub fn shell_quote(path: &Path) -> Option<String> {
+    let path = path.to_string_lossy();
+    if !path.is_empty() && path.chars().all(is_safe_char) {
+        return Some(path.into_owned());
+    }
+    let has_controls = path.chars().any(char::is_control);
+    if cfg!(windows) {
+        // Windows doesn't allow double quotes in file names, and both
+        // cmd and PowerShell accept a double quoted path.  Neither
+        // has a way to escape a control character that works in both.
+        if has_controls {
+            None
+        } else {
+            Some(format!("\"{}\"", path))
+        }
+    } else if has_controls {
+        // Within $'...' quotes, backslash escapes are expanded by the
+        // shell, so the controls can be sent as escapes
+        let mut quoted = String::from("$'");
+        for c in path.chars() {
+            match c {
+                '\\' => quoted.push_str("\\\\"),
+                '\'' => quoted.push_str("\\'"),
+                c if c.is_control() && (c as u32) < 0x80 => {
+                    write!(quoted, "\\x{:02x}", c as u32).ok();
+                }
+                c if c.is_control() => {
+                    write!(quoted, "\\u{:04x}", c as u32).ok();
+                }
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('\'');
+        Some(quoted)
+    } else {
+        // Nothing is special within single quotes, so the only thing
+        // to take care of is a single quote, which ends the quoted
+        // part, adds an escaped quote and then starts a new part
+        Some(format!("'{}'", path.replace('\'', "'\\''")))
+    }
+}
+
+/// Returns the input for a drop of `paths`, or None if none of the
+/// paths can be quoted
+pub fn input_for_drop(paths: &[PathBuf], action: DroppedDirectoryAction) -> Option<DropInput> {
+    let quoted: Vec<String> = paths
+        .iter()
+        .filter_map(|path| {
+            let quoted = shell_quote(path);
+            if quoted.is_none() {
+                log::error!(
+                    "not sending dropped file {:?}, as it contains control characters",
+                    path
+                );
+            }
+            quoted
+        })
+        .collect();
+    if quoted.is_empty() {
+        return None;
+    }
+    if action == DroppedDirectoryAction::ChangeDirectory
+        && paths.len() == 1
+        && quoted.len() == 1
+        && paths[0].is_dir()
+    {
+        return Some(DropInput::Command(format!("cd {}\r", quoted[0])));
+    }
+    Some(DropInput::Paste(quoted.join(" ")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(not(windows))]
+    fn quoting() {
+        let quote = |path: &str| shell_quote(Path::new(path)).unwrap();
+        assert_eq!(quote("/tmp/plain-name.txt"), "/tmp/plain-name.txt");
+        assert_eq!(quote("/tmp/My Documents"), "'/tmp/My Documents'");
+        assert_eq!(quote("/tmp/it's \"here\""), "'/tmp/it'\\''s \"here\"'");
+        assert_eq!(quote("/tmp/$HOME;rm"), "'/tmp/$HOME;rm'");
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn control_characters() {
+        let quote = |path: &str| shell_quote(Path::new(path)).unwrap();
+        // The end of a bracketed paste
+        assert_eq!(quote("/tmp/a\x1b[201~;rm"), "$'/tmp/a\\x1b[201~;rm'");
+        // Carriage return and line feed, which would press Enter
+        assert_eq!(quote("/tmp/a\rb"), "$'/tmp/a\\x0db'");
+        assert_eq!(quote("/tmp/a\nb"), "$'/tmp/a\\x0ab'");
+        // A C1 control, and the characters that are special in $'...'
+        assert_eq!(quote("/tmp/it's\\\u{9b}"), "$'/tmp/it\\'s\\\\\\u009b'");
+
+        let dropped = input_for_drop(
+            &[PathBuf::from("/tmp/x\ry")],
+            DroppedDirectoryAction::PastePath,
+        );
+        assert_eq!(
+            dropped,
+            Some(DropInput::Paste("$'/tmp/x\\x0dy'".to_string()))
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn drops() {
+        let paths = vec![PathBuf::from("/tmp/a b"), PathBuf::from("/tmp/c")];
+        assert_eq!(
+            input_for_drop(&paths, DroppedDirectoryAction::ChangeDirectory),
+            Some(DropInput::Paste("'/tmp/a b' /tmp/c".to_string()))
+        );
+
+        let dir = vec![std::env::temp_dir()];
+        let quoted = shell_quote(&dir[0]).unwrap();
+        assert_eq!(
+            input_for_drop(&dir, DroppedDirectoryAction::PastePath),
+            Some(DropInput::Paste(quoted.clone()))
+        );
+        assert_eq!(
+            input_for_drop(&dir, DroppedDirectoryAction::ChangeDirectory),
+            Some(DropInput::Command(format!("cd {}\r", quoted)))
+        );
+    }
+}
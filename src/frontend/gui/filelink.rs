@@ -0,0 +1,227 @@
+//! Support for the implicit hyperlinks that refer to files, such as
+//! the `src/main.rs:42:7` locations in compiler output.
+//! Hyperlink rules can use `$CWD` in their format to produce a
+//! `file://$CWD/...` URI; the placeholder stands for the working
+//! directory of the tab, which is only known when the link is used.
+//! A line number, and optionally a column, can be given in the
+//! fragment of a file URI, eg: `file:///src/main.rs#42:7`, and are
+//! passed to the `editor_command` when the link is opened.
+use crate::config::Config;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use termwiz::hyperlink::Hyperlink;
+use url::Url;
+
+const CWD_PREFIX: &str = "file://$CWD/";
+/// How long whether a hovered link exists is remembered; the file may
+/// be created or removed in the meantime
+const EXISTS_CACHE_DURATION: Duration = Duration::from_secs(2);
+
+/// Returns true if `uri` must be resolved against the working directory
+pub fn needs_cwd(uri: &str) -> bool {
+    uri.starts_with(CWD_PREFIX)
+}
+
+/// Resolves a `file://$CWD/` uri against `cwd`, the working directory
+/// of the tab.  Returns None if the uri refers to a file that doesn't
+/// exist, or if it is relative and the working directory isn't known.
+/// Other uris are returned unchanged.
+pub fn resolve(uri: &str, cwd: Option<&Url>) -> Option<String> {
+    if !needs_cwd(uri) {
+        return Some(uri.to_string());
+    }
+
+    let rest = &uri[CWD_PREFIX.len()..];
+    let (path, fragment) = match rest.find('#') {
+        Some(idx) => (&rest[..idx], Some(&rest[idx + 1..])),
+        None => (rest, None),
+    };
+
+    let path = if path.starts_with('/') {
+        PathBuf::from(path)
+    } else {
+        cwd?.to_file_path().ok()?.join(path)
+    };
+    if !path.exists() {
+        return None;
+    }
+
+    let mut url = Url::from_file_path(&path).ok()?;
+    url.set_fragment(fragment);
+    Some(url.into_string())
+}
+
+/// Remembers whether the most recently hovered link refers to a file
+/// that exists, so that the filesystem isn't checked each time the
+/// mouse moves over it
+#[derive(Default)]
+pub struct ExistsCache {
+    last: Option<(Arc<Hyperlink>, Option<Url>, Instant, bool)>,
+}
+
+impl ExistsCache {
+    /// Returns false if `link` refers to a file, relative to `cwd`,
+    /// that doesn't exist
+    pub fn link_exists(&mut self, link: &Arc<Hyperlink>, cwd: Option<&Url>) -> bool {
+        if !needs_cwd(link.uri()) {
+            return true;
+        }
+        if let Some((last, last_cwd, checked, exists)) = self.last.as_ref() {
+            if Arc::ptr_eq(last, link)
+                && last_cwd.as_ref() == cwd
+                && checked.elapsed() < EXISTS_CACHE_DURATION
+            {
+                return *exists;
+            }
+        }
+        let exists = resolve(link.uri(), cwd).is_some();
+        self.last
+            .replace((Arc::clone(link), cwd.cloned(), Instant::now(), exists));
+        exists
+    }
+}
+
+/// The location referred to by a file uri with a line fragment
+#[derive(Debug, PartialEq)]
+struct FileLocation {
+    file: PathBuf,
+    line: usize,
+    col: usize,
+}
+
+fn parse_location(uri: &str) -> Option<FileLocation> {
+    let url = Url::parse(uri).ok()?;
+    if url.scheme() != "file" {
+        return None;
+    }
+    let mut fragment = url.fragment()?.splitn(2, ':');
+    let line = fragment.next()?.parse().ok()?;
+    let col = match fragment.next() {
+        Some(col) => col.parse().ok()?,
+        None => 1,
+    };
+    Some(FileLocation {
+        file: url.to_file_path().ok()?,
+        line,
+        col,
+    })
+}
+
+/// Returns the argument vector that opens the file location
+/// referred to by `uri` using the `editor_command` from the config,
+/// or None if there is no editor command or `uri` isn't a location
+pub fn editor_argv(config: &Config, uri: &str) -> Option<Vec<String>> {
+    let template = config.editor_command.as_ref()?;
+    if template.is_empty() {
+        return None;
+    }
+    let location = parse_location(uri)?;
+    Some(expand_editor_template(template, &location))
+}
+
+fn expand_editor_template(template: &[String], location: &FileLocation) -> Vec<String> {
+    let file = location.file.to_string_lossy();
+    template
+        .iter()
+        .map(|arg| {
+            arg.replace("{file}", &file)
+                .replace("{line}", &location.line.to_string())
+                .replace("{col}", &location.col.to_string())
+        })
+        .collect()
+}
+
+/// Removes the line fragment from a file uri, so that the file can be
+/// opened by a program that doesn't understand it
+pub fn strip_location(uri: &str) -> String {
+    if parse_location(uri).is_some() {
+        if let Ok(mut url) = Url::parse(uri) {
+            url.set_fragment(None);
+            return url.into_string();
+        }
+    }
+    uri.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve_relative() {
+        let dir = std::env::current_dir().unwrap();
+        let cwd = Url::from_directory_path(&dir).unwrap();
+        let expected = Url::from_file_path(dir.join("Cargo.toml")).unwrap();
+
+        assert_eq!(
+            resolve("file://$CWD/Cargo.toml#1:2", Some(&cwd)),
+            Some(format!("{}#1:2", expected))
+        );
+        assert_eq!(resolve("file://$CWD/no-such-file.rs#1", Some(&cwd)), None);
+        // Relative paths can't be resolved without a working directory
+        assert_eq!(resolve("file://$CWD/Cargo.toml#1", None), None);
+        assert_eq!(
+            resolve("https://example.com/", None),
+            Some("https://example.com/".to_string())
+        );
+    }
+
+    #[test]
+    fn exists_cache() {
+        let dir = std::env::current_dir().unwrap();
+        let cwd = Url::from_directory_path(&dir).unwrap();
+        let mut cache = ExistsCache::default();
+
+        let link = Arc::new(Hyperlink::new_implicit("file://$CWD/Cargo.toml#1"));
+        assert!(cache.link_exists(&link, Some(&cwd)));
+        // The outcome depends on the working directory
+        assert!(!cache.link_exists(&link, None));
+        let missing = Arc::new(Hyperlink::new_implicit("file://$CWD/no-such-file.rs#1"));
+        assert!(!cache.link_exists(&missing, Some(&cwd)));
+        assert!(!cache.link_exists(&missing, Some(&cwd)));
+        let url = Arc::new(Hyperlink::new_implicit("https://example.com/"));
+        assert!(cache.link_exists(&url, None));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_absolute() {
+        assert_eq!(
+            resolve("file://$CWD//#3", None),
+            Some("file:///#3".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn locations() {
+        assert_eq!(
+            parse_location("file:///src/main.rs#42:7"),
+            Some(FileLocation {
+                file: PathBuf::from("/src/main.rs"),
+                line: 42,
+                col: 7
+            })
+        );
+        assert_eq!(
+            parse_location("file:///src/main.rs#42").map(|l| l.col),
+            Some(1)
+        );
+        assert_eq!(parse_location("file:///src/main.rs#intro"), None);
+        assert_eq!(parse_location("https://example.com/#42"), None);
+
+        let template = vec![
+            "code".to_string(),
+            "-g".to_string(),
+            "{file}:{line}:{col}".to_string(),
+        ];
+        assert_eq!(
+            expand_editor_template(&template, &parse_location("file:///a.rs#4").unwrap()),
+            vec!["code", "-g", "/a.rs:4:1"]
+        );
+
+        assert_eq!(strip_location("file:///a.rs#4:2"), "file:///a.rs");
+        assert_eq!(strip_location("file:///a.rs#intro"), "file:///a.rs#intro");
+    }
+}
@@ -12,6 +12,15 @@ uniform mat4 projection;
 uniform bool bg_and_line_layer;
 uniform sampler2D glyph_tex;
 
+// The background image shows through wherever the background is
+// the default background color
+uniform bool has_background_image;
+uniform sampler2D background_tex;
+uniform vec4 default_bg_color;
+uniform vec2 window_size;
+// Multipliers for the hue, saturation and brightness of the image
+uniform vec3 background_hsb;
+
 out vec4 color;
 
 float multiply_one(float src, float dst, float inv_dst_alpha, float inv_src_alpha) {
@@ -32,9 +41,36 @@ vec4 multiply(vec4 src, vec4 dst) {
       dst.a);
 }
 
+vec3 rgb2hsv(vec3 c) {
+  vec4 K = vec4(0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0);
+  vec4 p = mix(vec4(c.bg, K.wz), vec4(c.gb, K.xy), step(c.b, c.g));
+  vec4 q = mix(vec4(p.xyw, c.r), vec4(c.r, p.yzx), step(p.x, c.r));
+  float d = q.x - min(q.w, q.y);
+  float e = 1.0e-10;
+  return vec3(abs(q.z + (q.w - q.y) / (6.0 * d + e)), d / (q.x + e), q.x);
+}
+
+vec3 hsv2rgb(vec3 c) {
+  vec4 K = vec4(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
+  vec3 p = abs(fract(c.xxx + K.xyz) * 6.0 - K.www);
+  return c.z * mix(K.xxx, clamp(p - K.xxx, 0.0, 1.0), c.y);
+}
+
+vec4 background_image_color() {
+  vec4 texel = texture(background_tex, gl_FragCoord.xy / window_size);
+  vec3 hsv = rgb2hsv(texel.rgb);
+  hsv.x = fract(hsv.x * background_hsb.x);
+  hsv.y = clamp(hsv.y * background_hsb.y, 0.0, 1.0);
+  hsv.z = clamp(hsv.z * background_hsb.z, 0.0, 1.0);
+  return vec4(hsv2rgb(hsv), 1.0);
+}
+
 void main() {
   if (bg_and_line_layer) {
     color = o_bg_color;
+    if (has_background_image && distance(o_bg_color, default_bg_color) < 0.001) {
+      color = background_image_color();
+    }
 
     // Sample the underline glyph texture for this location.
     // Note that the texture is whitespace in the case where this is
@@ -1,83 +1,471 @@
-use crate::config::TextStyle;
+use crate::config::{configuration, FontAttributes, NotdefGlyphStyle, TextStyle};
+use crate::font::notdef::rasterize_notdef;
 use crate::font::units::*;
-use crate::font::{FontConfiguration, GlyphInfo};
+use crate::font::{
+    cell_width_padding, FontConfiguration, FontMetrics, GlyphInfo, LoadedFont, RasterizedGlyph,
+    NOTDEF_FONT_IDX,
+};
 use ::window::bitmaps::atlas::{Atlas, Sprite};
 use ::window::bitmaps::{Image, ImageTexture, Texture2d};
 use ::window::glium::backend::Context as GliumContext;
 use ::window::glium::texture::SrgbTexture2d;
 use ::window::*;
 use euclid::num::Zero;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 use std::rc::Rc;
 use std::sync::Arc;
 use termwiz::image::ImageData;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct GlyphKey {
+    /// The id of the FontConfiguration that the glyph was resolved
+    /// from, as the tabs of a window can each have their own fonts
+    pub fonts_id: usize,
     pub font_idx: usize,
     pub glyph_pos: u32,
-    pub style: TextStyle,
+    /// Identifies the TextStyle via the StyleTable of the GlyphCache
+    pub style_id: u32,
+    /// Snapped glyphs have different geometry from the same glyph
+    /// rendered with the natural metrics of the font
+    pub snap_to_cell: bool,
+    /// Glyphs for characters that are missing from every font are
+    /// drawn according to `notdef_glyph_style`, so changing it must
+    /// not find the glyphs drawn in the prior style.
+    /// This is None for every other glyph.
+    pub notdef_style: Option<NotdefGlyphStyle>,
 }
 
-/// We'd like to avoid allocating when resolving from the cache
-/// so this is the borrowed version of GlyphKey.
-/// It's a bit involved to make this work; more details can be
-/// found in the excellent guide here:
-/// <https://github.com/sunshowers/borrow-complex-key-example/blob/master/src/lib.rs>
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub struct BorrowedGlyphKey<'a> {
-    pub font_idx: usize,
-    pub glyph_pos: u32,
-    pub style: &'a TextStyle,
+impl GlyphKey {
+    fn new(
+        fonts_id: usize,
+        style_id: u32,
+        info: &GlyphInfo,
+        notdef_style: NotdefGlyphStyle,
+    ) -> Self {
+        Self {
+            fonts_id,
+            font_idx: info.font_idx,
+            glyph_pos: info.glyph_pos,
+            style_id,
+            snap_to_cell: info.snap_to_cell,
+            notdef_style: if info.font_idx == NOTDEF_FONT_IDX {
+                Some(notdef_style)
+            } else {
+                None
+            },
+        }
+    }
 }
 
-impl<'a> BorrowedGlyphKey<'a> {
-    fn to_owned(&self) -> GlyphKey {
-        GlyphKey {
-            font_idx: self.font_idx,
-            glyph_pos: self.glyph_pos,
-            style: self.style.clone(),
+/// Assigns a small integer id to each distinct TextStyle, so that
+/// GlyphKey doesn't need to hold its own copy of the style.
+/// Without this, every cache miss would clone the style (and the
+/// font family strings within it) into the key that is stored in
+/// the cache, and every lookup would need to hash the whole style
+/// again as part of the key.
+/// The table lives alongside the glyph cache and is discarded with
+/// it when the font configuration is reloaded.
+#[derive(Default)]
+struct StyleTable {
+    ids: HashMap<TextStyle, u32>,
+    /// The styles, indexed by id
+    styles: Vec<TextStyle>,
+    /// The most recently resolved id.  Runs of glyphs generally share
+    /// the same style, and comparing against it is cheaper than hashing.
+    last: Option<u32>,
+}
+
+impl StyleTable {
+    /// Returns the id for the style, assigning a new id if this
+    /// is the first time that the style has been seen
+    fn id_for(&mut self, style: &TextStyle) -> u32 {
+        if let Some(last) = self.last {
+            if self.styles[last as usize] == *style {
+                return last;
+            }
         }
+        let id = match self.ids.get(style) {
+            Some(id) => *id,
+            None => {
+                let id = self.styles.len() as u32;
+                self.ids.insert(style.clone(), id);
+                self.styles.push(style.clone());
+                id
+            }
+        };
+        self.last = Some(id);
+        id
+    }
+
+    /// Returns the number of bytes held by the table, including the
+    /// strings within the styles
+    fn bytes(&self) -> usize {
+        let style_bytes: usize = self
+            .styles
+            .iter()
+            .map(|style| {
+                let font: usize = style
+                    .font
+                    .iter()
+                    .map(|attr| {
+                        attr.family.capacity()
+                            + attr.file.as_ref().map_or(0, |file| file.as_os_str().len())
+                    })
+                    .sum();
+                let features: usize = style
+                    .harfbuzz_features
+                    .iter()
+                    .map(|feature| feature.capacity())
+                    .sum();
+                style.font.capacity() * std::mem::size_of::<FontAttributes>()
+                    + style.harfbuzz_features.capacity() * std::mem::size_of::<String>()
+                    + font
+                    + features
+            })
+            .sum();
+        // Each style is held twice: as a key of `ids` and in `styles`
+        2 * style_bytes
+            + self.ids.capacity() * std::mem::size_of::<(TextStyle, u32)>()
+            + self.styles.capacity() * std::mem::size_of::<TextStyle>()
     }
 }
 
-trait GlyphKeyTrait {
-    fn key<'k>(&'k self) -> BorrowedGlyphKey<'k>;
+/// The memory used by a GlyphCache, so that the cost of holding
+/// glyphs for many distinct styles can be seen
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GlyphCacheUsage {
+    pub glyphs: usize,
+    pub composites: usize,
+    pub styles: usize,
+    /// The bytes of the atlas that are occupied by the sprites of the
+    /// cached glyphs
+    pub sprite_bytes: usize,
+    /// The bytes of the cache entries: the keys, the glyphs and the
+    /// interned styles
+    pub overhead_bytes: usize,
+}
+
+impl std::fmt::Display for GlyphCacheUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} glyphs, {} composites, {} styles, {} KiB sprites + {} KiB overhead",
+            self.glyphs,
+            self.composites,
+            self.styles,
+            (self.sprite_bytes + 1023) / 1024,
+            (self.overhead_bytes + 1023) / 1024
+        )
+    }
 }
 
-impl GlyphKeyTrait for GlyphKey {
-    fn key<'k>(&'k self) -> BorrowedGlyphKey<'k> {
-        BorrowedGlyphKey {
-            font_idx: self.font_idx,
-            glyph_pos: self.glyph_pos,
-            style: &self.style,
+/// Returns the ranges of consecutive glyphs that belong to the same
+/// cluster.  The shaper assigns the same cluster to a base character
+/// and its combining marks, which are rendered in the same cell.
+fn cluster_ranges(infos: &[GlyphInfo]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![];
+    for (idx, info) in infos.iter().enumerate() {
+        match ranges.last_mut() {
+            Some(range) if infos[range.start].cluster == info.cluster => range.end = idx + 1,
+            _ => ranges.push(idx..idx + 1),
         }
     }
+    ranges
 }
 
-impl<'a> GlyphKeyTrait for BorrowedGlyphKey<'a> {
-    fn key<'k>(&'k self) -> BorrowedGlyphKey<'k> {
-        *self
+fn rasterize(
+    font: &LoadedFont,
+    metrics: &FontMetrics,
+    info: &GlyphInfo,
+    notdef_style: NotdefGlyphStyle,
+) -> anyhow::Result<RasterizedGlyph> {
+    if info.font_idx == NOTDEF_FONT_IDX {
+        // The character isn't present in any font
+        Ok(rasterize_notdef(
+            info.glyph_pos,
+            info.num_cells as usize,
+            metrics,
+            notdef_style,
+        ))
+    } else {
+        font.rasterize_glyph(info.glyph_pos, info.font_idx)
     }
 }
 
-impl<'a> std::borrow::Borrow<dyn GlyphKeyTrait + 'a> for GlyphKey {
-    fn borrow(&self) -> &(dyn GlyphKeyTrait + 'a) {
-        self
+/// How to treat a glyph that is larger than the cells that it occupies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GlyphFit {
+    /// Scale the glyph down to fit and center it over its cells.
+    /// This suits eg: color emoji, whose bitmaps are typically much
+    /// larger than the cells.
+    ScaleToCell,
+    /// Never scale the glyph; clip whatever falls outside of its cells.
+    /// If `allow_square_overflow` is set, glyphs that are roughly square
+    /// may extend horizontally past their cells rather than be clipped.
+    /// If `allow_overhang` is set, glyphs are only clipped vertically;
+    /// this is used for italic text, whose slanted glyphs overhang the
+    /// following cell by design.
+    Clip {
+        allow_square_overflow: bool,
+        allow_overhang: bool,
+    },
+    /// Never scale the glyph, and let it overlap its neighbors.  This is
+    /// used when `clip_glyphs_to_cells` has been disabled.
+    Overflow,
+    /// Stretch the glyph so that it exactly fills its cells.  This is
+    /// used for the Powerline separators, which need to touch the edges
+    /// of their cells to avoid seams between adjacent segments.
+    SnapToCell,
+}
+
+impl GlyphFit {
+    /// Determines how to fit `glyph`, which was rasterized for `info`.
+    /// Powerline separators are snapped to their cells, unless that has
+    /// been disabled.  Fonts are scaled if their font entry says so;
+    /// otherwise fonts with color glyphs are scaled and all others are
+    /// clipped, keeping the overhang of glyphs in an italic `style`.
+    fn for_glyph(
+        font: &LoadedFont,
+        style: &TextStyle,
+        info: &GlyphInfo,
+        glyph: &RasterizedGlyph,
+    ) -> Self {
+        let config = configuration();
+        if info.snap_to_cell
+            && info.font_idx != NOTDEF_FONT_IDX
+            && config.snap_powerline_glyphs_to_cell
+        {
+            return GlyphFit::SnapToCell;
+        }
+        let scale = info.font_idx != NOTDEF_FONT_IDX
+            && font.scale_to_cell(info.font_idx).unwrap_or(glyph.has_color);
+        if scale {
+            GlyphFit::ScaleToCell
+        } else if !config.clip_glyphs_to_cells {
+            GlyphFit::Overflow
+        } else {
+            GlyphFit::Clip {
+                allow_square_overflow: config.allow_square_glyphs_to_overflow,
+                allow_overhang: style.font.iter().any(|attr| attr.italic),
+            }
+        }
     }
 }
 
-impl<'a> PartialEq for (dyn GlyphKeyTrait + 'a) {
-    fn eq(&self, other: &Self) -> bool {
-        self.key().eq(&other.key())
+/// Returns the factor by which to scale `glyph` so that it fits
+/// within `num_cells` cells.  Glyphs are never scaled up.
+fn glyph_scale(
+    glyph: &RasterizedGlyph,
+    metrics: &FontMetrics,
+    num_cells: u8,
+    fit: GlyphFit,
+) -> f64 {
+    if fit != GlyphFit::ScaleToCell || glyph.width == 0 || glyph.height == 0 {
+        return 1.0;
     }
+    let span = metrics.cell_width.get() * num_cells.max(1) as f64;
+    let x_scale = span / glyph.width as f64;
+    let y_scale = metrics.cell_height.get() / glyph.height as f64;
+    x_scale.min(y_scale).min(1.0)
 }
 
-impl<'a> Eq for (dyn GlyphKeyTrait + 'a) {}
+/// Returns the size in pixels of the `num_cells` cells that a glyph
+/// occupies, as they are laid out on screen, along with the bearing_y
+/// that places the top of a glyph of that height at the top of the cells
+fn snapped_geometry(metrics: &FontMetrics, num_cells: u8) -> (usize, usize, PixelLength) {
+    let cell_height = metrics.cell_height.get().ceil();
+    let width = metrics.cell_width.get().ceil() as usize * num_cells.max(1) as usize;
+    (
+        width,
+        cell_height as usize,
+        PixelLength::new(cell_height) + metrics.descender,
+    )
+}
+
+/// Rounds the shaper's `x_offset` for a glyph to hundredths of a cell.
+/// Glyphs are positioned from the origin of their cells rather than by
+/// accumulating the advances of the font, which need not be a multiple
+/// of the cell width in a proportional fallback font.  Quantizing the
+/// offset means that a glyph lands in the same place in every cell.
+fn cell_relative_offset(x_offset: PixelLength, metrics: &FontMetrics) -> PixelLength {
+    let cell_width = metrics.cell_width.get();
+    if cell_width <= 0. {
+        return x_offset;
+    }
+    PixelLength::new((x_offset.get() / cell_width * 100.).round() / 100. * cell_width)
+}
+
+/// Crops away the parts of `glyph` that fall outside of the `num_cells`
+/// cells that it occupies, given that its left edge is `bearing_x` from
+/// the left of those cells.  Returns the cropped glyph along with its
+/// adjusted `bearing_x`.
+fn clip_glyph(
+    glyph: RasterizedGlyph,
+    bearing_x: PixelLength,
+    metrics: &FontMetrics,
+    num_cells: u8,
+    allow_square_overflow: bool,
+    allow_overhang: bool,
+) -> (RasterizedGlyph, PixelLength) {
+    if glyph.width == 0 || glyph.height == 0 {
+        return (glyph, bearing_x);
+    }
+
+    // Work in whole pixels, relative to the baseline and to the
+    // left edge of the cells
+    let ascent = (metrics.cell_height + metrics.descender).get().round() as isize;
+    let descent = -metrics.descender.get().round() as isize;
+    let span = (metrics.cell_width.get() * num_cells.max(1) as f64).round() as isize;
+    let top = glyph.bearing_y.get().round() as isize;
+    let left = bearing_x.get().round() as isize;
+    let (width, height) = (glyph.width as isize, glyph.height as isize);
+
+    let first_row = (top - ascent).max(0).min(height);
+    let end_row = (top + descent).min(height).max(first_row);
+
+    let aspect = glyph.width as f64 / glyph.height as f64;
+    let (first_col, end_col) =
+        if allow_overhang || (allow_square_overflow && (0.7..=1.4).contains(&aspect)) {
+            (0, width)
+        } else {
+            let first_col = (-left).max(0).min(width);
+            (first_col, (span - left).min(width).max(first_col))
+        };
+
+    if first_row == 0 && end_row == height && first_col == 0 && end_col == width {
+        return (glyph, bearing_x);
+    }
 
-impl<'a> std::hash::Hash for (dyn GlyphKeyTrait + 'a) {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.key().hash(state)
+    let (new_width, new_height) = (
+        (end_col - first_col) as usize,
+        (end_row - first_row) as usize,
+    );
+    let mut data = Vec::with_capacity(new_width * new_height * 4);
+    for row in first_row..end_row {
+        let start = ((row * width + first_col) * 4) as usize;
+        data.extend_from_slice(&glyph.data[start..start + new_width * 4]);
+    }
+
+    (
+        RasterizedGlyph {
+            data,
+            width: new_width,
+            height: new_height,
+            bearing_x: PixelLength::zero(),
+            bearing_y: PixelLength::new((top - first_row) as f64),
+            has_color: glyph.has_color,
+        },
+        bearing_x + PixelLength::new(first_col as f64),
+    )
+}
+
+/// Glyphs that aren't scaled are clipped to their cells, so that they
+/// don't spill over into their neighbors; the notdef glyph is already
+/// sized to fill its cells.  The left edge of a clipped glyph is placed
+/// relative to the origin of its first cell, taking into account the
+/// offset from the shaper, and the returned bearing_x includes that
+/// offset.  Glyphs that aren't clipped are returned unchanged along
+/// with None.
+fn clip_to_cells(
+    glyph: RasterizedGlyph,
+    info: &GlyphInfo,
+    metrics: &FontMetrics,
+    fit: GlyphFit,
+) -> (RasterizedGlyph, Option<PixelLength>) {
+    match fit {
+        GlyphFit::Clip {
+            allow_square_overflow,
+            allow_overhang,
+        } if info.font_idx != NOTDEF_FONT_IDX => {
+            let bearing_x = glyph.bearing_x
+                + cell_width_padding(metrics, info.num_cells)
+                + cell_relative_offset(info.x_offset, metrics);
+            let (glyph, bearing_x) = clip_glyph(
+                glyph,
+                bearing_x,
+                metrics,
+                info.num_cells,
+                allow_square_overflow,
+                allow_overhang,
+            );
+            (glyph, Some(bearing_x))
+        }
+        _ => (glyph, None),
+    }
+}
+
+/// Composites rasterized glyphs into a single glyph.  Each part is
+/// accompanied by the position of the left and top edges of its
+/// bitmap, relative to the pen position and baseline respectively.
+/// The resulting glyph takes its color-ness from the first part.
+fn composite_glyphs(parts: Vec<(PixelLength, PixelLength, RasterizedGlyph)>) -> RasterizedGlyph {
+    let has_color = parts.first().map(|(_, _, g)| g.has_color).unwrap_or(false);
+    let visible: Vec<(f64, f64, RasterizedGlyph)> = parts
+        .into_iter()
+        .filter(|(_, _, g)| g.width > 0 && g.height > 0)
+        .map(|(left, top, g)| (left.get(), top.get(), g))
+        .collect();
+
+    if visible.is_empty() {
+        return RasterizedGlyph {
+            data: vec![],
+            height: 0,
+            width: 0,
+            bearing_x: PixelLength::zero(),
+            bearing_y: PixelLength::zero(),
+            has_color,
+        };
+    }
+
+    let min_left = visible
+        .iter()
+        .map(|(l, _, _)| *l)
+        .fold(f64::INFINITY, f64::min);
+    let max_right = visible
+        .iter()
+        .map(|(l, _, g)| l + g.width as f64)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let max_top = visible
+        .iter()
+        .map(|(_, t, _)| *t)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let min_bottom = visible
+        .iter()
+        .map(|(_, t, g)| t - g.height as f64)
+        .fold(f64::INFINITY, f64::min);
+
+    let width = (max_right - min_left).ceil() as usize;
+    let height = (max_top - min_bottom).ceil() as usize;
+    let mut data = vec![0u8; width * height * 4];
+
+    for (left, top, glyph) in &visible {
+        let x0 = (left - min_left).round() as usize;
+        let y0 = (max_top - top).round() as usize;
+        for y in 0..glyph.height {
+            for x in 0..glyph.width {
+                let (dx, dy) = (x0 + x, y0 + y);
+                if dx >= width || dy >= height {
+                    continue;
+                }
+                let src = &glyph.data[(y * glyph.width + x) * 4..][..4];
+                let dest = &mut data[(dy * width + dx) * 4..][..4];
+                // The data is pre-multiplied, so this is the "over" operator
+                let alpha = u32::from(src[3]);
+                for (d, s) in dest.iter_mut().zip(src.iter()) {
+                    *d = (u32::from(*s) + u32::from(*d) * (255 - alpha) / 255).min(255) as u8;
+                }
+            }
+        }
+    }
+
+    RasterizedGlyph {
+        data,
+        height,
+        width,
+        bearing_x: PixelLength::new(min_left),
+        bearing_y: PixelLength::new(max_top),
+        has_color,
     }
 }
 
@@ -109,6 +497,9 @@ impl<T: Texture2d> std::fmt::Debug for CachedGlyph<T> {
 
 pub struct GlyphCache<T: Texture2d> {
     glyph_cache: HashMap<GlyphKey, Rc<CachedGlyph<T>>>,
+    /// Glyphs composited from the glyphs of a cluster
+    composite_cache: HashMap<Vec<GlyphKey>, Rc<CachedGlyph<T>>>,
+    styles: StyleTable,
     pub atlas: Atlas<T>,
     fonts: Rc<FontConfiguration>,
     image_cache: HashMap<usize, Sprite<T>>,
@@ -122,6 +513,8 @@ impl GlyphCache<ImageTexture> {
         Self {
             fonts: Rc::clone(fonts),
             glyph_cache: HashMap::new(),
+            composite_cache: HashMap::new(),
+            styles: StyleTable::default(),
             image_cache: HashMap::new(),
             atlas,
         }
@@ -146,6 +539,8 @@ impl GlyphCache<SrgbTexture2d> {
         Ok(Self {
             fonts: Rc::clone(fonts),
             glyph_cache: HashMap::new(),
+            composite_cache: HashMap::new(),
+            styles: StyleTable::default(),
             image_cache: HashMap::new(),
             atlas,
         })
@@ -153,57 +548,196 @@ impl GlyphCache<SrgbTexture2d> {
 }
 
 impl<T: Texture2d> GlyphCache<T> {
-    /// Resolve a glyph from the cache, rendering the glyph on-demand if
-    /// the cache doesn't already hold the desired glyph.
-    pub fn cached_glyph(
+    /// Resolves glyphs from `fonts` from now on.  The glyphs of the
+    /// fonts that were previously in use are kept, as they are keyed
+    /// by the id of their fonts, so that switching back to them, as
+    /// happens when switching between tabs with different fonts,
+    /// doesn't have to render them again.
+    pub fn set_fonts(&mut self, fonts: &Rc<FontConfiguration>) {
+        self.fonts = Rc::clone(fonts);
+    }
+
+    pub fn fonts_id(&self) -> usize {
+        self.fonts.id()
+    }
+
+    /// Returns the memory used by the cached glyphs.  The bytes of the
+    /// sprites are those of the atlas that they occupy.
+    pub fn usage(&self) -> GlyphCacheUsage {
+        // Each glyph is held by an Rc, whose allocation also holds the
+        // strong and weak counts
+        let glyph_bytes = std::mem::size_of::<CachedGlyph<T>>() + 2 * std::mem::size_of::<usize>();
+        let sprite_bytes = |glyph: &Rc<CachedGlyph<T>>| {
+            glyph.texture.as_ref().map_or(0, |sprite| {
+                sprite.coords.size.width as usize * sprite.coords.size.height as usize * 4
+            })
+        };
+
+        let mut usage = GlyphCacheUsage {
+            glyphs: self.glyph_cache.len(),
+            composites: self.composite_cache.len(),
+            styles: self.styles.styles.len(),
+            sprite_bytes: 0,
+            overhead_bytes: self.glyph_cache.capacity()
+                * std::mem::size_of::<(GlyphKey, Rc<CachedGlyph<T>>)>()
+                + self.composite_cache.capacity()
+                    * std::mem::size_of::<(Vec<GlyphKey>, Rc<CachedGlyph<T>>)>()
+                + self.styles.bytes(),
+        };
+        for glyph in self.glyph_cache.values() {
+            usage.sprite_bytes += sprite_bytes(glyph);
+            usage.overhead_bytes += glyph_bytes;
+        }
+        for (keys, glyph) in &self.composite_cache {
+            usage.sprite_bytes += sprite_bytes(glyph);
+            usage.overhead_bytes += glyph_bytes + keys.capacity() * std::mem::size_of::<GlyphKey>();
+        }
+        usage
+    }
+
+    /// Resolve a run of glyphs that share the same style from the cache,
+    /// returning them in the same order as `infos`.
+    /// Consecutive glyphs that belong to the same cluster, such as a
+    /// base character and its combining marks, are rendered in the same
+    /// cell, so they are composited into a single glyph which is paired
+    /// with the GlyphInfo of the first glyph in the cluster.
+    /// Any glyphs that the cache doesn't already hold are rendered
+    /// together: the font is resolved once for the whole run, all of
+    /// the missing glyphs are rasterized, and then they are all
+    /// uploaded to the atlas.
+    /// Rasterization happens on this thread, as the rasterizers are
+    /// not thread safe.
+    pub fn cached_glyphs<'a>(
         &mut self,
-        info: &GlyphInfo,
+        infos: &'a [GlyphInfo],
         style: &TextStyle,
-    ) -> anyhow::Result<Rc<CachedGlyph<T>>> {
-        let key = BorrowedGlyphKey {
-            font_idx: info.font_idx,
-            glyph_pos: info.glyph_pos,
-            style,
-        };
+    ) -> anyhow::Result<Vec<(&'a GlyphInfo, Rc<CachedGlyph<T>>)>> {
+        let style_id = self.styles.id_for(style);
+        let fonts_id = self.fonts.id();
+        let notdef_style = configuration().notdef_glyph_style;
+        let key_for = |info: &GlyphInfo| GlyphKey::new(fonts_id, style_id, info, notdef_style);
+        let clusters = cluster_ranges(infos);
 
-        if let Some(entry) = self.glyph_cache.get(&key as &dyn GlyphKeyTrait) {
-            return Ok(Rc::clone(entry));
+        let mut missing: Vec<&GlyphInfo> = vec![];
+        let mut missing_keys = HashSet::new();
+        for range in clusters.iter().filter(|range| range.len() == 1) {
+            let info = &infos[range.start];
+            let key = key_for(info);
+            if !self.glyph_cache.contains_key(&key) && missing_keys.insert(key) {
+                missing.push(info);
+            }
         }
 
-        let glyph = self.load_glyph(info, style)?;
-        self.glyph_cache.insert(key.to_owned(), Rc::clone(&glyph));
-        Ok(glyph)
+        if !missing.is_empty() {
+            let font = self.fonts.resolve_font(style)?;
+            let metrics = font.metrics();
+            let rasterized = missing
+                .iter()
+                .map(|info| rasterize(&font, &metrics, info, notdef_style))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            for (info, glyph) in missing.into_iter().zip(rasterized) {
+                let fit = GlyphFit::for_glyph(&font, style, info, &glyph);
+                let glyph = self.upload_glyph(info, &metrics, glyph, fit)?;
+                self.glyph_cache.insert(key_for(info), glyph);
+            }
+        }
+
+        let mut glyphs = Vec::with_capacity(clusters.len());
+        for range in clusters {
+            let info = &infos[range.start];
+            let glyph = if range.len() == 1 {
+                Rc::clone(&self.glyph_cache[&key_for(info)])
+            } else {
+                let keys: Vec<GlyphKey> = infos[range.clone()].iter().map(key_for).collect();
+                match self.composite_cache.get(&keys) {
+                    Some(glyph) => Rc::clone(glyph),
+                    None => {
+                        let glyph = self.load_composite(&infos[range], style, notdef_style)?;
+                        self.composite_cache.insert(keys, Rc::clone(&glyph));
+                        glyph
+                    }
+                }
+            };
+            glyphs.push((info, glyph));
+        }
+        Ok(glyphs)
     }
 
-    /// Perform the load and render of a glyph
-    #[allow(clippy::float_cmp)]
-    fn load_glyph(
+    /// Like `cached_glyphs`, but resolves the glyphs from `fonts` rather
+    /// than from the fonts that are in use, such as for text that is
+    /// drawn at another size than that of the terminal
+    pub fn cached_glyphs_with_fonts<'a>(
         &mut self,
-        info: &GlyphInfo,
+        fonts: &Rc<FontConfiguration>,
+        infos: &'a [GlyphInfo],
+        style: &TextStyle,
+    ) -> anyhow::Result<Vec<(&'a GlyphInfo, Rc<CachedGlyph<T>>)>> {
+        let prior = std::mem::replace(&mut self.fonts, Rc::clone(fonts));
+        let result = self.cached_glyphs(infos, style);
+        self.fonts = prior;
+        result
+    }
+
+    /// Render the glyphs of a cluster as a single glyph, with each
+    /// subsequent glyph overlaid on the first at the position given
+    /// by the shaper
+    fn load_composite(
+        &mut self,
+        infos: &[GlyphInfo],
         style: &TextStyle,
+        notdef_style: NotdefGlyphStyle,
     ) -> anyhow::Result<Rc<CachedGlyph<T>>> {
-        let metrics;
-        let glyph;
+        let font = self.fonts.resolve_font(style)?;
+        let metrics = font.metrics();
 
-        {
-            let font = self.fonts.resolve_font(style)?;
-            metrics = font.metrics();
-            glyph = font.rasterize_glyph(info.glyph_pos, info.font_idx)?;
+        let mut pen_x = PixelLength::zero();
+        let mut parts = vec![];
+        for info in infos {
+            let glyph = rasterize(&font, &metrics, info, notdef_style)?;
+            let left = pen_x + info.x_offset + glyph.bearing_x;
+            let top = info.y_offset + glyph.bearing_y;
+            pen_x += info.x_advance;
+            parts.push((left, top, glyph));
         }
+
+        // The shaper offsets are baked into the composite image
+        let mut info = infos[0].clone();
+        info.x_offset = PixelLength::zero();
+        info.y_offset = PixelLength::zero();
+        let glyph = composite_glyphs(parts);
+        let fit = GlyphFit::for_glyph(&font, style, &info, &glyph);
+        self.upload_glyph(&info, &metrics, glyph, fit)
+    }
+
+    /// Prepare a rasterized glyph for rendering, uploading its
+    /// image to the atlas
+    #[allow(clippy::float_cmp)]
+    fn upload_glyph(
+        &mut self,
+        info: &GlyphInfo,
+        metrics: &FontMetrics,
+        glyph: RasterizedGlyph,
+        fit: GlyphFit,
+    ) -> anyhow::Result<Rc<CachedGlyph<T>>> {
         let (cell_width, cell_height) = (metrics.cell_width, metrics.cell_height);
 
-        let scale = if PixelLength::new(glyph.height as f64) > cell_height * 1.5 {
-            // This is another way to detect overside glyph images
-            cell_height.get() / glyph.height as f64
+        let scale = glyph_scale(&glyph, metrics, info.num_cells, fit);
+
+        let (glyph, clipped_bearing_x) = clip_to_cells(glyph, info, metrics, fit);
+        // A clipped glyph has its offset folded into its bearing, so
+        // that the clipping and the position of the glyph agree
+        let x_offset = if clipped_bearing_x.is_some() {
+            PixelLength::zero()
         } else {
-            1.0f64
+            cell_relative_offset(info.x_offset, metrics) * scale
         };
         let glyph = if glyph.width == 0 || glyph.height == 0 {
             // a whitespace glyph
             CachedGlyph {
                 has_color: glyph.has_color,
                 texture: None,
-                x_offset: info.x_offset * scale,
+                x_offset,
                 y_offset: info.y_offset * scale,
                 bearing_x: PixelLength::zero(),
                 bearing_y: PixelLength::zero(),
@@ -217,11 +751,50 @@ impl<T: Texture2d> GlyphCache<T> {
                 &glyph.data,
             );
 
-            let bearing_x = glyph.bearing_x * scale;
+            let bearing_x = if let Some(bearing_x) = clipped_bearing_x {
+                bearing_x
+            } else if scale != 1.0 {
+                // Center the scaled glyph over the cells that it spans,
+                // as it generally won't fill them exactly; this is
+                // typically a color emoji occupying two cells
+                let span = cell_width.get() * info.num_cells.max(1) as f64;
+                let scaled_width = glyph.width as f64 * scale;
+                PixelLength::new(((span - scaled_width) / 2.).max(0.))
+            } else if info.font_idx == NOTDEF_FONT_IDX {
+                // The notdef glyph is already sized to fill its cells
+                glyph.bearing_x
+            } else {
+                glyph.bearing_x + cell_width_padding(metrics, info.num_cells)
+            };
             let bearing_y = glyph.bearing_y * scale;
-            let x_offset = info.x_offset * scale;
             let y_offset = info.y_offset * scale;
 
+            if fit == GlyphFit::SnapToCell {
+                // Ignore the metrics of the glyph and stretch it to span
+                // its cells exactly, from edge to edge
+                let (width, height, bearing_y) = snapped_geometry(metrics, info.num_cells);
+                if (width, height) != (glyph.width, glyph.height) {
+                    log::trace!(
+                        "snapping {:?} from {}x{} to {}x{}",
+                        info,
+                        glyph.width,
+                        glyph.height,
+                        width,
+                        height
+                    );
+                }
+                let tex = self.atlas.allocate(&raw_im.resize(width, height))?;
+                return Ok(Rc::new(CachedGlyph {
+                    has_color: glyph.has_color,
+                    texture: Some(tex),
+                    x_offset: PixelLength::zero(),
+                    y_offset: PixelLength::zero(),
+                    bearing_x: PixelLength::zero(),
+                    bearing_y,
+                    scale: 1.0,
+                }));
+            }
+
             let (scale, raw_im) = if scale != 1.0 {
                 log::trace!(
                     "physically scaling {:?} by {} bcos {}x{} > {}x{}",
@@ -281,3 +854,485 @@ impl<T: Texture2d> GlyphCache<T> {
         Ok(sprite)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+
+    fn styles() -> Vec<TextStyle> {
+        let mut styles = vec![];
+        for family in &[
+            "Fira Code",
+            "JetBrains Mono",
+            "Noto Color Emoji",
+            "monospace",
+        ] {
+            for &(bold, italic) in &[(false, false), (true, false), (false, true), (true, true)] {
+                styles.push(TextStyle {
+                    font: vec![
+                        FontAttributes {
+                            family: family.to_string(),
+                            bold,
+                            italic,
+                            ..Default::default()
+                        },
+                        FontAttributes::new("Noto Color Emoji"),
+                    ],
+                    foreground: None,
+                    harfbuzz_features: vec![],
+                });
+            }
+        }
+        styles
+    }
+
+    #[test]
+    fn style_ids() {
+        let styles = styles();
+        let mut table = StyleTable::default();
+        let ids: Vec<u32> = styles.iter().map(|style| table.id_for(style)).collect();
+        assert_eq!(ids, (0..styles.len() as u32).collect::<Vec<_>>());
+
+        // An equal style that is a distinct value maps to the same id
+        assert_eq!(table.id_for(&styles[3].clone()), 3);
+        assert_eq!(table.ids.len(), styles.len());
+    }
+
+    fn glyph_info(cluster: u32, glyph_pos: u32) -> GlyphInfo {
+        GlyphInfo {
+            #[cfg(debug_assertions)]
+            text: String::new(),
+            cluster,
+            num_cells: 1,
+            font_idx: 0,
+            glyph_pos,
+            x_advance: PixelLength::zero(),
+            y_advance: PixelLength::zero(),
+            x_offset: PixelLength::zero(),
+            y_offset: PixelLength::zero(),
+            snap_to_cell: false,
+        }
+    }
+
+    #[test]
+    fn notdef_glyph_keys_follow_the_style() {
+        let mut notdef = glyph_info(0, 0x2603);
+        notdef.font_idx = NOTDEF_FONT_IDX;
+        assert_ne!(
+            GlyphKey::new(0, 0, &notdef, NotdefGlyphStyle::HexBox),
+            GlyphKey::new(0, 0, &notdef, NotdefGlyphStyle::PlainBox)
+        );
+
+        // The style doesn't apply to glyphs from a font
+        let glyph = glyph_info(0, 4);
+        assert_eq!(
+            GlyphKey::new(0, 0, &glyph, NotdefGlyphStyle::HexBox),
+            GlyphKey::new(0, 0, &glyph, NotdefGlyphStyle::PlainBox)
+        );
+    }
+
+    fn solid_glyph(width: usize, height: usize) -> RasterizedGlyph {
+        RasterizedGlyph {
+            data: vec![0xff; width * height * 4],
+            height,
+            width,
+            bearing_x: PixelLength::zero(),
+            bearing_y: PixelLength::zero(),
+            has_color: false,
+        }
+    }
+
+    fn metrics() -> FontMetrics {
+        // 10x20 cells with the baseline 16 pixels from the top
+        FontMetrics {
+            cell_width: PixelLength::new(10.),
+            cell_height: PixelLength::new(20.),
+            descender: PixelLength::new(-4.),
+            underline_thickness: PixelLength::new(1.),
+            underline_position: PixelLength::new(-2.),
+        }
+    }
+
+    /// A powerline triangle from a patched font, which is a little too
+    /// large for the cell: it sticks out by a pixel on the right and
+    /// by two pixels above and below.
+    fn powerline_triangle() -> RasterizedGlyph {
+        let mut glyph = solid_glyph(11, 24);
+        glyph.bearing_y = PixelLength::new(18.);
+        glyph
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn powerline_glyphs_are_clipped() {
+        let metrics = metrics();
+        let glyph = powerline_triangle();
+
+        // Previously the glyph would have been scaled down to 20/24 of
+        // its size, leaving gaps above and below it; now it is unscaled
+        let clip = GlyphFit::Clip {
+            allow_square_overflow: false,
+            allow_overhang: false,
+        };
+        assert_eq!(glyph_scale(&glyph, &metrics, 1, clip), 1.0);
+        assert_eq!(
+            glyph_scale(&glyph, &metrics, 1, GlyphFit::ScaleToCell),
+            20. / 24.
+        );
+
+        // ...and it is clipped so that it exactly fills the cell
+        let (clipped, bearing_x) =
+            clip_glyph(glyph, PixelLength::zero(), &metrics, 1, false, false);
+        assert_eq!((clipped.width, clipped.height), (10, 20));
+        assert_eq!(bearing_x, PixelLength::new(0.));
+        assert_eq!(clipped.bearing_y, PixelLength::new(16.));
+        assert_eq!(clipped.data.len(), 10 * 20 * 4);
+    }
+
+    #[test]
+    fn glyphs_within_cells_are_not_clipped() {
+        let metrics = metrics();
+        let mut glyph = solid_glyph(6, 10);
+        glyph.bearing_y = PixelLength::new(10.);
+        let (clipped, bearing_x) =
+            clip_glyph(glyph, PixelLength::new(2.), &metrics, 1, false, false);
+        assert_eq!((clipped.width, clipped.height), (6, 10));
+        assert_eq!(bearing_x, PixelLength::new(2.));
+        assert_eq!(clipped.bearing_y, PixelLength::new(10.));
+    }
+
+    #[test]
+    fn square_glyphs_may_overflow() {
+        let metrics = metrics();
+        let icon = || {
+            let mut glyph = solid_glyph(16, 16);
+            glyph.bearing_y = PixelLength::new(14.);
+            glyph
+        };
+
+        let (clipped, _) = clip_glyph(icon(), PixelLength::zero(), &metrics, 1, false, false);
+        assert_eq!((clipped.width, clipped.height), (10, 16));
+
+        let (clipped, _) = clip_glyph(icon(), PixelLength::zero(), &metrics, 1, true, false);
+        assert_eq!((clipped.width, clipped.height), (16, 16));
+    }
+
+    #[test]
+    fn italic_glyphs_keep_their_overhang() {
+        let metrics = metrics();
+        // A slanted glyph whose top right overhangs the next cell, and
+        // whose descender extends below its cell
+        let slanted = || {
+            let mut glyph = solid_glyph(13, 24);
+            glyph.bearing_y = PixelLength::new(20.);
+            glyph
+        };
+
+        let (clipped, _) = clip_glyph(slanted(), PixelLength::zero(), &metrics, 1, false, false);
+        assert_eq!((clipped.width, clipped.height), (10, 20));
+
+        // Only the vertical overflow is clipped from italic glyphs
+        let (clipped, bearing_x) =
+            clip_glyph(slanted(), PixelLength::zero(), &metrics, 1, false, true);
+        assert_eq!((clipped.width, clipped.height), (13, 20));
+        assert_eq!(bearing_x, PixelLength::zero());
+    }
+
+    #[test]
+    fn proportional_fallbacks_stay_in_their_cells() {
+        let metrics = metrics();
+        let clip = GlyphFit::Clip {
+            allow_square_overflow: false,
+            allow_overhang: false,
+        };
+
+        // "Wil" from a proportional fallback font, whose advances and
+        // offsets bear no relation to the 10 pixel cells.  Laid out by
+        // its advances, the "l" would start 18.3 pixels in, rather
+        // than at the start of the third cell at 20 pixels.
+        let glyphs = vec![
+            (14, 0.4, 13.7, 0.37),
+            (3, 3.5, 4.6, -0.23),
+            (4, 6.2, 5.1, 0.61),
+        ];
+        for (idx, &(width, bearing_x, advance, x_offset)) in glyphs.iter().enumerate() {
+            let mut info = glyph_info(idx as u32, idx as u32);
+            info.x_advance = PixelLength::new(advance);
+            info.x_offset = PixelLength::new(x_offset);
+            let mut glyph = solid_glyph(width, 12);
+            glyph.bearing_x = PixelLength::new(bearing_x);
+            glyph.bearing_y = PixelLength::new(12.);
+
+            // The position is relative to the origin of the cell, and
+            // so doesn't depend on the glyphs that came before it
+            let (clipped, left) = clip_to_cells(glyph, &info, &metrics, clip);
+            let left = left.unwrap().get();
+            assert!(left >= 0., "glyph {} starts at {}", idx, left);
+            assert!(
+                left.round() + clipped.width as f64 <= 10.,
+                "glyph {} extends to {}",
+                idx,
+                left + clipped.width as f64
+            );
+        }
+
+        // Unless clipping is disabled, in which case the glyph is unchanged
+        let mut info = glyph_info(0, 0);
+        info.x_offset = PixelLength::new(0.37);
+        let (glyph, left) = clip_to_cells(solid_glyph(14, 12), &info, &metrics, GlyphFit::Overflow);
+        assert_eq!(glyph.width, 14);
+        assert!(left.is_none());
+    }
+
+    #[test]
+    fn offsets_are_rounded_to_hundredths_of_a_cell() {
+        let metrics = metrics();
+        let offset = |x: f64| cell_relative_offset(PixelLength::new(x), &metrics).get();
+        assert!((offset(0.37) - 0.4).abs() < 1e-9);
+        assert!((offset(-0.23) + 0.2).abs() < 1e-9);
+        assert!((offset(12.34) - 12.3).abs() < 1e-9);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn emoji_are_scaled_to_fit() {
+        let metrics = metrics();
+        let mut emoji = solid_glyph(136, 128);
+        emoji.has_color = true;
+        // The width is the limiting dimension of the two cells
+        assert_eq!(
+            glyph_scale(&emoji, &metrics, 2, GlyphFit::ScaleToCell),
+            20. / 136.
+        );
+        // Glyphs are never scaled up
+        assert_eq!(
+            glyph_scale(&solid_glyph(4, 4), &metrics, 1, GlyphFit::ScaleToCell),
+            1.0
+        );
+    }
+
+    #[test]
+    fn powerline_separators_snap() {
+        use crate::font::shaper::snaps_to_cell;
+        assert!(snaps_to_cell("\u{e0b0}"));
+        assert!(snaps_to_cell("\u{e0b3}"));
+        assert!(snaps_to_cell("\u{e0c6}"));
+        // The branch symbol and ordinary text are left alone
+        assert!(!snaps_to_cell("\u{e0a0}"));
+        assert!(!snaps_to_cell("a"));
+        assert!(!snaps_to_cell("\u{e0b0}\u{e0b0}"));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn snapped_glyphs_fill_their_cells() {
+        let mut metrics = metrics();
+        let (width, height, bearing_y) = snapped_geometry(&metrics, 1);
+        assert_eq!((width, height), (10, 20));
+        // The top of the glyph is at the top of the cell
+        assert_eq!(bearing_y.get(), 16.);
+
+        // Fractional cells are rounded up, as they are when laid out
+        metrics.cell_width = PixelLength::new(9.4);
+        metrics.cell_height = PixelLength::new(19.2);
+        let (width, height, bearing_y) = snapped_geometry(&metrics, 2);
+        assert_eq!((width, height), (20, 20));
+        assert_eq!(bearing_y.get(), 16.);
+    }
+
+    #[test]
+    fn clusters() {
+        // "e" followed by a combining mark, then "x"
+        let infos = vec![glyph_info(0, 1), glyph_info(0, 2), glyph_info(3, 3)];
+        assert_eq!(cluster_ranges(&infos), vec![0..2, 2..3]);
+        assert!(cluster_ranges(&[]).is_empty());
+    }
+
+    #[test]
+    fn composite() {
+        // A 2x2 base glyph sitting on the baseline, with a 1x1 mark
+        // above its right half
+        let parts = vec![
+            (
+                PixelLength::new(0.),
+                PixelLength::new(2.),
+                solid_glyph(2, 2),
+            ),
+            (
+                PixelLength::new(1.),
+                PixelLength::new(3.),
+                solid_glyph(1, 1),
+            ),
+        ];
+        let glyph = composite_glyphs(parts);
+        assert_eq!((glyph.width, glyph.height), (2, 3));
+        assert_eq!(glyph.bearing_x, PixelLength::new(0.));
+        assert_eq!(glyph.bearing_y, PixelLength::new(3.));
+
+        let alpha: Vec<u8> = glyph.data.chunks(4).map(|pixel| pixel[3]).collect();
+        assert_eq!(alpha, vec![0, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn usage() {
+        let fonts = Rc::new(FontConfiguration::new());
+        let metrics = metrics();
+        let mut cache = GlyphCache::new(&fonts, 512);
+        assert_eq!(cache.usage().glyphs, 0);
+        assert_eq!(cache.usage().sprite_bytes, 0);
+
+        let styles = styles();
+        let mut sprite_bytes = 0;
+        for style in &styles {
+            let style_id = cache.styles.id_for(style);
+            for glyph_pos in 0..4 {
+                let info = glyph_info(0, glyph_pos);
+                let glyph = cache
+                    .upload_glyph(&info, &metrics, solid_glyph(10, 20), GlyphFit::ScaleToCell)
+                    .unwrap();
+                let size = glyph.texture.as_ref().unwrap().coords.size;
+                sprite_bytes += size.width as usize * size.height as usize * 4;
+                cache.glyph_cache.insert(
+                    GlyphKey {
+                        fonts_id: 0,
+                        font_idx: 0,
+                        glyph_pos,
+                        style_id,
+                        snap_to_cell: false,
+                        notdef_style: None,
+                    },
+                    glyph,
+                );
+            }
+        }
+
+        let usage = cache.usage();
+        assert_eq!(usage.glyphs, styles.len() * 4);
+        assert_eq!(usage.composites, 0);
+        assert_eq!(usage.styles, styles.len());
+        assert_eq!(usage.sprite_bytes, sprite_bytes);
+        assert!(usage.overhead_bytes > cache.styles.bytes());
+
+        // Each style is held once by the table, rather than by each of
+        // the keys of its glyphs, so more glyphs don't hold more styles
+        let style_bytes = cache.styles.bytes();
+        let info = glyph_info(0, 4);
+        let glyph = cache
+            .upload_glyph(&info, &metrics, solid_glyph(10, 20), GlyphFit::ScaleToCell)
+            .unwrap();
+        cache.glyph_cache.insert(
+            GlyphKey {
+                fonts_id: 0,
+                font_idx: 0,
+                glyph_pos: 4,
+                style_id: cache.styles.id_for(&styles[0]),
+                snap_to_cell: false,
+                notdef_style: None,
+            },
+            glyph,
+        );
+        assert_eq!(cache.styles.bytes(), style_bytes);
+        assert_eq!(cache.usage().glyphs, styles.len() * 4 + 1);
+    }
+
+    /// Compares populating and then probing a cache whose keys hold
+    /// a copy of the TextStyle, as GlyphKey used to, with one whose
+    /// keys hold interned style ids.
+    /// Run with `cargo test --release glyph_key_bench -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn glyph_key_bench() {
+        #[derive(PartialEq, Eq, Hash)]
+        struct StyleKey {
+            font_idx: usize,
+            glyph_pos: u32,
+            style: TextStyle,
+        }
+
+        const GLYPHS: u32 = 2000;
+        const ROUNDS: usize = 20;
+        let styles = styles();
+
+        let start = Instant::now();
+        let mut by_style = HashMap::new();
+        for style in &styles {
+            for glyph_pos in 0..GLYPHS {
+                by_style.insert(
+                    StyleKey {
+                        font_idx: 0,
+                        glyph_pos,
+                        style: style.clone(),
+                    },
+                    glyph_pos,
+                );
+            }
+        }
+        let style_insert = start.elapsed();
+
+        let start = Instant::now();
+        let mut table = StyleTable::default();
+        let mut by_id = HashMap::new();
+        for style in &styles {
+            for glyph_pos in 0..GLYPHS {
+                by_id.insert(
+                    GlyphKey {
+                        fonts_id: 0,
+                        font_idx: 0,
+                        glyph_pos,
+                        style_id: table.id_for(style),
+                        snap_to_cell: false,
+                        notdef_style: None,
+                    },
+                    glyph_pos,
+                );
+            }
+        }
+        let id_insert = start.elapsed();
+
+        // The probe keys are built up front, so that only the cost
+        // of hashing and comparing the keys is measured
+        let probes: Vec<StyleKey> = styles
+            .iter()
+            .map(|style| StyleKey {
+                font_idx: 0,
+                glyph_pos: GLYPHS / 2,
+                style: style.clone(),
+            })
+            .collect();
+        let start = Instant::now();
+        let mut found = 0;
+        for _ in 0..ROUNDS {
+            for probe in &probes {
+                for _ in 0..GLYPHS {
+                    found += by_style.get(probe).is_some() as usize;
+                }
+            }
+        }
+        let style_lookup = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            for style in &styles {
+                for _ in 0..GLYPHS {
+                    let key = GlyphKey {
+                        fonts_id: 0,
+                        font_idx: 0,
+                        glyph_pos: GLYPHS / 2,
+                        style_id: table.id_for(style),
+                        snap_to_cell: false,
+                        notdef_style: None,
+                    };
+                    found += by_id.get(&key).is_some() as usize;
+                }
+            }
+        }
+        let id_lookup = start.elapsed();
+
+        assert_eq!(found, 2 * ROUNDS * styles.len() * GLYPHS as usize);
+        println!(
+            "insert: style {:?} id {:?}, lookup: style {:?} id {:?}",
+            style_insert, id_insert, style_lookup, id_lookup
+        );
+    }
+}
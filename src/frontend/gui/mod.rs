@@ -1,4 +1,4 @@
-use crate::config::configuration;
+use crate::config::{configuration, WindowConfigOverrides};
 use crate::font::FontConfiguration;
 use crate::frontend::FrontEnd;
 use crate::mux::tab::Tab;
@@ -8,15 +8,23 @@ use ::window::*;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
+mod atlasview;
+mod bell;
+mod filedrop;
+mod filelink;
 mod glyphcache;
 mod overlay;
+mod postprocess;
 mod quad;
 mod renderstate;
+mod resizebadge;
 mod scrollbar;
 mod selection;
+mod status;
 mod tabbar;
 mod termwindow;
 mod utilsprites;
+mod workspace;
 
 pub use selection::SelectionMode;
 
@@ -50,6 +58,11 @@ impl GuiFrontEnd {
             }
         }
         let connection = Connection::init()?;
+        // Clicking the Dock icon opens a window again after the last
+        // one was closed with quit_when_all_windows_are_closed = false
+        #[cfg(target_os = "macos")]
+        ::window::os::macos::set_reopen_handler(termwindow::spawn_window_in_default_domain);
+        crate::appearance::start_watching();
         let front_end = Rc::new(GuiFrontEnd { connection });
         Ok(front_end)
     }
@@ -62,7 +75,13 @@ impl FrontEnd for GuiFrontEnd {
                 if crate::frontend::activity::Activity::count() == 0 {
                     let mux = Mux::get().unwrap();
                     mux.prune_dead_windows();
-                    if mux.is_empty() {
+                    // Each window closes itself once it has no more tabs;
+                    // the process sticks around until the last one is gone.
+                    // Only macOS can open a window again after that, from
+                    // the Dock, so elsewhere the process always exits.
+                    let keep_running = cfg!(target_os = "macos")
+                        && !configuration().quit_when_all_windows_are_closed;
+                    if mux.is_empty() && !keep_running {
                         Connection::get().unwrap().terminate_message_loop();
                     }
                 }
@@ -76,7 +95,8 @@ impl FrontEnd for GuiFrontEnd {
         fontconfig: &Rc<FontConfiguration>,
         tab: &Rc<dyn Tab>,
         window_id: MuxWindowId,
+        overrides: &WindowConfigOverrides,
     ) -> anyhow::Result<()> {
-        termwindow::TermWindow::new_window(&configuration(), fontconfig, tab, window_id)
+        termwindow::TermWindow::new_window(&configuration(), fontconfig, tab, window_id, overrides)
     }
 }
@@ -1,3 +1,5 @@
+use crate::config::{configuration, TabColorOverrides};
+use crate::frontend::gui::overlay::{alloc_overlay_id, OverlayId};
 use crate::frontend::gui::selection::{SelectionCoordinate, SelectionRange};
 use crate::frontend::gui::termwindow::TermWindow;
 use crate::mux::domain::DomainId;
@@ -9,12 +11,10 @@ use std::cell::{RefCell, RefMut};
 use std::ops::Range;
 use std::rc::Rc;
 use std::sync::Arc;
-use unicode_segmentation::*;
+use unicode_segmentation::UnicodeSegmentation;
 use url::Url;
 use wezterm_term::color::ColorPalette;
-use wezterm_term::{
-    unicode_column_width, Clipboard, KeyCode, KeyModifiers, Line, MouseEvent, StableRowIndex,
-};
+use wezterm_term::{Clipboard, KeyCode, KeyModifiers, Line, MouseEvent, StableRowIndex};
 use window::WindowOps;
 
 pub struct CopyOverlay {
@@ -29,6 +29,7 @@ struct CopyRenderable {
     viewport: Option<StableRowIndex>,
     /// We use this to cancel ourselves later
     window: ::window::Window,
+    overlay_id: OverlayId,
 }
 
 struct Dimensions {
@@ -37,23 +38,56 @@ struct Dimensions {
     top: StableRowIndex,
 }
 
+/// Returns the cell ranges of the words in `line`.
+/// The line is split using Unicode word segmentation, so that eg:
+/// each CJK ideograph is a word of its own, and then the segments that
+/// consist only of whitespace and `boundary` characters are discarded.
+fn word_ranges(line: &Line, boundary: &str) -> Vec<Range<usize>> {
+    // Map the byte offset of each grapheme in the text of the line
+    // back to the cell that holds it
+    let mut text = String::new();
+    let mut cell_offsets = vec![];
+    for (idx, cell) in line.visible_cells() {
+        cell_offsets.push((text.len(), idx));
+        text.push_str(cell.str());
+    }
+    let num_cells = line.cells().len();
+    let cell_at = |offset: usize| match cell_offsets.binary_search_by_key(&offset, |&(o, _)| o) {
+        Ok(i) | Err(i) => cell_offsets
+            .get(i)
+            .map(|&(_, idx)| idx)
+            .unwrap_or(num_cells),
+    };
+
+    text.split_word_bound_indices()
+        .filter(|(_, word)| {
+            word.chars()
+                .any(|c| !c.is_whitespace() && !boundary.contains(c))
+        })
+        .map(|(offset, word)| cell_at(offset)..cell_at(offset + word.len()))
+        .collect()
+}
+
 impl CopyOverlay {
-    pub fn with_tab(term_window: &TermWindow, tab: &Rc<dyn Tab>) -> Rc<dyn Tab> {
+    pub fn with_tab(term_window: &TermWindow, tab: &Rc<dyn Tab>) -> (OverlayId, Rc<dyn Tab>) {
         let mut cursor = tab.renderer().get_cursor_position();
         cursor.shape = termwiz::surface::CursorShape::SteadyBlock;
 
         let window = term_window.window.clone().unwrap();
+        let overlay_id = alloc_overlay_id();
         let render = CopyRenderable {
             cursor,
             window,
+            overlay_id,
             delegate: Rc::clone(tab),
             start: None,
             viewport: term_window.get_viewport(tab.tab_id()),
         };
-        Rc::new(CopyOverlay {
+        let overlay = Rc::new(CopyOverlay {
             delegate: Rc::clone(tab),
             render: RefCell::new(render),
-        })
+        });
+        (overlay_id, overlay)
     }
 
     pub fn viewport_changed(&self, viewport: Option<StableRowIndex>) {
@@ -105,6 +139,7 @@ impl CopyRenderable {
                 let mut selection = term_window.selection(tab_id);
                 selection.start = Some(start);
                 selection.range = Some(range);
+                selection.rectangular = false;
                 window.invalidate();
             }
             Ok(())
@@ -163,7 +198,11 @@ impl CopyRenderable {
 
     fn close(&self) {
         self.set_viewport(None);
-        TermWindow::schedule_cancel_overlay(self.window.clone(), self.delegate.tab_id());
+        TermWindow::schedule_cancel_overlay(
+            self.window.clone(),
+            self.delegate.tab_id(),
+            self.overlay_id,
+        );
     }
 
     fn page_up(&mut self) {
@@ -270,96 +309,82 @@ impl CopyRenderable {
         self.select_to_cursor_pos();
     }
 
-    fn move_backward_one_word(&mut self) {
-        let y = if self.cursor.x == 0 && self.cursor.y > 0 {
-            self.cursor.x = usize::max_value();
-            self.cursor.y.saturating_sub(1)
-        } else {
-            self.cursor.y
-        };
-
-        let (top, lines) = self.get_lines(y..y + 1);
-        if let Some(line) = lines.get(0) {
-            self.cursor.y = top;
-            if self.cursor.x == usize::max_value() {
-                self.cursor.x = line.cells().len().saturating_sub(1);
-            }
-            let s = line.columns_as_str(0..self.cursor.x.saturating_add(1));
-
-            // "hello there you"
-            //              |_
-            //        |    _
-            //  |    _
-            //        |     _
-            //  |     _
-
-            let mut last_was_whitespace = false;
-
-            for (idx, word) in s.split_word_bounds().rev().enumerate() {
-                let width = unicode_column_width(word);
-
-                if is_whitespace_word(word) {
-                    self.cursor.x = self.cursor.x.saturating_sub(width);
-                    last_was_whitespace = true;
-                    continue;
-                }
-                last_was_whitespace = false;
-
-                if idx == 0 && width == 1 {
-                    // We were at the start of the initial word
-                    self.cursor.x = self.cursor.x.saturating_sub(width);
-                    continue;
-                }
+    /// Returns the cell ranges of the words in the line at `y`,
+    /// along with the number of cells in the line
+    fn words(&mut self, y: StableRowIndex) -> (Vec<Range<usize>>, usize) {
+        let config = configuration();
+        let (_top, lines) = self.get_lines(y..y + 1);
+        match lines.get(0) {
+            Some(line) => (
+                word_ranges(line, &config.selection_word_boundary),
+                line.cells().len(),
+            ),
+            None => (vec![], 0),
+        }
+    }
 
-                self.cursor.x = self.cursor.x.saturating_sub(width.saturating_sub(1));
+    fn move_backward_one_word(&mut self) {
+        let dims = self.delegate.renderer().get_dimensions();
+        let mut y = self.cursor.y;
+        let (mut words, _) = self.words(y);
+        let mut x = Some(self.cursor.x);
+
+        // Find the start of the word before the cursor, continuing on
+        // the prior lines if we reach the start of this one
+        loop {
+            if let Some(word) = words
+                .iter()
+                .rev()
+                .find(|word| x.map(|x| word.start < x).unwrap_or(true))
+            {
+                self.cursor.x = word.start;
                 break;
             }
-
-            if last_was_whitespace && self.cursor.y > 0 {
-                // The line begins with whitespace
-                self.cursor.x = usize::max_value();
-                self.cursor.y -= 1;
-                return self.move_backward_one_word();
+            if y > dims.scrollback_top {
+                y -= 1;
+                words = self.words(y).0;
+                x = None;
+            } else {
+                self.cursor.x = 0;
+                break;
             }
         }
+
+        self.cursor.y = y;
         self.select_to_cursor_pos();
     }
 
     fn move_forward_one_word(&mut self) {
-        let y = self.cursor.y;
-        let (top, lines) = self.get_lines(y..y + 1);
-        if let Some(line) = lines.get(0) {
-            self.cursor.y = top;
-            let width = line.cells().len();
-            let s = line.columns_as_str(self.cursor.x..width + 1);
-            let mut words = s.split_word_bounds();
-
-            if let Some(word) = words.next() {
-                self.cursor.x += unicode_column_width(word);
-                if !is_whitespace_word(word) {
-                    // We were part-way through a word, so look
-                    // at the next word
-                    if let Some(word) = words.next() {
-                        if is_whitespace_word(word) {
-                            self.cursor.x += unicode_column_width(word);
-                            // If we advance off the RHS, move to the start of the word on the
-                            // next line, if any!
-                            if self.cursor.x >= width {
-                                let dims = self.delegate.renderer().get_dimensions();
-                                let max_row = dims.scrollback_top + dims.scrollback_rows as isize;
-                                if self.cursor.y + 1 < max_row {
-                                    self.cursor.y += 1;
-                                    return self.move_to_start_of_line_content();
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    // We were in whitespace and advancing
-                    // has put us at the start of the next word
-                }
+        let dims = self.delegate.renderer().get_dimensions();
+        let max_row = dims.scrollback_top + dims.scrollback_rows as isize;
+        let mut y = self.cursor.y;
+        let (mut words, mut num_cells) = self.words(y);
+        let mut x = Some(self.cursor.x);
+
+        // Find the start of the word after the cursor, continuing on
+        // the following lines if we reach the end of this one
+        loop {
+            if let Some(word) = words
+                .iter()
+                .find(|word| x.map(|x| word.start > x).unwrap_or(true))
+            {
+                self.cursor.x = word.start;
+                break;
+            }
+            if y + 1 < max_row {
+                y += 1;
+                let (next_words, next_num_cells) = self.words(y);
+                words = next_words;
+                num_cells = next_num_cells;
+                x = None;
+            } else {
+                // There are no more words; stay on the last cell
+                self.cursor.x = num_cells.saturating_sub(1);
+                break;
             }
         }
+
+        self.cursor.y = y;
         self.select_to_cursor_pos();
     }
 
@@ -392,6 +417,10 @@ impl Tab for CopyOverlay {
         anyhow::bail!("ignoring paste while copying");
     }
 
+    fn send_text(&self, text: &str) -> anyhow::Result<()> {
+        self.delegate.send_text(text)
+    }
+
     fn reader(&self) -> anyhow::Result<Box<dyn std::io::Read + Send>> {
         panic!("do not call reader on CopyOverlay bar tab instance");
     }
@@ -493,6 +522,10 @@ impl Tab for CopyOverlay {
         self.delegate.is_dead()
     }
 
+    fn reap(&self) {
+        self.delegate.reap()
+    }
+
     fn palette(&self) -> ColorPalette {
         self.delegate.palette()
     }
@@ -505,6 +538,14 @@ impl Tab for CopyOverlay {
         self.delegate.erase_scrollback()
     }
 
+    fn reset_terminal(&self) {
+        self.delegate.reset_terminal()
+    }
+
+    fn color_overrides(&self) -> TabColorOverrides {
+        self.delegate.color_overrides()
+    }
+
     fn is_mouse_grabbed(&self) -> bool {
         // Force grabbing off while we're searching
         false
@@ -535,12 +576,28 @@ impl Renderable for CopyRenderable {
     fn get_dimensions(&self) -> RenderableDimensions {
         self.delegate.renderer().get_dimensions()
     }
+
+    fn is_reverse_video(&self) -> bool {
+        self.delegate.renderer().is_reverse_video()
+    }
 }
 
-fn is_whitespace_word(word: &str) -> bool {
-    if let Some(c) = word.chars().next() {
-        c.is_whitespace()
-    } else {
-        false
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn words_are_segmented() {
+        let boundary = " \t\n{}[]()\"'`";
+        let mut line = Line::with_width(16);
+        line.overlay_text_with_attribute(0, "foo(bar) 漢字", Default::default());
+        assert_eq!(
+            word_ranges(&line, boundary),
+            vec![0..3, 4..7, 9..11, 11..13]
+        );
+
+        let mut line = Line::with_width(16);
+        line.overlay_text_with_attribute(0, "  can't stop", Default::default());
+        assert_eq!(word_ranges(&line, boundary), vec![2..7, 8..12]);
     }
 }
@@ -0,0 +1,286 @@
+//! The debug overlay shows the most recent log records, including
+//! escape sequences that the terminal didn't understand, so that
+//! issues can be diagnosed without restarting wezterm with RUST_LOG
+//! set.  A simple command line allows clearing the records and
+//! adjusting the level of records that are retained.
+use crate::frontend::gui::glyphcache::GlyphCacheUsage;
+use crate::frontend::gui::overlay::prompt::{complete_words, read_line, PromptHost};
+use crate::logging::{self, LogEntry};
+use crate::mux::latency;
+use crate::mux::tab::TabId;
+use crate::termwiztermtab::TermWizTerminal;
+use log::{Level, LevelFilter};
+use std::time::UNIX_EPOCH;
+use termwiz::cell::{grapheme_column_width, AttributeChange, CellAttributes};
+use termwiz::color::{AnsiColor, ColorAttribute};
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+use unicode_segmentation::UnicodeSegmentation;
+
+const HELP: &str = "commands: clear, level <off|error|warn|info|debug|trace>";
+
+/// Returns the longest prefix of `s` that fits within `width` cells
+fn truncate_to_width(s: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (idx, g) in s.grapheme_indices(true) {
+        used += grapheme_column_width(g);
+        if used > width {
+            return &s[..idx];
+        }
+    }
+    s
+}
+
+fn format_time(entry: &LogEntry) -> String {
+    let since_epoch = entry.when.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() % 86400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Expands the log records into display lines, each paired with the
+/// level of the record that produced it
+fn display_lines(entries: &[LogEntry]) -> Vec<(Level, String)> {
+    let mut lines = vec![];
+    for entry in entries {
+        let mut msg_lines = entry.msg.lines();
+        let first = msg_lines.next().unwrap_or("");
+        lines.push((
+            entry.level,
+            format!(
+                "{} {:5} {} > {}",
+                format_time(entry),
+                entry.level,
+                entry.target,
+                first
+            ),
+        ));
+        for line in msg_lines {
+            lines.push((entry.level, format!("    {}", line)));
+        }
+    }
+    lines
+}
+
+fn render(
+    tab_id: TabId,
+    glyph_cache: &GlyphCacheUsage,
+    scroll_back: usize,
+    status: &str,
+    term: &mut TermWizTerminal,
+) -> anyhow::Result<usize> {
+    let size = term.get_screen_size()?;
+    let lines = display_lines(&logging::get_entries());
+
+    // One row for the header and one for the status line
+    let log_rows = size.rows.saturating_sub(2);
+    let scroll_back = scroll_back.min(lines.len().saturating_sub(log_rows));
+    let end = lines.len() - scroll_back;
+    let start = end.saturating_sub(log_rows);
+
+    let mut changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(0),
+        },
+        Change::Text(
+            truncate_to_width(
+                "Debug Overlay: PageUp/PageDown to scroll, ':' to enter a command, \
+                 Escape to close",
+                size.cols,
+            )
+            .to_string(),
+        ),
+    ];
+
+    for (row, (level, line)) in lines[start..end].iter().enumerate() {
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(row + 1),
+        });
+        match level {
+            Level::Error => changes.push(AttributeChange::Foreground(AnsiColor::Red.into()).into()),
+            Level::Warn => {
+                changes.push(AttributeChange::Foreground(AnsiColor::Yellow.into()).into())
+            }
+            Level::Info => {}
+            Level::Debug | Level::Trace => {
+                changes.push(AttributeChange::Foreground(AnsiColor::Grey.into()).into())
+            }
+        }
+        changes.push(Change::Text(truncate_to_width(line, size.cols).to_string()));
+        changes.push(Change::AllAttributes(CellAttributes::default()));
+    }
+
+    let status = if status.is_empty() {
+        format!(
+            "level: {} | {} records{}{} | glyph cache {}",
+            logging::ring_level(),
+            lines.len(),
+            if scroll_back > 0 {
+                format!(" | scrolled back {} lines", scroll_back)
+            } else {
+                String::new()
+            },
+            match latency::stats(tab_id) {
+                Some(stats) => format!(" | latency {}", stats),
+                None => String::new(),
+            },
+            glyph_cache
+        )
+    } else {
+        status.to_string()
+    };
+    changes.push(Change::CursorPosition {
+        x: Position::Absolute(0),
+        y: Position::Absolute(size.rows.saturating_sub(1)),
+    });
+    changes.push(AttributeChange::Reverse(true).into());
+    changes.push(Change::Text(format!(
+        "{:width$}",
+        truncate_to_width(&status, size.cols),
+        width = size.cols
+    )));
+    changes.push(AttributeChange::Reverse(false).into());
+
+    term.render(&changes)?;
+    term.flush()?;
+
+    Ok(scroll_back)
+}
+
+/// Parses and performs a command, returning the text to show in
+/// the status line
+fn run_command(line: &str) -> String {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next()) {
+        (None, _) => String::new(),
+        (Some("clear"), None) => {
+            logging::clear_entries();
+            String::new()
+        }
+        (Some("level"), Some(level)) => match level.parse::<LevelFilter>() {
+            Ok(level) => {
+                logging::set_ring_level(level);
+                format!("now retaining records at level {}", level)
+            }
+            Err(_) => format!("invalid level `{}`; {}", level, HELP),
+        },
+        (Some("help"), None) => HELP.to_string(),
+        _ => format!("unknown command `{}`; {}", line.trim(), HELP),
+    }
+}
+
+pub fn debug_overlay(
+    tab_id: TabId,
+    glyph_cache: GlyphCacheUsage,
+    mut term: TermWizTerminal,
+) -> anyhow::Result<()> {
+    // How many lines we are scrolled up from the most recent record
+    let mut scroll_back = 0;
+    let mut status = String::new();
+    let words = [
+        "clear", "help", "level", "off", "error", "warn", "info", "debug", "trace",
+    ];
+    let mut host = PromptHost::new("debug").with_completer(complete_words(
+        words.iter().map(|w| w.to_string()).collect(),
+    ));
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Debug".to_string())])?;
+
+    // Repaint as new records arrive
+    let waker = term.repaint_waker();
+    let _watch = logging::watch(Box::new(move || waker.wake()));
+
+    let mut generation = logging::ring_generation();
+    scroll_back = render(tab_id, &glyph_cache, scroll_back, &status, &mut term)?;
+
+    loop {
+        let page = term.get_screen_size()?.rows.saturating_sub(2).max(1);
+
+        match term.poll_input(None)? {
+            None | Some(InputEvent::Wake) => {
+                // Only re-render if new records arrived
+                let current = logging::ring_generation();
+                if current != generation {
+                    generation = current;
+                    scroll_back = render(tab_id, &glyph_cache, scroll_back, &status, &mut term)?;
+                }
+                continue;
+            }
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            })) => break,
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            })) => scroll_back += 1,
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            })) => scroll_back = scroll_back.saturating_sub(1),
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::PageUp,
+                ..
+            })) => scroll_back += page,
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::PageDown,
+                ..
+            })) => scroll_back = scroll_back.saturating_sub(page),
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::End, ..
+            })) => scroll_back = 0,
+            Some(InputEvent::Mouse(MouseEvent { mouse_buttons, .. })) => {
+                if mouse_buttons.contains(MouseButtons::VERT_WHEEL) {
+                    if mouse_buttons.contains(MouseButtons::WHEEL_POSITIVE) {
+                        scroll_back += 1;
+                    } else {
+                        scroll_back = scroll_back.saturating_sub(1);
+                    }
+                }
+            }
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(':'),
+                ..
+            }))
+            | Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Enter,
+                ..
+            })) => {
+                let rows = term.get_screen_size()?.rows;
+                term.render(&[
+                    Change::CursorPosition {
+                        x: Position::Absolute(0),
+                        y: Position::Absolute(rows.saturating_sub(1)),
+                    },
+                    Change::ClearToEndOfLine(ColorAttribute::Default),
+                ])?;
+
+                status = match read_line(&mut term, ":", &mut host)? {
+                    Some(line) => run_command(&line),
+                    None => String::new(),
+                };
+                generation = logging::ring_generation();
+                scroll_back = render(tab_id, &glyph_cache, scroll_back, &status, &mut term)?;
+                continue;
+            }
+            Some(InputEvent::Resized { .. }) => {}
+            _ => continue,
+        }
+
+        // Any interaction other than a command clears the previous status
+        status.clear();
+        scroll_back = render(tab_id, &glyph_cache, scroll_back, &status, &mut term)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! The export overlay asks where to save the text of a tab, which
+//! was extracted before the overlay started, and then saves it.
+use super::palette::prompt;
+use crate::frontend::gui::overlay::prompt::{complete_paths, PromptHost};
+use crate::mux::tab::TabId;
+use crate::termwiztermtab::TermWizTerminal;
+use std::path::{Path, PathBuf};
+use termwiz::input::{InputEvent, KeyEvent};
+use termwiz::surface::Change;
+use termwiz::terminal::Terminal;
+
+/// Writes `text` to `path`, returning a message that describes
+/// the outcome
+pub fn save_text(path: &Path, text: &str) -> String {
+    match std::fs::write(path, text) {
+        Ok(_) => format!("Saved {} lines to {}", text.lines().count(), path.display()),
+        Err(err) => format!("Failed to save to {}: {}", path.display(), err),
+    }
+}
+
+pub fn export_text(
+    _tab_id: TabId,
+    mut term: TermWizTerminal,
+    text: String,
+    default_path: PathBuf,
+) -> anyhow::Result<()> {
+    // Relative names are completed, and saved, alongside the default
+    let mut host = PromptHost::new("export")
+        .with_completer(complete_paths(default_path.parent().map(Path::to_path_buf)));
+    let line = match prompt(
+        &mut term,
+        &format!("Save to (leave empty for {}): ", default_path.display()),
+        &mut host,
+    )? {
+        Some(line) => line,
+        None => return Ok(()),
+    };
+    let path = match line.trim() {
+        "" => default_path,
+        name => {
+            let dir = default_path.parent().unwrap_or_else(|| Path::new("."));
+            dir.join(name)
+        }
+    };
+
+    term.render(&[Change::Text(format!(
+        "\r\n{}\r\nPress any key to continue",
+        save_text(&path, &text)
+    ))])?;
+    while let Some(event) = term.poll_input(None)? {
+        if let InputEvent::Key(KeyEvent { .. }) = event {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -5,20 +5,38 @@ use std::pin::Pin;
 use std::rc::Rc;
 
 mod copy;
+mod debug;
+mod export;
 mod launcher;
+mod palette;
+mod prompt;
 mod search;
 mod tabnavigator;
+mod workspace;
 
 pub use copy::CopyOverlay;
+pub use debug::debug_overlay;
+pub use export::{export_text, save_text};
 pub use launcher::launcher;
+pub use palette::{command_palette, palette_entries, workspace_entries, PaletteEntry};
 pub use search::SearchOverlay;
-pub use tabnavigator::tab_navigator;
+pub use tabnavigator::{tab_navigator, TabEntry};
+pub use workspace::rename_workspace;
+
+static OVERLAY_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+/// Identifies an overlay among those that are stacked over a tab
+pub type OverlayId = usize;
+
+pub fn alloc_overlay_id() -> OverlayId {
+    OVERLAY_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed)
+}
 
 pub fn start_overlay<T, F>(
     term_window: &TermWindow,
     tab: &Rc<dyn Tab>,
     func: F,
 ) -> (
+    OverlayId,
     Rc<dyn Tab>,
     Pin<Box<dyn std::future::Future<Output = Option<anyhow::Result<T>>>>>,
 )
@@ -27,6 +45,7 @@ where
     F: Send + 'static + FnOnce(TabId, TermWizTerminal) -> anyhow::Result<T>,
 {
     let tab_id = tab.tab_id();
+    let overlay_id = alloc_overlay_id();
     let dims = tab.renderer().get_dimensions();
     let (tw_term, tw_tab) = allocate(dims.cols, dims.viewport_rows);
 
@@ -34,9 +53,9 @@ where
 
     let future = promise::spawn::spawn_into_new_thread(move || {
         let res = func(tab_id, tw_term);
-        TermWindow::schedule_cancel_overlay(window, tab_id);
+        TermWindow::schedule_cancel_overlay(window, tab_id, overlay_id);
         res
     });
 
-    (tw_tab, Box::pin(future))
+    (overlay_id, tw_tab, Box::pin(future))
 }
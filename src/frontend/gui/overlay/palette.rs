@@ -0,0 +1,496 @@
+//! The command palette is an overlay that lists the actions that can
+//! be bound to keys, along with their current key binding.  Typing
+//! filters the list using a fuzzy match against the action label,
+//! and pressing Enter returns the selected action so that the gui
+//! can perform it.
+//! The workspace switcher is the same overlay, listing the workspaces.
+use crate::frontend::gui::overlay::prompt::{complete_paths, read_line, PromptHost};
+use crate::keyassignment::{
+    InputMap, KeyAssignment, ScrollbackCommand, SpawnCommand, SpawnTabDomain,
+};
+use crate::mux::tab::{ExportTextOptions, Pattern, TabId};
+use crate::termwiztermtab::TermWizTerminal;
+use std::path::PathBuf;
+use termwiz::cell::{AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+
+/// The number of rows used by the header and filter line
+const HEADER_ROWS: usize = 2;
+
+#[derive(Clone)]
+enum PaletteAction {
+    Assign(KeyAssignment),
+    /// Prompt for the tab number and then activate that tab
+    ActivateTab,
+    /// Prompt for a command line and then spawn it
+    SpawnCommand {
+        new_window: bool,
+    },
+}
+
+#[derive(Clone)]
+pub struct PaletteEntry {
+    label: String,
+    binding: Option<String>,
+    action: PaletteAction,
+}
+
+/// Builds the list of palette entries, resolving the current key
+/// binding for each of them from the supplied input map.
+pub fn palette_entries(input_map: &InputMap) -> Vec<PaletteEntry> {
+    use KeyAssignment::*;
+
+    let mut actions = vec![
+        (
+            "Spawn a new tab",
+            PaletteAction::Assign(SpawnTab(SpawnTabDomain::CurrentTabDomain)),
+        ),
+        (
+            "Spawn a new tab in the default domain",
+            PaletteAction::Assign(SpawnTab(SpawnTabDomain::DefaultDomain)),
+        ),
+        ("Spawn a new window", PaletteAction::Assign(SpawnWindow)),
+        (
+            "Spawn a command in a new tab...",
+            PaletteAction::SpawnCommand { new_window: false },
+        ),
+        (
+            "Spawn a command in a new window...",
+            PaletteAction::SpawnCommand { new_window: true },
+        ),
+        (
+            "Close the current tab",
+            PaletteAction::Assign(CloseCurrentTab),
+        ),
+        ("Activate tab...", PaletteAction::ActivateTab),
+        (
+            "Activate the previous tab",
+            PaletteAction::Assign(ActivateTabRelative(-1)),
+        ),
+        (
+            "Activate the next tab",
+            PaletteAction::Assign(ActivateTabRelative(1)),
+        ),
+        (
+            "Move the current tab left",
+            PaletteAction::Assign(MoveTabRelative(-1)),
+        ),
+        (
+            "Move the current tab right",
+            PaletteAction::Assign(MoveTabRelative(1)),
+        ),
+        (
+            "Show the tab navigator",
+            PaletteAction::Assign(ShowTabNavigator),
+        ),
+        (
+            "Rename the workspace of the window...",
+            PaletteAction::Assign(RenameWorkspace),
+        ),
+        (
+            "Switch to a workspace...",
+            PaletteAction::Assign(ShowWorkspaceSwitcher),
+        ),
+        ("Show the launcher", PaletteAction::Assign(ShowLauncher)),
+        ("Copy the selection", PaletteAction::Assign(Copy)),
+        ("Paste from the clipboard", PaletteAction::Assign(Paste)),
+        (
+            "Paste the primary selection",
+            PaletteAction::Assign(PastePrimarySelection),
+        ),
+        (
+            "Search the scrollback",
+            PaletteAction::Assign(Search(Pattern::CaseSensitiveString("".into()))),
+        ),
+        (
+            "Activate copy mode",
+            PaletteAction::Assign(ActivateCopyMode),
+        ),
+        (
+            "Clear the scrollback",
+            PaletteAction::Assign(ClearScrollback),
+        ),
+        ("Reset the terminal", PaletteAction::Assign(ResetTerminal)),
+        (
+            "Scroll up one page",
+            PaletteAction::Assign(ScrollByPage(-1)),
+        ),
+        (
+            "Scroll down one page",
+            PaletteAction::Assign(ScrollByPage(1)),
+        ),
+        (
+            "Increase the font size",
+            PaletteAction::Assign(IncreaseFontSize),
+        ),
+        (
+            "Decrease the font size",
+            PaletteAction::Assign(DecreaseFontSize),
+        ),
+        ("Reset the font size", PaletteAction::Assign(ResetFontSize)),
+        ("Hide the window", PaletteAction::Assign(Hide)),
+        (
+            "Show the debug overlay",
+            PaletteAction::Assign(ShowDebugOverlay),
+        ),
+        (
+            "Toggle the texture atlas view",
+            PaletteAction::Assign(ToggleTextureAtlas),
+        ),
+        (
+            "Toggle the input latency display",
+            PaletteAction::Assign(ToggleLatencyHud),
+        ),
+        (
+            "Start recording the output of the tab",
+            PaletteAction::Assign(StartRecording),
+        ),
+        (
+            "Stop recording the output of the tab",
+            PaletteAction::Assign(StopRecording),
+        ),
+        (
+            "Save the screen to a file",
+            PaletteAction::Assign(ExportText(ExportTextOptions::default())),
+        ),
+        (
+            "Save the scrollback to a file",
+            PaletteAction::Assign(ExportText(ExportTextOptions {
+                scrollback: true,
+                ..Default::default()
+            })),
+        ),
+        (
+            "Quick save the scrollback",
+            PaletteAction::Assign(QuickSave(ExportTextOptions {
+                scrollback: true,
+                ..Default::default()
+            })),
+        ),
+        (
+            "Open the scrollback in an editor",
+            PaletteAction::Assign(EmitScrollbackToCommand(ScrollbackCommand::default())),
+        ),
+        (
+            "Reload the configuration",
+            PaletteAction::Assign(ReloadConfiguration),
+        ),
+        (
+            "Edit the configuration file",
+            PaletteAction::Assign(OpenConfigFile),
+        ),
+        (
+            "Toggle Latin-1 decoding of the output",
+            PaletteAction::Assign(ToggleLatin1Encoding),
+        ),
+        ("Quit wezterm", PaletteAction::Assign(QuitApplication)),
+    ];
+
+    #[cfg(target_os = "macos")]
+    actions.push(("Hide wezterm", PaletteAction::Assign(HideApplication)));
+
+    actions
+        .drain(..)
+        .map(|(label, action)| {
+            let binding = match &action {
+                PaletteAction::Assign(assignment) => input_map.describe_key_for(assignment),
+                _ => None,
+            };
+            PaletteEntry {
+                label: label.to_string(),
+                binding,
+                action,
+            }
+        })
+        .collect()
+}
+
+/// Builds the entries that switch to the named workspaces; the
+/// title of the active tab of the window is shown alongside the name
+pub fn workspace_entries(workspaces: Vec<(String, String)>) -> Vec<PaletteEntry> {
+    workspaces
+        .into_iter()
+        .map(|(name, title)| PaletteEntry {
+            label: name.clone(),
+            binding: Some(title),
+            action: PaletteAction::Assign(KeyAssignment::SwitchToWorkspace(name)),
+        })
+        .collect()
+}
+
+/// Returns a score describing how well `filter` matches `label`,
+/// where lower is better, or None if the characters of `filter`
+/// do not appear in order in `label`.
+fn fuzzy_score(filter: &str, label: &str) -> Option<usize> {
+    let mut score = 0;
+    let mut last_match = None;
+    let mut haystack = label.char_indices();
+
+    for f in filter.chars().flat_map(char::to_lowercase) {
+        loop {
+            let (idx, c) = haystack.next()?;
+            if c.to_lowercase().eq(std::iter::once(f)) {
+                // Penalize gaps between consecutive matched characters,
+                // as well as a late start for the first one.
+                score += match last_match {
+                    Some(last) => idx - last - 1,
+                    None => idx,
+                };
+                last_match = Some(idx);
+                break;
+            }
+        }
+    }
+
+    Some(score)
+}
+
+/// Returns the indices of the entries that match the filter, best match first
+fn filter_entries(filter: &str, entries: &[PaletteEntry]) -> Vec<usize> {
+    let mut matches: Vec<(usize, usize)> = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, entry)| fuzzy_score(filter, &entry.label).map(|score| (score, idx)))
+        .collect();
+    // sort_by_key is stable, so equal scores retain the declaration order
+    matches.sort_by_key(|(score, _)| *score);
+    matches.into_iter().map(|(_, idx)| idx).collect()
+}
+
+/// Clears the overlay and reads a line of input using LineEditor.
+/// Returns Ok(None) if the user cancelled the prompt.
+pub(super) fn prompt(
+    term: &mut TermWizTerminal,
+    prompt: &str,
+    host: &mut PromptHost,
+) -> anyhow::Result<Option<String>> {
+    term.render(&[
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(0),
+        },
+    ])?;
+    read_line(term, prompt, host)
+}
+
+/// Resolves the final assignment for an entry, prompting for any
+/// arguments that it requires.
+fn resolve_action(
+    entry: &PaletteEntry,
+    term: &mut TermWizTerminal,
+    cwd: Option<PathBuf>,
+) -> anyhow::Result<Option<KeyAssignment>> {
+    match &entry.action {
+        PaletteAction::Assign(assignment) => Ok(Some(assignment.clone())),
+        PaletteAction::ActivateTab => {
+            let mut host = PromptHost::new("activate-tab");
+            loop {
+                let line = match prompt(
+                    term,
+                    "Tab number (1 is the first, -1 the last): ",
+                    &mut host,
+                )? {
+                    Some(line) => line,
+                    None => return Ok(None),
+                };
+                match line.trim().parse::<isize>() {
+                    Ok(n) if n > 0 => return Ok(Some(KeyAssignment::ActivateTab(n - 1))),
+                    Ok(n) if n < 0 => return Ok(Some(KeyAssignment::ActivateTab(n))),
+                    // Ask again until we get something sensible
+                    _ => {}
+                }
+            }
+        }
+        PaletteAction::SpawnCommand { new_window } => {
+            // Paths are completed relative to the directory of the tab
+            let mut host = PromptHost::new("spawn-command").with_completer(complete_paths(cwd));
+            let line = match prompt(
+                term,
+                "Command (leave empty for the default shell): ",
+                &mut host,
+            )? {
+                Some(line) => line,
+                None => return Ok(None),
+            };
+            let args: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+            let spawn = SpawnCommand {
+                args: if args.is_empty() { None } else { Some(args) },
+                ..SpawnCommand::default()
+            };
+            Ok(Some(if *new_window {
+                KeyAssignment::SpawnCommandInNewWindow(spawn)
+            } else {
+                KeyAssignment::SpawnCommandInNewTab(spawn)
+            }))
+        }
+    }
+}
+
+pub fn command_palette(
+    _tab_id: TabId,
+    mut term: TermWizTerminal,
+    title: &str,
+    entries: Vec<PaletteEntry>,
+    cwd: Option<PathBuf>,
+) -> anyhow::Result<Option<KeyAssignment>> {
+    let mut filter = String::new();
+    let mut matches = filter_entries(&filter, &entries);
+    let mut active_idx = 0;
+    let mut top_row = 0;
+
+    term.set_raw_mode()?;
+
+    fn render(
+        filter: &str,
+        matches: &[usize],
+        active_idx: usize,
+        top_row: usize,
+        entries: &[PaletteEntry],
+        term: &mut TermWizTerminal,
+    ) -> anyhow::Result<()> {
+        let size = term.get_screen_size()?;
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(
+                "Type to filter, press Enter to run the selected action.  \
+                Press Escape to cancel\r\n"
+                    .to_string(),
+            ),
+            Change::AllAttributes(CellAttributes::default()),
+            Change::Text(format!("> {}\r\n", filter)),
+        ];
+
+        let max_rows = size.rows.saturating_sub(HEADER_ROWS);
+        for (idx, entry_idx) in matches.iter().enumerate().skip(top_row).take(max_rows) {
+            let entry = &entries[*entry_idx];
+            if idx == active_idx {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+
+            let binding = entry.binding.as_ref().map(String::as_str).unwrap_or("");
+            // Right align the binding, falling back to a single space
+            // separator if the window is too narrow
+            let padding = size
+                .cols
+                .saturating_sub(entry.label.len() + binding.len() + 2)
+                .max(1);
+            changes.push(Change::Text(format!(
+                " {}{:padding$}{} \r\n",
+                entry.label,
+                "",
+                binding,
+                padding = padding
+            )));
+
+            if idx == active_idx {
+                changes.push(AttributeChange::Reverse(false).into());
+            }
+        }
+
+        term.render(&changes)?;
+        term.flush()
+    }
+
+    term.render(&[Change::Title(title.to_string())])?;
+    render(&filter, &matches, active_idx, top_row, &entries, &mut term)?;
+
+    while let Ok(Some(event)) = term.poll_input(None) {
+        match event {
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            }) => {
+                active_idx = active_idx.saturating_sub(1);
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            }) => {
+                active_idx = (active_idx + 1).min(matches.len().saturating_sub(1));
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }) => {
+                break;
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Backspace,
+                ..
+            }) => {
+                filter.pop();
+                matches = filter_entries(&filter, &entries);
+                active_idx = 0;
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('u'),
+                modifiers: Modifiers::CTRL,
+            }) => {
+                filter.clear();
+                matches = filter_entries(&filter, &entries);
+                active_idx = 0;
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers: Modifiers::NONE,
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers: Modifiers::SHIFT,
+            }) => {
+                filter.push(c);
+                matches = filter_entries(&filter, &entries);
+                active_idx = 0;
+            }
+            InputEvent::Mouse(MouseEvent {
+                y, mouse_buttons, ..
+            }) => {
+                let y = y as usize;
+                if y >= HEADER_ROWS && y - HEADER_ROWS + top_row < matches.len() {
+                    active_idx = y - HEADER_ROWS + top_row;
+
+                    if mouse_buttons == MouseButtons::LEFT {
+                        return resolve_action(&entries[matches[active_idx]], &mut term, cwd);
+                    }
+                }
+                if mouse_buttons != MouseButtons::NONE {
+                    // Treat any other mouse button as cancel
+                    break;
+                }
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Enter,
+                ..
+            }) => {
+                if let Some(entry_idx) = matches.get(active_idx) {
+                    return resolve_action(&entries[*entry_idx], &mut term, cwd);
+                }
+            }
+            _ => {}
+        }
+
+        // Keep the active row within the visible portion of the list
+        let max_rows = term
+            .get_screen_size()?
+            .rows
+            .saturating_sub(HEADER_ROWS)
+            .max(1);
+        if active_idx < top_row {
+            top_row = active_idx;
+        } else if active_idx >= top_row + max_rows {
+            top_row = active_idx + 1 - max_rows;
+        }
+
+        render(&filter, &matches, active_idx, top_row, &entries, &mut term)?;
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,310 @@
+//! Support for the prompts shown by overlays: a LineEditorHost whose
+//! history is kept in the runtime directory, so that it survives
+//! restarts, and that can complete the word under the cursor.
+//! The history of a prompt can be searched with CTRL-R, as can that of
+//! the search overlay.
+use crate::config::{configuration, HOME_DIR, RUNTIME_DIR};
+use crate::termwiztermtab::TermWizTerminal;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use termwiz::lineedit::{
+    BasicHistory, CompletionCandidate, History, HistoryIndex, LineEditor, LineEditorHost,
+    SearchDirection, SearchResult, SearchStyle,
+};
+
+/// The number of entries retained in each history file
+const MAX_HISTORY_ENTRIES: usize = 500;
+/// Longer lines are not retained; a long line is more likely to have
+/// been pasted than typed, and to be something that shouldn't be kept
+const MAX_ENTRY_LEN: usize = 1024;
+
+/// The history of a prompt, which is persisted to a file named
+/// after the prompt in the runtime directory
+pub struct PromptHistory {
+    path: PathBuf,
+    entries: VecDeque<String>,
+}
+
+impl PromptHistory {
+    pub fn load(name: &str) -> Self {
+        Self::with_path(RUNTIME_DIR.join(format!("{}-history", name)))
+    }
+
+    fn with_path(path: PathBuf) -> Self {
+        let entries = read_entries(&path);
+        Self { path, entries }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut data = String::new();
+        for entry in &self.entries {
+            data.push_str(entry);
+            data.push('\n');
+        }
+        std::fs::write(&self.path, data)?;
+        Ok(())
+    }
+}
+
+fn read_entries(path: &Path) -> VecDeque<String> {
+    let data = std::fs::read_to_string(path).unwrap_or_else(|_| String::new());
+    data.lines().map(str::to_string).collect()
+}
+
+impl History for PromptHistory {
+    fn get(&self, idx: HistoryIndex) -> Option<Cow<str>> {
+        self.entries.get(idx).map(|s| Cow::Borrowed(s.as_str()))
+    }
+
+    fn last(&self) -> Option<HistoryIndex> {
+        self.entries.len().checked_sub(1)
+    }
+
+    fn add(&mut self, line: &str) {
+        if line.trim().is_empty() || line.len() > MAX_ENTRY_LEN || line.contains('\n') {
+            return;
+        }
+        // Another prompt of the same kind may have added entries since
+        // we loaded the file
+        self.entries = read_entries(&self.path);
+        self.entries.retain(|entry| entry != line);
+        self.entries.push_back(line.to_string());
+        while self.entries.len() > MAX_HISTORY_ENTRIES {
+            self.entries.pop_front();
+        }
+        if let Err(err) = self.save() {
+            log::error!("failed to save {}: {:#}", self.path.display(), err);
+        }
+    }
+
+    fn search(
+        &self,
+        idx: HistoryIndex,
+        style: SearchStyle,
+        direction: SearchDirection,
+        pattern: &str,
+    ) -> Option<SearchResult> {
+        let mut idx = idx;
+        loop {
+            let line = self.entries.get(idx)?;
+            if let Some(cursor) = style.match_against(pattern, line) {
+                return Some(SearchResult {
+                    line: Cow::Borrowed(line.as_str()),
+                    idx,
+                    cursor,
+                });
+            }
+            idx = direction.next(idx)?;
+        }
+    }
+}
+
+/// Given the word under the cursor, returns the words that it can be
+/// completed to
+pub type Completer = Box<dyn Fn(&str) -> Vec<String>>;
+
+/// The LineEditorHost used by the prompts of the overlays
+pub struct PromptHost {
+    history: Box<dyn History>,
+    completer: Option<Completer>,
+}
+
+impl PromptHost {
+    /// Creates a host whose history is persisted under `name`.
+    /// Prompts whose answers shouldn't be written to disk, such as
+    /// confirmations, should use `PromptHost::without_history`.
+    pub fn new(name: &str) -> Self {
+        Self {
+            history: Box::new(PromptHistory::load(name)),
+            completer: None,
+        }
+    }
+
+    /// Creates a host whose history is only kept until it is dropped
+    pub fn without_history() -> Self {
+        Self {
+            history: Box::new(BasicHistory::default()),
+            completer: None,
+        }
+    }
+
+    /// Completes the word under the cursor using `completer`
+    pub fn with_completer(mut self, completer: Completer) -> Self {
+        self.completer.replace(completer);
+        self
+    }
+
+    /// Reads a line using `editor`, adding it to the history unless
+    /// the prompt was cancelled
+    pub fn read_line(&mut self, editor: &mut LineEditor) -> anyhow::Result<Option<String>> {
+        let line = editor.read_line(self)?;
+        if let Some(line) = line.as_ref() {
+            self.history.add(line);
+        }
+        Ok(line)
+    }
+}
+
+impl LineEditorHost for PromptHost {
+    fn history(&mut self) -> &mut dyn History {
+        &mut *self.history
+    }
+
+    fn complete(&self, line: &str, cursor_position: usize) -> Vec<CompletionCandidate> {
+        let completer = match self.completer.as_ref() {
+            Some(completer) => completer,
+            None => return vec![],
+        };
+        let start = line[..cursor_position]
+            .rfind(char::is_whitespace)
+            .map(|idx| idx + 1)
+            .unwrap_or(0);
+        let word = &line[start..cursor_position];
+        completer(word)
+            .into_iter()
+            .map(|text| CompletionCandidate {
+                range: start..cursor_position,
+                text,
+            })
+            .collect()
+    }
+}
+
+/// Returns a completer that offers the words in `words` that start
+/// with the word under the cursor
+pub fn complete_words(words: Vec<String>) -> Completer {
+    Box::new(move |word| {
+        words
+            .iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .cloned()
+            .collect()
+    })
+}
+
+/// Completes the names of files and directories.  Relative paths are
+/// relative to `cwd`, and a leading `~/` refers to the home directory.
+/// Directories are completed with a trailing slash so that completion
+/// can continue into them.
+pub fn complete_paths(cwd: Option<PathBuf>) -> Completer {
+    Box::new(move |word| {
+        let (dir_part, prefix) = match word.rfind('/') {
+            Some(idx) => (&word[..=idx], &word[idx + 1..]),
+            None => ("", word),
+        };
+        let dir = if dir_part.starts_with("~/") {
+            HOME_DIR.join(&dir_part[2..])
+        } else if Path::new(dir_part).is_absolute() {
+            PathBuf::from(dir_part)
+        } else {
+            match cwd.as_ref() {
+                Some(cwd) => cwd.join(dir_part),
+                None => return vec![],
+            }
+        };
+
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let mut candidates: Vec<String> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                // Hidden files are only offered once the dot is typed
+                if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.'))
+                {
+                    return None;
+                }
+                let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                Some(format!(
+                    "{}{}{}",
+                    dir_part,
+                    name,
+                    if is_dir { "/" } else { "" }
+                ))
+            })
+            .collect();
+        candidates.sort();
+        candidates
+    })
+}
+
+/// Reads a line of input at the current position of the cursor
+pub fn read_line(
+    term: &mut TermWizTerminal,
+    prompt: &str,
+    host: &mut PromptHost,
+) -> anyhow::Result<Option<String>> {
+    let mut editor = LineEditor::new(term);
+    editor.set_prompt(prompt);
+    editor.set_word_delimiters(&configuration().selection_word_boundary);
+    host.read_line(&mut editor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn history_is_persisted_and_capped() {
+        let path = std::env::temp_dir().join(format!("wezterm-history-{}", std::process::id()));
+        std::fs::remove_file(&path).ok();
+
+        let mut history = PromptHistory::with_path(path.clone());
+        history.add("first");
+        history.add("second");
+        history.add("first");
+        history.add("   ");
+        history.add(&"x".repeat(MAX_ENTRY_LEN + 1));
+
+        let reloaded = PromptHistory::with_path(path.clone());
+        assert_eq!(reloaded.entries, vec!["second", "first"]);
+        assert_eq!(
+            reloaded
+                .search(1, SearchStyle::Substring, SearchDirection::Backwards, "sec")
+                .map(|r| r.idx),
+            Some(0)
+        );
+
+        for n in 0..MAX_HISTORY_ENTRIES + 10 {
+            history.add(&n.to_string());
+        }
+        let reloaded = PromptHistory::with_path(path.clone());
+        assert_eq!(reloaded.entries.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(
+            reloaded.entries.back().map(String::as_str),
+            Some((MAX_HISTORY_ENTRIES + 9).to_string().as_str())
+        );
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn completes_word_under_cursor() {
+        let host = PromptHost::without_history().with_completer(complete_words(vec![
+            "clear".to_string(),
+            "help".to_string(),
+            "level".to_string(),
+        ]));
+        let candidates = host.complete("level he", 8);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].range, 6..8);
+        assert_eq!(candidates[0].text, "help");
+        assert!(host.complete("x", 1).is_empty());
+    }
+
+    #[test]
+    fn completes_paths() {
+        let cwd = std::env::current_dir().unwrap();
+        let complete = complete_paths(Some(cwd));
+        assert_eq!(complete("Cargo.to"), vec!["Cargo.toml"]);
+        assert!(complete("sr").contains(&"src/".to_string()));
+        assert!(complete("src/ma").contains(&"src/main.rs".to_string()));
+        assert!(complete_paths(None)("src").is_empty());
+    }
+}
@@ -1,18 +1,25 @@
+use crate::config::{configuration, TabColorOverrides};
+use crate::frontend::gui::overlay::prompt::PromptHistory;
+use crate::frontend::gui::overlay::{alloc_overlay_id, OverlayId};
 use crate::frontend::gui::selection::{SelectionCoordinate, SelectionRange};
 use crate::frontend::gui::termwindow::TermWindow;
 use crate::mux::domain::DomainId;
 use crate::mux::renderable::*;
+use crate::mux::search::{revalidate, search_in_chunks, SearchDirection};
+use crate::mux::snapshot::RenderSnapshot;
 use crate::mux::tab::{Pattern, SearchResult};
 use crate::mux::tab::{Tab, TabId};
 use portable_pty::PtySize;
 use rangeset::RangeSet;
 use std::cell::{RefCell, RefMut};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::ops::Range;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use termwiz::cell::{Cell, CellAttributes};
 use termwiz::color::AnsiColor;
+use termwiz::lineedit::{History, HistoryIndex, SearchDirection as HistoryDirection, SearchStyle};
 use url::Url;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::{Clipboard, KeyCode, KeyModifiers, Line, MouseEvent, StableRowIndex};
@@ -36,6 +43,14 @@ struct SearchRenderable {
     /// The most recently queried set of matches
     results: Vec<SearchResult>,
     by_line: HashMap<StableRowIndex, Vec<MatchResult>>,
+    /// Matches on lines that have changed since they were found,
+    /// and that no longer match
+    stale: BTreeSet<SearchResult>,
+    /// True while a search of a local tab is still in progress
+    searching: bool,
+    /// Incremented for each search, so that a search that is still
+    /// running on its thread can tell that it has been superseded
+    search_generation: Arc<AtomicUsize>,
 
     viewport: Option<StableRowIndex>,
     last_bar_pos: Option<StableRowIndex>,
@@ -47,23 +62,44 @@ struct SearchRenderable {
 
     /// We use this to cancel ourselves later
     window: ::window::Window,
+    overlay_id: OverlayId,
+
+    /// The patterns of earlier searches
+    history: PromptHistory,
+    /// The entry of the history that was most recently recalled
+    history_pos: Option<HistoryIndex>,
+    /// The text that CTRL-R is searching the history for; this is the
+    /// pattern that had been typed when CTRL-R was first pressed
+    history_search: Option<String>,
 }
 
 impl SearchOverlay {
-    pub fn with_tab(term_window: &TermWindow, tab: &Rc<dyn Tab>, pattern: Pattern) -> Rc<dyn Tab> {
+    pub fn with_tab(
+        term_window: &TermWindow,
+        tab: &Rc<dyn Tab>,
+        pattern: Pattern,
+    ) -> (OverlayId, Rc<dyn Tab>) {
         let viewport = term_window.get_viewport(tab.tab_id());
         let dims = tab.renderer().get_dimensions();
 
         let window = term_window.window.clone().unwrap();
+        let overlay_id = alloc_overlay_id();
         let mut renderer = SearchRenderable {
             delegate: Rc::clone(tab),
             pattern,
             results: vec![],
             by_line: HashMap::new(),
+            stale: BTreeSet::new(),
+            searching: false,
+            search_generation: Arc::new(AtomicUsize::new(0)),
             dirty_results: RangeSet::default(),
             viewport,
             last_bar_pos: None,
             window,
+            overlay_id,
+            history: PromptHistory::load("search"),
+            history_pos: None,
+            history_search: None,
             result_pos: None,
             width: dims.cols,
             height: dims.viewport_rows,
@@ -73,10 +109,11 @@ impl SearchOverlay {
         renderer.dirty_results.add(search_row);
         renderer.update_search();
 
-        Rc::new(SearchOverlay {
+        let overlay = Rc::new(SearchOverlay {
             renderer: RefCell::new(renderer),
             delegate: Rc::clone(tab),
-        })
+        });
+        (overlay_id, overlay)
     }
 
     pub fn viewport_changed(&self, viewport: Option<StableRowIndex>) {
@@ -114,6 +151,11 @@ impl Tab for SearchOverlay {
         Ok(())
     }
 
+    fn send_text(&self, text: &str) -> anyhow::Result<()> {
+        // composed text is typed into the search bar
+        self.send_paste(text)
+    }
+
     fn reader(&self) -> anyhow::Result<Box<dyn std::io::Read + Send>> {
         panic!("do not call reader on SearchOverlay bar tab instance");
     }
@@ -128,7 +170,7 @@ impl Tab for SearchOverlay {
 
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
         match (key, mods) {
-            (KeyCode::Escape, KeyModifiers::NONE) => self.renderer.borrow().close(),
+            (KeyCode::Escape, KeyModifiers::NONE) => self.renderer.borrow_mut().close(),
             (KeyCode::UpArrow, KeyModifiers::NONE)
             | (KeyCode::Enter, KeyModifiers::NONE)
             | (KeyCode::Char('p'), KeyModifiers::CTRL) => {
@@ -190,8 +232,20 @@ impl Tab for SearchOverlay {
                     r.activate_match_number(next);
                 }
             }
+            (KeyCode::UpArrow, KeyModifiers::ALT) => {
+                // Recall the pattern of an earlier search
+                self.renderer.borrow_mut().recall_history(true);
+            }
+            (KeyCode::DownArrow, KeyModifiers::ALT) => {
+                // Recall the pattern of a later search
+                self.renderer.borrow_mut().recall_history(false);
+            }
             (KeyCode::Char('r'), KeyModifiers::CTRL) => {
-                // CTRL-r cycles through pattern match types
+                // Search the history for an earlier pattern
+                self.renderer.borrow_mut().search_history();
+            }
+            (KeyCode::Char('t'), KeyModifiers::CTRL) => {
+                // CTRL-t cycles through pattern match types
                 let mut r = self.renderer.borrow_mut();
                 let pattern = match &r.pattern {
                     Pattern::CaseSensitiveString(s) => Pattern::CaseInSensitiveString(s.clone()),
@@ -204,15 +258,35 @@ impl Tab for SearchOverlay {
             (KeyCode::Char(c), KeyModifiers::NONE) | (KeyCode::Char(c), KeyModifiers::SHIFT) => {
                 // Type to add to the pattern
                 let mut r = self.renderer.borrow_mut();
+                r.history_search.take();
                 r.pattern.push(c);
                 r.update_search();
             }
             (KeyCode::Backspace, KeyModifiers::NONE) => {
                 // Backspace to edit the pattern
                 let mut r = self.renderer.borrow_mut();
+                r.history_search.take();
                 r.pattern.pop();
                 r.update_search();
             }
+            (KeyCode::Char('w'), KeyModifiers::CTRL) | (KeyCode::Backspace, KeyModifiers::ALT) => {
+                // Delete back to the start of the word, using the same
+                // word boundaries as double-click selection
+                let config = configuration();
+                let is_boundary =
+                    |c: char| c.is_whitespace() || config.selection_word_boundary.contains(c);
+                let mut r = self.renderer.borrow_mut();
+                r.history_search.take();
+                let word_end = r.pattern.trim_end_matches(is_boundary).len();
+                let word_start = r.pattern[..word_end]
+                    .char_indices()
+                    .rev()
+                    .find(|&(_, c)| is_boundary(c))
+                    .map(|(idx, c)| idx + c.len_utf8())
+                    .unwrap_or(0);
+                r.pattern.truncate(word_start);
+                r.update_search();
+            }
             _ => {}
         }
         Ok(())
@@ -229,6 +303,10 @@ impl Tab for SearchOverlay {
         self.delegate.is_dead()
     }
 
+    fn reap(&self) {
+        self.delegate.reap()
+    }
+
     fn palette(&self) -> ColorPalette {
         self.delegate.palette()
     }
@@ -240,6 +318,14 @@ impl Tab for SearchOverlay {
         self.delegate.erase_scrollback()
     }
 
+    fn reset_terminal(&self) {
+        self.delegate.reset_terminal()
+    }
+
+    fn color_overrides(&self) -> TabColorOverrides {
+        self.delegate.color_overrides()
+    }
+
     fn is_mouse_grabbed(&self) -> bool {
         // Force grabbing off while we're searching
         false
@@ -262,8 +348,64 @@ impl SearchRenderable {
         bottom
     }
 
-    fn close(&self) {
-        TermWindow::schedule_cancel_overlay(self.window.clone(), self.delegate.tab_id());
+    fn close(&mut self) {
+        self.history.add(&self.pattern);
+        TermWindow::schedule_cancel_overlay(
+            self.window.clone(),
+            self.delegate.tab_id(),
+            self.overlay_id,
+        );
+    }
+
+    /// Replaces the pattern with the next `older` or newer entry of the
+    /// history, if there is one
+    fn recall_history(&mut self, older: bool) {
+        let pos = match (self.history_pos, older) {
+            (None, true) => self.history.last(),
+            (None, false) => None,
+            (Some(pos), true) => pos.checked_sub(1),
+            (Some(pos), false) => Some(pos + 1),
+        };
+        let line = match pos.and_then(|pos| self.history.get(pos)) {
+            Some(line) => line.into_owned(),
+            None => return,
+        };
+        self.history_pos = pos;
+        self.history_search.take();
+        self.pattern.clear();
+        self.pattern.push_str(&line);
+        self.update_search();
+    }
+
+    /// Replaces the pattern with the next older entry of the history
+    /// that contains the pattern that was typed before CTRL-R was
+    /// first pressed, in the same way as CTRL-R in the prompts
+    fn search_history(&mut self) {
+        let text = match self.history_search.as_ref() {
+            Some(text) => text.clone(),
+            None => self.pattern.to_string(),
+        };
+        let start = match self.history_pos {
+            Some(pos) => pos.checked_sub(1),
+            None => self.history.last(),
+        };
+        let found = start.and_then(|idx| {
+            self.history
+                .search(
+                    idx,
+                    SearchStyle::Substring,
+                    HistoryDirection::Backwards,
+                    &text,
+                )
+                .map(|result| (result.idx, result.line.into_owned()))
+        });
+        self.history_search.replace(text);
+        if let Some((idx, line)) = found {
+            self.history_pos = Some(idx);
+            self.pattern.clear();
+            self.pattern.push_str(&line);
+            self.update_search();
+        }
     }
 
     fn set_viewport(&self, row: Option<StableRowIndex>) {
@@ -331,15 +473,25 @@ impl SearchRenderable {
 
         self.results.clear();
         self.by_line.clear();
+        self.stale.clear();
         self.result_pos.take();
+        self.searching = false;
+        let generation = self.search_generation.fetch_add(1, Ordering::SeqCst) + 1;
 
         let bar_pos = self.compute_search_row();
         self.dirty_results.add(bar_pos);
 
-        if !self.pattern.is_empty() {
+        if self.pattern.is_empty() {
+            self.set_viewport(None);
+            self.clear_selection();
+        } else if let Some(snapshot) = self.delegate.search_snapshot() {
+            self.searching = true;
+            self.start_incremental_search(snapshot, generation);
+        } else {
             let tab: Rc<dyn Tab> = self.delegate.clone();
             let window = self.window.clone();
             let pattern = self.pattern.clone();
+            let overlay_id = self.overlay_id;
             promise::spawn::spawn(async move {
                 let mut results = tab.search(pattern).await?;
                 results.sort();
@@ -351,7 +503,7 @@ impl SearchRenderable {
                         .downcast_mut::<TermWindow>()
                         .expect("to be TermWindow");
                     let state = term_window.tab_state(tab_id);
-                    if let Some(overlay) = state.overlay.as_ref() {
+                    if let Some(overlay) = state.get_overlay(overlay_id) {
                         if let Some(search_overlay) = overlay.downcast_ref::<SearchOverlay>() {
                             let mut r = search_overlay.renderer.borrow_mut();
                             r.results = results.take().unwrap();
@@ -370,9 +522,79 @@ impl SearchRenderable {
                 });
                 anyhow::Result::<()>::Ok(())
             });
-        } else {
-            self.set_viewport(None);
-            self.clear_selection();
+        }
+    }
+
+    /// Searches the scrollback of a local tab on a thread of its own,
+    /// from the bottom up, so that the nearest matches are shown while
+    /// the rest of a large scrollback is still being searched
+    fn start_incremental_search(&mut self, snapshot: Arc<RenderSnapshot>, generation: usize) {
+        let current_generation = Arc::clone(&self.search_generation);
+        let pattern = self.pattern.clone();
+        let tab_id = self.delegate.tab_id();
+        let overlay_id = self.overlay_id;
+        let window = self.window.clone();
+
+        std::thread::spawn(move || {
+            let start = snapshot.stable_rows().end - 1;
+            let result = search_in_chunks(
+                &*snapshot,
+                &pattern,
+                start,
+                SearchDirection::Backward,
+                |chunk, done| {
+                    if current_generation.load(Ordering::SeqCst) != generation {
+                        return false;
+                    }
+                    deliver_results(&window, tab_id, overlay_id, generation, chunk, done);
+                    true
+                },
+            );
+            if let Err(err) = result {
+                // Most likely an incomplete regex; it matches nothing
+                // until the user finishes typing it
+                log::trace!("search for {:?} failed: {:#}", pattern, err);
+                deliver_results(&window, tab_id, overlay_id, generation, vec![], true);
+            }
+        });
+    }
+
+    /// Merges a chunk of the matches found by `start_incremental_search`
+    fn add_results(&mut self, generation: usize, chunk: Vec<SearchResult>, done: bool) {
+        if self.search_generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        if done {
+            self.searching = false;
+            self.dirty_results.add(self.compute_search_row());
+        }
+        if chunk.is_empty() {
+            if done && self.results.is_empty() {
+                self.set_viewport(None);
+                self.clear_selection();
+            }
+            return;
+        }
+
+        let active = self.result_pos.map(|pos| self.results[pos]);
+        for idx in self.by_line.keys() {
+            self.dirty_results.add(*idx);
+        }
+        self.by_line.clear();
+        self.results.extend(chunk);
+        self.results.sort();
+        self.recompute_results();
+
+        match active {
+            // Keep the same match active, now that the others have moved
+            Some(active) => {
+                self.result_pos = self.results.binary_search(&active).ok();
+            }
+            // The first chunk starts with the match nearest to the bottom
+            None => {
+                let last = self.results.len() - 1;
+                self.activate_match_number(last);
+            }
         }
     }
 
@@ -388,9 +610,33 @@ impl SearchRenderable {
         });
     }
 
+    /// Returns false if `result` no longer matches because its lines
+    /// have changed since it was found, remembering that it is stale.
+    /// Tabs whose lines aren't available locally can't be checked.
+    fn is_still_valid(&mut self, result: &SearchResult) -> bool {
+        if self.stale.contains(result) {
+            return false;
+        }
+        if let Some(snapshot) = self.delegate.search_snapshot() {
+            if !revalidate(&*snapshot, &self.pattern, result) {
+                self.stale.insert(*result);
+                for idx in result.start_y..=result.end_y {
+                    self.dirty_results.add(idx);
+                }
+                return false;
+            }
+        }
+        true
+    }
+
     fn activate_match_number(&mut self, n: usize) {
         self.result_pos.replace(n);
         let result = self.results[n].clone();
+        if !self.is_still_valid(&result) {
+            // Don't jump to text that no longer matches
+            self.clear_selection();
+            return;
+        }
 
         let tab_id = self.delegate.tab_id();
         self.window.apply(move |term_window, _window| {
@@ -410,6 +656,7 @@ impl SearchRenderable {
                         y: result.end_y,
                     },
                 });
+                selection.rectangular = false;
             }
             Ok(())
         });
@@ -418,6 +665,45 @@ impl SearchRenderable {
     }
 }
 
+impl Drop for SearchRenderable {
+    fn drop(&mut self) {
+        // Stop any search that is still running
+        self.search_generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Passes a chunk of matches from the search thread to the overlay,
+/// if it is still open over `tab_id`
+fn deliver_results(
+    window: &::window::Window,
+    tab_id: TabId,
+    overlay_id: OverlayId,
+    generation: usize,
+    chunk: Vec<SearchResult>,
+    done: bool,
+) {
+    let window = window.clone();
+    let mut chunk = Some(chunk);
+    promise::spawn::spawn_into_main_thread(async move {
+        window.apply(move |term_window, _window| {
+            if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+                let state = term_window.tab_state(tab_id);
+                if let Some(overlay) = state.get_overlay(overlay_id) {
+                    if let Some(search_overlay) = overlay.downcast_ref::<SearchOverlay>() {
+                        if let Some(chunk) = chunk.take() {
+                            search_overlay
+                                .renderer
+                                .borrow_mut()
+                                .add_results(generation, chunk, done);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        });
+    });
+}
+
 impl Renderable for SearchRenderable {
     fn get_cursor_position(&self) -> StableCursorPosition {
         // move to the search box
@@ -457,20 +743,41 @@ impl Renderable for SearchRenderable {
                     Pattern::CaseInSensitiveString(_) => "ignore-case",
                     Pattern::Regex(_) => "regex",
                 };
+                let history = match self.history_search.as_ref() {
+                    Some(text) => format!(". history search: {}", text),
+                    None => String::new(),
+                };
                 line.overlay_text_with_attribute(
                     0,
                     &format!(
-                        "Search: {} ({}/{} matches. {})",
+                        "Search: {} ({}/{}{} matches. {}{})",
                         *self.pattern,
                         self.result_pos.map(|x| x + 1).unwrap_or(0),
                         self.results.len(),
-                        mode
+                        if self.searching { "+" } else { "" },
+                        mode,
+                        history
                     ),
                     rev,
                 );
                 self.last_bar_pos = Some(search_row);
             } else if let Some(matches) = self.by_line.get(&stable_idx) {
+                // The line may have changed since the matches were found
+                let snapshot = if line.is_dirty() {
+                    self.delegate.search_snapshot()
+                } else {
+                    None
+                };
                 for m in matches {
+                    let result = self.results[m.result_index];
+                    if let Some(snapshot) = snapshot.as_ref() {
+                        if !revalidate(&**snapshot, &self.pattern, &result) {
+                            self.stale.insert(result);
+                        }
+                    }
+                    if self.stale.contains(&result) {
+                        continue;
+                    }
                     // highlight
                     for cell_idx in m.range.clone() {
                         if let Some(cell) = line.cells_mut_for_attr_changes_only().get_mut(cell_idx)
@@ -498,4 +805,8 @@ impl Renderable for SearchRenderable {
     fn get_dimensions(&self) -> RenderableDimensions {
         self.delegate.renderer().get_dimensions()
     }
+
+    fn is_reverse_video(&self) -> bool {
+        self.delegate.renderer().is_reverse_video()
+    }
 }
@@ -1,30 +1,146 @@
+use crate::frontend::gui::overlay::palette::prompt;
+use crate::frontend::gui::overlay::prompt::{complete_words, PromptHost};
+use crate::mux::activity::{format_age, TabActivity, BUSY_WINDOW};
 use crate::mux::tab::TabId;
 use crate::mux::window::WindowId;
 use crate::mux::Mux;
 use crate::termwiztermtab::TermWizTerminal;
 use anyhow::anyhow;
+use portable_pty::ExitStatus;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use termwiz::cell::{AttributeChange, CellAttributes};
 use termwiz::color::ColorAttribute;
 use termwiz::input::{InputEvent, KeyCode, KeyEvent, MouseButtons, MouseEvent};
 use termwiz::surface::{Change, Position};
 use termwiz::terminal::Terminal;
 
+/// How often the list is redrawn, so that the ages and the spinners of
+/// busy tabs stay current
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// A tab in the navigator
+pub struct TabEntry {
+    pub title: String,
+    pub tab_id: TabId,
+    /// Shared with the thread that reads the output of the tab, so that
+    /// it is current whenever the list is drawn
+    pub activity: Option<Arc<TabActivity>>,
+    /// Whether the process in the tab had exited when the navigator
+    /// was opened
+    pub exited: bool,
+    /// The exit status of the process, if it has been collected
+    pub exit_status: Option<ExitStatus>,
+}
+
+impl TabEntry {
+    fn is_exited(&self) -> bool {
+        self.exited || self.activity.as_ref().map_or(false, |a| a.is_closed())
+    }
+
+    fn output_age(&self) -> Option<Duration> {
+        self.activity.as_ref().and_then(|a| a.output_age())
+    }
+}
+
+/// The order in which the tabs are listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TabSort {
+    /// The order of the tabs in the window
+    Index,
+    /// The tab that most recently produced output first
+    Recency,
+    Title,
+}
+
+impl TabSort {
+    fn next(self) -> Self {
+        match self {
+            TabSort::Index => TabSort::Recency,
+            TabSort::Recency => TabSort::Title,
+            TabSort::Title => TabSort::Index,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            TabSort::Index => "index",
+            TabSort::Recency => "recent output",
+            TabSort::Title => "title",
+        }
+    }
+
+    /// Returns the indices of `tab_list` in the order that they are listed
+    fn order(self, tab_list: &[TabEntry]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..tab_list.len()).collect();
+        match self {
+            TabSort::Index => {}
+            TabSort::Recency => {
+                // Tabs that have never produced output go last
+                order.sort_by_key(|&idx| {
+                    let age = tab_list[idx].output_age();
+                    (age.is_none(), age)
+                })
+            }
+            TabSort::Title => order.sort_by_key(|&idx| tab_list[idx].title.to_lowercase()),
+        }
+        order
+    }
+}
+
+/// Returns the entries of `order` whose titles contain `filter`,
+/// ignoring case
+fn filter_order(order: Vec<usize>, tab_list: &[TabEntry], filter: &str) -> Vec<usize> {
+    let filter = filter.to_lowercase();
+    order
+        .into_iter()
+        .filter(|&idx| tab_list[idx].title.to_lowercase().contains(&filter))
+        .collect()
+}
+
+/// Returns the distinct words of the titles of the tabs, which are
+/// offered as completions when typing a filter
+fn title_words(tab_list: &[TabEntry]) -> Vec<String> {
+    let mut words: Vec<String> = tab_list
+        .iter()
+        .flat_map(|entry| entry.title.split_whitespace())
+        .map(str::to_string)
+        .collect();
+    words.sort();
+    words.dedup();
+    words
+}
+
 pub fn tab_navigator(
     tab_id: TabId,
     mut term: TermWizTerminal,
-    tab_list: Vec<(String, TabId)>,
+    tab_list: Vec<TabEntry>,
     mux_window_id: WindowId,
 ) -> anyhow::Result<()> {
+    // The index in `tab_list` of the highlighted tab; it is tracked by
+    // tab rather than by row so that it stays put when the sort changes
     let mut active_tab_idx = tab_list
         .iter()
-        .position(|(_title, id)| *id == tab_id)
+        .position(|entry| entry.tab_id == tab_id)
         .unwrap_or(0);
+    let mut sort = TabSort::Index;
+    let started = Instant::now();
+    let mut filter = String::new();
+    // The filters are only useful while the tabs have these titles,
+    // so they aren't kept in a history file
+    let mut host =
+        PromptHost::without_history().with_completer(complete_words(title_words(&tab_list)));
 
     term.set_raw_mode()?;
 
     fn render(
         active_tab_idx: usize,
-        tab_list: &[(String, TabId)],
+        tab_list: &[TabEntry],
+        order: &[usize],
+        sort: TabSort,
+        filter: &str,
+        started: Instant,
         term: &mut TermWizTerminal,
     ) -> anyhow::Result<()> {
         // let dims = term.get_screen_size()?;
@@ -34,19 +150,51 @@ pub fn tab_navigator(
                 x: Position::Absolute(0),
                 y: Position::Absolute(0),
             },
-            Change::Text(
-                "Select a tab and press Enter to activate it.  Press Escape to cancel\r\n"
-                    .to_string(),
-            ),
+            Change::Text(format!(
+                "Select a tab and press Enter to activate it.  Press Escape to cancel.  \
+                 Press s to change the order (by {}) and / to filter by title{}\r\n",
+                sort.label(),
+                if filter.is_empty() {
+                    String::new()
+                } else {
+                    format!(" (showing {:?})", filter)
+                }
+            )),
             Change::AllAttributes(CellAttributes::default()),
         ];
 
-        for (idx, (title, _tab_id)) in tab_list.iter().enumerate() {
+        let frame = (started.elapsed().as_millis() / REFRESH_INTERVAL.as_millis()) as usize;
+
+        for &idx in order {
+            let entry = &tab_list[idx];
+            let age = entry.output_age();
+            let (spinner, status) = if entry.is_exited() {
+                let status = match &entry.exit_status {
+                    Some(status) => format!("exited with status {}", status.exit_code()),
+                    None => "exited".to_string(),
+                };
+                (' ', status)
+            } else {
+                let busy = age.map_or(false, |age| age < BUSY_WINDOW);
+                let spinner = if busy {
+                    SPINNER[frame % SPINNER.len()]
+                } else {
+                    ' '
+                };
+                (spinner, format_age(age))
+            };
+
             if idx == active_tab_idx {
                 changes.push(AttributeChange::Reverse(true).into());
             }
 
-            changes.push(Change::Text(format!(" {}. {} \r\n", idx + 1, title)));
+            changes.push(Change::Text(format!(
+                " {} {}. {} ({}) \r\n",
+                spinner,
+                idx + 1,
+                entry.title,
+                status
+            )));
 
             if idx == active_tab_idx {
                 changes.push(AttributeChange::Reverse(false).into());
@@ -59,13 +207,18 @@ pub fn tab_navigator(
 
     term.render(&[Change::Title("Tab Navigator".to_string())])?;
 
-    render(active_tab_idx, &tab_list, &mut term)?;
+    let mut order = sort.order(&tab_list);
+    render(
+        active_tab_idx,
+        &tab_list,
+        &order,
+        sort,
+        &filter,
+        started,
+        &mut term,
+    )?;
 
-    fn select_tab_by_idx(
-        idx: usize,
-        mux_window_id: WindowId,
-        tab_list: &Vec<(String, TabId)>,
-    ) -> bool {
+    fn select_tab_by_idx(idx: usize, mux_window_id: WindowId, tab_list: &[TabEntry]) -> bool {
         if idx >= tab_list.len() {
             false
         } else {
@@ -82,7 +235,17 @@ pub fn tab_navigator(
         }
     }
 
-    while let Ok(Some(event)) = term.poll_input(None) {
+    loop {
+        // Waking up periodically keeps the ages and spinners current
+        let event = match term.poll_input(Some(REFRESH_INTERVAL)) {
+            Ok(Some(event)) => event,
+            Ok(None) => InputEvent::Wake,
+            Err(_) => break,
+        };
+        let row = order
+            .iter()
+            .position(|&idx| idx == active_tab_idx)
+            .unwrap_or(0);
         match event {
             InputEvent::Key(KeyEvent {
                 key: KeyCode::Char('k'),
@@ -92,7 +255,9 @@ pub fn tab_navigator(
                 key: KeyCode::UpArrow,
                 ..
             }) => {
-                active_tab_idx = active_tab_idx.saturating_sub(1);
+                if let Some(&idx) = order.get(row.saturating_sub(1)) {
+                    active_tab_idx = idx;
+                }
             }
             InputEvent::Key(KeyEvent {
                 key: KeyCode::Char('j'),
@@ -102,7 +267,24 @@ pub fn tab_navigator(
                 key: KeyCode::DownArrow,
                 ..
             }) => {
-                active_tab_idx = (active_tab_idx + 1).min(tab_list.len() - 1);
+                if let Some(&idx) = order.get((row + 1).min(order.len().saturating_sub(1))) {
+                    active_tab_idx = idx;
+                }
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('s'),
+                ..
+            }) => {
+                sort = sort.next();
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('/'),
+                ..
+            }) => {
+                // Tab completes the words of the titles
+                if let Some(line) = prompt(&mut term, "Filter by title: ", &mut host)? {
+                    filter = line.trim().to_string();
+                }
             }
             InputEvent::Key(KeyEvent {
                 key: KeyCode::Escape,
@@ -114,6 +296,8 @@ pub fn tab_navigator(
                 key: KeyCode::Char(c),
                 ..
             }) => {
+                // The numbers are those of the tabs in the window,
+                // whatever order they are listed in
                 if c >= '1' && c <= '9' {
                     let idx = c as u8 - '1' as u8;
                     if select_tab_by_idx(idx as usize, mux_window_id, &tab_list) {
@@ -124,8 +308,8 @@ pub fn tab_navigator(
             InputEvent::Mouse(MouseEvent {
                 y, mouse_buttons, ..
             }) => {
-                if y > 0 && y as usize <= tab_list.len() {
-                    active_tab_idx = y as usize - 1;
+                if y > 0 && y as usize <= order.len() {
+                    active_tab_idx = order[y as usize - 1];
 
                     if mouse_buttons == MouseButtons::LEFT {
                         select_tab_by_idx(active_tab_idx, mux_window_id, &tab_list);
@@ -141,13 +325,58 @@ pub fn tab_navigator(
                 key: KeyCode::Enter,
                 ..
             }) => {
-                select_tab_by_idx(active_tab_idx, mux_window_id, &tab_list);
-                break;
+                // Every tab may have been filtered out
+                if order.contains(&active_tab_idx) {
+                    select_tab_by_idx(active_tab_idx, mux_window_id, &tab_list);
+                    break;
+                }
             }
             _ => {}
         }
-        render(active_tab_idx, &tab_list, &mut term)?;
+        // Recency changes as the tabs produce output, so re-sort each time
+        order = filter_order(sort.order(&tab_list), &tab_list, &filter);
+        if !order.contains(&active_tab_idx) {
+            if let Some(&idx) = order.first() {
+                active_tab_idx = idx;
+            }
+        }
+        render(
+            active_tab_idx,
+            &tab_list,
+            &order,
+            sort,
+            &filter,
+            started,
+            &mut term,
+        )?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(title: &str) -> TabEntry {
+        TabEntry {
+            title: title.to_string(),
+            tab_id: 0,
+            activity: None,
+            exited: false,
+            exit_status: None,
+        }
+    }
+
+    #[test]
+    fn filter_by_title() {
+        let tab_list = vec![entry("vim src/main.rs"), entry("bash"), entry("Vim notes")];
+        assert_eq!(filter_order(vec![2, 1, 0], &tab_list, "vim"), vec![2, 0]);
+        assert_eq!(filter_order(vec![0, 1, 2], &tab_list, ""), vec![0, 1, 2]);
+        assert!(filter_order(vec![0, 1, 2], &tab_list, "zsh").is_empty());
+        assert_eq!(
+            title_words(&tab_list),
+            vec!["Vim", "bash", "notes", "src/main.rs", "vim"]
+        );
+    }
+}
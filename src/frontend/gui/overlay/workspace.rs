@@ -0,0 +1,29 @@
+use crate::frontend::gui::overlay::palette::prompt;
+use crate::frontend::gui::overlay::prompt::{complete_words, PromptHost};
+use crate::mux::tab::TabId;
+use crate::termwiztermtab::TermWizTerminal;
+use termwiz::surface::Change;
+use termwiz::terminal::Terminal;
+
+/// Prompts for the name of the workspace of a window, offering the
+/// names of the other workspaces as completions.
+/// Returns Ok(None) if the user cancelled the prompt, and an empty
+/// name if they wish to clear the name.
+pub fn rename_workspace(
+    _tab_id: TabId,
+    mut term: TermWizTerminal,
+    current: Option<String>,
+    workspaces: Vec<String>,
+) -> anyhow::Result<Option<String>> {
+    term.render(&[Change::Title("Rename Workspace".to_string())])?;
+    let message = match current {
+        Some(current) => format!(
+            "Workspace name (currently {}; leave empty to clear): ",
+            current
+        ),
+        None => "Workspace name: ".to_string(),
+    };
+    let mut host = PromptHost::new("workspace").with_completer(complete_words(workspaces));
+    let name = prompt(&mut term, &message, &mut host)?;
+    Ok(name.map(|name| name.trim().to_string()))
+}
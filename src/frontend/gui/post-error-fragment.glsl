@@ -0,0 +1,22 @@
+precision mediump float;
+
+in vec2 o_tex;
+
+uniform sampler2D frame_tex;
+uniform vec2 resolution;
+
+out vec4 color;
+
+// The width of the border that shows that the custom shader failed
+// to compile, in pixels
+const float border = 4.0;
+
+void main() {
+  vec2 pixel = o_tex * resolution;
+  if (pixel.x < border || pixel.y < border ||
+      pixel.x > resolution.x - border || pixel.y > resolution.y - border) {
+    color = vec4(0.8, 0.1, 0.1, 1.0);
+  } else {
+    color = texture(frame_tex, o_tex);
+  }
+}
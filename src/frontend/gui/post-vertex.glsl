@@ -0,0 +1,11 @@
+precision mediump float;
+
+in vec2 position;
+in vec2 tex;
+
+out vec2 o_tex;
+
+void main() {
+  o_tex = tex;
+  gl_Position = vec4(position, 0.0, 1.0);
+}
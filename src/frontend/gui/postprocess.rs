@@ -0,0 +1,218 @@
+//! Runs the `custom_shader` over each frame.  The frame is drawn into
+//! an offscreen texture, which the shader then draws onto the window.
+//! The shader is reloaded when its file changes; while it fails to
+//! compile, the frame is drawn unchanged inside a red border, so that
+//! the window remains usable while the shader is being worked on.
+//! None of this is set up unless `custom_shader` is configured.
+
+// Clippy hates the implement_vertex macro and won't let me scope
+// this warning to its use
+#![allow(clippy::unneeded_field_pattern)]
+
+use super::renderstate::OpenGLRenderState;
+use ::window::glium;
+use ::window::glium::backend::Context as GliumContext;
+use ::window::glium::texture::{MipmapsOption, Texture2d, UncompressedFloatFormat};
+use ::window::glium::uniforms::{
+    MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction,
+};
+use ::window::glium::{uniform, IndexBuffer, Surface, VertexBuffer};
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the shader file is checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Copy, Clone)]
+pub struct PostVertex {
+    pub position: (f32, f32),
+    pub tex: (f32, f32),
+}
+::window::glium::implement_vertex!(PostVertex, position, tex);
+
+pub struct PostProcess {
+    path: PathBuf,
+    /// The modification time of the file when it was last loaded
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    /// The user's shader, or None if it failed to compile
+    program: Option<glium::Program>,
+    error_program: glium::Program,
+    /// The texture into which the frame is drawn; it is allocated on
+    /// first use and whenever the size of the window changes
+    texture: Option<Rc<Texture2d>>,
+    vertices: VertexBuffer<PostVertex>,
+    indices: IndexBuffer<u32>,
+    started: Instant,
+}
+
+/// Returns the modification time of `path`, or None if it can't be
+/// determined, such as when the file doesn't exist
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl PostProcess {
+    pub fn new(context: &Rc<GliumContext>, path: &Path) -> anyhow::Result<Self> {
+        let error_program = OpenGLRenderState::compile_program(
+            context,
+            include_str!("post-vertex.glsl"),
+            include_str!("post-error-fragment.glsl"),
+        )?;
+
+        // A single quad that covers the whole window
+        let mut vertices = vec![];
+        for &(x, y) in &[(0., 0.), (1., 0.), (0., 1.), (1., 1.)] {
+            vertices.push(PostVertex {
+                position: (x * 2. - 1., y * 2. - 1.),
+                tex: (x, y),
+            });
+        }
+        let vertices = VertexBuffer::new(context, &vertices)?;
+        let indices = IndexBuffer::new(
+            context,
+            glium::index::PrimitiveType::TrianglesList,
+            &[0, 1, 2, 1, 2, 3],
+        )?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            modified: modified_time(path),
+            last_check: Instant::now(),
+            program: Self::load(context, path),
+            error_program,
+            texture: None,
+            vertices,
+            indices,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Compiles the shader in `path`, reporting the reason if that fails
+    fn load(context: &Rc<GliumContext>, path: &Path) -> Option<glium::Program> {
+        let result = std::fs::read_to_string(path)
+            .with_context(|| format!("reading {}", path.display()))
+            .and_then(|source| {
+                OpenGLRenderState::compile_program(
+                    context,
+                    include_str!("post-vertex.glsl"),
+                    &source,
+                )
+            });
+        match result {
+            Ok(program) => {
+                log::info!("loaded custom_shader {}", path.display());
+                Some(program)
+            }
+            Err(err) => {
+                let message = format!(
+                    "custom_shader {} could not be used, so the frame is drawn \
+                     unchanged until it is fixed: {:#}",
+                    path.display(),
+                    err
+                );
+                log::error!("{}", message);
+                crate::toast_notification("wezterm", &message);
+                None
+            }
+        }
+    }
+
+    /// Reloads the shader if its file has changed since it was last
+    /// loaded.  Returns true if it was reloaded.
+    pub fn reload_if_changed(&mut self, context: &Rc<GliumContext>) -> bool {
+        let now = Instant::now();
+        if now.duration_since(self.last_check) < CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = now;
+
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        self.program = Self::load(context, &self.path);
+        true
+    }
+
+    /// Returns true if the shader uses the `time` uniform, in which
+    /// case the window must be repainted continually
+    pub fn is_animated(&self) -> bool {
+        self.program
+            .as_ref()
+            .map_or(false, |program| program.get_uniform("time").is_some())
+    }
+
+    /// Returns the texture into which a frame of `width` by `height`
+    /// pixels should be drawn
+    pub fn target(
+        &mut self,
+        context: &Rc<GliumContext>,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<Rc<Texture2d>> {
+        if let Some(texture) = self.texture.as_ref() {
+            if texture.width() == width && texture.height() == height {
+                return Ok(Rc::clone(texture));
+            }
+        }
+        let texture = Rc::new(Texture2d::empty_with_format(
+            context,
+            UncompressedFloatFormat::U8U8U8U8,
+            MipmapsOption::NoMipmap,
+            width,
+            height,
+        )?);
+        self.texture.replace(Rc::clone(&texture));
+        Ok(texture)
+    }
+
+    /// Draws `texture` onto `frame` using the shader.  `cursor` is the
+    /// position of the cursor in pixels, measured from the bottom left
+    /// corner of the window, just as `gl_FragCoord` is.
+    pub fn draw(
+        &self,
+        frame: &mut impl Surface,
+        texture: &Texture2d,
+        cursor: (f32, f32),
+    ) -> anyhow::Result<()> {
+        let resolution = (texture.width() as f32, texture.height() as f32);
+        let frame_tex = Sampler::new(texture)
+            .wrap_function(SamplerWrapFunction::Clamp)
+            .magnify_filter(MagnifySamplerFilter::Nearest)
+            .minify_filter(MinifySamplerFilter::Nearest);
+
+        match self.program.as_ref() {
+            Some(program) => frame.draw(
+                &self.vertices,
+                &self.indices,
+                program,
+                &uniform! {
+                    frame_tex: frame_tex,
+                    resolution: resolution,
+                    time: self.started.elapsed().as_secs_f32(),
+                    cursor: cursor,
+                },
+                &Default::default(),
+            )?,
+            None => frame.draw(
+                &self.vertices,
+                &self.indices,
+                &self.error_program,
+                &uniform! {
+                    frame_tex: frame_tex,
+                    resolution: resolution,
+                },
+                &Default::default(),
+            )?,
+        }
+        Ok(())
+    }
+}
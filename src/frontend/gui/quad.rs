@@ -56,6 +56,9 @@ pub struct Quads {
     pub row_starts: Vec<usize>,
     /// The vertex index for the first vertex of the scroll bar thumb
     pub scroll_thumb: usize,
+    /// The vertex index for the first vertex of the quad that covers
+    /// the whole window, behind the cells
+    pub background: usize,
 }
 
 pub struct MappedQuads<'a> {
@@ -87,6 +90,13 @@ impl<'a> MappedQuads<'a> {
             vert: &mut self.mapping[start..start + VERTICES_PER_CELL],
         }
     }
+
+    pub fn background<'b>(&'b mut self) -> Quad<'b> {
+        let start = self.quads.background;
+        Quad {
+            vert: &mut self.mapping[start..start + VERTICES_PER_CELL],
+        }
+    }
 }
 
 impl Quads {
@@ -99,6 +109,36 @@ impl Quads {
     }
 }
 
+/// Quads that are drawn on their own, rather than through the vertex
+/// buffer that holds the cells of the window
+#[derive(Default)]
+pub struct FreeQuads {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl FreeQuads {
+    /// Adds a quad; those added later are drawn over earlier ones
+    pub fn allocate<'a>(&'a mut self) -> Quad<'a> {
+        let idx = self.vertices.len();
+        self.vertices
+            .resize_with(idx + VERTICES_PER_CELL, Vertex::default);
+        for corner in &[
+            V_TOP_LEFT,
+            V_TOP_RIGHT,
+            V_BOT_LEFT,
+            V_TOP_RIGHT,
+            V_BOT_LEFT,
+            V_BOT_RIGHT,
+        ] {
+            self.indices.push((idx + corner) as u32);
+        }
+        Quad {
+            vert: &mut self.vertices[idx..idx + VERTICES_PER_CELL],
+        }
+    }
+}
+
 /// A helper for updating the 4 vertices that compose a glyph cell
 pub struct Quad<'a> {
     vert: &'a mut [Vertex],
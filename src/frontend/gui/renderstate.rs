@@ -1,20 +1,26 @@
-use super::glyphcache::{CachedGlyph, GlyphCache};
+use super::glyphcache::{CachedGlyph, GlyphCache, GlyphCacheUsage};
+use super::postprocess::PostProcess;
 use super::quad::*;
 use super::utilsprites::{RenderMetrics, UtilSprites};
 use crate::config::{configuration, TextStyle};
 use crate::font::{FontConfiguration, GlyphInfo};
-use ::window::bitmaps::ImageTexture;
+use ::window::bitmaps::{ImageTexture, Texture2d};
 use ::window::glium::backend::Context as GliumContext;
 use ::window::glium::texture::SrgbTexture2d;
 use ::window::glium::{IndexBuffer, VertexBuffer};
 use ::window::*;
-use anyhow::{anyhow, bail};
+use anyhow::bail;
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 pub struct SoftwareRenderState {
     pub glyph_cache: RefCell<GlyphCache<ImageTexture>>,
     pub util_sprites: UtilSprites<ImageTexture>,
+    /// The util sprites of the other fonts that the glyph cache holds
+    /// glyphs for, keyed by the id of the fonts; see `switch_fonts`
+    other_util_sprites: HashMap<usize, UtilSprites<ImageTexture>>,
 }
 
 impl SoftwareRenderState {
@@ -28,6 +34,7 @@ impl SoftwareRenderState {
         Ok(Self {
             glyph_cache,
             util_sprites,
+            other_util_sprites: HashMap::new(),
         })
     }
 }
@@ -36,10 +43,24 @@ pub struct OpenGLRenderState {
     pub context: Rc<GliumContext>,
     pub glyph_cache: RefCell<GlyphCache<SrgbTexture2d>>,
     pub util_sprites: UtilSprites<SrgbTexture2d>,
+    /// The util sprites of the other fonts that the glyph cache holds
+    /// glyphs for, keyed by the id of the fonts; see `switch_fonts`
+    other_util_sprites: HashMap<usize, UtilSprites<SrgbTexture2d>>,
     pub program: glium::Program,
     pub glyph_vertex_buffer: RefCell<VertexBuffer<Vertex>>,
     pub glyph_index_buffer: IndexBuffer<u32>,
     pub quads: Quads,
+    /// Draws the debugging view of the texture atlas; it is compiled
+    /// by `compile_atlas_view_program` the first time that the view
+    /// is shown
+    pub atlas_view_program: RefCell<Option<glium::Program>>,
+    /// The most recently loaded background image and the path that it
+    /// was loaded from.  The texture is None if it failed to load, so
+    /// that the failure is only reported once.
+    background_image: RefCell<Option<(PathBuf, Option<Rc<SrgbTexture2d>>)>>,
+    /// Runs the `custom_shader` over the frame; None unless it is
+    /// configured
+    pub post_process: RefCell<Option<PostProcess>>,
 }
 
 impl OpenGLRenderState {
@@ -54,12 +75,42 @@ impl OpenGLRenderState {
         let glyph_cache = RefCell::new(GlyphCache::new_gl(&context, fonts, size)?);
         let util_sprites = UtilSprites::new(&mut *glyph_cache.borrow_mut(), metrics)?;
 
+        let program = Self::compile_program(
+            &context,
+            include_str!("vertex.glsl"),
+            include_str!("fragment.glsl"),
+        )?;
+
+        let (glyph_vertex_buffer, glyph_index_buffer, quads) =
+            Self::compute_vertices(&context, metrics, pixel_width as f32, pixel_height as f32)?;
+
+        Ok(Self {
+            context,
+            glyph_cache,
+            util_sprites,
+            other_util_sprites: HashMap::new(),
+            program,
+            glyph_vertex_buffer: RefCell::new(glyph_vertex_buffer),
+            glyph_index_buffer,
+            quads,
+            atlas_view_program: RefCell::new(None),
+            background_image: RefCell::new(None),
+            post_process: RefCell::new(None),
+        })
+    }
+
+    /// Compiles a program, trying each of the GLSL versions that we
+    /// support in turn
+    pub fn compile_program(
+        context: &Rc<GliumContext>,
+        vertex_shader: &str,
+        fragment_shader: &str,
+    ) -> anyhow::Result<glium::Program> {
         let mut errors = vec![];
-        let mut program = None;
         for version in &["330", "300 es"] {
             let source = glium::program::ProgramCreationInput::SourceCode {
-                vertex_shader: &Self::vertex_shader(version),
-                fragment_shader: &Self::fragment_shader(version),
+                vertex_shader: &format!("#version {}\n{}", version, vertex_shader),
+                fragment_shader: &format!("#version {}\n{}", version, fragment_shader),
                 outputs_srgb: true,
                 tessellation_control_shader: None,
                 tessellation_evaluation_shader: None,
@@ -68,30 +119,103 @@ impl OpenGLRenderState {
                 geometry_shader: None,
             };
             log::info!("compiling a prog with version {}", version);
-            match glium::Program::new(&context, source) {
-                Ok(prog) => {
-                    program = Some(prog);
-                    break;
-                }
+            match glium::Program::new(context, source) {
+                Ok(prog) => return Ok(prog),
                 Err(err) => errors.push(err.to_string()),
             };
         }
+        bail!("Failed to compile shaders: {}", errors.join("\n"))
+    }
 
-        let program =
-            program.ok_or_else(|| anyhow!("Failed to compile shaders: {}", errors.join("\n")))?;
+    /// Compiles the program that draws the debugging view of the
+    /// texture atlas, unless that has already been done
+    pub fn compile_atlas_view_program(&self) -> anyhow::Result<()> {
+        let mut program = self.atlas_view_program.borrow_mut();
+        if program.is_none() {
+            program.replace(Self::compile_program(
+                &self.context,
+                include_str!("atlas-vertex.glsl"),
+                include_str!("atlas-fragment.glsl"),
+            )?);
+        }
+        Ok(())
+    }
 
-        let (glyph_vertex_buffer, glyph_index_buffer, quads) =
-            Self::compute_vertices(&context, metrics, pixel_width as f32, pixel_height as f32)?;
+    /// Sets up, replaces or tears down the post-processing stage so
+    /// that it runs the shader at `path`.  Returns true if there is a
+    /// stage to run.
+    pub fn update_post_process(&self, path: Option<&Path>) -> bool {
+        let mut post = self.post_process.borrow_mut();
+        let path = match path {
+            Some(path) => path,
+            None => {
+                post.take();
+                return false;
+            }
+        };
+        if post.as_ref().map_or(true, |post| post.path() != path) {
+            match PostProcess::new(&self.context, path) {
+                Ok(stage) => {
+                    post.replace(stage);
+                }
+                Err(err) => {
+                    log::error!("failed to set up custom_shader: {:#}", err);
+                    post.take();
+                }
+            }
+        }
+        post.is_some()
+    }
 
-        Ok(Self {
-            context,
-            glyph_cache,
-            util_sprites,
-            program,
-            glyph_vertex_buffer: RefCell::new(glyph_vertex_buffer),
-            glyph_index_buffer,
-            quads,
-        })
+    /// Reloads the custom shader if its file has changed.  Returns
+    /// true if the window should be repainted, which is after a reload
+    /// and continually while the shader uses the `time` uniform.
+    pub fn check_post_process(&self) -> bool {
+        match self.post_process.borrow_mut().as_mut() {
+            Some(post) => {
+                let reloaded = post.reload_if_changed(&self.context);
+                reloaded || post.is_animated()
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the texture for the background image at `path`.
+    /// The image is only decoded again when the path changes.
+    pub fn background_image(&self, path: &Path) -> Option<Rc<SrgbTexture2d>> {
+        let mut cache = self.background_image.borrow_mut();
+        if let Some((cached_path, texture)) = cache.as_ref() {
+            if cached_path == path {
+                return texture.clone();
+            }
+        }
+
+        let texture = match Self::load_background_image(&self.context, path) {
+            Ok(texture) => Some(Rc::new(texture)),
+            Err(err) => {
+                log::error!(
+                    "failed to load background image {}: {:#}",
+                    path.display(),
+                    err
+                );
+                None
+            }
+        };
+        cache.replace((path.to_path_buf(), texture.clone()));
+        texture
+    }
+
+    fn load_background_image(
+        context: &Rc<GliumContext>,
+        path: &Path,
+    ) -> anyhow::Result<SrgbTexture2d> {
+        let image = image::open(path)?.to_rgba();
+        let dimensions = image.dimensions();
+        // The rows are reversed because texture coordinates start
+        // from the bottom of the image
+        let image =
+            glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), dimensions);
+        Ok(SrgbTexture2d::new(context, image)?)
     }
 
     pub fn advise_of_window_size_change(
@@ -113,14 +237,6 @@ impl OpenGLRenderState {
         Ok(())
     }
 
-    fn vertex_shader(version: &str) -> String {
-        format!("#version {}\n{}", version, include_str!("vertex.glsl"))
-    }
-
-    fn fragment_shader(version: &str) -> String {
-        format!("#version {}\n{}", version, include_str!("fragment.glsl"))
-    }
-
     /// Compute a vertex buffer to hold the quads that comprise the visible
     /// portion of the screen.   We recreate this when the screen is resized.
     /// The idea is that we want to minimize any heavy lifting and computation
@@ -145,8 +261,10 @@ impl OpenGLRenderState {
         let avail_height = (height as usize)
             .saturating_sub((config.window_padding.top + config.window_padding.bottom) as usize);
 
-        let num_cols = avail_width as usize / cell_width as usize;
-        let num_rows = avail_height as usize / cell_height as usize;
+        // The terminal is never smaller than a single cell, so ensure
+        // that we have at least one quad to render it into
+        let num_cols = (avail_width as usize / cell_width as usize).max(1);
+        let num_rows = (avail_height as usize / cell_height as usize).max(1);
 
         let padding_left = config.window_padding.left as f32;
         let padding_top = config.window_padding.top as f32;
@@ -201,6 +319,11 @@ impl OpenGLRenderState {
             idx
         };
 
+        // The background quad is defined first so that the cells are
+        // drawn over it
+        quads.background =
+            define_quad(width / -2.0, height / -2.0, width / 2.0, height / 2.0) as usize;
+
         for y in 0..num_rows {
             let y_pos = (height / -2.0) + (y as f32 * cell_height) + padding_top;
 
@@ -238,6 +361,28 @@ impl OpenGLRenderState {
     }
 }
 
+fn switch_fonts<T: Texture2d>(
+    glyph_cache: &mut GlyphCache<T>,
+    util_sprites: &mut UtilSprites<T>,
+    other_util_sprites: &mut HashMap<usize, UtilSprites<T>>,
+    fonts: &Rc<FontConfiguration>,
+    metrics: &RenderMetrics,
+) -> anyhow::Result<()> {
+    let prior_id = glyph_cache.fonts_id();
+    if prior_id == fonts.id() {
+        return Ok(());
+    }
+    // The util sprites depend on the size of the cells, so each of
+    // the fonts has its own
+    let sprites = match other_util_sprites.remove(&fonts.id()) {
+        Some(sprites) => sprites,
+        None => UtilSprites::new(glyph_cache, metrics)?,
+    };
+    glyph_cache.set_fonts(fonts);
+    other_util_sprites.insert(prior_id, std::mem::replace(util_sprites, sprites));
+    Ok(())
+}
+
 #[allow(clippy::large_enum_variant)]
 pub enum RenderState {
     Software(SoftwareRenderState),
@@ -256,18 +401,48 @@ impl RenderState {
                 let size = size.unwrap_or_else(|| software.glyph_cache.borrow().atlas.size());
                 let mut glyph_cache = GlyphCache::new(fonts, size);
                 software.util_sprites = UtilSprites::new(&mut glyph_cache, metrics)?;
+                software.other_util_sprites.clear();
                 *software.glyph_cache.borrow_mut() = glyph_cache;
             }
             RenderState::GL(gl) => {
                 let size = size.unwrap_or_else(|| gl.glyph_cache.borrow().atlas.size());
                 let mut glyph_cache = GlyphCache::new_gl(&gl.context, fonts, size)?;
                 gl.util_sprites = UtilSprites::new(&mut glyph_cache, metrics)?;
+                gl.other_util_sprites.clear();
                 *gl.glyph_cache.borrow_mut() = glyph_cache;
             }
         };
         Ok(())
     }
 
+    /// Switches to rendering with `fonts`, whose glyphs are held in
+    /// the same atlas as those of the fonts that were in use, so that
+    /// switching between tabs with different fonts doesn't discard
+    /// any glyphs.  This fails if the atlas has no room for the util
+    /// sprites of `fonts`, in which case the atlas has to be recreated.
+    pub fn switch_fonts(
+        &mut self,
+        fonts: &Rc<FontConfiguration>,
+        metrics: &RenderMetrics,
+    ) -> anyhow::Result<()> {
+        match self {
+            RenderState::Software(software) => switch_fonts(
+                &mut software.glyph_cache.borrow_mut(),
+                &mut software.util_sprites,
+                &mut software.other_util_sprites,
+                fonts,
+                metrics,
+            ),
+            RenderState::GL(gl) => switch_fonts(
+                &mut gl.glyph_cache.borrow_mut(),
+                &mut gl.util_sprites,
+                &mut gl.other_util_sprites,
+                fonts,
+                metrics,
+            ),
+        }
+    }
+
     pub fn advise_of_window_size_change(
         &mut self,
         metrics: &RenderMetrics,
@@ -280,15 +455,25 @@ impl RenderState {
         Ok(())
     }
 
-    pub fn cached_software_glyph(
+    pub fn cached_software_glyphs<'a>(
         &self,
-        info: &GlyphInfo,
+        infos: &'a [GlyphInfo],
         style: &TextStyle,
-    ) -> anyhow::Result<Rc<CachedGlyph<ImageTexture>>> {
+    ) -> anyhow::Result<Vec<(&'a GlyphInfo, Rc<CachedGlyph<ImageTexture>>)>> {
         if let RenderState::Software(software) = self {
-            software.glyph_cache.borrow_mut().cached_glyph(info, style)
+            software
+                .glyph_cache
+                .borrow_mut()
+                .cached_glyphs(infos, style)
         } else {
-            bail!("attempted to call cached_software_glyph when in gl mode")
+            bail!("attempted to call cached_software_glyphs when in gl mode")
+        }
+    }
+
+    pub fn glyph_cache_usage(&self) -> GlyphCacheUsage {
+        match self {
+            RenderState::Software(software) => software.glyph_cache.borrow().usage(),
+            RenderState::GL(gl) => gl.glyph_cache.borrow().usage(),
         }
     }
 
@@ -0,0 +1,199 @@
+//! Shows the size of the terminal, such as "120×40", in a badge in the
+//! middle of the window while the window is being resized.  The text
+//! of the badge is rendered through the normal glyph pipeline, from
+//! fonts that are FONT_SCALE times the size of those of the terminal,
+//! and drawn over the terminal, so it never changes the terminal or
+//! its pty.  It fades into the background once resizing stops.
+use super::glyphcache::CachedGlyph;
+use super::utilsprites::RenderMetrics;
+use crate::font::shaper::GlyphInfo;
+use ::window::bitmaps::Texture2d;
+use ::window::{Point, Rect, Size};
+use portable_pty::PtySize;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use termwiz::color::RgbColor;
+
+/// How long the badge is shown after the most recent resize
+const SHOW_FOR: Duration = Duration::from_millis(500);
+/// How much of SHOW_FOR is spent fading out
+const FADE_FOR: Duration = Duration::from_millis(200);
+/// How many times larger than the text of the terminal that
+/// of the badge is
+pub const FONT_SCALE: f64 = 3.0;
+
+pub struct ResizeBadge {
+    /// The time of the most recent resize
+    resized: Instant,
+}
+
+impl ResizeBadge {
+    pub fn new(now: Instant) -> Self {
+        Self { resized: now }
+    }
+
+    /// Returns how opaque the badge is at `now`, from 1.0 down to 0.0
+    /// while it fades out, or None once it should no longer be shown
+    pub fn opacity(&self, now: Instant) -> Option<f32> {
+        let elapsed = now.saturating_duration_since(self.resized);
+        if elapsed >= SHOW_FOR {
+            return None;
+        }
+        let remaining = SHOW_FOR - elapsed;
+        if remaining >= FADE_FOR {
+            Some(1.)
+        } else {
+            Some(remaining.as_secs_f32() / FADE_FOR.as_secs_f32())
+        }
+    }
+}
+
+pub fn badge_text(size: &PtySize) -> String {
+    format!("{}×{}", size.cols, size.rows)
+}
+
+/// Returns a color `amount` of the way from `from` to `to`
+fn blend(from: RgbColor, to: RgbColor, amount: f32) -> RgbColor {
+    let mix = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * amount).round() as u8;
+    RgbColor::new(
+        mix(from.red, to.red),
+        mix(from.green, to.green),
+        mix(from.blue, to.blue),
+    )
+}
+
+/// The badge as it is to be painted
+pub struct Badge<T: Texture2d> {
+    /// The area covered by the badge, in pixels from the top left
+    /// of the window
+    pub rect: Rect,
+    pub foreground: RgbColor,
+    pub background: RgbColor,
+    /// The glyphs of the text, with the position of the top left
+    /// corner of each.  Whitespace glyphs have no texture.
+    pub glyphs: Vec<(Point, Rc<CachedGlyph<T>>)>,
+}
+
+/// Lays out the badge in the middle of a window of `window_size`.
+/// `glyphs` are the text of the badge, rendered from fonts whose cells
+/// are described by `metrics`; the badge has a cell of padding on
+/// either side of the text, and half a cell above and below it.
+/// The badge is in the reverse of the `foreground` and `background` of
+/// the palette, and fades into the background as `opacity` falls.
+pub fn layout<T: Texture2d>(
+    window_size: Size,
+    metrics: &RenderMetrics,
+    glyphs: &[(&GlyphInfo, Rc<CachedGlyph<T>>)],
+    foreground: RgbColor,
+    background: RgbColor,
+    opacity: f32,
+) -> Badge<T> {
+    let cell = metrics.cell_size;
+    let cells: isize = glyphs.iter().map(|(info, _)| info.num_cells as isize).sum();
+    let size = Size::new((cells + 2) * cell.width, cell.height * 2);
+    let rect = Rect::new(
+        Point::new(
+            (window_size.width - size.width).max(0) / 2,
+            (window_size.height - size.height).max(0) / 2,
+        ),
+        size,
+    );
+
+    let baseline = (rect.origin.y + cell.height / 2 + cell.height) as f64 + metrics.descender.get();
+    let mut x = rect.origin.x + cell.width;
+    let glyphs = glyphs
+        .iter()
+        .map(|(info, glyph)| {
+            let left = x as f64 + (glyph.x_offset + glyph.bearing_x).get();
+            let top = baseline - (glyph.y_offset + glyph.bearing_y).get();
+            x += info.num_cells as isize * cell.width;
+            (Point::new(left as isize, top as isize), Rc::clone(glyph))
+        })
+        .collect();
+
+    Badge {
+        rect,
+        foreground: background,
+        background: blend(background, foreground, opacity),
+        glyphs,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::font::units::PixelLength;
+    use ::window::bitmaps::ImageTexture;
+
+    #[test]
+    fn fades_out() {
+        let start = Instant::now();
+        let badge = ResizeBadge::new(start);
+        assert_eq!(badge.opacity(start), Some(1.));
+        assert_eq!(badge.opacity(start + Duration::from_millis(300)), Some(1.));
+        let fading = badge.opacity(start + Duration::from_millis(400)).unwrap();
+        assert!(fading > 0.4 && fading < 0.6);
+        assert_eq!(badge.opacity(start + SHOW_FOR), None);
+    }
+
+    #[test]
+    fn centered() {
+        let size = PtySize {
+            rows: 40,
+            cols: 120,
+            pixel_width: 0,
+            pixel_height: 0,
+        };
+        assert_eq!(badge_text(&size), "120×40");
+
+        let metrics = RenderMetrics {
+            descender: PixelLength::new(-4.),
+            descender_row: 0,
+            descender_plus_two: 0,
+            underline_height: 1,
+            strike_row: 0,
+            cell_size: Size::new(10, 20),
+        };
+        let info = |cluster, num_cells| GlyphInfo {
+            #[cfg(debug_assertions)]
+            text: String::new(),
+            cluster,
+            num_cells,
+            font_idx: 0,
+            glyph_pos: 0,
+            x_advance: PixelLength::zero(),
+            y_advance: PixelLength::zero(),
+            x_offset: PixelLength::zero(),
+            y_offset: PixelLength::zero(),
+            snap_to_cell: false,
+        };
+        let glyph = Rc::new(CachedGlyph::<ImageTexture> {
+            has_color: false,
+            x_offset: PixelLength::zero(),
+            y_offset: PixelLength::zero(),
+            bearing_x: PixelLength::new(1.),
+            bearing_y: PixelLength::new(12.),
+            texture: None,
+            scale: 1.,
+        });
+        let (wide, narrow) = (info(0, 2), info(1, 1));
+        let glyphs = vec![(&wide, Rc::clone(&glyph)), (&narrow, Rc::clone(&glyph))];
+
+        let white = RgbColor::new(0xff, 0xff, 0xff);
+        let black = RgbColor::new(0, 0, 0);
+        let badge = layout(Size::new(200, 100), &metrics, &glyphs, white, black, 1.);
+
+        // 3 cells of text and a cell of padding on either side
+        assert_eq!(badge.rect, Rect::new(Point::new(75, 30), Size::new(50, 40)));
+        assert_eq!(badge.foreground, black);
+        assert_eq!(badge.background, white);
+        // The baseline is at 30 + 10 + 20 - 4 = 56
+        let origins: Vec<Point> = badge.glyphs.iter().map(|(origin, _)| *origin).collect();
+        assert_eq!(origins, vec![Point::new(86, 44), Point::new(106, 44)]);
+
+        // A window that is too small for the badge crops it
+        let badge = layout(Size::new(20, 10), &metrics, &glyphs, white, black, 0.);
+        assert_eq!(badge.rect.origin, Point::new(0, 0));
+        assert_eq!(badge.background, black);
+    }
+}
@@ -1,6 +1,7 @@
 // The range_plus_one lint can't see when the LHS is not compatible with
 // and inclusive range
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
+use crate::config::configuration;
 use crate::mux::renderable::Renderable;
 use serde::{Deserialize, Serialize};
 use std::ops::Range;
@@ -12,6 +13,9 @@ pub enum SelectionMode {
     Cell,
     Word,
     Line,
+    /// A rectangular selection of the columns between the
+    /// start and end coordinates
+    Block,
 }
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
@@ -21,6 +25,9 @@ pub struct Selection {
     pub start: Option<SelectionCoordinate>,
     /// Holds the not-normalized selection range.
     pub range: Option<SelectionRange>,
+    /// If true, the selection is a rectangular block rather
+    /// than a run of text
+    pub rectangular: bool,
 }
 
 impl Selection {
@@ -33,12 +40,29 @@ impl Selection {
     pub fn begin(&mut self, start: SelectionCoordinate) {
         self.range = None;
         self.start = Some(start);
+        self.rectangular = false;
     }
 
     #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.range.is_none()
     }
+
+    /// Yields the selected columns for the specified row, taking
+    /// into account whether this is a rectangular selection.
+    pub fn cols_for_row(&self, row: StableRowIndex) -> Range<usize> {
+        match self.range {
+            None => 0..0,
+            Some(range) if self.rectangular => {
+                if range.rows().contains(&row) {
+                    range.rectangular_cols()
+                } else {
+                    0..0
+                }
+            }
+            Some(range) => range.cols_for_row(row),
+        }
+    }
 }
 
 /// The x,y coordinates of either the start or end of a selection region
@@ -56,15 +80,16 @@ pub struct SelectionRange {
     pub end: SelectionCoordinate,
 }
 
-// TODO: expose is_double_click_word in config file
-fn is_double_click_word(s: &str) -> bool {
-    match s.len() {
-        1 => match s.chars().nth(0).unwrap() {
-            ' ' | '\t' | '\n' | '{' | '[' | '}' | ']' | '(' | ')' | '"' | '\'' => false,
-            _ => true,
-        },
-        0 => false,
-        _ => true,
+/// Returns true if the grapheme `s` is part of a word, rather than
+/// being one of the `boundary` characters that delimit words.
+/// Whitespace is always considered to be a boundary.
+pub fn is_double_click_word(s: &str, boundary: &str) -> bool {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => false,
+        (Some(c), None) => !c.is_whitespace() && !boundary.contains(c),
+        // A multi-codepoint grapheme is always a word
+        (Some(_), Some(_)) => true,
     }
 }
 
@@ -89,11 +114,13 @@ impl SelectionRange {
     /// Computes the selection range for the word around the specified coords
     pub fn word_around(start: SelectionCoordinate, renderer: &mut dyn Renderable) -> Self {
         let (first, lines) = renderer.get_lines(start.y..start.y + 1);
+        let config = configuration();
+        let is_word = |s: &str| is_double_click_word(s, &config.selection_word_boundary);
 
         // TODO: if selection_range.start.x == 0, search backwards for wrapping
         // lines too.
 
-        match lines[0].compute_double_click_range(start.x, is_double_click_word) {
+        match lines[0].compute_double_click_range(start.x, is_word) {
             DoubleClickRange::Range(click_range) => Self {
                 start: SelectionCoordinate {
                     x: click_range.start,
@@ -120,7 +147,7 @@ impl SelectionRange {
                     if first != y_cont {
                         break;
                     }
-                    match lines[0].compute_double_click_range(0, is_double_click_word) {
+                    match lines[0].compute_double_click_range(0, is_word) {
                         DoubleClickRange::Range(range_end) => {
                             if range_end.end > range_end.start {
                                 end_coord = SelectionCoordinate {
@@ -220,4 +247,10 @@ impl SelectionRange {
             0..usize::max_value()
         }
     }
+
+    /// Yields the columns spanned by a rectangular selection.
+    /// These are the same for each of its rows.
+    pub fn rectangular_cols(&self) -> Range<usize> {
+        self.start.x.min(self.end.x)..self.start.x.max(self.end.x).saturating_add(1)
+    }
 }
@@ -0,0 +1,80 @@
+//! Runs the `status_command` from the configuration at the configured
+//! interval, keeping its most recent output for the status area at
+//! the right of the tab bar.
+use crate::config::configuration;
+use anyhow::bail;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+
+lazy_static::lazy_static! {
+    static ref COMMAND_STATUS: Mutex<Option<String>> = Mutex::new(None);
+}
+static START: Once = Once::new();
+
+/// Returns the most recent output of the `status_command`, starting
+/// the thread that runs it on the first call
+pub fn command_status() -> Option<String> {
+    START.call_once(|| {
+        std::thread::spawn(update_loop);
+    });
+    COMMAND_STATUS.lock().unwrap().clone()
+}
+
+fn update_loop() {
+    loop {
+        // The config is re-read each time so that reloading it
+        // changes the command and the interval
+        let config = configuration();
+        let status = match config.status_command.as_ref() {
+            Some(args) if !args.is_empty() => match run_command(args) {
+                Ok(status) => Some(status),
+                Err(err) => {
+                    log::error!("status_command {:?}: {:#}", args, err);
+                    None
+                }
+            },
+            _ => None,
+        };
+        *COMMAND_STATUS.lock().unwrap() = status;
+
+        let interval = Duration::from_millis(config.status_update_interval.max(100));
+        drop(config);
+        std::thread::sleep(interval);
+    }
+}
+
+fn run_command(args: &[String]) -> anyhow::Result<String> {
+    let output = Command::new(&args[0])
+        .args(&args[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        bail!("exited with {}", output.status);
+    }
+    Ok(status_from_output(&output.stdout))
+}
+
+/// The status area is a single row, so the lines of the output
+/// are joined together
+fn status_from_output(stdout: &[u8]) -> String {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn output_is_one_line() {
+        assert_eq!(status_from_output(b"12:30\n"), "12:30");
+        assert_eq!(status_from_output(b" 80%\r\n\ncharging\n"), "80% charging");
+        assert_eq!(status_from_output(b""), "");
+    }
+}
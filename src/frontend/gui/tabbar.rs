@@ -3,7 +3,8 @@ use crate::mux::window::Window as MuxWindow;
 use std::cell::Ref;
 use termwiz::cell::unicode_column_width;
 use termwiz::cell::{Cell, CellAttributes};
-use termwiz::color::ColorSpec;
+use termwiz::color::{ColorSpec, RgbColor};
+use termwiz::escape::osc::Progress;
 use unicode_segmentation::UnicodeSegmentation;
 use wezterm_term::Line;
 
@@ -11,6 +12,16 @@ use wezterm_term::Line;
 pub struct TabBarState {
     line: Line,
     items: Vec<TabEntry>,
+    progress: Vec<ProgressStrip>,
+}
+
+/// A strip drawn along the bottom of a tab's entry in the tab bar
+/// to indicate the progress reported by the application in that tab
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgressStrip {
+    pub x: usize,
+    pub width: usize,
+    pub color: RgbColor,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -32,6 +43,7 @@ impl TabBarState {
         Self {
             line: Line::with_width(1),
             items: vec![],
+            progress: vec![],
         }
     }
 
@@ -39,15 +51,25 @@ impl TabBarState {
         &self.line
     }
 
+    pub fn progress(&self) -> &[ProgressStrip] {
+        &self.progress
+    }
+
     /// Build a new tab bar from the current state
     /// mouse_x is some if the mouse is on the same row as the tab bar.
     /// title_width is the total number of cell columns in the window.
     /// window allows access to the tabs associated with the window.
+    /// status is shown at the right of the bar, in the space that is
+    /// left over by the tabs.
+    /// unseen_output indicates, for each tab, whether it produced
+    /// output that hasn't been seen; those tabs are marked.
     pub fn new(
         title_width: usize,
         mouse_x: Option<usize>,
         window: &Ref<MuxWindow>,
         colors: Option<&TabBarColors>,
+        status: Option<&str>,
+        unseen_output: &[bool],
     ) -> Self {
         // We ultimately want to produce a line looking like this:
         // ` | tab1-title x | tab2-title x |  +      . - X `
@@ -57,10 +79,23 @@ impl TabBarState {
         let per_tab_overhead = 2;
         let system_overhead = 3;
 
+        // Tabs can override their background color so that they stand
+        // out from the others, even when inactive
+        let tab_backgrounds: Vec<_> = window
+            .iter()
+            .map(|w| w.color_overrides().background)
+            .collect();
+
+        let tab_progress: Vec<_> = window.iter().map(|w| w.get_progress()).collect();
+
         let tab_titles: Vec<_> = window
             .iter()
-            .map(|w| {
+            .enumerate()
+            .map(|(idx, w)| {
                 let mut title = w.get_title();
+                if unseen_output.get(idx).cloned().unwrap_or(false) {
+                    title.insert_str(0, "\u{2022} ");
+                }
                 // We have a preferred soft minimum on tab width to make it
                 // easier to click on tab titles, but we'll still go below
                 // this if there are too many tabs to fit the window at
@@ -91,6 +126,7 @@ impl TabBarState {
         let active_tab_no = window.get_active_idx();
         let mut x = 0;
         let mut items = vec![];
+        let mut progress = vec![];
 
         for (tab_idx, tab_title) in tab_titles.iter().enumerate() {
             let tab_title_len = unicode_column_width(tab_title).min(tab_width_max);
@@ -100,13 +136,16 @@ impl TabBarState {
                 .unwrap_or(false);
             let active = tab_idx == active_tab_no;
 
-            let cell_attrs = if active {
+            let mut cell_attrs = if active {
                 colors.active_tab.as_cell_attributes()
             } else if hover {
                 colors.inactive_tab_hover.as_cell_attributes()
             } else {
                 colors.inactive_tab.as_cell_attributes()
             };
+            if let Some(background) = tab_backgrounds[tab_idx] {
+                cell_attrs.set_background(ColorSpec::TrueColor(background));
+            }
 
             let tab_start_idx = x;
 
@@ -125,10 +164,19 @@ impl TabBarState {
             line.set_cell(x, Cell::new(' ', cell_attrs));
             x += 1;
 
+            let width = x - tab_start_idx;
+            if let Some((filled, color)) = progress_extent(tab_progress[tab_idx], width, &colors) {
+                progress.push(ProgressStrip {
+                    x: tab_start_idx,
+                    width: filled,
+                    color,
+                });
+            }
+
             items.push(TabEntry {
                 item: TabBarItem::Tab(tab_idx),
                 x: tab_start_idx,
-                width: x - tab_start_idx,
+                width,
             });
         }
 
@@ -166,7 +214,23 @@ impl TabBarState {
             line.set_cell(idx, black_cell.clone());
         }
 
-        Self { line, items }
+        // Right align the status, leaving a cell of space either side
+        if let Some(status) = status {
+            let graphemes = truncate_left(status, title_width.saturating_sub(x + 2));
+            let width: usize = graphemes.iter().map(|g| unicode_column_width(g)).sum();
+            let cell_attrs = colors.status.as_cell_attributes();
+            let mut status_x = title_width.saturating_sub(width + 1);
+            for sub in graphemes {
+                line.set_cell(status_x, Cell::new_grapheme(sub, cell_attrs.clone()));
+                status_x += unicode_column_width(sub);
+            }
+        }
+
+        Self {
+            line,
+            items,
+            progress,
+        }
     }
 
     /// Determine which component the mouse is over
@@ -179,3 +243,53 @@ impl TabBarState {
         TabBarItem::None
     }
 }
+
+/// Returns the graphemes at the end of `text` that fit in `max_width`
+/// cells, dropping those at the start that don't fit
+fn truncate_left(text: &str, max_width: usize) -> Vec<&str> {
+    let mut graphemes = vec![];
+    let mut width = 0;
+    for sub in text.graphemes(true).rev() {
+        width += unicode_column_width(sub);
+        if width > max_width {
+            break;
+        }
+        graphemes.push(sub);
+    }
+    graphemes.reverse();
+    graphemes
+}
+
+/// Computes how many of the `width` cells of a tab should be covered
+/// by its progress strip, and the color of that strip.
+/// States that don't carry a meaningful percentage span the whole tab.
+fn progress_extent(
+    progress: Progress,
+    width: usize,
+    colors: &TabBarColors,
+) -> Option<(usize, RgbColor)> {
+    let fraction = |pct: u8| (width * pct as usize + 99) / 100;
+    match progress {
+        Progress::None => None,
+        Progress::SetPercentage(pct) => Some((fraction(pct), colors.progress)),
+        Progress::SetIndeterminate => Some((width, colors.progress)),
+        Progress::SetError(0) => Some((width, colors.progress_error)),
+        Progress::SetError(pct) => Some((fraction(pct), colors.progress_error)),
+        Progress::Paused(0) => Some((width, colors.progress_paused)),
+        Progress::Paused(pct) => Some((fraction(pct), colors.progress_paused)),
+    }
+    .filter(|(filled, _)| *filled > 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn status_is_truncated_from_the_left() {
+        assert_eq!(truncate_left("12:30 80%", 20).concat(), "12:30 80%");
+        assert_eq!(truncate_left("12:30 80%", 3).concat(), "80%");
+        assert_eq!(truncate_left("\u{4e2d}\u{6587}", 3).concat(), "\u{6587}");
+        assert!(truncate_left("12:30", 0).is_empty());
+    }
+}
@@ -1,31 +1,48 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
+use super::glyphcache::GlyphCache;
 use super::quad::*;
 use super::renderstate::*;
-use super::utilsprites::RenderMetrics;
-use crate::config::{configuration, ConfigHandle, TextStyle};
+use super::utilsprites::{CursorCell, RenderMetrics};
+use crate::appearance::Appearance;
+use crate::config::{
+    configuration, ConfigChanges, ConfigHandle, CursorColorMode, TabColorOverrides,
+    TabFontOverride, TextStyle, WindowConfigOverrides,
+};
 use crate::font::shaper::GlyphInfo;
 use crate::font::units::*;
 use crate::font::FontConfiguration;
 use crate::frontend::activity::Activity;
 use crate::frontend::front_end;
+use crate::frontend::gui::atlasview;
+use crate::frontend::gui::bell;
+use crate::frontend::gui::filedrop::{self, DropInput};
+use crate::frontend::gui::filelink;
 use crate::frontend::gui::overlay::{
-    launcher, start_overlay, tab_navigator, CopyOverlay, SearchOverlay,
+    command_palette, debug_overlay, export_text, launcher, palette_entries, rename_workspace,
+    save_text, start_overlay, tab_navigator, workspace_entries, CopyOverlay, OverlayId,
+    PaletteEntry, SearchOverlay, TabEntry,
 };
+use crate::frontend::gui::resizebadge::{self, ResizeBadge};
 use crate::frontend::gui::scrollbar::*;
 use crate::frontend::gui::selection::*;
 use crate::frontend::gui::tabbar::{TabBarItem, TabBarState};
+use crate::frontend::gui::workspace;
 use crate::keyassignment::{
-    InputMap, KeyAssignment, MouseEventTrigger, SpawnCommand, SpawnTabDomain,
+    InputMap, KeyAssignment, MouseEventTrigger, ScrollbackCommand, SpawnCommand, SpawnTabDomain,
 };
 use crate::mux::domain::{DomainId, DomainState};
+use crate::mux::latency;
+use crate::mux::recording;
 use crate::mux::renderable::{RenderableDimensions, StableCursorPosition};
-use crate::mux::tab::{Tab, TabId};
+use crate::mux::tab::{ExportTextOptions, Tab, TabId};
 use crate::mux::window::WindowId as MuxWindowId;
-use crate::mux::Mux;
+use crate::mux::{Mux, MuxNotification, MuxSubscriber};
+use crate::termwiztermtab::TermWizTerminalTab;
 use ::wezterm_term::input::MouseButton as TMB;
 use ::wezterm_term::input::MouseEventKind as TMEK;
 use ::window::bitmaps::atlas::{OutOfTextureSpace, SpriteSlice};
 use ::window::bitmaps::Texture2d;
+use ::window::glium::texture::SrgbTexture2d;
 use ::window::glium::uniforms::{
     MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction,
 };
@@ -38,7 +55,7 @@ use lru::LruCache;
 use portable_pty::{CommandBuilder, PtySize};
 use std::any::Any;
 use std::cell::{RefCell, RefMut};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::ops::Range;
 use std::ops::{Add, Sub};
@@ -46,15 +63,25 @@ use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
+use termwiz::cellcluster::CellCluster;
 use termwiz::color::RgbColor;
+use termwiz::escape::osc::Progress;
+use termwiz::escape::parser::Encoding;
 use termwiz::hyperlink::Hyperlink;
 use termwiz::surface::{CursorShape, CursorVisibility};
 use wezterm_term::color::ColorPalette;
 use wezterm_term::input::LastMouseClick;
+use wezterm_term::selection::{expand_cols_to_cells, selection_text, SelectionTextOptions};
 use wezterm_term::{Line, StableRowIndex, Underline};
 
 const ATLAS_SIZE: usize = 4096;
 
+/// The minimum interval between resizing the tabs in a window.
+/// Interactively dragging the window edge can produce a storm of
+/// resize events, and there is no benefit to resizing the ptys
+/// for each of them.
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(50);
+
 struct RenderScreenLineOpenGLParams<'a> {
     line_idx: usize,
     stable_line_idx: Option<StableRowIndex>,
@@ -70,7 +97,7 @@ struct RenderScreenLineOpenGLParams<'a> {
     background: Color,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct RowsAndCols {
     rows: usize,
     cols: usize,
@@ -103,7 +130,12 @@ impl wezterm_term::Clipboard for ClipboardHelper {
     }
 
     fn set_contents(&self, data: Option<String>) -> anyhow::Result<()> {
-        self.window.set_clipboard(data.unwrap_or_else(String::new));
+        // This is called from the thread that parses the output of the
+        // tab, so leave it to the main thread to update the clipboard
+        let window = self.window.clone();
+        promise::spawn::spawn_into_main_thread(async move {
+            window.set_clipboard(Clipboard::Clipboard, data.unwrap_or_else(String::new));
+        });
         Ok(())
     }
 }
@@ -141,6 +173,28 @@ impl PrevCursorPos {
     }
 }
 
+/// Tracks a selection that is being extended by dragging the mouse,
+/// so that the viewport can be scrolled while the mouse is held
+/// above or below the terminal area
+#[derive(Clone, Copy)]
+struct SelectionDrag {
+    mode: Option<SelectionMode>,
+    /// How many rows to extend the selection by on each maintenance
+    /// tick; negative to extend up into the scrollback, zero when the
+    /// mouse is within the terminal area
+    rows_per_tick: StableRowIndex,
+}
+
+/// A table from the `key_tables` config that was activated by
+/// the ActivateKeyTable assignment
+struct ActiveKeyTable {
+    name: String,
+    one_shot: bool,
+    timeout: Option<Duration>,
+    /// When the table is deactivated, unless another key is pressed
+    expires: Option<Instant>,
+}
+
 #[derive(Default, Clone)]
 pub struct TabState {
     /// If is_some(), the top row of the visible screen.
@@ -148,14 +202,54 @@ pub struct TabState {
     /// scrollback.
     viewport: Option<StableRowIndex>,
     selection: Selection,
-    /// If is_some(), rather than display the actual tab
+    /// If non-empty, rather than display the actual tab
     /// contents, we're overlaying a little internal application
-    /// tab.  We'll also route input to it.
-    pub overlay: Option<Rc<dyn Tab>>,
+    /// tab.  Overlays can be opened from other overlays; only the
+    /// most recently opened one is displayed and receives input,
+    /// and the others resume when it is cancelled.
+    overlays: Vec<(OverlayId, Rc<dyn Tab>)>,
+    /// When we last showed a notification requested by this tab
+    last_notification: Option<Instant>,
+    /// When the bell of this tab last rang
+    last_bell: Option<Instant>,
+    /// While the visual bell of this tab is showing, the time at
+    /// which it ends
+    visual_bell_until: Option<Instant>,
+    /// Output arrived while the tab wasn't active, or while it was
+    /// scrolled back, and the bottom of the tab hasn't been seen since
+    has_unseen_output: bool,
+    /// The fonts of a tab that has a font override
+    fonts: Option<TabFonts>,
+}
+
+#[derive(Clone)]
+struct TabFonts {
+    /// The override that `fonts` were created for
+    font_override: TabFontOverride,
+    fonts: Rc<FontConfiguration>,
+}
+
+impl TabState {
+    /// Returns the overlay that is displayed and receives input
+    pub fn overlay(&self) -> Option<&Rc<dyn Tab>> {
+        self.overlays.last().map(|(_, overlay)| overlay)
+    }
+
+    /// Returns the overlay identified by `overlay_id`, whether or not
+    /// it is the one that is displayed
+    pub fn get_overlay(&self, overlay_id: OverlayId) -> Option<&Rc<dyn Tab>> {
+        self.overlays
+            .iter()
+            .find(|(id, _)| *id == overlay_id)
+            .map(|(_, overlay)| overlay)
+    }
 }
 
+/// The text is shaped with the fonts whose id is `fonts_id`, as the
+/// tabs of a window can each have their own fonts
 #[derive(PartialEq, Eq, Hash)]
 struct ShapeCacheKey {
+    fonts_id: usize,
     style: TextStyle,
     text: String,
 }
@@ -167,13 +261,23 @@ struct ShapeCacheKey {
 /// <https://github.com/sunshowers/borrow-complex-key-example/blob/master/src/lib.rs>
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 struct BorrowedShapeCacheKey<'a> {
+    fonts_id: usize,
     style: &'a TextStyle,
     text: &'a str,
 }
 
+/// A cluster of cells from a line, along with the style that was
+/// matched for its attributes and the result of shaping its text
+struct ShapedCluster {
+    cluster: CellCluster,
+    style: TextStyle,
+    glyph_info: Rc<Vec<GlyphInfo>>,
+}
+
 impl<'a> BorrowedShapeCacheKey<'a> {
     fn to_owned(&self) -> ShapeCacheKey {
         ShapeCacheKey {
+            fonts_id: self.fonts_id,
             style: self.style.clone(),
             text: self.text.to_owned(),
         }
@@ -187,6 +291,7 @@ trait ShapeCacheKeyTrait {
 impl ShapeCacheKeyTrait for ShapeCacheKey {
     fn key<'k>(&'k self) -> BorrowedShapeCacheKey<'k> {
         BorrowedShapeCacheKey {
+            fonts_id: self.fonts_id,
             style: &self.style,
             text: &self.text,
         }
@@ -230,22 +335,56 @@ pub struct TermWindow {
     pub window: Option<Window>,
     /// When we most recently received keyboard focus
     focused: Option<Instant>,
+    /// The fonts of the active tab, which determine the size of the
+    /// cells
     fonts: Rc<FontConfiguration>,
+    /// The fonts of the tabs that don't have a font override
+    window_fonts: Rc<FontConfiguration>,
+    /// Configuration that applies only to this window
+    config_overrides: WindowConfigOverrides,
     /// Window dimensions and dpi
     dimensions: Dimensions,
     /// Terminal dimensions
     terminal_size: PtySize,
+    /// A terminal size that has yet to be applied to the tabs
+    pending_tab_resize: Option<PtySize>,
+    /// When we last applied a size to the tabs
+    last_tab_resize: Instant,
     mux_window_id: MuxWindowId,
     render_metrics: RenderMetrics,
     render_state: RenderState,
     input_map: InputMap,
     show_tab_bar: bool,
     show_scroll_bar: bool,
+    /// Whether the texture atlas debug view is drawn
+    show_texture_atlas: bool,
+    /// Whether the input latency is measured and shown in the top
+    /// right corner
+    show_latency_hud: bool,
+    /// Shows the size of the terminal while the window is resized
+    resize_badge: Option<ResizeBadge>,
+    /// The fonts of the resize badge, with the id of the fonts that
+    /// they were scaled from
+    resize_badge_fonts: RefCell<Option<(usize, Rc<FontConfiguration>)>>,
     tab_bar: TabBarState,
+    /// The progress most recently shown in the taskbar
+    taskbar_progress: TaskbarProgress,
     last_mouse_coords: (usize, i64),
     last_mouse_terminal_coords: (usize, StableRowIndex),
+    /// The fraction of a line that pixel-precise scrolling has moved
+    /// by, but not yet scrolled the viewport
+    scroll_pixel_remainder: f64,
+    /// The lines moved by the wheel while the mouse is grabbed that
+    /// have yet to add up to a whole tick to report to the application
+    wheel_report_remainder: i64,
     scroll_drag_start: Option<isize>,
-    config_generation: usize,
+    /// The configuration that the window was most recently updated
+    /// for, which a reloaded configuration is compared with
+    applied_config: ConfigHandle,
+    /// The palettes of the color schemes named by the overrides of the
+    /// window and its tabs, which are converted from the configuration
+    /// once rather than as each frame is painted
+    scheme_palettes: RefCell<HashMap<String, ColorPalette>>,
     prev_cursor: PrevCursorPos,
     last_scroll_info: RenderableDimensions,
 
@@ -260,12 +399,97 @@ pub struct TermWindow {
     /// Keeps track of double and triple clicks
     last_mouse_click: Option<LastMouseClick>,
 
-    /// The URL over which we are currently hovering
+    /// The URL over which we are currently hovering.  The renderer
+    /// underlines the cells whose link is this same instance.
     current_highlight: Option<Arc<Hyperlink>>,
+    /// The rows of the viewport that contain cells of current_highlight
+    current_highlight_rows: Range<StableRowIndex>,
+    /// Whether the hovered file link exists, to avoid checking the
+    /// filesystem each time the mouse moves
+    file_link_exists: filelink::ExistsCache,
+    /// The modifiers that are held, as far as the key and mouse events
+    /// that we have seen tell us
+    held_modifiers: termwiz::input::Modifiers,
+    /// The target of current_highlight, which is shown in the status
+    /// area while the hyperlink_preview_mods are held
+    link_preview: Option<String>,
+
+    /// The mouse cursor that we last asked the window to show
+    current_mouse_cursor: Option<MouseCursor>,
+
+    /// Set while the left button is dragging out a selection
+    selection_drag: Option<SelectionDrag>,
 
     shape_cache: RefCell<LruCache<ShapeCacheKey, anyhow::Result<Rc<Vec<GlyphInfo>>>>>,
+    /// Shaped lines, keyed by the id of the fonts that shaped them and
+    /// by Line::compute_shape_hash
+    line_shape_cache: RefCell<LruCache<(usize, u64), Rc<Vec<ShapedCluster>>>>,
 
     last_blink_paint: Instant,
+
+    /// The text that the input method is composing, which is shown
+    /// at the cursor until it is committed
+    ime_preedit: Option<String>,
+
+    /// When the leader key was pressed, the time at which the chord
+    /// that it began expires
+    leader_expires: Option<Instant>,
+
+    /// The active key tables; keys are looked up in the last of them
+    key_table_stack: Vec<ActiveKeyTable>,
+
+    /// The text in the status area of the tab bar
+    status_text: Option<String>,
+
+    broadcast: BroadcastTargets,
+
+    /// Notifies us of the tabs that have produced output
+    tab_output: Option<MuxSubscriber>,
+}
+
+/// The tabs of the window that the keys typed into any one of them
+/// are sent to, as well as to the one they were typed into
+#[derive(Default)]
+struct BroadcastTargets {
+    /// Every tab in the window is a target
+    all_tabs: bool,
+    tabs: HashSet<TabId>,
+}
+
+impl BroadcastTargets {
+    fn contains(&self, tab_id: TabId) -> bool {
+        self.all_tabs || self.tabs.contains(&tab_id)
+    }
+}
+
+/// Spawns a tab in the default domain and opens a new window for it
+pub fn spawn_window_in_default_domain() {
+    async fn new_window() -> anyhow::Result<()> {
+        let mux = Mux::get().unwrap();
+        let config = crate::config::configuration();
+        let fonts = Rc::new(FontConfiguration::new());
+        let window_id = mux.new_empty_window();
+        let tab = mux
+            .default_domain()
+            .spawn(config.initial_size(), None, None, window_id)
+            .await?;
+        let front_end = front_end().expect("to be called on gui thread");
+        front_end.spawn_new_window(&fonts, &tab, window_id, &WindowConfigOverrides::default())?;
+        Ok::<(), anyhow::Error>(())
+    }
+    promise::spawn::spawn(async move {
+        new_window().await.ok();
+    });
+}
+
+fn subscribe_to_tab_output() -> Option<MuxSubscriber> {
+    match Mux::get().unwrap().subscribe() {
+        Ok(subscriber) => Some(subscriber),
+        Err(err) => {
+            log::error!("unable to subscribe to tab output: {:#}", err);
+            None
+        }
+    }
 }
 
 fn mouse_press_to_tmb(press: &MousePress) -> TMB {
@@ -276,6 +500,34 @@ fn mouse_press_to_tmb(press: &MousePress) -> TMB {
     }
 }
 
+/// Opens the uri using the command from the configuration, falling
+/// back to the default opener for the system.  File locations are
+/// opened using the editor command, if one is configured.
+fn open_uri(uri: &str) -> anyhow::Result<()> {
+    let config = configuration();
+    let argv = match filelink::editor_argv(&config, uri) {
+        Some(argv) => argv,
+        None => {
+            let uri = filelink::strip_location(uri);
+            match config.open_uri_argv(&uri) {
+                Some(argv) => argv,
+                None => {
+                    open::that(&uri)?;
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    let mut child = std::process::Command::new(&argv[0])
+        .args(&argv[1..])
+        .spawn()?;
+    // The command may run for a while (eg: an editor), so reap it
+    // from another thread rather than blocking the gui
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
 #[derive(Debug)]
 enum Key {
     Code(::termwiz::input::KeyCode),
@@ -286,9 +538,15 @@ enum Key {
 impl WindowCallbacks for TermWindow {
     fn created(&mut self, window: &Window) {
         self.window.replace(window.clone());
+        workspace::register_window(self.mux_window_id, window);
     }
 
     fn can_close(&mut self) -> bool {
+        if self.show_latency_hud {
+            self.show_latency_hud = false;
+            latency::stop_measuring();
+        }
+
         let mux = Mux::get().unwrap();
         let tab_ids: Vec<TabId> = if let Some(win) = mux.get_window(self.mux_window_id) {
             win.iter().map(|tab| tab.tab_id()).collect()
@@ -313,6 +571,13 @@ impl WindowCallbacks for TermWindow {
         if self.focused.is_none() {
             self.last_mouse_click = None;
             self.current_mouse_button = None;
+            self.selection_drag = None;
+            // Another application may change the cursor while we
+            // are not focused, so make sure that we set it again
+            self.current_mouse_cursor = None;
+            // We won't see the modifiers being released elsewhere
+            self.held_modifiers = termwiz::input::Modifiers::NONE;
+            self.update_link_preview();
         }
 
         // Reset the cursor blink phase
@@ -348,6 +613,7 @@ impl WindowCallbacks for TermWindow {
 
         let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
         self.last_mouse_coords = (x, y);
+        self.held_modifiers = window_mods_to_termwiz_mods(event.modifiers);
 
         let in_tab_bar = self.show_tab_bar && y == 0 && event.coords.y >= 0;
         let in_scroll_bar = self.show_scroll_bar && x >= self.terminal_size.cols as usize;
@@ -357,6 +623,7 @@ impl WindowCallbacks for TermWindow {
         match event.kind {
             WMEK::Release(ref press) => {
                 self.current_mouse_button = None;
+                self.selection_drag = None;
                 if press == &MousePress::Left && self.scroll_drag_start.take().is_some() {
                     // Completed a drag
                     return;
@@ -382,19 +649,29 @@ impl WindowCallbacks for TermWindow {
                 self.current_mouse_button = Some(press.clone());
             }
 
-            WMEK::VertWheel(amount) if !tab.is_mouse_grabbed() => {
+            WMEK::VertWheel(_) | WMEK::VertPixelWheel(_) if !tab.is_mouse_grabbed() => {
                 // adjust viewport
-                let dims = tab.renderer().get_dimensions();
-                let position = self
-                    .get_viewport(tab.tab_id())
-                    .unwrap_or(dims.physical_top)
-                    .saturating_sub(amount.into());
-                self.set_viewport(tab.tab_id(), Some(position), dims);
-                context.invalidate();
+                let lines = self.wheel_lines(&event.kind);
+                if lines != 0 {
+                    let dims = tab.renderer().get_dimensions();
+                    let position = self
+                        .get_viewport(tab.tab_id())
+                        .unwrap_or(dims.physical_top)
+                        .saturating_sub(lines as StableRowIndex);
+                    self.set_viewport(tab.tab_id(), Some(position), dims);
+                    context.invalidate();
+                }
                 return;
             }
 
             WMEK::Move => {
+                if self.selection_drag.is_some() {
+                    let rows_per_tick = self.selection_autoscroll_rate(event.coords.y);
+                    if let Some(drag) = self.selection_drag.as_mut() {
+                        drag.rows_per_tick = rows_per_tick;
+                    }
+                }
+
                 let current_viewport = self.get_viewport(tab.tab_id());
                 if let Some(from_top) = self.scroll_drag_start.as_ref() {
                     // Dragging the scroll bar
@@ -447,13 +724,58 @@ impl WindowCallbacks for TermWindow {
             // NOP!
             return;
         }
+        if dimensions.pixel_width != self.dimensions.pixel_width
+            || dimensions.pixel_height != self.dimensions.pixel_height
+        {
+            self.resize_badge.replace(ResizeBadge::new(Instant::now()));
+        }
         self.scaling_changed(dimensions, self.fonts.get_font_scale());
     }
 
+    fn ime_preedit(&mut self, preedit: Option<String>) {
+        let preedit = preedit.filter(|text| !text.is_empty());
+        if preedit != self.ime_preedit {
+            self.ime_preedit = preedit;
+            if let Some(window) = self.window.as_ref() {
+                window.invalidate();
+            }
+        }
+    }
+
+    fn dropped_files(&mut self, paths: Vec<std::path::PathBuf>) {
+        let tab = match self.get_active_tab_or_overlay() {
+            Some(tab) => tab,
+            None => return,
+        };
+        let result =
+            match filedrop::input_for_drop(&paths, configuration().dropped_directory_action) {
+                Some(DropInput::Paste(text)) => tab.send_paste(&text),
+                Some(DropInput::Command(command)) => tab.send_text(&command),
+                None => return,
+            };
+        if let Err(err) = result {
+            log::error!("while sending dropped files to the tab: {:#}", err);
+        }
+        self.maybe_scroll_to_bottom_for_input(&tab);
+    }
+
     fn key_event(&mut self, window_key: &KeyEvent, context: &dyn WindowOps) -> bool {
+        if let Some(modifier) = window_key.key.modifier() {
+            // Pressing or releasing a modifier may show or hide the
+            // target of the hovered link
+            let modifier = window_mods_to_termwiz_mods(modifier);
+            let held = window_mods_to_termwiz_mods(window_key.modifiers);
+            self.held_modifiers = if window_key.key_is_down {
+                held | modifier
+            } else {
+                held - modifier
+            };
+            self.update_link_preview();
+        }
         if !window_key.key_is_down {
             return false;
         }
+        let received = Instant::now();
 
         // log::error!("key_event {:?}", key);
 
@@ -464,6 +786,59 @@ impl WindowCallbacks for TermWindow {
         let modifiers = window_mods_to_termwiz_mods(window_key.modifiers);
         let raw_modifiers = window_mods_to_termwiz_mods(window_key.raw_modifiers);
 
+        let key = self.win_key_code_to_termwiz_key_code(&window_key.key);
+        let raw_key = window_key
+            .raw_key
+            .as_ref()
+            .map(|key| self.win_key_code_to_termwiz_key_code(key));
+        // Both the raw and the composed forms of the key are candidates
+        // for the leader and for the bindings that follow it
+        let mut candidates = vec![];
+        if let Some(Key::Code(raw_key)) = &raw_key {
+            candidates.push((*raw_key, raw_modifiers));
+        }
+        if let Key::Code(key) = &key {
+            candidates.push((*key, modifiers));
+        }
+
+        if let Some(handled) = self.key_table_key_event(&tab, &candidates, context) {
+            return handled;
+        }
+
+        if let Some(leader) = configuration().leader.as_ref() {
+            if self.leader_expires.is_some() {
+                if candidates.iter().any(|(key, _)| key.is_modifier()) {
+                    // Wait for the key that the modifier is pressed with
+                    return false;
+                }
+                self.disarm_leader(&tab, false);
+
+                for (key, mods) in &candidates {
+                    if let Some(assignment) = self
+                        .input_map
+                        .lookup_key(*key, *mods | termwiz::input::Modifiers::LEADER)
+                    {
+                        self.perform_key_assignment(&tab, &assignment).ok();
+                        context.invalidate();
+                        return true;
+                    }
+                }
+
+                // Not a LEADER binding; carry on and handle it as usual
+                if leader.send_leader_when_unmatched {
+                    tab.key_down(leader.key, leader.mods).ok();
+                }
+            } else if candidates.iter().any(|&(key, mods)| {
+                key.normalize_shift_to_upper_case(mods) == leader.key && mods == leader.mods
+            }) {
+                self.leader_expires =
+                    Some(Instant::now() + Duration::from_millis(leader.timeout_milliseconds));
+                self.update_title();
+                context.invalidate();
+                return true;
+            }
+        }
+
         // First chance to operate on the raw key; if it matches a
         // user-defined key binding then we execute it and stop there.
         if let Some(key) = &window_key.raw_key {
@@ -495,35 +870,48 @@ impl WindowCallbacks for TermWindow {
                         && window_key.raw_modifiers.contains(Modifiers::ALT)
                         && !config.send_composed_key_when_alt_is_pressed);
 
-                if bypass_compose && tab.key_down(key, raw_modifiers).is_ok() {
-                    if !key.is_modifier() && self.tab_state(tab.tab_id()).overlay.is_none() {
-                        self.maybe_scroll_to_bottom_for_input(&tab);
+                if bypass_compose {
+                    if !key.is_modifier() {
+                        latency::key_received(tab.tab_id(), received);
+                    }
+                    if tab.key_down(key, raw_modifiers).is_ok() {
+                        self.broadcast_key(&tab, key, raw_modifiers);
+                        if !key.is_modifier() && self.tab_state(tab.tab_id()).overlay().is_none() {
+                            self.maybe_scroll_to_bottom_for_input(&tab);
+                        }
+                        context.invalidate();
+                        return true;
                     }
-                    context.invalidate();
-                    return true;
                 }
             }
         }
 
-        let key = self.win_key_code_to_termwiz_key_code(&window_key.key);
         match key {
             Key::Code(key) => {
                 if let Some(assignment) = self.input_map.lookup_key(key, modifiers) {
                     self.perform_key_assignment(&tab, &assignment).ok();
                     context.invalidate();
                     true
-                } else if tab.key_down(key, modifiers).is_ok() {
-                    if !key.is_modifier() && self.tab_state(tab.tab_id()).overlay.is_none() {
-                        self.maybe_scroll_to_bottom_for_input(&tab);
-                    }
-                    context.invalidate();
-                    true
                 } else {
-                    false
+                    if !key.is_modifier() {
+                        latency::key_received(tab.tab_id(), received);
+                    }
+                    if tab.key_down(key, modifiers).is_ok() {
+                        self.broadcast_key(&tab, key, modifiers);
+                        if !key.is_modifier() && self.tab_state(tab.tab_id()).overlay().is_none() {
+                            self.maybe_scroll_to_bottom_for_input(&tab);
+                        }
+                        context.invalidate();
+                        true
+                    } else {
+                        false
+                    }
                 }
             }
             Key::Composed(s) => {
-                tab.writer().write_all(s.as_bytes()).ok();
+                latency::key_received(tab.tab_id(), received);
+                tab.send_text(&s).ok();
+                self.broadcast_text(&tab, &s);
                 self.maybe_scroll_to_bottom_for_input(&tab);
                 context.invalidate();
                 true
@@ -542,6 +930,7 @@ impl WindowCallbacks for TermWindow {
         };
 
         self.check_for_config_reload();
+        self.update_tab_fonts();
         self.update_text_cursor(&tab);
         self.update_title();
 
@@ -565,6 +954,7 @@ impl WindowCallbacks for TermWindow {
         }
         log::debug!("paint_tab elapsed={:?}", start.elapsed());
         metrics::value!("gui.paint.software", start.elapsed());
+        self.latency_frame_presented(&tab);
     }
 
     fn opengl_context_lost(&mut self, prior_window: &dyn WindowOps) -> anyhow::Result<()> {
@@ -590,18 +980,30 @@ impl WindowCallbacks for TermWindow {
                 focused: None,
                 mux_window_id,
                 fonts: Rc::clone(&self.fonts),
+                window_fonts: Rc::clone(&self.window_fonts),
+                config_overrides: self.config_overrides.clone(),
                 render_metrics: self.render_metrics.clone(),
                 dimensions,
                 terminal_size: self.terminal_size.clone(),
+                pending_tab_resize: self.pending_tab_resize.clone(),
+                last_tab_resize: self.last_tab_resize,
                 render_state,
                 input_map: InputMap::new(),
                 show_tab_bar: self.show_tab_bar,
                 show_scroll_bar: self.show_scroll_bar,
+                show_texture_atlas: self.show_texture_atlas,
+                show_latency_hud: self.show_latency_hud,
+                resize_badge: None,
+                resize_badge_fonts: RefCell::new(None),
                 tab_bar: self.tab_bar.clone(),
+                taskbar_progress: self.taskbar_progress,
                 last_mouse_coords: self.last_mouse_coords.clone(),
                 last_mouse_terminal_coords: self.last_mouse_terminal_coords.clone(),
+                scroll_pixel_remainder: 0.,
+                wheel_report_remainder: 0,
                 scroll_drag_start: self.scroll_drag_start.clone(),
-                config_generation: self.config_generation,
+                applied_config: self.applied_config.clone(),
+                scheme_palettes: RefCell::new(self.scheme_palettes.borrow().clone()),
                 prev_cursor: self.prev_cursor.clone(),
                 last_scroll_info: self.last_scroll_info.clone(),
                 clipboard_contents: Arc::clone(&clipboard_contents),
@@ -609,8 +1011,21 @@ impl WindowCallbacks for TermWindow {
                 current_mouse_button: self.current_mouse_button.clone(),
                 last_mouse_click: self.last_mouse_click.clone(),
                 current_highlight: self.current_highlight.clone(),
+                current_highlight_rows: self.current_highlight_rows.clone(),
+                file_link_exists: filelink::ExistsCache::default(),
+                held_modifiers: termwiz::input::Modifiers::NONE,
+                link_preview: None,
+                current_mouse_cursor: None,
+                selection_drag: None,
                 shape_cache: RefCell::new(LruCache::new(65536)),
+                line_shape_cache: RefCell::new(LruCache::new(1024)),
                 last_blink_paint: Instant::now(),
+                ime_preedit: None,
+                leader_expires: None,
+                key_table_stack: vec![],
+                status_text: None,
+                broadcast: BroadcastTargets::default(),
+                tab_output: subscribe_to_tab_output(),
             }),
         )?;
 
@@ -681,9 +1096,20 @@ impl WindowCallbacks for TermWindow {
             }
         };
         self.check_for_config_reload();
+        self.update_tab_fonts();
         self.update_text_cursor(&tab);
         let start = std::time::Instant::now();
-        if let Err(err) = self.paint_tab_opengl(&tab, frame) {
+        let custom_shader = configuration().custom_shader.clone();
+        let post_process = self
+            .render_state
+            .opengl()
+            .update_post_process(custom_shader.as_deref());
+        let result = if post_process {
+            self.paint_tab_post_processed(&tab, frame)
+        } else {
+            self.paint_tab_opengl(&tab, frame)
+        };
+        if let Err(err) = result {
             if let Some(&OutOfTextureSpace { size }) = err.downcast_ref::<OutOfTextureSpace>() {
                 log::error!("out of texture space, allocating {}", size);
                 if let Err(err) = self.recreate_texture_atlas(Some(size)) {
@@ -702,6 +1128,7 @@ impl WindowCallbacks for TermWindow {
         }
         log::debug!("paint_tab_opengl elapsed={:?}", start.elapsed());
         metrics::value!("gui.paint.opengl", start.elapsed());
+        self.latency_frame_presented(&tab);
         self.update_title();
     }
 }
@@ -724,11 +1151,18 @@ impl TermWindow {
         fontconfig: &Rc<FontConfiguration>,
         tab: &Rc<dyn Tab>,
         mux_window_id: MuxWindowId,
+        config_overrides: &WindowConfigOverrides,
     ) -> anyhow::Result<()> {
         let dims = tab.renderer().get_dimensions();
         let physical_rows = dims.viewport_rows;
         let physical_cols = dims.cols;
 
+        fontconfig.set_font_size(config_overrides.font_size);
+        if let Some(workspace) = config_overrides.workspace.as_ref() {
+            if let Some(mut window) = Mux::get().unwrap().get_window_mut(mux_window_id) {
+                window.set_workspace(Some(workspace.clone()));
+            }
+        }
         let render_metrics = RenderMetrics::new(fontconfig);
 
         let terminal_size = PtySize {
@@ -780,18 +1214,30 @@ impl TermWindow {
                 focused: None,
                 mux_window_id,
                 fonts: Rc::clone(fontconfig),
+                window_fonts: Rc::clone(fontconfig),
+                config_overrides: config_overrides.clone(),
                 render_metrics,
                 dimensions,
                 terminal_size,
+                pending_tab_resize: None,
+                last_tab_resize: Instant::now(),
                 render_state,
                 input_map: InputMap::new(),
                 show_tab_bar,
                 show_scroll_bar: config.enable_scroll_bar,
+                show_texture_atlas: false,
+                show_latency_hud: false,
+                resize_badge: None,
+                resize_badge_fonts: RefCell::new(None),
                 tab_bar: TabBarState::default(),
+                taskbar_progress: TaskbarProgress::None,
                 last_mouse_coords: (0, -1),
                 last_mouse_terminal_coords: (0, 0),
+                scroll_pixel_remainder: 0.,
+                wheel_report_remainder: 0,
                 scroll_drag_start: None,
-                config_generation: config.generation(),
+                applied_config: config.clone(),
+                scheme_palettes: RefCell::new(HashMap::new()),
                 prev_cursor: PrevCursorPos::new(),
                 last_scroll_info: RenderableDimensions::default(),
                 clipboard_contents: Arc::clone(&clipboard_contents),
@@ -799,8 +1245,21 @@ impl TermWindow {
                 current_mouse_button: None,
                 last_mouse_click: None,
                 current_highlight: None,
+                current_highlight_rows: 0..0,
+                file_link_exists: filelink::ExistsCache::default(),
+                held_modifiers: termwiz::input::Modifiers::NONE,
+                link_preview: None,
+                current_mouse_cursor: None,
+                selection_drag: None,
                 shape_cache: RefCell::new(LruCache::new(65536)),
+                line_shape_cache: RefCell::new(LruCache::new(1024)),
                 last_blink_paint: Instant::now(),
+                ime_preedit: None,
+                leader_expires: None,
+                key_table_stack: vec![],
+                status_text: None,
+                broadcast: BroadcastTargets::default(),
+                tab_output: subscribe_to_tab_output(),
             }),
         )?;
 
@@ -867,9 +1326,30 @@ impl TermWindow {
     fn periodic_window_maintenance(&mut self, _window: &dyn WindowOps) -> anyhow::Result<()> {
         let mux = Mux::get().unwrap();
 
+        self.apply_pending_tab_resize();
+        self.process_tab_output();
+        self.update_tab_fonts();
+        mux.update_tab_visibility();
+        if self.update_tab_progress() {
+            self.update_title();
+        }
+
         if let Some(tab) = self.get_active_tab_or_overlay() {
             let mut needs_invalidate = false;
 
+            if let Some(expires) = self.leader_expires {
+                if Instant::now() >= expires {
+                    self.disarm_leader(&tab, true);
+                    needs_invalidate = true;
+                }
+            }
+            if self.expire_key_tables() {
+                needs_invalidate = true;
+            }
+            self.update_status_text();
+
+            self.autoscroll_selection(&tab);
+
             // If the config was reloaded, ask the window to apply
             // and render any changes
             self.check_for_config_reload();
@@ -941,6 +1421,25 @@ impl TermWindow {
                 }
             }
 
+            if let RenderState::GL(gl_state) = &self.render_state {
+                if gl_state.check_post_process() {
+                    needs_invalidate = true;
+                }
+            }
+
+            // Repaint the resize badge as it fades out, and once more
+            // to remove it
+            if let Some(badge) = self.resize_badge.as_ref() {
+                match badge.opacity(Instant::now()) {
+                    Some(opacity) if opacity >= 1. => {}
+                    Some(_) => needs_invalidate = true,
+                    None => {
+                        self.resize_badge.take();
+                        needs_invalidate = true;
+                    }
+                }
+            }
+
             if needs_invalidate {
                 self.window.as_ref().unwrap().invalidate();
             }
@@ -948,9 +1447,164 @@ impl TermWindow {
             self.window.as_ref().unwrap().close();
         }
 
+        self.dispatch_notifications();
+        self.dispatch_bells();
+        self.dispatch_resize_requests();
+
         Ok(())
     }
 
+    /// Resizes the window to fit the cell grid most recently requested
+    /// by the application in the active tab, if that is permitted by
+    /// the configuration.  Requests from the other tabs are discarded.
+    fn dispatch_resize_requests(&mut self) {
+        let mux = Mux::get().unwrap();
+        let tabs: Vec<Rc<dyn Tab>> = match mux.get_window(self.mux_window_id) {
+            Some(window) => window.iter().cloned().collect(),
+            None => return,
+        };
+        let active_tab_id = self.get_active_tab_no_overlay().map(|tab| tab.tab_id());
+
+        let mut request = None;
+        for tab in tabs {
+            if let Some(r) = tab.take_resize_request() {
+                if Some(tab.tab_id()) == active_tab_id {
+                    request.replace((r, tab.resize_requests_are_trusted()));
+                }
+            }
+        }
+
+        let request = match request {
+            Some((request, trusted)) if trusted || configuration().allow_window_resize_requests => {
+                request
+            }
+            Some((request, _)) => {
+                log::debug!("ignoring resize request {:?}", request);
+                return;
+            }
+            None => return,
+        };
+
+        let cells = RowsAndCols {
+            rows: request.rows,
+            cols: request.cols,
+        };
+        if cells != self.current_cell_dimensions() {
+            let dimensions = self.dimensions;
+            self.apply_dimensions(&dimensions, Some(cells));
+        }
+    }
+
+    /// Show the desktop notifications requested by the tabs in this
+    /// window, discarding those that arrive within the configured
+    /// cooldown period of the prior notification from the same tab
+    fn dispatch_notifications(&mut self) {
+        let mux = Mux::get().unwrap();
+        let tabs: Vec<Rc<dyn Tab>> = match mux.get_window(self.mux_window_id) {
+            Some(window) => window.iter().cloned().collect(),
+            None => return,
+        };
+        let cooldown = Duration::from_millis(configuration().notification_cooldown_ms);
+
+        for tab in tabs {
+            let notification = match tab.take_notifications().pop() {
+                Some(notification) => notification,
+                None => continue,
+            };
+
+            let now = Instant::now();
+            let mut state = self.tab_state(tab.tab_id());
+            if let Some(last) = state.last_notification {
+                if now.duration_since(last) < cooldown {
+                    log::trace!("discarding notification {:?}", notification);
+                    continue;
+                }
+            }
+            state.last_notification.replace(now);
+            drop(state);
+
+            // Include the tab title so that the user can tell which
+            // tab needs their attention
+            let title = match &notification.title {
+                Some(title) => format!("{} - {}", title, tab.get_title()),
+                None => tab.get_title(),
+            };
+
+            let window = self.window.clone();
+            let tab_id = tab.tab_id();
+            crate::toast_notification_with_click(&title, &notification.body, move || {
+                promise::spawn::spawn_into_main_thread(async move {
+                    if let Some(window) = window {
+                        window.apply(move |myself, window| {
+                            if let Some(myself) = myself.downcast_mut::<Self>() {
+                                myself.activate_tab_by_id(tab_id)?;
+                                window.show();
+                            }
+                            Ok(())
+                        });
+                    }
+                });
+            });
+        }
+    }
+
+    /// Rings the bell for the tabs in this window whose application
+    /// has rung it, ignoring bells that arrive within the configured
+    /// cooldown period of the prior bell from the same tab, and
+    /// repaints as the visual bell starts and ends
+    fn dispatch_bells(&mut self) {
+        let mux = Mux::get().unwrap();
+        let tabs: Vec<Rc<dyn Tab>> = match mux.get_window(self.mux_window_id) {
+            Some(window) => window.iter().cloned().collect(),
+            None => return,
+        };
+        let config = configuration();
+        let cooldown = Duration::from_millis(config.bell_cooldown_ms);
+        let focused = self.focused.is_some();
+        let now = Instant::now();
+        let mut needs_invalidate = false;
+
+        for tab in tabs {
+            let rung = tab.take_bell();
+            let mut state = self.tab_state(tab.tab_id());
+            if let Some(until) = state.visual_bell_until {
+                if now >= until {
+                    state.visual_bell_until.take();
+                    needs_invalidate = true;
+                }
+            }
+            if !rung {
+                continue;
+            }
+            if let Some(last) = state.last_bell {
+                if now.duration_since(last) < cooldown {
+                    continue;
+                }
+            }
+            state.last_bell.replace(now);
+
+            if let Some(duration) = bell::ring(&config, focused) {
+                state.visual_bell_until.replace(now + duration);
+                needs_invalidate = true;
+            }
+        }
+
+        if needs_invalidate {
+            if let Some(window) = self.window.as_ref() {
+                window.invalidate();
+            }
+        }
+    }
+
+    fn activate_tab_by_id(&mut self, tab_id: TabId) -> anyhow::Result<()> {
+        let mux = Mux::get().unwrap();
+        let idx = mux
+            .get_window(self.mux_window_id)
+            .and_then(|window| window.idx_by_id(tab_id))
+            .ok_or_else(|| anyhow!("tab {} is no longer in this window", tab_id))?;
+        self.activate_tab(idx as isize)
+    }
+
     fn win_key_code_to_termwiz_key_code(&self, key: &::window::KeyCode) -> Key {
         use ::termwiz::input::KeyCode as KC;
         use ::window::KeyCode as WK;
@@ -1068,14 +1722,35 @@ impl TermWindow {
     }
 
     fn check_for_config_reload(&mut self) {
-        if self.config_generation != configuration().generation() {
+        if self.applied_config.generation() != configuration().generation() {
             self.config_was_reloaded();
         }
     }
 
+    /// Updates the window for the options that changed.  The colors are
+    /// resolved as each frame is painted, so a change that is limited to
+    /// them, or to the appearance that they follow, only needs the window
+    /// to be painted again; the fonts, along with the glyphs rendered
+    /// from them, are only discarded when the font options changed.
     fn config_was_reloaded(&mut self) {
         let config = configuration();
-        self.config_generation = config.generation();
+        let changes = ConfigChanges::between(&self.applied_config, &config);
+        self.applied_config = config.clone();
+        self.scheme_palettes.borrow_mut().clear();
+        log::debug!(
+            "window {} applying changes to {}",
+            self.mux_window_id,
+            changes
+        );
+
+        #[cfg(target_os = "macos")]
+        {
+            window::os::macos::use_ime(config.use_ime);
+            window::os::macos::set_option_composes(
+                config.send_composed_key_when_left_alt_is_pressed,
+                config.send_composed_key_when_right_alt_is_pressed,
+            );
+        }
 
         let mux = Mux::get().unwrap();
         let window = match mux.get_window(self.mux_window_id) {
@@ -1089,91 +1764,364 @@ impl TermWindow {
         }
 
         self.show_scroll_bar = config.enable_scroll_bar;
-        self.shape_cache.borrow_mut().clear();
-        self.input_map = InputMap::new();
-        let dimensions = self.dimensions;
-        let cell_dims = self.current_cell_dimensions();
-        self.apply_scale_change(&dimensions, self.fonts.get_font_scale());
-        self.apply_dimensions(&dimensions, Some(cell_dims));
+        if changes.keys {
+            self.input_map = InputMap::new();
+        }
+        if changes.fonts || changes.other {
+            let dimensions = self.dimensions;
+            let cell_dims = self.current_cell_dimensions();
+            if changes.fonts {
+                self.apply_scale_change(&dimensions, self.fonts.get_font_scale());
+            }
+            // The padding or the tab bar may have changed
+            self.apply_dimensions(&dimensions, Some(cell_dims));
+        }
         if let Some(window) = self.window.as_ref() {
             window.invalidate();
         }
     }
 
-    fn update_scrollbar(&mut self) {
-        if !self.show_scroll_bar {
-            return;
+    /// Notes which tabs of the window have produced output that hasn't
+    /// been seen, so that the tab bar can indicate them, and scrolls
+    /// tabs to the bottom on output if the config asks for that.
+    /// The viewport of each tab is otherwise left where it was, so a
+    /// tab that was scrolled back is still scrolled back when it is
+    /// activated again.
+    fn process_tab_output(&mut self) {
+        let mut output = HashSet::new();
+        if let Some(subscriber) = self.tab_output.as_ref() {
+            while let Ok(MuxNotification::TabOutput(tab_id)) = subscriber.try_recv() {
+                output.insert(tab_id);
+            }
         }
 
-        let tab = match self.get_active_tab_or_overlay() {
-            Some(tab) => tab,
+        let mux = Mux::get().unwrap();
+        let (tab_ids, active) = match mux.get_window(self.mux_window_id) {
+            Some(window) => (
+                window.iter().map(|tab| tab.tab_id()).collect::<Vec<_>>(),
+                window.get_active().map(|tab| tab.tab_id()),
+            ),
             None => return,
         };
+        let config = configuration();
 
-        let render_dims = tab.renderer().get_dimensions();
-        if render_dims == self.last_scroll_info {
-            return;
+        let mut changed = false;
+        for tab_id in tab_ids {
+            let mut state = self.tab_state(tab_id);
+            if output.contains(&tab_id) {
+                // The output may have changed the title, such as when
+                // an application restores the title that it saved,
+                // without changing anything that needs to be repainted
+                changed |= Some(tab_id) == active;
+                if config.scroll_to_bottom_on_output && state.overlay().is_none() {
+                    state.viewport = None;
+                }
+                if Some(tab_id) != active || state.viewport.is_some() {
+                    changed |= !state.has_unseen_output;
+                    state.has_unseen_output = true;
+                }
+            }
+            if Some(tab_id) == active && state.viewport.is_none() && state.has_unseen_output {
+                state.has_unseen_output = false;
+                changed = true;
+            }
         }
 
-        self.last_scroll_info = render_dims;
-
-        if let Some(window) = self.window.as_ref() {
-            window.invalidate();
+        if changed {
+            self.update_title();
         }
     }
 
-    fn update_title(&mut self) {
+    /// Returns the tabs, other than `tab`, that the input typed into
+    /// `tab` is broadcast to.  Overlays don't broadcast their input,
+    /// and tabs that are showing an overlay don't receive it.
+    fn broadcast_targets(&mut self, tab: &Rc<dyn Tab>) -> Vec<Rc<dyn Tab>> {
         let mux = Mux::get().unwrap();
         let window = match mux.get_window(self.mux_window_id) {
             Some(window) => window,
-            _ => return,
+            None => return vec![],
         };
-        let config = configuration();
+        let tabs: Vec<Rc<dyn Tab>> = window.iter().cloned().collect();
+        drop(window);
 
-        let new_tab_bar = TabBarState::new(
-            self.terminal_size.cols as usize,
-            if self.last_mouse_coords.1 == 0 {
-                Some(self.last_mouse_coords.0)
-            } else {
-                None
-            },
-            &window,
-            config.colors.as_ref().and_then(|c| c.tab_bar.as_ref()),
-        );
-        if new_tab_bar != self.tab_bar {
-            self.tab_bar = new_tab_bar;
-            if let Some(window) = self.window.as_ref() {
-                window.invalidate();
-            }
-        }
+        // Forget the tabs that have since closed or moved elsewhere
+        self.broadcast
+            .tabs
+            .retain(|tab_id| tabs.iter().any(|t| t.tab_id() == *tab_id));
 
-        let num_tabs = window.len();
+        if !self.broadcast.contains(tab.tab_id())
+            || !tabs.iter().any(|t| t.tab_id() == tab.tab_id())
+        {
+            return vec![];
+        }
+        tabs.into_iter()
+            .filter(|t| {
+                t.tab_id() != tab.tab_id()
+                    && self.broadcast.contains(t.tab_id())
+                    && self.tab_state(t.tab_id()).overlay().is_none()
+            })
+            .collect()
+    }
 
-        if num_tabs == 0 {
-            return;
+    fn broadcast_key(
+        &mut self,
+        tab: &Rc<dyn Tab>,
+        key: ::termwiz::input::KeyCode,
+        mods: ::termwiz::input::Modifiers,
+    ) {
+        for target in self.broadcast_targets(tab) {
+            target.key_down(key, mods).ok();
         }
+    }
 
-        let tab_no = window.get_active_idx();
+    fn broadcast_text(&mut self, tab: &Rc<dyn Tab>, text: &str) {
+        for target in self.broadcast_targets(tab) {
+            target.send_text(text).ok();
+        }
+    }
 
-        let title = match window.get_active() {
+    /// Ends the chord that the leader key began.  If it `expired`
+    /// without another key being pressed, the leader is sent to the
+    /// tab when the config asks for unmatched leaders to be sent.
+    fn disarm_leader(&mut self, tab: &Rc<dyn Tab>, expired: bool) {
+        self.leader_expires = None;
+        if expired {
+            if let Some(leader) = configuration().leader.as_ref() {
+                if leader.send_leader_when_unmatched {
+                    tab.key_down(leader.key, leader.mods).ok();
+                }
+            }
+        }
+        self.update_title();
+    }
+
+    /// Collects the status text from the link preview or the active
+    /// tab, or failing those from the `status_command`.  Only the tab
+    /// bar, or the title, needs to be updated when it changes.
+    fn update_status_text(&mut self) {
+        let status = self
+            .link_preview
+            .clone()
+            .or_else(|| {
+                self.get_active_tab_no_overlay()
+                    .and_then(|tab| tab.get_status_text())
+            })
+            .or_else(super::status::command_status)
+            .filter(|status| !status.is_empty());
+        if status != self.status_text {
+            self.status_text = status;
+            self.update_title();
+        }
+    }
+
+    /// Deactivates the key tables whose timeout has expired, returning
+    /// true if there were any
+    fn expire_key_tables(&mut self) -> bool {
+        let now = Instant::now();
+        let len = self.key_table_stack.len();
+        self.key_table_stack
+            .retain(|table| table.expires.map(|expires| now < expires).unwrap_or(true));
+        if self.key_table_stack.len() == len {
+            return false;
+        }
+        self.update_title();
+        true
+    }
+
+    /// Looks up a key in the active key table.  Returns None if there
+    /// is no active table, or if the key isn't in it and the table
+    /// passes such keys through to be handled as usual.
+    fn key_table_key_event(
+        &mut self,
+        tab: &Rc<dyn Tab>,
+        candidates: &[(termwiz::input::KeyCode, termwiz::input::Modifiers)],
+        context: &dyn WindowOps,
+    ) -> Option<bool> {
+        self.expire_key_tables();
+        let active = self.key_table_stack.last_mut()?;
+        if candidates.iter().any(|(key, _)| key.is_modifier()) {
+            // Wait for the key that the modifier is pressed with
+            return Some(false);
+        }
+
+        let name = active.name.clone();
+        if active.one_shot {
+            self.key_table_stack.pop();
+        } else if let Some(timeout) = active.timeout {
+            active.expires = Some(Instant::now() + timeout);
+        }
+        self.update_title();
+
+        let (assignment, pass_through) = match self.input_map.key_table(&name) {
+            Some(table) => (
+                candidates
+                    .iter()
+                    .find_map(|&(key, mods)| table.lookup_key(key, mods)),
+                table.pass_through_unmatched,
+            ),
+            // The table went away when the config was reloaded
+            None => return None,
+        };
+
+        match assignment {
+            Some(assignment) => {
+                self.perform_key_assignment(tab, &assignment).ok();
+                context.invalidate();
+                Some(true)
+            }
+            None if pass_through => None,
+            None => Some(true),
+        }
+    }
+
+    fn update_scrollbar(&mut self) {
+        if !self.show_scroll_bar {
+            return;
+        }
+
+        let tab = match self.get_active_tab_or_overlay() {
+            Some(tab) => tab,
+            None => return,
+        };
+
+        let render_dims = tab.renderer().get_dimensions();
+        if render_dims == self.last_scroll_info {
+            return;
+        }
+
+        self.last_scroll_info = render_dims;
+
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
+    /// Clears the progress of the tabs whose application can no longer
+    /// clear it itself; returns true if any were cleared
+    fn update_tab_progress(&self) -> bool {
+        let mux = Mux::get().unwrap();
+        let window = match mux.get_window(self.mux_window_id) {
+            Some(window) => window,
+            None => return false,
+        };
+        let mut changed = false;
+        for tab in window.iter() {
+            changed |= tab.update_progress();
+        }
+        changed
+    }
+
+    fn update_title(&mut self) {
+        let mux = Mux::get().unwrap();
+        let window = match mux.get_window(self.mux_window_id) {
+            Some(window) => window,
+            _ => return,
+        };
+        let config = configuration();
+        let unseen_output: Vec<bool> = window
+            .iter()
+            .map(|tab| self.tab_state(tab.tab_id()).has_unseen_output)
+            .collect();
+
+        let new_tab_bar = TabBarState::new(
+            self.terminal_size.cols as usize,
+            if self.last_mouse_coords.1 == 0 {
+                Some(self.last_mouse_coords.0)
+            } else {
+                None
+            },
+            &window,
+            config.colors.as_ref().and_then(|c| c.tab_bar.as_ref()),
+            self.status_text.as_ref().map(String::as_str),
+            &unseen_output,
+        );
+        if new_tab_bar != self.tab_bar {
+            self.tab_bar = new_tab_bar;
+            if let Some(window) = self.window.as_ref() {
+                window.invalidate();
+            }
+        }
+
+        let num_tabs = window.len();
+
+        if num_tabs == 0 {
+            return;
+        }
+
+        let tab_no = window.get_active_idx();
+
+        let progress = match window.get_active().map(|tab| tab.get_progress()) {
+            None | Some(Progress::None) => TaskbarProgress::None,
+            Some(Progress::SetPercentage(pct)) => TaskbarProgress::Normal(pct),
+            Some(Progress::SetError(pct)) => TaskbarProgress::Error(pct),
+            Some(Progress::SetIndeterminate) => TaskbarProgress::Indeterminate,
+            Some(Progress::Paused(pct)) => TaskbarProgress::Paused(pct),
+        };
+
+        let title = match window.get_active() {
             Some(tab) => self
                 .tab_state(tab.tab_id())
-                .overlay
-                .as_ref()
+                .overlay()
                 .unwrap_or(tab)
                 .get_title(),
             None => return,
         };
+        let broadcasting = window
+            .get_active()
+            .map_or(false, |tab| self.broadcast.contains(tab.tab_id()));
+        let title = match config.window_title_format.as_ref() {
+            Some(format) => workspace::format_window_title(
+                format,
+                &title,
+                window.get_workspace(),
+                tab_no + 1,
+                num_tabs,
+            ),
+            None => match window.get_workspace() {
+                Some(name) => format!("{}: {}", name, title),
+                None => title,
+            },
+        };
 
         drop(window);
 
         if let Some(window) = self.window.as_ref() {
+            if progress != self.taskbar_progress {
+                self.taskbar_progress = progress;
+                window.set_progress(progress);
+            }
+
+            // Show the active key table, and that the leader is armed
+            // and waiting for the next key
+            let mut prefix = String::new();
+            if let Some(table) = self.key_table_stack.last() {
+                prefix.push_str(&format!("[{}] ", table.name));
+            }
+            if self.leader_expires.is_some() {
+                prefix.push_str("[LEADER] ");
+            }
+            if broadcasting {
+                prefix.push_str("[BROADCAST] ");
+            }
+            let mut title = prefix + &title;
+            if !self.show_tab_bar {
+                if let Some(status) = self.status_text.as_ref() {
+                    title.push_str(" - ");
+                    title.push_str(status);
+                }
+            }
+
             let show_tab_bar;
             if num_tabs == 1 {
                 window.set_title(&title);
                 show_tab_bar = config.enable_tab_bar && !config.hide_tab_bar_if_only_one_tab;
             } else {
-                window.set_title(&format!("[{}/{}] {}", tab_no + 1, num_tabs, title));
+                if config.window_title_format.is_some() {
+                    // The format decides whether to show the tab number
+                    window.set_title(&title);
+                } else {
+                    window.set_title(&format!("[{}/{}] {}", tab_no + 1, num_tabs, title));
+                }
                 show_tab_bar = config.enable_tab_bar;
             }
 
@@ -1187,6 +2135,113 @@ impl TermWindow {
         }
     }
 
+    /// Shows the text that the input method is composing over the cells
+    /// at the cursor, underlined to show that it is provisional
+    fn overlay_ime_preedit(
+        &self,
+        lines: &mut [Line],
+        stable_top: StableRowIndex,
+        cursor: &StableCursorPosition,
+    ) {
+        if let Some(preedit) = self.ime_preedit.as_ref() {
+            let idx = cursor.y - stable_top;
+            if idx < 0 {
+                return;
+            }
+            if let Some(line) = lines.get_mut(idx as usize) {
+                let mut attr = termwiz::cell::CellAttributes::default();
+                attr.set_underline(Underline::Single);
+                line.overlay_text_with_attribute(cursor.x, preedit, attr);
+            }
+        }
+    }
+
+    /// Completes the latency measurement of a key press whose echo
+    /// was just painted.  The HUD is then out of date, so another
+    /// frame is painted to show the new percentiles.
+    fn latency_frame_presented(&self, tab: &Rc<dyn Tab>) {
+        if latency::frame_presented(tab.tab_id()) && self.show_latency_hud {
+            if let Some(window) = self.window.as_ref() {
+                window.invalidate();
+            }
+        }
+    }
+
+    /// Draws the input latency of `tab` over the right end of the
+    /// first line, if the latency HUD is shown
+    fn overlay_latency_hud(&self, lines: &mut [Line], tab: &Rc<dyn Tab>) {
+        if !self.show_latency_hud {
+            return;
+        }
+        let text = match latency::stats(tab.tab_id()) {
+            Some(stats) => format!(" {} ", stats.brief()),
+            None => " type to measure latency ".to_string(),
+        };
+        if let Some(line) = lines.first_mut() {
+            // The text is ASCII, so its length is its width
+            let x = line.cells().len().saturating_sub(text.len());
+            let mut attr = termwiz::cell::CellAttributes::default();
+            attr.set_reverse(true);
+            line.overlay_text_with_attribute(x, &text, attr);
+        }
+    }
+
+    /// Returns the fonts of the resize badge, which are those of
+    /// the active tab scaled up
+    fn resize_badge_fonts(&self) -> Rc<FontConfiguration> {
+        let mut badge_fonts = self.resize_badge_fonts.borrow_mut();
+        match badge_fonts.as_ref() {
+            Some((fonts_id, fonts)) if *fonts_id == self.fonts.id() => Rc::clone(fonts),
+            _ => {
+                let fonts = Rc::new(self.fonts.scaled(resizebadge::FONT_SCALE));
+                badge_fonts.replace((self.fonts.id(), Rc::clone(&fonts)));
+                fonts
+            }
+        }
+    }
+
+    /// Lays out the badge that shows the size of the terminal of `tab`
+    /// while the window is being resized, rendering its glyphs into
+    /// `glyph_cache`.  Returns None when no badge is shown.
+    fn resize_badge<T: Texture2d>(
+        &self,
+        glyph_cache: &RefCell<GlyphCache<T>>,
+        tab: &Rc<dyn Tab>,
+        palette: &ColorPalette,
+    ) -> anyhow::Result<Option<resizebadge::Badge<T>>> {
+        let opacity = match self
+            .resize_badge
+            .as_ref()
+            .and_then(|badge| badge.opacity(Instant::now()))
+        {
+            Some(opacity) => opacity,
+            None => return Ok(None),
+        };
+        // The tabs may not have been resized yet, so this is the size
+        // that they are about to be given
+        let size = self.terminal_size_for_tab(tab, self.terminal_size);
+        let text = resizebadge::badge_text(&size);
+
+        let fonts = self.resize_badge_fonts();
+        let config = configuration();
+        let infos = self.shape_text_with_fonts(&fonts, &config.font, &text)?;
+        let glyphs =
+            glyph_cache
+                .borrow_mut()
+                .cached_glyphs_with_fonts(&fonts, &infos, &config.font)?;
+        Ok(Some(resizebadge::layout(
+            Size::new(
+                self.dimensions.pixel_width as isize,
+                self.dimensions.pixel_height as isize,
+            ),
+            &RenderMetrics::new(&fonts),
+            &glyphs,
+            palette.foreground,
+            palette.background,
+            opacity,
+        )))
+    }
+
     fn update_text_cursor(&mut self, tab: &Rc<dyn Tab>) {
         let term = tab.renderer();
         let cursor = term.get_cursor_position();
@@ -1294,16 +2349,24 @@ impl TermWindow {
         // overlay, but since the overlay runs in a different thread, accessing
         // the mux list is a bit awkward.  To get the ball rolling we capture
         // the list of tabs up front and live with a static list.
-        let tabs: Vec<(String, TabId)> = window
+        // The activity of each tab is shared with its reader thread, so
+        // the ages and busy markers are live even though the list isn't.
+        let tabs: Vec<TabEntry> = window
             .iter()
-            .map(|tab| (tab.get_title(), tab.tab_id()))
+            .map(|tab| TabEntry {
+                title: tab.get_title(),
+                tab_id: tab.tab_id(),
+                activity: mux.tab_activity(tab.tab_id()),
+                exited: tab.is_dead(),
+                exit_status: tab.exit_status(),
+            })
             .collect();
 
         let mux_window_id = self.mux_window_id;
-        let (overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
+        let (overlay_id, overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
             tab_navigator(tab_id, term, tabs, mux_window_id)
         });
-        self.assign_overlay(tab.tab_id(), overlay);
+        self.assign_overlay(tab.tab_id(), overlay_id, overlay);
         promise::spawn::spawn(future);
     }
 
@@ -1353,7 +2416,7 @@ impl TermWindow {
         let domain_id_of_current_tab = tab.domain_id();
         let size = self.terminal_size;
 
-        let (overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
+        let (overlay_id, overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
             launcher(
                 tab_id,
                 domain_id_of_current_tab,
@@ -1364,7 +2427,110 @@ impl TermWindow {
                 size,
             )
         });
-        self.assign_overlay(tab.tab_id(), overlay);
+        self.assign_overlay(tab.tab_id(), overlay_id, overlay);
+        promise::spawn::spawn(future);
+    }
+
+    fn show_command_palette(&mut self) {
+        let entries = palette_entries(&self.input_map);
+        self.show_palette("Command Palette", entries);
+    }
+
+    fn show_workspace_switcher(&mut self) {
+        let workspaces = workspace::list_workspaces();
+        if workspaces.is_empty() {
+            crate::toast_notification("wezterm", "No window has been given a workspace name yet");
+            return;
+        }
+        self.show_palette("Workspaces", workspace_entries(workspaces));
+    }
+
+    fn show_palette(&mut self, title: &'static str, entries: Vec<PaletteEntry>) {
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+
+        let cwd = tab
+            .get_current_working_dir()
+            .and_then(|url| url.to_file_path().ok());
+        let (overlay_id, overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
+            command_palette(tab_id, term, title, entries, cwd)
+        });
+        self.assign_overlay(tab.tab_id(), overlay_id, overlay);
+
+        // The selected action is performed only after the overlay has
+        // completed and scheduled its own cancellation, so that actions
+        // which start a new overlay (eg: Search) aren't cancelled along
+        // with the palette.
+        let window = self.window.as_ref().unwrap().clone();
+        let tab_id = tab.tab_id();
+        promise::spawn::spawn(async move {
+            if let Some(Ok(Some(assignment))) = future.await {
+                window.apply(move |myself, _| {
+                    if let Some(myself) = myself.downcast_mut::<Self>() {
+                        let mux = Mux::get().unwrap();
+                        if let Some(tab) = mux.get_tab(tab_id) {
+                            myself.perform_key_assignment(&tab, &assignment)?;
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        });
+    }
+
+    fn show_rename_workspace(&mut self) {
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+        let current = mux
+            .get_window(self.mux_window_id)
+            .and_then(|window| window.get_workspace().map(str::to_string));
+        let workspaces = workspace::list_workspaces()
+            .into_iter()
+            .map(|(name, _title)| name)
+            .collect();
+
+        let (overlay_id, overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
+            rename_workspace(tab_id, term, current, workspaces)
+        });
+        self.assign_overlay(tab.tab_id(), overlay_id, overlay);
+
+        let window = self.window.as_ref().unwrap().clone();
+        promise::spawn::spawn(async move {
+            if let Some(Ok(Some(name))) = future.await {
+                window.apply(move |myself, _| {
+                    if let Some(myself) = myself.downcast_mut::<Self>() {
+                        let mux = Mux::get().unwrap();
+                        if let Some(mut window) = mux.get_window_mut(myself.mux_window_id) {
+                            window.set_workspace(Some(name.clone()));
+                        }
+                        myself.update_title();
+                    }
+                    Ok(())
+                });
+            }
+        });
+    }
+
+    fn show_debug_overlay(&mut self) {
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+
+        // The cache belongs to this thread, so the overlay shows its
+        // usage as of the time that the overlay was opened
+        let glyph_cache = self.render_state.glyph_cache_usage();
+        let (overlay_id, overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
+            debug_overlay(tab_id, glyph_cache, term)
+        });
+        self.assign_overlay(tab.tab_id(), overlay_id, overlay);
         promise::spawn::spawn(future);
     }
 
@@ -1433,121 +2599,210 @@ impl TermWindow {
         let spawn = spawn.clone();
 
         promise::spawn::spawn(async move {
-            let mux = Mux::get().unwrap();
-            let activity = Activity::new();
+            if let Err(err) =
+                Self::spawn_command_internal(spawn, new_window, size, mux_window_id, clipboard)
+                    .await
+            {
+                log::error!("Failed to spawn: {:#}", err);
+            }
+        });
+    }
 
-            let mux_window_id = if new_window {
-                mux.new_empty_window()
-            } else {
-                mux_window_id
-            };
+    async fn spawn_command_internal(
+        spawn: SpawnCommand,
+        new_window: bool,
+        size: PtySize,
+        mux_window_id: MuxWindowId,
+        clipboard: ClipboardHelper,
+    ) -> anyhow::Result<Rc<dyn Tab>> {
+        let mux = Mux::get().unwrap();
+        let activity = Activity::new();
+
+        let mux_window_id = if new_window {
+            mux.new_empty_window()
+        } else {
+            mux_window_id
+        };
 
-            let (domain, cwd) = match spawn.domain {
-                SpawnTabDomain::DefaultDomain => {
+        let (domain, cwd) = match spawn.domain {
+            SpawnTabDomain::DefaultDomain => {
+                let cwd = mux
+                    .get_active_tab_for_window(mux_window_id)
+                    .and_then(|tab| tab.get_current_working_dir());
+                (mux.default_domain().clone(), cwd)
+            }
+            SpawnTabDomain::CurrentTabDomain => {
+                if new_window {
+                    // CurrentTabDomain is the default value for the spawn domain.
+                    // It doesn't make sense to use it when spawning a new window,
+                    // so we treat it as DefaultDomain instead.
                     let cwd = mux
                         .get_active_tab_for_window(mux_window_id)
                         .and_then(|tab| tab.get_current_working_dir());
                     (mux.default_domain().clone(), cwd)
+                } else {
+                    let tab = match mux.get_active_tab_for_window(mux_window_id) {
+                        Some(tab) => tab,
+                        None => bail!("window has no tabs?"),
+                    };
+                    (
+                        mux.get_domain(tab.domain_id())
+                            .ok_or_else(|| anyhow!("current tab has unresolvable domain id!?"))?,
+                        tab.get_current_working_dir(),
+                    )
                 }
-                SpawnTabDomain::CurrentTabDomain => {
-                    if new_window {
-                        // CurrentTabDomain is the default value for the spawn domain.
-                        // It doesn't make sense to use it when spawning a new window,
-                        // so we treat it as DefaultDomain instead.
-                        let cwd = mux
-                            .get_active_tab_for_window(mux_window_id)
-                            .and_then(|tab| tab.get_current_working_dir());
-                        (mux.default_domain().clone(), cwd)
+            }
+            SpawnTabDomain::Domain(id) => (
+                mux.get_domain(id)
+                    .ok_or_else(|| anyhow!("spawn_tab called with unresolvable domain id!?"))?,
+                None,
+            ),
+            SpawnTabDomain::DomainName(name) => (
+                mux.get_domain_by_name(&name).ok_or_else(|| {
+                    anyhow!("spawn_tab called with unresolvable domain name {}", name)
+                })?,
+                None,
+            ),
+        };
+
+        if domain.state() == DomainState::Detached {
+            bail!("Cannot spawn a tab into a Detached domain");
+        }
+
+        let cwd = if let Some(cwd) = spawn.cwd.as_ref() {
+            Some(cwd.to_str().map(|s| s.to_owned()).ok_or_else(|| {
+                anyhow!(
+                    "Domain::spawn requires that the cwd be unicode in {:?}",
+                    cwd
+                )
+            })?)
+        } else {
+            match cwd {
+                Some(url) if url.scheme() == "file" => {
+                    let path = url.path().to_string();
+                    // On Windows the file URI can produce a path like:
+                    // `/C:\Users` which is valid in a file URI, but the leading slash
+                    // is not liked by the windows file APIs, so we strip it off here.
+                    let bytes = path.as_bytes();
+                    if bytes.len() > 2 && bytes[0] == b'/' && bytes[2] == b':' {
+                        Some(path[1..].to_owned())
                     } else {
-                        let tab = match mux.get_active_tab_for_window(mux_window_id) {
-                            Some(tab) => tab,
-                            None => bail!("window has no tabs?"),
-                        };
-                        (
-                            mux.get_domain(tab.domain_id()).ok_or_else(|| {
-                                anyhow!("current tab has unresolvable domain id!?")
-                            })?,
-                            tab.get_current_working_dir(),
-                        )
+                        Some(path)
                     }
                 }
-                SpawnTabDomain::Domain(id) => (
-                    mux.get_domain(id)
-                        .ok_or_else(|| anyhow!("spawn_tab called with unresolvable domain id!?"))?,
-                    None,
-                ),
-                SpawnTabDomain::DomainName(name) => (
-                    mux.get_domain_by_name(&name).ok_or_else(|| {
-                        anyhow!("spawn_tab called with unresolvable domain name {}", name)
-                    })?,
-                    None,
-                ),
-            };
+                Some(_) | None => None,
+            }
+        };
 
-            if domain.state() == DomainState::Detached {
-                bail!("Cannot spawn a tab into a Detached domain");
+        let cmd_builder = if let Some(args) = spawn.args {
+            let mut builder = CommandBuilder::from_argv(args.iter().map(Into::into).collect());
+            for (k, v) in spawn.set_environment_variables.iter() {
+                builder.env(k, v);
+            }
+            if let Some(cwd) = spawn.cwd {
+                builder.cwd(cwd);
             }
+            Some(builder)
+        } else {
+            None
+        };
 
-            let cwd = if let Some(cwd) = spawn.cwd.as_ref() {
-                Some(cwd.to_str().map(|s| s.to_owned()).ok_or_else(|| {
-                    anyhow!(
-                        "Domain::spawn requires that the cwd be unicode in {:?}",
-                        cwd
-                    )
-                })?)
-            } else {
-                match cwd {
-                    Some(url) if url.scheme() == "file" => {
-                        let path = url.path().to_string();
-                        // On Windows the file URI can produce a path like:
-                        // `/C:\Users` which is valid in a file URI, but the leading slash
-                        // is not liked by the windows file APIs, so we strip it off here.
-                        let bytes = path.as_bytes();
-                        if bytes.len() > 2 && bytes[0] == b'/' && bytes[2] == b':' {
-                            Some(path[1..].to_owned())
-                        } else {
-                            Some(path)
-                        }
-                    }
-                    Some(_) | None => None,
-                }
-            };
+        let tab = domain.spawn(size, cmd_builder, cwd, mux_window_id).await?;
+        let tab_id = tab.tab_id();
 
-            let cmd_builder = if let Some(args) = spawn.args {
-                let mut builder = CommandBuilder::from_argv(args.iter().map(Into::into).collect());
-                for (k, v) in spawn.set_environment_variables.iter() {
-                    builder.env(k, v);
-                }
-                if let Some(cwd) = spawn.cwd {
-                    builder.cwd(cwd);
-                }
-                Some(builder)
-            } else {
-                None
-            };
+        if spawn.colors != TabColorOverrides::default() {
+            let mut colors = tab.color_overrides();
+            colors.merge_from(&spawn.colors);
+            tab.set_color_overrides(colors);
+        }
 
-            let tab = domain.spawn(size, cmd_builder, cwd, mux_window_id).await?;
-            let tab_id = tab.tab_id();
+        if spawn.font != TabFontOverride::default() {
+            let mut font = tab.font_override();
+            font.merge_from(&spawn.font);
+            tab.set_font_override(font);
+        }
 
-            if new_window {
-                let front_end = front_end().expect("to be called on gui thread");
-                let fonts = Rc::new(FontConfiguration::new());
-                front_end.spawn_new_window(&fonts, &tab, mux_window_id)?;
-            } else {
-                let clipboard: Arc<dyn wezterm_term::Clipboard> = Arc::new(clipboard);
-                tab.set_clipboard(&clipboard);
-                let mut window = mux
-                    .get_window_mut(mux_window_id)
-                    .ok_or_else(|| anyhow!("no such window!?"))?;
-                if let Some(idx) = window.idx_by_id(tab_id) {
-                    window.set_active(idx);
-                }
+        if let Some(encoding) = spawn.encoding {
+            tab.set_encoding(encoding.into());
+        }
+
+        if new_window {
+            let front_end = front_end().expect("to be called on gui thread");
+            let fonts = Rc::new(FontConfiguration::new());
+            front_end.spawn_new_window(&fonts, &tab, mux_window_id, &spawn.window_overrides)?;
+        } else {
+            let clipboard: Arc<dyn wezterm_term::Clipboard> = Arc::new(clipboard);
+            tab.set_clipboard(&clipboard);
+            let mut window = mux
+                .get_window_mut(mux_window_id)
+                .ok_or_else(|| anyhow!("no such window!?"))?;
+            if let Some(idx) = window.idx_by_id(tab_id) {
+                window.set_active(idx);
             }
+        }
 
-            drop(activity);
+        drop(activity);
 
-            Ok(())
+        Ok(tab)
+    }
+
+    /// Writes the text of the active tab to a temporary file and runs
+    /// a command on it in a new tab.  The file is deleted when that
+    /// tab is closed.
+    fn emit_scrollback_to_command(&mut self, command: &ScrollbackCommand) -> anyhow::Result<()> {
+        use std::io::Write;
+
+        let tab = match self.get_active_tab_no_overlay() {
+            Some(tab) => tab,
+            None => return Ok(()),
+        };
+        let text = tab.export_text(&command.export_options());
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let path = std::env::temp_dir().join(format!(
+            "wezterm-scrollback-{}-{}-{}.txt",
+            std::process::id(),
+            tab.tab_id(),
+            timestamp
+        ));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&path)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .map_err(|err| anyhow!("writing {}: {}", path.display(), err))?;
+
+        let mut args = command.command();
+        args.push(path.to_string_lossy().into_owned());
+        let spawn = SpawnCommand {
+            args: Some(args),
+            ..Default::default()
+        };
+        let size = self.terminal_size;
+        let mux_window_id = self.mux_window_id;
+        let clipboard = ClipboardHelper {
+            window: self.window.as_ref().unwrap().clone(),
+            clipboard_contents: Arc::clone(&self.clipboard_contents),
+        };
+        promise::spawn::spawn(async move {
+            let mux = Mux::get().unwrap();
+            match Self::spawn_command_internal(spawn, false, size, mux_window_id, clipboard).await {
+                Ok(tab) => mux.remove_file_with_tab(tab.tab_id(), path),
+                Err(err) => {
+                    log::error!("Failed to spawn: {:#}", err);
+                    std::fs::remove_file(&path).ok();
+                }
+            }
         });
+        Ok(())
     }
 
     fn spawn_tab(&mut self, domain: &SpawnTabDomain) {
@@ -1561,31 +2816,29 @@ impl TermWindow {
     }
 
     fn selection_text(&self, tab: &Rc<dyn Tab>) -> String {
-        let mut s = String::new();
-        if let Some(sel) = self
-            .selection(tab.tab_id())
-            .range
-            .as_ref()
-            .map(|r| r.normalize())
-        {
-            let mut last_was_wrapped = false;
-            let mut renderer = tab.renderer();
-            let (first_row, lines) = renderer.get_lines(sel.rows());
-            for (idx, line) in lines.iter().enumerate() {
-                let cols = sel.cols_for_row(first_row + idx as StableRowIndex);
-                let last_col_idx = cols.end.min(line.cells().len()).saturating_sub(1);
-                if !s.is_empty() && !last_was_wrapped {
-                    s.push('\n');
-                }
-                s.push_str(line.columns_as_str(cols).trim_end());
-
-                let last_cell = &line.cells()[last_col_idx];
-                // TODO: should really test for any unicode whitespace
-                last_was_wrapped = last_cell.attrs().wrapped() && last_cell.str() != " ";
+        let config = configuration();
+        let selection = *self.selection(tab.tab_id());
+        match selection.range.as_ref().map(|r| r.normalize()) {
+            Some(sel) => {
+                let mut renderer = tab.renderer();
+                let (first_row, lines) = renderer.get_lines(sel.rows());
+                selection_text(
+                    lines.iter().enumerate().map(|(idx, line)| {
+                        (
+                            line,
+                            selection.cols_for_row(first_row + idx as StableRowIndex),
+                        )
+                    }),
+                    selection.rectangular,
+                    &SelectionTextOptions {
+                        trim_trailing_whitespace: config.selection_trim_trailing_whitespace,
+                        preserve_tabs: config.selection_preserve_tabs,
+                        escapes: false,
+                    },
+                )
             }
+            None => String::new(),
         }
-
-        s
     }
 
     fn paste_from_clipboard(&mut self, tab: &Rc<dyn Tab>, clipboard: Clipboard) {
@@ -1597,12 +2850,16 @@ impl TermWindow {
                 window.apply(move |term_window, _window| {
                     let clip = clip.clone();
                     if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
-                        if let Some(tab) =
-                            term_window.tab_state(tab_id).overlay.clone().or_else(|| {
-                                let mux = Mux::get().unwrap();
-                                mux.get_tab(tab_id)
-                            })
-                        {
+                        let overlay = term_window.tab_state(tab_id).overlay().cloned();
+                        if let Some(tab) = overlay.or_else(|| {
+                            let mux = Mux::get().unwrap();
+                            mux.get_tab(tab_id)
+                        }) {
+                            if configuration().broadcast_paste {
+                                for target in term_window.broadcast_targets(&tab) {
+                                    target.trickle_paste(clip.clone()).ok();
+                                }
+                            }
                             tab.trickle_paste(clip).ok();
                         }
                     }
@@ -1612,6 +2869,102 @@ impl TermWindow {
         });
     }
 
+    /// Opens the config file in an editor in a new tab, creating the
+    /// file first if there isn't one yet.  The file is watched, so
+    /// saving it from the editor applies the changes.
+    fn open_config_file(&mut self) {
+        let path = match crate::config::config_file_for_editing() {
+            Ok(path) => path,
+            Err(err) => {
+                let message = format!("Could not open the config file: {:#}", err);
+                log::error!("{}", message);
+                crate::toast_notification("wezterm", &message);
+                return;
+            }
+        };
+        let args = configuration().config_file_editor_command(&path);
+        self.spawn_command(
+            &SpawnCommand {
+                label: Some("Edit the configuration".to_string()),
+                args: Some(args),
+                cwd: path.parent().map(std::path::Path::to_path_buf),
+                // The file is local, so the editor must be too
+                domain: SpawnTabDomain::DomainName("local".to_string()),
+                ..Default::default()
+            },
+            false,
+        );
+    }
+
+    /// Records the output of `tab` to a new cast file in the home
+    /// directory
+    fn start_recording(&self, tab: &Rc<dyn Tab>) -> anyhow::Result<()> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = crate::config::HOME_DIR.join(format!(
+            "wezterm-recording-{}-{}.cast",
+            timestamp,
+            tab.tab_id()
+        ));
+        let dims = tab.renderer().get_dimensions();
+        recording::start_recording(
+            tab.tab_id(),
+            &path,
+            dims.cols as u16,
+            dims.viewport_rows as u16,
+        )?;
+        crate::toast_notification(
+            "wezterm",
+            &format!("Recording the tab to {}", path.display()),
+        );
+        Ok(())
+    }
+
+    /// Returns a timestamped path in the quick save directory at
+    /// which to save the text of `tab`
+    fn quick_save_path(tab: &Rc<dyn Tab>) -> std::path::PathBuf {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        configuration()
+            .quick_save_directory
+            .clone()
+            .unwrap_or_else(|| crate::config::HOME_DIR.clone())
+            .join(format!("wezterm-{}-{}.txt", timestamp, tab.tab_id()))
+    }
+
+    fn show_export_text(&mut self, options: &ExportTextOptions) {
+        let tab = match self.get_active_tab_no_overlay() {
+            Some(tab) => tab,
+            None => return,
+        };
+        let text = tab.export_text(options);
+        let path = Self::quick_save_path(&tab);
+
+        let (overlay_id, overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
+            export_text(tab_id, term, text, path)
+        });
+        self.assign_overlay(tab.tab_id(), overlay_id, overlay);
+        promise::spawn::spawn(future);
+    }
+
+    /// Copies the text of a completed selection to the primary
+    /// selection and, if so configured or if there is no primary
+    /// selection, to the clipboard
+    fn complete_selection(&self, text: String) {
+        let window = self.window.as_ref().unwrap();
+        if configuration().copy_selection_to_clipboard
+            || !Connection::get().unwrap().has_primary_selection()
+        {
+            window.set_clipboard(Clipboard::Clipboard, text.clone());
+        }
+        window.set_clipboard(Clipboard::PrimarySelection, text);
+        window.invalidate();
+    }
+
     fn perform_key_assignment(
         &mut self,
         tab: &Rc<dyn Tab>,
@@ -1638,7 +2991,7 @@ impl TermWindow {
                 self.window
                     .as_ref()
                     .unwrap()
-                    .set_clipboard(self.selection_text(tab));
+                    .set_clipboard(Clipboard::Clipboard, self.selection_text(tab));
             }
             Paste => {
                 self.paste_from_clipboard(tab, Clipboard::default());
@@ -1655,7 +3008,10 @@ impl TermWindow {
             ActivateTab(n) => {
                 self.activate_tab(*n)?;
             }
-            SendString(s) => tab.writer().write_all(s.as_bytes())?,
+            SendString(s) => {
+                tab.send_text(s)?;
+                self.broadcast_text(tab, s);
+            }
             Hide => {
                 if let Some(w) = self.window.as_ref() {
                     w.hide();
@@ -1674,6 +3030,133 @@ impl TermWindow {
             ScrollByPage(n) => self.scroll_by_page(*n)?,
             ShowTabNavigator => self.show_tab_navigator(),
             ShowLauncher => self.show_launcher(),
+            ShowCommandPalette => self.show_command_palette(),
+            ShowDebugOverlay => self.show_debug_overlay(),
+            StartRecording => {
+                if let Some(tab) = self.get_active_tab_no_overlay() {
+                    self.start_recording(&tab)?;
+                }
+            }
+            StopRecording => {
+                if let Some(tab) = self.get_active_tab_no_overlay() {
+                    if recording::stop_recording(tab.tab_id()) {
+                        crate::toast_notification("wezterm", "Stopped recording the tab");
+                    }
+                }
+            }
+            ExportText(options) => self.show_export_text(options),
+            EmitScrollbackToCommand(command) => self.emit_scrollback_to_command(command)?,
+            ActivateKeyTable(activation) => {
+                if self.input_map.key_table(&activation.name).is_none() {
+                    log::error!("there is no key table named {}", activation.name);
+                    return Ok(());
+                }
+                let timeout = activation.timeout_milliseconds.map(Duration::from_millis);
+                self.key_table_stack.push(ActiveKeyTable {
+                    name: activation.name.clone(),
+                    one_shot: activation.one_shot,
+                    timeout,
+                    expires: timeout.map(|timeout| Instant::now() + timeout),
+                });
+                self.update_title();
+            }
+            PopKeyTable => {
+                self.key_table_stack.pop();
+                self.update_title();
+            }
+            ToggleBroadcastToWindow => {
+                self.broadcast.all_tabs = !self.broadcast.all_tabs;
+                self.update_title();
+            }
+            ToggleBroadcastToTab => {
+                if let Some(tab) = self.get_active_tab_no_overlay() {
+                    let tab_id = tab.tab_id();
+                    if !self.broadcast.tabs.remove(&tab_id) {
+                        self.broadcast.tabs.insert(tab_id);
+                    }
+                    self.update_title();
+                }
+            }
+            ClearBroadcast => {
+                self.broadcast = BroadcastTargets::default();
+                self.update_title();
+            }
+            CycleAppearance => {
+                let config = configuration();
+                let before = config.applied_color_scheme().cloned();
+                let appearance = crate::appearance::cycle_override();
+                // There's nothing to announce when no scheme is
+                // configured for the new appearance, or it wasn't found
+                if config.applied_color_scheme() != before.as_ref() {
+                    let message = match appearance {
+                        Some(Appearance::Dark) => "Using the dark color scheme",
+                        Some(Appearance::Light) => "Using the light color scheme",
+                        None => "Following the system appearance",
+                    };
+                    crate::toast_notification("wezterm", message);
+                }
+                self.check_for_config_reload();
+            }
+            RenameWorkspace => self.show_rename_workspace(),
+            ShowWorkspaceSwitcher => self.show_workspace_switcher(),
+            SwitchToWorkspace(name) => match workspace::find_workspace(name) {
+                Some(mux_window_id) => {
+                    if !workspace::focus_window(mux_window_id) {
+                        log::error!("workspace {} has no window to focus", name);
+                    }
+                }
+                None => crate::toast_notification(
+                    "wezterm",
+                    &format!("There is no workspace named {}", name),
+                ),
+            },
+            ToggleTextureAtlas => {
+                // The view is rarely used, so its program is only
+                // compiled once it is first shown
+                let compiled = match &self.render_state {
+                    RenderState::GL(gl_state) if !self.show_texture_atlas => {
+                        gl_state.compile_atlas_view_program()
+                    }
+                    _ => Ok(()),
+                };
+                match compiled {
+                    Ok(()) => {
+                        self.show_texture_atlas = !self.show_texture_atlas;
+                        if let Some(window) = self.window.as_ref() {
+                            window.invalidate();
+                        }
+                    }
+                    Err(err) => log::error!("unable to show the texture atlas: {:#}", err),
+                }
+            }
+            ToggleLatencyHud => {
+                self.show_latency_hud = !self.show_latency_hud;
+                if self.show_latency_hud {
+                    latency::start_measuring();
+                } else {
+                    latency::stop_measuring();
+                }
+                if let Some(window) = self.window.as_ref() {
+                    window.invalidate();
+                }
+            }
+            OpenConfigFile => self.open_config_file(),
+            ToggleLatin1Encoding => {
+                if let Some(tab) = self.get_active_tab_no_overlay() {
+                    let encoding = match tab.encoding() {
+                        Encoding::Latin1 => Encoding::Utf8,
+                        Encoding::Utf8 | Encoding::Cp437 => Encoding::Latin1,
+                    };
+                    tab.set_encoding(encoding);
+                }
+            }
+            QuickSave(options) => {
+                if let Some(tab) = self.get_active_tab_no_overlay() {
+                    let path = Self::quick_save_path(&tab);
+                    let message = save_text(&path, &tab.export_text(options));
+                    crate::toast_notification("wezterm", &message);
+                }
+            }
             HideApplication => {
                 let con = Connection::get().expect("call on gui thread");
                 con.hide_application();
@@ -1689,11 +3172,14 @@ impl TermWindow {
                 // Ensure that we spawn the `open` call outside of the context
                 // of our window loop; on Windows it can cause a panic due to
                 // triggering our WndProc recursively.
-                if let Some(link) = self.current_highlight.as_ref().cloned() {
+                let uri = self.current_highlight.as_ref().and_then(|link| {
+                    filelink::resolve(link.uri(), tab.get_current_working_dir().as_ref())
+                });
+                if let Some(uri) = uri {
                     promise::spawn::spawn(async move {
-                        log::error!("clicking {}", link.uri());
-                        if let Err(err) = open::that(link.uri()) {
-                            log::error!("failed to open {}: {:?}", link.uri(), err);
+                        log::error!("clicking {}", uri);
+                        if let Err(err) = open_uri(&uri) {
+                            log::error!("failed to open {}: {:?}", uri, err);
                         }
                     });
                 }
@@ -1701,9 +3187,7 @@ impl TermWindow {
             CompleteSelectionOrOpenLinkAtMouseCursor => {
                 let text = self.selection_text(&tab);
                 if !text.is_empty() {
-                    let window = self.window.as_ref().unwrap();
-                    window.set_clipboard(text);
-                    window.invalidate();
+                    self.complete_selection(text);
                 } else {
                     return self.perform_key_assignment(tab, &KeyAssignment::OpenLinkAtMouseCursor);
                 }
@@ -1711,9 +3195,7 @@ impl TermWindow {
             CompleteSelection => {
                 let text = self.selection_text(&tab);
                 if !text.is_empty() {
-                    let window = self.window.as_ref().unwrap();
-                    window.set_clipboard(text);
-                    window.invalidate();
+                    self.complete_selection(text);
                 }
             }
             ClearScrollback => {
@@ -1721,16 +3203,21 @@ impl TermWindow {
                 let window = self.window.as_ref().unwrap();
                 window.invalidate();
             }
+            ResetTerminal => {
+                tab.reset_terminal();
+                let window = self.window.as_ref().unwrap();
+                window.invalidate();
+            }
             Search(pattern) => {
                 if let Some(tab) = self.get_active_tab_no_overlay() {
-                    let search = SearchOverlay::with_tab(self, &tab, pattern.clone());
-                    self.assign_overlay(tab.tab_id(), search);
+                    let (overlay_id, search) = SearchOverlay::with_tab(self, &tab, pattern.clone());
+                    self.assign_overlay(tab.tab_id(), overlay_id, search);
                 }
             }
             ActivateCopyMode => {
                 if let Some(tab) = self.get_active_tab_no_overlay() {
-                    let copy = CopyOverlay::with_tab(self, &tab);
-                    self.assign_overlay(tab.tab_id(), copy);
+                    let (overlay_id, copy) = CopyOverlay::with_tab(self, &tab);
+                    self.assign_overlay(tab.tab_id(), overlay_id, copy);
                 }
             }
         };
@@ -1738,28 +3225,21 @@ impl TermWindow {
     }
 
     pub fn spawn_new_window(&mut self) {
-        async fn new_window() -> anyhow::Result<()> {
-            let mux = Mux::get().unwrap();
-            let config = crate::config::configuration();
-            let fonts = Rc::new(FontConfiguration::new());
-            let window_id = mux.new_empty_window();
-            let tab = mux
-                .default_domain()
-                .spawn(config.initial_size(), None, None, window_id)
-                .await?;
-            let front_end = front_end().expect("to be called on gui thread");
-            front_end.spawn_new_window(&fonts, &tab, window_id)?;
-            Ok::<(), anyhow::Error>(())
-        }
-        promise::spawn::spawn(async move {
-            new_window().await.ok();
-        });
+        spawn_window_in_default_domain();
     }
 
     fn apply_scale_change(&mut self, dimensions: &Dimensions, font_scale: f64) {
-        self.fonts
-            .change_scaling(font_scale, dimensions.dpi as f64 / 96.);
+        let dpi_scale = dimensions.dpi as f64 / 96.;
+        self.window_fonts.change_scaling(font_scale, dpi_scale);
+        for state in self.tab_state.borrow().values() {
+            if let Some(tab_fonts) = state.fonts.as_ref() {
+                tab_fonts.fonts.change_scaling(font_scale, dpi_scale);
+            }
+        }
         self.render_metrics = RenderMetrics::new(&self.fonts);
+        self.resize_badge_fonts.borrow_mut().take();
+        self.shape_cache.borrow_mut().clear();
+        self.line_shape_cache.borrow_mut().clear();
 
         self.recreate_texture_atlas(None)
             .expect("failed to recreate atlas");
@@ -1786,8 +3266,8 @@ impl TermWindow {
             // Scaling preserves existing terminal dimensions, yielding a new
             // overall set of window dimensions
             let size = PtySize {
-                rows: cell_dims.rows as u16,
-                cols: cell_dims.cols as u16,
+                rows: cell_dims.rows.max(1) as u16,
+                cols: cell_dims.cols.max(1) as u16,
                 pixel_height: cell_dims.rows as u16 * self.render_metrics.cell_size.height as u16,
                 pixel_width: cell_dims.cols as u16 * self.render_metrics.cell_size.width as u16,
             };
@@ -1799,7 +3279,8 @@ impl TermWindow {
                 + (config.window_padding.top + config.window_padding.bottom);
 
             let pixel_width = (cols * self.render_metrics.cell_size.width as u16)
-                + (config.window_padding.left + self.effective_right_padding(&config));
+                + (config.window_padding.left
+                    + effective_right_padding(&config, &self.render_metrics));
 
             let dims = Dimensions {
                 pixel_width: pixel_width as usize,
@@ -1810,22 +3291,7 @@ impl TermWindow {
             (size, dims)
         } else {
             // Resize of the window dimensions may result in changed terminal dimensions
-            let avail_width = dimensions.pixel_width
-                - (config.window_padding.left + self.effective_right_padding(&config)) as usize;
-            let avail_height = dimensions.pixel_height
-                - (config.window_padding.top + config.window_padding.bottom) as usize;
-
-            let rows = (avail_height / self.render_metrics.cell_size.height as usize)
-                .saturating_sub(if self.show_tab_bar { 1 } else { 0 });
-            let cols = avail_width / self.render_metrics.cell_size.width as usize;
-
-            let size = PtySize {
-                rows: rows as u16,
-                cols: cols as u16,
-                pixel_height: avail_height as u16,
-                pixel_width: avail_width as u16,
-            };
-
+            let size = self.terminal_size_for(dimensions, &self.render_metrics);
             (size, *dimensions)
         };
 
@@ -1837,14 +3303,11 @@ impl TermWindow {
             )
             .expect("failed to advise of resize");
 
+        if size.rows != self.terminal_size.rows || size.cols != self.terminal_size.cols {
+            self.pending_tab_resize.replace(size);
+        }
         self.terminal_size = size;
-
-        let mux = Mux::get().unwrap();
-        if let Some(window) = mux.get_window(self.mux_window_id) {
-            for tab in window.iter() {
-                tab.resize(size).ok();
-            }
-        };
+        self.apply_pending_tab_resize();
         self.update_title();
 
         // Queue up a speculative resize in order to preserve the number of rows+cols
@@ -1856,6 +3319,161 @@ impl TermWindow {
         }
     }
 
+    /// Returns the size of the terminal that fits in a window of
+    /// `dimensions` using cells of the size given by `render_metrics`
+    fn terminal_size_for(
+        &self,
+        dimensions: &Dimensions,
+        render_metrics: &RenderMetrics,
+    ) -> PtySize {
+        let config = configuration();
+        let padding_width = (config.window_padding.left
+            + effective_right_padding(&config, render_metrics))
+            as usize;
+        let padding_height = (config.window_padding.top + config.window_padding.bottom) as usize;
+        let avail_width = dimensions.pixel_width.saturating_sub(padding_width);
+        let avail_height = dimensions.pixel_height.saturating_sub(padding_height);
+
+        // Never allow the terminal to collapse below a single cell;
+        // a zero sized terminal is not meaningful to the model or
+        // to the programs running in the pty.
+        let rows = (avail_height / render_metrics.cell_size.height as usize)
+            .saturating_sub(if self.show_tab_bar { 1 } else { 0 })
+            .max(1);
+        let cols = (avail_width / render_metrics.cell_size.width as usize).max(1);
+
+        // The tab bar is not part of the text area, so exclude it
+        // from the pixel size reported to the application
+        let tab_bar_height = if self.show_tab_bar {
+            render_metrics.cell_size.height as usize
+        } else {
+            0
+        };
+
+        PtySize {
+            rows: rows as u16,
+            cols: cols as u16,
+            pixel_height: avail_height.saturating_sub(tab_bar_height) as u16,
+            pixel_width: avail_width as u16,
+        }
+    }
+
+    /// Returns the fonts with which `tab` is displayed
+    fn tab_fonts(&self, tab: &Rc<dyn Tab>) -> Rc<FontConfiguration> {
+        self.tab_state(tab.tab_id())
+            .fonts
+            .as_ref()
+            .map_or_else(|| Rc::clone(&self.window_fonts), |f| Rc::clone(&f.fonts))
+    }
+
+    /// Returns the size of the terminal of `tab`, given `size`, which
+    /// is the size for the fonts of the active tab.  A tab whose fonts
+    /// differ has cells of a different size, and so a different number
+    /// of rows and columns in the same window.
+    fn terminal_size_for_tab(&self, tab: &Rc<dyn Tab>, size: PtySize) -> PtySize {
+        let fonts = self.tab_fonts(tab);
+        if Rc::ptr_eq(&fonts, &self.fonts) {
+            size
+        } else {
+            self.terminal_size_for(&self.dimensions, &RenderMetrics::new(&fonts))
+        }
+    }
+
+    /// Creates or drops the fonts of the tabs whose font override has
+    /// changed, resizing them to fit their new cells, and switches to
+    /// the fonts of the active tab
+    fn update_tab_fonts(&mut self) {
+        let mux = Mux::get().unwrap();
+        let (tabs, active) = match mux.get_window(self.mux_window_id) {
+            Some(window) => (
+                window.iter().cloned().collect::<Vec<_>>(),
+                window.get_active().cloned(),
+            ),
+            None => return,
+        };
+
+        let mut changed = false;
+        for tab in &tabs {
+            let font_override = tab.font_override();
+            let mut state = self.tab_state(tab.tab_id());
+            if font_override == TabFontOverride::default() {
+                changed |= state.fonts.take().is_some();
+            } else if state
+                .fonts
+                .as_ref()
+                .map_or(true, |f| f.font_override != font_override)
+            {
+                let fonts = Rc::new(self.window_fonts.with_override(&font_override));
+                state.fonts.replace(TabFonts {
+                    font_override,
+                    fonts,
+                });
+                changed = true;
+            }
+        }
+
+        let fonts = match active.as_ref() {
+            Some(tab) => self.tab_fonts(tab),
+            None => Rc::clone(&self.window_fonts),
+        };
+        if !Rc::ptr_eq(&fonts, &self.fonts) {
+            self.fonts = fonts;
+            self.render_metrics = RenderMetrics::new(&self.fonts);
+            // The glyphs and shapes of each of the fonts are cached
+            // side by side, keyed by the id of the fonts
+            if let Err(err) = self
+                .render_state
+                .switch_fonts(&self.fonts, &self.render_metrics)
+            {
+                log::debug!("{:#}; recreating the texture atlas", err);
+                self.recreate_texture_atlas(None)
+                    .expect("failed to recreate atlas");
+            }
+            // Compute the size of the terminal using the new cells
+            let dimensions = self.dimensions;
+            self.apply_dimensions(&dimensions, None);
+            if let Some(window) = self.window.as_ref() {
+                window.invalidate();
+            }
+        }
+
+        if changed {
+            self.pending_tab_resize.replace(self.terminal_size);
+            self.apply_pending_tab_resize();
+        }
+    }
+
+    /// Applies any pending terminal size to the tabs in this window.
+    /// To avoid flooding the ptys while the window is being resized
+    /// interactively, this is rate limited to RESIZE_DEBOUNCE; the
+    /// periodic maintenance timer calls this again so that the final
+    /// size is always applied.
+    fn apply_pending_tab_resize(&mut self) {
+        if self.pending_tab_resize.is_none() || self.last_tab_resize.elapsed() < RESIZE_DEBOUNCE {
+            return;
+        }
+        if let Some(size) = self.pending_tab_resize.take() {
+            self.last_tab_resize = Instant::now();
+            let mux = Mux::get().unwrap();
+            if let Some(window) = mux.get_window(self.mux_window_id) {
+                for tab in window.iter() {
+                    let size = self.terminal_size_for_tab(tab, size);
+                    tab.resize(size).ok();
+                    // Overlays that run their own terminal, such as the
+                    // tab navigator, need to be told about the new size
+                    // too; they receive it as an InputEvent::Resized.
+                    // The others are views of the tab that was resized.
+                    let overlays = self.tab_state(tab.tab_id()).overlays.clone();
+                    for (_, overlay) in overlays {
+                        if overlay.downcast_ref::<TermWizTerminalTab>().is_some() {
+                            overlay.resize(size).ok();
+                        }
+                    }
+                }
+            };
+        }
+    }
+
     fn current_cell_dimensions(&self) -> RowsAndCols {
         RowsAndCols {
             rows: self.terminal_size.rows as usize,
@@ -1880,10 +3498,16 @@ impl TermWindow {
     }
 
     fn decrease_font_size(&mut self) {
-        self.scaling_changed(self.dimensions, self.fonts.get_font_scale() * 0.9);
+        let font_scale = self
+            .fonts
+            .clamp_font_scale(self.fonts.get_font_scale() * 0.9);
+        self.scaling_changed(self.dimensions, font_scale);
     }
     fn increase_font_size(&mut self) {
-        self.scaling_changed(self.dimensions, self.fonts.get_font_scale() * 1.1);
+        let font_scale = self
+            .fonts
+            .clamp_font_scale(self.fonts.get_font_scale() * 1.1);
+        self.scaling_changed(self.dimensions, font_scale);
     }
     fn reset_font_size(&mut self) {
         self.scaling_changed(self.dimensions, 1.);
@@ -1912,8 +3536,41 @@ impl TermWindow {
         self.activate_tab_relative(0)
     }
 
+    /// Returns the palette to use when rendering the tab.
+    /// The color overrides of the tab take precedence over those of
+    /// this window, which in turn take precedence over the palette
+    /// of the tab.
+    fn palette(&self, tab: &Rc<dyn Tab>) -> ColorPalette {
+        let config = configuration();
+        let mut overrides = TabColorOverrides {
+            color_scheme: self.config_overrides.color_scheme.clone(),
+            background: None,
+        };
+        overrides.merge_from(&tab.color_overrides());
+        let mut palette = overrides.apply_to_palette(
+            |name| {
+                let mut palettes = self.scheme_palettes.borrow_mut();
+                if !palettes.contains_key(name) {
+                    let scheme = config.color_schemes.get(name)?;
+                    palettes.insert(name.to_string(), scheme.clone().into());
+                }
+                palettes.get(name).cloned()
+            },
+            || tab.palette(),
+        );
+
+        if tab.renderer().is_reverse_video() {
+            palette = palette.reverse_video();
+        }
+        // The visual bell flashes the screen by inverting it
+        if self.tab_state(tab.tab_id()).visual_bell_until.is_some() {
+            palette = palette.reverse_video();
+        }
+        palette
+    }
+
     fn paint_tab(&mut self, tab: &Rc<dyn Tab>, ctx: &mut dyn PaintContext) -> anyhow::Result<()> {
-        let palette = tab.palette();
+        let palette = self.palette(tab);
         let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
 
         let mut term = tab.renderer();
@@ -1934,6 +3591,21 @@ impl TermWindow {
                 &palette,
                 &dims,
             )?;
+
+            let cell_size = self.render_metrics.cell_size;
+            let strip_height = self.render_metrics.underline_height;
+            for strip in self.tab_bar.progress() {
+                ctx.clear_rect(
+                    Rect::new(
+                        Point::new(
+                            strip.x as isize * cell_size.width,
+                            cell_size.height - strip_height,
+                        ),
+                        Size::new(strip.width as isize * cell_size.width, strip_height),
+                    ),
+                    rgbcolor_to_window_color(strip.color),
+                );
+            }
         }
 
         {
@@ -1942,16 +3614,17 @@ impl TermWindow {
                 None => dims.physical_top..dims.physical_top + dims.viewport_rows as StableRowIndex,
             };
 
-            let (stable_top, lines) = term.get_lines(stable_range);
+            let (stable_top, mut lines) = term.get_lines(stable_range);
+            self.overlay_ime_preedit(&mut lines, stable_top, &cursor);
+            self.overlay_latency_hud(&mut lines, tab);
 
             for (line_idx, line) in lines.iter().enumerate() {
                 let stable_row = stable_top + line_idx as StableRowIndex;
 
-                let selrange = self
-                    .selection(tab.tab_id())
-                    .range
-                    .map(|sel| sel.cols_for_row(stable_row))
-                    .unwrap_or(0..0);
+                let selrange = expand_cols_to_cells(
+                    line,
+                    self.selection(tab.tab_id()).cols_for_row(stable_row),
+                );
 
                 self.render_screen_line(
                     ctx,
@@ -2011,7 +3684,7 @@ impl TermWindow {
             bg,
         );
         // right padding / scroll bar
-        let padding_right = self.effective_right_padding(&config);
+        let padding_right = effective_right_padding(&config, &self.render_metrics);
 
         ctx.clear_rect(
             Rect::new(
@@ -2055,19 +3728,102 @@ impl TermWindow {
             );
         }
 
+        let software = self.render_state.software();
+        if let Some(badge) = self.resize_badge(&software.glyph_cache, tab, &palette)? {
+            ctx.clear_rect(badge.rect, rgbcolor_to_window_color(badge.background));
+            let foreground = rgbcolor_to_window_color(badge.foreground);
+            for (origin, glyph) in &badge.glyphs {
+                if let Some(texture) = glyph.texture.as_ref() {
+                    ctx.draw_image(
+                        *origin,
+                        Some(texture.coords),
+                        &*texture.texture.image.borrow(),
+                        if glyph.has_color {
+                            Operator::Over
+                        } else {
+                            Operator::MultiplyThenOver(foreground)
+                        },
+                    );
+                }
+            }
+        }
+
+        if self.show_texture_atlas {
+            atlasview::paint_software(
+                ctx,
+                &self.dimensions,
+                &self.render_state.software().glyph_cache.borrow().atlas,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Paints the tab into the texture of the post-processing stage,
+    /// and then runs the custom shader over it to draw the frame
+    fn paint_tab_post_processed(
+        &mut self,
+        tab: &Rc<dyn Tab>,
+        frame: &mut glium::Frame,
+    ) -> anyhow::Result<()> {
+        let gl_state = self.render_state.opengl();
+        let context = Rc::clone(&gl_state.context);
+        let texture = gl_state
+            .post_process
+            .borrow_mut()
+            .as_mut()
+            .map(|post| {
+                post.target(
+                    &context,
+                    self.dimensions.pixel_width as u32,
+                    self.dimensions.pixel_height as u32,
+                )
+            })
+            .transpose()?;
+        let texture = match texture {
+            Some(texture) => texture,
+            None => return self.paint_tab_opengl(tab, frame),
+        };
+
+        {
+            let mut target = glium::framebuffer::SimpleFrameBuffer::new(&context, &*texture)?;
+            self.paint_tab_opengl(tab, &mut target)?;
+        }
+
+        let cursor = self.cursor_pixel_position(tab);
+        let gl_state = self.render_state.opengl();
+        if let Some(post) = gl_state.post_process.borrow().as_ref() {
+            post.draw(frame, &texture, cursor)?;
+        }
         Ok(())
     }
 
-    fn effective_right_padding(&self, config: &ConfigHandle) -> u16 {
-        effective_right_padding(config, &self.render_metrics)
+    /// Returns the position of the bottom left corner of the cursor in
+    /// pixels, measured from the bottom left corner of the window, as
+    /// the custom shader expects
+    fn cursor_pixel_position(&self, tab: &Rc<dyn Tab>) -> (f32, f32) {
+        let config = configuration();
+        let term = tab.renderer();
+        let cursor = term.get_cursor_position();
+        let top = self
+            .get_viewport(tab.tab_id())
+            .unwrap_or_else(|| term.get_dimensions().physical_top);
+        let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
+        let cell_width = self.render_metrics.cell_size.width as f32;
+        let cell_height = self.render_metrics.cell_size.height as f32;
+
+        let x = config.window_padding.left as f32 + cursor.x as f32 * cell_width;
+        let rows_above = (cursor.y - top) as f32 + first_line_offset as f32 + 1.;
+        let y = config.window_padding.top as f32 + rows_above * cell_height;
+        (x, self.dimensions.pixel_height as f32 - y)
     }
 
     fn paint_tab_opengl(
         &mut self,
         tab: &Rc<dyn Tab>,
-        frame: &mut glium::Frame,
+        frame: &mut impl Surface,
     ) -> anyhow::Result<()> {
-        let palette = tab.palette();
+        let palette = self.palette(tab);
 
         let background_color = palette.resolve_bg(wezterm_term::color::ColorAttribute::Default);
         let (r, g, b, a) = background_color.to_tuple_rgba();
@@ -2090,7 +3846,9 @@ impl TermWindow {
                 None => dims.physical_top..dims.physical_top + dims.viewport_rows as StableRowIndex,
             };
 
-            let (top, vp_lines) = term.get_lines(stable_range);
+            let (top, mut vp_lines) = term.get_lines(stable_range);
+            self.overlay_ime_preedit(&mut vp_lines, top, &cursor);
+            self.overlay_latency_hud(&mut vp_lines, tab);
             stable_top = top;
             lines = vp_lines;
         }
@@ -2120,6 +3878,18 @@ impl TermWindow {
                 },
                 &mut quads,
             )?;
+
+            // The progress strips are drawn using the cursor layer,
+            // which is otherwise unused in the tab bar
+            let strip_texture = gl_state.util_sprites.cursor_underline.texture_coords();
+            for strip in self.tab_bar.progress() {
+                let color = rgbcolor_to_window_color(strip.color);
+                for x in strip.x..strip.x + strip.width {
+                    let mut quad = quads.cell(x, 0)?;
+                    quad.set_cursor(strip_texture);
+                    quad.set_cursor_color(color);
+                }
+            }
         }
 
         {
@@ -2146,7 +3916,7 @@ impl TermWindow {
             let bottom = top + thumb_size;
 
             let config = configuration();
-            let padding = self.effective_right_padding(&config) as f32;
+            let padding = effective_right_padding(&config, &self.render_metrics) as f32;
 
             let right = self.dimensions.pixel_width as f32 / 2.;
             let left = right - padding;
@@ -2164,13 +3934,27 @@ impl TermWindow {
             quad.set_cursor_color(rgbcolor_to_window_color(background_color));
         }
 
-        let selrange = self.selection(tab.tab_id()).range.clone();
+        {
+            // The quad behind the cells fills the padding with the
+            // background color, or the background image
+            let mut quad = quads.background();
+            let color = rgbcolor_to_window_color(background_color);
+            let white_space = gl_state.util_sprites.white_space.texture_coords();
+            quad.set_bg_color(color);
+            quad.set_fg_color(color);
+            quad.set_texture(white_space);
+            quad.set_texture_adjust(0., 0., 0., 0.);
+            quad.set_underline(white_space);
+            quad.set_has_color(false);
+            quad.set_cursor(white_space);
+            quad.set_cursor_color(color);
+        }
+
+        let selection = *self.selection(tab.tab_id());
 
         for (line_idx, line) in lines.iter().enumerate() {
             let stable_row = stable_top + line_idx as StableRowIndex;
-            let selrange = selrange
-                .map(|sel| sel.cols_for_row(stable_row))
-                .unwrap_or(0..0);
+            let selrange = expand_cols_to_cells(line, selection.cols_for_row(stable_row));
 
             self.render_screen_line_opengl(
                 RenderScreenLineOpenGLParams {
@@ -2190,6 +3974,8 @@ impl TermWindow {
             )?;
         }
 
+        let badge = self.resize_badge(&gl_state.glyph_cache, tab, &palette)?;
+
         let tex = gl_state.glyph_cache.borrow().atlas.texture();
         let projection = euclid::Transform3D::<f32, f32, f32>::ortho(
             -(self.dimensions.pixel_width as f32) / 2.0,
@@ -2217,6 +4003,24 @@ impl TermWindow {
             .magnify_filter(MagnifySamplerFilter::Nearest)
             .minify_filter(MinifySamplerFilter::Nearest);
 
+        let background_image = config
+            .window_background_image
+            .as_ref()
+            .and_then(|path| gl_state.background_image(path));
+        let hsb = if self.focused.is_some() {
+            config.window_background_image_hsb
+        } else {
+            config
+                .inactive_window_background_image_hsb
+                .unwrap_or(config.window_background_image_hsb)
+        };
+        // The sampler must be bound to a texture even when there is no
+        // image, so the glyph texture stands in for it
+        let background_tex = Sampler::new(background_image.as_ref().map_or(&*tex, |t| &**t))
+            .wrap_function(SamplerWrapFunction::Clamp)
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .minify_filter(MinifySamplerFilter::Linear);
+
         // Pass 1: Draw backgrounds, strikethrough and underline
         frame.draw(
             &*vb,
@@ -2226,6 +4030,14 @@ impl TermWindow {
                 projection: projection,
                 glyph_tex:  glyph_tex,
                 bg_and_line_layer: true,
+                has_background_image: background_image.is_some(),
+                background_tex: background_tex,
+                default_bg_color: rgbcolor_to_window_color(background_color).to_tuple_rgba(),
+                window_size: (
+                    self.dimensions.pixel_width as f32,
+                    self.dimensions.pixel_height as f32,
+                ),
+                background_hsb: (hsb.hue, hsb.saturation, hsb.brightness),
             },
             &draw_params,
         )?;
@@ -2265,9 +4077,125 @@ impl TermWindow {
             &draw_params,
         )?;
 
+        // Pass 3: Draw the resize badge over the cells, with its
+        // background and then its glyphs
+        if let Some(badge) = badge {
+            let quads = self.resize_badge_quads(&badge);
+            let vb = glium::VertexBuffer::new(&gl_state.context, &quads.vertices)?;
+            let ib = glium::IndexBuffer::new(
+                &gl_state.context,
+                glium::index::PrimitiveType::TrianglesList,
+                &quads.indices,
+            )?;
+            for &bg_and_line_layer in &[true, false] {
+                frame.draw(
+                    &vb,
+                    &ib,
+                    &gl_state.program,
+                    &uniform! {
+                        projection: projection,
+                        glyph_tex: glyph_tex,
+                        bg_and_line_layer: bg_and_line_layer,
+                        has_background_image: false,
+                    },
+                    &draw_params,
+                )?;
+            }
+        }
+
+        // Pass 4: Draw the texture atlas view
+        let atlas_view_program = gl_state.atlas_view_program.borrow();
+        let atlas_view_program = atlas_view_program
+            .as_ref()
+            .filter(|_| self.show_texture_atlas);
+        if let Some(atlas_view_program) = atlas_view_program {
+            let view =
+                atlasview::opengl_vertices(&self.dimensions, &gl_state.glyph_cache.borrow().atlas);
+            let vb = glium::VertexBuffer::new(&gl_state.context, &view.vertices)?;
+            let uniforms = uniform! {
+                projection: projection,
+                atlas_tex: glyph_tex,
+            };
+            frame.draw(
+                &vb,
+                &glium::IndexBuffer::new(
+                    &gl_state.context,
+                    glium::index::PrimitiveType::TrianglesList,
+                    &view.triangles,
+                )?,
+                atlas_view_program,
+                &uniforms,
+                &draw_params,
+            )?;
+            frame.draw(
+                &vb,
+                &glium::IndexBuffer::new(
+                    &gl_state.context,
+                    glium::index::PrimitiveType::LinesList,
+                    &view.lines,
+                )?,
+                atlas_view_program,
+                &uniforms,
+                &draw_params,
+            )?;
+        }
+
         Ok(())
     }
 
+    /// Builds the quads that draw `badge` with the OpenGL renderer
+    fn resize_badge_quads(&self, badge: &resizebadge::Badge<SrgbTexture2d>) -> FreeQuads {
+        let gl_state = self.render_state.opengl();
+        let white_space = gl_state.util_sprites.white_space.texture_coords();
+        let foreground = rgbcolor_to_window_color(badge.foreground);
+        let background = rgbcolor_to_window_color(badge.background);
+        // The quads are positioned relative to the middle of the window
+        let left_of = |x: isize| x as f32 - self.dimensions.pixel_width as f32 / 2.;
+        let top_of = |y: isize| y as f32 - self.dimensions.pixel_height as f32 / 2.;
+
+        let mut quads = FreeQuads::default();
+        let rect = badge.rect;
+        let mut quad = quads.allocate();
+        quad.set_position(
+            left_of(rect.min_x()),
+            top_of(rect.min_y()),
+            left_of(rect.max_x()),
+            top_of(rect.max_y()),
+        );
+        quad.set_bg_color(background);
+        quad.set_fg_color(foreground);
+        quad.set_texture(white_space);
+        quad.set_texture_adjust(0., 0., 0., 0.);
+        quad.set_underline(white_space);
+        quad.set_has_color(false);
+        quad.set_cursor(white_space);
+        quad.set_cursor_color(background);
+
+        for (origin, glyph) in &badge.glyphs {
+            let texture = match glyph.texture.as_ref() {
+                Some(texture) => texture,
+                None => continue,
+            };
+            let size = texture.coords.size;
+            let mut quad = quads.allocate();
+            quad.set_position(
+                left_of(origin.x),
+                top_of(origin.y),
+                left_of(origin.x + size.width),
+                top_of(origin.y + size.height),
+            );
+            quad.set_bg_color(background);
+            quad.set_fg_color(foreground);
+            quad.set_texture(texture.texture_coords());
+            quad.set_texture_adjust(0., 0., 0., 0.);
+            quad.set_underline(white_space);
+            quad.set_has_color(glyph.has_color);
+            quad.set_cursor(white_space);
+            quad.set_cursor_color(background);
+        }
+        quads
+    }
+
     fn lookup_cached_shape(
         &self,
         key: &dyn ShapeCacheKeyTrait,
@@ -2279,6 +4207,158 @@ impl TermWindow {
         }
     }
 
+    fn shape_text(&self, style: &TextStyle, text: &str) -> anyhow::Result<Rc<Vec<GlyphInfo>>> {
+        self.shape_text_with_fonts(&self.fonts, style, text)
+    }
+
+    /// Shapes `text` using `fonts` rather than the fonts of the
+    /// active tab; the shapes of both are cached side by side
+    fn shape_text_with_fonts(
+        &self,
+        fonts: &Rc<FontConfiguration>,
+        style: &TextStyle,
+        text: &str,
+    ) -> anyhow::Result<Rc<Vec<GlyphInfo>>> {
+        let key = BorrowedShapeCacheKey {
+            fonts_id: fonts.id(),
+            style,
+            text,
+        };
+        match self.lookup_cached_shape(&key) {
+            Some(result) => result,
+            None => {
+                let font = fonts.resolve_font(style)?;
+                match font.shape(text) {
+                    Ok(info) => {
+                        self.shape_cache
+                            .borrow_mut()
+                            .put(key.to_owned(), Ok(Rc::new(info)));
+                        self.lookup_cached_shape(&key).unwrap()
+                    }
+                    Err(err) => {
+                        let res = anyhow!("shaper error: {}", err);
+                        self.shape_cache.borrow_mut().put(key.to_owned(), Err(err));
+                        Err(res)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Breaks the line into clusters of cells with the same attributes
+    /// and shapes them.  The result depends only on the content of the
+    /// line and on the fonts, so it is cached by the hash of the line:
+    /// a line that was merely marked dirty by the cursor passing over it,
+    /// or that moved when the screen scrolled, isn't shaped again.
+    /// Colors, the cursor and the selection are resolved afresh each time
+    /// the line is rendered, which is why palette changes don't need to
+    /// invalidate the cache, while font changes do.
+    fn shape_line_cached(
+        &self,
+        line: &Line,
+        config: &ConfigHandle,
+    ) -> anyhow::Result<Rc<Vec<ShapedCluster>>> {
+        let hash = (self.fonts.id(), line.compute_shape_hash());
+        if let Some(shaped) = self.line_shape_cache.borrow_mut().get(&hash) {
+            return Ok(Rc::clone(shaped));
+        }
+
+        let mut shaped = vec![];
+        for cluster in line.cluster() {
+            let style = self.fonts.match_style(config, &cluster.attrs);
+            let glyph_info = self.shape_text(style, &cluster.text)?;
+            shaped.push(ShapedCluster {
+                style: style.clone(),
+                glyph_info,
+                cluster,
+            });
+        }
+
+        let shaped = Rc::new(shaped);
+        self.line_shape_cache
+            .borrow_mut()
+            .put(hash, Rc::clone(&shaped));
+        Ok(shaped)
+    }
+
+    /// Shapes the line as `shape_line_cached` does.  `cursor_x` is the
+    /// column of the cursor if it is on this line; when
+    /// `cursor_breaks_ligatures` is enabled and the cursor is over a
+    /// ligature, the text of that ligature is shaped again with
+    /// ligatures disabled, so that the cursor covers just one of the
+    /// characters while the rest of the cluster keeps its shaping.
+    /// Only the line with the cursor pays for that.
+    fn shape_line(
+        &self,
+        line: &Line,
+        config: &ConfigHandle,
+        cursor_x: Option<usize>,
+    ) -> anyhow::Result<Rc<Vec<ShapedCluster>>> {
+        let shaped = self.shape_line_cached(line, config)?;
+        let cursor_x = match cursor_x {
+            Some(x) if config.cursor_breaks_ligatures => x,
+            _ => return Ok(shaped),
+        };
+
+        // Returns the index of the ligature glyph under the cursor
+        let ligature_at_cursor = |item: &ShapedCluster| {
+            item.glyph_info.iter().position(|info| {
+                let cell_idx = item.cluster.byte_to_cell_idx[info.cluster as usize];
+                let num_cells = info.num_cells as usize;
+                // A double width character also spans two cells, but
+                // isn't a ligature
+                let own_width = line.cells().get(cell_idx).map_or(1, |c| c.width());
+                num_cells > own_width && (cell_idx..cell_idx + num_cells).contains(&cursor_x)
+            })
+        };
+        if !shaped.iter().any(|item| ligature_at_cursor(item).is_some()) {
+            return Ok(shaped);
+        }
+
+        let mut result = Vec::with_capacity(shaped.len());
+        for item in shaped.iter() {
+            let glyph_info = match ligature_at_cursor(item) {
+                Some(idx) => {
+                    // The ligature spans the text up to the start of
+                    // the next cluster of glyphs
+                    let start = item.glyph_info[idx].cluster as usize;
+                    let end = item.glyph_info[idx + 1..]
+                        .iter()
+                        .map(|info| info.cluster as usize)
+                        .find(|&cluster| cluster > start)
+                        .unwrap_or_else(|| item.cluster.text.len());
+                    let style = item.style.make_without_ligatures();
+                    let span = self.shape_text(&style, &item.cluster.text[start..end])?;
+
+                    let mut glyph_info: Vec<GlyphInfo> = item
+                        .glyph_info
+                        .iter()
+                        .filter(|info| (info.cluster as usize) < start)
+                        .cloned()
+                        .collect();
+                    glyph_info.extend(span.iter().map(|info| GlyphInfo {
+                        cluster: info.cluster + start as u32,
+                        ..info.clone()
+                    }));
+                    glyph_info.extend(
+                        item.glyph_info
+                            .iter()
+                            .filter(|info| info.cluster as usize >= end)
+                            .cloned(),
+                    );
+                    Rc::new(glyph_info)
+                }
+                None => Rc::clone(&item.glyph_info),
+            };
+            result.push(ShapedCluster {
+                cluster: item.cluster.clone(),
+                style: item.style.clone(),
+                glyph_info,
+            });
+        }
+        Ok(Rc::new(result))
+    }
+
     /// "Render" a line of the terminal screen into the vertex buffer.
     /// This is nominally a matter of setting the fg/bg color and the
     /// texture coordinates for a given glyph.  There's a little bit
@@ -2291,17 +4371,25 @@ impl TermWindow {
         let gl_state = self.render_state.opengl();
 
         let num_cols = params.dims.cols;
+        let cursor_width = cursor_width(params.line, params.stable_line_idx, params.cursor);
 
-        // Break the line into clusters of cells with the same attributes
-        let cell_clusters = params.line.cluster();
+        let shaped = self.shape_line(
+            params.line,
+            params.config,
+            cursor_column(params.stable_line_idx, params.cursor),
+        )?;
         let mut last_cell_idx = 0;
-        for cluster in cell_clusters {
+        for ShapedCluster {
+            cluster,
+            style,
+            glyph_info,
+        } in shaped.iter()
+        {
             let attrs = &cluster.attrs;
             let is_highlited_hyperlink = match (&attrs.hyperlink, &self.current_highlight) {
                 (&Some(ref this), &Some(ref highlight)) => Arc::ptr_eq(this, highlight),
                 _ => false,
             };
-            let style = self.fonts.match_style(params.config, attrs);
 
             let bg_color = params.palette.resolve_bg(attrs.background);
             let fg_color = match attrs.foreground {
@@ -2342,40 +4430,13 @@ impl TermWindow {
             let glyph_color = rgbcolor_to_window_color(fg_color);
             let bg_color = rgbcolor_to_window_color(bg_color);
 
-            // Shape the printable text from this cluster
-            let glyph_info = {
-                let key = BorrowedShapeCacheKey {
-                    style,
-                    text: &cluster.text,
-                };
-                match self.lookup_cached_shape(&key) {
-                    Some(Ok(info)) => info,
-                    Some(Err(err)) => return Err(err),
-                    None => {
-                        let font = self.fonts.resolve_font(style)?;
-                        match font.shape(&cluster.text) {
-                            Ok(info) => {
-                                self.shape_cache
-                                    .borrow_mut()
-                                    .put(key.to_owned(), Ok(Rc::new(info)));
-                                self.lookup_cached_shape(&key).unwrap().unwrap()
-                            }
-                            Err(err) => {
-                                let res = anyhow!("shaper error: {}", err);
-                                self.shape_cache.borrow_mut().put(key.to_owned(), Err(err));
-                                return Err(res);
-                            }
-                        }
-                    }
-                }
-            };
+            let glyphs = gl_state
+                .glyph_cache
+                .borrow_mut()
+                .cached_glyphs(glyph_info, style)?;
 
-            for info in glyph_info.iter() {
+            for (info, glyph) in glyphs.iter() {
                 let cell_idx = cluster.byte_to_cell_idx[info.cluster as usize];
-                let glyph = gl_state
-                    .glyph_cache
-                    .borrow_mut()
-                    .cached_glyph(info, style)?;
 
                 let left = (glyph.x_offset + glyph.bearing_x).get() as f32;
                 let top = ((PixelLength::new(self.render_metrics.cell_size.height as f64)
@@ -2408,17 +4469,25 @@ impl TermWindow {
                     }
                     last_cell_idx = cell_idx;
 
-                    let (glyph_color, bg_color, cursor_shape) = self.compute_cell_fg_bg(
-                        params.stable_line_idx,
-                        cell_idx,
-                        params.cursor,
-                        &params.selection,
-                        glyph_color,
-                        bg_color,
-                        params.palette,
-                    );
+                    let (glyph_color, bg_color, cursor_shape, cursor_cell) = self
+                        .compute_cell_fg_bg(
+                            params.stable_line_idx,
+                            cell_idx,
+                            params.cursor,
+                            cursor_width,
+                            &params.selection,
+                            glyph_color,
+                            bg_color,
+                            params.palette,
+                        );
 
-                    if let Some(image) = attrs.image.as_ref() {
+                    // Cells that lie entirely within the letterboxing around an
+                    // image are rendered as plain blank cells
+                    let image = attrs
+                        .image
+                        .as_ref()
+                        .and_then(|image| image.visible_region().map(|region| (image, region)));
+                    if let Some((image, region)) = image {
                         // Render iTerm2 style image attributes
 
                         if let Ok(sprite) = gl_state
@@ -2429,8 +4498,8 @@ impl TermWindow {
                             let width = sprite.coords.size.width;
                             let height = sprite.coords.size.height;
 
-                            let top_left = image.top_left();
-                            let bottom_right = image.bottom_right();
+                            let top_left = region.top_left;
+                            let bottom_right = region.bottom_right;
                             let origin = Point::new(
                                 sprite.coords.origin.x + (*top_left.x * width as f32) as isize,
                                 sprite.coords.origin.y + (*top_left.y * height as f32) as isize,
@@ -2453,14 +4522,23 @@ impl TermWindow {
 
                             quad.set_fg_color(glyph_color);
                             quad.set_bg_color(bg_color);
+                            // Shrink the quad to the portion of the cell that
+                            // is covered by the image
+                            let cell_width = self.render_metrics.cell_size.width as f32;
+                            let cell_height = self.render_metrics.cell_size.height as f32;
                             quad.set_texture(texture_rect);
-                            quad.set_texture_adjust(0., 0., 0., 0.);
+                            quad.set_texture_adjust(
+                                region.cell_top_left.0 * cell_width,
+                                region.cell_top_left.1 * cell_height,
+                                (region.cell_bottom_right.0 - 1.) * cell_width,
+                                (region.cell_bottom_right.1 - 1.) * cell_height,
+                            );
                             quad.set_underline(gl_state.util_sprites.white_space.texture_coords());
                             quad.set_has_color(true);
                             quad.set_cursor(
                                 gl_state
                                     .util_sprites
-                                    .cursor_sprite(cursor_shape)
+                                    .cursor_sprite(cursor_shape, cursor_cell)
                                     .texture_coords(),
                             );
                             quad.set_cursor_color(params.cursor_border_color);
@@ -2505,7 +4583,7 @@ impl TermWindow {
                     quad.set_cursor(
                         gl_state
                             .util_sprites
-                            .cursor_sprite(cursor_shape)
+                            .cursor_sprite(cursor_shape, cursor_cell)
                             .texture_coords(),
                     );
                     quad.set_cursor_color(params.cursor_border_color);
@@ -2526,10 +4604,11 @@ impl TermWindow {
             // Even though we don't have a cell for these, they still
             // hold the cursor or the selection so we need to compute
             // the colors in the usual way.
-            let (glyph_color, bg_color, cursor_shape) = self.compute_cell_fg_bg(
+            let (glyph_color, bg_color, cursor_shape, cursor_cell) = self.compute_cell_fg_bg(
                 params.stable_line_idx,
                 cell_idx,
                 params.cursor,
+                cursor_width,
                 &params.selection,
                 params.foreground,
                 params.background,
@@ -2550,7 +4629,7 @@ impl TermWindow {
             quad.set_cursor(
                 gl_state
                     .util_sprites
-                    .cursor_sprite(cursor_shape)
+                    .cursor_sprite(cursor_shape, cursor_cell)
                     .texture_coords(),
             );
             quad.set_cursor_color(params.cursor_border_color);
@@ -2578,17 +4657,21 @@ impl TermWindow {
 
         let num_cols = dims.cols;
         let cursor_border_color = rgbcolor_to_window_color(palette.cursor_border);
+        let cursor_width = cursor_width(line, stable_line_idx, cursor);
 
-        // Break the line into clusters of cells with the same attributes
-        let cell_clusters = line.cluster();
+        let shaped = self.shape_line(line, &config, cursor_column(stable_line_idx, cursor))?;
         let mut last_cell_idx = 0;
-        for cluster in cell_clusters {
+        for ShapedCluster {
+            cluster,
+            style,
+            glyph_info,
+        } in shaped.iter()
+        {
             let attrs = &cluster.attrs;
             let is_highlited_hyperlink = match (&attrs.hyperlink, &self.current_highlight) {
                 (&Some(ref this), &Some(ref highlight)) => this == highlight,
                 _ => false,
             };
-            let style = self.fonts.match_style(&config, attrs);
 
             let bg_color = palette.resolve_bg(attrs.background);
             let fg_color = match attrs.foreground {
@@ -2627,15 +4710,12 @@ impl TermWindow {
             let glyph_color = rgbcolor_to_window_color(fg_color);
             let bg_color = rgbcolor_to_window_color(bg_color);
 
-            // Shape the printable text from this cluster
-            let glyph_info = {
-                let font = self.fonts.resolve_font(style)?;
-                font.shape(&cluster.text)?
-            };
+            let glyphs = self
+                .render_state
+                .cached_software_glyphs(glyph_info, style)?;
 
-            for info in &glyph_info {
+            for (info, glyph) in glyphs.iter() {
                 let cell_idx = cluster.byte_to_cell_idx[info.cluster as usize];
-                let glyph = self.render_state.cached_software_glyph(info, style)?;
 
                 let left = (glyph.x_offset + glyph.bearing_x).get() as f32;
                 let top = ((PixelLength::new(self.render_metrics.cell_size.to_f64().height)
@@ -2666,15 +4746,17 @@ impl TermWindow {
                     }
                     last_cell_idx = cell_idx;
 
-                    let (glyph_color, bg_color, cursor_shape) = self.compute_cell_fg_bg(
-                        stable_line_idx,
-                        cell_idx,
-                        cursor,
-                        &selection,
-                        glyph_color,
-                        bg_color,
-                        palette,
-                    );
+                    let (glyph_color, bg_color, cursor_shape, cursor_cell) = self
+                        .compute_cell_fg_bg(
+                            stable_line_idx,
+                            cell_idx,
+                            cursor,
+                            cursor_width,
+                            &selection,
+                            glyph_color,
+                            bg_color,
+                            palette,
+                        );
 
                     let cell_rect = Rect::new(
                         Point::new(
@@ -2729,7 +4811,11 @@ impl TermWindow {
                                 Operator::MultiplyThenOver(glyph_color)
                             },
                         );
-                    } else if let Some(image) = attrs.image.as_ref() {
+                    } else if let Some((image, region)) = attrs
+                        .image
+                        .as_ref()
+                        .and_then(|image| image.visible_region().map(|region| (image, region)))
+                    {
                         // Render iTerm2 style image attributes
                         let software = self.render_state.software();
                         if let Ok(sprite) = software
@@ -2740,8 +4826,8 @@ impl TermWindow {
                             let width = sprite.coords.size.width;
                             let height = sprite.coords.size.height;
 
-                            let top_left = image.top_left();
-                            let bottom_right = image.bottom_right();
+                            let top_left = region.top_left;
+                            let bottom_right = region.bottom_right;
                             let origin = Point::new(
                                 sprite.coords.origin.x + (*top_left.x * width as f32) as isize,
                                 sprite.coords.origin.y + (*top_left.y * height as f32) as isize,
@@ -2755,8 +4841,18 @@ impl TermWindow {
                                 ),
                             );
 
+                            // Offset to the portion of the cell that is
+                            // covered by the image
+                            let cell_width = cell_rect.size.width as f32;
+                            let cell_height = cell_rect.size.height as f32;
+                            let dest = Point::new(
+                                cell_rect.origin.x + (region.cell_top_left.0 * cell_width) as isize,
+                                cell_rect.origin.y
+                                    + (region.cell_top_left.1 * cell_height) as isize,
+                            );
+
                             ctx.draw_image(
-                                cell_rect.origin,
+                                dest,
                                 Some(coords),
                                 &*sprite.texture.image.borrow(),
                                 Operator::Over,
@@ -2766,7 +4862,9 @@ impl TermWindow {
 
                     if cursor_shape.is_some() {
                         let software = self.render_state.software();
-                        let sprite = software.util_sprites.cursor_sprite(cursor_shape);
+                        let sprite = software
+                            .util_sprites
+                            .cursor_sprite(cursor_shape, cursor_cell);
                         ctx.draw_image(
                             cell_rect.origin,
                             Some(sprite.coords),
@@ -2789,10 +4887,11 @@ impl TermWindow {
             // Even though we don't have a cell for these, they still
             // hold the cursor or the selection so we need to compute
             // the colors in the usual way.
-            let (_glyph_color, bg_color, cursor_shape) = self.compute_cell_fg_bg(
+            let (_glyph_color, bg_color, cursor_shape, cursor_cell) = self.compute_cell_fg_bg(
                 stable_line_idx,
                 cell_idx,
                 cursor,
+                cursor_width,
                 &selection,
                 rgbcolor_to_window_color(palette.foreground),
                 rgbcolor_to_window_color(palette.background),
@@ -2810,7 +4909,9 @@ impl TermWindow {
 
             if cursor_shape.is_some() {
                 let software = self.render_state.software();
-                let sprite = software.util_sprites.cursor_sprite(cursor_shape);
+                let sprite = software
+                    .util_sprites
+                    .cursor_sprite(cursor_shape, cursor_cell);
                 ctx.draw_image(
                     cell_rect.origin,
                     Some(sprite.coords),
@@ -2848,14 +4949,25 @@ impl TermWindow {
         stable_line_idx: Option<StableRowIndex>,
         cell_idx: usize,
         cursor: &StableCursorPosition,
+        cursor_width: usize,
         selection: &Range<usize>,
         fg_color: Color,
         bg_color: Color,
         palette: &ColorPalette,
-    ) -> (Color, Color, Option<CursorShape>) {
+    ) -> (Color, Color, Option<CursorShape>, CursorCell) {
         let selected = selection.contains(&cell_idx);
 
-        let is_cursor = stable_line_idx == Some(cursor.y) && cursor.x == cell_idx;
+        // The cursor covers both halves of a double-width character
+        let is_cursor = stable_line_idx == Some(cursor.y)
+            && cell_idx >= cursor.x
+            && cell_idx < cursor.x + cursor_width;
+        let cursor_cell = if cursor_width < 2 {
+            CursorCell::Whole
+        } else if cell_idx == cursor.x {
+            CursorCell::Left
+        } else {
+            CursorCell::Right
+        };
 
         let (cursor_shape, visibility) =
             if is_cursor && cursor.visibility == CursorVisibility::Visible {
@@ -2897,16 +5009,24 @@ impl TermWindow {
         let (fg_color, bg_color) =
             match (selected, self.focused.is_some(), cursor_shape, visibility) {
                 // Selected text overrides colors
-                (true, _, _, CursorVisibility::Hidden) => (
-                    rgbcolor_to_window_color(palette.selection_fg),
-                    rgbcolor_to_window_color(palette.selection_bg),
-                ),
+                (true, _, _, CursorVisibility::Hidden) => {
+                    let (fg, bg) = palette.selection_colors(
+                        window_color_to_rgbcolor(fg_color),
+                        window_color_to_rgbcolor(bg_color),
+                    );
+                    (rgbcolor_to_window_color(fg), rgbcolor_to_window_color(bg))
+                }
                 // Cursor cell overrides colors
                 (_, true, CursorShape::BlinkingBlock, CursorVisibility::Visible)
-                | (_, true, CursorShape::SteadyBlock, CursorVisibility::Visible) => (
-                    rgbcolor_to_window_color(palette.cursor_fg),
-                    rgbcolor_to_window_color(palette.cursor_bg),
-                ),
+                | (_, true, CursorShape::SteadyBlock, CursorVisibility::Visible) => {
+                    match configuration().cursor_color_mode {
+                        CursorColorMode::Palette => (
+                            rgbcolor_to_window_color(palette.cursor_fg),
+                            rgbcolor_to_window_color(palette.cursor_bg),
+                        ),
+                        CursorColorMode::Invert => (bg_color, fg_color),
+                    }
+                }
                 // Normally, render the cell as configured (or if the window is unfocused)
                 _ => (fg_color, bg_color),
             };
@@ -2919,6 +5039,7 @@ impl TermWindow {
             } else {
                 None
             },
+            cursor_cell,
         )
     }
 
@@ -2960,7 +5081,7 @@ impl TermWindow {
 
             // This is a bit gross.  If we add other overlays that need this information,
             // this should get extracted out into a trait
-            if let Some(overlay) = state.overlay.as_ref() {
+            for (_, overlay) in &state.overlays {
                 if let Some(search_overlay) = overlay.downcast_ref::<SearchOverlay>() {
                     search_overlay.viewport_changed(pos);
                 } else if let Some(copy) = overlay.downcast_ref::<CopyOverlay>() {
@@ -3000,7 +5121,7 @@ impl TermWindow {
             _ => {}
         }
         self.update_title();
-        context.set_cursor(Some(MouseCursor::Arrow));
+        self.set_mouse_cursor(MouseCursor::Arrow, context);
     }
 
     fn mouse_event_scroll_bar(
@@ -3056,14 +5177,99 @@ impl TermWindow {
                 }
             };
         }
-        context.set_cursor(Some(MouseCursor::Arrow));
+        self.set_mouse_cursor(MouseCursor::Arrow, context);
+    }
+
+    /// Changes the mouse cursor, but only if it differs from the one
+    /// that we last set; this is called for every mouse event and
+    /// most of those don't change the cursor.
+    fn set_mouse_cursor(&mut self, cursor: MouseCursor, context: &dyn WindowOps) {
+        if self.current_mouse_cursor != Some(cursor) {
+            self.current_mouse_cursor = Some(cursor);
+            context.set_cursor(Some(cursor));
+        }
+    }
+
+    /// Converts a vertical wheel event into the number of lines to
+    /// scroll by; positive values are up.  Pixel-precise events are
+    /// accumulated so that slow trackpad motion eventually scrolls.
+    fn wheel_lines(&mut self, kind: &WMEK) -> i64 {
+        let config = configuration();
+        match kind {
+            WMEK::VertWheel(ticks) => *ticks as i64 * config.scroll_lines_per_tick as i64,
+            WMEK::VertPixelWheel(pixels) => {
+                let pixels_per_line = config
+                    .scroll_pixels_per_line
+                    .unwrap_or(self.render_metrics.cell_size.height as f64)
+                    .max(1.);
+                accumulate_pixel_scroll(&mut self.scroll_pixel_remainder, *pixels, pixels_per_line)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Converts a number of lines from `wheel_lines` into discrete
+    /// wheel ticks to report to an application that has grabbed the
+    /// mouse, accumulating lines that don't add up to a whole tick.
+    fn wheel_ticks(&mut self, lines: i64) -> i64 {
+        let lines_per_tick = configuration().scroll_lines_per_tick.max(1) as i64;
+        accumulate_wheel_ticks(&mut self.wheel_report_remainder, lines, lines_per_tick)
+    }
+
+    /// Computes how quickly a drag selection should scroll, given the
+    /// vertical pixel position of the mouse.  The further the mouse is
+    /// past the top or bottom of the terminal area, the faster we go.
+    fn selection_autoscroll_rate(&self, mouse_y: isize) -> StableRowIndex {
+        let cell_height = self.render_metrics.cell_size.height.max(1);
+        let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
+        let top = configuration().window_padding.top as isize + first_line_offset * cell_height;
+        let bottom = top + self.terminal_size.rows as isize * cell_height;
+        let max_rate = (self.terminal_size.rows as StableRowIndex).max(1);
+
+        if mouse_y < top {
+            -(1 + (top - mouse_y) / cell_height).min(max_rate)
+        } else if mouse_y >= bottom {
+            (1 + (mouse_y - bottom) / cell_height).min(max_rate)
+        } else {
+            0
+        }
+    }
+
+    /// Called periodically while a drag selection is in progress and the
+    /// mouse is outside of the terminal area; moves the end of the
+    /// selection, and with it the viewport, towards the mouse.
+    fn autoscroll_selection(&mut self, tab: &Rc<dyn Tab>) {
+        let drag = match self.selection_drag {
+            Some(drag) if drag.rows_per_tick != 0 => drag,
+            _ => return,
+        };
+
+        let dims = tab.renderer().get_dimensions();
+        let (x, y) = self.last_mouse_terminal_coords;
+        let bottom = dims.physical_top + dims.viewport_rows as StableRowIndex - 1;
+        let new_y = (y + drag.rows_per_tick)
+            .max(dims.scrollback_top)
+            .min(bottom);
+        if new_y == y {
+            // We've reached the top or bottom of the scrollback
+            return;
+        }
+
+        self.last_mouse_terminal_coords = (x, new_y);
+        self.extend_selection_at_mouse_cursor(drag.mode, tab);
     }
 
     fn extend_selection_at_mouse_cursor(&mut self, mode: Option<SelectionMode>, tab: &Rc<dyn Tab>) {
-        let mode = mode.unwrap_or(SelectionMode::Cell);
+        let mode = mode.unwrap_or_else(|| {
+            if self.selection(tab.tab_id()).rectangular {
+                SelectionMode::Block
+            } else {
+                SelectionMode::Cell
+            }
+        });
         let (x, y) = self.last_mouse_terminal_coords;
         match mode {
-            SelectionMode::Cell => {
+            SelectionMode::Cell | SelectionMode::Block => {
                 let end = SelectionCoordinate { x, y };
                 let selection_range = self.selection(tab.tab_id()).range.take();
                 let sel = match selection_range {
@@ -3074,6 +5280,7 @@ impl TermWindow {
                     Some(sel) => sel.extend(end),
                 };
                 self.selection(tab.tab_id()).range = Some(sel);
+                self.selection(tab.tab_id()).rectangular = mode == SelectionMode::Block;
             }
             SelectionMode::Word => {
                 let end_word =
@@ -3154,59 +5361,169 @@ impl TermWindow {
                 self.selection(tab.tab_id())
                     .begin(SelectionCoordinate { x, y });
             }
+            SelectionMode::Block => {
+                let mut selection = self.selection(tab.tab_id());
+                selection.begin(SelectionCoordinate { x, y });
+                selection.rectangular = true;
+            }
         }
 
         self.window.as_ref().unwrap().invalidate();
     }
 
-    fn mouse_event_terminal(
+    /// Updates current_highlight given that the mouse is over cell `x`
+    /// of `stable_row`.  Nothing is repainted unless the hovered link
+    /// changes, and then only if the rows that contain the previous or
+    /// the new link are visible.
+    fn update_hovered_link(
         &mut self,
-        tab: Rc<dyn Tab>,
+        tab: &Rc<dyn Tab>,
         x: usize,
-        y: i64,
-        event: &MouseEvent,
+        stable_row: StableRowIndex,
         context: &dyn WindowOps,
     ) {
-        let dims = tab.renderer().get_dimensions();
-        let stable_row =
-            self.get_viewport(tab.tab_id()).unwrap_or(dims.physical_top) + y as StableRowIndex;
-
-        self.last_mouse_terminal_coords = (x, stable_row);
-
-        let (top, mut lines) = tab.renderer().get_lines(stable_row..stable_row + 1);
+        let (top, lines) = tab.renderer().get_lines(stable_row..stable_row + 1);
         let new_highlight = if top == stable_row {
-            if let Some(line) = lines.get_mut(0) {
-                if let Some(cell) = line.cells().get(x) {
-                    cell.attrs().hyperlink.as_ref().cloned()
+            lines
+                .get(0)
+                .and_then(|line| line.cells().get(x))
+                .and_then(|cell| cell.attrs().hyperlink.as_ref().cloned())
+        } else {
+            None
+        };
+        // Don't highlight links to files that don't exist
+        let new_highlight = match new_highlight {
+            Some(link) if filelink::needs_cwd(link.uri()) => {
+                let cwd = tab.get_current_working_dir();
+                if self.file_link_exists.link_exists(&link, cwd.as_ref()) {
+                    Some(link)
                 } else {
                     None
                 }
-            } else {
-                None
+            }
+            new_highlight => new_highlight,
+        };
+
+        let unchanged = match (self.current_highlight.as_ref(), new_highlight.as_ref()) {
+            (Some(old_link), Some(new_link)) => Arc::ptr_eq(old_link, new_link),
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return;
+        }
+
+        let new_rows = match new_highlight.as_ref() {
+            Some(link) => self.hyperlink_rows(tab, link, stable_row),
+            None => 0..0,
+        };
+        let old_rows = std::mem::replace(&mut self.current_highlight_rows, new_rows.clone());
+        self.current_highlight = new_highlight;
+
+        let dims = tab.renderer().get_dimensions();
+        let top = self.get_viewport(tab.tab_id()).unwrap_or(dims.physical_top);
+        let visible = top..top + dims.viewport_rows as StableRowIndex;
+        let is_visible =
+            |rows: &Range<StableRowIndex>| rows.start < visible.end && visible.start < rows.end;
+        if is_visible(&old_rows) || is_visible(&new_rows) {
+            // Repaint so that we render the underline correctly
+            context.invalidate();
+        }
+    }
+
+    /// Shows the target of current_highlight in the status area while
+    /// exactly the hyperlink_preview_mods are held, and restores the
+    /// status once they are released.  The link itself is already
+    /// underlined, so only the tab bar, or the title, is updated.
+    fn update_link_preview(&mut self) {
+        let preview = if self.held_modifiers == configuration().hyperlink_preview_mods {
+            match (
+                self.current_highlight.as_ref(),
+                self.get_active_tab_or_overlay(),
+            ) {
+                (Some(link), Some(tab)) => {
+                    filelink::resolve(link.uri(), tab.get_current_working_dir().as_ref())
+                }
+                _ => None,
             }
         } else {
             None
         };
+        if preview != self.link_preview {
+            self.link_preview = preview;
+            self.update_status_text();
+        }
+    }
 
-        match (self.current_highlight.as_ref(), new_highlight) {
-            (Some(old_link), Some(new_link)) if Arc::ptr_eq(&old_link, &new_link) => {
-                // Unchanged
-            }
-            (_, rhs) => {
-                // We're hovering over a different URL, so invalidate and repaint
-                // so that we render the underline correctly
-                self.current_highlight = rhs;
-                context.invalidate();
-            }
+    /// Returns the rows of the viewport, around `stable_row`, that
+    /// contain cells belonging to `link`; a link may wrap across rows
+    fn hyperlink_rows(
+        &self,
+        tab: &Rc<dyn Tab>,
+        link: &Arc<Hyperlink>,
+        stable_row: StableRowIndex,
+    ) -> Range<StableRowIndex> {
+        let dims = tab.renderer().get_dimensions();
+        let viewport = self.get_viewport(tab.tab_id()).unwrap_or(dims.physical_top);
+        let (top, lines) = tab
+            .renderer()
+            .get_lines(viewport..viewport + dims.viewport_rows as StableRowIndex);
+        if stable_row < top || stable_row >= top + lines.len() as StableRowIndex {
+            return stable_row..stable_row + 1;
+        }
+
+        let has_link = |idx: usize| {
+            lines[idx].cells().iter().any(|cell| {
+                cell.attrs()
+                    .hyperlink
+                    .as_ref()
+                    .map(|l| Arc::ptr_eq(l, link))
+                    .unwrap_or(false)
+            })
         };
 
-        context.set_cursor(Some(if self.current_highlight.is_some() {
-            // When hovering over a hyperlink, show an appropriate
-            // mouse cursor to give the cue that it is clickable
-            MouseCursor::Hand
-        } else {
-            MouseCursor::Text
-        }));
+        let row = (stable_row - top) as usize;
+        let mut start = row;
+        while start > 0 && has_link(start - 1) {
+            start -= 1;
+        }
+        let mut end = row + 1;
+        while end < lines.len() && has_link(end) {
+            end += 1;
+        }
+        top + start as StableRowIndex..top + end as StableRowIndex
+    }
+
+    fn mouse_event_terminal(
+        &mut self,
+        tab: Rc<dyn Tab>,
+        x: usize,
+        y: i64,
+        event: &MouseEvent,
+        context: &dyn WindowOps,
+    ) {
+        let dims = tab.renderer().get_dimensions();
+        let stable_row =
+            self.get_viewport(tab.tab_id()).unwrap_or(dims.physical_top) + y as StableRowIndex;
+
+        // Moving within a cell can't change what we're hovering over
+        let cell_changed = self.last_mouse_terminal_coords != (x, stable_row);
+        self.last_mouse_terminal_coords = (x, stable_row);
+        if cell_changed || event.kind != WMEK::Move {
+            self.update_hovered_link(&tab, x, stable_row, context);
+        }
+        self.update_link_preview();
+
+        self.set_mouse_cursor(
+            if self.current_highlight.is_some() {
+                // When hovering over a hyperlink, show an appropriate
+                // mouse cursor to give the cue that it is clickable
+                MouseCursor::Hand
+            } else {
+                MouseCursor::Text
+            },
+            context,
+        );
 
         let event_trigger_type = match &event.kind {
             WMEK::Press(press) => {
@@ -3248,7 +5565,10 @@ impl TermWindow {
                     None
                 }
             }
-            WMEK::VertWheel(_) | WMEK::HorzWheel(_) => None,
+            WMEK::VertWheel(_)
+            | WMEK::HorzWheel(_)
+            | WMEK::VertPixelWheel(_)
+            | WMEK::HorzPixelWheel(_) => None,
         };
 
         let ignore_grab_modifier = Modifiers::SHIFT;
@@ -3271,16 +5591,45 @@ impl TermWindow {
                 .input_map
                 .lookup_mouse(event_trigger_type.clone(), modifiers)
             {
+                if let (
+                    MouseEventTrigger::Drag {
+                        button: TMB::Left, ..
+                    },
+                    KeyAssignment::ExtendSelectionToMouseCursor(mode),
+                ) = (&event_trigger_type, &action)
+                {
+                    self.selection_drag = Some(SelectionDrag {
+                        mode: *mode,
+                        rows_per_tick: self.selection_autoscroll_rate(event.coords.y),
+                    });
+                }
                 self.perform_key_assignment(&tab, &action).ok();
             }
 
             return;
         }
 
+        // Applications only understand discrete wheel ticks
+        let wheel_ticks = match event.kind {
+            WMEK::VertWheel(_) | WMEK::VertPixelWheel(_) => {
+                let lines = self.wheel_lines(&event.kind);
+                let ticks = self.wheel_ticks(lines);
+                if ticks == 0 {
+                    return;
+                }
+                ticks
+            }
+            _ => 0,
+        };
+
         let mouse_event = wezterm_term::MouseEvent {
             kind: match event.kind {
                 WMEK::Move => TMEK::Move,
-                WMEK::VertWheel(_) | WMEK::HorzWheel(_) | WMEK::Press(_) => TMEK::Press,
+                WMEK::VertWheel(_)
+                | WMEK::HorzWheel(_)
+                | WMEK::VertPixelWheel(_)
+                | WMEK::HorzPixelWheel(_)
+                | WMEK::Press(_) => TMEK::Press,
                 WMEK::Release(_) => TMEK::Release,
             },
             button: match event.kind {
@@ -3296,14 +5645,14 @@ impl TermWindow {
                         TMB::None
                     }
                 }
-                WMEK::VertWheel(amount) => {
-                    if amount > 0 {
-                        TMB::WheelUp(amount as usize)
+                WMEK::VertWheel(_) | WMEK::VertPixelWheel(_) => {
+                    if wheel_ticks > 0 {
+                        TMB::WheelUp(wheel_ticks as usize)
                     } else {
-                        TMB::WheelDown((-amount) as usize)
+                        TMB::WheelDown((-wheel_ticks) as usize)
                     }
                 }
-                WMEK::HorzWheel(_) => TMB::None,
+                WMEK::HorzWheel(_) | WMEK::HorzPixelWheel(_) => TMB::None,
             },
             x,
             y,
@@ -3341,7 +5690,10 @@ impl TermWindow {
         };
 
         let tab_id = tab.tab_id();
-        self.tab_state(tab_id).overlay.clone().or_else(|| Some(tab))
+        self.tab_state(tab_id)
+            .overlay()
+            .cloned()
+            .or_else(|| Some(tab))
     }
 
     fn get_active_tab_no_overlay(&self) -> Option<Rc<dyn Tab>> {
@@ -3349,33 +5701,101 @@ impl TermWindow {
         mux.get_active_tab_for_window(self.mux_window_id)
     }
 
-    /// Removes any overlay for the specified tab
-    fn cancel_overlay_for_tab(&self, tab_id: TabId) {
-        self.tab_state(tab_id).overlay.take();
+    /// Removes the overlay identified by `overlay_id` from the specified
+    /// tab.  If it was the topmost overlay then the one beneath it, if
+    /// any, becomes active again.
+    fn cancel_overlay_for_tab(&mut self, tab_id: TabId, overlay_id: OverlayId) {
+        self.tab_state(tab_id)
+            .overlays
+            .retain(|(id, _)| *id != overlay_id);
+        self.update_title();
         if let Some(window) = self.window.as_ref() {
             window.invalidate();
         }
     }
 
-    pub fn schedule_cancel_overlay(window: Window, tab_id: TabId) {
+    pub fn schedule_cancel_overlay(window: Window, tab_id: TabId, overlay_id: OverlayId) {
         window.apply(move |myself, _| {
             if let Some(myself) = myself.downcast_mut::<Self>() {
-                myself.cancel_overlay_for_tab(tab_id);
+                myself.cancel_overlay_for_tab(tab_id, overlay_id);
             }
             Ok(())
         });
     }
 
-    pub fn assign_overlay(&mut self, tab_id: TabId, overlay: Rc<dyn Tab>) {
-        self.tab_state(tab_id).overlay.replace(overlay);
+    /// Opens `overlay` over the specified tab, suspending any overlay
+    /// that is already open there until this one is cancelled
+    pub fn assign_overlay(&mut self, tab_id: TabId, overlay_id: OverlayId, overlay: Rc<dyn Tab>) {
+        self.tab_state(tab_id).overlays.push((overlay_id, overlay));
         self.update_title();
     }
 }
 
+/// Returns the column of the cursor if it is on the line at `stable_line_idx`
+fn cursor_column(
+    stable_line_idx: Option<StableRowIndex>,
+    cursor: &StableCursorPosition,
+) -> Option<usize> {
+    if stable_line_idx == Some(cursor.y) {
+        Some(cursor.x)
+    } else {
+        None
+    }
+}
+
+/// Returns the number of cells covered by the cursor if it is on this
+/// line; this is 2 when the cursor is over a double-width character.
+fn cursor_width(
+    line: &Line,
+    stable_line_idx: Option<StableRowIndex>,
+    cursor: &StableCursorPosition,
+) -> usize {
+    if stable_line_idx != Some(cursor.y) {
+        return 1;
+    }
+    line.cells()
+        .get(cursor.x)
+        .map(|cell| cell.width().max(1))
+        .unwrap_or(1)
+}
+
+/// Adds `pixels` of scrolling to `remainder`, the fraction of a line
+/// that was left over from before, and returns the whole lines that
+/// they add up to.  Motion in the other direction isn't eaten up by
+/// what was left over.
+fn accumulate_pixel_scroll(remainder: &mut f64, pixels: f64, pixels_per_line: f64) -> i64 {
+    if *remainder * pixels < 0. {
+        *remainder = 0.;
+    }
+    *remainder += pixels / pixels_per_line;
+    let lines = remainder.trunc();
+    *remainder -= lines;
+    lines as i64
+}
+
+/// Adds `lines` of scrolling to `remainder`, the lines that were left
+/// over from before, and returns the whole ticks of `lines_per_tick`
+/// that they add up to.  Motion in the other direction isn't eaten
+/// up by what was left over.
+fn accumulate_wheel_ticks(remainder: &mut i64, lines: i64, lines_per_tick: i64) -> i64 {
+    if *remainder * lines < 0 {
+        *remainder = 0;
+    }
+    *remainder += lines;
+    let ticks = *remainder / lines_per_tick;
+    *remainder %= lines_per_tick;
+    ticks
+}
+
 fn rgbcolor_to_window_color(color: RgbColor) -> Color {
     Color::rgba(color.red, color.green, color.blue, 0xff)
 }
 
+fn window_color_to_rgbcolor(color: Color) -> RgbColor {
+    let (red, green, blue, _) = color.as_rgba();
+    RgbColor::new(red, green, blue)
+}
+
 fn window_mods_to_termwiz_mods(modifiers: ::window::Modifiers) -> termwiz::input::Modifiers {
     let mut result = termwiz::input::Modifiers::NONE;
     if modifiers.contains(::window::Modifiers::SHIFT) {
@@ -3398,3 +5818,44 @@ fn window_mods_to_termwiz_mods(modifiers: ::window::Modifiers) -> termwiz::input
     }
     result
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pixel_scroll_accumulates() {
+        let mut remainder = 0.;
+        // Slow motion eventually scrolls a line
+        assert_eq!(accumulate_pixel_scroll(&mut remainder, 6., 20.), 0);
+        assert_eq!(accumulate_pixel_scroll(&mut remainder, 6., 20.), 0);
+        assert_eq!(accumulate_pixel_scroll(&mut remainder, 10., 20.), 1);
+        assert!((remainder - 0.1).abs() < 1e-9);
+        // Fast motion scrolls several lines at once
+        assert_eq!(accumulate_pixel_scroll(&mut remainder, 45., 20.), 2);
+        assert!((remainder - 0.35).abs() < 1e-9);
+        // Reversing discards what was left over
+        assert_eq!(accumulate_pixel_scroll(&mut remainder, -15., 20.), 0);
+        assert!((remainder + 0.75).abs() < 1e-9);
+        assert_eq!(accumulate_pixel_scroll(&mut remainder, -5., 20.), -1);
+        assert!(remainder.abs() < 1e-9);
+    }
+
+    #[test]
+    fn wheel_ticks_accumulate() {
+        let mut remainder = 0;
+        assert_eq!(accumulate_wheel_ticks(&mut remainder, 1, 3), 0);
+        assert_eq!(accumulate_wheel_ticks(&mut remainder, 1, 3), 0);
+        assert_eq!(accumulate_wheel_ticks(&mut remainder, 1, 3), 1);
+        assert_eq!(remainder, 0);
+        assert_eq!(accumulate_wheel_ticks(&mut remainder, 7, 3), 2);
+        assert_eq!(remainder, 1);
+        // Reversing discards what was left over
+        assert_eq!(accumulate_wheel_ticks(&mut remainder, -2, 3), 0);
+        assert_eq!(remainder, -2);
+        assert_eq!(accumulate_wheel_ticks(&mut remainder, -4, 3), -2);
+        assert_eq!(remainder, 0);
+        // A tick per line reports every line
+        assert_eq!(accumulate_wheel_ticks(&mut remainder, 5, 1), 5);
+    }
+}
@@ -48,6 +48,16 @@ impl RenderMetrics {
     }
 }
 
+/// Which portion of the cursor a cell holds.  When the cursor is
+/// over a double-width character it spans two cells, and each
+/// of those cells draws its half of the cursor outline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorCell {
+    Whole,
+    Left,
+    Right,
+}
+
 pub struct UtilSprites<T: Texture2d> {
     pub white_space: Sprite<T>,
     pub single_underline: Sprite<T>,
@@ -56,6 +66,8 @@ pub struct UtilSprites<T: Texture2d> {
     pub single_and_strike: Sprite<T>,
     pub double_and_strike: Sprite<T>,
     pub cursor_box: Sprite<T>,
+    pub cursor_box_left: Sprite<T>,
+    pub cursor_box_right: Sprite<T>,
     pub cursor_i_beam: Sprite<T>,
     pub cursor_underline: Sprite<T>,
     pub overline: Sprite<T>,
@@ -220,59 +232,83 @@ impl<T: Texture2d> UtilSprites<T> {
             / metrics.cell_size.height as f64)
             .ceil() as usize;
 
+        let draw_top_bottom = |buffer: &mut Image| {
+            for i in 0..metrics.underline_height {
+                // Top border
+                buffer.draw_line(
+                    Point::new(cell_rect.origin.x, cell_rect.origin.y + i),
+                    Point::new(
+                        cell_rect.origin.x + metrics.cell_size.width,
+                        cell_rect.origin.y + i,
+                    ),
+                    white,
+                    Operator::Source,
+                );
+                // Bottom border
+                buffer.draw_line(
+                    Point::new(
+                        cell_rect.origin.x,
+                        cell_rect.origin.y + metrics.cell_size.height.saturating_sub(1 + i),
+                    ),
+                    Point::new(
+                        cell_rect.origin.x + metrics.cell_size.width,
+                        cell_rect.origin.y + metrics.cell_size.height.saturating_sub(1 + i),
+                    ),
+                    white,
+                    Operator::Source,
+                );
+            }
+        };
+
+        let draw_left = |buffer: &mut Image| {
+            for i in 0..border_width {
+                buffer.draw_line(
+                    Point::new(cell_rect.origin.x + i as isize, cell_rect.origin.y),
+                    Point::new(
+                        cell_rect.origin.x + i as isize,
+                        cell_rect.origin.y + metrics.cell_size.height,
+                    ),
+                    white,
+                    Operator::Source,
+                );
+            }
+        };
+
+        let draw_right = |buffer: &mut Image| {
+            for i in 0..border_width {
+                buffer.draw_line(
+                    Point::new(
+                        cell_rect.origin.x + metrics.cell_size.width.saturating_sub(1 + i as isize),
+                        cell_rect.origin.y,
+                    ),
+                    Point::new(
+                        cell_rect.origin.x + metrics.cell_size.width.saturating_sub(1 + i as isize),
+                        cell_rect.origin.y + metrics.cell_size.height,
+                    ),
+                    white,
+                    Operator::Source,
+                );
+            }
+        };
+
         buffer.clear_rect(cell_rect, black);
-        for i in 0..metrics.underline_height {
-            // Top border
-            buffer.draw_line(
-                Point::new(cell_rect.origin.x, cell_rect.origin.y + i),
-                Point::new(
-                    cell_rect.origin.x + metrics.cell_size.width,
-                    cell_rect.origin.y + i,
-                ),
-                white,
-                Operator::Source,
-            );
-            // Bottom border
-            buffer.draw_line(
-                Point::new(
-                    cell_rect.origin.x,
-                    cell_rect.origin.y + metrics.cell_size.height.saturating_sub(1 + i),
-                ),
-                Point::new(
-                    cell_rect.origin.x + metrics.cell_size.width,
-                    cell_rect.origin.y + metrics.cell_size.height.saturating_sub(1 + i),
-                ),
-                white,
-                Operator::Source,
-            );
-        }
-        for i in 0..border_width {
-            // Left border
-            buffer.draw_line(
-                Point::new(cell_rect.origin.x + i as isize, cell_rect.origin.y),
-                Point::new(
-                    cell_rect.origin.x + i as isize,
-                    cell_rect.origin.y + metrics.cell_size.height,
-                ),
-                white,
-                Operator::Source,
-            );
-            // Right border
-            buffer.draw_line(
-                Point::new(
-                    cell_rect.origin.x + metrics.cell_size.width.saturating_sub(1 + i as isize),
-                    cell_rect.origin.y,
-                ),
-                Point::new(
-                    cell_rect.origin.x + metrics.cell_size.width.saturating_sub(1 + i as isize),
-                    cell_rect.origin.y + metrics.cell_size.height,
-                ),
-                white,
-                Operator::Source,
-            );
-        }
+        draw_top_bottom(&mut buffer);
+        draw_left(&mut buffer);
+        draw_right(&mut buffer);
         let cursor_box = glyph_cache.atlas.allocate(&buffer)?;
 
+        // The left and right halves of the box, for when the cursor
+        // spans a double-width cell
+        buffer.clear_rect(cell_rect, black);
+        draw_top_bottom(&mut buffer);
+        draw_left(&mut buffer);
+        let cursor_box_left = glyph_cache.atlas.allocate(&buffer)?;
+
+        buffer.clear_rect(cell_rect, black);
+        draw_top_bottom(&mut buffer);
+        draw_right(&mut buffer);
+        let cursor_box_right = glyph_cache.atlas.allocate(&buffer)?;
+
         buffer.clear_rect(cell_rect, black);
         for i in 0..border_width * 2 {
             // Left border
@@ -314,6 +350,8 @@ impl<T: Texture2d> UtilSprites<T> {
             single_and_strike,
             double_and_strike,
             cursor_box,
+            cursor_box_left,
+            cursor_box_right,
             cursor_i_beam,
             cursor_underline,
             overline,
@@ -369,13 +407,21 @@ impl<T: Texture2d> UtilSprites<T> {
         }
     }
 
-    pub fn cursor_sprite(&self, shape: Option<CursorShape>) -> &Sprite<T> {
+    pub fn cursor_sprite(&self, shape: Option<CursorShape>, cell: CursorCell) -> &Sprite<T> {
         match shape {
             None => &self.white_space,
             Some(shape) => match shape {
                 CursorShape::Default => &self.white_space,
-                CursorShape::BlinkingBlock | CursorShape::SteadyBlock => &self.cursor_box,
-                CursorShape::BlinkingBar | CursorShape::SteadyBar => &self.cursor_i_beam,
+                CursorShape::BlinkingBlock | CursorShape::SteadyBlock => match cell {
+                    CursorCell::Whole => &self.cursor_box,
+                    CursorCell::Left => &self.cursor_box_left,
+                    CursorCell::Right => &self.cursor_box_right,
+                },
+                CursorShape::BlinkingBar | CursorShape::SteadyBar => match cell {
+                    // The bar sits at the leading edge of the cursor
+                    CursorCell::Right => &self.white_space,
+                    CursorCell::Whole | CursorCell::Left => &self.cursor_i_beam,
+                },
                 CursorShape::BlinkingUnderline | CursorShape::SteadyUnderline => {
                     &self.cursor_underline
                 }
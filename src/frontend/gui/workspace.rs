@@ -0,0 +1,151 @@
+//! Workspaces label windows, so that the window that is used for a
+//! particular project can be recognized, and switched to by name.
+//! The name is held by the mux window; this module keeps track of the
+//! gui window of each mux window so that it can be focused, and
+//! formats the title of a window using the `window_title_format`.
+use crate::mux::window::WindowId as MuxWindowId;
+use crate::mux::Mux;
+use ::window::{Window, WindowOps};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    static WINDOWS: RefCell<HashMap<MuxWindowId, Window>> = RefCell::new(HashMap::new());
+}
+
+/// Forgets the windows whose mux window has gone away
+fn prune_windows(windows: &mut HashMap<MuxWindowId, Window>) {
+    let mux = Mux::get().unwrap();
+    windows.retain(|mux_window_id, _| mux.get_window(*mux_window_id).is_some());
+}
+
+/// Records the gui window that displays `mux_window_id`
+pub fn register_window(mux_window_id: MuxWindowId, window: &Window) {
+    WINDOWS.with(|windows| {
+        let mut windows = windows.borrow_mut();
+        prune_windows(&mut windows);
+        windows.insert(mux_window_id, window.clone());
+    });
+}
+
+/// Returns the lowest numbered window whose workspace is `name`
+pub fn find_workspace(name: &str) -> Option<MuxWindowId> {
+    let mux = Mux::get().unwrap();
+    let mut window_ids = mux.iter_windows();
+    window_ids.sort();
+    window_ids.into_iter().find(|mux_window_id| {
+        mux.get_window(*mux_window_id)
+            .map_or(false, |window| window.get_workspace() == Some(name))
+    })
+}
+
+/// Returns the names of the workspaces along with the title of the
+/// active tab of their window.  If several windows share a name,
+/// only the one that `find_workspace` would pick is listed.
+pub fn list_workspaces() -> Vec<(String, String)> {
+    let mux = Mux::get().unwrap();
+    let mut window_ids = mux.iter_windows();
+    window_ids.sort();
+
+    let mut workspaces: Vec<(String, String)> = vec![];
+    for mux_window_id in window_ids {
+        let window = match mux.get_window(mux_window_id) {
+            Some(window) => window,
+            None => continue,
+        };
+        let name = match window.get_workspace() {
+            Some(name) => name,
+            None => continue,
+        };
+        if workspaces.iter().any(|(existing, _)| existing == name) {
+            continue;
+        }
+        let title = window
+            .get_active()
+            .map(|tab| tab.get_title())
+            .unwrap_or_else(String::new);
+        workspaces.push((name.to_string(), title));
+    }
+    workspaces
+}
+
+/// Raises and focuses the gui window of `mux_window_id`, returning
+/// false if it has no gui window
+pub fn focus_window(mux_window_id: MuxWindowId) -> bool {
+    let window = WINDOWS.with(|windows| {
+        let mut windows = windows.borrow_mut();
+        prune_windows(&mut windows);
+        windows.get(&mux_window_id).cloned()
+    });
+    match window {
+        Some(window) => {
+            window.focus();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Expands the `{title}`, `{workspace}`, `{tab_index}` and
+/// `{tab_count}` variables in a `window_title_format`.
+/// `tab_index` is 1 for the first tab.  Anything else between braces
+/// is left as it is, and an unnamed window has an empty workspace.
+pub fn format_window_title(
+    format: &str,
+    title: &str,
+    workspace: Option<&str>,
+    tab_index: usize,
+    tab_count: usize,
+) -> String {
+    let mut result = String::new();
+    let mut remaining = format;
+    while let Some(start) = remaining.find('{') {
+        result.push_str(&remaining[..start]);
+        let rest = &remaining[start..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => {
+                remaining = rest;
+                break;
+            }
+        };
+        match &rest[1..end] {
+            "title" => result.push_str(title),
+            "workspace" => result.push_str(workspace.unwrap_or("")),
+            "tab_index" => result.push_str(&tab_index.to_string()),
+            "tab_count" => result.push_str(&tab_count.to_string()),
+            _ => result.push_str(&rest[..=end]),
+        }
+        remaining = &rest[end + 1..];
+    }
+    result.push_str(remaining);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn title_format() {
+        assert_eq!(
+            format_window_title(
+                "{workspace}: {title} ({tab_index}/{tab_count})",
+                "vim",
+                Some("wezterm"),
+                2,
+                3
+            ),
+            "wezterm: vim (2/3)"
+        );
+        assert_eq!(
+            format_window_title("[{workspace}] {title}", "bash", None, 1, 1),
+            "[] bash"
+        );
+        // Variables in the title itself are not expanded
+        assert_eq!(
+            format_window_title("{title} {nope} {", "{workspace}", Some("w"), 1, 1),
+            "{workspace} {nope} {"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config::WindowConfigOverrides;
 use crate::font::FontConfiguration;
 use crate::mux::tab::Tab;
 use crate::mux::window::WindowId;
@@ -99,11 +100,14 @@ pub trait FrontEnd: Downcast {
     /// error, or until there are no more windows left to manage.
     fn run_forever(&self) -> anyhow::Result<()>;
 
+    /// Create a new window that displays `tab`.
+    /// `overrides` take precedence over the configuration for that window.
     fn spawn_new_window(
         &self,
         fontconfig: &Rc<FontConfiguration>,
         tab: &Rc<dyn Tab>,
         window_id: WindowId,
+        overrides: &WindowConfigOverrides,
     ) -> anyhow::Result<()>;
 }
 impl_downcast!(FrontEnd);
@@ -1,4 +1,5 @@
 //! Implements the multiplexer server frontend
+use crate::config::WindowConfigOverrides;
 use crate::font::FontConfiguration;
 use crate::frontend::FrontEnd;
 use crate::mux::tab::Tab;
@@ -68,6 +69,7 @@ impl FrontEnd for MuxServerFrontEnd {
         _fontconfig: &Rc<FontConfiguration>,
         _tab: &Rc<dyn Tab>,
         _window_id: WindowId,
+        _overrides: &WindowConfigOverrides,
     ) -> anyhow::Result<()> {
         Ok(())
     }
@@ -1,9 +1,11 @@
-use crate::config::configuration;
+use crate::config::{
+    configuration, TabColorOverrides, TabEncoding, TabFontOverride, WindowConfigOverrides,
+};
 use crate::frontend::gui::SelectionMode;
 use crate::mux::domain::DomainId;
-use crate::mux::tab::Pattern;
+use crate::mux::tab::{ExportTextOptions, Pattern};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use wezterm_term::input::MouseButton;
 use wezterm_term::{KeyCode, KeyModifiers};
@@ -42,7 +44,7 @@ impl Default for SpawnTabDomain {
     }
 }
 
-#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct SpawnCommand {
     /// Optional descriptive label
     pub label: Option<String>,
@@ -64,13 +66,106 @@ pub struct SpawnCommand {
     /// Specifies a map of environment variables that should be set.
     /// Whether this is used depends on the domain.
     #[serde(default)]
-    pub set_environment_variables: HashMap<String, String>,
+    pub set_environment_variables: BTreeMap<String, String>,
 
     #[serde(default)]
     pub domain: SpawnTabDomain,
+
+    /// Colors to use for the spawned tab in preference to those
+    /// from the configuration and the domain
+    #[serde(default)]
+    pub colors: TabColorOverrides,
+
+    /// The font to use for the spawned tab in preference to that
+    /// from the configuration
+    #[serde(default)]
+    pub font: TabFontOverride,
+
+    /// When spawning into a new window, these take precedence over
+    /// the configuration for that window.  They are ignored when
+    /// spawning into a new tab.
+    #[serde(default)]
+    pub window_overrides: WindowConfigOverrides,
+
+    /// How the bytes exchanged with the spawned tab are translated.
+    /// If omitted, the tab uses UTF-8.
+    #[serde(default)]
+    pub encoding: Option<TabEncoding>,
+}
+
+/// Which part of a tab EmitScrollbackToCommand passes to the command
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ScrollbackCapture {
+    /// The visible screen
+    Viewport,
+    /// The scrollback and the visible screen
+    Scrollback,
+    /// The most recent command and its output; see
+    /// `ExportTextOptions::since_last_prompt`
+    SinceLastPrompt,
+}
+
+impl Default for ScrollbackCapture {
+    fn default() -> Self {
+        Self::Scrollback
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ScrollbackCommand {
+    /// The command to run in a new tab; the path of a temporary file
+    /// holding the text is appended to it.  Defaults to `$EDITOR`,
+    /// split on whitespace, or `vi` if that isn't set.
+    #[serde(default)]
+    pub args: Option<Vec<String>>,
+    #[serde(default)]
+    pub capture: ScrollbackCapture,
+    /// Preserve the colors and styles of the text as SGR escapes,
+    /// for commands such as `less -R` that can display them
+    #[serde(default)]
+    pub escapes: bool,
+}
+
+impl ScrollbackCommand {
+    pub fn export_options(&self) -> ExportTextOptions {
+        ExportTextOptions {
+            scrollback: self.capture == ScrollbackCapture::Scrollback,
+            since_last_prompt: self.capture == ScrollbackCapture::SinceLastPrompt,
+            escapes: self.escapes,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the command line to run, without the file name
+    pub fn command(&self) -> Vec<String> {
+        match &self.args {
+            Some(args) => args.clone(),
+            None => std::env::var("EDITOR")
+                .ok()
+                .filter(|editor| !editor.trim().is_empty())
+                .unwrap_or_else(|| "vi".to_string())
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+        }
+    }
 }
 
+/// Makes a table from the `key_tables` config the one in which
+/// subsequent keys are looked up
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct KeyTableActivation {
+    pub name: String,
+    /// If true, the table is deactivated again after the next key
+    #[serde(default)]
+    pub one_shot: bool,
+    /// If set, the table is deactivated when no key has been pressed
+    /// for this many milliseconds
+    #[serde(default)]
+    pub timeout_milliseconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub enum KeyAssignment {
     SpawnTab(SpawnTabDomain),
     SpawnWindow,
@@ -100,20 +195,58 @@ pub enum KeyAssignment {
     SpawnCommandInNewWindow(SpawnCommand),
     ShowLauncher,
     ClearScrollback,
+    ResetTerminal,
     Search(Pattern),
     ActivateCopyMode,
+    ShowCommandPalette,
+    ShowDebugOverlay,
+    StartRecording,
+    StopRecording,
+    ExportText(ExportTextOptions),
+    QuickSave(ExportTextOptions),
+    EmitScrollbackToCommand(ScrollbackCommand),
+    ActivateKeyTable(KeyTableActivation),
+    PopKeyTable,
+    ToggleBroadcastToWindow,
+    ToggleBroadcastToTab,
+    ClearBroadcast,
 
     SelectTextAtMouseCursor(SelectionMode),
     ExtendSelectionToMouseCursor(Option<SelectionMode>),
     OpenLinkAtMouseCursor,
     CompleteSelection,
     CompleteSelectionOrOpenLinkAtMouseCursor,
+    CycleAppearance,
+    RenameWorkspace,
+    ShowWorkspaceSwitcher,
+    SwitchToWorkspace(String),
+    ToggleTextureAtlas,
+    ToggleLatencyHud,
+    OpenConfigFile,
+    ToggleLatin1Encoding,
 }
 impl_lua_conversion!(KeyAssignment);
 
+/// The assignments of a table from the `key_tables` config
+pub struct KeyTableMap {
+    keys: HashMap<(KeyCode, KeyModifiers), KeyAssignment>,
+    /// Whether keys that aren't in the table are handled as usual,
+    /// rather than swallowed
+    pub pass_through_unmatched: bool,
+}
+
+impl KeyTableMap {
+    pub fn lookup_key(&self, key: KeyCode, mods: KeyModifiers) -> Option<KeyAssignment> {
+        self.keys
+            .get(&(key.normalize_shift_to_upper_case(mods), mods))
+            .cloned()
+    }
+}
+
 pub struct InputMap {
     keys: HashMap<(KeyCode, KeyModifiers), KeyAssignment>,
     mouse: HashMap<(MouseEventTrigger, KeyModifiers), KeyAssignment>,
+    key_tables: HashMap<String, KeyTableMap>,
 }
 
 impl InputMap {
@@ -247,6 +380,8 @@ impl InputMap {
                 [KeyModifiers::SHIFT, KeyCode::PageDown, ScrollByPage(1)],
                 [KeyModifiers::ALT, KeyCode::Char('9'), ShowTabNavigator],
                 [ctrl_shift, KeyCode::Char('X'), ActivateCopyMode],
+                [ctrl_shift, KeyCode::Char('P'), ShowCommandPalette],
+                [ctrl_shift, KeyCode::Char('L'), ShowDebugOverlay],
             );
 
             #[cfg(target_os = "macos")]
@@ -335,13 +470,37 @@ impl InputMap {
                     },
                     ExtendSelectionToMouseCursor(Some(SelectionMode::Line))
                 ],
+                [
+                    KeyModifiers::ALT,
+                    MouseEventTrigger::Down {
+                        streak: 1,
+                        button: MouseButton::Left
+                    },
+                    SelectTextAtMouseCursor(SelectionMode::Block)
+                ],
+                [
+                    KeyModifiers::ALT,
+                    MouseEventTrigger::Drag {
+                        streak: 1,
+                        button: MouseButton::Left
+                    },
+                    ExtendSelectionToMouseCursor(Some(SelectionMode::Block))
+                ],
+                [
+                    KeyModifiers::ALT,
+                    MouseEventTrigger::Up {
+                        streak: 1,
+                        button: MouseButton::Left
+                    },
+                    CompleteSelection
+                ],
                 [
                     KeyModifiers::NONE,
                     MouseEventTrigger::Down {
                         streak: 1,
                         button: MouseButton::Middle
                     },
-                    Paste
+                    PastePrimarySelection
                 ],
             );
         }
@@ -349,7 +508,35 @@ impl InputMap {
         keys.retain(|_, v| *v != KeyAssignment::DisableDefaultAssignment);
         mouse.retain(|_, v| *v != KeyAssignment::DisableDefaultAssignment);
 
-        Self { keys, mouse }
+        let key_tables = config
+            .key_tables
+            .iter()
+            .map(|(name, table)| {
+                let keys = table
+                    .keys
+                    .iter()
+                    .map(|k| ((k.key, k.mods), k.action.clone()))
+                    .collect();
+                (
+                    name.clone(),
+                    KeyTableMap {
+                        keys,
+                        pass_through_unmatched: table.pass_through_unmatched,
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            keys,
+            mouse,
+            key_tables,
+        }
+    }
+
+    /// Returns the table from the `key_tables` config with this name
+    pub fn key_table(&self, name: &str) -> Option<&KeyTableMap> {
+        self.key_tables.get(name)
     }
 
     pub fn lookup_key(&self, key: KeyCode, mods: KeyModifiers) -> Option<KeyAssignment> {
@@ -365,4 +552,40 @@ impl InputMap {
     ) -> Option<KeyAssignment> {
         self.mouse.get(&(event, mods)).cloned()
     }
+
+    /// Returns a human readable description of a key binding that
+    /// triggers the specified assignment, or None if it isn't bound.
+    /// If there are multiple bindings, the shortest description is used.
+    pub fn describe_key_for(&self, assignment: &KeyAssignment) -> Option<String> {
+        let mut candidates: Vec<String> = self
+            .keys
+            .iter()
+            .filter(|(_, v)| *v == assignment)
+            .map(|((code, mods), _)| describe_key(code, *mods))
+            .collect();
+        // Sort first so that the choice is stable between calls
+        candidates.sort();
+        candidates.into_iter().min_by_key(String::len)
+    }
+}
+
+fn describe_key(code: &KeyCode, mods: KeyModifiers) -> String {
+    let mut result = String::new();
+    for (flag, label) in &[
+        (KeyModifiers::LEADER, "LEADER"),
+        (KeyModifiers::SUPER, "SUPER"),
+        (KeyModifiers::CTRL, "CTRL"),
+        (KeyModifiers::ALT, "ALT"),
+        (KeyModifiers::SHIFT, "SHIFT"),
+    ] {
+        if mods.contains(*flag) {
+            result.push_str(label);
+            result.push('+');
+        }
+    }
+    match code {
+        KeyCode::Char(c) if !c.is_control() => result.push(*c),
+        _ => result.push_str(&format!("{:?}", code)),
+    }
+    result
 }
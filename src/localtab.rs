@@ -1,22 +1,110 @@
+use crate::config::{TabColorOverrides, TabFontOverride};
+use crate::mux::childwatch;
 use crate::mux::domain::DomainId;
+use crate::mux::latency;
+use crate::mux::recording;
 use crate::mux::renderable::Renderable;
-use crate::mux::tab::{alloc_tab_id, Tab, TabId};
+use crate::mux::search::{Search, SearchDirection};
+use crate::mux::snapshot::{SnapshotBuffer, SnapshotRenderer};
 use crate::mux::tab::{Pattern, SearchResult};
+use crate::mux::tab::{Tab, TabId};
+use crate::mux::{is_pty_closed_error, Mux, OutputParser, ThreadedWriter};
 use anyhow::Error;
 use async_trait::async_trait;
-use portable_pty::{Child, MasterPty, PtySize};
+use portable_pty::{Child, ExitStatus, MasterPty, PtySize};
 use std::cell::{RefCell, RefMut};
-use std::sync::Arc;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use termwiz::escape::osc::Progress;
+use termwiz::escape::parser::Encoding;
 use url::Url;
 use wezterm_term::color::ColorPalette;
-use wezterm_term::{Clipboard, KeyCode, KeyModifiers, MouseEvent, StableRowIndex, Terminal};
+use wezterm_term::{
+    Clipboard, KeyCode, KeyModifiers, MouseEvent, ResizeRequest, Terminal, ToastNotification,
+};
+
+/// Wraps the master side of the pty so that writes made after the
+/// slave has been closed are discarded rather than failing; the first
+/// such failure marks the tab as dead.
+/// Writes go through the same thread as the input that the terminal
+/// generates, so that they are ordered with it and don't block.
+struct LocalPty {
+    master: Box<dyn MasterPty>,
+    writer: ThreadedWriter,
+    tab_id: TabId,
+    closed: bool,
+}
+
+impl LocalPty {
+    fn mark_closed(&mut self) {
+        if self.closed {
+            return;
+        }
+        log::info!("pty of tab {} is closed; discarding input", self.tab_id);
+        self.closed = true;
+        promise::spawn::spawn_into_main_thread(async move {
+            let mux = Mux::get().unwrap();
+            mux.prune_dead_windows();
+        });
+    }
+}
+
+impl Write for LocalPty {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.closed {
+            return Ok(buf.len());
+        }
+        let result = match self.writer.write(buf) {
+            Err(err) if is_pty_closed_error(&err) => {
+                self.mark_closed();
+                Ok(buf.len())
+            }
+            result => result,
+        };
+        latency::pty_written(self.tab_id);
+        result
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        match self.writer.flush() {
+            Err(err) if is_pty_closed_error(&err) => {
+                self.mark_closed();
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
 
 pub struct LocalTab {
     tab_id: TabId,
-    terminal: RefCell<Terminal>,
+    /// The terminal model is updated with the output of the pty on
+    /// the thread that reads it, and with input on the main thread
+    terminal: Arc<Mutex<Terminal>>,
+    /// Snapshots of the terminal are published here after it changes
+    snapshots: Arc<SnapshotBuffer>,
+    /// Reads the snapshots on behalf of the renderer
+    renderer: RefCell<SnapshotRenderer>,
     process: RefCell<Box<dyn Child>>,
-    pty: RefCell<Box<dyn MasterPty>>,
+    pty: RefCell<LocalPty>,
     domain_id: DomainId,
+    color_overrides: RefCell<TabColorOverrides>,
+    /// Set when spawning the tab, and by the application using the
+    /// user variables that TabFontOverride::apply_user_var recognizes
+    font_override: RefCell<TabFontOverride>,
+    /// The foreground process group at the time that progress was
+    /// last reported, so that we can clear it when that program exits
+    progress_owner: RefCell<Option<u32>>,
+    /// The progress as of the last call to `output_processed`
+    last_progress: RefCell<Progress>,
+    /// Set once the process has been reaped
+    exit_status: RefCell<Option<ExitStatus>>,
+    /// Whether we will be notified of the exit of the process; if
+    /// not, `is_dead` has to poll it
+    watched: bool,
 }
 
 #[async_trait(?Send)]
@@ -27,42 +115,137 @@ impl Tab for LocalTab {
     }
 
     fn renderer(&self) -> RefMut<dyn Renderable> {
-        RefMut::map(self.terminal.borrow_mut(), |t| &mut *t)
+        let mut renderer = self.renderer.borrow_mut();
+        renderer.refresh();
+        renderer
     }
 
     fn is_dead(&self) -> bool {
-        if let Ok(None) = self.process.borrow_mut().try_wait() {
-            false
-        } else {
-            log::error!("is_dead: {:?}", self.tab_id);
-            true
+        if self.pty.borrow().closed {
+            return true;
+        }
+        if self.exit_status.borrow().is_none() && !self.watched {
+            self.reap();
+        }
+        self.exit_status.borrow().is_some()
+    }
+
+    fn reap(&self) {
+        if self.exit_status.borrow().is_some() {
+            return;
         }
+        let status = match self.process.borrow_mut().try_wait() {
+            Ok(None) => return,
+            Ok(Some(status)) => status,
+            Err(err) => {
+                log::error!("failed to wait for process of tab {}: {}", self.tab_id, err);
+                ExitStatus::with_exit_code(1)
+            }
+        };
+        log::info!(
+            "process of tab {} exited with status {}",
+            self.tab_id,
+            status.exit_code()
+        );
+        *self.exit_status.borrow_mut() = Some(status);
+    }
+
+    fn exit_status(&self) -> Option<ExitStatus> {
+        self.exit_status.borrow().clone()
     }
 
     fn set_clipboard(&self, clipboard: &Arc<dyn Clipboard>) {
-        self.terminal.borrow_mut().set_clipboard(clipboard);
+        self.terminal.lock().unwrap().set_clipboard(clipboard);
     }
 
     fn advance_bytes(&self, buf: &[u8]) {
-        self.terminal.borrow_mut().advance_bytes(buf)
+        {
+            let mut terminal = self.terminal.lock().unwrap();
+            terminal.advance_bytes(buf);
+            self.snapshots.publish(&mut terminal);
+        }
+        self.output_processed();
+    }
+
+    fn output_parser(&self) -> Option<OutputParser> {
+        let terminal = Arc::clone(&self.terminal);
+        let snapshots = Arc::clone(&self.snapshots);
+        Some(Box::new(move |buf: &[u8]| {
+            let mut terminal = terminal.lock().unwrap();
+            terminal.advance_bytes(buf);
+            snapshots.publish(&mut terminal);
+        }))
+    }
+
+    fn output_processed(&self) {
+        latency::output_applied(self.tab_id);
+
+        if self.terminal.lock().unwrap().take_tab_info_request() {
+            let mux = Mux::get().unwrap();
+            match mux.window_containing_tab(self.tab_id) {
+                Some(window_id) => self
+                    .terminal
+                    .lock()
+                    .unwrap()
+                    .report_tab_info(self.tab_id, window_id),
+                None => log::debug!("tab {} is not in a window", self.tab_id),
+            }
+        }
+
+        let user_vars = self.terminal.lock().unwrap().take_user_vars();
+        for (name, value) in user_vars {
+            if !self
+                .font_override
+                .borrow_mut()
+                .apply_user_var(&name, &value)
+            {
+                log::debug!("ignoring user var {}={:?}", name, value);
+            }
+        }
+
+        let progress = self.terminal.lock().unwrap().get_progress();
+        if progress != *self.last_progress.borrow() {
+            *self.progress_owner.borrow_mut() = match progress {
+                Progress::None => None,
+                _ => self.pty.borrow().master.process_group_leader(),
+            };
+            *self.last_progress.borrow_mut() = progress;
+        }
     }
 
     fn mouse_event(&self, event: MouseEvent) -> Result<(), Error> {
-        self.terminal.borrow_mut().mouse_event(event)
+        if self.pty.borrow().closed {
+            return Ok(());
+        }
+        let result = self.terminal.lock().unwrap().mouse_event(event);
+        self.check_write(result)
     }
 
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
-        self.terminal.borrow_mut().key_down(key, mods)
+        if self.pty.borrow().closed {
+            return Ok(());
+        }
+        let result = self.terminal.lock().unwrap().key_down(key, mods);
+        latency::pty_written(self.tab_id);
+        self.check_write(result)
     }
 
     fn resize(&self, size: PtySize) -> Result<(), Error> {
-        self.pty.borrow_mut().resize(size)?;
-        self.terminal.borrow_mut().resize(
+        // Update the model before the pty so that the output that the
+        // application produces in response to SIGWINCH lands on a
+        // screen that has already been reflowed to the new size.
+        // We hold the lock until the pty has been resized so that
+        // the parser thread can't interleave output in between.
+        let mut terminal = self.terminal.lock().unwrap();
+        terminal.resize(
             size.rows as usize,
             size.cols as usize,
             size.pixel_width as usize,
             size.pixel_height as usize,
         );
+        self.snapshots.publish(&mut terminal);
+        recording::record_resize(self.tab_id, size.cols, size.rows);
+        self.pty.borrow_mut().master.resize(size)?;
         Ok(())
     }
 
@@ -71,19 +254,31 @@ impl Tab for LocalTab {
     }
 
     fn reader(&self) -> Result<Box<dyn std::io::Read + Send>, Error> {
-        self.pty.borrow_mut().try_clone_reader()
+        self.pty.borrow_mut().master.try_clone_reader()
     }
 
     fn send_paste(&self, text: &str) -> Result<(), Error> {
-        self.terminal.borrow_mut().send_paste(text)
+        if self.pty.borrow().closed {
+            return Ok(());
+        }
+        let result = self.terminal.lock().unwrap().send_paste(text);
+        self.check_write(result)
+    }
+
+    fn send_text(&self, text: &str) -> Result<(), Error> {
+        if self.pty.borrow().closed {
+            return Ok(());
+        }
+        let result = self.terminal.lock().unwrap().send_text(text);
+        self.check_write(result)
     }
 
     fn get_title(&self) -> String {
-        self.terminal.borrow_mut().get_title().to_string()
+        self.terminal.lock().unwrap().get_title().to_string()
     }
 
     fn palette(&self) -> ColorPalette {
-        self.terminal.borrow().palette()
+        self.terminal.lock().unwrap().palette()
     }
 
     fn domain_id(&self) -> DomainId {
@@ -91,142 +286,168 @@ impl Tab for LocalTab {
     }
 
     fn erase_scrollback(&self) {
-        self.terminal.borrow_mut().erase_scrollback();
+        self.terminal.lock().unwrap().erase_scrollback();
+        self.publish();
     }
 
-    fn focus_changed(&self, focused: bool) {
-        self.terminal.borrow_mut().focus_changed(focused);
+    fn reset_terminal(&self) {
+        self.terminal.lock().unwrap().full_reset();
+        self.publish();
     }
 
-    fn is_mouse_grabbed(&self) -> bool {
-        self.terminal.borrow().is_mouse_grabbed()
+    fn color_overrides(&self) -> TabColorOverrides {
+        self.color_overrides.borrow().clone()
     }
 
-    fn get_current_working_dir(&self) -> Option<Url> {
-        self.terminal.borrow().get_current_dir().cloned()
+    fn set_color_overrides(&self, overrides: TabColorOverrides) {
+        *self.color_overrides.borrow_mut() = overrides;
     }
 
-    async fn search(&self, mut pattern: Pattern) -> anyhow::Result<Vec<SearchResult>> {
-        let term = self.terminal.borrow();
-        let screen = term.screen();
+    fn font_override(&self) -> TabFontOverride {
+        self.font_override.borrow().clone()
+    }
 
-        if let Pattern::CaseInSensitiveString(s) = &mut pattern {
-            // normalize the case so we match everything lowercase
-            *s = s.to_lowercase()
-        }
+    fn set_font_override(&self, font: TabFontOverride) {
+        *self.font_override.borrow_mut() = font;
+    }
 
-        let mut results = vec![];
-        let mut haystack = String::new();
-        let mut coords = vec![];
+    fn encoding(&self) -> Encoding {
+        self.terminal.lock().unwrap().encoding()
+    }
 
-        struct Coord {
-            byte_idx: usize,
-            grapheme_idx: usize,
-            stable_row: StableRowIndex,
-        }
+    fn set_encoding(&self, encoding: Encoding) {
+        self.terminal.lock().unwrap().set_encoding(encoding);
+    }
 
-        fn haystack_idx_to_coord(idx: usize, coords: &[Coord]) -> (usize, StableRowIndex) {
-            let c = coords
-                .binary_search_by(|ele| ele.byte_idx.cmp(&idx))
-                .or_else(|i| -> Result<usize, usize> { Ok(i) })
-                .unwrap();
-            let coord = coords.get(c).or_else(|| coords.last()).unwrap();
-            (coord.grapheme_idx, coord.stable_row)
-        }
+    fn focus_changed(&self, focused: bool) {
+        self.terminal.lock().unwrap().focus_changed(focused);
+    }
 
-        fn collect_matches(
-            results: &mut Vec<SearchResult>,
-            pattern: &Pattern,
-            haystack: &str,
-            coords: &[Coord],
-        ) {
-            if haystack.is_empty() {
-                return;
-            }
-            match pattern {
-                // Rust only provides a case sensitive match_indices function, so
-                // we have to pre-arrange to lowercase both the pattern and the
-                // haystack strings
-                Pattern::CaseInSensitiveString(s) | Pattern::CaseSensitiveString(s) => {
-                    for (idx, s) in haystack.match_indices(s) {
-                        let (start_x, start_y) = haystack_idx_to_coord(idx, coords);
-                        let (end_x, end_y) = haystack_idx_to_coord(idx + s.len(), coords);
-                        results.push(SearchResult {
-                            start_x,
-                            start_y,
-                            end_x,
-                            end_y,
-                        });
-                    }
-                }
-                Pattern::Regex(r) => {
-                    if let Ok(re) = regex::Regex::new(r) {
-                        for m in re.find_iter(haystack) {
-                            let (start_x, start_y) = haystack_idx_to_coord(m.start(), coords);
-                            let (end_x, end_y) = haystack_idx_to_coord(m.end(), coords);
-                            results.push(SearchResult {
-                                start_x,
-                                start_y,
-                                end_x,
-                                end_y,
-                            });
-                        }
-                    }
-                }
+    fn is_mouse_grabbed(&self) -> bool {
+        self.terminal.lock().unwrap().is_mouse_grabbed()
+    }
+
+    fn get_progress(&self) -> Progress {
+        self.terminal.lock().unwrap().get_progress()
+    }
+
+    fn update_progress(&self) -> bool {
+        let owner = *self.progress_owner.borrow();
+        match owner {
+            Some(owner) if self.pty.borrow().master.process_group_leader() != Some(owner) => {
+                // The program that reported the progress is no longer in
+                // the foreground, so it can't clear the progress itself
+                self.terminal.lock().unwrap().clear_progress();
+                self.progress_owner.borrow_mut().take();
+                *self.last_progress.borrow_mut() = Progress::None;
+                true
             }
+            _ => false,
         }
+    }
 
-        for (idx, line) in screen.lines.iter().enumerate() {
-            let stable_row = screen.phys_to_stable_row_index(idx);
-
-            let mut wrapped = false;
-            for (grapheme_idx, cell) in line.visible_cells() {
-                coords.push(Coord {
-                    byte_idx: haystack.len(),
-                    grapheme_idx,
-                    stable_row,
-                });
-
-                let s = cell.str();
-                if let Pattern::CaseInSensitiveString(_) = &pattern {
-                    // normalize the case so we match everything lowercase
-                    haystack.push_str(&s.to_lowercase());
-                } else {
-                    haystack.push_str(cell.str());
-                }
-                wrapped = cell.attrs().wrapped();
-            }
+    fn get_status_text(&self) -> Option<String> {
+        self.terminal
+            .lock()
+            .unwrap()
+            .get_status_text()
+            .map(str::to_string)
+    }
 
-            if !wrapped {
-                if let Pattern::Regex(_) = &pattern {
-                    haystack.push('\n');
-                } else {
-                    collect_matches(&mut results, &pattern, &haystack, &coords);
-                    haystack.clear();
-                    coords.clear();
-                }
-            }
+    fn take_notifications(&self) -> Vec<ToastNotification> {
+        self.terminal.lock().unwrap().take_notifications()
+    }
+
+    fn take_resize_request(&self) -> Option<ResizeRequest> {
+        self.terminal.lock().unwrap().take_resize_request()
+    }
+
+    fn take_bell(&self) -> bool {
+        self.terminal.lock().unwrap().take_bell()
+    }
+
+    fn get_current_working_dir(&self) -> Option<Url> {
+        self.terminal.lock().unwrap().get_current_dir().cloned()
+    }
+
+    async fn search(&self, pattern: Pattern) -> anyhow::Result<Vec<SearchResult>> {
+        let snapshot = self.snapshots.latest();
+        let start = snapshot.stable_rows().start;
+        match Search::new(&*snapshot, &pattern, start, SearchDirection::Forward) {
+            Ok(search) => Ok(search.collect()),
+            // An incomplete regex matches nothing until the user
+            // finishes typing it
+            Err(_) => Ok(vec![]),
         }
+    }
 
-        collect_matches(&mut results, &pattern, &haystack, &coords);
-        Ok(results)
+    fn snapshot_buffer(&self) -> Option<Arc<SnapshotBuffer>> {
+        Some(Arc::clone(&self.snapshots))
     }
 }
 
 impl LocalTab {
+    /// Publishes a snapshot of the terminal after it has been changed
+    fn publish(&self) {
+        self.snapshots.publish(&mut self.terminal.lock().unwrap());
+    }
+
+    /// The terminal writes to its own handle on the pty, so failures
+    /// to write through it are reported to us as errors; treat those
+    /// that indicate that the pty is closed in the same way as the
+    /// writes that are made via `LocalPty`.
+    fn check_write(&self, result: Result<(), Error>) -> Result<(), Error> {
+        match result {
+            Err(err)
+                if err.chain().any(|e| {
+                    e.downcast_ref::<std::io::Error>()
+                        .map(is_pty_closed_error)
+                        .unwrap_or(false)
+                }) =>
+            {
+                self.pty.borrow_mut().mark_closed();
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// `tab_id` is allocated by the caller with `alloc_tab_id`, so
+    /// that it can be passed to the process in its environment.
+    /// `writer` must be the one that `terminal` writes its input to.
     pub fn new(
-        terminal: Terminal,
+        tab_id: TabId,
+        mut terminal: Terminal,
         process: Box<dyn Child>,
         pty: Box<dyn MasterPty>,
+        writer: ThreadedWriter,
         domain_id: DomainId,
     ) -> Self {
-        let tab_id = alloc_tab_id();
+        let watched = process
+            .process_id()
+            .map(|pid| childwatch::watch_process(tab_id, pid))
+            .unwrap_or(false);
+        let snapshots = Arc::new(SnapshotBuffer::new(&mut terminal));
+        let renderer = RefCell::new(SnapshotRenderer::new(&snapshots));
         Self {
             tab_id,
-            terminal: RefCell::new(terminal),
+            terminal: Arc::new(Mutex::new(terminal)),
+            snapshots,
+            renderer,
             process: RefCell::new(process),
-            pty: RefCell::new(pty),
+            pty: RefCell::new(LocalPty {
+                master: pty,
+                writer,
+                tab_id,
+                closed: false,
+            }),
             domain_id,
+            color_overrides: RefCell::new(TabColorOverrides::default()),
+            font_override: RefCell::new(TabFontOverride::default()),
+            progress_owner: RefCell::new(None),
+            last_progress: RefCell::new(Progress::None),
+            exit_status: RefCell::new(None),
+            watched,
         }
     }
 }
@@ -0,0 +1,150 @@
+//! Logging setup.
+//! Log records are passed through to pretty_env_logger as before, but
+//! recent records are also retained in a ring buffer so that they can
+//! be viewed from within the gui via the debug overlay, without having
+//! to restart wezterm with RUST_LOG set.
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// How many records to retain in the ring buffer
+const MAX_ENTRIES: usize = 2000;
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub when: SystemTime,
+    pub level: Level,
+    pub target: String,
+    pub msg: String,
+}
+
+struct Ring {
+    entries: VecDeque<LogEntry>,
+    level: LevelFilter,
+    /// The filter that applies to the stderr logger
+    inner_filter: LevelFilter,
+    /// Incremented each time the ring is changed, so that viewers
+    /// can tell when they need to re-render
+    generation: usize,
+}
+
+lazy_static::lazy_static! {
+    static ref RING: Mutex<Ring> = Mutex::new(Ring {
+        entries: VecDeque::new(),
+        level: LevelFilter::Info,
+        inner_filter: LevelFilter::Off,
+        generation: 0,
+    });
+    static ref WATCHERS: Mutex<HashMap<usize, Box<dyn Fn() + Send>>> = Mutex::new(HashMap::new());
+}
+
+static WATCHER_ID: AtomicUsize = AtomicUsize::new(0);
+
+struct Logger {
+    inner: Box<dyn Log>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata) || metadata.level() <= RING.lock().unwrap().level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+
+        let mut ring = RING.lock().unwrap();
+        if record.level() <= ring.level {
+            if ring.entries.len() >= MAX_ENTRIES {
+                ring.entries.pop_front();
+            }
+            ring.entries.push_back(LogEntry {
+                when: SystemTime::now(),
+                level: record.level(),
+                target: record.target().to_string(),
+                msg: record.args().to_string(),
+            });
+            ring.generation += 1;
+            drop(ring);
+            for watcher in WATCHERS.lock().unwrap().values() {
+                watcher();
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs the logger.  Must be called at most once.
+pub fn setup_logger() {
+    let mut builder = pretty_env_logger::formatted_timed_builder();
+    if let Ok(s) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&s);
+    }
+    let inner = builder.build();
+    let inner_filter = inner.filter();
+
+    if log::set_boxed_logger(Box::new(Logger {
+        inner: Box::new(inner),
+    }))
+    .is_ok()
+    {
+        let mut ring = RING.lock().unwrap();
+        ring.inner_filter = inner_filter;
+        log::set_max_level(inner_filter.max(ring.level));
+    }
+}
+
+/// Changes the level of the records that are retained in the ring
+/// buffer.  This doesn't affect what is written to stderr.
+pub fn set_ring_level(level: LevelFilter) {
+    let mut ring = RING.lock().unwrap();
+    ring.level = level;
+    log::set_max_level(ring.inner_filter.max(level));
+}
+
+pub fn ring_level() -> LevelFilter {
+    RING.lock().unwrap().level
+}
+
+/// Returns the generation of the ring buffer
+pub fn ring_generation() -> usize {
+    RING.lock().unwrap().generation
+}
+
+/// Returns a copy of the records in the ring buffer, oldest first
+pub fn get_entries() -> Vec<LogEntry> {
+    RING.lock().unwrap().entries.iter().cloned().collect()
+}
+
+pub fn clear_entries() {
+    let mut ring = RING.lock().unwrap();
+    ring.entries.clear();
+    ring.generation += 1;
+}
+
+/// Stops calling the function passed to `watch` once dropped
+pub struct Watch {
+    id: usize,
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        WATCHERS.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Arranges for `func` to be called each time a record is added to
+/// the ring buffer, until the returned Watch is dropped.  `func` is
+/// called on the thread that logged the record, so it should be quick
+/// and must not log anything itself.
+pub fn watch(func: Box<dyn Fn() + Send>) -> Watch {
+    let id = WATCHER_ID.fetch_add(1, Ordering::Relaxed);
+    WATCHERS.lock().unwrap().insert(id, func);
+    Watch { id }
+}
@@ -21,27 +21,35 @@ use tabout::{tabulate_output, Alignment, Column};
 // This module defines a macro, so it must be referenced before any other mods
 mod scripting;
 
+mod appearance;
 mod config;
 mod connui;
+mod crash;
 mod frontend;
 mod keyassignment;
 mod localtab;
+mod logging;
 mod markdown;
 mod mux;
 mod ratelim;
+mod replay;
 mod server;
 mod ssh;
+mod sshconfig;
 mod stats;
 mod termwiztermtab;
 mod update;
 
+use crate::config::{TabColorOverrides, TabEncoding, WindowConfigOverrides};
 use crate::frontend::activity::Activity;
 use crate::frontend::{front_end, FrontEndSelection};
 use crate::mux::domain::{Domain, LocalDomain};
+use crate::mux::tab::Tab;
 use crate::mux::Mux;
 use crate::server::client::{unix_connect_with_retry, Client};
 use crate::server::domain::{ClientDomain, ClientDomainConfig};
 use portable_pty::cmdbuilder::CommandBuilder;
+use portable_pty::PtySize;
 
 mod font;
 use crate::font::locator::FontLocatorSelection;
@@ -76,6 +84,21 @@ struct Opt {
     #[structopt(short = "n")]
     skip_config: bool,
 
+    /// Load the configuration from this file, rather than searching
+    /// for wezterm.lua
+    #[structopt(long = "config-file", parse(from_os_str))]
+    config_file: Option<PathBuf>,
+
+    /// Override a configuration option; the value is a lua expression.
+    /// May be repeated, eg: `--config font_size=16 --config 'color_scheme="Batman"'`
+    #[structopt(
+        long = "config",
+        value_name = "name=value",
+        parse(try_from_str = config::parse_config_override),
+        number_of_values = 1
+    )]
+    config_override: Vec<(String, String)>,
+
     #[structopt(subcommand)]
     cmd: Option<SubCommand>,
 }
@@ -120,17 +143,175 @@ struct StartCommand {
     daemonize: bool,
 
     /// Specify the current working directory for the initially
-    /// spawned program
+    /// spawned programs
     #[structopt(long = "cwd", parse(from_os_str))]
     cwd: Option<OsString>,
 
+    /// Override the initial_cols and initial_rows from the
+    /// configuration, using the form COLSxROWS; for example
+    /// `--window-size 120x40`
+    #[structopt(long = "window-size")]
+    window_size: Option<WindowSize>,
+
+    /// Open the tabs described by the JSON file LAYOUT, which has
+    /// the form `{"tabs": [{"args": ["htop"], "cwd": "/tmp"}]}`.
+    /// Tabs with no args run your shell.
+    #[structopt(long = "layout", parse(from_os_str))]
+    layout: Option<PathBuf>,
+
+    /// Record the output of the first tab to the asciinema cast
+    /// file RECORD, which can be played back with `wezterm replay`
+    #[structopt(long = "record", parse(from_os_str))]
+    record: Option<PathBuf>,
+
+    /// Override the font_size from the configuration for the
+    /// initially spawned window
+    #[structopt(long = "font-size")]
+    font_size: Option<f64>,
+
+    /// Override the color_scheme from the configuration for the
+    /// initially spawned window
+    #[structopt(long = "color-scheme")]
+    color_scheme: Option<String>,
+
+    /// Name the workspace of the initially spawned window; the
+    /// name can be shown in the title of the window and can be
+    /// used to switch to the window
+    #[structopt(long = "workspace")]
+    workspace: Option<String>,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm start -- bash -l` will spawn bash
     /// as if it were a login shell.
+    /// Use `--tab` to separate the programs to run in additional
+    /// tabs; for example: `wezterm start -- nvim foo.rs --tab -- htop`.
     #[structopt(parse(from_os_str))]
     prog: Vec<OsString>,
 }
 
+/// The initial size of the window, in cells
+#[derive(Debug, Clone, Copy)]
+struct WindowSize {
+    cols: u16,
+    rows: u16,
+}
+
+impl FromStr for WindowSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('x').collect();
+        if parts.len() == 2 {
+            let cols = parts[0].parse()?;
+            let rows = parts[1].parse()?;
+            if cols > 0 && rows > 0 {
+                return Ok(Self { cols, rows });
+            }
+        }
+        bail!(
+            "failed to parse window size from `{}`; expected COLSxROWS",
+            s
+        );
+    }
+}
+
+/// The contents of the file passed to `wezterm start --layout`
+#[derive(Debug, serde::Deserialize)]
+struct StartupLayout {
+    tabs: Vec<StartupTab>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct StartupTab {
+    /// The program to run and its arguments; if empty, the
+    /// default program is run
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+}
+
+impl StartupLayout {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("reading layout file {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("parsing layout file {}", path.display()))
+    }
+}
+
+/// Splits the arguments that follow `--` into the argument lists of
+/// the programs to run in each tab; `--tab`, optionally followed by
+/// `--`, starts a new list.
+fn split_tab_args(args: &[OsString]) -> Vec<Vec<OsString>> {
+    let mut tabs = vec![vec![]];
+    for arg in args {
+        if arg == "--tab" {
+            tabs.push(vec![]);
+            continue;
+        }
+        let current = tabs.last_mut().unwrap();
+        if arg == "--" && current.is_empty() && tabs.len() > 1 {
+            continue;
+        }
+        current.push(arg.clone());
+    }
+    tabs
+}
+
+impl StartCommand {
+    /// Returns the commands to run in each of the initial tabs;
+    /// None means that the default program is run with no
+    /// particular working directory
+    fn tab_commands(&self) -> anyhow::Result<Vec<Option<CommandBuilder>>> {
+        let mut tabs = vec![];
+
+        if let Some(layout) = &self.layout {
+            for tab in StartupLayout::load(layout)?.tabs {
+                let mut builder = if tab.args.is_empty() {
+                    CommandBuilder::new_default_prog()
+                } else {
+                    CommandBuilder::from_argv(tab.args.into_iter().map(Into::into).collect())
+                };
+                if let Some(cwd) = tab.cwd.map(OsString::from).or_else(|| self.cwd.clone()) {
+                    builder.cwd(cwd);
+                }
+                tabs.push(Some(builder));
+            }
+        }
+
+        if !self.prog.is_empty() || tabs.is_empty() {
+            for argv in split_tab_args(&self.prog) {
+                if argv.is_empty() && self.cwd.is_none() {
+                    tabs.push(None);
+                    continue;
+                }
+                let mut builder = if argv.is_empty() {
+                    CommandBuilder::new_default_prog()
+                } else {
+                    CommandBuilder::from_argv(argv)
+                };
+                if let Some(cwd) = &self.cwd {
+                    builder.cwd(cwd);
+                }
+                tabs.push(Some(builder));
+            }
+        }
+
+        Ok(tabs)
+    }
+
+    /// Returns the size at which to spawn the initial tabs
+    fn initial_size(&self, config: &config::ConfigHandle) -> PtySize {
+        let mut size = config.initial_size();
+        if let Some(window_size) = self.window_size {
+            size.cols = window_size.cols;
+            size.rows = window_size.rows;
+        }
+        size
+    }
+}
+
 #[derive(Debug, StructOpt, Clone)]
 enum SubCommand {
     #[structopt(name = "start", about = "Start a front-end")]
@@ -150,6 +331,30 @@ enum SubCommand {
 
     #[structopt(name = "imgcat", about = "Output an image to the terminal")]
     ImageCat(ImgCatCommand),
+
+    #[structopt(name = "replay", about = "Play back a recording of a tab")]
+    Replay(ReplayCommand),
+}
+
+#[derive(Debug, StructOpt, Clone)]
+struct ReplayCommand {
+    #[structopt(
+        long = "front-end",
+        possible_values = &FrontEndSelection::variants(),
+        case_insensitive = true
+    )]
+    front_end: Option<FrontEndSelection>,
+
+    /// Play back at this multiple of the original speed
+    #[structopt(long = "speed", default_value = "1.0")]
+    speed: f64,
+
+    /// The asciinema cast file to play back, as recorded by
+    /// `wezterm start --record` or the StartRecording action.
+    /// While playing, Space pauses, the arrow keys seek, `+` and `-`
+    /// change the speed and `q` closes the window.
+    #[structopt(parse(from_os_str))]
+    file: PathBuf,
 }
 
 #[derive(Debug, StructOpt, Clone)]
@@ -172,6 +377,37 @@ enum CliSubCommand {
 
     #[structopt(name = "tlscreds", about = "obtain tls credentials")]
     TlsCreds,
+
+    #[structopt(name = "get-text", about = "print the text of a tab")]
+    GetText {
+        /// The tab to read, as shown by `wezterm cli list`
+        #[structopt(long = "tab-id")]
+        tab_id: mux::tab::TabId,
+
+        /// Print the scrollback as well as the visible screen
+        #[structopt(long = "scrollback")]
+        scrollback: bool,
+
+        /// The first line to print, counting from 0 at the top of the
+        /// screen; negative values reach into the scrollback
+        #[structopt(long = "start-line", allow_hyphen_values = true)]
+        start_line: Option<isize>,
+
+        /// The line after the last line to print, counted in the same
+        /// way as --start-line.  Defaults to the bottom of the screen.
+        #[structopt(long = "end-line", allow_hyphen_values = true)]
+        end_line: Option<isize>,
+
+        /// Include the colors and styles of the text as escape sequences
+        #[structopt(long = "escapes")]
+        escapes: bool,
+
+        /// Print the output of the most recent command, starting from
+        /// its prompt.  This requires the shell to mark its prompts
+        /// using OSC 133.
+        #[structopt(long = "since-last-prompt")]
+        since_last_prompt: bool,
+    },
 }
 
 #[derive(Debug, StructOpt, Clone)]
@@ -185,12 +421,34 @@ struct SshCommand {
 
     /// Specifies the remote system using the form:
     /// `[username@]host[:port]`.
-    /// If `username@` is omitted, then your local $USER is used
-    /// instead.
-    /// If `:port` is omitted, then the standard ssh port (22) is
-    /// used instead.
+    /// `host` may be an alias defined in `~/.ssh/config`.
+    /// If `username@` is omitted, then the User from `~/.ssh/config`
+    /// or your local $USER is used instead.
+    /// If `:port` is omitted, then the Port from `~/.ssh/config` or
+    /// the standard ssh port (22) is used instead.
     user_at_host_and_port: SshParameters,
 
+    /// Connect to this port, in place of the one from
+    /// `[username@]host[:port]`
+    #[structopt(short = "p", long = "port")]
+    port: Option<u16>,
+
+    /// Authenticate using this private key file if the ssh agent
+    /// can't authenticate us
+    #[structopt(short = "i", long = "identity", parse(from_os_str))]
+    identity_file: Option<PathBuf>,
+
+    /// Connect via these jump hosts, specified as for the -J option
+    /// of ssh: `[username@]host[:port]`, separated by commas.
+    /// This requires the ssh program.
+    #[structopt(short = "J", long = "jump")]
+    proxy_jump: Option<String>,
+
+    /// Use the named color scheme for the session in place of
+    /// the color_scheme from the configuration
+    #[structopt(long = "color-scheme")]
+    color_scheme: Option<String>,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm ssh user@host -- bash -l` will spawn bash
     /// as if it were a login shell.
@@ -211,6 +469,20 @@ struct SerialCommand {
     #[structopt(long = "baud")]
     baud: Option<usize>,
 
+    /// Use the named color scheme for the session in place of
+    /// the color_scheme from the configuration
+    #[structopt(long = "color-scheme")]
+    color_scheme: Option<String>,
+
+    /// How the bytes exchanged with the device are translated to and
+    /// from characters.  The default is UTF-8.
+    #[structopt(
+        long = "encoding",
+        possible_values = &TabEncoding::variants(),
+        case_insensitive = true
+    )]
+    encoding: Option<TabEncoding>,
+
     /// Specifies the serial device name.
     /// On Windows systems this can be a name like `COM0`.
     /// On posix systems this will be something like `/dev/ttyUSB0`
@@ -318,10 +590,21 @@ pub fn running_under_wsl() -> bool {
 
 #[derive(Clone, Debug)]
 pub struct SshParameters {
-    pub username: String,
+    /// None if the username was omitted
+    pub username: Option<String>,
     pub host_and_port: String,
 }
 
+impl SshParameters {
+    /// Returns the username, defaulting to the local user
+    pub fn username(&self) -> anyhow::Result<String> {
+        match &self.username {
+            Some(username) => Ok(username.clone()),
+            None => username_from_env(),
+        }
+    }
+}
+
 fn username_from_env() -> anyhow::Result<String> {
     #[cfg(unix)]
     const USER: &str = "USER";
@@ -333,7 +616,10 @@ fn username_from_env() -> anyhow::Result<String> {
 
 impl Display for SshParameters {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}@{}", self.username, self.host_and_port)
+        match &self.username {
+            Some(username) => write!(f, "{}@{}", username, self.host_and_port),
+            None => write!(f, "{}", self.host_and_port),
+        }
     }
 }
 
@@ -345,12 +631,12 @@ impl FromStr for SshParameters {
 
         if parts.len() == 2 {
             Ok(Self {
-                username: parts[0].to_string(),
+                username: Some(parts[0].to_string()),
                 host_and_port: parts[1].to_string(),
             })
         } else if parts.len() == 1 {
             Ok(Self {
-                username: username_from_env()?,
+                username: None,
                 host_and_port: parts[0].to_string(),
             })
         } else {
@@ -362,7 +648,32 @@ impl FromStr for SshParameters {
 async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
     // Establish the connection; it may show UI for authentication
     let params = &opts.user_at_host_and_port;
-    let sess = ssh::async_ssh_connect(&params.host_and_port, &params.username).await?;
+    let (alias, port) = match params.host_and_port.rfind(':') {
+        Some(idx) => (
+            &params.host_and_port[..idx],
+            Some(params.host_and_port[idx + 1..].parse::<u16>()?),
+        ),
+        None => (params.host_and_port.as_str(), None),
+    };
+    // Apply ~/.ssh/config, giving precedence to the command line
+    let host_config = sshconfig::SshHostConfig::for_host(alias);
+    let host = host_config.hostname.as_deref().unwrap_or(alias);
+    let port = opts.port.or(port).or(host_config.port).unwrap_or(22);
+    let username = match (&params.username, &host_config.user) {
+        (Some(username), _) | (None, Some(username)) => username.clone(),
+        (None, None) => username_from_env()?,
+    };
+    let options = ssh::SshConnectOptions {
+        identity_file: opts
+            .identity_file
+            .clone()
+            .or_else(|| host_config.identity_file.clone()),
+        proxy_jump: opts
+            .proxy_jump
+            .clone()
+            .or_else(|| host_config.proxy_jump.clone()),
+    };
+    let sess = ssh::async_ssh_connect(&format!("{}:{}", host, port), &username, options).await?;
     // Now we have a connected session, set up the ssh domain and make it
     // the default domain
     let gui = front_end().unwrap();
@@ -390,8 +701,17 @@ async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
     let tab = domain
         .spawn(config.initial_size(), cmd, None, window_id)
         .await?;
+    tab.set_color_overrides(TabColorOverrides {
+        color_scheme: opts.color_scheme,
+        ..TabColorOverrides::default()
+    });
     let fontconfig = Rc::new(FontConfiguration::new());
-    gui.spawn_new_window(&fontconfig, &tab, window_id)?;
+    gui.spawn_new_window(
+        &fontconfig,
+        &tab,
+        window_id,
+        &WindowConfigOverrides::default(),
+    )?;
 
     Ok(())
 }
@@ -445,12 +765,52 @@ fn run_serial(config: config::ConfigHandle, opts: &SerialCommand) -> anyhow::Res
 
     let window_id = mux.new_empty_window();
     let tab = block_on(domain.spawn(config.initial_size(), None, None, window_id))?; // FIXME: blocking
-    gui.spawn_new_window(&fontconfig, &tab, window_id)?;
+    tab.set_color_overrides(TabColorOverrides {
+        color_scheme: opts.color_scheme.clone(),
+        ..TabColorOverrides::default()
+    });
+    if let Some(encoding) = opts.encoding {
+        tab.set_encoding(encoding.into());
+    }
+    gui.spawn_new_window(
+        &fontconfig,
+        &tab,
+        window_id,
+        &WindowConfigOverrides::default(),
+    )?;
 
     maybe_show_configuration_error_window();
     gui.run_forever()
 }
 
+fn run_replay(config: config::ConfigHandle, opts: &ReplayCommand) -> anyhow::Result<()> {
+    if !opts.speed.is_finite() || opts.speed <= 0.0 {
+        bail!("--speed must be greater than zero");
+    }
+    let cast = mux::recording::CastFile::load(&opts.file)?;
+    let speed = opts.speed;
+
+    let domain: Arc<dyn Domain> = Arc::new(LocalDomain::new("local")?);
+    let mux = Rc::new(mux::Mux::new(Some(domain.clone())));
+    Mux::set_mux(&mux);
+
+    let front_end = opts.front_end.unwrap_or(config.front_end);
+    let gui = front_end.try_new()?;
+
+    let activity = Activity::new();
+    promise::spawn::spawn(async move {
+        let (width, height) = (cast.width, cast.height);
+        if let Err(err) =
+            termwiztermtab::run(width, height, move |term| replay::replay(term, cast, speed)).await
+        {
+            terminate_with_error(err);
+        }
+        drop(activity);
+    });
+
+    gui.run_forever()
+}
+
 fn client_domains(config: &config::ConfigHandle) -> Vec<ClientDomainConfig> {
     let mut domains = vec![];
     for unix_dom in &config.unix_domains {
@@ -495,7 +855,13 @@ fn run_mux_client(config: config::ConfigHandle, opts: &ConnectCommand) -> anyhow
 
     let activity = Activity::new();
     promise::spawn::spawn(async {
-        if let Err(err) = spawn_tab_in_default_domain_if_mux_is_empty(cmd).await {
+        if let Err(err) = spawn_tab_in_default_domain_if_mux_is_empty(
+            vec![cmd],
+            config::configuration().initial_size(),
+            WindowConfigOverrides::default(),
+        )
+        .await
+        {
             terminate_with_error(err);
         }
         drop(activity);
@@ -504,37 +870,50 @@ fn run_mux_client(config: config::ConfigHandle, opts: &ConnectCommand) -> anyhow
     gui.run_forever()
 }
 
+/// Spawns a window with a tab for each of `tabs` in the default
+/// domain, unless attaching to it produced some tabs.
+/// An entry of None in `tabs` runs the default program.
+/// Returns the first of the tabs, if any were spawned.
 async fn spawn_tab_in_default_domain_if_mux_is_empty(
-    cmd: Option<CommandBuilder>,
-) -> anyhow::Result<()> {
+    tabs: Vec<Option<CommandBuilder>>,
+    size: PtySize,
+    overrides: WindowConfigOverrides,
+) -> anyhow::Result<Option<Rc<dyn Tab>>> {
     let mux = Mux::get().unwrap();
 
     if !mux.is_empty() {
-        return Ok(());
+        return Ok(None);
     }
     let domain = mux.default_domain();
     domain.attach().await?;
 
     if !mux.is_empty() {
-        return Ok(());
+        return Ok(None);
     }
 
-    let config = config::configuration();
     let window_id = mux.new_empty_window();
-    let tab = mux
-        .default_domain()
-        .spawn(config.initial_size(), cmd, None, window_id)
-        .await?;
+    let mut first_tab = None;
+    for cmd in tabs {
+        let tab = mux
+            .default_domain()
+            .spawn(size, cmd, None, window_id)
+            .await?;
+        first_tab.get_or_insert(tab);
+    }
+    let tab = first_tab.ok_or_else(|| anyhow!("no tabs were specified"))?;
     let fontconfig = Rc::new(FontConfiguration::new());
     front_end()
         .unwrap()
-        .spawn_new_window(&fontconfig, &tab, window_id)?;
-    Ok(())
+        .spawn_new_window(&fontconfig, &tab, window_id, &overrides)?;
+    Ok(Some(tab))
 }
 
 async fn async_run_terminal_gui(
-    cmd: Option<CommandBuilder>,
+    tabs: Vec<Option<CommandBuilder>>,
+    size: PtySize,
     do_auto_connect: bool,
+    overrides: WindowConfigOverrides,
+    record: Option<PathBuf>,
 ) -> anyhow::Result<()> {
     let mux = Mux::get().unwrap();
 
@@ -555,10 +934,19 @@ async fn async_run_terminal_gui(
         }
     }
 
-    spawn_tab_in_default_domain_if_mux_is_empty(cmd).await
+    let tab = spawn_tab_in_default_domain_if_mux_is_empty(tabs, size, overrides).await?;
+    if let (Some(tab), Some(path)) = (tab, record) {
+        mux::recording::start_recording(tab.tab_id(), &path, size.cols, size.rows)?;
+    }
+    Ok(())
 }
 
 fn run_terminal_gui(config: config::ConfigHandle, opts: StartCommand) -> anyhow::Result<()> {
+    // Do this before daemonizing changes the working directory, as
+    // the layout file may be specified relative to it
+    let tabs = opts.tab_commands()?;
+    let size = opts.initial_size(&config);
+
     #[cfg(unix)]
     {
         if opts.daemonize {
@@ -620,20 +1008,15 @@ fn run_terminal_gui(config: config::ConfigHandle, opts: StartCommand) -> anyhow:
         .unwrap_or(config.font_rasterizer)
         .set_default();
 
-    let need_builder = !opts.prog.is_empty() || opts.cwd.is_some();
-
-    let cmd = if need_builder {
-        let mut builder = if opts.prog.is_empty() {
-            CommandBuilder::new_default_prog()
-        } else {
-            CommandBuilder::from_argv(opts.prog)
-        };
-        if let Some(cwd) = opts.cwd {
-            builder.cwd(cwd);
+    if let Some(size) = opts.font_size {
+        if !size.is_finite() || size <= 0.0 {
+            bail!("--font-size must be greater than zero");
         }
-        Some(builder)
-    } else {
-        None
+    }
+    let overrides = WindowConfigOverrides {
+        font_size: opts.font_size,
+        color_scheme: opts.color_scheme.clone(),
+        workspace: opts.workspace.clone(),
     };
 
     let domain: Arc<dyn Domain> = Arc::new(LocalDomain::new("local")?);
@@ -647,7 +1030,9 @@ fn run_terminal_gui(config: config::ConfigHandle, opts: StartCommand) -> anyhow:
         front_end_selection != FrontEndSelection::MuxServer && !opts.no_auto_connect;
 
     promise::spawn::spawn(async move {
-        if let Err(err) = async_run_terminal_gui(cmd, do_auto_connect).await {
+        if let Err(err) =
+            async_run_terminal_gui(tabs, size, do_auto_connect, overrides, opts.record).await
+        {
             terminate_with_error(err);
         }
         drop(activity);
@@ -692,6 +1077,45 @@ fn toast_notification(title: &str, message: &str) {
     }
 }
 
+/// Shows a notification, calling `on_click` from some other thread if
+/// the user clicks on it.  Clicks can only be detected on systems that
+/// use the freedesktop notification protocol; elsewhere this is
+/// equivalent to `toast_notification`.
+fn toast_notification_with_click<F: FnOnce() + Send + 'static>(
+    title: &str,
+    message: &str,
+    on_click: F,
+) {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        let title = title.to_owned();
+        let message = message.to_owned();
+
+        // Waiting for the action blocks until the notification
+        // is dismissed, so do that in its own thread
+        std::thread::spawn(move || {
+            if let Ok(handle) = notify_rust::Notification::new()
+                .summary(&title)
+                .body(&message)
+                .action("default", "Show")
+                .show()
+            {
+                handle.wait_for_action(|action| {
+                    if action == "default" {
+                        on_click();
+                    }
+                });
+            }
+        });
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        drop(on_click);
+        toast_notification(title, message);
+    }
+}
+
 fn fatal_toast_notification(title: &str, message: &str) {
     toast_notification(title, message);
     // We need a short delay otherwise the notification
@@ -700,16 +1124,6 @@ fn fatal_toast_notification(title: &str, message: &str) {
     std::thread::sleep(std::time::Duration::new(2, 0));
 }
 
-fn notify_on_panic() {
-    let default_hook = std::panic::take_hook();
-    std::panic::set_hook(Box::new(move |info| {
-        if let Some(s) = info.payload().downcast_ref::<&str>() {
-            fatal_toast_notification("Wezterm panic", s);
-        }
-        default_hook(info);
-    }));
-}
-
 fn terminate_with_error_message(err: &str) -> ! {
     log::error!("{}; terminating", err);
     fatal_toast_notification("Wezterm Error", &err);
@@ -721,7 +1135,7 @@ fn terminate_with_error(err: anyhow::Error) -> ! {
 }
 
 fn main() {
-    notify_on_panic();
+    crash::install_panic_hook();
     if let Err(e) = run() {
         terminate_with_error(e);
     }
@@ -874,19 +1288,26 @@ fn run() -> anyhow::Result<()> {
             std::env::set_current_dir(config::HOME_DIR.as_path())?;
         }
     };
-    pretty_env_logger::init_timed();
+    logging::setup_logger();
     stats::Stats::init()?;
     let _saver = umask::UmaskSaver::new();
 
     let opts = Opt::from_args();
-    if !opts.skip_config {
-        config::reload();
-    }
+    config::set_config_sources(
+        opts.config_file.clone(),
+        opts.skip_config,
+        opts.config_override.clone(),
+    );
+    config::reload();
     let config = crate::config::configuration();
 
     #[cfg(target_os = "macos")]
     {
         window::os::macos::use_ime(config.use_ime);
+        window::os::macos::set_option_composes(
+            config.send_composed_key_when_left_alt_is_pressed,
+            config.send_composed_key_when_right_alt_is_pressed,
+        );
     }
 
     match opts
@@ -903,6 +1324,7 @@ fn run() -> anyhow::Result<()> {
         SubCommand::Serial(serial) => run_serial(config, &serial),
         SubCommand::Connect(connect) => run_mux_client(config, &connect),
         SubCommand::ImageCat(cmd) => cmd.run(),
+        SubCommand::Replay(replay) => run_replay(config, &replay),
         SubCommand::Cli(cli) => {
             // Start a front end so that the futures executor is running
             let front_end = FrontEndSelection::Null.try_new()?;
@@ -921,6 +1343,10 @@ fn run() -> anyhow::Result<()> {
                             name: "TABID".to_string(),
                             alignment: Alignment::Right,
                         },
+                        Column {
+                            name: "WORKSPACE".to_string(),
+                            alignment: Alignment::Left,
+                        },
                         Column {
                             name: "SIZE".to_string(),
                             alignment: Alignment::Left,
@@ -940,6 +1366,7 @@ fn run() -> anyhow::Result<()> {
                         data.push(vec![
                             entry.window_id.to_string(),
                             entry.tab_id.to_string(),
+                            entry.workspace.clone().unwrap_or_else(String::new),
                             format!("{}x{}", entry.size.cols, entry.size.rows),
                             entry.title.clone(),
                             entry
@@ -990,6 +1417,30 @@ fn run() -> anyhow::Result<()> {
                     crate::server::codec::Pdu::GetTlsCredsResponse(creds)
                         .encode(std::io::stdout().lock(), 0)?;
                 }
+                CliSubCommand::GetText {
+                    tab_id,
+                    scrollback,
+                    start_line,
+                    end_line,
+                    escapes,
+                    since_last_prompt,
+                } => {
+                    let response = block_on(client.get_text(crate::server::codec::GetText {
+                        tab_id,
+                        options: mux::tab::ExportTextOptions {
+                            scrollback,
+                            start_line,
+                            end_line,
+                            escapes,
+                            since_last_prompt,
+                        },
+                    }))?;
+                    let mut stdout = std::io::stdout();
+                    stdout.write_all(response.text.as_bytes())?;
+                    if !response.text.is_empty() {
+                        stdout.write_all(b"\n")?;
+                    }
+                }
             }
             Ok(())
         }
@@ -1015,3 +1466,65 @@ fn consume_stream_then_exit_process<F: Read, T: Write>(from_stream: F, to_stream
     std::thread::sleep(std::time::Duration::new(2, 0));
     std::process::exit(0);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn window_size() {
+        let size: WindowSize = "120x40".parse().unwrap();
+        assert_eq!((size.cols, size.rows), (120, 40));
+        assert!("120".parse::<WindowSize>().is_err());
+        assert!("120x40x2".parse::<WindowSize>().is_err());
+        assert!("0x40".parse::<WindowSize>().is_err());
+        assert!("120x".parse::<WindowSize>().is_err());
+        assert!("-1x40".parse::<WindowSize>().is_err());
+        assert!("120X40".parse::<WindowSize>().is_err());
+    }
+
+    #[test]
+    fn tab_args() {
+        let args = |args: &[&str]| -> Vec<OsString> { args.iter().map(OsString::from).collect() };
+        assert_eq!(split_tab_args(&[]), vec![args(&[])]);
+        assert_eq!(
+            split_tab_args(&args(&["nvim", "foo.rs", "--tab", "--", "htop"])),
+            vec![args(&["nvim", "foo.rs"]), args(&["htop"])]
+        );
+        // The `--` after `--tab` is optional, and any others are
+        // arguments of the program
+        assert_eq!(
+            split_tab_args(&args(&[
+                "htop", "--tab", "ls", "--", "-l", "--tab", "--", "echo", "--"
+            ])),
+            vec![
+                args(&["htop"]),
+                args(&["ls", "--", "-l"]),
+                args(&["echo", "--"])
+            ]
+        );
+        // An empty list runs the default program
+        assert_eq!(
+            split_tab_args(&args(&["--tab", "htop"])),
+            vec![args(&[]), args(&["htop"])]
+        );
+    }
+
+    #[test]
+    fn startup_layout() {
+        let layout: StartupLayout = serde_json::from_str(
+            r#"{"tabs": [{"args": ["htop"], "cwd": "/tmp"}, {}, {"cwd": "/"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(layout.tabs.len(), 3);
+        assert_eq!(layout.tabs[0].args, vec!["htop".to_string()]);
+        assert_eq!(layout.tabs[0].cwd, Some(PathBuf::from("/tmp")));
+        assert!(layout.tabs[1].args.is_empty());
+        assert_eq!(layout.tabs[1].cwd, None);
+        assert!(layout.tabs[2].args.is_empty());
+        assert_eq!(layout.tabs[2].cwd, Some(PathBuf::from("/")));
+
+        assert!(serde_json::from_str::<StartupLayout>(r#"{"tabs": [{"args": "htop"}]}"#).is_err());
+        assert!(serde_json::from_str::<StartupLayout>("{}").is_err());
+    }
+}
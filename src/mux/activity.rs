@@ -0,0 +1,109 @@
+//! Tracks when each tab last produced output, and whether its pty has
+//! been closed, so that the tab navigator can show which tabs are busy.
+//! The pty reader thread records each chunk that it reads with a single
+//! relaxed atomic store, which costs nothing next to the read itself;
+//! the times are kept as milliseconds since the start of the process so
+//! that they fit in an atomic.
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+lazy_static::lazy_static! {
+    static ref EPOCH: Instant = Instant::now();
+}
+
+/// Output this recent counts as the tab being busy
+pub const BUSY_WINDOW: Duration = Duration::from_secs(1);
+
+/// The activity of a tab; shared between the thread that reads its pty
+/// and anything that wants to display it
+#[derive(Default)]
+pub struct TabActivity {
+    /// One more than the milliseconds from `EPOCH` to the most recent
+    /// output, or zero if there hasn't been any
+    last_output: AtomicU64,
+    closed: AtomicBool,
+}
+
+impl TabActivity {
+    /// Records that output was read just now
+    pub fn output_read(&self) {
+        let millis = EPOCH.elapsed().as_millis() as u64 + 1;
+        self.last_output.store(millis, Ordering::Relaxed);
+    }
+
+    /// Records that the pty has been closed
+    pub fn pty_closed(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the time of the most recent output
+    pub fn last_output(&self) -> Option<Instant> {
+        match self.last_output.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Some(*EPOCH + Duration::from_millis(millis - 1)),
+        }
+    }
+
+    /// Returns how long ago the most recent output was
+    pub fn output_age(&self) -> Option<Duration> {
+        self.last_output().map(|when| when.elapsed())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+}
+
+/// Formats `age`, the time since a tab last produced output, as a
+/// short string such as "2s" or "5m".  Tabs that have never produced
+/// output, or not for a day, are "idle".
+pub fn format_age(age: Option<Duration>) -> String {
+    let secs = match age {
+        Some(age) => age.as_secs(),
+        None => return "idle".to_string(),
+    };
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 60 * 60 {
+        format!("{}m", secs / 60)
+    } else if secs < 24 * 60 * 60 {
+        format!("{}h", secs / (60 * 60))
+    } else {
+        "idle".to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ages() {
+        assert_eq!(format_age(None), "idle");
+        assert_eq!(format_age(Some(Duration::from_millis(300))), "0s");
+        assert_eq!(format_age(Some(Duration::from_secs(2))), "2s");
+        assert_eq!(format_age(Some(Duration::from_secs(5 * 60 + 59))), "5m");
+        assert_eq!(format_age(Some(Duration::from_secs(3 * 60 * 60))), "3h");
+        assert_eq!(
+            format_age(Some(Duration::from_secs(2 * 24 * 60 * 60))),
+            "idle"
+        );
+    }
+
+    #[test]
+    fn activity() {
+        let activity = TabActivity::default();
+        assert_eq!(activity.last_output(), None);
+        assert!(!activity.is_closed());
+
+        let before = Instant::now();
+        activity.output_read();
+        let when = activity.last_output().unwrap();
+        // The time is only kept to the millisecond
+        assert!(when + Duration::from_millis(1) >= before);
+        assert!(when <= Instant::now());
+
+        activity.pty_closed();
+        assert!(activity.is_closed());
+    }
+}
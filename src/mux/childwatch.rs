@@ -0,0 +1,176 @@
+//! Learns of the exit of the processes spawned into local tabs
+//! without having to poll them.
+//! On unix, a SIGCHLD handler writes to a self-pipe that is monitored
+//! by a thread, which asks the mux to reap its tabs.  On Windows, a
+//! thread waits on the handle of each process.  Either way, the exit
+//! status is collected on the main thread, where it is available from
+//! `Tab::exit_status`, and the affected tabs are removed straight away.
+use crate::mux::tab::TabId;
+use crate::mux::Mux;
+
+/// Prepares to be notified of the exit of child processes.  This is
+/// called when the mux is created, so that a child that exits before
+/// `watch_process` is called for it isn't missed.
+pub fn install() {
+    imp::install()
+}
+
+/// Arranges for the mux to be told when the process `pid`, which
+/// belongs to `tab_id`, exits.  Returns false if that isn't possible,
+/// in which case the caller needs to poll the process instead.
+pub fn watch_process(tab_id: TabId, pid: u32) -> bool {
+    imp::watch_process(tab_id, pid)
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::*;
+    use filedescriptor::{FileDescriptor, Pipe};
+    use std::io::Read;
+    use std::os::unix::io::IntoRawFd;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    /// The write end of the self-pipe, for use by the signal handler
+    static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn errno() -> *mut libc::c_int {
+        libc::__errno_location()
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    unsafe fn errno() -> *mut libc::c_int {
+        libc::__error()
+    }
+
+    #[cfg(any(target_os = "openbsd", target_os = "netbsd"))]
+    unsafe fn errno() -> *mut libc::c_int {
+        libc::__errno()
+    }
+
+    extern "C" fn on_sigchld(_signal: libc::c_int) {
+        let fd = WRITE_FD.load(Ordering::Relaxed);
+        if fd != -1 {
+            // The pipe is non-blocking; if it is full then a wakeup
+            // is already pending, and that is all that we need.
+            // The write may clobber errno, which the code that we
+            // interrupted could be about to examine.
+            unsafe {
+                let saved_errno = *errno();
+                libc::write(fd, b"x".as_ptr() as *const _, 1);
+                *errno() = saved_errno;
+            }
+        }
+    }
+
+    fn install_handler() -> anyhow::Result<()> {
+        let mut pipe = Pipe::new()?;
+        pipe.write.set_non_blocking(true)?;
+        WRITE_FD.store(pipe.write.into_raw_fd(), Ordering::Relaxed);
+
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_sigchld as usize;
+            action.sa_flags = libc::SA_RESTART | libc::SA_NOCLDSTOP;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(libc::SIGCHLD, &action, std::ptr::null_mut()) != 0 {
+                anyhow::bail!(
+                    "failed to install SIGCHLD handler: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+
+        let mut read: FileDescriptor = pipe.read;
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            loop {
+                match read.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        promise::spawn::spawn_into_main_thread(async move {
+                            // There can't be any tabs to reap until
+                            // the mux has been set up
+                            if let Some(mux) = Mux::get() {
+                                mux.reap_children();
+                            }
+                        });
+                    }
+                    Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(err) => {
+                        log::error!("error reading SIGCHLD pipe: {}", err);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    lazy_static::lazy_static! {
+        static ref INSTALLED: bool = match install_handler() {
+            Ok(_) => true,
+            Err(err) => {
+                log::error!("{:#}; will poll for child exit instead", err);
+                false
+            }
+        };
+    }
+
+    pub fn install() {
+        lazy_static::initialize(&INSTALLED);
+    }
+
+    pub fn watch_process(_tab_id: TabId, _pid: u32) -> bool {
+        // SIGCHLD covers all of our children; we don't need to do
+        // anything specific to this one
+        *INSTALLED
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::*;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::OpenProcess;
+    use winapi::um::synchapi::WaitForSingleObject;
+    use winapi::um::winbase::INFINITE;
+    use winapi::um::winnt::SYNCHRONIZE;
+
+    /// The process handle, sent to the waiter thread
+    struct Handle(winapi::um::winnt::HANDLE);
+    unsafe impl Send for Handle {}
+
+    pub fn install() {
+        // Each process is waited on by handle, which works whenever
+        // the process exits, so there is nothing to set up
+    }
+
+    pub fn watch_process(tab_id: TabId, pid: u32) -> bool {
+        let handle = unsafe { OpenProcess(SYNCHRONIZE, 0, pid) };
+        if handle.is_null() {
+            log::error!(
+                "failed to open process {} for tab {}: {}; will poll for its exit instead",
+                pid,
+                tab_id,
+                std::io::Error::last_os_error()
+            );
+            return false;
+        }
+        let handle = Handle(handle);
+
+        std::thread::spawn(move || {
+            unsafe {
+                WaitForSingleObject(handle.0, INFINITE);
+                CloseHandle(handle.0);
+            }
+            promise::spawn::spawn_into_main_thread(async move {
+                let mux = Mux::get().unwrap();
+                mux.reap_children();
+            });
+        });
+
+        true
+    }
+}
@@ -7,7 +7,7 @@
 
 use crate::config::configuration;
 use crate::localtab::LocalTab;
-use crate::mux::tab::Tab;
+use crate::mux::tab::{alloc_tab_id, Tab};
 use crate::mux::window::WindowId;
 use crate::mux::Mux;
 use anyhow::{bail, Error};
@@ -111,6 +111,10 @@ impl Domain for LocalDomain {
             }
             None => config.build_prog(None)?,
         };
+        // Lets scripts find their tab, eg: to target `wezterm cli`
+        let tab_id = alloc_tab_id();
+        cmd.env("WEZTERM_TAB", tab_id.to_string());
+        cmd.env("WEZTERM_WINDOW", window.to_string());
         if let Some(dir) = command_dir {
             // I'm not normally a fan of existence checking, but not checking here
             // can be painful; in the case where a tab is local but has connected
@@ -124,7 +128,9 @@ impl Domain for LocalDomain {
         let child = pair.slave.spawn_command(cmd)?;
         info!("spawned: {:?}", child);
 
-        let writer = pair.master.try_clone_writer()?;
+        // Input is written from a separate thread so that it doesn't
+        // block the main thread when the child isn't reading it
+        let writer = crate::mux::threaded_pty_writer(pair.master.try_clone_writer()?);
 
         let terminal = wezterm_term::Terminal::new(
             size.rows as usize,
@@ -134,11 +140,18 @@ impl Domain for LocalDomain {
             std::sync::Arc::new(crate::config::TermConfig {}),
             "WezTerm",
             crate::wezterm_version(),
-            Box::new(writer),
+            Box::new(writer.clone()),
         );
 
         let mux = Mux::get().unwrap();
-        let tab: Rc<dyn Tab> = Rc::new(LocalTab::new(terminal, child, pair.master, self.id));
+        let tab: Rc<dyn Tab> = Rc::new(LocalTab::new(
+            tab_id,
+            terminal,
+            child,
+            pair.master,
+            writer,
+            self.id,
+        ));
 
         mux.add_tab(&tab)?;
         mux.add_tab_to_window(&tab, window)?;
@@ -0,0 +1,322 @@
+//! Measures the latency of typing: the time from receiving a key press
+//! until it is written to the pty, until the first output that follows
+//! it is read back from the pty, and until a frame that includes that
+//! output has been painted.  Nothing is measured unless something, such
+//! as the latency HUD, has asked for it with `start_measuring`; even
+//! then the bookkeeping is limited to a few timestamps and fixed size
+//! rings of samples per tab, so that it doesn't disturb what it measures.
+//! Only one key press per tab is in flight at a time: keys that are
+//! typed while one is being measured are not measured themselves.
+use crate::mux::tab::TabId;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How many of the most recent samples the percentiles are computed from
+const NUM_SAMPLES: usize = 256;
+/// A key press that hasn't been echoed after this long is assumed to
+/// produce no output, and is forgotten
+const ECHO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The number of users that want latency to be measured
+static MEASURING: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static::lazy_static! {
+    static ref TABS: Mutex<HashMap<TabId, TabLatency>> = Mutex::new(HashMap::new());
+}
+
+/// A ring of the most recent samples, in microseconds
+struct Samples {
+    micros: [u32; NUM_SAMPLES],
+    len: usize,
+    next: usize,
+}
+
+impl Default for Samples {
+    fn default() -> Self {
+        Self {
+            micros: [0; NUM_SAMPLES],
+            len: 0,
+            next: 0,
+        }
+    }
+}
+
+impl Samples {
+    fn push(&mut self, latency: Duration) {
+        self.micros[self.next] = latency.as_micros().min(u32::max_value() as u128) as u32;
+        self.next = (self.next + 1) % NUM_SAMPLES;
+        self.len = (self.len + 1).min(NUM_SAMPLES);
+    }
+
+    /// Returns the 50th and 99th percentiles, using the nearest rank
+    fn percentiles(&self) -> Option<(Duration, Duration)> {
+        if self.len == 0 {
+            return None;
+        }
+        // The copy lives on the stack, so this doesn't allocate
+        let mut sorted = self.micros;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable();
+        let rank = |p: usize| {
+            let idx = ((self.len * p + 99) / 100).max(1) - 1;
+            Duration::from_micros(u64::from(sorted[idx]))
+        };
+        Some((rank(50), rank(99)))
+    }
+}
+
+/// The 50th and 99th percentiles of the latency of each stage,
+/// measured from the key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub write: (Duration, Duration),
+    pub echo: (Duration, Duration),
+    pub frame: (Duration, Duration),
+    /// How many key presses the percentiles were computed from
+    pub samples: usize,
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.1}", d.as_secs_f64() * 1000.)
+}
+
+impl LatencyStats {
+    /// Describes the time until the frame was painted, which is what
+    /// the typist experiences
+    pub fn brief(&self) -> String {
+        format!(
+            "p50 {}ms p99 {}ms",
+            millis(self.frame.0),
+            millis(self.frame.1)
+        )
+    }
+}
+
+impl std::fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "p50/p99 ms: write {}/{} echo {}/{} frame {}/{} ({} keys)",
+            millis(self.write.0),
+            millis(self.write.1),
+            millis(self.echo.0),
+            millis(self.echo.1),
+            millis(self.frame.0),
+            millis(self.frame.1),
+            self.samples
+        )
+    }
+}
+
+#[derive(Default)]
+struct TabLatency {
+    /// When the key press that is being measured was received
+    key: Option<Instant>,
+    written: Option<Instant>,
+    echoed: Option<Instant>,
+    /// Set once the output has been applied to the terminal model, so
+    /// that a frame painted between reading and applying the output
+    /// isn't counted
+    applied: bool,
+    write: Samples,
+    echo: Samples,
+    frame: Samples,
+}
+
+impl TabLatency {
+    /// Forgets the key press that is being measured
+    fn reset(&mut self) {
+        self.key = None;
+        self.written = None;
+        self.echoed = None;
+        self.applied = false;
+    }
+
+    /// Returns true if a key press is being measured and hasn't yet
+    /// reached ECHO_TIMEOUT
+    fn key_is_recent(&self, now: Instant) -> bool {
+        self.key.map_or(false, |key| {
+            now.saturating_duration_since(key) < ECHO_TIMEOUT
+        })
+    }
+
+    fn key_received(&mut self, now: Instant) {
+        if self.key_is_recent(now) {
+            return;
+        }
+        self.reset();
+        self.key = Some(now);
+    }
+
+    fn pty_written(&mut self, now: Instant) {
+        if self.key.is_some() && self.written.is_none() {
+            self.written = Some(now);
+        }
+    }
+
+    fn output_read(&mut self, now: Instant) {
+        if !self.key_is_recent(now) {
+            self.reset();
+        } else if self.written.is_some() && self.echoed.is_none() {
+            self.echoed = Some(now);
+        }
+    }
+
+    fn output_applied(&mut self) {
+        if self.echoed.is_some() {
+            self.applied = true;
+        }
+    }
+
+    /// Records the samples for the key press if a frame that shows its
+    /// echo has now been painted, returning true if it has
+    fn frame_presented(&mut self, now: Instant) -> bool {
+        if !self.applied {
+            return false;
+        }
+        if let (Some(key), Some(written), Some(echoed)) = (self.key, self.written, self.echoed) {
+            self.write.push(written.saturating_duration_since(key));
+            self.echo.push(echoed.saturating_duration_since(key));
+            self.frame.push(now.saturating_duration_since(key));
+        }
+        self.reset();
+        true
+    }
+
+    fn stats(&self) -> Option<LatencyStats> {
+        Some(LatencyStats {
+            write: self.write.percentiles()?,
+            echo: self.echo.percentiles()?,
+            frame: self.frame.percentiles()?,
+            samples: self.frame.len,
+        })
+    }
+}
+
+/// Returns true if latency is being measured
+pub fn is_measuring() -> bool {
+    MEASURING.load(Ordering::Relaxed) > 0
+}
+
+/// Starts measuring latency, until a matching call to `stop_measuring`
+pub fn start_measuring() {
+    MEASURING.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn stop_measuring() {
+    if MEASURING.fetch_sub(1, Ordering::Relaxed) == 1 {
+        TABS.lock().unwrap().clear();
+    }
+}
+
+/// Applies `func` to the bookkeeping of `tab_id` while measuring
+fn with_tab<R: Default>(tab_id: TabId, func: impl FnOnce(&mut TabLatency) -> R) -> R {
+    if !is_measuring() {
+        return R::default();
+    }
+    let mut tabs = TABS.lock().unwrap();
+    func(tabs.entry(tab_id).or_default())
+}
+
+/// Called when a key press that is to be sent to the tab is received
+pub fn key_received(tab_id: TabId, when: Instant) {
+    with_tab(tab_id, |tab| tab.key_received(when))
+}
+
+/// Called when input has been written to the pty of the tab
+pub fn pty_written(tab_id: TabId) {
+    with_tab(tab_id, |tab| tab.pty_written(Instant::now()))
+}
+
+/// Called on the thread that reads the pty when output has been read
+pub fn output_read(tab_id: TabId) {
+    with_tab(tab_id, |tab| tab.output_read(Instant::now()))
+}
+
+/// Called when the output has been applied to the terminal model
+pub fn output_applied(tab_id: TabId) {
+    with_tab(tab_id, |tab| tab.output_applied())
+}
+
+/// Called when a frame showing the tab has been painted.  Returns true
+/// if that completed the measurement of a key press.
+pub fn frame_presented(tab_id: TabId) -> bool {
+    with_tab(tab_id, |tab| tab.frame_presented(Instant::now()))
+}
+
+/// Returns the percentiles for the tab, or None if no key presses
+/// have been measured
+pub fn stats(tab_id: TabId) -> Option<LatencyStats> {
+    with_tab(tab_id, |tab| tab.stats())
+}
+
+pub fn forget_tab(tab_id: TabId) {
+    if is_measuring() {
+        TABS.lock().unwrap().remove(&tab_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let mut samples = Samples::default();
+        assert_eq!(samples.percentiles(), None);
+        for ms in 1..=100 {
+            samples.push(Duration::from_millis(ms));
+        }
+        assert_eq!(
+            samples.percentiles(),
+            Some((Duration::from_millis(50), Duration::from_millis(99)))
+        );
+
+        // Only the most recent samples are retained
+        for _ in 0..NUM_SAMPLES {
+            samples.push(Duration::from_millis(7));
+        }
+        assert_eq!(
+            samples.percentiles(),
+            Some((Duration::from_millis(7), Duration::from_millis(7)))
+        );
+    }
+
+    #[test]
+    fn stages() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut tab = TabLatency::default();
+
+        tab.key_received(at(0));
+        tab.pty_written(at(1));
+        // A key typed before the first is echoed isn't measured
+        tab.key_received(at(2));
+        tab.output_read(at(5));
+        // The output hasn't been applied, so this frame doesn't show it
+        assert!(!tab.frame_presented(at(6)));
+        tab.output_applied();
+        assert!(tab.frame_presented(at(10)));
+
+        let stats = tab.stats().unwrap();
+        assert_eq!(stats.write.0, Duration::from_millis(1));
+        assert_eq!(stats.echo.0, Duration::from_millis(5));
+        assert_eq!(stats.frame.0, Duration::from_millis(10));
+        assert_eq!(stats.samples, 1);
+
+        // Output that isn't preceded by a key press isn't measured
+        tab.output_read(at(20));
+        tab.output_applied();
+        assert!(!tab.frame_presented(at(21)));
+
+        // Nor is output that arrives long after the key press
+        tab.key_received(at(100));
+        tab.pty_written(at(101));
+        tab.output_read(at(2000));
+        tab.output_applied();
+        assert!(!tab.frame_presented(at(2001)));
+        assert_eq!(tab.stats().unwrap().samples, 1);
+    }
+}
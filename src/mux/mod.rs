@@ -1,3 +1,5 @@
+use crate::config::configuration;
+use crate::mux::activity::TabActivity;
 use crate::mux::tab::{Tab, TabId};
 use crate::mux::window::{Window, WindowId};
 use crate::ratelim::RateLimiter;
@@ -7,17 +9,25 @@ use domain::{Domain, DomainId};
 use log::{debug, error};
 use portable_pty::ExitStatus;
 use std::cell::{Ref, RefCell, RefMut};
-use std::collections::HashMap;
-use std::io::Read;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::PathBuf;
 use std::rc::Rc;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 use thiserror::*;
 
+pub mod activity;
+pub mod childwatch;
 pub mod domain;
+pub mod latency;
+pub mod recording;
 pub mod renderable;
+pub mod search;
+pub mod snapshot;
 pub mod tab;
+pub mod throttle;
 pub mod window;
 
 #[derive(Clone, Debug)]
@@ -36,13 +46,134 @@ pub struct Mux {
     domains: RefCell<HashMap<DomainId, Arc<dyn Domain>>>,
     domains_by_name: RefCell<HashMap<String, Arc<dyn Domain>>>,
     subscribers: RefCell<HashMap<usize, PollableSender<MuxNotification>>>,
+    /// Files that are deleted when the tab that they belong to is removed
+    tab_temp_files: RefCell<HashMap<TabId, Vec<PathBuf>>>,
+    /// When each tab last produced output; updated by its reader thread
+    tab_activity: RefCell<HashMap<TabId, Arc<TabActivity>>>,
 }
 
-fn read_from_tab_pty(tab_id: TabId, mut reader: Box<dyn std::io::Read>) {
+/// Tracks how much output has been read from a pty but not yet
+/// applied to the terminal model
+#[derive(Default)]
+struct PendingOutput {
+    bytes: Mutex<usize>,
+    cond: Condvar,
+}
+
+impl PendingOutput {
+    /// Blocks until fewer than `limit` bytes are pending, then
+    /// records that `len` more are on their way.  A chunk is always
+    /// admitted when nothing is pending, so that a limit that is smaller
+    /// than a single read doesn't stall the output entirely.
+    fn wait_and_add(&self, limit: usize, len: usize) {
+        let mut bytes = self.bytes.lock().unwrap();
+        while *bytes > 0 && *bytes >= limit {
+            bytes = self.cond.wait(bytes).unwrap();
+        }
+        *bytes += len;
+    }
+
+    fn processed(&self, len: usize) {
+        let mut bytes = self.bytes.lock().unwrap();
+        *bytes = bytes.saturating_sub(len);
+        self.cond.notify_one();
+    }
+}
+
+/// Returns true if `err` indicates that the slave side of a pty has
+/// been closed, which is the normal way for the child to go away,
+/// rather than a problem that is worth reporting.
+pub fn is_pty_closed_error(err: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        if err.raw_os_error() == Some(libc::EIO) {
+            return true;
+        }
+    }
+    err.kind() == std::io::ErrorKind::BrokenPipe
+}
+
+/// Forwards the data written to it to a thread that writes it to
+/// the pty, so that the thread that generates input for the pty
+/// doesn't block when the child isn't reading it.
+/// Once the writer thread has failed, writes fail with BrokenPipe.
+/// Clones write to the same thread, so the data written through each
+/// of them reaches the pty in the order that it was written.
+#[derive(Clone)]
+pub struct ThreadedWriter {
+    sender: std::sync::mpsc::Sender<Vec<u8>>,
+}
+
+impl Write for ThreadedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.sender.send(buf.to_vec()).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "the pty writer thread has exited",
+            )
+        })?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns a writer that passes the data written to it to `writer`
+/// from a separate thread; see `ThreadedWriter`.
+pub fn threaded_pty_writer(mut writer: Box<dyn Write + Send>) -> ThreadedWriter {
+    let (sender, receiver) = std::sync::mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        for data in receiver {
+            if let Err(err) = writer.write_all(&data).and_then(|_| writer.flush()) {
+                if is_pty_closed_error(&err) {
+                    debug!("write_pty: pty closed: {}", err);
+                } else {
+                    error!("write_pty failed: {}", err);
+                }
+                break;
+            }
+        }
+    });
+    ThreadedWriter { sender }
+}
+
+/// Applies the output read from the pty to the terminal model on the
+/// thread that reads it; see `Tab::output_parser`
+pub type OutputParser = Box<dyn FnMut(&[u8]) + Send>;
+
+fn read_from_tab_pty(
+    tab_id: TabId,
+    mut reader: Box<dyn std::io::Read>,
+    parser: Option<OutputParser>,
+    activity: Arc<TabActivity>,
+) {
     const BUFSIZE: usize = 32 * 1024;
     let mut buf = [0; BUFSIZE];
 
     let mut lim = RateLimiter::new(|config| config.ratelimit_output_bytes_per_second);
+    let pending = Arc::new(PendingOutput::default());
+
+    let parser = parser.map(|parser| {
+        // Set while a notification that the model has changed is queued
+        // for the main thread, so that we queue at most one at a time
+        let notify_pending = Arc::new(AtomicBool::new(false));
+        let processed = move || {
+            if !notify_pending.swap(true, Ordering::AcqRel) {
+                let notify_pending = Arc::clone(&notify_pending);
+                promise::spawn::spawn_into_main_thread(async move {
+                    notify_pending.store(false, Ordering::Release);
+                    let mux = Mux::get().unwrap();
+                    if let Some(tab) = mux.get_tab(tab_id) {
+                        tab.output_processed();
+                        mux.notify(MuxNotification::TabOutput(tab_id));
+                    }
+                });
+            }
+        };
+        throttle::spawn_output_parser(tab_id, parser, Box::new(processed))
+    });
 
     loop {
         match reader.read(&mut buf) {
@@ -50,6 +181,11 @@ fn read_from_tab_pty(tab_id: TabId, mut reader: Box<dyn std::io::Read>) {
                 error!("read_pty EOF: tab_id {}", tab_id);
                 break;
             }
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) if is_pty_closed_error(&err) => {
+                debug!("read_pty: tab {} pty closed: {}", tab_id, err);
+                break;
+            }
             Err(err) => {
                 error!("read_pty failed: tab {} {:?}", tab_id, err);
                 break;
@@ -57,19 +193,40 @@ fn read_from_tab_pty(tab_id: TabId, mut reader: Box<dyn std::io::Read>) {
             Ok(size) => {
                 let buf = &buf[..size];
                 let mut pos = 0;
+                recording::record_output(tab_id, buf);
+                latency::output_read(tab_id);
+                activity.output_read();
 
                 while pos < size {
                     match lim.admit_check((size - pos) as u32) {
                         Ok(len) => {
                             let len = len as usize;
+
+                            if let Some(parser) = parser.as_ref() {
+                                // The parsing thread only accepts a few
+                                // reads at a time, which naturally stops
+                                // us from reading further ahead of the model
+                                parser.send(&buf[pos..pos + len]);
+                                pos += len;
+                                continue;
+                            }
+
                             let data = buf[pos..pos + len].to_vec();
                             pos += len;
+
+                            // Don't get further ahead of the terminal model
+                            // than permitted; while we wait here, the pty
+                            // buffer fills and the child blocks on its writes
+                            pending.wait_and_add(configuration().max_pending_output_bytes, len);
+
+                            let pending = Arc::clone(&pending);
                             promise::spawn::spawn_into_main_thread_with_low_priority(async move {
                                 let mux = Mux::get().unwrap();
                                 if let Some(tab) = mux.get_tab(tab_id) {
                                     tab.advance_bytes(&data);
                                     mux.notify(MuxNotification::TabOutput(tab_id));
                                 }
+                                pending.processed(len);
                             });
                         }
                         Err(delay) => {
@@ -81,6 +238,11 @@ fn read_from_tab_pty(tab_id: TabId, mut reader: Box<dyn std::io::Read>) {
             }
         }
     }
+    activity.pty_closed();
+    recording::stop_recording(tab_id);
+    latency::forget_tab(tab_id);
+    // Let the parsing thread finish with the output that it has
+    drop(parser);
     promise::spawn::spawn_into_main_thread(async move {
         let mux = Mux::get().unwrap();
         mux.remove_tab(tab_id);
@@ -104,6 +266,8 @@ impl Mux {
             );
         }
 
+        childwatch::install();
+
         Self {
             tabs: RefCell::new(HashMap::new()),
             windows: RefCell::new(HashMap::new()),
@@ -111,6 +275,8 @@ impl Mux {
             domains_by_name: RefCell::new(domains_by_name),
             domains: RefCell::new(domains),
             subscribers: RefCell::new(HashMap::new()),
+            tab_temp_files: RefCell::new(HashMap::new()),
+            tab_activity: RefCell::new(HashMap::new()),
         }
     }
 
@@ -182,19 +348,78 @@ impl Mux {
         self.tabs.borrow().get(&tab_id).map(Rc::clone)
     }
 
+    /// Returns the output activity of `tab_id`, which can be passed to
+    /// other threads
+    pub fn tab_activity(&self, tab_id: TabId) -> Option<Arc<TabActivity>> {
+        self.tab_activity.borrow().get(&tab_id).map(Arc::clone)
+    }
+
     pub fn add_tab(&self, tab: &Rc<dyn Tab>) -> Result<(), Error> {
         self.tabs.borrow_mut().insert(tab.tab_id(), Rc::clone(tab));
 
         let reader = tab.reader()?;
+        let parser = tab.output_parser();
         let tab_id = tab.tab_id();
-        thread::spawn(move || read_from_tab_pty(tab_id, reader));
+        let activity = Arc::new(TabActivity::default());
+        self.tab_activity
+            .borrow_mut()
+            .insert(tab_id, Arc::clone(&activity));
+        thread::spawn(move || read_from_tab_pty(tab_id, reader, parser, activity));
 
         Ok(())
     }
 
+    /// Tells the output throttle which tabs aren't visible: those that
+    /// aren't the active tab of their window
+    pub fn update_tab_visibility(&self) {
+        let mut inactive = HashSet::new();
+        for window in self.windows.borrow().values() {
+            let active = window.get_active_idx();
+            for (idx, tab) in window.iter().enumerate() {
+                if idx != active {
+                    inactive.insert(tab.tab_id());
+                }
+            }
+        }
+        throttle::set_inactive_tabs(&inactive);
+    }
+
     pub fn remove_tab(&self, tab_id: TabId) {
         debug!("removing tab {}", tab_id);
+        self.forget_tab(tab_id);
+        self.prune_dead_windows();
+    }
+
+    /// Arranges for `path` to be deleted when `tab_id` is removed,
+    /// such as a temporary file that was passed to the program
+    /// running in the tab
+    pub fn remove_file_with_tab(&self, tab_id: TabId, path: PathBuf) {
+        self.tab_temp_files
+            .borrow_mut()
+            .entry(tab_id)
+            .or_insert_with(Vec::new)
+            .push(path);
+    }
+
+    fn forget_tab(&self, tab_id: TabId) {
         self.tabs.borrow_mut().remove(&tab_id);
+        self.tab_activity.borrow_mut().remove(&tab_id);
+        if let Some(paths) = self.tab_temp_files.borrow_mut().remove(&tab_id) {
+            for path in paths {
+                if let Err(err) = std::fs::remove_file(&path) {
+                    error!("failed to remove {}: {}", path.display(), err);
+                }
+            }
+        }
+    }
+
+    /// Called when one or more child processes have exited; collects
+    /// their exit status and removes the tabs that they belonged to
+    pub fn reap_children(&self) {
+        let tabs: Vec<Rc<dyn Tab>> = self.tabs.borrow().values().cloned().collect();
+        for tab in tabs {
+            tab.reap();
+        }
         self.prune_dead_windows();
     }
 
@@ -217,7 +442,7 @@ impl Mux {
             .collect();
 
         for tab_id in dead_tab_ids {
-            self.tabs.borrow_mut().remove(&tab_id);
+            self.forget_tab(tab_id);
         }
 
         for window_id in dead_windows {
@@ -230,7 +455,7 @@ impl Mux {
         let mut windows = self.windows.borrow_mut();
         if let Some(window) = windows.remove(&window_id) {
             for tab in window.iter() {
-                self.tabs.borrow_mut().remove(&tab.tab_id());
+                self.forget_tab(tab.tab_id());
             }
         }
     }
@@ -253,6 +478,15 @@ impl Mux {
         }))
     }
 
+    /// Returns the window that contains `tab_id`
+    pub fn window_containing_tab(&self, tab_id: TabId) -> Option<WindowId> {
+        self.windows
+            .borrow()
+            .iter()
+            .find(|(_, window)| window.idx_by_id(tab_id).is_some())
+            .map(|(window_id, _)| *window_id)
+    }
+
     pub fn get_active_tab_for_window(&self, window_id: WindowId) -> Option<Rc<dyn Tab>> {
         let window = self.get_window(window_id)?;
         window.get_active().map(Rc::clone)
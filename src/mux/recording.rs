@@ -0,0 +1,382 @@
+//! Records the output of tabs to files in the asciinema v2 "cast"
+//! format, and reads those files back for `wezterm replay`.
+//! The output is tapped on the thread that reads it from the pty and
+//! handed to a thread that writes the file, so that recording doesn't
+//! hold up the processing of the output.
+//! Only the output of the tab and changes to its size are recorded;
+//! the input that is sent to the tab, such as a password typed at a
+//! prompt that doesn't echo it, never reaches the recorder.
+use crate::mux::tab::TabId;
+use anyhow::{anyhow, bail, Context};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+enum Event {
+    Output(Instant, Vec<u8>),
+    Resize(Instant, u16, u16),
+}
+
+lazy_static::lazy_static! {
+    static ref RECORDERS: Mutex<HashMap<TabId, Sender<Event>>> = Mutex::new(HashMap::new());
+}
+
+/// Formats events as the lines of a cast file
+struct CastWriter<W: Write> {
+    out: W,
+    start: Instant,
+    /// The tail of the output that isn't yet a complete UTF-8
+    /// sequence; the cast format records output as text
+    pending: Vec<u8>,
+}
+
+impl<W: Write> CastWriter<W> {
+    fn new(mut out: W, cols: u16, rows: u16, term: &str, start: Instant) -> anyhow::Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let header = serde_json::json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": timestamp,
+            "env": {"TERM": term},
+        });
+        writeln!(out, "{}", header)?;
+        Ok(Self {
+            out,
+            start,
+            pending: vec![],
+        })
+    }
+
+    fn write_line(&mut self, when: Instant, kind: &str, data: &str) -> anyhow::Result<()> {
+        let time = when.saturating_duration_since(self.start).as_secs_f64();
+        writeln!(self.out, "{}", serde_json::to_string(&(time, kind, data))?)?;
+        Ok(())
+    }
+
+    /// Removes the longest prefix of `pending` that can be decoded,
+    /// replacing invalid sequences with U+FFFD.  If `flush` is false,
+    /// an incomplete sequence at the end is retained.
+    fn decode_pending(&mut self, flush: bool) -> String {
+        let mut text = String::new();
+        let mut pos = 0;
+        while pos < self.pending.len() {
+            match std::str::from_utf8(&self.pending[pos..]) {
+                Ok(s) => {
+                    text.push_str(s);
+                    pos = self.pending.len();
+                }
+                Err(err) => {
+                    let valid = err.valid_up_to();
+                    text.push_str(std::str::from_utf8(&self.pending[pos..pos + valid]).unwrap());
+                    pos += valid;
+                    match err.error_len() {
+                        Some(len) => {
+                            text.push('\u{fffd}');
+                            pos += len;
+                        }
+                        None if flush => {
+                            text.push('\u{fffd}');
+                            pos = self.pending.len();
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+        self.pending.drain(..pos);
+        text
+    }
+
+    fn write_event(&mut self, event: Event) -> anyhow::Result<()> {
+        match event {
+            Event::Output(when, data) => {
+                self.pending.extend_from_slice(&data);
+                let text = self.decode_pending(false);
+                if !text.is_empty() {
+                    self.write_line(when, "o", &text)?;
+                }
+            }
+            Event::Resize(when, cols, rows) => {
+                self.write_line(when, "r", &format!("{}x{}", cols, rows))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> anyhow::Result<()> {
+        let text = self.decode_pending(true);
+        if !text.is_empty() {
+            self.write_line(Instant::now(), "o", &text)?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Starts recording the output of `tab_id`, whose current size is
+/// `cols` x `rows`, to a new cast file at `path`
+pub fn start_recording(tab_id: TabId, path: &Path, cols: u16, rows: u16) -> anyhow::Result<()> {
+    let mut recorders = RECORDERS.lock().unwrap();
+    if recorders.contains_key(&tab_id) {
+        bail!("tab {} is already being recorded", tab_id);
+    }
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("creating recording {}", path.display()))?;
+    let term = crate::config::configuration().term.clone();
+    let mut writer = CastWriter::new(BufWriter::new(file), cols, rows, &term, Instant::now())?;
+
+    let (sender, receiver) = channel();
+    let path = path.to_path_buf();
+    std::thread::spawn(move || {
+        // The loop ends when the recording is stopped, which drops
+        // the sender, but the events that were sent before that are
+        // still written
+        let result = receiver
+            .into_iter()
+            .try_for_each(|event| writer.write_event(event))
+            .and_then(|_| writer.finish());
+        match result {
+            Ok(_) => log::info!("finished recording {}", path.display()),
+            Err(err) => log::error!("while recording {}: {:#}", path.display(), err),
+        }
+    });
+    recorders.insert(tab_id, sender);
+    Ok(())
+}
+
+/// Stops recording `tab_id`, returning false if it wasn't being recorded
+pub fn stop_recording(tab_id: TabId) -> bool {
+    RECORDERS.lock().unwrap().remove(&tab_id).is_some()
+}
+
+pub fn is_recording(tab_id: TabId) -> bool {
+    RECORDERS.lock().unwrap().contains_key(&tab_id)
+}
+
+fn record(tab_id: TabId, event: Event) {
+    let mut recorders = RECORDERS.lock().unwrap();
+    if let Some(sender) = recorders.get(&tab_id) {
+        if sender.send(event).is_err() {
+            // The writer failed and has already logged why
+            recorders.remove(&tab_id);
+        }
+    }
+}
+
+/// Called with the output of `tab_id` as it is read from the pty
+pub fn record_output(tab_id: TabId, data: &[u8]) {
+    record(tab_id, Event::Output(Instant::now(), data.to_vec()));
+}
+
+/// Called when `tab_id` is resized
+pub fn record_resize(tab_id: TabId, cols: u16, rows: u16) {
+    record(tab_id, Event::Resize(Instant::now(), cols, rows));
+}
+
+/// An event from a cast file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastEvent {
+    /// Seconds since the start of the recording
+    pub time: f64,
+    /// `o` for output, `r` for a resize; other kinds, such as the
+    /// input that asciinema can optionally record, are ignored
+    pub kind: String,
+    pub data: String,
+}
+
+/// The contents of a cast file
+#[derive(Debug)]
+pub struct CastFile {
+    pub width: usize,
+    pub height: usize,
+    pub events: Vec<CastEvent>,
+}
+
+impl CastFile {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("opening recording {}", path.display()))?;
+        Self::parse(BufReader::new(file))
+            .with_context(|| format!("reading recording {}", path.display()))
+    }
+
+    fn parse<R: BufRead>(reader: R) -> anyhow::Result<Self> {
+        let mut lines = reader.lines();
+        let header: serde_json::Value = serde_json::from_str(
+            &lines
+                .next()
+                .ok_or_else(|| anyhow!("the recording is empty"))??,
+        )?;
+        if header["version"] != 2 {
+            bail!("only version 2 cast files are supported");
+        }
+        let dimension = |name: &str| {
+            header[name]
+                .as_u64()
+                .map(|n| n as usize)
+                .ok_or_else(|| anyhow!("the header has no {}", name))
+        };
+        let width = dimension("width")?;
+        let height = dimension("height")?;
+
+        let mut events = vec![];
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (time, kind, data): (f64, String, String) = serde_json::from_str(&line)?;
+            events.push(CastEvent { time, kind, data });
+        }
+
+        Ok(Self {
+            width,
+            height,
+            events,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn record_to_vec(events: Vec<Event>, start: Instant) -> String {
+        let mut out = vec![];
+        let mut writer = CastWriter::new(&mut out, 80, 24, "xterm-256color", start).unwrap();
+        for event in events {
+            writer.write_event(event).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let start = Instant::now();
+        let cast = record_to_vec(
+            vec![
+                Event::Output(start + Duration::from_millis(500), b"hello\r\n".to_vec()),
+                Event::Resize(start + Duration::from_secs(1), 100, 30),
+                Event::Output(start + Duration::from_secs(2), b"\x1b[1mbold".to_vec()),
+            ],
+            start,
+        );
+
+        let cast = CastFile::parse(cast.as_bytes()).unwrap();
+        assert_eq!((cast.width, cast.height), (80, 24));
+        assert_eq!(
+            cast.events,
+            vec![
+                CastEvent {
+                    time: 0.5,
+                    kind: "o".to_string(),
+                    data: "hello\r\n".to_string()
+                },
+                CastEvent {
+                    time: 1.0,
+                    kind: "r".to_string(),
+                    data: "100x30".to_string()
+                },
+                CastEvent {
+                    time: 2.0,
+                    kind: "o".to_string(),
+                    data: "\x1b[1mbold".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn split_utf8() {
+        let start = Instant::now();
+        let snowman = "☃".as_bytes();
+        let cast = record_to_vec(
+            vec![
+                Event::Output(start, snowman[..1].to_vec()),
+                Event::Output(start, snowman[1..].to_vec()),
+                Event::Output(start, b"\xffok\xe2".to_vec()),
+            ],
+            start,
+        );
+
+        let cast = CastFile::parse(cast.as_bytes()).unwrap();
+        let text: Vec<&str> = cast.events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(text, vec!["☃", "\u{fffd}ok", "\u{fffd}"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn input_is_not_recorded() {
+        use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+        use std::io::Read;
+
+        // Type a password at a prompt that doesn't echo it, recording
+        // what is read from the pty in the same way as read_from_tab_pty
+        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(&[
+            "-c",
+            "stty -echo; printf 'Password: '; read secret; printf '\\r\\nok\\r\\n'",
+        ]);
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        let mut master = pair.master;
+
+        let tab_id = crate::mux::tab::alloc_tab_id();
+        let path = std::env::temp_dir().join(format!("wezterm-recording-test-{}.cast", tab_id));
+        start_recording(tab_id, &path, 80, 24).unwrap();
+        assert!(is_recording(tab_id));
+
+        let mut output = String::new();
+        let mut read_until = |text: &str| {
+            let mut buf = [0u8; 1024];
+            while !output.contains(text) {
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(size) => {
+                        record_output(tab_id, &buf[..size]);
+                        output.push_str(&String::from_utf8_lossy(&buf[..size]));
+                    }
+                }
+            }
+        };
+        // Echo has been turned off once the prompt is shown
+        read_until("Password: ");
+        master.write_all(b"hunter2\n").unwrap();
+        master.flush().unwrap();
+        read_until("ok");
+        child.wait().unwrap();
+
+        assert!(stop_recording(tab_id));
+        assert!(!is_recording(tab_id));
+
+        // Wait for the writer thread to finish the file
+        let mut recorded = None;
+        for _ in 0..100 {
+            if let Ok(cast) = CastFile::load(&path) {
+                let text: String = cast.events.iter().map(|e| e.data.as_str()).collect();
+                if text.contains("ok") {
+                    recorded = Some(text);
+                    break;
+                }
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        std::fs::remove_file(&path).ok();
+
+        let recorded = recorded.unwrap();
+        assert!(recorded.contains("Password: "));
+        assert!(!recorded.contains("hunter2"));
+    }
+}
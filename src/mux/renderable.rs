@@ -65,6 +65,11 @@ pub trait Renderable: Downcast {
 
     /// Returns render related dimensions
     fn get_dimensions(&self) -> RenderableDimensions;
+
+    /// Returns true if the screen is in reverse video mode (DECSCNM),
+    /// in which case the default foreground and background colors are
+    /// swapped when rendering
+    fn is_reverse_video(&self) -> bool;
 }
 impl_downcast!(Renderable);
 
@@ -129,4 +134,8 @@ impl Renderable for Terminal {
             scrollback_top: screen.phys_to_stable_row_index(0),
         }
     }
+
+    fn is_reverse_video(&self) -> bool {
+        self.reverse_video_enabled()
+    }
 }
@@ -0,0 +1,491 @@
+//! Searches the lines of a tab, scrollback included, for a pattern.
+//! The search proceeds one logical line at a time, where a logical
+//! line is a run of physical lines joined by soft wraps, so that it
+//! can stop as soon as enough matches have been found and so that
+//! matches can span the places where long lines were wrapped.
+//! It is designed to run on a thread of its own against a snapshot
+//! of the lines, so that searching a large scrollback doesn't hold up
+//! the UI or the processing of output.
+use crate::mux::snapshot::RenderSnapshot;
+use crate::mux::tab::{Pattern, SearchResult};
+use std::collections::VecDeque;
+use std::ops::Range;
+use wezterm_term::{Line, StableRowIndex};
+
+/// The lines that can be searched
+pub trait SearchableLines {
+    /// The range of rows for which `line` returns a line
+    fn rows(&self) -> Range<StableRowIndex>;
+    fn line(&self, stable_row: StableRowIndex) -> Option<&Line>;
+}
+
+impl SearchableLines for RenderSnapshot {
+    fn rows(&self) -> Range<StableRowIndex> {
+        self.stable_rows()
+    }
+
+    fn line(&self, stable_row: StableRowIndex) -> Option<&Line> {
+        self.get_line(stable_row)
+    }
+}
+
+/// Which way a search proceeds from the row at which it starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchDirection {
+    /// Towards the top of the scrollback; the matches are produced
+    /// from the bottom up
+    Backward,
+    /// Towards the bottom of the screen
+    Forward,
+}
+
+enum Matcher {
+    /// The needle and the haystack are both lowercased when the
+    /// case is to be ignored
+    Text {
+        needle: String,
+        ignore_case: bool,
+    },
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    fn new(pattern: &Pattern) -> anyhow::Result<Self> {
+        Ok(match pattern {
+            Pattern::CaseSensitiveString(s) => Matcher::Text {
+                needle: s.clone(),
+                ignore_case: false,
+            },
+            Pattern::CaseInSensitiveString(s) => Matcher::Text {
+                needle: s.to_lowercase(),
+                ignore_case: true,
+            },
+            Pattern::Regex(r) => Matcher::Regex(regex::Regex::new(r)?),
+        })
+    }
+
+    fn ignore_case(&self) -> bool {
+        match self {
+            Matcher::Text { ignore_case, .. } => *ignore_case,
+            Matcher::Regex(_) => false,
+        }
+    }
+
+    fn find(&self, haystack: &str) -> Vec<Range<usize>> {
+        match self {
+            Matcher::Text { needle, .. } => {
+                if needle.is_empty() {
+                    return vec![];
+                }
+                haystack
+                    .match_indices(needle.as_str())
+                    .map(|(idx, s)| idx..idx + s.len())
+                    .collect()
+            }
+            Matcher::Regex(re) => re
+                .find_iter(haystack)
+                .filter(|m| m.start() != m.end())
+                .map(|m| m.start()..m.end())
+                .collect(),
+        }
+    }
+}
+
+/// Where the text of a cell is found in the text of a logical line
+struct Coord {
+    byte_idx: usize,
+    x: usize,
+    width: usize,
+    stable_row: StableRowIndex,
+}
+
+/// The text of a logical line, along with the cells that it came from
+struct LogicalLine {
+    rows: Range<StableRowIndex>,
+    text: String,
+    coords: Vec<Coord>,
+}
+
+impl LogicalLine {
+    /// Collects the logical line that includes `stable_row`
+    fn at<L: SearchableLines + ?Sized>(
+        lines: &L,
+        stable_row: StableRowIndex,
+        ignore_case: bool,
+    ) -> Self {
+        let all_rows = lines.rows();
+        let wraps = |row| lines.line(row).map_or(false, Line::last_cell_was_wrapped);
+
+        let mut start = stable_row;
+        while start > all_rows.start && wraps(start - 1) {
+            start -= 1;
+        }
+        let mut end = stable_row + 1;
+        while end < all_rows.end && wraps(end - 1) {
+            end += 1;
+        }
+
+        let mut text = String::new();
+        let mut coords = vec![];
+        for row in start..end {
+            if let Some(line) = lines.line(row) {
+                for (x, cell) in line.visible_cells() {
+                    coords.push(Coord {
+                        byte_idx: text.len(),
+                        x,
+                        width: cell.width().max(1),
+                        stable_row: row,
+                    });
+                    if ignore_case {
+                        text.push_str(&cell.str().to_lowercase());
+                    } else {
+                        text.push_str(cell.str());
+                    }
+                }
+            }
+        }
+
+        Self {
+            rows: start..end,
+            text,
+            coords,
+        }
+    }
+
+    /// Translates a range of bytes of the text to the cells that hold it
+    fn result(&self, range: Range<usize>) -> SearchResult {
+        // The cell in which the match starts, and the one in which it ends
+        let first = match self
+            .coords
+            .binary_search_by(|c| c.byte_idx.cmp(&range.start))
+        {
+            Ok(idx) => idx,
+            Err(idx) => idx.saturating_sub(1),
+        };
+        let last = match self.coords.binary_search_by(|c| c.byte_idx.cmp(&range.end)) {
+            Ok(idx) | Err(idx) => idx.saturating_sub(1),
+        };
+        let first = &self.coords[first];
+        let last = &self.coords[last];
+        SearchResult {
+            start_y: first.stable_row,
+            start_x: first.x,
+            end_y: last.stable_row,
+            end_x: last.x + last.width,
+        }
+    }
+
+    fn matches(&self, matcher: &Matcher) -> Vec<SearchResult> {
+        if self.coords.is_empty() {
+            return vec![];
+        }
+        matcher
+            .find(&self.text)
+            .into_iter()
+            .map(|range| self.result(range))
+            .collect()
+    }
+}
+
+/// Lazily produces the matches for a pattern, a logical line at a time.
+/// In each direction, the matches are produced in the order in which
+/// they are encountered.
+pub struct Search<'a, L: SearchableLines + ?Sized> {
+    lines: &'a L,
+    matcher: Matcher,
+    direction: SearchDirection,
+    next_row: Option<StableRowIndex>,
+    pending: VecDeque<SearchResult>,
+    rows_scanned: usize,
+}
+
+impl<'a, L: SearchableLines + ?Sized> Search<'a, L> {
+    /// Prepares to search `lines` for `pattern`, starting with the
+    /// logical line that contains `start`.  Fails if the pattern is
+    /// an invalid regex.
+    pub fn new(
+        lines: &'a L,
+        pattern: &Pattern,
+        start: StableRowIndex,
+        direction: SearchDirection,
+    ) -> anyhow::Result<Self> {
+        let rows = lines.rows();
+        let next_row = if rows.start >= rows.end {
+            None
+        } else {
+            Some(start.max(rows.start).min(rows.end - 1))
+        };
+        Ok(Self {
+            lines,
+            matcher: Matcher::new(pattern)?,
+            direction,
+            next_row,
+            pending: VecDeque::new(),
+            rows_scanned: 0,
+        })
+    }
+
+    /// How many rows have been searched so far
+    pub fn rows_scanned(&self) -> usize {
+        self.rows_scanned
+    }
+
+    /// Searches the next logical line.  Returns false once there are
+    /// no more lines.
+    fn scan_next_line(&mut self) -> bool {
+        let row = match self.next_row {
+            Some(row) => row,
+            None => return false,
+        };
+        let line = LogicalLine::at(self.lines, row, self.matcher.ignore_case());
+        let mut matches = line.matches(&self.matcher);
+        let all_rows = self.lines.rows();
+        self.rows_scanned += (line.rows.end - line.rows.start) as usize;
+
+        self.next_row = match self.direction {
+            SearchDirection::Backward => {
+                matches.reverse();
+                Some(line.rows.start - 1).filter(|row| *row >= all_rows.start)
+            }
+            SearchDirection::Forward => Some(line.rows.end).filter(|row| *row < all_rows.end),
+        };
+        self.pending.extend(matches);
+        true
+    }
+}
+
+impl<'a, L: SearchableLines + ?Sized> Iterator for Search<'a, L> {
+    type Item = SearchResult;
+
+    fn next(&mut self) -> Option<SearchResult> {
+        loop {
+            if let Some(result) = self.pending.pop_front() {
+                return Some(result);
+            }
+            if !self.scan_next_line() {
+                return None;
+            }
+        }
+    }
+}
+
+/// The most matches that are delivered together
+const CHUNK_SIZE: usize = 256;
+/// The matches found so far are delivered after scanning this many
+/// rows, even if there aren't enough of them to fill a chunk
+const ROWS_PER_CHUNK: usize = 2000;
+
+/// Searches `lines` as `Search` does, passing the matches to
+/// `on_chunk` a number at a time, so that they can be shown before the
+/// search is complete.  The second parameter of `on_chunk` is true for
+/// its final call.  The search stops early if `on_chunk` returns false.
+pub fn search_in_chunks<L: SearchableLines + ?Sized>(
+    lines: &L,
+    pattern: &Pattern,
+    start: StableRowIndex,
+    direction: SearchDirection,
+    mut on_chunk: impl FnMut(Vec<SearchResult>, bool) -> bool,
+) -> anyhow::Result<()> {
+    let mut search = Search::new(lines, pattern, start, direction)?;
+    let mut chunk = vec![];
+    let mut flushed_at = 0;
+    loop {
+        chunk.extend(search.pending.drain(..));
+        let more = search.scan_next_line();
+        let flush = chunk.len() >= CHUNK_SIZE
+            || (!chunk.is_empty() && search.rows_scanned() - flushed_at >= ROWS_PER_CHUNK);
+        if !more {
+            chunk.extend(search.pending.drain(..));
+            on_chunk(chunk, true);
+            return Ok(());
+        }
+        if flush {
+            flushed_at = search.rows_scanned();
+            if !on_chunk(std::mem::take(&mut chunk), false) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Returns true if `result` is still a match for `pattern` in `lines`.
+/// The lines may have changed since `result` was found, so this is
+/// checked before the match is highlighted or jumped to.
+pub fn revalidate<L: SearchableLines + ?Sized>(
+    lines: &L,
+    pattern: &Pattern,
+    result: &SearchResult,
+) -> bool {
+    let matcher = match Matcher::new(pattern) {
+        Ok(matcher) => matcher,
+        Err(_) => return false,
+    };
+    if !lines.rows().contains(&result.start_y) {
+        return false;
+    }
+    LogicalLine::at(lines, result.start_y, matcher.ignore_case())
+        .matches(&matcher)
+        .contains(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::cell::CellAttributes;
+
+    struct TestLines(Vec<Line>);
+
+    impl SearchableLines for TestLines {
+        fn rows(&self) -> Range<StableRowIndex> {
+            0..self.0.len() as StableRowIndex
+        }
+
+        fn line(&self, stable_row: StableRowIndex) -> Option<&Line> {
+            self.0.get(stable_row as usize)
+        }
+    }
+
+    /// Lines ending with `+` are soft wrapped into the line that follows
+    fn lines(text: &[&str]) -> TestLines {
+        TestLines(
+            text.iter()
+                .map(|s| {
+                    let attrs = CellAttributes::default();
+                    if s.ends_with('+') {
+                        Line::from_text_with_wrapped_last_col(&s[..s.len() - 1], &attrs)
+                    } else {
+                        Line::from_text(s, &attrs)
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    fn result(
+        start_y: StableRowIndex,
+        start_x: usize,
+        end_y: StableRowIndex,
+        end_x: usize,
+    ) -> SearchResult {
+        SearchResult {
+            start_y,
+            start_x,
+            end_y,
+            end_x,
+        }
+    }
+
+    fn all(lines: &TestLines, pattern: Pattern, direction: SearchDirection) -> Vec<SearchResult> {
+        let start = match direction {
+            SearchDirection::Forward => 0,
+            SearchDirection::Backward => lines.rows().end - 1,
+        };
+        Search::new(lines, &pattern, start, direction)
+            .unwrap()
+            .collect()
+    }
+
+    #[test]
+    fn across_soft_wrap() {
+        let lines = lines(&["hello wo+", "rld", "world"]);
+        assert_eq!(
+            all(
+                &lines,
+                Pattern::CaseSensitiveString("world".into()),
+                SearchDirection::Forward
+            ),
+            vec![result(0, 6, 1, 3), result(2, 0, 2, 5)]
+        );
+        assert_eq!(
+            all(
+                &lines,
+                Pattern::Regex("o+r".into()),
+                SearchDirection::Backward
+            ),
+            vec![result(2, 1, 2, 3), result(0, 7, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn hard_newlines_separate_lines() {
+        // Without the wrap, the match would need to span two lines
+        let lines = lines(&["hello wo", "rld"]);
+        assert_eq!(
+            all(
+                &lines,
+                Pattern::CaseSensitiveString("world".into()),
+                SearchDirection::Forward
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn ignore_case() {
+        let lines = lines(&["Foo fOO+", "fo", "bar"]);
+        assert_eq!(
+            all(
+                &lines,
+                Pattern::CaseInSensitiveString("FOO".into()),
+                SearchDirection::Forward
+            ),
+            vec![result(0, 0, 0, 3), result(0, 4, 0, 7)]
+        );
+    }
+
+    #[test]
+    fn starts_from_the_logical_line() {
+        let lines = lines(&["ab", "ab+", "ab", "ab"]);
+        let pattern = Pattern::CaseSensitiveString("ab".into());
+        // Row 2 continues row 1, so the search starts from row 1
+        let found: Vec<_> = Search::new(&lines, &pattern, 2, SearchDirection::Backward)
+            .unwrap()
+            .collect();
+        assert_eq!(
+            found,
+            vec![result(2, 0, 2, 2), result(1, 0, 1, 2), result(0, 0, 0, 2)]
+        );
+    }
+
+    #[test]
+    fn chunks() {
+        let text: Vec<String> = (0..5000).map(|i| format!("line {}", i)).collect();
+        let text: Vec<&str> = text.iter().map(String::as_str).collect();
+        let lines = lines(&text);
+
+        let mut chunks = vec![];
+        search_in_chunks(
+            &lines,
+            &Pattern::Regex("line 4?2$".into()),
+            4999,
+            SearchDirection::Backward,
+            |chunk, done| {
+                chunks.push((chunk, done));
+                true
+            },
+        )
+        .unwrap();
+        // The match on row 42 is found after more than a chunk's worth
+        // of rows have been searched, so it is delivered straight away
+        // rather than waiting for the search to reach the top
+        assert_eq!(
+            chunks,
+            vec![
+                (vec![result(42, 0, 42, 7)], false),
+                (vec![result(2, 0, 2, 6)], true),
+            ]
+        );
+    }
+
+    #[test]
+    fn revalidation() {
+        let mut lines = lines(&["foo bar"]);
+        let pattern = Pattern::CaseSensitiveString("bar".into());
+        let found = all(&lines, pattern.clone(), SearchDirection::Forward);
+        assert_eq!(found, vec![result(0, 4, 0, 7)]);
+        assert!(revalidate(&lines, &pattern, &found[0]));
+
+        lines.0[0] = Line::from_text("foo baz", &CellAttributes::default());
+        assert!(!revalidate(&lines, &pattern, &found[0]));
+    }
+}
@@ -0,0 +1,423 @@
+//! Double-buffered snapshots of the renderable state of a terminal.
+//! The owner of the terminal model publishes a snapshot after changing
+//! the model, and the renderer reads the most recently published one
+//! without borrowing the model, so that neither painting nor output
+//! processing has to wait for the other.
+//! Lines are shared between successive snapshots until they change.
+//! Each copy of a line is labelled with a sequence number so that
+//! the reader can tell which lines have changed since it last fetched
+//! them, which takes the place of the dirty flags in the model.
+//! Snapshots can be read from any thread, which lets the search overlay
+//! search the lines of a tab without holding up the gui thread.
+use crate::config::configuration;
+use crate::mux::renderable::{Renderable, RenderableDimensions, StableCursorPosition};
+use rangeset::RangeSet;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use wezterm_term::{Line, StableRowIndex, Terminal};
+
+#[derive(Clone)]
+struct SnapshotLine {
+    seqno: u64,
+    line: Arc<Line>,
+}
+
+/// The number of rows in each chunk of a snapshot
+const CHUNK_ROWS: StableRowIndex = 256;
+
+/// A run of lines within the chunk of stable rows that starts at a
+/// multiple of `CHUNK_ROWS`.  Chunks are shared between successive
+/// snapshots while none of their lines change, so that publishing a
+/// snapshot doesn't have to touch the whole of the scrollback.
+struct Chunk {
+    /// The stable row of the first of `lines`
+    first_row: StableRowIndex,
+    lines: Vec<SnapshotLine>,
+}
+
+impl Chunk {
+    fn line(&self, stable_row: StableRowIndex) -> Option<&SnapshotLine> {
+        let idx = stable_row - self.first_row;
+        if idx < 0 {
+            None
+        } else {
+            self.lines.get(idx as usize)
+        }
+    }
+
+    /// Returns true if this chunk holds all of the rows in `rows`
+    fn covers(&self, rows: &Range<StableRowIndex>) -> bool {
+        self.first_row <= rows.start
+            && self.first_row + self.lines.len() as StableRowIndex >= rows.end
+    }
+}
+
+fn chunk_index(stable_row: StableRowIndex) -> StableRowIndex {
+    stable_row.div_euclid(CHUNK_ROWS)
+}
+
+/// The renderable state of a terminal at the time it was published
+#[derive(Default)]
+pub struct RenderSnapshot {
+    cursor: StableCursorPosition,
+    dimensions: RenderableDimensions,
+    reverse_video: bool,
+    alt_screen: bool,
+    /// The number of lines, starting from `dimensions.scrollback_top`
+    num_lines: usize,
+    /// The chunk that holds `dimensions.scrollback_top`
+    first_chunk: StableRowIndex,
+    chunks: Vec<Arc<Chunk>>,
+}
+
+impl RenderSnapshot {
+    fn line(&self, stable_row: StableRowIndex) -> Option<&SnapshotLine> {
+        if !self.stable_rows().contains(&stable_row) {
+            return None;
+        }
+        let idx = chunk_index(stable_row) - self.first_chunk;
+        self.chunks.get(idx as usize)?.line(stable_row)
+    }
+
+    /// The line at index `idx`, counting from the top of the scrollback
+    fn line_at(&self, idx: usize) -> &SnapshotLine {
+        self.line(self.dimensions.scrollback_top + idx as StableRowIndex)
+            .expect("idx to be within the snapshot")
+    }
+
+    /// The stable rows of the lines in this snapshot
+    pub fn stable_rows(&self) -> Range<StableRowIndex> {
+        let top = self.dimensions.scrollback_top;
+        top..top + self.num_lines as StableRowIndex
+    }
+
+    pub fn get_line(&self, stable_row: StableRowIndex) -> Option<&Line> {
+        self.line(stable_row).map(|line| &*line.line)
+    }
+
+    /// Translates a range of stable rows to a range of indices
+    /// counting from the top of the scrollback, adjusting it in the
+    /// same way as `Screen::stable_range` when it falls outside of the
+    /// available lines
+    fn stable_range(&self, range: &Range<StableRowIndex>) -> Range<usize> {
+        let range_len = (range.end - range.start).max(0) as usize;
+        let top = self.dimensions.scrollback_top;
+        let len = self.num_lines;
+        if len == 0 {
+            return 0..0;
+        }
+
+        let first = range.start - top;
+        if first < 0 || first as usize >= len {
+            return 0..range_len.min(len);
+        }
+
+        let last = range.end.saturating_sub(1) - top;
+        if last < 0 || last as usize >= len {
+            return (len - 1).saturating_sub(range_len)..len;
+        }
+
+        first as usize..last as usize + 1
+    }
+}
+
+/// Holds the most recently published snapshot of a terminal
+pub struct SnapshotBuffer {
+    latest: Mutex<Arc<RenderSnapshot>>,
+    next_seqno: AtomicU64,
+}
+
+impl SnapshotBuffer {
+    pub fn new(terminal: &mut Terminal) -> Self {
+        let buffer = Self {
+            latest: Mutex::new(Arc::new(RenderSnapshot::default())),
+            next_seqno: AtomicU64::new(1),
+        };
+        buffer.publish(terminal);
+        buffer
+    }
+
+    /// Returns the most recently published snapshot
+    pub fn latest(&self) -> Arc<RenderSnapshot> {
+        Arc::clone(&self.latest.lock().unwrap())
+    }
+
+    /// Publishes a snapshot of the current state of `terminal`.
+    /// Lines that aren't dirty are shared with the prior snapshot;
+    /// the dirty flags of the lines in the model are cleared.
+    /// Escape sequences can only change the lines in the viewport, so
+    /// the rows that were above the viewport in the prior snapshot and
+    /// still are now are taken from the prior snapshot without looking
+    /// at the model, which keeps the cost of publishing in proportion
+    /// to the size of the viewport rather than that of the scrollback.
+    pub fn publish(&self, terminal: &mut Terminal) {
+        let prior = self.latest();
+        let config = configuration();
+
+        let cursor = terminal.get_cursor_position();
+        let dimensions = terminal.get_dimensions();
+        let reverse_video = terminal.is_reverse_video();
+        let alt_screen = terminal.is_alt_screen_active();
+        // The lines are rewrapped when the width changes, and the
+        // primary and alternate screens have different lines
+        let reuse = dimensions.cols == prior.dimensions.cols && alt_screen == prior.alt_screen;
+        let top = dimensions.scrollback_top;
+        let frozen_end = if reuse {
+            prior
+                .dimensions
+                .physical_top
+                .min(dimensions.physical_top)
+                .max(top)
+        } else {
+            top
+        };
+
+        let screen = terminal.screen_mut();
+        let num_lines = screen.lines.len();
+        let end = top + num_lines as StableRowIndex;
+        let first_chunk = chunk_index(top);
+        let mut chunks = vec![];
+        if num_lines > 0 {
+            for chunk_idx in first_chunk..=chunk_index(end - 1) {
+                let rows =
+                    (chunk_idx * CHUNK_ROWS).max(top)..((chunk_idx + 1) * CHUNK_ROWS).min(end);
+
+                let prior_idx = chunk_idx - prior.first_chunk;
+                if rows.end <= frozen_end && prior_idx >= 0 {
+                    if let Some(chunk) = prior.chunks.get(prior_idx as usize) {
+                        if chunk.covers(&rows) {
+                            chunks.push(Arc::clone(chunk));
+                            continue;
+                        }
+                    }
+                }
+
+                let mut lines = Vec::with_capacity(rows.len());
+                for stable_row in rows.clone() {
+                    let line = &mut screen.lines[(stable_row - top) as usize];
+                    if stable_row < frozen_end || (reuse && !line.is_dirty()) {
+                        if let Some(prior_line) = prior.line(stable_row) {
+                            lines.push(prior_line.clone());
+                            continue;
+                        }
+                    }
+                    line.scan_and_create_hyperlinks(&config.hyperlink_rules);
+                    line.clear_dirty();
+                    lines.push(SnapshotLine {
+                        seqno: self.next_seqno.fetch_add(1, Ordering::Relaxed),
+                        line: Arc::new(line.clone()),
+                    });
+                }
+                chunks.push(Arc::new(Chunk {
+                    first_row: rows.start,
+                    lines,
+                }));
+            }
+        }
+
+        *self.latest.lock().unwrap() = Arc::new(RenderSnapshot {
+            cursor,
+            dimensions,
+            reverse_video,
+            alt_screen,
+            num_lines,
+            first_chunk,
+            chunks,
+        });
+    }
+}
+
+/// Presents the snapshots published to a SnapshotBuffer as a
+/// Renderable.  A line is dirty if it has changed since it was last
+/// returned from `get_lines`.
+pub struct SnapshotRenderer {
+    buffer: Arc<SnapshotBuffer>,
+    current: Arc<RenderSnapshot>,
+    /// The sequence number of the copy of each row that was most
+    /// recently returned from `get_lines`
+    seen: HashMap<StableRowIndex, u64>,
+}
+
+impl SnapshotRenderer {
+    pub fn new(buffer: &Arc<SnapshotBuffer>) -> Self {
+        Self {
+            buffer: Arc::clone(buffer),
+            current: buffer.latest(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Switches to the most recently published snapshot
+    pub fn refresh(&mut self) {
+        let latest = self.buffer.latest();
+        if !Arc::ptr_eq(&latest, &self.current) {
+            self.current = latest;
+            let top = self.current.dimensions.scrollback_top;
+            let end = top + self.current.num_lines as StableRowIndex;
+            self.seen.retain(|&row, _| row >= top && row < end);
+        }
+    }
+
+    fn is_dirty(&self, stable_row: StableRowIndex, line: &SnapshotLine) -> bool {
+        self.seen.get(&stable_row) != Some(&line.seqno)
+    }
+}
+
+impl Renderable for SnapshotRenderer {
+    fn get_cursor_position(&self) -> StableCursorPosition {
+        self.current.cursor
+    }
+
+    fn get_dirty_lines(&self, lines: Range<StableRowIndex>) -> RangeSet<StableRowIndex> {
+        let top = self.current.dimensions.scrollback_top;
+        let mut set = RangeSet::new();
+        for idx in self.current.stable_range(&lines) {
+            let stable_row = top + idx as StableRowIndex;
+            if self.is_dirty(stable_row, self.current.line_at(idx)) {
+                set.add(stable_row);
+            }
+        }
+        set
+    }
+
+    fn get_lines(&mut self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
+        let current = Arc::clone(&self.current);
+        let top = current.dimensions.scrollback_top;
+        let range = current.stable_range(&lines);
+        let first = top + range.start as StableRowIndex;
+        let mut result = Vec::with_capacity(range.len());
+        for idx in range {
+            let stable_row = top + idx as StableRowIndex;
+            let snapshot_line = current.line_at(idx);
+            let mut line = (*snapshot_line.line).clone();
+            if self.is_dirty(stable_row, snapshot_line) {
+                line.set_dirty();
+            }
+            self.seen.insert(stable_row, snapshot_line.seqno);
+            result.push(line);
+        }
+        (first, result)
+    }
+
+    fn get_dimensions(&self) -> RenderableDimensions {
+        self.current.dimensions
+    }
+
+    fn is_reverse_video(&self) -> bool {
+        self.current.reverse_video
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::TermConfig;
+    use termwiz::cell::Intensity;
+
+    fn terminal(rows: usize, cols: usize) -> Terminal {
+        Terminal::new(
+            rows,
+            cols,
+            cols * 8,
+            rows * 16,
+            Arc::new(TermConfig {}),
+            "WezTerm",
+            "O_o",
+            Box::new(Vec::new()),
+        )
+    }
+
+    fn text(snapshot: &RenderSnapshot) -> Vec<String> {
+        snapshot
+            .stable_rows()
+            .map(|row| {
+                snapshot
+                    .get_line(row)
+                    .unwrap()
+                    .as_str()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn read_from_another_thread() {
+        let mut term = terminal(3, 10);
+        term.advance_bytes("\x1b[1mbold\x1b[0m plain\r\nsecond");
+        let buffer = Arc::new(SnapshotBuffer::new(&mut term));
+
+        let reader = Arc::clone(&buffer);
+        let snapshot = std::thread::spawn(move || reader.latest()).join().unwrap();
+        assert_eq!(text(&snapshot), vec!["bold plain", "second", ""]);
+        let line = snapshot.get_line(0).unwrap();
+        assert_eq!(line.cells()[0].attrs().intensity(), Intensity::Bold);
+        assert_eq!(line.cells()[5].attrs().intensity(), Intensity::Normal);
+    }
+
+    #[test]
+    fn publish_scrollback() {
+        let mut term = terminal(2, 10);
+        let buffer = Arc::new(SnapshotBuffer::new(&mut term));
+        term.advance_bytes("one\r\ntwo\r\nthree\r\nfour");
+        // Snapshots are copies; the new output is only visible once
+        // it has been published
+        let prior = buffer.latest();
+        buffer.publish(&mut term);
+        assert_eq!(prior.stable_rows(), 0..2);
+        assert_eq!(text(&prior), vec!["", ""]);
+
+        let snapshot = buffer.latest();
+        assert_eq!(snapshot.stable_rows(), 0..4);
+        assert_eq!(text(&snapshot), vec!["one", "two", "three", "four"]);
+    }
+
+    #[test]
+    fn scrollback_is_shared() {
+        let mut term = terminal(2, 10);
+        let buffer = SnapshotBuffer::new(&mut term);
+        for i in 0..600 {
+            term.advance_bytes(format!("{}\r\n", i));
+        }
+        buffer.publish(&mut term);
+        let prior = buffer.latest();
+
+        term.advance_bytes("more\r\n");
+        buffer.publish(&mut term);
+        let snapshot = buffer.latest();
+        // The chunks that were above the viewport are reused
+        assert!(Arc::ptr_eq(&prior.chunks[0], &snapshot.chunks[0]));
+        assert!(Arc::ptr_eq(&prior.chunks[1], &snapshot.chunks[1]));
+        let line = |row| {
+            snapshot
+                .get_line(row)
+                .unwrap()
+                .as_str()
+                .trim_end()
+                .to_string()
+        };
+        assert_eq!(line(0), "0");
+        assert_eq!(line(599), "599");
+        assert_eq!(line(600), "more");
+        assert_eq!(snapshot.stable_rows(), 0..602);
+    }
+
+    #[test]
+    fn alt_screen() {
+        let mut term = terminal(2, 10);
+        term.advance_bytes("one\r\ntwo\r\nthree");
+        let buffer = SnapshotBuffer::new(&mut term);
+
+        term.advance_bytes("\x1b[?1049h\x1b[2J\x1b[Halt");
+        buffer.publish(&mut term);
+        let snapshot = buffer.latest();
+        let top = snapshot.stable_rows().start;
+        assert_eq!(snapshot.get_line(top).unwrap().as_str().trim_end(), "alt");
+
+        term.advance_bytes("\x1b[?1049l");
+        buffer.publish(&mut term);
+        assert_eq!(text(&buffer.latest()), vec!["one", "two", "three"]);
+    }
+}
@@ -1,15 +1,25 @@
+use crate::config::{configuration, TabColorOverrides, TabFontOverride};
 use crate::mux::domain::DomainId;
-use crate::mux::renderable::Renderable;
-use crate::mux::Mux;
+use crate::mux::renderable::{Renderable, RenderableDimensions};
+use crate::mux::snapshot::{RenderSnapshot, SnapshotBuffer};
+use crate::mux::{Mux, OutputParser};
 use async_trait::async_trait;
 use downcast_rs::{impl_downcast, Downcast};
-use portable_pty::PtySize;
+use portable_pty::{ExitStatus, PtySize};
 use serde::{Deserialize, Serialize};
 use std::cell::RefMut;
+use std::io::Write;
+use std::ops::Range;
 use std::sync::{Arc, Mutex};
+use termwiz::escape::osc::Progress;
+use termwiz::escape::parser::Encoding;
 use url::Url;
 use wezterm_term::color::ColorPalette;
-use wezterm_term::{Clipboard, KeyCode, KeyModifiers, MouseEvent, StableRowIndex};
+use wezterm_term::selection::{selection_text, SelectionTextOptions};
+use wezterm_term::{
+    Clipboard, KeyCode, KeyModifiers, Line, MouseEvent, ResizeRequest, StableRowIndex,
+    ToastNotification,
+};
 
 static TAB_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
 pub type TabId = usize;
@@ -84,24 +94,155 @@ pub struct SearchResult {
     pub end_x: usize,
 }
 
+/// Which lines of a tab to export as text, and how
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ExportTextOptions {
+    /// Export the scrollback as well as the visible screen
+    #[serde(default)]
+    pub scrollback: bool,
+    /// The first line to export, counting from 0 at the top of the
+    /// screen; negative values reach into the scrollback.  When set,
+    /// this takes precedence over `scrollback`.
+    #[serde(default)]
+    pub start_line: Option<isize>,
+    /// The line after the last line to export, counted in the same
+    /// way as `start_line`.  Defaults to the bottom of the screen.
+    #[serde(default)]
+    pub end_line: Option<isize>,
+    /// Preserve the colors and styles of the text as SGR escapes
+    #[serde(default)]
+    pub escapes: bool,
+    /// Export the output of the most recent command, starting from
+    /// its prompt, in place of the lines selected by the other fields.
+    /// This relies on the shell marking its prompts using `OSC 133;A`;
+    /// when it hasn't, the screen is exported.
+    #[serde(default)]
+    pub since_last_prompt: bool,
+}
+
+impl ExportTextOptions {
+    /// Computes the range of stable rows to export
+    pub fn rows(&self, dims: &RenderableDimensions) -> Range<StableRowIndex> {
+        let top = dims.physical_top;
+        let bottom = top + dims.viewport_rows as StableRowIndex;
+        let start = match self.start_line {
+            Some(line) => top + line,
+            None if self.scrollback => dims.scrollback_top,
+            None => top,
+        };
+        let end = self.end_line.map(|line| top + line).unwrap_or(bottom);
+        let start = start.max(dims.scrollback_top).min(bottom);
+        let end = end.max(start).min(bottom);
+        start..end
+    }
+}
+
+/// Given the lines from the top of the scrollback to the bottom of
+/// the screen, the first of which is `first_row`, returns the
+/// indices of the lines that hold the most recent command and its
+/// output.  If the cursor is on the line of the most recent prompt,
+/// the shell is waiting there for the next command, so the command
+/// before it is the one that is returned.
+fn last_command_lines(
+    first_row: StableRowIndex,
+    lines: &[Line],
+    cursor_row: StableRowIndex,
+) -> Option<Range<usize>> {
+    let prompts: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.is_prompt())
+        .map(|(idx, _)| idx)
+        .collect();
+    let last = *prompts.last()?;
+    if first_row + last as StableRowIndex == cursor_row {
+        if prompts.len() > 1 {
+            Some(prompts[prompts.len() - 2]..last)
+        } else {
+            None
+        }
+    } else {
+        Some(last..lines.len())
+    }
+}
+
 #[async_trait(?Send)]
 pub trait Tab: Downcast {
     fn tab_id(&self) -> TabId;
     fn renderer(&self) -> RefMut<dyn Renderable>;
     fn get_title(&self) -> String;
     fn send_paste(&self, text: &str) -> anyhow::Result<()>;
+    /// Sends text to the tab as though it had been typed, such as the
+    /// text composed by an input method.  Tabs that have a terminal
+    /// encode it in the input encoding of that terminal; the default
+    /// writes it as UTF-8.
+    fn send_text(&self, text: &str) -> anyhow::Result<()> {
+        let mut writer = self.writer();
+        writer.write_all(text.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
     fn reader(&self) -> anyhow::Result<Box<dyn std::io::Read + Send>>;
     fn writer(&self) -> RefMut<dyn std::io::Write>;
     fn resize(&self, size: PtySize) -> anyhow::Result<()>;
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()>;
     fn mouse_event(&self, event: MouseEvent) -> anyhow::Result<()>;
     fn advance_bytes(&self, buf: &[u8]);
+    /// Returns a function that applies the output read from the pty
+    /// to the terminal model; the mux calls it on the thread that
+    /// reads the pty, and then calls `output_processed` on the main
+    /// thread.  Tabs that return None have their output passed to
+    /// `advance_bytes` on the main thread instead.
+    fn output_parser(&self) -> Option<OutputParser> {
+        None
+    }
+    /// Called on the main thread after output has been applied to
+    /// the model by the function returned from `output_parser`
+    fn output_processed(&self) {}
     fn is_dead(&self) -> bool;
+    /// Collects the exit status of the process associated with this
+    /// tab, if it has exited.  This is called when we have been
+    /// notified that a child process has exited; subsequent calls
+    /// to `is_dead` will reflect the result.
+    fn reap(&self) {}
+    /// Returns the exit status of the process associated with this
+    /// tab, once it has been collected by `reap`
+    fn exit_status(&self) -> Option<ExitStatus> {
+        None
+    }
     fn palette(&self) -> ColorPalette;
     fn domain_id(&self) -> DomainId;
 
     fn erase_scrollback(&self) {}
 
+    /// Resets the terminal modes and clears the screen, as though
+    /// the application had sent RIS
+    fn reset_terminal(&self) {}
+
+    /// Returns the colors that should be used to render this tab
+    /// in preference to those from the configuration
+    fn color_overrides(&self) -> TabColorOverrides {
+        TabColorOverrides::default()
+    }
+
+    fn set_color_overrides(&self, _overrides: TabColorOverrides) {}
+
+    /// Returns the font that should be used to render this tab in
+    /// preference to that from the configuration
+    fn font_override(&self) -> TabFontOverride {
+        TabFontOverride::default()
+    }
+
+    fn set_font_override(&self, _font: TabFontOverride) {}
+
+    /// Returns the encoding that the output of the program is decoded
+    /// from
+    fn encoding(&self) -> Encoding {
+        Encoding::Utf8
+    }
+
+    fn set_encoding(&self, _encoding: Encoding) {}
+
     /// Called to advise on whether this tab has focus
     fn focus_changed(&self, _focused: bool) {}
 
@@ -112,6 +253,19 @@ pub trait Tab: Downcast {
         Ok(vec![])
     }
 
+    /// Returns the buffer to which snapshots of the lines of the tab
+    /// are published, for tabs whose lines are available locally
+    fn snapshot_buffer(&self) -> Option<Arc<SnapshotBuffer>> {
+        None
+    }
+
+    /// Returns a snapshot of the lines of the tab that can be searched
+    /// on another thread using `crate::mux::search`, for tabs whose
+    /// lines are available locally
+    fn search_snapshot(&self) -> Option<Arc<RenderSnapshot>> {
+        self.snapshot_buffer().map(|buffer| buffer.latest())
+    }
+
     /// Returns true if the terminal has grabbed the mouse and wants to
     /// give the embedded application a chance to process events.
     /// In practice this controls whether the gui will perform local
@@ -122,6 +276,90 @@ pub trait Tab: Downcast {
 
     fn get_current_working_dir(&self) -> Option<Url>;
 
+    /// Returns the progress of a long running operation in the tab,
+    /// as reported by the application using OSC 9;4
+    fn get_progress(&self) -> Progress {
+        Progress::None
+    }
+
+    /// Clears the progress if the application that reported it can no
+    /// longer clear it itself, such as when it has left the foreground.
+    /// Returns true if the progress was cleared.
+    fn update_progress(&self) -> bool {
+        false
+    }
+
+    /// Returns the text for the status area of the tab bar, as set
+    /// by the application using OSC 7777
+    fn get_status_text(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the desktop notifications that the application has
+    /// requested since the last call
+    fn take_notifications(&self) -> Vec<ToastNotification> {
+        vec![]
+    }
+
+    /// Returns the most recent request from the application to
+    /// resize the terminal, using CSI 8 t or CSI 4 t
+    fn take_resize_request(&self) -> Option<ResizeRequest> {
+        None
+    }
+
+    /// Returns true if the resize requests of this tab are made by
+    /// wezterm itself rather than by an application, and so are
+    /// honored regardless of `allow_window_resize_requests`
+    fn resize_requests_are_trusted(&self) -> bool {
+        false
+    }
+
+    /// Returns true if the application has rung the bell since the
+    /// last call
+    fn take_bell(&self) -> bool {
+        false
+    }
+
+    /// Returns the text of the lines selected by `options`, with the
+    /// wide characters, soft wraps and trailing whitespace handled in
+    /// the same way as when copying a selection
+    fn export_text(&self, options: &ExportTextOptions) -> String {
+        let config = configuration();
+        let mut renderer = self.renderer();
+        let dims = renderer.get_dimensions();
+        let lines = if options.since_last_prompt {
+            let bottom = dims.physical_top + dims.viewport_rows as StableRowIndex;
+            let cursor_row = renderer.get_cursor_position().y;
+            let (first_row, mut lines) = renderer.get_lines(dims.scrollback_top..bottom);
+            match last_command_lines(first_row, &lines, cursor_row) {
+                Some(range) => {
+                    lines.truncate(range.end);
+                    lines.drain(..range.start);
+                    lines
+                }
+                None => {
+                    let screen = ExportTextOptions::default().rows(&dims);
+                    renderer.get_lines(screen).1
+                }
+            }
+        } else {
+            let rows = options.rows(&dims);
+            if rows.start == rows.end {
+                return String::new();
+            }
+            renderer.get_lines(rows).1
+        };
+        selection_text(
+            lines.iter().map(|line| (line, 0..usize::max_value())),
+            false,
+            &SelectionTextOptions {
+                trim_trailing_whitespace: config.selection_trim_trailing_whitespace,
+                preserve_tabs: config.selection_preserve_tabs,
+                escapes: options.escapes,
+            },
+        )
+    }
+
     fn trickle_paste(&self, text: String) -> anyhow::Result<()> {
         if text.len() <= PASTE_CHUNK_SIZE {
             // Send it all now
@@ -0,0 +1,197 @@
+//! Throttles the processing of the output of tabs that aren't visible.
+//! When `inactive_tab_output_batch_milliseconds` is set, the output of
+//! a tab that isn't the active tab of its window is collected and
+//! applied to the terminal model in batches, which costs much less
+//! than parsing each read as it arrives when a program produces a lot
+//! of output.  A batch is applied in full as soon as the tab becomes
+//! visible, when it reaches `inactive_tab_output_buffer_bytes`, or
+//! when it contains an escape sequence whose effect shouldn't wait,
+//! such as a change of title, a notification or a clipboard write.
+use crate::config::configuration;
+use crate::mux::tab::TabId;
+use crate::mux::OutputParser;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many reads may be queued for the parsing thread before the
+/// reading thread waits for it
+const QUEUED_READS: usize = 4;
+
+struct TabThrottle {
+    inactive: Arc<AtomicBool>,
+    /// Used to wake the parsing thread when the tab becomes visible
+    wake: SyncSender<Vec<u8>>,
+}
+
+lazy_static::lazy_static! {
+    static ref TABS: Mutex<HashMap<TabId, TabThrottle>> = Mutex::new(HashMap::new());
+}
+
+/// Updates the set of tabs whose output is throttled.  Tabs that
+/// aren't in `inactive` are visible, and any output that is waiting
+/// to be applied to them is applied immediately.
+pub fn set_inactive_tabs(inactive: &HashSet<TabId>) {
+    for (tab_id, throttle) in TABS.lock().unwrap().iter() {
+        let is_inactive = inactive.contains(tab_id);
+        let was_inactive = throttle.inactive.swap(is_inactive, Ordering::AcqRel);
+        if was_inactive && !is_inactive {
+            // If the queue is full then the thread is about to
+            // process it anyway, and will see the new state
+            throttle.wake.try_send(vec![]).ok();
+        }
+    }
+}
+
+/// Passes the output read from the pty of a tab to the thread that
+/// parses it; the thread exits once this has been dropped
+pub struct OutputSender {
+    tab_id: TabId,
+    sender: SyncSender<Vec<u8>>,
+}
+
+impl OutputSender {
+    /// Queues `data` to be parsed, waiting if the parsing thread is
+    /// too far behind.  Returns false if the thread has exited.
+    pub fn send(&self, data: &[u8]) -> bool {
+        self.sender.send(data.to_vec()).is_ok()
+    }
+}
+
+impl Drop for OutputSender {
+    fn drop(&mut self) {
+        // Drop the other sender, so that the thread sees the end
+        TABS.lock().unwrap().remove(&self.tab_id);
+    }
+}
+
+/// Spawns a thread that applies the output of `tab_id` to its model
+/// using `parser`, calling `processed` after each batch
+pub fn spawn_output_parser(
+    tab_id: TabId,
+    parser: OutputParser,
+    processed: Box<dyn FnMut() + Send>,
+) -> OutputSender {
+    let (sender, receiver) = sync_channel(QUEUED_READS);
+    let inactive = Arc::new(AtomicBool::new(false));
+    TABS.lock().unwrap().insert(
+        tab_id,
+        TabThrottle {
+            inactive: Arc::clone(&inactive),
+            wake: sender.clone(),
+        },
+    );
+
+    std::thread::spawn(move || parse_output(receiver, &inactive, parser, processed));
+    OutputSender { tab_id, sender }
+}
+
+fn parse_output(
+    receiver: Receiver<Vec<u8>>,
+    inactive: &AtomicBool,
+    mut parser: OutputParser,
+    mut processed: Box<dyn FnMut() + Send>,
+) {
+    let mut batch: Vec<u8> = vec![];
+    let mut batch_started = Instant::now();
+
+    loop {
+        let config = configuration();
+        let interval = Duration::from_millis(config.inactive_tab_output_batch_milliseconds);
+        let throttled = interval != Duration::from_millis(0) && inactive.load(Ordering::Acquire);
+
+        let data = if batch.is_empty() {
+            receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            let remaining = interval
+                .checked_sub(batch_started.elapsed())
+                .unwrap_or_else(|| Duration::from_millis(0));
+            receiver.recv_timeout(remaining)
+        };
+
+        let mut urgent = false;
+        match data {
+            Ok(data) => {
+                if batch.is_empty() {
+                    batch_started = Instant::now();
+                }
+                // Look back a little, in case the start of an escape
+                // sequence was at the end of the prior read
+                let scan_from = batch.len().saturating_sub(8);
+                batch.extend_from_slice(&data);
+                urgent = has_urgent_sequence(&batch[scan_from..]);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                if !batch.is_empty() {
+                    parser(&batch);
+                    processed();
+                }
+                return;
+            }
+        }
+
+        // The tab may have become visible while we were waiting
+        let throttled = throttled && inactive.load(Ordering::Acquire);
+        if batch.is_empty()
+            || (throttled
+                && !urgent
+                && batch.len() < config.inactive_tab_output_buffer_bytes
+                && batch_started.elapsed() < interval)
+        {
+            continue;
+        }
+
+        parser(&batch);
+        batch.clear();
+        processed();
+    }
+}
+
+/// Returns true if `data` contains the start of an OSC sequence that
+/// changes the title (0, 1 and 2), shows a notification (9 and 777)
+/// or writes to the clipboard (52)
+fn has_urgent_sequence(data: &[u8]) -> bool {
+    const URGENT: &[&[u8]] = &[b"0", b"1", b"2", b"9", b"52", b"777"];
+    let mut pos = 0;
+    while pos < data.len() {
+        let params = match data[pos] {
+            0x1b if data.get(pos + 1) == Some(&b']') => &data[pos + 2..],
+            0x9d => &data[pos + 1..],
+            _ => {
+                pos += 1;
+                continue;
+            }
+        };
+        let digits = params.iter().take_while(|b| b.is_ascii_digit()).count();
+        if params.get(digits) == Some(&b';') && URGENT.contains(&&params[..digits]) {
+            return true;
+        }
+        pos += 1;
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn urgent_sequences() {
+        assert!(has_urgent_sequence(b"text\x1b]2;title\x07"));
+        assert!(has_urgent_sequence(b"\x1b]0;title\x1b\\"));
+        assert!(has_urgent_sequence(b"\x1b]52;c;aGVsbG8=\x07"));
+        assert!(has_urgent_sequence(b"\x1b]777;notify;hi;there\x07"));
+        assert!(has_urgent_sequence(b"\x9d9;done\x07"));
+
+        assert!(!has_urgent_sequence(b"plain text"));
+        // Setting the palette or the working directory can wait
+        assert!(!has_urgent_sequence(b"\x1b]4;1;rgb:ff/00/00\x07"));
+        assert!(!has_urgent_sequence(b"\x1b]7;file://host/tmp\x07"));
+        assert!(!has_urgent_sequence(b"\x1b]21;x\x07"));
+        // Not yet complete enough to tell
+        assert!(!has_urgent_sequence(b"\x1b]5"));
+    }
+}
@@ -12,6 +12,8 @@ pub struct Window {
     active: usize,
     clipboard: Option<Arc<dyn Clipboard>>,
     invalidated: bool,
+    /// The name of the workspace, which labels the window
+    workspace: Option<String>,
 }
 
 impl Window {
@@ -22,9 +24,20 @@ impl Window {
             active: 0,
             clipboard: None,
             invalidated: false,
+            workspace: None,
         }
     }
 
+    pub fn get_workspace(&self) -> Option<&str> {
+        self.workspace.as_ref().map(String::as_str)
+    }
+
+    /// Sets the name of the workspace; an empty name clears it
+    pub fn set_workspace(&mut self, workspace: Option<String>) {
+        self.workspace = workspace.filter(|name| !name.is_empty());
+        self.invalidated = true;
+    }
+
     pub fn set_clipboard(&mut self, clipboard: &Arc<dyn Clipboard>) {
         self.clipboard.replace(Arc::clone(clipboard));
     }
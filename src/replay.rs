@@ -0,0 +1,134 @@
+//! Plays back a recording made by the StartRecording key assignment,
+//! or by asciinema, into a tab.  The tab is read-only: its input
+//! controls the playback rather than going to a program.
+use crate::mux::recording::CastFile;
+use crate::termwiztermtab::TermWizTerminal;
+use std::time::{Duration, Instant};
+use termwiz::input::{InputEvent, KeyCode, KeyEvent};
+use termwiz::surface::Change;
+use termwiz::terminal::{ScreenSize, Terminal};
+
+/// How far the arrow keys seek, in seconds of recording time
+const SEEK_SECONDS: f64 = 5.0;
+/// The slowest playback speed; the delay until the next event would
+/// otherwise grow without bound as the speed is halved
+const MIN_SPEED: f64 = 1.0 / 64.0;
+
+/// Parses the `COLSxROWS` data of a resize event
+fn parse_size(data: &str) -> Option<(usize, usize)> {
+    let mut fields = data.splitn(2, 'x');
+    let cols = fields.next()?.parse().ok()?;
+    let rows = fields.next()?.parse().ok()?;
+    if cols == 0 || rows == 0 {
+        return None;
+    }
+    Some((cols, rows))
+}
+
+/// Asks for the window to be resized to fit the recording
+fn resize(term: &mut TermWizTerminal, cols: usize, rows: usize) -> anyhow::Result<()> {
+    term.set_screen_size(ScreenSize {
+        cols,
+        rows,
+        xpixel: 0,
+        ypixel: 0,
+    })
+}
+
+pub fn replay(mut term: TermWizTerminal, cast: CastFile, mut speed: f64) -> anyhow::Result<()> {
+    speed = speed.max(MIN_SPEED);
+    term.set_raw_mode()?;
+    term.render(&[Change::Title(
+        "Replay: Space pauses, arrows seek, +/- change speed, q quits".to_string(),
+    )])?;
+
+    // The position in the recording, in seconds
+    let mut position = 0.0;
+    // The index of the next event to play
+    let mut next = 0;
+    let mut paused = false;
+    let mut last_tick = Instant::now();
+
+    loop {
+        while next < cast.events.len() && cast.events[next].time <= position {
+            let event = &cast.events[next];
+            match event.kind.as_str() {
+                "o" => term.write_raw(event.data.as_bytes())?,
+                "r" => match parse_size(&event.data) {
+                    Some((cols, rows)) => resize(&mut term, cols, rows)?,
+                    None => log::error!("invalid resize event {:?} in recording", event.data),
+                },
+                _ => {}
+            }
+            next += 1;
+        }
+        term.flush()?;
+
+        let wait = if paused || next == cast.events.len() {
+            None
+        } else {
+            let delay = (cast.events[next].time - position) / speed;
+            Some(Duration::from_secs_f64(delay.max(0.0)))
+        };
+
+        match term.poll_input(wait)? {
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }))
+            | Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('q'),
+                ..
+            })) => break,
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(' '),
+                ..
+            })) => paused = !paused,
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::RightArrow,
+                ..
+            })) => position += SEEK_SECONDS,
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::LeftArrow,
+                ..
+            })) => {
+                // The output can't be undone, so reset the terminal
+                // and play the recording up to the new position
+                position = (position - SEEK_SECONDS).max(0.0);
+                next = 0;
+                term.write_raw(b"\x1bc")?;
+                resize(&mut term, cast.width, cast.height)?;
+            }
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('+'),
+                ..
+            })) => speed *= 2.0,
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('-'),
+                ..
+            })) => speed = (speed / 2.0).max(MIN_SPEED),
+            _ => {}
+        }
+
+        let now = Instant::now();
+        if !paused {
+            position += now.duration_since(last_tick).as_secs_f64() * speed;
+        }
+        last_tick = now;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resize_events() {
+        assert_eq!(parse_size("100x30"), Some((100, 30)));
+        assert_eq!(parse_size("100"), None);
+        assert_eq!(parse_size("0x30"), None);
+        assert_eq!(parse_size("ax30"), None);
+    }
+}
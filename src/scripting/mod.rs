@@ -166,6 +166,9 @@ struct TextStyleAttributes {
     /// useful in a `[[font_rules]]` section to implement changing
     /// the text color for eg: bold text.
     pub foreground: Option<termwiz::color::RgbColor>,
+    /// Additional harfbuzz features for this text style
+    #[serde(default)]
+    pub harfbuzz_features: Vec<String>,
 }
 impl_lua_conversion!(TextStyleAttributes);
 
@@ -190,8 +193,10 @@ fn font<'lua>(
         family,
         bold: attrs.bold,
         italic: attrs.italic,
+        ..Default::default()
     });
     text_style.foreground = attrs.foreground;
+    text_style.harfbuzz_features = attrs.harfbuzz_features;
 
     Ok(text_style)
 }
@@ -216,9 +221,11 @@ fn font_with_fallback<'lua>(
             family,
             bold: attrs.bold,
             italic: attrs.italic,
+            ..Default::default()
         });
     }
     text_style.foreground = attrs.foreground;
+    text_style.harfbuzz_features = attrs.harfbuzz_features;
 
     Ok(text_style)
 }
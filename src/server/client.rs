@@ -8,7 +8,7 @@ use crate::server::domain::{ClientDomain, ClientDomainConfig};
 use crate::server::pollable::*;
 use crate::server::tab::ClientTab;
 use crate::server::UnixStream;
-use crate::ssh::ssh_connect_with_ui;
+use crate::ssh::{ssh_connect_with_ui, SshConnectOptions};
 use anyhow::{anyhow, bail, Context, Error};
 use crossbeam::channel::TryRecvError;
 use filedescriptor::{pollfd, AsRawSocketDescriptor};
@@ -389,7 +389,12 @@ impl Reconnectable {
         initial: bool,
         ui: &mut ConnectionUI,
     ) -> anyhow::Result<()> {
-        let sess = ssh_connect_with_ui(&ssh_dom.remote_address, &ssh_dom.username, ui)?;
+        let sess = ssh_connect_with_ui(
+            &ssh_dom.remote_address,
+            &ssh_dom.username,
+            &SshConnectOptions::default(),
+            ui,
+        )?;
         sess.set_timeout(ssh_dom.timeout.as_secs().try_into()?);
 
         let mut chan = sess.channel_session()?;
@@ -517,8 +522,12 @@ impl Reconnectable {
         if let Some(Ok(ssh_params)) = tls_client.ssh_parameters() {
             if self.tls_creds.is_none() {
                 // We need to bootstrap via an ssh session
-                let sess =
-                    ssh_connect_with_ui(&ssh_params.host_and_port, &ssh_params.username, ui)?;
+                let sess = ssh_connect_with_ui(
+                    &ssh_params.host_and_port,
+                    &ssh_params.username()?,
+                    &SshConnectOptions::default(),
+                    ui,
+                )?;
 
                 let creds = ui.run_and_log_error(|| {
                     let mut chan = sess.channel_session()?;
@@ -863,6 +872,7 @@ impl Client {
     rpc!(spawn, Spawn, SpawnResponse);
     rpc!(write_to_tab, WriteToTab, UnitResponse);
     rpc!(send_paste, SendPaste, UnitResponse);
+    rpc!(send_text, SendText, UnitResponse);
     rpc!(key_down, SendKeyDown, UnitResponse);
     rpc!(mouse_event, SendMouseEvent, UnitResponse);
     rpc!(resize, Resize, UnitResponse);
@@ -879,4 +889,5 @@ impl Client {
         SearchTabScrollbackRequest,
         SearchTabScrollbackResponse
     );
+    rpc!(get_text, GetText, GetTextResponse);
 }
@@ -246,7 +246,7 @@ macro_rules! pdu {
 /// The overall version of the codec.
 /// This must be bumped when backwards incompatible changes
 /// are made to the types and protocol.
-pub const CODEC_VERSION: usize = 4;
+pub const CODEC_VERSION: usize = 9;
 
 // Defines the Pdu enum.
 // Each struct has an explicit identifying number.
@@ -278,6 +278,9 @@ pdu! {
     TabLivenessResponse: 30,
     SearchTabScrollbackRequest: 31,
     SearchTabScrollbackResponse: 32,
+    GetText: 33,
+    GetTextResponse: 34,
+    SendText: 35,
 }
 
 impl Pdu {
@@ -436,6 +439,7 @@ pub struct WindowAndTabEntry {
     pub title: String,
     pub size: PtySize,
     pub working_dir: Option<SerdeUrl>,
+    pub workspace: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
@@ -471,6 +475,14 @@ pub struct SendPaste {
     pub data: String,
 }
 
+/// Text that is sent to the tab as though it had been typed, and that
+/// the server encodes in the input encoding of the tab
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SendText {
+    pub tab_id: TabId,
+    pub data: String,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct SendKeyDown {
     pub tab_id: TabId,
@@ -547,6 +559,7 @@ pub struct GetTabRenderChangesResponse {
     pub mouse_grabbed: bool,
     pub cursor_position: StableCursorPosition,
     pub dimensions: RenderableDimensions,
+    pub reverse_video: bool,
     pub dirty_lines: Vec<Range<StableRowIndex>>,
     pub title: String,
     pub working_dir: Option<SerdeUrl>,
@@ -711,6 +724,17 @@ pub struct SearchTabScrollbackResponse {
     pub results: Vec<crate::mux::tab::SearchResult>,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetText {
+    pub tab_id: TabId,
+    pub options: crate::mux::tab::ExportTextOptions,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetTextResponse {
+    pub text: String,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1,4 +1,6 @@
-use crate::config::{SshDomain, TlsDomainClient, UnixDomain};
+use crate::config::{
+    SshDomain, TabColorOverrides, TlsDomainClient, UnixDomain, WindowConfigOverrides,
+};
 use crate::connui::ConnectionUI;
 use crate::font::FontConfiguration;
 use crate::frontend::front_end;
@@ -24,6 +26,8 @@ pub struct ClientInner {
     pub remote_domain_id: DomainId,
     remote_to_local_window: Mutex<HashMap<WindowId, WindowId>>,
     remote_to_local_tab: Mutex<HashMap<TabId, TabId>>,
+    /// The colors to use for tabs in this domain
+    pub color_overrides: TabColorOverrides,
 }
 
 impl ClientInner {
@@ -116,10 +120,23 @@ impl ClientDomainConfig {
             ClientDomainConfig::Ssh(ssh) => ssh.connect_automatically,
         }
     }
+
+    pub fn color_overrides(&self) -> TabColorOverrides {
+        match self {
+            ClientDomainConfig::Unix(_) | ClientDomainConfig::Tls(_) => {
+                TabColorOverrides::default()
+            }
+            ClientDomainConfig::Ssh(ssh) => ssh.colors.clone(),
+        }
+    }
 }
 
 impl ClientInner {
-    pub fn new(local_domain_id: DomainId, client: Client) -> Self {
+    pub fn new(
+        local_domain_id: DomainId,
+        client: Client,
+        color_overrides: TabColorOverrides,
+    ) -> Self {
         // Assumption: that the domain id on the other end is
         // always the first created default domain.  In the future
         // we'll add a way to discover/enumerate domains to populate
@@ -131,6 +148,7 @@ impl ClientInner {
             remote_domain_id,
             remote_to_local_window: Mutex::new(HashMap::new()),
             remote_to_local_tab: Mutex::new(HashMap::new()),
+            color_overrides,
         }
     }
 }
@@ -248,6 +266,9 @@ impl ClientDomain {
                 if window.idx_by_id(tab.tab_id()).is_none() {
                     window.push(&tab);
                 }
+                if window.get_workspace() != entry.workspace.as_ref().map(String::as_str) {
+                    window.set_workspace(entry.workspace.clone());
+                }
             } else {
                 log::info!("spawn new local window");
                 let fonts = Rc::new(FontConfiguration::new());
@@ -257,7 +278,15 @@ impl ClientDomain {
 
                 front_end()
                     .unwrap()
-                    .spawn_new_window(&fonts, &tab, local_window_id)
+                    .spawn_new_window(
+                        &fonts,
+                        &tab,
+                        local_window_id,
+                        &WindowConfigOverrides {
+                            workspace: entry.workspace.clone(),
+                            ..WindowConfigOverrides::default()
+                        },
+                    )
                     .unwrap();
             }
         }
@@ -277,7 +306,11 @@ impl ClientDomain {
             .downcast_ref::<Self>()
             .ok_or_else(|| anyhow!("domain {} is not a ClientDomain", domain_id))?;
 
-        let inner = Arc::new(ClientInner::new(domain_id, client));
+        let inner = Arc::new(ClientInner::new(
+            domain_id,
+            client,
+            domain.config.color_overrides(),
+        ));
         *domain.inner.borrow_mut() = Some(Arc::clone(&inner));
 
         Self::process_tab_list(inner, tabs)?;
@@ -387,3 +420,52 @@ impl Domain for ClientDomain {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Palette;
+    use std::collections::BTreeMap;
+    use termwiz::color::RgbColor;
+    use wezterm_term::color::ColorPalette;
+
+    #[test]
+    fn ssh_domain_colors_apply_to_its_tabs() {
+        let red = RgbColor::new(0xff, 0, 0);
+        let green = RgbColor::new(0, 0xff, 0);
+        let config = ClientDomainConfig::Ssh(SshDomain {
+            colors: TabColorOverrides {
+                color_scheme: Some("Production".to_string()),
+                background: Some(red),
+            },
+            ..Default::default()
+        });
+
+        let mut schemes = BTreeMap::new();
+        schemes.insert(
+            "Production".to_string(),
+            Palette {
+                foreground: Some(green),
+                ..Default::default()
+            },
+        );
+
+        // This is how the window resolves the palette of a ClientTab,
+        // whose overrides are taken from its domain
+        let palette = config.color_overrides().apply_to_palette(
+            |name| schemes.get(name).cloned().map(Into::into),
+            ColorPalette::default,
+        );
+        assert_eq!(palette.foreground, green);
+        assert_eq!(palette.background, red);
+
+        // Domains without colors leave the palette of the tab alone
+        let unix = ClientDomainConfig::Unix(Default::default());
+        let palette = unix.color_overrides().apply_to_palette(
+            |name| schemes.get(name).cloned().map(Into::into),
+            ColorPalette::default,
+        );
+        assert_eq!(palette.foreground, ColorPalette::default().foreground);
+        assert_eq!(palette.background, ColorPalette::default().background);
+    }
+}
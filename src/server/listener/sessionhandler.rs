@@ -24,6 +24,7 @@ struct PerTab {
     dimensions: RenderableDimensions,
     dirty_lines: RangeSet<StableRowIndex>,
     mouse_grabbed: bool,
+    reverse_video: bool,
 }
 
 impl PerTab {
@@ -48,6 +49,11 @@ impl PerTab {
             changed = true;
         }
 
+        let reverse_video = tab.renderer().is_reverse_video();
+        if reverse_video != self.reverse_video {
+            changed = true;
+        }
+
         let title = tab.get_title();
         if title != self.title {
             changed = true;
@@ -98,6 +104,7 @@ impl PerTab {
         self.dimensions = dims;
         self.dirty_lines = all_dirty_lines;
         self.mouse_grabbed = mouse_grabbed;
+        self.reverse_video = reverse_video;
 
         let dirty_lines = dirty_delta.iter().cloned().collect();
         let bonus_lines = bonus_lines.into();
@@ -107,6 +114,7 @@ impl PerTab {
             dirty_lines,
             dimensions: dims,
             cursor_position,
+            reverse_video,
             title,
             bonus_lines,
             working_dir: working_dir.map(Into::into),
@@ -215,6 +223,7 @@ impl SessionHandler {
                                             pixel_width: 0,
                                         },
                                         working_dir: working_dir.map(Into::into),
+                                        workspace: window.get_workspace().map(str::to_string),
                                     });
                                 }
                             }
@@ -262,6 +271,24 @@ impl SessionHandler {
                     )
                 });
             }
+            Pdu::SendText(SendText { tab_id, data }) => {
+                let sender = self.to_write_tx.clone();
+                let per_tab = self.per_tab(tab_id);
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let tab = mux
+                                .get_tab(tab_id)
+                                .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+                            tab.send_text(&data)?;
+                            maybe_push_tab_changes(&tab, sender, per_tab)?;
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                });
+            }
 
             Pdu::SearchTabScrollbackRequest(SearchTabScrollbackRequest { tab_id, pattern }) => {
                 use crate::mux::tab::Pattern;
@@ -285,6 +312,22 @@ impl SessionHandler {
                 });
             }
 
+            Pdu::GetText(GetText { tab_id, options }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let tab = mux
+                                .get_tab(tab_id)
+                                .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+                            let text = tab.export_text(&options);
+                            Ok(Pdu::GetTextResponse(GetTextResponse { text }))
+                        },
+                        send_response,
+                    )
+                });
+            }
+
             Pdu::Resize(Resize { tab_id, size }) => {
                 spawn_into_main_thread(async move {
                     catch(
@@ -445,6 +488,7 @@ impl SessionHandler {
             | Pdu::UnitResponse { .. }
             | Pdu::TabLivenessResponse { .. }
             | Pdu::SearchTabScrollbackResponse { .. }
+            | Pdu::GetTextResponse { .. }
             | Pdu::GetLinesResponse { .. }
             | Pdu::GetCodecVersionResponse { .. }
             | Pdu::GetTlsCredsResponse { .. }
@@ -1,4 +1,4 @@
-use crate::config::configuration;
+use crate::config::{configuration, TabColorOverrides};
 use crate::mux::domain::DomainId;
 use crate::mux::renderable::{Renderable, RenderableDimensions};
 use crate::mux::tab::{alloc_tab_id, Pattern, SearchResult, Tab, TabId};
@@ -31,6 +31,7 @@ pub struct ClientTab {
     mouse: Rc<RefCell<MouseState>>,
     clipboard: RefCell<Option<Arc<dyn Clipboard>>>,
     mouse_grabbed: RefCell<bool>,
+    color_overrides: RefCell<TabColorOverrides>,
 }
 
 impl ClientTab {
@@ -83,6 +84,7 @@ impl ClientTab {
             reader,
             clipboard: RefCell::new(None),
             mouse_grabbed: RefCell::new(false),
+            color_overrides: RefCell::new(client.color_overrides.clone()),
         }
     }
 
@@ -162,6 +164,27 @@ impl Tab for ClientTab {
         Ok(())
     }
 
+    fn send_text(&self, text: &str) -> anyhow::Result<()> {
+        let client = Arc::clone(&self.client);
+        let remote_tab_id = self.remote_tab_id;
+        let data = text.to_owned();
+        promise::spawn::spawn(async move {
+            client
+                .client
+                .send_text(SendText {
+                    tab_id: remote_tab_id,
+                    data,
+                })
+                .await
+        });
+        self.renderable
+            .borrow()
+            .inner
+            .borrow_mut()
+            .update_last_send();
+        Ok(())
+    }
+
     fn reader(&self) -> anyhow::Result<Box<dyn std::io::Read + Send>> {
         info!("made reader for ClientTab");
         Ok(Box::new(self.reader.read.try_clone()?))
@@ -274,7 +297,7 @@ impl Tab for ClientTab {
         let palette = || {
             let config = configuration();
 
-            if let Some(scheme_name) = config.color_scheme.as_ref() {
+            if let Some(scheme_name) = config.effective_color_scheme() {
                 if let Some(palette) = config.color_schemes.get(scheme_name) {
                     return palette.clone().into();
                 }
@@ -300,6 +323,14 @@ impl Tab for ClientTab {
         self.client.local_domain_id
     }
 
+    fn color_overrides(&self) -> TabColorOverrides {
+        self.color_overrides.borrow().clone()
+    }
+
+    fn set_color_overrides(&self, overrides: TabColorOverrides) {
+        *self.color_overrides.borrow_mut() = overrides;
+    }
+
     fn is_mouse_grabbed(&self) -> bool {
         *self.mouse_grabbed.borrow()
     }
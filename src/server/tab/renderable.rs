@@ -64,6 +64,7 @@ pub struct RenderableInner {
 
     cursor_position: StableCursorPosition,
     pub dimensions: RenderableDimensions,
+    reverse_video: bool,
 
     lines: LruCache<StableRowIndex, LineEntry>,
     pub title: String,
@@ -103,6 +104,7 @@ impl RenderableInner {
             poll_in_progress: AtomicBool::new(false),
             poll_interval: BASE_POLL_INTERVAL,
             cursor_position: StableCursorPosition::default(),
+            reverse_video: false,
             dimensions,
             lines: LruCache::new(configuration().scrollback_lines),
             title: title.to_string(),
@@ -338,6 +340,10 @@ impl RenderableInner {
             self.cursor_position = delta.cursor_position;
         }
         self.dimensions = delta.dimensions;
+        // Toggling reverse video changes the colors of every line,
+        // even those that we already hold
+        let reverse_video_changed = delta.reverse_video != self.reverse_video;
+        self.reverse_video = delta.reverse_video;
         self.title = delta.title;
         self.working_dir = delta.working_dir.map(Into::into);
 
@@ -347,7 +353,7 @@ impl RenderableInner {
             dirty.remove(stable_row);
         }
 
-        if !dirty.is_empty() {
+        if !dirty.is_empty() || reverse_video_changed {
             Mux::get()
                 .unwrap()
                 .notify(crate::mux::MuxNotification::TabOutput(self.local_tab_id));
@@ -718,4 +724,8 @@ impl Renderable for RenderableState {
     fn get_dimensions(&self) -> RenderableDimensions {
         self.inner.borrow().dimensions
     }
+
+    fn is_reverse_video(&self) -> bool {
+        self.inner.borrow().reverse_video
+    }
 }
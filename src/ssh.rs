@@ -1,18 +1,19 @@
 use crate::connui::ConnectionUI;
 use crate::localtab::LocalTab;
 use crate::mux::domain::{alloc_domain_id, Domain, DomainId, DomainState};
-use crate::mux::tab::Tab;
+use crate::mux::tab::{alloc_tab_id, Tab};
 use crate::mux::window::WindowId;
 use crate::mux::Mux;
 use anyhow::{anyhow, bail, Context, Error};
 use async_trait::async_trait;
+use filedescriptor::FileDescriptor;
 use portable_pty::cmdbuilder::CommandBuilder;
 use portable_pty::{PtySize, PtySystem};
 use promise::{Future, Promise};
 use std::collections::HashSet;
 use std::io::Write;
 use std::net::TcpStream;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 impl ssh2::KeyboardInteractivePrompt for ConnectionUI {
@@ -37,18 +38,59 @@ impl ssh2::KeyboardInteractivePrompt for ConnectionUI {
     }
 }
 
-pub fn async_ssh_connect(remote_address: &str, username: &str) -> Future<ssh2::Session> {
+/// Settings that control how we connect to an ssh server, beyond its
+/// address and the name of the user
+#[derive(Debug, Default, Clone)]
+pub struct SshConnectOptions {
+    /// A private key to authenticate with if the agent can't
+    pub identity_file: Option<PathBuf>,
+    /// Reach the server via these hosts, in the form used by the
+    /// ProxyJump option of ssh: `[user@]host[:port]`, separated by
+    /// commas
+    pub proxy_jump: Option<String>,
+}
+
+/// Runs the system ssh client to forward a connection to `host:port`
+/// through the hosts in `proxy_jump`, returning our end of it
+fn proxy_jump_stream(proxy_jump: &str, host: &str, port: u16) -> anyhow::Result<FileDescriptor> {
+    let mut jumps: Vec<&str> = proxy_jump.split(',').collect();
+    let last = jumps.pop().unwrap();
+
+    let (ours, theirs) = filedescriptor::socketpair()?;
+    let mut cmd = std::process::Command::new("ssh");
+    if !jumps.is_empty() {
+        cmd.arg("-J").arg(jumps.join(","));
+    }
+    cmd.arg("-W")
+        .arg(format!("{}:{}", host, port))
+        .arg(format!("ssh://{}", last))
+        .stdin(theirs.as_stdio()?)
+        .stdout(theirs.as_stdio()?);
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("spawning ssh to connect via {}", proxy_jump))?;
+    std::thread::spawn(move || child.wait());
+
+    Ok(ours)
+}
+
+pub fn async_ssh_connect(
+    remote_address: &str,
+    username: &str,
+    options: SshConnectOptions,
+) -> Future<ssh2::Session> {
     let mut promise = Promise::new();
     let future = promise.get_future().unwrap();
     let remote_address = remote_address.to_owned();
     let username = username.to_owned();
-    std::thread::spawn(move || promise.result(ssh_connect(&remote_address, &username)));
+    std::thread::spawn(move || promise.result(ssh_connect(&remote_address, &username, &options)));
     future
 }
 
 pub fn ssh_connect_with_ui(
     remote_address: &str,
     username: &str,
+    options: &SshConnectOptions,
     ui: &mut ConnectionUI,
 ) -> anyhow::Result<ssh2::Session> {
     let cloned_ui = ui.clone();
@@ -65,13 +107,22 @@ pub fn ssh_connect_with_ui(
             }
         };
 
-        ui.output_str(&format!("Connecting to {} using SSH\n", remote_address));
-
-        let tcp = TcpStream::connect(&remote_address)
-            .with_context(|| format!("ssh connecting to {}", remote_address))?;
-        ui.output_str("SSH: Connected OK!\n");
-        tcp.set_nodelay(true)?;
-        sess.set_tcp_stream(tcp);
+        if let Some(proxy_jump) = &options.proxy_jump {
+            ui.output_str(&format!(
+                "Connecting to {} via {} using SSH\n",
+                remote_address, proxy_jump
+            ));
+            let stream = proxy_jump_stream(proxy_jump, remote_host_name, port)?;
+            sess.set_tcp_stream(stream);
+        } else {
+            ui.output_str(&format!("Connecting to {} using SSH\n", remote_address));
+
+            let tcp = TcpStream::connect(&remote_address)
+                .with_context(|| format!("ssh connecting to {}", remote_address))?;
+            ui.output_str("SSH: Connected OK!\n");
+            tcp.set_nodelay(true)?;
+            sess.set_tcp_stream(tcp);
+        }
         sess.handshake()
             .with_context(|| format!("ssh handshake with {}", remote_address))?;
 
@@ -182,6 +233,34 @@ pub fn ssh_connect_with_ui(
                 }
             }
 
+            if !sess.authenticated() && methods.contains("publickey") {
+                if let Some(identity) = &options.identity_file {
+                    let mut result = sess.userauth_pubkey_file(&username, None, identity, None);
+                    if result.is_err() {
+                        // The key may be protected by a passphrase
+                        let passphrase =
+                            ui.password(&format!("🔐 Passphrase for {}: ", identity.display()))?;
+                        result = sess.userauth_pubkey_file(
+                            &username,
+                            None,
+                            identity,
+                            Some(passphrase.as_str()),
+                        );
+                    }
+                    match result {
+                        Err(err) => log::error!(
+                            "while attempting auth with {}: {}",
+                            identity.display(),
+                            err
+                        ),
+                        Ok(_) if sess.authenticated() => {
+                            ui.output_str("publickey auth successful!\n")
+                        }
+                        Ok(_) => {}
+                    }
+                }
+            }
+
             if !sess.authenticated() && methods.contains("password") {
                 ui.output_str(&format!(
                     "Password authentication for {}@{}\n",
@@ -208,10 +287,14 @@ pub fn ssh_connect_with_ui(
     })
 }
 
-pub fn ssh_connect(remote_address: &str, username: &str) -> anyhow::Result<ssh2::Session> {
+pub fn ssh_connect(
+    remote_address: &str,
+    username: &str,
+    options: &SshConnectOptions,
+) -> anyhow::Result<ssh2::Session> {
     let mut ui = ConnectionUI::new();
     ui.title("🔐 wezterm: SSH authentication");
-    let sess = ssh_connect_with_ui(remote_address, username, &mut ui)?;
+    let sess = ssh_connect_with_ui(remote_address, username, options, &mut ui)?;
     ui.close();
     Ok(sess)
 }
@@ -250,7 +333,9 @@ impl Domain for RemoteSshDomain {
         let child = pair.slave.spawn_command(cmd)?;
         log::info!("spawned: {:?}", child);
 
-        let writer = pair.master.try_clone_writer()?;
+        // Input is written from a separate thread so that it doesn't
+        // block the main thread when the child isn't reading it
+        let writer = crate::mux::threaded_pty_writer(pair.master.try_clone_writer()?);
 
         let terminal = wezterm_term::Terminal::new(
             size.rows as usize,
@@ -260,11 +345,18 @@ impl Domain for RemoteSshDomain {
             std::sync::Arc::new(crate::config::TermConfig {}),
             "WezTerm",
             crate::wezterm_version(),
-            Box::new(writer),
+            Box::new(writer.clone()),
         );
 
         let mux = Mux::get().unwrap();
-        let tab: Rc<dyn Tab> = Rc::new(LocalTab::new(terminal, child, pair.master, self.id));
+        let tab: Rc<dyn Tab> = Rc::new(LocalTab::new(
+            alloc_tab_id(),
+            terminal,
+            child,
+            pair.master,
+            writer,
+            self.id,
+        ));
 
         mux.add_tab(&tab)?;
         mux.add_tab_to_window(&tab, window)?;
@@ -0,0 +1,209 @@
+//! Reads the subset of the OpenSSH client configuration file,
+//! `~/.ssh/config`, that `wezterm ssh` needs in order to treat the
+//! host aliases defined there in the same way as `ssh` does.
+//! `Match` blocks and `Include` directives are not supported; the
+//! settings inside `Match` blocks are ignored.
+use crate::config::HOME_DIR;
+use std::path::PathBuf;
+
+/// The settings that apply to a host
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SshHostConfig {
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_file: Option<PathBuf>,
+    pub proxy_jump: Option<String>,
+}
+
+impl SshHostConfig {
+    /// Returns the settings for `host` from the user's ssh config
+    /// file, or no settings if there is no such file
+    pub fn for_host(host: &str) -> Self {
+        let path = HOME_DIR.join(".ssh").join("config");
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text, host),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Collects the settings that apply to `host` from the text of
+    /// a config file.  As with ssh, the first value that is found
+    /// for each setting is the one that is used.
+    fn parse(text: &str, host: &str) -> Self {
+        let mut config = Self::default();
+        // Settings before the first Host line apply to all hosts
+        let mut active = true;
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, value) = match line.find(|c: char| c.is_whitespace() || c == '=') {
+                Some(idx) => (
+                    &line[..idx],
+                    line[idx..]
+                        .trim_start_matches(|c: char| c.is_whitespace() || c == '=')
+                        .trim_matches('"'),
+                ),
+                None => continue,
+            };
+
+            match keyword.to_lowercase().as_str() {
+                "host" => {
+                    let patterns: Vec<&str> = value.split_whitespace().collect();
+                    active = host_matches(&patterns, host);
+                }
+                "match" => active = false,
+                _ if !active => {}
+                "hostname" => {
+                    config
+                        .hostname
+                        .get_or_insert_with(|| value.replace("%h", host));
+                }
+                "user" => {
+                    config.user.get_or_insert_with(|| value.to_string());
+                }
+                "port" => {
+                    if config.port.is_none() {
+                        config.port = value.parse().ok();
+                    }
+                }
+                "identityfile" => {
+                    config
+                        .identity_file
+                        .get_or_insert_with(|| expand_tilde(value));
+                }
+                "proxyjump" => {
+                    config.proxy_jump.get_or_insert_with(|| value.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        if config.proxy_jump.as_ref().map(String::as_str) == Some("none") {
+            config.proxy_jump = None;
+        }
+
+        config
+    }
+}
+
+fn expand_tilde(path: &str) -> PathBuf {
+    if path == "~" {
+        HOME_DIR.clone()
+    } else if path.starts_with("~/") {
+        HOME_DIR.join(&path[2..])
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// Returns true if `host` matches any of the patterns of a Host line
+/// and doesn't match any of its negated patterns
+fn host_matches(patterns: &[&str], host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        if pattern.starts_with('!') {
+            if wildcard_match(&pattern[1..], host) {
+                return false;
+            }
+        } else if wildcard_match(pattern, host) {
+            matched = true;
+        }
+    }
+    matched
+}
+
+/// Matches `s` against a pattern in which `*` matches any sequence of
+/// characters and `?` matches any single character
+fn wildcard_match(pattern: &str, s: &str) -> bool {
+    fn matches(pattern: &[char], s: &[char]) -> bool {
+        match pattern.first() {
+            None => s.is_empty(),
+            Some('*') => (0..=s.len()).any(|skip| matches(&pattern[1..], &s[skip..])),
+            Some('?') => !s.is_empty() && matches(&pattern[1..], &s[1..]),
+            Some(c) => s.first() == Some(c) && matches(&pattern[1..], &s[1..]),
+        }
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    matches(&pattern, &s)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"
+# Comments and blank lines are ignored
+Host dev
+    HostName dev.example.com
+    User wez
+    Port 2222
+    IdentityFile ~/.ssh/dev_key
+
+Host *.internal !bastion.internal
+    ProxyJump=admin@bastion.internal:2200
+    User ops
+
+Host ?ouse
+    HostName %h.example.com
+
+Match host dev
+    User ignored
+
+Host *
+    User fallback
+    Port 22
+"#;
+
+    #[test]
+    fn alias() {
+        assert_eq!(
+            SshHostConfig::parse(CONFIG, "dev"),
+            SshHostConfig {
+                hostname: Some("dev.example.com".to_string()),
+                user: Some("wez".to_string()),
+                port: Some(2222),
+                identity_file: Some(HOME_DIR.join(".ssh/dev_key")),
+                proxy_jump: None,
+            }
+        );
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(
+            SshHostConfig::parse(CONFIG, "db.internal"),
+            SshHostConfig {
+                hostname: None,
+                user: Some("ops".to_string()),
+                port: Some(22),
+                identity_file: None,
+                proxy_jump: Some("admin@bastion.internal:2200".to_string()),
+            }
+        );
+        assert_eq!(
+            SshHostConfig::parse(CONFIG, "bastion.internal").proxy_jump,
+            None
+        );
+        assert_eq!(
+            SshHostConfig::parse(CONFIG, "mouse").hostname,
+            Some("mouse.example.com".to_string())
+        );
+        assert_eq!(SshHostConfig::parse(CONFIG, "grouse").hostname, None);
+    }
+
+    #[test]
+    fn proxy_jump_none() {
+        let config = "Host a\n  ProxyJump none\nHost *\n  ProxyJump jump\n";
+        assert_eq!(SshHostConfig::parse(config, "a").proxy_jump, None);
+        assert_eq!(
+            SshHostConfig::parse(config, "b").proxy_jump,
+            Some("jump".to_string())
+        );
+    }
+}
@@ -60,7 +60,7 @@ impl Inner {
                 let inner = inner.lock().unwrap();
                 let mut data = vec![];
                 for (key, histogram) in &inner.histograms {
-                    if key.name().ends_with(".size") {
+                    if key.name().ends_with(".size") || key.name().ends_with(".pct") {
                         let p50 = histogram.value_at_percentile(50.);
                         let p75 = histogram.value_at_percentile(75.);
                         let p95 = histogram.value_at_percentile(95.);
@@ -3,6 +3,7 @@
 //! input from the user as part of eg: setting up an ssh
 //! session.
 
+use crate::config::WindowConfigOverrides;
 use crate::font::FontConfiguration;
 use crate::frontend::front_end;
 use crate::mux::domain::{alloc_domain_id, Domain, DomainId, DomainState};
@@ -12,7 +13,7 @@ use crate::mux::window::WindowId;
 use crate::mux::Mux;
 use anyhow::{bail, Error};
 use async_trait::async_trait;
-use crossbeam::channel::{unbounded as channel, Receiver, Sender};
+use crossbeam::channel::{bounded, unbounded as channel, Receiver, Select, Sender};
 use filedescriptor::{FileDescriptor, Pipe};
 use portable_pty::*;
 use std::cell::RefCell;
@@ -20,7 +21,7 @@ use std::cell::RefMut;
 use std::io::BufWriter;
 use std::io::Write;
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use termwiz::caps::{Capabilities, ColorLevel, ProbeHints};
 use termwiz::input::{InputEvent, KeyEvent, MouseEvent as TermWizMouseEvent};
@@ -30,7 +31,7 @@ use termwiz::surface::Change;
 use termwiz::terminal::{ScreenSize, Terminal, TerminalWaker};
 use url::Url;
 use wezterm_term::color::ColorPalette;
-use wezterm_term::{KeyCode, KeyModifiers, MouseEvent};
+use wezterm_term::{KeyCode, KeyModifiers, MouseEvent, ResizeRequest};
 
 struct TermWizTerminalDomain {
     domain_id: DomainId,
@@ -87,6 +88,8 @@ pub struct TermWizTerminalTab {
     dead: RefCell<bool>,
     writer: RefCell<Vec<u8>>,
     render_rx: FileDescriptor,
+    /// Set by `TermWizTerminal::set_screen_size`
+    resize_request: Arc<Mutex<Option<ResizeRequest>>>,
 }
 
 impl TermWizTerminalTab {
@@ -96,6 +99,7 @@ impl TermWizTerminalTab {
         height: usize,
         input_tx: Sender<InputEvent>,
         render_rx: FileDescriptor,
+        resize_request: Arc<Mutex<Option<ResizeRequest>>>,
     ) -> Self {
         let tab_id = alloc_tab_id();
 
@@ -118,6 +122,7 @@ impl TermWizTerminalTab {
             render_rx,
             input_tx,
             dead: RefCell::new(false),
+            resize_request,
         }
     }
 }
@@ -150,11 +155,8 @@ impl Tab for TermWizTerminalTab {
     }
 
     fn resize(&self, size: PtySize) -> anyhow::Result<()> {
-        self.input_tx.send(InputEvent::Resized {
-            rows: size.rows as usize,
-            cols: size.cols as usize,
-        })?;
-
+        // As with the pty, update the model before notifying the
+        // application of the new size
         self.terminal.borrow_mut().resize(
             size.rows as usize,
             size.cols as usize,
@@ -162,6 +164,11 @@ impl Tab for TermWizTerminalTab {
             size.pixel_height as usize,
         );
 
+        self.input_tx.send(InputEvent::Resized {
+            rows: size.rows as usize,
+            cols: size.cols as usize,
+        })?;
+
         Ok(())
     }
 
@@ -227,12 +234,87 @@ impl Tab for TermWizTerminalTab {
     fn erase_scrollback(&self) {
         self.terminal.borrow_mut().erase_scrollback();
     }
+
+    fn reset_terminal(&self) {
+        self.terminal.borrow_mut().full_reset();
+    }
+
+    fn take_resize_request(&self) -> Option<ResizeRequest> {
+        self.resize_request.lock().unwrap().take()
+    }
+
+    fn resize_requests_are_trusted(&self) -> bool {
+        true
+    }
 }
 
 pub struct TermWizTerminal {
     render_tx: TermWizTerminalRenderTty,
     input_rx: Receiver<InputEvent>,
     renderer: TerminfoRenderer,
+    wake_tx: Sender<()>,
+    wake_rx: Receiver<()>,
+    resize_request: Arc<Mutex<Option<ResizeRequest>>>,
+}
+
+/// Wakes a TermWizTerminal that is waiting for input, causing
+/// `poll_input` to return `InputEvent::Wake`.  This allows an overlay
+/// that shows live data to repaint when the data changes rather than
+/// only in response to input.
+#[derive(Clone)]
+pub struct TermWizTerminalWaker {
+    wake_tx: Sender<()>,
+}
+
+impl TermWizTerminalWaker {
+    pub fn wake(&self) {
+        // If a wake is already pending then there's no need for another
+        self.wake_tx.try_send(()).ok();
+    }
+}
+
+impl TermWizTerminal {
+    fn new(
+        render_tx: FileDescriptor,
+        input_rx: Receiver<InputEvent>,
+        width: usize,
+        height: usize,
+        resize_request: Arc<Mutex<Option<ResizeRequest>>>,
+    ) -> Self {
+        let (wake_tx, wake_rx) = bounded(1);
+        Self {
+            render_tx: TermWizTerminalRenderTty {
+                render_tx: BufWriter::new(render_tx),
+                screen_size: ScreenSize {
+                    cols: width,
+                    rows: height,
+                    xpixel: 0,
+                    ypixel: 0,
+                },
+            },
+            input_rx,
+            renderer: new_wezterm_terminfo_renderer(),
+            wake_tx,
+            wake_rx,
+            resize_request,
+        }
+    }
+
+    /// Returns a waker for this terminal; see TermWizTerminalWaker.
+    /// The `waker` method of the Terminal trait can't be used here,
+    /// as its type is specific to system terminals.
+    pub fn repaint_waker(&self) -> TermWizTerminalWaker {
+        TermWizTerminalWaker {
+            wake_tx: self.wake_tx.clone(),
+        }
+    }
+
+    /// Passes `data` straight to the terminal in the tab rather than
+    /// rendering changes; this is used to play back recorded output
+    pub fn write_raw(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        self.render_tx.write_all(data)?;
+        Ok(())
+    }
 }
 
 struct TermWizTerminalRenderTty {
@@ -257,20 +339,24 @@ impl termwiz::render::RenderTty for TermWizTerminalRenderTty {
 
 impl TermWizTerminal {
     fn do_input_poll(&mut self, wait: Option<Duration>) -> anyhow::Result<Option<InputEvent>> {
-        if let Some(timeout) = wait {
-            match self.input_rx.recv_timeout(timeout) {
-                Ok(input) => Ok(Some(input)),
-                Err(err) => {
-                    if err.is_timeout() {
-                        Ok(None)
-                    } else {
-                        Err(err.into())
-                    }
-                }
-            }
+        let mut select = Select::new();
+        let input = select.recv(&self.input_rx);
+        select.recv(&self.wake_rx);
+
+        let oper = match wait {
+            Some(timeout) => match select.select_timeout(timeout) {
+                Ok(oper) => oper,
+                Err(_) => return Ok(None),
+            },
+            None => select.select(),
+        };
+
+        if oper.index() == input {
+            // This fails once the tab has gone away
+            Ok(Some(oper.recv(&self.input_rx)?))
         } else {
-            let input = self.input_rx.recv()?;
-            Ok(Some(input))
+            oper.recv(&self.wake_rx)?;
+            Ok(Some(InputEvent::Wake))
         }
     }
 }
@@ -315,8 +401,15 @@ impl termwiz::terminal::Terminal for TermWizTerminal {
         Ok(self.render_tx.screen_size)
     }
 
-    fn set_screen_size(&mut self, _size: ScreenSize) -> anyhow::Result<()> {
-        bail!("TermWizTerminalTab cannot set screen size");
+    /// Asks the window to resize to fit `size`.  The size changes
+    /// once the window has been resized, which is reported by
+    /// `InputEvent::Resized`.
+    fn set_screen_size(&mut self, size: ScreenSize) -> anyhow::Result<()> {
+        self.resize_request.lock().unwrap().replace(ResizeRequest {
+            rows: size.rows,
+            cols: size.cols,
+        });
+        Ok(())
     }
 
     fn render(&mut self, changes: &[Change]) -> anyhow::Result<()> {
@@ -341,7 +434,7 @@ impl termwiz::terminal::Terminal for TermWizTerminal {
 
     fn waker(&self) -> TerminalWaker {
         // TODO: TerminalWaker assumes that we're a SystemTerminal but that
-        // isn't the case here.
+        // isn't the case here.  Use repaint_waker instead.
         panic!("TermWizTerminal::waker called!?");
     }
 }
@@ -350,25 +443,25 @@ pub fn allocate(width: usize, height: usize) -> (TermWizTerminal, Rc<dyn Tab>) {
     let render_pipe = Pipe::new().expect("Pipe creation not to fail");
 
     let (input_tx, input_rx) = channel();
+    let resize_request = Arc::new(Mutex::new(None));
 
-    let renderer = new_wezterm_terminfo_renderer();
-
-    let tw_term = TermWizTerminal {
-        render_tx: TermWizTerminalRenderTty {
-            render_tx: BufWriter::new(render_pipe.write),
-            screen_size: ScreenSize {
-                cols: width,
-                rows: height,
-                xpixel: 0,
-                ypixel: 0,
-            },
-        },
+    let tw_term = TermWizTerminal::new(
+        render_pipe.write,
         input_rx,
-        renderer,
-    };
+        width,
+        height,
+        Arc::clone(&resize_request),
+    );
 
     let domain_id = 0;
-    let tab = TermWizTerminalTab::new(domain_id, width, height, input_tx, render_pipe.read);
+    let tab = TermWizTerminalTab::new(
+        domain_id,
+        width,
+        height,
+        input_tx,
+        render_pipe.read,
+        resize_request,
+    );
 
     // Add the tab to the mux so that the output is processed
     let tab: Rc<dyn Tab> = Rc::new(tab);
@@ -414,28 +507,22 @@ pub async fn run<
     let render_pipe = Pipe::new().expect("Pipe creation not to fail");
     let render_rx = render_pipe.read;
     let (input_tx, input_rx) = channel();
+    let resize_request = Arc::new(Mutex::new(None));
 
-    let renderer = new_wezterm_terminfo_renderer();
-
-    let tw_term = TermWizTerminal {
-        render_tx: TermWizTerminalRenderTty {
-            render_tx: BufWriter::new(render_pipe.write),
-            screen_size: ScreenSize {
-                cols: width,
-                rows: height,
-                xpixel: 0,
-                ypixel: 0,
-            },
-        },
+    let tw_term = TermWizTerminal::new(
+        render_pipe.write,
         input_rx,
-        renderer,
-    };
+        width,
+        height,
+        Arc::clone(&resize_request),
+    );
 
     async fn register_tab(
         input_tx: Sender<InputEvent>,
         render_rx: FileDescriptor,
         width: usize,
         height: usize,
+        resize_request: Arc<Mutex<Option<ResizeRequest>>>,
     ) -> anyhow::Result<WindowId> {
         let mux = Mux::get().unwrap();
 
@@ -445,7 +532,14 @@ pub async fn run<
 
         let window_id = mux.new_empty_window();
 
-        let tab = TermWizTerminalTab::new(domain.domain_id(), width, height, input_tx, render_rx);
+        let tab = TermWizTerminalTab::new(
+            domain.domain_id(),
+            width,
+            height,
+            input_tx,
+            render_rx,
+            resize_request,
+        );
         let tab: Rc<dyn Tab> = Rc::new(tab);
 
         mux.add_tab(&tab)?;
@@ -454,13 +548,18 @@ pub async fn run<
         let fontconfig = Rc::new(FontConfiguration::new());
 
         let gui = front_end().unwrap();
-        gui.spawn_new_window(&fontconfig, &tab, window_id)?;
+        gui.spawn_new_window(
+            &fontconfig,
+            &tab,
+            window_id,
+            &WindowConfigOverrides::default(),
+        )?;
 
         Ok(window_id)
     }
 
     let window_id: WindowId = promise::spawn::spawn_into_main_thread(async move {
-        register_tab(input_tx, render_rx, width, height).await
+        register_tab(input_tx, render_rx, width, height, resize_request).await
     })
     .await
     .unwrap_or_else(|| bail!("task panicked or was cancelled"))?;
@@ -27,9 +27,47 @@ pub struct ColorPalette {
     pub cursor_border: RgbColor,
     pub selection_fg: RgbColor,
     pub selection_bg: RgbColor,
+    /// When set, selected text keeps its own color if that is legible
+    /// over the selection, and is only drawn in `selection_fg` if not
+    pub selection_fg_auto: bool,
+    /// The opacity of `selection_bg`, which is blended over the
+    /// background of the selected cells
+    pub selection_bg_alpha: u8,
     pub scrollbar_thumb: RgbColor,
 }
 
+/// The contrast ratio that selected text must have against the
+/// selection for its own color to be kept when `selection_fg_auto`
+/// is set; this is the WCAG minimum for legible text
+pub const MIN_SELECTION_CONTRAST: f32 = 4.5;
+
+/// Returns the WCAG relative luminance of a color
+fn relative_luminance(color: RgbColor) -> f32 {
+    let (r, g, b, _) = color.to_linear_tuple_rgba();
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Returns the WCAG contrast ratio of two colors, which ranges from
+/// 1 for identical colors to 21 for black against white
+pub fn contrast_ratio(a: RgbColor, b: RgbColor) -> f32 {
+    let a = relative_luminance(a);
+    let b = relative_luminance(b);
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Blends `color` with opacity `alpha` over `under`
+fn blend(color: RgbColor, alpha: u8, under: RgbColor) -> RgbColor {
+    let mix = |c: u8, u: u8| {
+        let alpha = alpha as u32;
+        ((c as u32 * alpha + u as u32 * (255 - alpha) + 127) / 255) as u8
+    };
+    RgbColor::new(
+        mix(color.red, under.red),
+        mix(color.green, under.green),
+        mix(color.blue, under.blue),
+    )
+}
+
 /// Adjust the color to make it appear disabled.
 /// This is not defined on RgbColor itself in order
 /// to avoid termwiz requiring a dep on the palette crate.
@@ -76,6 +114,18 @@ impl ColorPalette {
         }
     }
 
+    /// Returns the foreground and background colors of a selected
+    /// cell whose own colors are `fg` and `bg`
+    pub fn selection_colors(&self, fg: RgbColor, bg: RgbColor) -> (RgbColor, RgbColor) {
+        let bg = blend(self.selection_bg, self.selection_bg_alpha, bg);
+        let fg = if self.selection_fg_auto && contrast_ratio(fg, bg) >= MIN_SELECTION_CONTRAST {
+            fg
+        } else {
+            self.selection_fg
+        };
+        (fg, bg)
+    }
+
     /// Returns a greyed out version of the whole palette
     pub fn grey_out(&self) -> Self {
         Self {
@@ -87,9 +137,25 @@ impl ColorPalette {
             cursor_border: grey_out(self.cursor_border),
             selection_fg: grey_out(self.selection_fg),
             selection_bg: grey_out(self.selection_bg),
+            selection_fg_auto: self.selection_fg_auto,
+            selection_bg_alpha: self.selection_bg_alpha,
             scrollbar_thumb: grey_out(self.scrollbar_thumb),
         }
     }
+
+    /// Returns the palette to use when the screen is in reverse video
+    /// mode.  The default foreground and background are swapped, as are
+    /// the cursor colors, so cells that are themselves reversed render
+    /// with the normal colors.
+    pub fn reverse_video(&self) -> Self {
+        Self {
+            foreground: self.background,
+            background: self.foreground,
+            cursor_fg: self.cursor_bg,
+            cursor_bg: self.cursor_fg,
+            ..self.clone()
+        }
+    }
 }
 
 impl Default for ColorPalette {
@@ -242,7 +308,43 @@ impl Default for ColorPalette {
             cursor_border,
             selection_fg,
             selection_bg,
+            selection_fg_auto: false,
+            selection_bg_alpha: 0xff,
             scrollbar_thumb,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn selection_colors() {
+        let white = RgbColor::new(0xff, 0xff, 0xff);
+        let black = RgbColor::new(0, 0, 0);
+        let blue = RgbColor::new(0, 0, 0xee);
+        let yellow = RgbColor::new(0xff, 0xff, 0);
+
+        let mut palette = ColorPalette::default();
+        palette.selection_fg = black;
+        palette.selection_bg = white;
+
+        // By default the selection colors replace those of the cell
+        assert_eq!(palette.selection_colors(blue, yellow), (black, white));
+
+        // In auto mode, only illegible text is given the selection fg
+        palette.selection_fg_auto = true;
+        assert_eq!(palette.selection_colors(blue, yellow), (blue, white));
+        assert_eq!(palette.selection_colors(yellow, blue), (black, white));
+        assert!(contrast_ratio(blue, white) >= MIN_SELECTION_CONTRAST);
+
+        // A translucent selection is blended over the cell background
+        palette.selection_fg_auto = false;
+        palette.selection_bg_alpha = 0x80;
+        let (_, bg) = palette.selection_colors(blue, black);
+        assert_eq!(bg, RgbColor::new(0x80, 0x80, 0x80));
+        palette.selection_bg_alpha = 0;
+        assert_eq!(palette.selection_colors(blue, yellow).1, yellow);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::color::ColorPalette;
+use termwiz::cell::UnicodeVersion;
 use termwiz::hyperlink::Rule as HyperlinkRule;
 
 /// TerminalConfiguration allows for the embedding application to pass configuration
@@ -6,7 +7,9 @@ use termwiz::hyperlink::Rule as HyperlinkRule;
 /// The configuration can be changed at runtime; provided that the implementation
 /// increments the generation counter appropriately, the changes will be detected
 /// and applied at the next appropriate opportunity.
-pub trait TerminalConfiguration: std::fmt::Debug {
+/// The terminal may be driven from a thread other than the one that
+/// created it, so implementations must be usable from any thread.
+pub trait TerminalConfiguration: std::fmt::Debug + Send + Sync {
     /// Returns a generation counter for the active
     /// configuration.  If the implementation may be
     /// changed at runtime, it must increment the generation
@@ -28,6 +31,26 @@ pub trait TerminalConfiguration: std::fmt::Debug {
         false
     }
 
+    /// Return true if a full reset (RIS) should also discard the
+    /// scrollback, rather than just clearing the visible screen
+    fn clear_scrollback_on_reset(&self) -> bool {
+        false
+    }
+
+    /// Return true if bytes from 0x80 to 0x9f in the output, that aren't
+    /// part of a UTF-8 sequence, are the 8-bit forms of the C1 controls,
+    /// as sent by some legacy systems.  Otherwise they are invalid UTF-8.
+    fn enable_8bit_c1_controls(&self) -> bool {
+        false
+    }
+
+    /// Returns the version of Unicode whose character widths are used
+    /// to lay out text, until the application selects another one
+    /// using the iTerm2 `UnicodeVersion` escape sequence.
+    fn unicode_version(&self) -> UnicodeVersion {
+        UnicodeVersion::LATEST
+    }
+
     /// Returns the current generation and its associated hyperlink rules.
     /// hyperlink rules are used to recognize and automatically generate
     /// hyperlink attributes for runs of text that match the provided rules.
@@ -76,17 +76,38 @@ impl Screen {
         scrollback_size(&self.config, self.allow_scrollback)
     }
 
+    /// Rewraps the lines to `physical_cols`, returning the new
+    /// positions of the cursor and of the `saved` cursor
     fn rewrap_lines(
         &mut self,
         physical_cols: usize,
         physical_rows: usize,
         cursor_x: usize,
         cursor_y: PhysRowIndex,
-    ) -> (usize, PhysRowIndex) {
+        saved: Option<(usize, PhysRowIndex)>,
+        is_active: bool,
+    ) -> ((usize, PhysRowIndex), Option<(usize, PhysRowIndex)>) {
         let mut rewrapped = VecDeque::new();
         let mut logical_line: Option<Line> = None;
         let mut logical_cursor_x: Option<usize> = None;
-        let mut adjusted_cursor = (cursor_y, cursor_y);
+        let mut logical_saved_x: Option<usize> = None;
+        let mut adjusted_cursor = (cursor_x, cursor_y);
+        let mut adjusted_saved = None;
+        let num_lines = self.lines.len();
+        let narrowing = is_active && physical_cols < self.physical_cols;
+
+        // A shell that is redrawing its prompt repaints every line of
+        // it, not just the line with the cursor.  If the application
+        // marked where the prompt starts, the lines from there to the
+        // cursor are treated as belonging to the prompt.
+        let prompt_start = if narrowing {
+            self.lines
+                .iter()
+                .take(cursor_y + 1)
+                .rposition(Line::is_prompt)
+        } else {
+            None
+        };
 
         for (phys_idx, mut line) in self.lines.drain(..).enumerate() {
             line.invalidate_implicit_hyperlinks();
@@ -96,17 +117,22 @@ impl Screen {
                 line.set_last_cell_was_wrapped(false);
             }
 
-            let line = match logical_line.take() {
-                None => {
-                    if phys_idx == cursor_y {
-                        logical_cursor_x = Some(cursor_x);
-                    }
-                    line
+            // The final line has no successor to be joined with, so it
+            // terminates its logical line even if it is marked as wrapped
+            let was_wrapped = was_wrapped && phys_idx + 1 < num_lines;
+
+            let prior_len = logical_line.as_ref().map_or(0, |prior| prior.cells().len());
+            if phys_idx == cursor_y {
+                logical_cursor_x = Some(cursor_x + prior_len);
+            }
+            if let Some((saved_x, saved_y)) = saved {
+                if phys_idx == saved_y {
+                    logical_saved_x = Some(saved_x + prior_len);
                 }
+            }
+            let mut line = match logical_line.take() {
+                None => line,
                 Some(mut prior) => {
-                    if phys_idx == cursor_y {
-                        logical_cursor_x = Some(cursor_x + prior.cells().len());
-                    }
                     prior.append_line(line);
                     prior
                 }
@@ -117,10 +143,38 @@ impl Screen {
                 continue;
             }
 
+            let content_len = line
+                .cells()
+                .iter()
+                .rposition(|c| c.str() != " ")
+                .map(|idx| idx + 1)
+                .unwrap_or(0);
+
             if let Some(x) = logical_cursor_x.take() {
-                let num_lines = x / physical_cols;
-                let last_x = x - (num_lines * physical_cols);
-                adjusted_cursor = (last_x, rewrapped.len() + num_lines);
+                // When narrowing, anything to the right of the cursor on
+                // its logical line is about to be split across additional
+                // rows.  The application (typically a shell redrawing its
+                // prompt in response to SIGWINCH) will repaint from the
+                // cursor onwards, so drop that content now rather than
+                // leaving stale fragments behind on the wrapped rows.
+                if narrowing && x < content_len && content_len > physical_cols {
+                    line.resize(x);
+                }
+                adjusted_cursor = Self::rewrapped_position(x, physical_cols, rewrapped.len());
+            } else if narrowing
+                && content_len > physical_cols
+                && prompt_start.map_or(false, |start| phys_idx >= start && phys_idx < cursor_y)
+            {
+                // An earlier line of the prompt, such as one with a right
+                // aligned segment that reached the right margin.  The
+                // shell is about to repaint it, so rather than wrapping
+                // the overflow onto a row of its own, where nothing would
+                // ever clear it, discard it.
+                line.resize(physical_cols);
+            }
+
+            if let Some(x) = logical_saved_x.take() {
+                adjusted_saved = Some(Self::rewrapped_position(x, physical_cols, rewrapped.len()));
             }
 
             if line.cells().len() <= physical_cols {
@@ -145,20 +199,57 @@ impl Screen {
             self.lines.pop_back();
         }
 
-        adjusted_cursor
+        // A saved position on a row that no longer exists, such as a
+        // blank row that was pruned, keeps its distance from the cursor
+        let adjusted_saved = saved.map(|(saved_x, saved_y)| {
+            adjusted_saved.unwrap_or_else(|| {
+                (
+                    saved_x,
+                    (adjusted_cursor.1 + saved_y).saturating_sub(cursor_y),
+                )
+            })
+        });
+
+        (adjusted_cursor, adjusted_saved)
     }
 
-    /// Resize the physical, viewable portion of the screen
+    /// Returns the position in the rewrapped lines of the cell at `x`
+    /// in a logical line that starts at the row `first_row`.
+    /// If `x` falls on an exact multiple of the width, the position is
+    /// just beyond the right margin of the prior row; that is where it
+    /// would be had the content been printed at this width, and the
+    /// caller will treat it as a pending wrap position.
+    fn rewrapped_position(
+        x: usize,
+        physical_cols: usize,
+        first_row: PhysRowIndex,
+    ) -> (usize, PhysRowIndex) {
+        let (num_lines, last_x) = if x > 0 && x % physical_cols == 0 {
+            (x / physical_cols - 1, physical_cols)
+        } else {
+            (x / physical_cols, x % physical_cols)
+        };
+        (last_x, first_row + num_lines)
+    }
+
+    /// Resize the physical, viewable portion of the screen.
+    /// `is_active` should be true if this is the screen that is currently
+    /// being displayed, and thus the screen to which the cursor belongs.
+    /// `saved` is the position that was saved by DECSC, which is moved
+    /// along with the text that it was saved over; the new positions
+    /// of both it and the cursor are returned.
     pub fn resize(
         &mut self,
         physical_rows: usize,
         physical_cols: usize,
         cursor: CursorPosition,
-    ) -> CursorPosition {
+        saved: Option<CursorPosition>,
+        is_active: bool,
+    ) -> (CursorPosition, Option<CursorPosition>) {
         let physical_rows = physical_rows.max(1);
         let physical_cols = physical_cols.max(1);
         if physical_rows == self.physical_rows && physical_cols == self.physical_cols {
-            return cursor;
+            return (cursor, saved);
         }
         log::debug!("resize screen to {}x{}", physical_cols, physical_rows);
 
@@ -172,14 +263,28 @@ impl Screen {
             }
         }
 
-        let (cursor_x, cursor_y) = if physical_cols != self.physical_cols {
+        // The saved position is tracked by its row in the lines, which
+        // is unaffected by the resize until the rows are rewrapped
+        let saved_phys = saved.map(|saved| {
+            let phys = cursor_phys as i64 + saved.y - cursor.y;
+            (saved.x, phys.max(0) as PhysRowIndex)
+        });
+
+        let ((cursor_x, cursor_y), saved_phys) = if physical_cols != self.physical_cols {
             // Check to see if we need to rewrap lines that were
             // wrapped due to reaching the right hand side of the terminal.
             // For each one that we find, we need to join it with its
             // successor and then re-split it
-            self.rewrap_lines(physical_cols, physical_rows, cursor.x, cursor_phys)
+            self.rewrap_lines(
+                physical_cols,
+                physical_rows,
+                cursor.x,
+                cursor_phys,
+                saved_phys,
+                is_active,
+            )
         } else {
-            (cursor.x, cursor_phys)
+            ((cursor.x, cursor_phys), saved_phys)
         };
 
         let capacity = physical_rows + self.scrollback_size();
@@ -212,14 +317,36 @@ impl Screen {
             self.lines.push_back(Line::with_width(physical_cols));
         }
 
+        let saved = saved.and_then(|saved| {
+            let (x, phys) = saved_phys?;
+            let top = self.lines.len().saturating_sub(physical_rows);
+            Some(CursorPosition {
+                x,
+                y: (phys as i64 - top as i64).max(0),
+                ..saved
+            })
+        });
+
+        // The alternate screen has no scrollback, so lines that the rewrap
+        // pushed off the top are discarded rather than being retained as
+        // though they were scrollback
+        if !self.allow_scrollback {
+            let excess = self.lines.len().saturating_sub(physical_rows);
+            for _ in 0..excess {
+                self.lines.pop_front();
+            }
+            self.stable_row_index_offset += excess;
+        }
+
         self.physical_rows = physical_rows;
         self.physical_cols = physical_cols;
-        CursorPosition {
+        let cursor = CursorPosition {
             x: cursor_x,
             y: vis_cursor_y,
             shape: cursor.shape,
             visibility: cursor.visibility,
-        }
+        };
+        (cursor, saved)
     }
 
     /// Get mutable reference to a line, relative to start of scrollback.
@@ -257,21 +384,74 @@ impl Screen {
         self.lines.iter().map(|l| l.clone()).collect()
     }
 
-    pub fn insert_cell(&mut self, x: usize, y: VisibleRowIndex, right_margin: usize) {
+    /// Inserts a blank cell with the specified attributes at x, moving
+    /// the cells up to the right margin one position to the right.
+    pub fn insert_cell(
+        &mut self,
+        x: usize,
+        y: VisibleRowIndex,
+        right_margin: usize,
+        attr: &CellAttributes,
+    ) {
         let phys_cols = self.physical_cols;
 
+        self.split_double_width(x, y);
         let line_idx = self.phys_row(y);
         let line = self.line_mut(line_idx);
-        line.insert_cell(x, Cell::default(), right_margin);
+        // Pad out short lines so that the cell that is pushed past the
+        // right margin is the one that is removed, and the cells beyond
+        // the margin stay where they are
+        if line.cells().len() < right_margin {
+            line.resize(right_margin);
+        }
+        line.insert_cell(x, Cell::new(' ', attr.clone()), right_margin);
         if line.cells().len() > phys_cols {
             line.resize(phys_cols);
         }
+        line.set_dirty();
+
+        // If the second half of a double width character was pushed
+        // past the right margin, the first half has to go too
+        if line.cells()[right_margin - 1].width() > 1 {
+            let blank = Cell::new(' ', line.cells()[right_margin - 1].attrs().clone());
+            line.set_cell(right_margin - 1, blank);
+        }
     }
 
-    pub fn erase_cell(&mut self, x: usize, y: VisibleRowIndex, right_margin: usize) {
+    /// Removes the cell at x, moving the cells up to the right margin
+    /// one position to the left and placing a blank cell with the
+    /// specified attributes at the right margin.
+    pub fn erase_cell(
+        &mut self,
+        x: usize,
+        y: VisibleRowIndex,
+        right_margin: usize,
+        attr: &CellAttributes,
+    ) {
         let line_idx = self.phys_row(y);
         let line = self.line_mut(line_idx);
+        if line.cells().len() < right_margin {
+            line.resize(right_margin);
+        }
         line.erase_cell_with_margin(x, right_margin);
+        line.set_cell(right_margin - 1, Cell::new(' ', attr.clone()));
+        line.set_dirty();
+    }
+
+    /// If x is the second half of a double width character, replaces
+    /// the whole character with blanks, so that an edit that starts
+    /// at x doesn't leave half of the character behind.
+    pub fn split_double_width(&mut self, x: usize, y: VisibleRowIndex) {
+        if x == 0 {
+            return;
+        }
+        let line_idx = self.phys_row(y);
+        let line = self.line_mut(line_idx);
+        let prior = match line.cells().get(x - 1) {
+            Some(cell) if cell.width() > 1 => cell.attrs().clone(),
+            _ => return,
+        };
+        line.set_cell(x - 1, Cell::new(' ', prior));
     }
 
     /// Set a cell.  the x and y coordinates are relative to the visible screeen
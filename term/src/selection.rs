@@ -1,7 +1,11 @@
 // The range_plus_one lint can't see when the LHS is not compatible with
 // and inclusive range
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
-use super::{ScrollbackOrVisibleRowIndex, VisibleRowIndex};
+use super::{
+    Blink, Cell, CellAttributes, Intensity, Line, ScrollbackOrVisibleRowIndex, Underline,
+    VisibleRowIndex,
+};
+use crate::color::ColorAttribute;
 #[cfg(feature = "use_serde")]
 use serde::{Deserialize, Serialize};
 use std::ops::Range;
@@ -119,3 +123,205 @@ impl SelectionRange {
         }
     }
 }
+
+/// Widens `cols` so that any double-width cell that it only partially
+/// covers is included in its entirety.
+pub fn expand_cols_to_cells(line: &Line, cols: Range<usize>) -> Range<usize> {
+    let mut start = cols.start;
+    let mut end = cols.end;
+    for (idx, cell) in line.visible_cells() {
+        if idx >= end {
+            break;
+        }
+        let cell_end = idx + cell.width().max(1);
+        if idx < start && cell_end > start {
+            start = idx;
+        }
+        if cell_end > end {
+            end = cell_end;
+        }
+    }
+    start..end
+}
+
+/// Controls how `selection_text` turns cells into text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectionTextOptions {
+    /// If true, all trailing whitespace is removed from each line.
+    /// Otherwise only the blank cells that pad the line beyond the
+    /// content written by the application are removed.
+    pub trim_trailing_whitespace: bool,
+    /// If true, runs of blank cells that the cursor was moved over
+    /// by a horizontal tab are copied as a tab character rather
+    /// than as spaces.
+    pub preserve_tabs: bool,
+    /// If true, the colors and styles of the text are included as
+    /// SGR escape sequences, so that the text can be replayed into a
+    /// terminal.
+    pub escapes: bool,
+}
+
+impl Default for SelectionTextOptions {
+    fn default() -> Self {
+        Self {
+            trim_trailing_whitespace: true,
+            preserve_tabs: true,
+            escapes: false,
+        }
+    }
+}
+
+/// Returns true if the cell is blank padding that wasn't written
+/// by the application
+fn is_padding(cell: &Cell) -> bool {
+    cell.str() == " " && !cell.attrs().written() && !cell.attrs().tab()
+}
+
+/// The SGR sequence that selects the default graphic rendition
+const SGR_RESET: &str = "\x1b[0m";
+
+fn push_color_params(params: &mut Vec<String>, color: ColorAttribute, base: u8) {
+    match color {
+        ColorAttribute::Default => {}
+        ColorAttribute::PaletteIndex(idx) if idx < 8 => params.push((base + idx).to_string()),
+        ColorAttribute::PaletteIndex(idx) if idx < 16 => {
+            params.push((base + 60 + idx - 8).to_string())
+        }
+        ColorAttribute::PaletteIndex(idx) => params.push(format!("{};5;{}", base + 8, idx)),
+        ColorAttribute::TrueColorWithPaletteFallback(rgb, _)
+        | ColorAttribute::TrueColorWithDefaultFallback(rgb) => params.push(format!(
+            "{};2;{};{};{}",
+            base + 8,
+            rgb.red,
+            rgb.green,
+            rgb.blue
+        )),
+    }
+}
+
+/// Returns the SGR sequence that selects the graphic rendition of
+/// `attrs`, starting from the default rendition
+fn sgr_for_attrs(attrs: &CellAttributes) -> String {
+    let mut params = vec!["0".to_string()];
+    let mut flag = |enabled: bool, param: &str| {
+        if enabled {
+            params.push(param.to_string());
+        }
+    };
+    flag(attrs.intensity() == Intensity::Bold, "1");
+    flag(attrs.intensity() == Intensity::Half, "2");
+    flag(attrs.italic(), "3");
+    flag(attrs.underline() == Underline::Single, "4");
+    flag(attrs.underline() == Underline::Double, "21");
+    flag(attrs.blink() == Blink::Slow, "5");
+    flag(attrs.blink() == Blink::Rapid, "6");
+    flag(attrs.reverse(), "7");
+    flag(attrs.invisible(), "8");
+    flag(attrs.strikethrough(), "9");
+    flag(attrs.overline(), "53");
+    push_color_params(&mut params, attrs.foreground, 30);
+    push_color_params(&mut params, attrs.background, 40);
+    format!("\x1b[{}m", params.join(";"))
+}
+
+/// Extracts the text from a selection.
+/// `rows` yields each line in the selection together with the
+/// range of columns selected from it; double-width characters that
+/// are partially covered by that range are included in full.
+///
+/// For a regular selection, trailing whitespace is removed from
+/// each line as described by `options` and lines are joined with
+/// newlines, except where a line was soft-wrapped onto the next, in
+/// which case the two form a single run of text and nothing is
+/// removed at the wrap.
+///
+/// When `rectangular` is true each row is padded with spaces to the
+/// width of its column range, and rows are always joined with
+/// newlines, so that the result is column aligned.  Tabs are not
+/// preserved in this mode as they would break the alignment.
+///
+/// When `options.escapes` is true, an SGR sequence is emitted each
+/// time the rendition changes, and the text ends with a reset if
+/// it doesn't already end in the default rendition.
+pub fn selection_text<'a, I>(rows: I, rectangular: bool, options: &SelectionTextOptions) -> String
+where
+    I: IntoIterator<Item = (&'a Line, Range<usize>)>,
+{
+    let mut s = String::new();
+    let mut sgr = SGR_RESET.to_string();
+    let mut rows = rows.into_iter().peekable();
+    while let Some((line, cols)) = rows.next() {
+        let is_last = rows.peek().is_none();
+        let rect_width = cols.end.saturating_sub(cols.start);
+        let cols = expand_cols_to_cells(line, cols);
+
+        // A soft-wrapped line continues on the next row
+        let wrapped = !rectangular
+            && !is_last
+            && cols.end >= line.cells().len()
+            && line.last_cell_was_wrapped();
+        let content_end = if rectangular || wrapped || options.trim_trailing_whitespace {
+            cols.end
+        } else {
+            cols.end.min(line.written_len())
+        };
+        let preserve_tabs = options.preserve_tabs && !rectangular;
+
+        let mut text = String::new();
+        let mut width = 0;
+        let mut in_tab = false;
+        for (idx, cell) in line.visible_cells() {
+            if idx < cols.start {
+                continue;
+            }
+            if idx >= content_end {
+                break;
+            }
+            width += cell.width();
+            if options.escapes {
+                let cell_sgr = sgr_for_attrs(cell.attrs());
+                if cell_sgr != sgr {
+                    text.push_str(&cell_sgr);
+                    sgr = cell_sgr;
+                }
+            }
+            if preserve_tabs {
+                if cell.attrs().tab() {
+                    text.push('\t');
+                    in_tab = true;
+                    continue;
+                }
+                if in_tab && is_padding(cell) {
+                    continue;
+                }
+                in_tab = false;
+            }
+            text.push_str(cell.str());
+        }
+
+        if rectangular {
+            s.push_str(&text);
+            for _ in width..rect_width {
+                s.push(' ');
+            }
+            if !is_last {
+                s.push('\n');
+            }
+        } else if wrapped {
+            s.push_str(&text);
+        } else {
+            if options.trim_trailing_whitespace {
+                s.push_str(text.trim_end());
+            } else {
+                s.push_str(&text);
+            }
+            if !is_last {
+                s.push('\n');
+            }
+        }
+    }
+    if sgr != SGR_RESET {
+        s.push_str(SGR_RESET);
+    }
+    s
+}
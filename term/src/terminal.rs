@@ -1,8 +1,11 @@
 use super::*;
 use std::sync::Arc;
-use termwiz::escape::parser::Parser;
+use termwiz::escape::parser::{Encoding, Parser};
 
-pub trait Clipboard {
+/// The clipboard of the embedding application.
+/// The terminal may be driven from a thread other than the one that
+/// created it, so implementations must be usable from any thread.
+pub trait Clipboard: Send + Sync {
     fn get_contents(&self) -> anyhow::Result<String>;
     fn set_contents(&self, data: Option<String>) -> anyhow::Result<()>;
 }
@@ -65,7 +68,7 @@ impl Terminal {
         term_program: &str,
         term_version: &str,
         // writing to the writer sends data to input of the pty
-        writer: Box<dyn std::io::Write>,
+        writer: Box<dyn std::io::Write + Send>,
     ) -> Terminal {
         Terminal {
             state: TerminalState::new(
@@ -90,8 +93,28 @@ impl Terminal {
     pub fn advance_bytes<B: AsRef<[u8]>>(&mut self, bytes: B) {
         let bytes = bytes.as_ref();
 
+        // This follows the configuration as it is reloaded
+        self.parser
+            .set_c1_controls(self.state.enable_8bit_c1_controls());
         let mut performer = Performer::new(&mut self.state);
 
-        self.parser.parse(bytes, |action| performer.perform(action));
+        self.parser
+            .parse_with_raw(bytes, |action, raw| performer.perform_raw(action, raw));
+        // The input follows the encoding that the program selected
+        self.state.set_input_encoding(self.parser.encoding());
+    }
+
+    /// Returns the encoding that the output of the program is decoded
+    /// from.  The program can select one with `ESC % G` or `ESC % @`.
+    pub fn encoding(&self) -> Encoding {
+        self.parser.encoding()
+    }
+
+    /// Selects the encoding that the output of the program is decoded
+    /// from, and that its input is encoded in, such as CP437 for a BBS
+    /// or Latin-1 for a legacy system
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.parser.set_encoding(encoding);
+        self.state.set_input_encoding(encoding);
     }
 }
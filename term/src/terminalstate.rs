@@ -6,24 +6,61 @@ use crate::color::{ColorPalette, RgbColor};
 use anyhow::bail;
 use image::{self, GenericImageView};
 use log::{debug, error};
-use num_traits::FromPrimitive;
-use ordered_float::NotNan;
+use num_traits::{FromPrimitive, ToPrimitive};
 use std::collections::HashMap;
 use std::fmt::Write;
 use std::sync::Arc;
 use termwiz::escape::csi::{
-    Cursor, CursorStyle, DecPrivateMode, DecPrivateModeCode, Device, Edit, EraseInDisplay,
-    EraseInLine, Mode, Sgr, TabulationClear, TerminalMode, TerminalModeCode, Window,
+    Cursor, CursorStyle, CursorTabulationControl, DecPrivateMode, DecPrivateModeCode, Device, Edit,
+    EraseInDisplay, EraseInLine, Mode, Sgr, TabulationClear, TerminalMode, TerminalModeCode,
+    Window,
 };
-use termwiz::escape::osc::{ChangeColorPair, ColorOrQuery, ITermFileData, ITermProprietary};
+use termwiz::escape::osc::{
+    ChangeColorPair, ColorOrQuery, FinalTermSemanticPrompt, ITermFileData, ITermProprietary,
+    ITermUnicodeVersionOp, Progress,
+};
+use termwiz::escape::parser::Encoding;
 use termwiz::escape::{
     Action, ControlCode, DeviceControlMode, Esc, EscCode, OneBased, OperatingSystemCommand, Sixel,
     SixelData, CSI,
 };
-use termwiz::image::{ImageCell, ImageData, TextureCoordinate};
+use termwiz::image::{ImageCell, ImageData, ImagePlacement};
 use termwiz::surface::{CursorShape, CursorVisibility};
 use url::Url;
 
+/// How many notifications to retain if they are not being collected
+const MAX_PENDING_NOTIFICATIONS: usize = 16;
+/// How many titles XTWINOPS 22 can save; pushing more than this
+/// discards the oldest
+const MAX_TITLE_STACK_DEPTH: usize = 10;
+
+/// The titles saved by XTWINOPS 22.  Only the titles that were named
+/// by the push are saved, and a pop only restores those that it names.
+#[derive(Debug, Clone, Default)]
+struct SavedTitles {
+    icon: Option<String>,
+    window: Option<String>,
+}
+
+/// A desktop notification requested by the application, using
+/// either OSC 9 or OSC 777;notify
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToastNotification {
+    /// OSC 9 doesn't provide a title
+    pub title: Option<String>,
+    pub body: String,
+}
+
+/// A request from the application to resize the terminal, using
+/// either CSI 8 t (in cells) or CSI 4 t (in pixels, which is
+/// converted to cells).  Dimensions that the application omitted
+/// are filled in from the current size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeRequest {
+    pub rows: usize,
+    pub cols: usize,
+}
+
 struct TabStop {
     tabs: Vec<bool>,
     tab_width: usize,
@@ -40,7 +77,17 @@ impl TabStop {
     }
 
     fn set_tab_stop(&mut self, col: usize) {
-        self.tabs[col] = true;
+        if let Some(t) = self.tabs.get_mut(col) {
+            *t = true;
+        }
+    }
+
+    /// Restores the stops to every `tab_width` columns
+    fn set_defaults(&mut self) {
+        let tab_width = self.tab_width;
+        for (i, t) in self.tabs.iter_mut().enumerate() {
+            *t = (i % tab_width) == 0;
+        }
     }
 
     fn find_prev_tab_stop(&self, col: usize) -> Option<usize> {
@@ -81,7 +128,8 @@ impl TabStop {
                 }
             }
             TabulationClear::ClearAllCharacterTabStops
-            | TabulationClear::ClearCharacterTabStopsAtActiveLine => {
+            | TabulationClear::ClearCharacterTabStopsAtActiveLine
+            | TabulationClear::ClearAllTabStops => {
                 for t in &mut self.tabs {
                     *t = false;
                 }
@@ -91,15 +139,96 @@ impl TabStop {
     }
 }
 
+/// The character sets that can be designated into G0 and G1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharSet {
+    Ascii,
+    Uk,
+    DecLineDrawing,
+}
+
+impl CharSet {
+    /// Translates a grapheme from this character set into the
+    /// corresponding unicode text.
+    /// <https://vt100.net/docs/vt220-rm/table2-4.html>
+    fn map<'a>(self, g: &'a str) -> &'a str {
+        match self {
+            CharSet::Ascii => g,
+            CharSet::Uk => match g {
+                "#" => "£",
+                _ => g,
+            },
+            CharSet::DecLineDrawing => match g {
+                "_" => " ",
+                "`" => "◆",
+                "a" => "▒",
+                "b" => "␉",
+                "c" => "␌",
+                "d" => "␍",
+                "e" => "␊",
+                "f" => "°",
+                "g" => "±",
+                "h" => "␤",
+                "i" => "␋",
+                "j" => "┘",
+                "k" => "┐",
+                "l" => "┌",
+                "m" => "└",
+                "n" => "┼",
+                "o" => "⎺",
+                "p" => "⎻",
+                "q" => "─",
+                "r" => "⎼",
+                "s" => "⎽",
+                "t" => "├",
+                "u" => "┤",
+                "v" => "┴",
+                "w" => "┬",
+                "x" => "│",
+                "y" => "≤",
+                "z" => "≥",
+                "{" => "π",
+                "|" => "≠",
+                "}" => "£",
+                "~" => "·",
+                _ => g,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct SavedCursor {
     position: CursorPosition,
     wrap_next: bool,
     pen: CellAttributes,
     dec_origin_mode: bool,
+    g0_charset: CharSet,
+    g1_charset: CharSet,
+    shift_out: bool,
     // TODO: selective_erase when supported
 }
 
+impl SavedCursor {
+    /// Returns the saved position, placing a pending wrap just beyond
+    /// the right margin, as `TerminalState::resize` does for the cursor
+    fn logical_position(&self) -> CursorPosition {
+        let mut position = self.position;
+        if self.wrap_next {
+            position.x += 1;
+        }
+        position
+    }
+
+    /// Updates the saved position after a resize to `physical_cols`
+    fn set_logical_position(&mut self, position: CursorPosition, physical_cols: usize) {
+        let physical_cols = physical_cols.max(1);
+        self.wrap_next = position.x >= physical_cols;
+        self.position.x = position.x.min(physical_cols - 1);
+        self.position.y = position.y;
+    }
+}
+
 struct ScreenOrAlt {
     /// The primary screen + scrollback
     screen: Screen,
@@ -157,8 +286,50 @@ impl ScreenOrAlt {
         physical_cols: usize,
         cursor: CursorPosition,
     ) -> CursorPosition {
-        let cursor_main = self.screen.resize(physical_rows, physical_cols, cursor);
-        let cursor_alt = self.alt_screen.resize(physical_rows, physical_cols, cursor);
+        // While the alternate screen is active, the position that matters
+        // for the primary screen is the one that was saved when switching
+        // away from it; rewrap that so that it is restored to the right
+        // place when switching back.  Otherwise, the saved position moves
+        // along with the text, so that a shell that saves the cursor
+        // before drawing its right prompt restores it to the same place
+        // in its left prompt.
+        let saved_main = self
+            .saved_cursor
+            .as_ref()
+            .map(SavedCursor::logical_position);
+        let (cursor_main, saved_main) = match (self.alt_screen_is_active, saved_main) {
+            (true, Some(saved)) => {
+                let (saved, _) =
+                    self.screen
+                        .resize(physical_rows, physical_cols, saved, None, false);
+                (saved, Some(saved))
+            }
+            (true, None) => self
+                .screen
+                .resize(physical_rows, physical_cols, cursor, None, false),
+            (false, saved) => self
+                .screen
+                .resize(physical_rows, physical_cols, cursor, saved, true),
+        };
+        if let (Some(saved), Some(position)) = (self.saved_cursor.as_mut(), saved_main) {
+            saved.set_logical_position(position, physical_cols);
+        }
+
+        let saved_alt = self
+            .alt_saved_cursor
+            .as_ref()
+            .map(SavedCursor::logical_position);
+        let (cursor_alt, saved_alt) = self.alt_screen.resize(
+            physical_rows,
+            physical_cols,
+            cursor,
+            saved_alt,
+            self.alt_screen_is_active,
+        );
+        if let (Some(saved), Some(position)) = (self.alt_saved_cursor.as_mut(), saved_alt) {
+            saved.set_logical_position(position, physical_cols);
+        }
+
         if self.alt_screen_is_active {
             cursor_alt
         } else {
@@ -200,6 +371,26 @@ impl ScreenOrAlt {
     }
 }
 
+/// The states of a mode that DECRPM reports in reply to DECRQM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModeReport {
+    NotRecognized = 0,
+    Set = 1,
+    Reset = 2,
+    PermanentlySet = 3,
+    PermanentlyReset = 4,
+}
+
+impl ModeReport {
+    fn from_flag(set: bool) -> Self {
+        if set {
+            Self::Set
+        } else {
+            Self::Reset
+        }
+    }
+}
+
 /// Manages the state for the terminal
 pub struct TerminalState {
     config: Arc<dyn TerminalConfiguration>,
@@ -219,12 +410,21 @@ pub struct TerminalState {
     /// If true, writing a character inserts a new cell
     insert: bool,
 
+    /// The most recently printed grapheme, prior to charset
+    /// translation, which is what REP repeats
+    last_graphic: Option<String>,
+
     /// https://vt100.net/docs/vt510-rm/DECAWM.html
     dec_auto_wrap: bool,
 
     /// Reverse Wraparound Mode
     reverse_wraparound_mode: bool,
 
+    /// https://vt100.net/docs/vt510-rm/DECSCNM.html
+    /// The cells themselves are unchanged; the renderer swaps the
+    /// default foreground and background colors when this is set.
+    reverse_video_mode: bool,
+
     /// https://vt100.net/docs/vt510-rm/DECOM.html
     /// When OriginMode is enabled, cursor is constrained to the
     /// scroll region and its position is relative to the scroll
@@ -269,12 +469,24 @@ pub struct TerminalState {
     button_event_mouse: bool,
     current_mouse_button: MouseButton,
     cursor_visible: bool,
-    dec_line_drawing_mode: bool,
+
+    /// The character sets designated into G0 and G1
+    g0_charset: CharSet,
+    g1_charset: CharSet,
+    /// When set (by SO), G1 is used to translate printed text,
+    /// rather than G0
+    shift_out: bool,
 
     tabs: TabStop,
+    /// The alternate screen has its own set of tab stops
+    alt_tabs: TabStop,
 
     /// The terminal title string
     title: String,
+    /// The icon title, which is set separately from the window title
+    /// by OSC 1, and which we don't otherwise display
+    icon_title: String,
+    title_stack: Vec<SavedTitles>,
     palette: Option<ColorPalette>,
 
     pixel_width: usize,
@@ -284,10 +496,39 @@ pub struct TerminalState {
 
     current_dir: Option<Url>,
 
+    /// The progress most recently reported via OSC 9;4
+    progress: Progress,
+
+    /// The status text most recently set via OSC 7777
+    status_text: Option<String>,
+
+    /// The version of Unicode whose character widths are in effect
+    unicode_version: UnicodeVersion,
+    /// The versions saved by `UnicodeVersion=push`, along with
+    /// their optional labels
+    unicode_version_stack: Vec<(Option<String>, UnicodeVersion)>,
+
+    /// Notifications that have yet to be collected by the host
+    notifications: Vec<ToastNotification>,
+
+    /// The most recent resize request that has yet to be collected
+    /// by the host
+    resize_request: Option<ResizeRequest>,
+    /// Set when the application rings the bell
+    bell_rung: bool,
+    /// The user variables set with OSC 1337 SetUserVar that have yet
+    /// to be collected by the host, in the order that they were set
+    user_vars: Vec<(String, String)>,
+    /// Set when the application asks for the ids of its tab and window
+    tab_info_requested: bool,
+
     term_program: String,
     term_version: String,
 
-    writer: Box<dyn std::io::Write>,
+    /// The encoding of the keyboard input and pastes, which is that of
+    /// the output of the program
+    input_encoding: Encoding,
+    writer: Box<dyn std::io::Write + Send>,
 }
 
 fn encode_modifiers(mods: KeyModifiers) -> u8 {
@@ -321,6 +562,16 @@ fn default_color_map() -> HashMap<u16, RgbColor> {
     color_map
 }
 
+/// Formats bytes as space separated hex so that the raw form of
+/// sequences that we didn't understand is visible in the logs
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 impl TerminalState {
     /// Constructs the terminal state.
     /// You generally want the `Terminal` struct rather than this one;
@@ -333,11 +584,12 @@ impl TerminalState {
         config: Arc<dyn TerminalConfiguration>,
         term_program: &str,
         term_version: &str,
-        writer: Box<dyn std::io::Write>,
+        writer: Box<dyn std::io::Write + Send>,
     ) -> TerminalState {
         let screen = ScreenOrAlt::new(physical_rows, physical_cols, &config);
 
         let color_map = default_color_map();
+        let unicode_version = config.unicode_version();
 
         TerminalState {
             config,
@@ -352,6 +604,7 @@ impl TerminalState {
             // a dec terminal is false, because it is more useful this way.
             dec_auto_wrap: true,
             reverse_wraparound_mode: false,
+            reverse_video_mode: false,
             dec_origin_mode: false,
             insert: false,
             application_cursor_keys: false,
@@ -367,17 +620,33 @@ impl TerminalState {
             button_event_mouse: false,
             mouse_tracking: false,
             cursor_visible: true,
-            dec_line_drawing_mode: false,
+            g0_charset: CharSet::Ascii,
+            g1_charset: CharSet::Ascii,
+            shift_out: false,
+            last_graphic: None,
             current_mouse_button: MouseButton::None,
             tabs: TabStop::new(physical_cols, 8),
+            alt_tabs: TabStop::new(physical_cols, 8),
             title: "wezterm".to_string(),
+            icon_title: "wezterm".to_string(),
+            title_stack: vec![],
             palette: None,
             pixel_height,
             pixel_width,
             clipboard: None,
             current_dir: None,
+            progress: Progress::None,
+            status_text: None,
+            unicode_version,
+            unicode_version_stack: vec![],
+            notifications: vec![],
+            resize_request: None,
+            bell_rung: false,
+            user_vars: vec![],
+            tab_info_requested: false,
             term_program: term_program.to_string(),
             term_version: term_version.to_string(),
+            input_encoding: Encoding::Utf8,
             writer: Box::new(std::io::BufWriter::new(writer)),
         }
     }
@@ -400,6 +669,157 @@ impl TerminalState {
         self.current_dir.as_ref()
     }
 
+    /// Returns the progress of a long running operation, as reported
+    /// by the application using the OSC 9;4 escape sequence.
+    pub fn get_progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// Discards the reported progress; used when the application
+    /// that reported it is no longer running.
+    pub fn clear_progress(&mut self) {
+        self.progress = Progress::None;
+    }
+
+    /// Returns the text for the status area of the tab bar, as set
+    /// by the application using the OSC 7777 escape sequence.
+    pub fn get_status_text(&self) -> Option<&str> {
+        self.status_text.as_ref().map(String::as_str)
+    }
+
+    /// Returns true if the output may contain 8-bit C1 controls
+    pub(crate) fn enable_8bit_c1_controls(&self) -> bool {
+        self.config.enable_8bit_c1_controls()
+    }
+
+    pub(crate) fn set_input_encoding(&mut self, encoding: Encoding) {
+        self.input_encoding = encoding;
+    }
+
+    /// Returns the version of Unicode whose character widths are
+    /// currently used to lay out text.
+    pub fn get_unicode_version(&self) -> UnicodeVersion {
+        self.unicode_version
+    }
+
+    fn apply_unicode_version_op(&mut self, op: ITermUnicodeVersionOp) {
+        match op {
+            ITermUnicodeVersionOp::Set(n) => self.unicode_version = UnicodeVersion(n),
+            ITermUnicodeVersionOp::Push(label) => {
+                self.unicode_version_stack
+                    .push((label, self.unicode_version));
+            }
+            ITermUnicodeVersionOp::Pop(None) => {
+                if let Some((_, version)) = self.unicode_version_stack.pop() {
+                    self.unicode_version = version;
+                }
+            }
+            ITermUnicodeVersionOp::Pop(Some(label)) => {
+                // Pops everything down to and including the entry with
+                // the matching label; if there is no such entry then
+                // the stack is left alone
+                if let Some(idx) = self
+                    .unicode_version_stack
+                    .iter()
+                    .rposition(|(l, _)| l.as_ref() == Some(&label))
+                {
+                    self.unicode_version = self.unicode_version_stack[idx].1;
+                    self.unicode_version_stack.truncate(idx);
+                }
+            }
+        }
+    }
+
+    /// Returns the notifications that the application has requested
+    /// since the last call, oldest first.
+    pub fn take_notifications(&mut self) -> Vec<ToastNotification> {
+        std::mem::take(&mut self.notifications)
+    }
+
+    /// Returns the most recent request from the application to resize
+    /// the terminal, if any, since the last call.  It is up to the
+    /// host to decide whether to honor it.
+    pub fn take_resize_request(&mut self) -> Option<ResizeRequest> {
+        self.resize_request.take()
+    }
+
+    /// Returns true if the application has rung the bell since the
+    /// last call.  A burst of bells is reported as a single bell.
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::replace(&mut self.bell_rung, false)
+    }
+
+    /// Returns the user variables that the application has set since
+    /// the last call.  Only the most recent value of each is kept.
+    pub fn take_user_vars(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.user_vars)
+    }
+
+    /// Saves the icon and/or window title, for XTWINOPS 22
+    fn push_title(&mut self, icon: bool, window: bool) {
+        if self.title_stack.len() >= MAX_TITLE_STACK_DEPTH {
+            self.title_stack.remove(0);
+        }
+        self.title_stack.push(SavedTitles {
+            icon: if icon {
+                Some(self.icon_title.clone())
+            } else {
+                None
+            },
+            window: if window {
+                Some(self.title.clone())
+            } else {
+                None
+            },
+        });
+    }
+
+    /// Restores the icon and/or window title, for XTWINOPS 23.
+    /// Popping an empty stack does nothing.
+    fn pop_title(&mut self, icon: bool, window: bool) {
+        if let Some(saved) = self.title_stack.pop() {
+            if let Some(title) = saved.icon.filter(|_| icon) {
+                self.icon_title = title;
+            }
+            if let Some(title) = saved.window.filter(|_| window) {
+                self.title = title;
+            }
+        }
+    }
+
+    /// Returns true if the application has asked for the ids of its
+    /// tab and window since the last call.  The embedding application
+    /// knows those, and answers using `report_tab_info`.
+    pub fn take_tab_info_request(&mut self) -> bool {
+        std::mem::replace(&mut self.tab_info_requested, false)
+    }
+
+    /// Answers the request made with `OSC 1337 ; ReportTabInfo`
+    pub fn report_tab_info(&mut self, tab_id: usize, window_id: usize) {
+        write!(
+            self.writer,
+            "{}",
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::ReportTabInfo {
+                tab_id,
+                window_id
+            })
+        )
+        .ok();
+        self.writer.flush().ok();
+    }
+
+    fn set_user_var(&mut self, name: String, value: String) {
+        self.user_vars.retain(|(existing, _)| *existing != name);
+        self.user_vars.push((name, value));
+    }
+
+    fn push_notification(&mut self, notification: ToastNotification) {
+        if self.notifications.len() >= MAX_PENDING_NOTIFICATIONS {
+            self.notifications.remove(0);
+        }
+        self.notifications.push(notification);
+    }
+
     /// Returns a copy of the palette.
     /// By default we don't keep a copy in the terminal state,
     /// preferring to take the config values from the users
@@ -436,6 +856,28 @@ impl TerminalState {
         &mut self.screen
     }
 
+    /// Returns true if the alternate screen is the active screen
+    pub fn is_alt_screen_active(&self) -> bool {
+        self.screen.is_alt_screen_active()
+    }
+
+    /// Returns the tab stops for the active screen
+    fn tabs(&self) -> &TabStop {
+        if self.screen.alt_screen_is_active {
+            &self.alt_tabs
+        } else {
+            &self.tabs
+        }
+    }
+
+    fn tabs_mut(&mut self) -> &mut TabStop {
+        if self.screen.alt_screen_is_active {
+            &mut self.alt_tabs
+        } else {
+            &mut self.tabs
+        }
+    }
+
     fn set_clipboard_contents(&self, text: Option<String>) -> anyhow::Result<()> {
         if let Some(clip) = self.clipboard.as_ref() {
             clip.set_contents(text)?;
@@ -648,6 +1090,74 @@ impl TerminalState {
         }
     }
 
+    /// Restores the modes that are reset by DECSTR to their defaults,
+    /// leaving the screen contents alone.
+    /// <https://vt100.net/docs/vt510-rm/DECSTR.html>
+    /// In addition to the documented set of modes, we also reset the
+    /// mouse reporting and bracketed paste modes so that the terminal
+    /// is usable again after a misbehaving application exits.
+    fn soft_reset(&mut self) {
+        self.pen = CellAttributes::default();
+        self.wrap_next = false;
+        self.insert = false;
+        self.dec_origin_mode = false;
+        // Note that xterm deviates from the documented DECSTR
+        // setting for dec_auto_wrap, so we do too
+        self.dec_auto_wrap = true;
+        self.reverse_wraparound_mode = false;
+        self.cursor_visible = true;
+        self.g0_charset = CharSet::Ascii;
+        self.g1_charset = CharSet::Ascii;
+        self.shift_out = false;
+        self.application_cursor_keys = false;
+        self.application_keypad = false;
+        self.bracketed_paste = false;
+        self.focus_tracking = false;
+        self.mouse_tracking = false;
+        self.sgr_mouse = false;
+        self.any_event_mouse = false;
+        self.button_event_mouse = false;
+        self.current_mouse_button = MouseButton::None;
+        self.left_and_right_margin_mode = false;
+        self.top_and_bottom_margins = 0..self.screen().physical_rows as VisibleRowIndex;
+        self.left_and_right_margins = 0..self.screen().physical_cols;
+        self.screen.saved_cursor = None;
+        self.screen.alt_saved_cursor = None;
+        // Repaint the cursor line in case the cursor was hidden
+        let y = self.cursor.y;
+        self.screen_mut().dirty_line(y);
+    }
+
+    /// Resets the terminal to its initial state, as though it had
+    /// just been created.  This is what RIS does, and is also used
+    /// to recover the terminal locally without requiring the
+    /// cooperation of the application.
+    pub fn full_reset(&mut self) {
+        self.soft_reset();
+        self.cursor = Default::default();
+        self.set_reverse_video(false);
+        self.use_private_color_registers_for_each_graphic = false;
+        self.color_map = default_color_map();
+        self.sixel_scrolling = true;
+        self.dec_ansi_mode = false;
+        self.last_graphic = None;
+        self.tabs = TabStop::new(self.screen().physical_cols, 8);
+        self.alt_tabs = TabStop::new(self.screen().physical_cols, 8);
+        self.palette.take();
+        self.unicode_version = self.config.unicode_version();
+        self.unicode_version_stack.clear();
+        self.title_stack.clear();
+
+        if self.screen.is_alt_screen_active() {
+            self.erase_in_display(EraseInDisplay::EraseDisplay);
+            self.screen.activate_primary_screen();
+        }
+        self.erase_in_display(EraseInDisplay::EraseDisplay);
+        if self.config.clear_scrollback_on_reset() {
+            self.screen_mut().erase_scrollback();
+        }
+    }
+
     /// Discards the scrollback, leaving only the data that is present
     /// in the viewport.
     pub fn erase_scrollback(&mut self) {
@@ -669,6 +1179,22 @@ impl TerminalState {
         self.bracketed_paste
     }
 
+    /// Returns true if the application has enabled reverse video
+    /// (DECSCNM), in which case the default foreground and background
+    /// colors should be swapped when rendering the screen.
+    pub fn reverse_video_enabled(&self) -> bool {
+        self.reverse_video_mode
+    }
+
+    fn set_reverse_video(&mut self, enabled: bool) {
+        // Some applications toggle this rapidly to flash the screen,
+        // so only repaint if it actually changed
+        if self.reverse_video_mode != enabled {
+            self.reverse_video_mode = enabled;
+            self.make_all_lines_dirty();
+        }
+    }
+
     /// Advise the terminal about a change in its focus state
     pub fn focus_changed(&mut self, focused: bool) {
         if self.focus_tracking {
@@ -683,14 +1209,23 @@ impl TerminalState {
     pub fn send_paste(&mut self, text: &str) -> Result<(), Error> {
         if self.bracketed_paste {
             let buf = format!("\x1b[200~{}\x1b[201~", text);
-            self.writer.write_all(buf.as_bytes())?;
+            self.writer.write_all(&self.input_encoding.encode(&buf))?;
         } else {
-            self.writer.write_all(text.as_bytes())?;
+            self.writer.write_all(&self.input_encoding.encode(text))?;
         }
         self.writer.flush()?;
         Ok(())
     }
 
+    /// Send text to the terminal as though it had been typed, such as
+    /// the text composed by an input method.  It is encoded in the
+    /// input encoding that the program selected.
+    pub fn send_text(&mut self, text: &str) -> Result<(), Error> {
+        self.writer.write_all(&self.input_encoding.encode(text))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
     fn csi_u_encode(&self, buf: &mut String, c: char, mods: KeyModifiers) -> Result<(), Error> {
         if self.config.enable_csi_u_key_encoding() {
             write!(buf, "\x1b[{};{}u", c as u32, 1 + encode_modifiers(mods))?;
@@ -953,7 +1488,8 @@ impl TerminalState {
         };
 
         // debug!("sending {:?}, {:?}", to_send, key);
-        self.writer.write_all(to_send.as_bytes())?;
+        self.writer
+            .write_all(&self.input_encoding.encode(&to_send))?;
         self.writer.flush()?;
 
         Ok(())
@@ -968,18 +1504,33 @@ impl TerminalState {
         pixel_width: usize,
         pixel_height: usize,
     ) {
-        let adjusted_cursor = self
-            .screen
-            .resize(physical_rows, physical_cols, self.cursor);
+        // A pending wrap logically places the cursor just beyond the
+        // right margin; express it that way so that rewrapping keeps it
+        // positioned after the last printed character rather than on it.
+        let mut cursor = self.cursor;
+        if self.wrap_next {
+            cursor.x += 1;
+        }
+        let adjusted_cursor = self.screen.resize(physical_rows, physical_cols, cursor);
+
+        // The screen clamps the size to at least 1x1
+        let physical_rows = self.screen().physical_rows;
+        let physical_cols = self.screen().physical_cols;
+
         self.top_and_bottom_margins = 0..physical_rows as i64;
         self.left_and_right_margins = 0..physical_cols;
         self.pixel_height = pixel_height;
         self.pixel_width = pixel_width;
         self.tabs.resize(physical_cols);
+        self.alt_tabs.resize(physical_cols);
         self.set_cursor_pos(
             &Position::Absolute(adjusted_cursor.x as i64),
             &Position::Absolute(adjusted_cursor.y),
         );
+
+        // If the cursor ended up beyond the right margin, then it is
+        // sitting in the pending wrap position at the new width
+        self.wrap_next = adjusted_cursor.x >= physical_cols && self.dec_auto_wrap;
     }
 
     /// Clear the dirty flag for all dirty lines
@@ -1026,64 +1577,72 @@ impl TerminalState {
         screen.dirty_line(y);
     }
 
-    /// Sets the cursor position. x and y are 0-based and relative to the
-    /// top left of the visible screen.
+    /// Sets the cursor position. x and y are 0-based.  When origin mode
+    /// is enabled, absolute positions are relative to the top left of the
+    /// margins and the cursor is confined to the margins; otherwise they
+    /// are relative to the top left of the visible screen.
     fn set_cursor_pos(&mut self, x: &Position, y: &Position) {
+        let (cols, rows) = if self.dec_origin_mode {
+            (
+                self.left_and_right_margins.start as i64..self.left_and_right_margins.end as i64,
+                self.top_and_bottom_margins.clone(),
+            )
+        } else {
+            (
+                0..self.screen().physical_cols as i64,
+                0..self.screen().physical_rows as VisibleRowIndex,
+            )
+        };
+
         let x = match *x {
-            Position::Relative(x) => (self.cursor.x as i64 + x)
-                .min(
-                    if self.dec_origin_mode {
-                        self.left_and_right_margins.end
-                    } else {
-                        self.screen().physical_cols
-                    } as i64
-                        - 1,
-                )
-                .max(0),
-            Position::Absolute(x) => (x + if self.dec_origin_mode {
-                self.left_and_right_margins.start
-            } else {
-                0
-            } as i64)
-                .min(
-                    if self.dec_origin_mode {
-                        self.left_and_right_margins.end
-                    } else {
-                        self.screen().physical_cols
-                    } as i64
-                        - 1,
-                )
-                .max(0),
+            Position::Relative(x) => self.cursor.x as i64 + x,
+            Position::Absolute(x) => cols.start + x,
         };
+        let x = x.min(cols.end - 1).max(cols.start);
 
         let y = match *y {
-            Position::Relative(y) => (self.cursor.y + y)
-                .min(
-                    if self.dec_origin_mode {
-                        self.top_and_bottom_margins.end
-                    } else {
-                        self.screen().physical_rows as i64
-                    } - 1,
-                )
-                .max(0),
-            Position::Absolute(y) => (y + if self.dec_origin_mode {
-                self.top_and_bottom_margins.start
-            } else {
-                0
-            })
-            .min(
-                if self.dec_origin_mode {
-                    self.top_and_bottom_margins.end
-                } else {
-                    self.screen().physical_rows as i64
-                } - 1,
-            )
-            .max(0),
+            Position::Relative(y) => self.cursor.y + y,
+            Position::Absolute(y) => rows.start + y,
         };
+        let y = y.min(rows.end - 1).max(rows.start);
 
         self.set_cursor_position_absolute(x as usize, y);
     }
 
+    /// Moves the cursor to the left margin of its line
+    fn move_to_left_margin(&mut self) {
+        let left = self.left_and_right_margins.start;
+        if self.cursor.x != left {
+            self.set_cursor_position_absolute(left, self.cursor.y);
+        }
+    }
+
+    /// Returns the column at which text printed at the cursor wraps.
+    /// Text to the right of the right margin, which can only be placed
+    /// there by cursor positioning, wraps at the edge of the screen.
+    fn right_margin_for_cursor(&self) -> usize {
+        if self.cursor.x < self.left_and_right_margins.end {
+            self.left_and_right_margins.end
+        } else {
+            self.screen().physical_cols
+        }
+    }
+
+    /// Performs an automatic wrap to the start of the next line.
+    /// When the wrap happens at the edge of the screen, the line is
+    /// marked as soft wrapped so that selection and rewrapping can
+    /// tell that the next line is logically a continuation of it,
+    /// rather than having been started by an explicit newline.
+    fn soft_wrap(&mut self) {
+        if self.right_margin_for_cursor() == self.screen().physical_cols {
+            let y = self.cursor.y;
+            let screen = self.screen_mut();
+            let phys = screen.phys_row(y);
+            screen.line_mut(phys).set_last_cell_was_wrapped(true);
+        }
+        self.new_line(true);
+    }
+
     fn scroll_up(&mut self, num_rows: usize) {
         let top_and_bottom_margins = self.top_and_bottom_margins.clone();
         let left_and_right_margins = self.left_and_right_margins.clone();
@@ -1112,12 +1671,16 @@ impl TerminalState {
         };
         let y = self.cursor.y;
         let y = if y == self.top_and_bottom_margins.end - 1 {
-            self.scroll_up(1);
+            // Only the text within the left and right margins scrolls;
+            // outside of them the cursor stays put on the bottom margin
+            if self.left_and_right_margins.contains(&self.cursor.x) {
+                self.scroll_up(1);
+            }
             y
         } else {
-            y + 1
+            (y + 1).min(self.screen().physical_rows as VisibleRowIndex - 1)
         };
-        self.set_cursor_pos(&Position::Absolute(x as i64), &Position::Absolute(y as i64));
+        self.set_cursor_position_absolute(x, y);
     }
 
     /// Moves the cursor down one line in the same column.
@@ -1166,19 +1729,24 @@ impl TerminalState {
 
     /// Sets a horizontal tab stop at the column where the cursor is.
     fn c1_hts(&mut self) {
-        self.tabs.set_tab_stop(self.cursor.x);
+        let x = self.cursor.x;
+        self.tabs_mut().set_tab_stop(x);
     }
 
     /// Moves the cursor to the next tab stop. If there are no more tab stops,
     /// the cursor moves to the right margin. HT does not cause text to auto
     /// wrap.
     fn c0_horizontal_tab(&mut self) {
-        let x = match self.tabs.find_next_tab_stop(self.cursor.x) {
+        let x = match self.tabs().find_next_tab_stop(self.cursor.x) {
             Some(x) => x,
             None => self.left_and_right_margins.end - 1,
         };
-        self.cursor.x = x.min(self.left_and_right_margins.end - 1);
+        let x = x.min(self.left_and_right_margins.end - 1);
         let y = self.cursor.y;
+        let screen = self.screen_mut();
+        let phys = screen.phys_row(y);
+        screen.line_mut(phys).mark_tab(self.cursor.x..x);
+        self.cursor.x = x;
         self.screen_mut().dirty_line(y);
     }
 
@@ -1308,29 +1876,45 @@ impl TerminalState {
             return;
         }
 
-        let image_data = Arc::new(ImageData::with_raw_data(png_image_data));
-        self.assign_image_to_cells(width, height, image_data);
+        let (cell_width, cell_height) = self.cell_pixel_size();
+        let placement = ImagePlacement::compute(
+            (width as usize, height as usize),
+            (None, None),
+            false,
+            (cell_width, cell_height),
+            self.pixel_width,
+        );
+        let image_data = Arc::new(ImageData::with_placement(png_image_data, placement));
+        self.assign_image_to_cells(image_data);
     }
 
-    fn assign_image_to_cells(&mut self, width: u32, height: u32, image_data: Arc<ImageData>) {
+    /// Returns the size of a cell in pixels
+    fn cell_pixel_size(&self) -> (usize, usize) {
         let physical_cols = self.screen().physical_cols;
         let physical_rows = self.screen().physical_rows;
-        let cell_pixel_width = self.pixel_width / physical_cols;
-        let cell_pixel_height = self.pixel_height / physical_rows;
-
-        let width_in_cells = (width as f32 / cell_pixel_width as f32).ceil() as usize;
-        let height_in_cells = (height as f32 / cell_pixel_height as f32).ceil() as usize;
+        (
+            self.pixel_width / physical_cols,
+            self.pixel_height / physical_rows,
+        )
+    }
 
-        let mut ypos = NotNan::new(0.0).unwrap();
+    /// Fills the cells spanned by the placement with slices of the image,
+    /// starting at the cursor position.  Rows are added via new_line, so
+    /// images that are taller than the screen scroll along with their cells.
+    fn assign_image_to_cells(&mut self, image_data: Arc<ImageData>) {
+        let placement = match image_data.placement() {
+            Some(placement) => *placement,
+            None => return,
+        };
         let cursor_x = self.cursor.x;
-        let x_delta = 1.0 / (width as f32 / (self.pixel_width as f32 / physical_cols as f32));
-        let y_delta = 1.0 / (height as f32 / (self.pixel_height as f32 / physical_rows as f32));
+        let width_in_cells = placement
+            .cols
+            .min(self.screen().physical_cols.saturating_sub(cursor_x));
         debug!(
-            "image is {}x{} cells, {}x{} pixels",
-            width_in_cells, height_in_cells, width, height
+            "image is {}x{} cells, displayed at {}x{} pixels",
+            placement.cols, placement.rows, placement.width, placement.height
         );
-        for _ in 0..height_in_cells {
-            let mut xpos = NotNan::new(0.0).unwrap();
+        for y in 0..placement.rows {
             let cursor_y = self.cursor.y;
             debug!(
                 "setting cells for y={} x=[{}..{}]",
@@ -1339,23 +1923,22 @@ impl TerminalState {
                 cursor_x + width_in_cells
             );
             for x in 0..width_in_cells {
+                let (top_left, bottom_right) = placement.cell_texture_coords(x, y);
                 self.screen_mut().set_cell(
                     cursor_x + x,
-                    cursor_y, // + y as VisibleRowIndex,
+                    cursor_y,
                     &Cell::new(
                         ' ',
                         CellAttributes::default()
                             .set_image(Some(Box::new(ImageCell::new(
-                                TextureCoordinate::new(xpos, ypos),
-                                TextureCoordinate::new(xpos + x_delta, ypos + y_delta),
+                                top_left,
+                                bottom_right,
                                 image_data.clone(),
                             ))))
                             .clone(),
                     ),
                 );
-                xpos += x_delta;
             }
-            ypos += y_delta;
             self.new_line(false);
         }
     }
@@ -1387,33 +1970,25 @@ impl TerminalState {
         // Figure out the dimensions.
         let physical_cols = self.screen().physical_cols;
         let physical_rows = self.screen().physical_rows;
-        let cell_pixel_width = self.pixel_width / physical_cols;
-        let cell_pixel_height = self.pixel_height / physical_rows;
+        let (cell_pixel_width, cell_pixel_height) = self.cell_pixel_size();
 
         let width = image.width.to_pixels(cell_pixel_width, physical_cols);
         let height = image.height.to_pixels(cell_pixel_height, physical_rows);
+        let max_width = physical_cols.saturating_sub(self.cursor.x) * cell_pixel_width;
 
-        // Compute any Automatic dimensions
-        let (width, height) = match (width, height) {
-            (None, None) => (
+        let placement = ImagePlacement::compute(
+            (
                 decoded_image.width() as usize,
                 decoded_image.height() as usize,
             ),
-            (Some(w), None) => {
-                let scale = decoded_image.width() as f32 / w as f32;
-                let h = decoded_image.height() as f32 * scale;
-                (w, h as usize)
-            }
-            (None, Some(h)) => {
-                let scale = decoded_image.height() as f32 / h as f32;
-                let w = decoded_image.width() as f32 * scale;
-                (w as usize, h)
-            }
-            (Some(w), Some(h)) => (w, h),
-        };
+            (width, height),
+            image.preserve_aspect_ratio,
+            (cell_pixel_width, cell_pixel_height),
+            max_width,
+        );
 
-        let image_data = Arc::new(ImageData::with_raw_data(image.data));
-        self.assign_image_to_cells(width as u32, height as u32, image_data);
+        let image_data = Arc::new(ImageData::with_placement(image.data, placement));
+        self.assign_image_to_cells(image_data);
 
         // FIXME: check cursor positioning in iterm
         /*
@@ -1427,25 +2002,7 @@ impl TerminalState {
     fn perform_device(&mut self, dev: Device) {
         match dev {
             Device::DeviceAttributes(a) => error!("unhandled: {:?}", a),
-            Device::SoftReset => {
-                // TODO: see https://vt100.net/docs/vt510-rm/DECSTR.html
-                self.pen = CellAttributes::default();
-                self.insert = false;
-                self.dec_origin_mode = false;
-                // Note that xterm deviates from the documented DECSTR
-                // setting for dec_auto_wrap, so we do too
-                self.dec_auto_wrap = true;
-                self.application_cursor_keys = false;
-                self.application_keypad = false;
-                self.top_and_bottom_margins = 0..self.screen().physical_rows as i64;
-                self.left_and_right_margins = 0..self.screen().physical_cols;
-                self.screen.activate_alt_screen();
-                self.screen.saved_cursor().take();
-                self.screen.activate_primary_screen();
-                self.screen.saved_cursor().take();
-
-                self.reverse_wraparound_mode = false;
-            }
+            Device::SoftReset => self.soft_reset(),
             Device::RequestPrimaryDeviceAttributes => {
                 let mut ident = "\x1b[?65".to_string(); // Vt500
                 ident.push_str(";4"); // Sixel graphics
@@ -1476,7 +2033,76 @@ impl TerminalState {
         }
     }
 
+    /// Returns the flag that holds the state of `code`, for the DEC
+    /// private modes that need nothing more than that.  DECSET and
+    /// DECRST update the flag and DECRQM reports it, so that the two
+    /// can't disagree.
+    fn dec_mode_flag(&mut self, code: &DecPrivateModeCode) -> Option<&mut bool> {
+        match code {
+            DecPrivateModeCode::ApplicationCursorKeys => Some(&mut self.application_cursor_keys),
+            DecPrivateModeCode::DecAnsiMode => Some(&mut self.dec_ansi_mode),
+            DecPrivateModeCode::AutoWrap => Some(&mut self.dec_auto_wrap),
+            DecPrivateModeCode::ShowCursor => Some(&mut self.cursor_visible),
+            DecPrivateModeCode::ReverseWraparound => Some(&mut self.reverse_wraparound_mode),
+            DecPrivateModeCode::SixelScrolling => Some(&mut self.sixel_scrolling),
+            DecPrivateModeCode::MouseTracking => Some(&mut self.mouse_tracking),
+            DecPrivateModeCode::ButtonEventMouse => Some(&mut self.button_event_mouse),
+            DecPrivateModeCode::AnyEventMouse => Some(&mut self.any_event_mouse),
+            DecPrivateModeCode::FocusTracking => Some(&mut self.focus_tracking),
+            DecPrivateModeCode::SGRMouse => Some(&mut self.sgr_mouse),
+            DecPrivateModeCode::BracketedPaste => Some(&mut self.bracketed_paste),
+            DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic => {
+                Some(&mut self.use_private_color_registers_for_each_graphic)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the state of `mode` as reported by DECRPM
+    fn dec_mode_report(&mut self, mode: &DecPrivateMode) -> ModeReport {
+        let code = match mode {
+            DecPrivateMode::Code(code) => code,
+            DecPrivateMode::Unspecified(_) => return ModeReport::NotRecognized,
+        };
+        if let Some(flag) = self.dec_mode_flag(code) {
+            return ModeReport::from_flag(*flag);
+        }
+        match code {
+            DecPrivateModeCode::OriginMode => ModeReport::from_flag(self.dec_origin_mode),
+            DecPrivateModeCode::ReverseVideo => ModeReport::from_flag(self.reverse_video_mode),
+            DecPrivateModeCode::LeftRightMarginMode => {
+                ModeReport::from_flag(self.left_and_right_margin_mode)
+            }
+            DecPrivateModeCode::EnableAlternateScreen
+            | DecPrivateModeCode::OptEnableAlternateScreen
+            | DecPrivateModeCode::ClearAndEnableAlternateScreen => {
+                ModeReport::from_flag(self.screen.is_alt_screen_active())
+            }
+            // Key repeat is left to the gui, which always repeats
+            DecPrivateModeCode::AutoRepeat => ModeReport::PermanentlySet,
+            // These are accepted, but have no effect
+            DecPrivateModeCode::Select132Columns
+            | DecPrivateModeCode::SmoothScroll
+            | DecPrivateModeCode::StartBlinkingCursor
+            | DecPrivateModeCode::HighlightMouseTracking
+            | DecPrivateModeCode::SaveCursor => ModeReport::PermanentlyReset,
+            _ => ModeReport::NotRecognized,
+        }
+    }
+
     fn perform_csi_mode(&mut self, mode: Mode) {
+        let flag_value = match &mode {
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(code)) => Some((code, true)),
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(code)) => Some((code, false)),
+            _ => None,
+        };
+        if let Some((code, enable)) = flag_value {
+            if let Some(flag) = self.dec_mode_flag(code) {
+                *flag = enable;
+                return;
+            }
+        }
+
         match mode {
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::StartBlinkingCursor,
@@ -1490,18 +2116,6 @@ impl TerminalState {
                 // We leave key repeat to the GUI layer prefs
             }
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ReverseWraparound,
-            )) => {
-                self.reverse_wraparound_mode = true;
-            }
-
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ReverseWraparound,
-            )) => {
-                self.reverse_wraparound_mode = false;
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::LeftRightMarginMode,
             )) => {
@@ -1523,14 +2137,6 @@ impl TerminalState {
                 self.dec_restore_cursor();
             }
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AutoWrap)) => {
-                self.dec_auto_wrap = true;
-            }
-
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AutoWrap)) => {
-                self.dec_auto_wrap = false;
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::OriginMode)) => {
                 self.dec_origin_mode = true;
                 self.set_cursor_pos(&Position::Absolute(0), &Position::Absolute(0));
@@ -1541,26 +2147,17 @@ impl TerminalState {
                 self.set_cursor_pos(&Position::Absolute(0), &Position::Absolute(0));
             }
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic,
-            )) => {
-                self.use_private_color_registers_for_each_graphic = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic,
-            )) => {
-                self.use_private_color_registers_for_each_graphic = false;
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SmoothScroll))
             | Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SmoothScroll)) => {
                 // We always output at our "best" rate
             }
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ReverseVideo))
-            | Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ReverseVideo)) => {
-                // I'm mostly intentionally ignoring this in favor
-                // of respecting the configured colors
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ReverseVideo)) => {
+                self.set_reverse_video(true);
+            }
+
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ReverseVideo)) => {
+                self.set_reverse_video(false);
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::Select132Columns))
@@ -1585,13 +2182,6 @@ impl TerminalState {
                 self.insert = false;
             }
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::BracketedPaste)) => {
-                self.bracketed_paste = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::BracketedPaste)) => {
-                self.bracketed_paste = false;
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::OptEnableAlternateScreen,
             ))
@@ -1622,37 +2212,6 @@ impl TerminalState {
                 }
             }
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ApplicationCursorKeys,
-            )) => {
-                self.application_cursor_keys = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ApplicationCursorKeys,
-            )) => {
-                self.application_cursor_keys = false;
-            }
-
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SixelScrolling)) => {
-                self.sixel_scrolling = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SixelScrolling)) => {
-                self.sixel_scrolling = false;
-            }
-
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::DecAnsiMode)) => {
-                self.dec_ansi_mode = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::DecAnsiMode)) => {
-                self.dec_ansi_mode = false;
-            }
-
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
-                self.cursor_visible = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
-                self.cursor_visible = false;
-            }
             Mode::SetMode(TerminalMode::Code(TerminalModeCode::ShowCursor)) => {
                 self.cursor_visible = true;
             }
@@ -1660,13 +2219,6 @@ impl TerminalState {
                 self.cursor_visible = false;
             }
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::MouseTracking)) => {
-                self.mouse_tracking = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::MouseTracking)) => {
-                self.mouse_tracking = false;
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::HighlightMouseTracking,
             ))
@@ -1674,36 +2226,6 @@ impl TerminalState {
                 DecPrivateModeCode::HighlightMouseTracking,
             )) => {}
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ButtonEventMouse)) => {
-                self.button_event_mouse = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ButtonEventMouse,
-            )) => {
-                self.button_event_mouse = false;
-            }
-
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse)) => {
-                self.any_event_mouse = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse)) => {
-                self.any_event_mouse = false;
-            }
-
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
-                self.focus_tracking = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
-                self.focus_tracking = false;
-            }
-
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRMouse)) => {
-                self.sgr_mouse = true;
-            }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRMouse)) => {
-                self.sgr_mouse = false;
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::ClearAndEnableAlternateScreen,
             )) => {
@@ -1723,6 +2245,39 @@ impl TerminalState {
                     self.dec_restore_cursor();
                 }
             }
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(_))
+            | Mode::ResetDecPrivateMode(DecPrivateMode::Code(_)) => {
+                // The modes that are held in a flag were applied above
+            }
+
+            Mode::QueryDecPrivateMode(mode) => {
+                let report = self.dec_mode_report(&mode);
+                let value = match mode {
+                    DecPrivateMode::Code(code) => code.to_u16().unwrap_or(0),
+                    DecPrivateMode::Unspecified(n) => n,
+                };
+                write!(self.writer, "{}?{};{}$y", CSI, value, report as u8).ok();
+                self.writer.flush().ok();
+            }
+
+            Mode::QueryMode(mode) => {
+                let (value, report) = match mode {
+                    TerminalMode::Code(code) => (
+                        code.to_u16().unwrap_or(0),
+                        match code {
+                            TerminalModeCode::Insert => ModeReport::from_flag(self.insert),
+                            TerminalModeCode::ShowCursor => {
+                                ModeReport::from_flag(self.cursor_visible)
+                            }
+                            _ => ModeReport::NotRecognized,
+                        },
+                    ),
+                    TerminalMode::Unspecified(n) => (n, ModeReport::NotRecognized),
+                };
+                write!(self.writer, "{}{};{}$y", CSI, value, report as u8).ok();
+                self.writer.flush().ok();
+            }
+
             Mode::SaveDecPrivateMode(DecPrivateMode::Code(n))
             | Mode::RestoreDecPrivateMode(DecPrivateMode::Code(n)) => {
                 error!("save/restore dec mode {:?} unimplemented", n)
@@ -1807,6 +2362,18 @@ impl TerminalState {
                 self.writer.flush().ok();
             }
 
+            Window::ReportScreenSizeCells => {
+                // We don't know anything about the display, so we report
+                // the largest size that we know we can show: our own
+                let screen = self.screen();
+                let height = Some(screen.physical_rows as i64);
+                let width = Some(screen.physical_cols as i64);
+
+                let response = Window::ReportScreenSizeCellsResponse { width, height };
+                write!(self.writer, "{}", CSI::Window(response)).ok();
+                self.writer.flush().ok();
+            }
+
             Window::ReportCellSizePixels => {
                 let screen = self.screen();
                 let height = screen.physical_rows;
@@ -1855,17 +2422,39 @@ impl TerminalState {
                 write!(self.writer, "\x1bP{}!~{:04x}\x1b\\", request_id, checksum).ok();
                 self.writer.flush().ok();
             }
-            Window::ResizeWindowCells { .. } => {
-                // We don't allow the application to change the window size; that's
-                // up to the user!
+            Window::ResizeWindowCells { width, height } => {
+                // This is only a request; the embedding application
+                // decides whether to allow the window size to change
+                let screen = self.screen();
+                let rows = height.filter(|&h| h > 0).map(|h| h as usize);
+                let cols = width.filter(|&w| w > 0).map(|w| w as usize);
+                self.resize_request.replace(ResizeRequest {
+                    rows: rows.unwrap_or(screen.physical_rows),
+                    cols: cols.unwrap_or(screen.physical_cols),
+                });
+            }
+            Window::ResizeWindowPixels { width, height } => {
+                let screen = self.screen();
+                let cell_height = (self.pixel_height / screen.physical_rows).max(1);
+                let cell_width = (self.pixel_width / screen.physical_cols).max(1);
+                let rows = height
+                    .filter(|&h| h > 0)
+                    .map(|h| (h as usize / cell_height).max(1));
+                let cols = width
+                    .filter(|&w| w > 0)
+                    .map(|w| (w as usize / cell_width).max(1));
+                self.resize_request.replace(ResizeRequest {
+                    rows: rows.unwrap_or(screen.physical_rows),
+                    cols: cols.unwrap_or(screen.physical_cols),
+                });
             }
             Window::Iconify | Window::DeIconify => {}
-            Window::PopIconAndWindowTitle
-            | Window::PopWindowTitle
-            | Window::PopIconTitle
-            | Window::PushIconAndWindowTitle
-            | Window::PushIconTitle
-            | Window::PushWindowTitle => {}
+            Window::PushIconAndWindowTitle => self.push_title(true, true),
+            Window::PushIconTitle => self.push_title(true, false),
+            Window::PushWindowTitle => self.push_title(false, true),
+            Window::PopIconAndWindowTitle => self.pop_title(true, true),
+            Window::PopIconTitle => self.pop_title(true, false),
+            Window::PopWindowTitle => self.pop_title(false, true),
 
             _ => error!("unhandled Window CSI {:?}", window),
         }
@@ -1903,16 +2492,27 @@ impl TerminalState {
     fn perform_csi_edit(&mut self, edit: Edit) {
         match edit {
             Edit::DeleteCharacter(n) => {
+                // https://vt100.net/docs/vt510-rm/DCH.html
+                // Text between the cursor and the right margin moves to
+                // the left, and blanks with the current background are
+                // shifted in at the right margin.
+                // DCH has no effect outside the left and right margins.
                 let y = self.cursor.y;
                 let x = self.cursor.x;
+                self.wrap_next = false;
 
-                if x >= self.left_and_right_margins.start && x < self.left_and_right_margins.end {
+                if self.left_and_right_margins.contains(&x) {
                     let right_margin = self.left_and_right_margins.end;
                     let limit = (x + n as usize).min(right_margin);
+                    let blank = self.pen.clone_sgr_only();
 
                     let screen = self.screen_mut();
-                    for _ in x..limit as usize {
-                        screen.erase_cell(x, y, right_margin);
+                    // Deleting any part of a double width character
+                    // blanks the whole of it
+                    screen.split_double_width(x, y);
+                    screen.split_double_width(limit, y);
+                    for _ in x..limit {
+                        screen.erase_cell(x, y, right_margin, &blank);
                     }
                 }
             }
@@ -1927,15 +2527,33 @@ impl TerminalState {
                         &left_and_right_margins,
                         n as usize,
                     );
+                    // The lines shifted in at the bottom margin take on
+                    // the current background color
+                    let n = (n as VisibleRowIndex)
+                        .min(top_and_bottom_margins.end - top_and_bottom_margins.start);
+                    let blank = self.pen.clone_sgr_only();
+                    let screen = self.screen_mut();
+                    for y in top_and_bottom_margins.end - n..top_and_bottom_margins.end {
+                        screen.clear_line(y, left_and_right_margins.clone(), &blank);
+                    }
+                    // https://vt100.net/docs/vt510-rm/DL.html
+                    // The cursor is reset to the left margin
+                    self.move_to_left_margin();
                 }
             }
             Edit::EraseCharacter(n) => {
+                // https://vt100.net/docs/vt510-rm/ECH.html
+                // Erased characters are replaced by blanks with the
+                // current background, up to the edge of the screen.
+                // The cursor doesn't move.
                 let y = self.cursor.y;
                 let x = self.cursor.x;
+                self.wrap_next = false;
                 let limit = (x + n as usize).min(self.screen().physical_cols);
                 {
                     let blank = Cell::new(' ', self.pen.clone_sgr_only());
                     let screen = self.screen_mut();
+                    screen.split_double_width(limit, y);
                     for x in x..limit as usize {
                         screen.set_cell(x, y, &blank);
                     }
@@ -1961,16 +2579,19 @@ impl TerminalState {
                 // attribute. The cursor remains at the beginning of the blank characters. Text
                 // between the cursor and right margin moves to the right. Characters scrolled past
                 // the right margin are lost. ICH has no effect outside the scrolling margins.
+                // Like xterm, only the left and right margins are considered,
+                // and the blanks take on the current background color.
 
                 let y = self.cursor.y;
                 let x = self.cursor.x;
-                if self.top_and_bottom_margins.contains(&y)
-                    && self.left_and_right_margins.contains(&x)
-                {
+                self.wrap_next = false;
+                if self.left_and_right_margins.contains(&x) {
                     let margin = self.left_and_right_margins.end;
+                    let n = (n as usize).min(margin - x);
+                    let blank = self.pen.clone_sgr_only();
                     let screen = self.screen_mut();
-                    for _ in 0..n as usize {
-                        screen.insert_cell(x, y, margin);
+                    for _ in 0..n {
+                        screen.insert_cell(x, y, margin, &blank);
                     }
                 }
             }
@@ -1985,62 +2606,25 @@ impl TerminalState {
                         &left_and_right_margins,
                         n as usize,
                     );
+                    // The inserted lines take on the current background color
+                    let n = (n as VisibleRowIndex)
+                        .min(top_and_bottom_margins.end - top_and_bottom_margins.start);
+                    let blank = self.pen.clone_sgr_only();
+                    let screen = self.screen_mut();
+                    for y in top_and_bottom_margins.start..top_and_bottom_margins.start + n {
+                        screen.clear_line(y, left_and_right_margins.clone(), &blank);
+                    }
+                    // https://vt100.net/docs/vt510-rm/IL.html
+                    // The cursor is reset to the left margin
+                    self.move_to_left_margin();
                 }
             }
             Edit::ScrollDown(n) => self.scroll_down(n as usize),
             Edit::ScrollUp(n) => self.scroll_up(n as usize),
             Edit::EraseInDisplay(erase) => self.erase_in_display(erase),
-            Edit::Repeat(n) => {
-                let mut y = self.cursor.y;
-                let mut x = self.cursor.x;
-                let left_and_right_margins = self.left_and_right_margins.clone();
-                let top_and_bottom_margins = self.top_and_bottom_margins.clone();
-
-                // Resolve the source cell.  It may be a double-wide character.
-                let cell = {
-                    let screen = self.screen_mut();
-                    let to_copy = x.saturating_sub(1);
-                    let line_idx = screen.phys_row(y);
-                    let line = screen.line_mut(line_idx);
-
-                    let candidate = &line.cells()[to_copy];
-                    if candidate.str() == " " && to_copy > 0 {
-                        // It's a blank.  It may be the second part of
-                        // a double-wide pair; look ahead of it.
-                        let prior = &line.cells()[to_copy - 1];
-                        if prior.width() > 1 {
-                            prior.clone()
-                        } else {
-                            candidate.clone()
-                        }
-                    } else {
-                        candidate.clone()
-                    }
-                };
-
-                for _ in 0..n {
-                    {
-                        let screen = self.screen_mut();
-                        let line_idx = screen.phys_row(y);
-                        let line = screen.line_mut(line_idx);
-
-                        line.set_cell(x, cell.clone());
-                    }
-                    x += 1;
-                    if x > left_and_right_margins.end - 1 {
-                        x = left_and_right_margins.start;
-                        if y == top_and_bottom_margins.end - 1 {
-                            self.scroll_up(1);
-                        } else {
-                            y += 1;
-                            if y > top_and_bottom_margins.end - 1 {
-                                y = top_and_bottom_margins.end;
-                            }
-                        }
-                    }
-                }
-                self.cursor.x = x;
-                self.cursor.y = y;
+            Edit::Repeat(_) => {
+                // REP goes through the regular printing logic, and so is
+                // handled by the Performer
             }
         }
     }
@@ -2050,9 +2634,8 @@ impl TerminalState {
         // The terminal only recognizes this control function if vertical split
         // screen mode (DECLRMM) is set.
         if self.left_and_right_margin_mode {
-            let rows = self.screen().physical_rows as u32;
             let cols = self.screen().physical_cols as u32;
-            let left = left.as_zero_based().min(rows - 1).max(0) as usize;
+            let left = left.as_zero_based().min(cols - 1).max(0) as usize;
             let right = right.as_zero_based().min(cols - 1).max(0) as usize;
 
             // The value of the left margin (Pl) must be less than the right margin (Pr).
@@ -2105,20 +2688,45 @@ impl TerminalState {
                 }
             }
             Cursor::BackwardTabulation(n) => {
+                // Stop at the left margin, unless we're already left of it
+                let left = if self.cursor.x >= self.left_and_right_margins.start {
+                    self.left_and_right_margins.start
+                } else {
+                    0
+                };
+                let mut x = self.cursor.x;
                 for _ in 0..n {
-                    let x = match self.tabs.find_prev_tab_stop(self.cursor.x) {
-                        Some(x) => x,
-                        None => 0,
+                    x = match self.tabs().find_prev_tab_stop(x) {
+                        Some(x) => x.max(left),
+                        None => left,
                     };
-                    self.set_cursor_pos(&Position::Absolute(x as i64), &Position::Relative(0));
                 }
+                let y = self.cursor.y;
+                self.set_cursor_position_absolute(x, y);
             }
 
             Cursor::TabulationClear(to_clear) => {
-                self.tabs.clear(to_clear, self.cursor.x);
+                let x = self.cursor.x;
+                self.tabs_mut().clear(to_clear, x);
             }
 
-            Cursor::TabulationControl(_) => {}
+            Cursor::TabulationControl(control) => {
+                let x = self.cursor.x;
+                match control {
+                    CursorTabulationControl::SetCharacterTabStopAtActivePosition => {
+                        self.tabs_mut().set_tab_stop(x)
+                    }
+                    CursorTabulationControl::ClearCharacterTabStopAtActivePosition => self
+                        .tabs_mut()
+                        .clear(TabulationClear::ClearCharacterTabStopAtActivePosition, x),
+                    CursorTabulationControl::ClearAllCharacterTabStopsAtActiveLine
+                    | CursorTabulationControl::ClearAllCharacterTabStops => self
+                        .tabs_mut()
+                        .clear(TabulationClear::ClearAllCharacterTabStops, x),
+                    _ => log::error!("unhandled TabulationControl {:?}", control),
+                }
+            }
+            Cursor::SetDefaultTabStops => self.tabs_mut().set_defaults(),
             Cursor::LineTabulation(_) => {}
 
             Cursor::Left(n) => {
@@ -2225,19 +2833,10 @@ impl TerminalState {
                 &Position::Relative(0),
             ),
 
-            Cursor::CharacterPositionAbsolute(col) => {
-                let col = col.as_zero_based() as usize;
-                let col = if self.dec_origin_mode {
-                    col + self.left_and_right_margins.start
-                } else {
-                    col
-                };
-                self.cursor.x = col.min(self.screen().physical_cols - 1);
-                self.wrap_next = false;
-                let y = self.cursor.y;
-                let screen = self.screen_mut();
-                screen.dirty_line(y);
-            }
+            Cursor::CharacterPositionAbsolute(col) => self.set_cursor_pos(
+                &Position::Absolute(i64::from(col.as_zero_based())),
+                &Position::Relative(0),
+            ),
 
             Cursor::CharacterPositionBackward(col) => self.set_cursor_pos(
                 &Position::Relative(-(i64::from(col))),
@@ -2365,6 +2964,9 @@ impl TerminalState {
             wrap_next: self.wrap_next,
             pen: self.pen.clone(),
             dec_origin_mode: self.dec_origin_mode,
+            g0_charset: self.g0_charset,
+            g1_charset: self.g1_charset,
+            shift_out: self.shift_out,
         };
         debug!(
             "saving cursor {:?} is_alt={}",
@@ -2385,6 +2987,9 @@ impl TerminalState {
                 wrap_next: false,
                 pen: Default::default(),
                 dec_origin_mode: false,
+                g0_charset: CharSet::Ascii,
+                g1_charset: CharSet::Ascii,
+                shift_out: false,
             });
         debug!(
             "restore cursor {:?} is_alt={}",
@@ -2400,6 +3005,9 @@ impl TerminalState {
         self.wrap_next = saved.wrap_next;
         self.pen = saved.pen;
         self.dec_origin_mode = saved.dec_origin_mode;
+        self.g0_charset = saved.g0_charset;
+        self.g1_charset = saved.g1_charset;
+        self.shift_out = saved.shift_out;
     }
 
     fn perform_csi_sgr(&mut self, sgr: Sgr) {
@@ -2450,6 +3058,9 @@ impl TerminalState {
 pub(crate) struct Performer<'a> {
     pub state: &'a mut TerminalState,
     print: Option<String>,
+    /// The raw bytes of the sequence being performed, so that those
+    /// that we don't understand can be reported as they were received
+    raw: Vec<u8>,
 }
 
 impl<'a> Deref for Performer<'a> {
@@ -2474,7 +3085,63 @@ impl<'a> Drop for Performer<'a> {
 
 impl<'a> Performer<'a> {
     pub fn new(state: &'a mut TerminalState) -> Self {
-        Self { state, print: None }
+        Self {
+            state,
+            print: None,
+            raw: vec![],
+        }
+    }
+
+    /// Attaches a zero width grapheme, such as a combining mark that
+    /// arrived separately from its base character, to the cell that
+    /// was most recently printed so that the cluster is treated as a
+    /// single cell.
+    /// Returns false if there is no such cell on the cursor line.
+    fn attach_to_previous_cell(&mut self, g: &str) -> bool {
+        let wrap_next = self.wrap_next;
+        // When a wrap is pending the cursor remains on the last cell
+        // that was printed
+        let x = if wrap_next {
+            self.cursor.x
+        } else {
+            match self.cursor.x.checked_sub(1) {
+                Some(x) => x,
+                None => return false,
+            }
+        };
+        let y = self.cursor.y;
+        let screen = self.screen_mut();
+        let line_idx = screen.phys_row(y);
+        let line = screen.line_mut(line_idx);
+
+        // The cell to the left of the cursor may be the second half
+        // of a double width character
+        let x = match x.checked_sub(1).and_then(|prior| line.cells().get(prior)) {
+            Some(cell) if !wrap_next && cell.width() > 1 => x - 1,
+            _ => x,
+        };
+
+        let mut cell = match line.cells().get(x) {
+            Some(cell) => cell.clone(),
+            None => return false,
+        };
+        let prior_width = cell.width();
+        cell.append_grapheme_for_version(g, self.unicode_version);
+        let grown = cell.width().saturating_sub(prior_width);
+        line.set_cell(x, cell);
+
+        // eg: VS16 changes the presentation of the base character to
+        // a double width emoji
+        if grown > 0 && !wrap_next {
+            let width = self.right_margin_for_cursor();
+            if self.cursor.x + grown < width {
+                self.cursor.x += grown;
+            } else {
+                self.cursor.x = width.saturating_sub(1);
+                self.wrap_next = self.dec_auto_wrap;
+            }
+        }
+        true
     }
 
     fn flush_print(&mut self) {
@@ -2483,53 +3150,63 @@ impl<'a> Performer<'a> {
             None => return,
         };
 
-        for g in unicode_segmentation::UnicodeSegmentation::graphemes(p.as_str(), true) {
-            let g = if self.dec_line_drawing_mode {
-                match g {
-                    "j" => "┘",
-                    "k" => "┐",
-                    "l" => "┌",
-                    "m" => "└",
-                    "n" => "┼",
-                    "q" => "─",
-                    "t" => "├",
-                    "u" => "┤",
-                    "v" => "┴",
-                    "w" => "┬",
-                    "x" => "│",
-                    _ => g,
-                }
-            } else {
-                g
-            };
+        let charset = if self.shift_out {
+            self.g1_charset
+        } else {
+            self.g0_charset
+        };
 
-            if self.wrap_next {
-                self.new_line(true);
-            }
+        for graphic in unicode_segmentation::UnicodeSegmentation::graphemes(p.as_str(), true) {
+            let g = charset.map(graphic);
 
-            let x = self.cursor.x;
-            let y = self.cursor.y;
-            let width = self.left_and_right_margins.end;
+            if grapheme_column_width_for_version(g, self.unicode_version) == 0
+                && self.attach_to_previous_cell(g)
+            {
+                continue;
+            }
 
-            let mut pen = self.pen.clone();
             // the max(1) here is to ensure that we advance to the next cell
             // position for zero-width graphemes.  We want to make sure that
             // they occupy a cell so that we can re-emit them when we output them.
             // If we didn't do this, then we'd effectively filter them out from
             // the model, which seems like a lossy design choice.
-            let print_width = unicode_column_width(g).max(1);
+            let print_width = unicode_column_width_for_version(g, self.unicode_version).max(1);
+            self.last_graphic.replace(graphic.to_string());
+
+            // A double width character that doesn't fit in the space that
+            // remains before the margin is wrapped to the next line first,
+            // rather than being split across the margin
+            if !self.wrap_next
+                && self.dec_auto_wrap
+                && print_width > 1
+                && self.cursor.x > self.left_and_right_margins.start
+                && self.cursor.x + print_width > self.right_margin_for_cursor()
+            {
+                self.wrap_next = true;
+            }
 
-            if x + print_width >= width {
-                pen.set_wrapped(true);
+            // The wrap is deferred until the next printable character so
+            // that text filling the last column doesn't move the cursor
+            // to the next line prematurely
+            if self.wrap_next {
+                self.soft_wrap();
             }
 
-            let cell = Cell::new_grapheme(g, pen);
+            let x = self.cursor.x;
+            let y = self.cursor.y;
+            let width = self.right_margin_for_cursor();
+
+            let mut pen = self.pen.clone();
+            pen.set_written(true);
+
+            let cell = Cell::new_grapheme_for_version(g, self.unicode_version, pen);
 
             if self.insert {
-                let margin = self.left_and_right_margins.end;
+                let margin = width;
+                let blank = self.pen.clone_sgr_only();
                 let screen = self.screen_mut();
                 for _ in x..x + print_width as usize {
-                    screen.insert_cell(x, y, margin);
+                    screen.insert_cell(x, y, margin, &blank);
                 }
             }
 
@@ -2546,6 +3223,32 @@ impl<'a> Performer<'a> {
         }
     }
 
+    /// REP: prints the most recently printed grapheme n more times, with
+    /// the same wrapping, insert mode and margin handling as if the
+    /// application had sent those characters itself
+    fn repeat_last_graphic(&mut self, n: u32) {
+        if let Some(graphic) = self.last_graphic.clone() {
+            // Limit the repeat to filling the screen; anything beyond that
+            // would simply scroll away
+            let limit = self.screen().physical_rows * self.screen().physical_cols;
+            self.print = Some(graphic.repeat((n as usize).min(limit)));
+            self.flush_print();
+        }
+    }
+
+    /// Performs an action that was decoded from `raw`
+    pub fn perform_raw(&mut self, action: Action, raw: &[u8]) {
+        match action {
+            // Printable text is never reported, so skip the copy
+            Action::Print(_) => {}
+            _ => {
+                self.raw.clear();
+                self.raw.extend_from_slice(raw);
+            }
+        }
+        self.perform(action);
+    }
+
     pub fn perform(&mut self, action: Action) {
         debug!("perform {:?}", action);
         match action {
@@ -2667,17 +3370,19 @@ impl<'a> Performer<'a> {
                     && self.cursor.y == self.top_and_bottom_margins.start
                 {
                     // Backspace off the top-left wraps around to the bottom right
-                    let x_pos = Position::Absolute(self.left_and_right_margins.end as i64 - 1);
-                    let y_pos = Position::Absolute(self.top_and_bottom_margins.end - 1);
-                    self.set_cursor_pos(&x_pos, &y_pos);
+                    self.set_cursor_position_absolute(
+                        self.left_and_right_margins.end - 1,
+                        self.top_and_bottom_margins.end - 1,
+                    );
                 } else if self.reverse_wraparound_mode
                     && self.dec_auto_wrap
                     && self.cursor.x <= self.left_and_right_margins.start
                 {
                     // Backspace off the left wraps around to the prior line on the right
-                    let x_pos = Position::Absolute(self.left_and_right_margins.end as i64 - 1);
-                    let y_pos = Position::Relative(-1);
-                    self.set_cursor_pos(&x_pos, &y_pos);
+                    self.set_cursor_position_absolute(
+                        self.left_and_right_margins.end - 1,
+                        (self.cursor.y - 1).max(0),
+                    );
                 } else if self.reverse_wraparound_mode
                     && self.dec_auto_wrap
                     && self.cursor.x == self.left_and_right_margins.end - 1
@@ -2696,8 +3401,10 @@ impl<'a> Performer<'a> {
             ControlCode::HTS => self.c1_hts(),
             ControlCode::IND => self.c1_index(),
             ControlCode::NEL => self.c1_nel(),
-            ControlCode::Bell => error!("Ding! (this is the bell)"),
+            ControlCode::Bell => self.bell_rung = true,
             ControlCode::RI => self.c1_reverse_index(),
+            ControlCode::ShiftOut => self.shift_out = true,
+            ControlCode::ShiftIn => self.shift_out = false,
             _ => error!("unhandled ControlCode {:?}", control),
         }
     }
@@ -2707,14 +3414,17 @@ impl<'a> Performer<'a> {
         match csi {
             CSI::Sgr(sgr) => self.state.perform_csi_sgr(sgr),
             CSI::Cursor(cursor) => self.state.perform_csi_cursor(cursor),
+            CSI::Edit(Edit::Repeat(n)) => self.repeat_last_graphic(n),
             CSI::Edit(edit) => self.state.perform_csi_edit(edit),
             CSI::Mode(mode) => self.state.perform_csi_mode(mode),
             CSI::Device(dev) => self.state.perform_device(*dev),
             CSI::Mouse(mouse) => error!("mouse report sent by app? {:?}", mouse),
             CSI::Window(window) => self.state.perform_csi_window(window),
-            CSI::Unspecified(unspec) => {
-                error!("unknown unspecified CSI: {:?}", format!("{}", unspec))
-            }
+            CSI::Unspecified(unspec) => error!(
+                "unknown unspecified CSI: {:?} [{}]",
+                format!("{}", unspec),
+                hex_dump(&self.raw)
+            ),
         };
     }
 
@@ -2737,12 +3447,12 @@ impl<'a> Performer<'a> {
             Esc::Code(EscCode::Index) => self.c1_index(),
             Esc::Code(EscCode::NextLine) => self.c1_nel(),
             Esc::Code(EscCode::HorizontalTabSet) => self.c1_hts(),
-            Esc::Code(EscCode::DecLineDrawing) => {
-                self.dec_line_drawing_mode = true;
-            }
-            Esc::Code(EscCode::AsciiCharacterSet) => {
-                self.dec_line_drawing_mode = false;
-            }
+            Esc::Code(EscCode::DecLineDrawing) => self.g0_charset = CharSet::DecLineDrawing,
+            Esc::Code(EscCode::UkCharacterSet) => self.g0_charset = CharSet::Uk,
+            Esc::Code(EscCode::AsciiCharacterSet) => self.g0_charset = CharSet::Ascii,
+            Esc::Code(EscCode::DecLineDrawingG1) => self.g1_charset = CharSet::DecLineDrawing,
+            Esc::Code(EscCode::UkCharacterSetG1) => self.g1_charset = CharSet::Uk,
+            Esc::Code(EscCode::AsciiCharacterSetG1) => self.g1_charset = CharSet::Ascii,
             Esc::Code(EscCode::DecSaveCursorPosition) => self.dec_save_cursor(),
             Esc::Code(EscCode::DecRestoreCursorPosition) => self.dec_restore_cursor(),
 
@@ -2772,50 +3482,30 @@ impl<'a> Performer<'a> {
             // on. This may imply, if applicable: remove tabulation stops, remove qualified areas,
             // reset graphic rendition, erase all positions, move active position to first
             // character position of first line.
-            Esc::Code(EscCode::FullReset) => {
-                self.pen = Default::default();
-                self.cursor = Default::default();
-                self.wrap_next = false;
-                self.insert = false;
-                self.dec_auto_wrap = true;
-                self.reverse_wraparound_mode = false;
-                self.dec_origin_mode = false;
-                self.use_private_color_registers_for_each_graphic = false;
-                self.color_map = default_color_map();
-                self.application_cursor_keys = false;
-                self.sixel_scrolling = true;
-                self.dec_ansi_mode = false;
-                self.application_keypad = false;
-                self.bracketed_paste = false;
-                self.focus_tracking = false;
-                self.sgr_mouse = false;
-                self.any_event_mouse = false;
-                self.button_event_mouse = false;
-                self.current_mouse_button = MouseButton::None;
-                self.cursor_visible = true;
-                self.dec_line_drawing_mode = false;
-                self.tabs = TabStop::new(self.screen().physical_cols, 8);
-                self.palette.take();
-                self.top_and_bottom_margins = 0..self.screen().physical_rows as VisibleRowIndex;
-                self.left_and_right_margins = 0..self.screen().physical_cols;
+            Esc::Code(EscCode::FullReset) => self.full_reset(),
 
-                self.screen.activate_primary_screen();
-                self.erase_in_display(EraseInDisplay::EraseDisplay);
-            }
+            // The parser has already switched to the selected encoding
+            Esc::Code(EscCode::SelectLatin1Encoding) | Esc::Code(EscCode::SelectUtf8Encoding) => {}
 
-            _ => error!("ESC: unhandled {:?}", esc),
+            _ => error!("ESC: unhandled {:?} [{}]", esc, hex_dump(&self.raw)),
         }
     }
 
     fn osc_dispatch(&mut self, osc: OperatingSystemCommand) {
         self.flush_print();
         match osc {
-            OperatingSystemCommand::SetIconNameAndWindowTitle(title)
-            | OperatingSystemCommand::SetWindowTitleSun(title)
+            OperatingSystemCommand::SetIconNameAndWindowTitle(title) => {
+                self.icon_title = title.clone();
+                self.title = title;
+            }
+            OperatingSystemCommand::SetWindowTitleSun(title)
             | OperatingSystemCommand::SetWindowTitle(title) => {
-                self.title = title.clone();
+                self.title = title;
+            }
+            OperatingSystemCommand::SetIconNameSun(title)
+            | OperatingSystemCommand::SetIconName(title) => {
+                self.icon_title = title;
             }
-            OperatingSystemCommand::SetIconNameSun(_) | OperatingSystemCommand::SetIconName(_) => {}
             OperatingSystemCommand::SetHyperlink(link) => {
                 self.set_hyperlink(link);
             }
@@ -2825,7 +3515,7 @@ impl<'a> Performer<'a> {
                 for item in unspec {
                     write!(&mut output, " {}", String::from_utf8_lossy(&item)).ok();
                 }
-                error!("{}", output);
+                error!("{} [{}]", output, hex_dump(&self.raw));
             }
 
             OperatingSystemCommand::ClearSelection(_) => {
@@ -2840,14 +3530,52 @@ impl<'a> Performer<'a> {
             }
             OperatingSystemCommand::ITermProprietary(iterm) => match iterm {
                 ITermProprietary::File(image) => self.set_image(*image),
+                ITermProprietary::UnicodeVersion(op) => self.apply_unicode_version_op(op),
+                ITermProprietary::SetUserVar { name, value } => self.set_user_var(name, value),
+                ITermProprietary::RequestTabInfo => self.tab_info_requested = true,
                 _ => error!("unhandled iterm2: {:?}", iterm),
             },
             OperatingSystemCommand::SystemNotification(message) => {
-                error!("Application sends SystemNotification: {}", message);
+                self.push_notification(ToastNotification {
+                    title: None,
+                    body: message,
+                });
+            }
+            OperatingSystemCommand::RxvtExtension(params) => {
+                if params.get(0).map(String::as_str) == Some("notify") && params.len() >= 2 {
+                    // The body may itself contain semicolons
+                    self.push_notification(ToastNotification {
+                        title: Some(params[1].clone()),
+                        body: params[2..].join(";"),
+                    });
+                } else {
+                    error!("unhandled rxvt extension: {:?}", params);
+                }
+            }
+            OperatingSystemCommand::ConEmuProgress(progress) => {
+                self.progress = progress;
+            }
+            OperatingSystemCommand::SetStatusText(text) => {
+                self.status_text = if text.is_empty() { None } else { Some(text) };
             }
             OperatingSystemCommand::CurrentWorkingDirectory(url) => {
                 self.current_dir = Url::parse(&url).ok();
             }
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::FreshLineAndStartPrompt,
+            ) => {
+                if self.cursor.x != 0 {
+                    self.new_line(true);
+                }
+                let y = self.cursor.y;
+                let screen = self.screen_mut();
+                let phys = screen.phys_row(y);
+                screen.line_mut(phys).set_prompt(true);
+                screen.dirty_line(y);
+            }
+            OperatingSystemCommand::FinalTermSemanticPrompt(marker) => {
+                log::trace!("FinalTermSemanticPrompt: {:?}", marker);
+            }
             OperatingSystemCommand::ChangeColorNumber(specs) => {
                 log::trace!("ChangeColorNumber: {:?}", specs);
                 for pair in specs {
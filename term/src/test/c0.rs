@@ -40,3 +40,15 @@ fn test_tab() {
     term.print("\t");
     term.assert_cursor_pos(24, 0, None);
 }
+
+#[test]
+fn test_so_si() {
+    let mut term = TestTerm::new(2, 8, 0);
+    // Designate DEC line drawing into G1; it only applies while shifted out
+    term.print("\x1b)0lq\x0elqk\x0flq");
+    assert_visible_contents(&term, file!(), line!(), &["lq┌─┐lq ", "        "]);
+
+    // Designating into G0 applies immediately
+    term.print("\r\n\x1b(0x~\x1b(Bx\x1b(A#");
+    assert_visible_contents(&term, file!(), line!(), &["lq┌─┐lq ", "│·x£    "]);
+}
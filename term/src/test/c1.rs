@@ -1,6 +1,7 @@
 //! Testing C1 control sequences
 
 use super::*;
+use termwiz::escape::parser::Encoding;
 
 #[test]
 fn test_ind() {
@@ -69,3 +70,23 @@ fn test_ri() {
     term.assert_cursor_pos(1, 0, None);
     assert_visible_contents(&term, file!(), line!(), &["  ", "a ", "b ", "c "]);
 }
+
+#[test]
+fn test_8bit_c1_in_utf8() {
+    let mut term = TestTerm::new(1, 5, 0);
+    // 0x9b is not a CSI, and the second byte of U+049B is not one either
+    term.print(b"\x9b1m");
+    term.print("\u{49b}.");
+    assert_visible_contents(&term, file!(), line!(), &["\u{fffd}1m\u{49b}."]);
+}
+
+#[test]
+fn test_latin1() {
+    let mut term = TestTerm::new(1, 4, 0);
+    term.print("\x1b%@");
+    assert_eq!(term.encoding(), Encoding::Latin1);
+    term.print(b"\xe9\xe8");
+    term.print("\x1b%G\u{fc}");
+    assert_eq!(term.encoding(), Encoding::Utf8);
+    assert_visible_contents(&term, file!(), line!(), &["\u{e9}\u{e8}\u{fc} "]);
+}
@@ -192,3 +192,379 @@ fn test_ed_erase_scrollback() {
     term.print("b");
     assert_all_contents(&term, file!(), line!(), &["111", "222", "ab "]);
 }
+
+/// Fills a 4x6 screen with distinct characters and sets the margins
+/// to rows 2-3 and columns 2-5, as the vttest margin tests do
+fn margin_term() -> TestTerm {
+    let mut term = TestTerm::new(4, 6, 0);
+    term.print("abcdef\r\nghijkl\r\nmnopqr\r\nstuvwx");
+    // DECLRMM, DECSLRM and DECSTBM
+    term.print("\x1b[?69h\x1b[2;5s\x1b[2;3r");
+    term
+}
+
+#[test]
+fn test_scroll_within_margins() {
+    let mut term = margin_term();
+    term.cup(2, 2);
+    term.clean_dirty_lines();
+
+    // IND on the bottom margin scrolls only the text within the margins
+    term.print("\x1bD");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "gnopql", "m    r", "stuvwx"],
+    );
+    term.assert_cursor_pos(2, 2, None);
+    term.assert_dirty_lines(&[1, 2], Some("only the scroll region is dirtied"));
+
+    // RI on the top margin scrolls it back down
+    term.cup(2, 1);
+    term.print("\x1bM");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "g    l", "mnopqr", "stuvwx"],
+    );
+}
+
+#[test]
+fn test_lf_outside_left_and_right_margins() {
+    let mut term = margin_term();
+    // The cursor is on the bottom margin, but to the right of the
+    // right margin, so LF doesn't scroll
+    term.cup(5, 2);
+    term.print("\n");
+    term.assert_cursor_pos(5, 2, None);
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "ghijkl", "mnopqr", "stuvwx"],
+    );
+}
+
+#[test]
+fn test_origin_mode_within_margins() {
+    let mut term = margin_term();
+    // DECOM homes the cursor to the top left of the margins
+    term.print("\x1b[?6h");
+    term.assert_cursor_pos(1, 1, None);
+    term.cup(0, 0);
+    term.assert_cursor_pos(1, 1, None);
+    term.cup(1, 1);
+    term.assert_cursor_pos(2, 2, None);
+    // and the cursor is confined to the margins
+    term.cup(10, 10);
+    term.assert_cursor_pos(4, 2, None);
+
+    // HPA and VPA are relative to the margins too
+    term.print("\x1b[2`");
+    term.assert_cursor_pos(2, 2, None);
+    term.print("\x1b[1d");
+    term.assert_cursor_pos(2, 1, None);
+
+    // Printed text wraps within the margins, and wrapping on the
+    // bottom margin scrolls the region
+    term.cup(0, 0);
+    term.print("123456789");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "g5678l", "m9   r", "stuvwx"],
+    );
+    term.assert_cursor_pos(2, 2, None);
+
+    // Leaving origin mode homes the cursor to the top left of the screen
+    term.print("\x1b[?6l");
+    term.assert_cursor_pos(0, 0, None);
+}
+
+#[test]
+fn test_decslrm_right_of_rows() {
+    // The left margin is clamped to the number of columns, not rows
+    let mut term = TestTerm::new(2, 6, 0);
+    term.print("\x1b[?69h\x1b[4;6s\x1b[?6h");
+    term.assert_cursor_pos(3, 0, None);
+}
+
+#[test]
+fn test_ich_dch_within_margins() {
+    let mut term = margin_term();
+    term.cup(2, 1);
+    term.clean_dirty_lines();
+    term.print("\x1b[@");
+    // The character pushed past the right margin is lost, but the
+    // character beyond the margin doesn't move
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "gh ijl", "mnopqr", "stuvwx"],
+    );
+    term.assert_dirty_lines(&[1], None);
+
+    term.cup(2, 2);
+    term.clean_dirty_lines();
+    term.print("\x1b[P");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "gh ijl", "mnpq r", "stuvwx"],
+    );
+    term.assert_dirty_lines(&[2], None);
+}
+
+#[test]
+fn test_il_dl_within_margins() {
+    let mut term = margin_term();
+    term.cup(3, 1);
+    term.print("\x1b[L");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "g    l", "mhijkr", "stuvwx"],
+    );
+    // The cursor moves to the left margin
+    term.assert_cursor_pos(1, 1, None);
+
+    term.cup(3, 1);
+    term.print("\x1b[M");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "ghijkl", "m    r", "stuvwx"],
+    );
+    term.assert_cursor_pos(1, 1, None);
+}
+
+#[test]
+fn test_edit_bce() {
+    let mut term = TestTerm::new(4, 6, 0);
+    term.print("abcdef\r\nghijkl\r\nmnopqr\r\nstuvwx");
+    // Set background color to blue
+    term.print("\x1b[44m");
+
+    let navy = CellAttributes::default()
+        .set_background(color::AnsiColor::Navy)
+        .background;
+    let navy_cols = |term: &TestTerm, row: usize| -> Vec<usize> {
+        term.screen().visible_lines()[row]
+            .cells()
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.attrs().background == navy)
+            .map(|(idx, _)| idx)
+            .collect()
+    };
+
+    term.cup(1, 0);
+    term.clean_dirty_lines();
+    term.print("\x1b[2@");
+    assert_eq!(navy_cols(&term, 0), vec![1, 2]);
+    term.assert_dirty_lines(&[0], None);
+
+    term.cup(1, 1);
+    term.clean_dirty_lines();
+    term.print("\x1b[2P");
+    assert_eq!(navy_cols(&term, 1), vec![4, 5]);
+    term.assert_dirty_lines(&[1], None);
+
+    term.cup(1, 2);
+    term.clean_dirty_lines();
+    term.print("\x1b[2X");
+    assert_eq!(navy_cols(&term, 2), vec![1, 2]);
+    term.assert_dirty_lines(&[2], None);
+    term.assert_cursor_pos(1, 2, None);
+
+    term.cup(0, 3);
+    term.print("\x1b[L");
+    assert_eq!(navy_cols(&term, 3), vec![0, 1, 2, 3, 4, 5]);
+
+    term.cup(0, 0);
+    term.print("\x1b[M");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["gjkl  ", "m  pqr", "      ", "      "],
+    );
+    assert_eq!(navy_cols(&term, 3), vec![0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn test_edit_double_width() {
+    // Inserting at the second half of a double width character
+    // blanks the whole of it
+    let mut term = TestTerm::new(1, 6, 0);
+    term.print("a\u{4e2d}bc");
+    term.cup(2, 0);
+    term.print("\x1b[@");
+    assert_visible_contents(&term, file!(), line!(), &["a   bc"]);
+
+    // As does deleting it
+    let mut term = TestTerm::new(1, 6, 0);
+    term.print("a\u{4e2d}bc");
+    term.cup(2, 0);
+    term.print("\x1b[P");
+    assert_visible_contents(&term, file!(), line!(), &["a bc  "]);
+
+    // or erasing only its first half
+    let mut term = TestTerm::new(1, 6, 0);
+    term.print("a\u{4e2d}bc");
+    term.cup(0, 0);
+    term.print("\x1b[2X");
+    assert_visible_contents(&term, file!(), line!(), &["   bc "]);
+
+    // A double width character pushed past the right margin is lost,
+    // rather than being left with only its first half on screen
+    let mut term = TestTerm::new(1, 4, 0);
+    term.print("ab\u{4e2d}");
+    term.cup(0, 0);
+    term.print("\x1b[@");
+    assert_visible_contents(&term, file!(), line!(), &[" ab "]);
+}
+
+#[test]
+fn test_rep_wrap() {
+    // REP repeats the last printed character even when a wrap is
+    // pending, and wraps in the same way that printing does
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("abcd\x1b[2b");
+    assert_visible_contents(&term, file!(), line!(), &["abcd", "dd  ", "    "]);
+    term.assert_cursor_pos(2, 1, None);
+
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("\u{4e2d}\x1b[2b");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["\u{4e2d}\u{4e2d}", "\u{4e2d}  ", "    "],
+    );
+}
+
+#[test]
+fn test_ich_outside_scroll_region() {
+    // Only the left and right margins limit ICH, so that a status line
+    // below the scroll region can be edited in place
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("abc\r\ndef\r\nghi\x1b[1;2r");
+    term.cup(0, 2);
+    term.print("\x1b[@");
+    assert_visible_contents(&term, file!(), line!(), &["abc ", "def ", " ghi"]);
+}
+
+#[test]
+fn test_print_right_of_right_margin() {
+    let mut term = TestTerm::new(2, 6, 0);
+    term.print("\x1b[?69h\x1b[1;3s");
+    // Text beyond the right margin wraps at the edge of the screen
+    term.cup(4, 0);
+    term.print("xyz");
+    assert_visible_contents(&term, file!(), line!(), &["    xy", "z     "]);
+}
+
+#[test]
+fn test_tab_stops() {
+    let mut term = TestTerm::new(3, 25, 0);
+
+    // TBC 3 clears all of the stops, so HT moves to the right margin
+    term.print("\x1b[3g\t");
+    term.assert_cursor_pos(24, 0, None);
+
+    // DECST8C restores the defaults; CHT and CBT move by N stops
+    term.print("\x1b[?5W");
+    term.cup(0, 0);
+    term.print("\x1b[2I");
+    term.assert_cursor_pos(16, 0, None);
+    term.print("\x1b[Z");
+    term.assert_cursor_pos(8, 0, None);
+    term.print("\x1b[3Z");
+    term.assert_cursor_pos(0, 0, Some("CBT stops at the left margin"));
+
+    // HTS sets a stop and TBC 0 clears it again
+    term.cup(4, 0);
+    term.print("\x1bH");
+    term.cup(0, 0);
+    term.print("\t");
+    term.assert_cursor_pos(4, 0, None);
+    term.print("\x1b[g");
+    term.cup(0, 0);
+    term.print("\t");
+    term.assert_cursor_pos(8, 0, None);
+
+    // CTC 0 and 2 do the same
+    term.cup(5, 0);
+    term.print("\x1b[W");
+    term.cup(0, 0);
+    term.print("\t");
+    term.assert_cursor_pos(5, 0, None);
+    term.print("\x1b[2W");
+    term.cup(0, 0);
+    term.print("\t");
+    term.assert_cursor_pos(8, 0, None);
+}
+
+#[test]
+fn test_alt_screen_tab_stops() {
+    let mut term = TestTerm::new(3, 25, 0);
+
+    term.print("\x1b[?1049h\x1b[3g");
+    term.cup(0, 0);
+    term.print("\t");
+    term.assert_cursor_pos(24, 0, Some("alt screen stops were cleared"));
+
+    term.print("\x1b[?1049l");
+    term.cup(0, 0);
+    term.print("\t");
+    term.assert_cursor_pos(8, 0, Some("primary screen stops are unaffected"));
+
+    // Resizing extends the stops of both screens
+    term.resize(3, 40, 3 * 16, 40 * 8);
+    term.print("\x1b[?1049h");
+    term.cup(0, 0);
+    term.print("\t");
+    term.assert_cursor_pos(32, 0, None);
+    term.print("\x1b[?1049l");
+    term.cup(24, 0);
+    term.print("\t");
+    term.assert_cursor_pos(32, 0, None);
+}
+
+#[test]
+fn test_resize_requests() {
+    let mut term = TestTerm::new(4, 10, 0);
+    assert_eq!(term.take_resize_request(), None);
+
+    term.print("\x1b[8;20;80t");
+    assert_eq!(
+        term.take_resize_request(),
+        Some(ResizeRequest { rows: 20, cols: 80 })
+    );
+    assert_eq!(term.take_resize_request(), None, "the request is consumed");
+
+    // Omitted dimensions retain their current value
+    term.print("\x1b[8;;40t");
+    assert_eq!(
+        term.take_resize_request(),
+        Some(ResizeRequest { rows: 4, cols: 40 })
+    );
+
+    // Pixel sizes are converted to cells; the cells are 8x16 here
+    term.print("\x1b[4;160;800t");
+    assert_eq!(
+        term.take_resize_request(),
+        Some(ResizeRequest {
+            rows: 10,
+            cols: 100
+        })
+    );
+}
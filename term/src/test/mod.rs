@@ -7,44 +7,64 @@ use bitflags::bitflags;
 mod c1;
 mod csi;
 // mod selection; FIXME: port to render layer
+mod selection_text;
 use crate::color::ColorPalette;
 use pretty_assertions::assert_eq;
-use std::cell::RefCell;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use termwiz::escape::csi::{Edit, EraseInDisplay, EraseInLine};
+use termwiz::escape::osc::Progress;
+use termwiz::escape::parser::Encoding;
 use termwiz::escape::{OneBased, OperatingSystemCommand, CSI};
 use termwiz::surface::{CursorShape, CursorVisibility};
 
 #[derive(Debug)]
 struct LocalClip {
-    clip: RefCell<Option<String>>,
+    clip: Mutex<Option<String>>,
 }
 
 impl LocalClip {
     fn new() -> Self {
         Self {
-            clip: RefCell::new(None),
+            clip: Mutex::new(None),
         }
     }
 }
 
 impl Clipboard for LocalClip {
     fn set_contents(&self, clip: Option<String>) -> anyhow::Result<()> {
-        *self.clip.borrow_mut() = clip;
+        *self.clip.lock().unwrap() = clip;
         Ok(())
     }
 
     fn get_contents(&self) -> anyhow::Result<String> {
         self.clip
-            .borrow()
+            .lock()
+            .unwrap()
             .as_ref()
             .map(|c| c.clone())
             .ok_or_else(|| anyhow::anyhow!("no clipboard"))
     }
 }
 
+/// Collects what the terminal writes to the pty, such as its replies
+/// to queries
+#[derive(Clone, Default)]
+struct HostWriter(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for HostWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 struct TestTerm {
     term: Terminal,
+    host: HostWriter,
 }
 
 #[derive(Debug)]
@@ -68,6 +88,7 @@ impl TestTerm {
             .filter_level(log::LevelFilter::Trace)
             .try_init();
 
+        let host = HostWriter::default();
         let mut term = Terminal::new(
             height,
             width,
@@ -76,12 +97,12 @@ impl TestTerm {
             Arc::new(TestTermConfig { scrollback }),
             "WezTerm",
             "O_o",
-            Box::new(Vec::new()),
+            Box::new(host.clone()),
         );
         let clip: Arc<dyn Clipboard> = Arc::new(LocalClip::new());
         term.set_clipboard(&clip);
 
-        let mut term = Self { term };
+        let mut term = Self { term, host };
 
         term.set_auto_wrap(true);
 
@@ -92,6 +113,13 @@ impl TestTerm {
         self.term.advance_bytes(bytes);
     }
 
+    /// Returns what the terminal has written to the pty since the
+    /// last call
+    fn take_host_output(&mut self) -> String {
+        let output = std::mem::take(&mut *self.host.0.lock().unwrap());
+        String::from_utf8(output).unwrap()
+    }
+
     #[allow(dead_code)]
     fn set_mode(&mut self, mode: &str, enable: bool) {
         self.print(CSI);
@@ -421,6 +449,77 @@ fn cursor_movement_damage() {
     term.assert_dirty_lines(&[0, 1], Some("cursor movement dirties old and new lines"));
 }
 
+#[test]
+fn test_reverse_video() {
+    let mut term = TestTerm::new(2, 3, 0);
+    term.print("a");
+    term.clean_dirty_lines();
+    assert!(!term.reverse_video_enabled());
+
+    term.print("\x1b[?5h");
+    assert!(term.reverse_video_enabled());
+    term.assert_dirty_lines(&[0, 1], Some("reverse video repaints the screen"));
+    // The cells are left untouched
+    assert_visible_contents(&term, file!(), line!(), &["a  ", "   "]);
+    assert!(!term.screen().lines[0].cells()[0].attrs().reverse());
+
+    // Setting it again doesn't repaint
+    term.clean_dirty_lines();
+    term.print("\x1b[?5h");
+    term.assert_dirty_lines(&[], None);
+
+    term.print("\x1b[?5l");
+    assert!(!term.reverse_video_enabled());
+    term.assert_dirty_lines(&[0, 1], None);
+
+    term.print("\x1b[?5h\x1bc");
+    assert!(!term.reverse_video_enabled(), "RIS resets reverse video");
+}
+
+#[test]
+fn test_soft_reset() {
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("\x1b[?1049halt");
+    term.print("\x1b[?25l\x1b[?1000h\x1b[?2004h\x1b[2;3r\x1b[?6h\x1b(0");
+    assert!(term.is_mouse_grabbed());
+    assert!(term.bracketed_paste_enabled());
+
+    term.print("\x1b[!p");
+    assert!(!term.is_mouse_grabbed(), "DECSTR disables mouse reporting");
+    assert!(
+        !term.bracketed_paste_enabled(),
+        "DECSTR disables bracketed paste"
+    );
+    assert_eq!(term.cursor_pos().visibility, CursorVisibility::Visible);
+
+    // Origin mode, margins and the line drawing charset are reset,
+    // and we're still on the alternate screen
+    term.cup(0, 0);
+    term.print("q");
+    assert_visible_contents(&term, file!(), line!(), &["qlt ", "    ", "    "]);
+}
+
+#[test]
+fn test_full_reset() {
+    let mut term = TestTerm::new(2, 4, 3);
+    term.print("1\r\n2\r\n3\r\nab");
+    let num_lines = term.screen().lines.len();
+
+    term.print("\x1b[?1049hcd\x1b[?1000h\x1b[?25l\x1bc");
+    assert!(!term.is_mouse_grabbed());
+    term.assert_cursor_pos(0, 0, Some("RIS homes the cursor"));
+    assert_eq!(term.cursor_pos().visibility, CursorVisibility::Visible);
+    assert_visible_contents(&term, file!(), line!(), &["    ", "    "]);
+    assert_eq!(
+        term.screen().lines.len(),
+        num_lines,
+        "the scrollback is retained by default"
+    );
+
+    term.print("xy");
+    assert_visible_contents(&term, file!(), line!(), &["xy  ", "    "]);
+}
+
 /// Replicates a bug I initially found via:
 /// $ vim
 /// :help
@@ -580,6 +679,188 @@ fn test_resize_wrap() {
     );
 }
 
+/// Test that the cursor stays at the end of a prompt when the
+/// terminal is resized narrower and then wider again.
+#[test]
+fn test_resize_prompt_cursor() {
+    let mut term = TestTerm::new(4, 10, 0);
+    term.print("$ abc");
+    term.assert_cursor_pos(5, 0, None);
+
+    term.resize(4, 4, 0, 0);
+    assert_visible_contents(&term, file!(), line!(), &["$ ab", "c", "    ", "    "]);
+    term.assert_cursor_pos(1, 1, Some("cursor follows the wrapped prompt"));
+
+    term.resize(4, 10, 0, 0);
+    assert_visible_contents(&term, file!(), line!(), &["$ abc"]);
+    term.assert_cursor_pos(5, 0, Some("cursor returns to the end of the prompt"));
+}
+
+/// Test that narrowing the terminal doesn't leave fragments of a right
+/// aligned prompt behind, and that a cursor position saved by the shell
+/// is moved along with the prompt.
+#[test]
+fn test_resize_right_prompt() {
+    let mut term = TestTerm::new(5, 20, 0);
+    term.print("0123456789abcdefghijKL\r\n");
+    // The first line of the prompt has a right aligned segment that
+    // reaches the right margin
+    term.print("\x1b]133;A\x07~/src\x1b[3;16H12:00\r\n");
+    // The shell saves the cursor, draws the right prompt and restores it
+    term.print("$ \x1b7\x1b[4;19Hok\x1b8ls");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &[
+            "0123456789abcdefghij",
+            "KL",
+            "~/src          12:00",
+            "$ ls              ok",
+            "",
+        ],
+    );
+
+    term.resize(5, 10, 0, 0);
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["0123456789", "abcdefghij", "KL", "~/src", "$ ls"],
+    );
+    term.assert_cursor_pos(4, 4, None);
+
+    term.print("\x1b8");
+    term.assert_cursor_pos(2, 4, Some("the saved position moved with the prompt"));
+}
+
+/// Test that a pending wrap at the right margin is preserved as a
+/// position after the last printed character when growing wider.
+#[test]
+fn test_resize_pending_wrap() {
+    let mut term = TestTerm::new(4, 5, 0);
+    term.print("$ abc");
+    term.assert_cursor_pos(4, 0, None);
+
+    term.resize(4, 10, 0, 0);
+    term.assert_cursor_pos(5, 0, Some("cursor moves past the last character"));
+
+    term.print("d");
+    assert_visible_contents(&term, file!(), line!(), &["$ abcd"]);
+    term.assert_cursor_pos(6, 0, None);
+}
+
+#[test]
+fn test_alt_screen_1049() {
+    let mut term = TestTerm::new(3, 5, 10);
+    term.print("abc\r\nde");
+    let num_lines = term.screen().lines.len();
+
+    term.print("\x1b[?1049h");
+    assert_visible_contents(&term, file!(), line!(), &["     ", "     ", "     "]);
+    term.print("vim1\r\nvim2\r\nvim3\r\nvim4");
+
+    // Setting it again while active doesn't replace the saved cursor
+    term.print("\x1b[?1049h");
+    term.cup(4, 2);
+
+    term.print("\x1b[?1049l");
+    assert_visible_contents(&term, file!(), line!(), &["abc  ", "de   ", "     "]);
+    term.assert_cursor_pos(2, 1, Some("the cursor is restored"));
+    assert_eq!(
+        term.screen().lines.len(),
+        num_lines,
+        "nothing from the alternate screen is added to the scrollback"
+    );
+}
+
+#[test]
+fn test_alt_screen_47_1047_1048() {
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("\x1b[?47hx\x1b[?47l");
+    assert_visible_contents(&term, file!(), line!(), &["    ", "    "]);
+
+    // 47 doesn't clear the alternate screen
+    term.print("\x1b[?47h");
+    assert_visible_contents(&term, file!(), line!(), &["x   ", "    "]);
+
+    // but resetting 1047 clears it before switching back
+    term.print("\x1b[?1047l\x1b[?47h");
+    assert_visible_contents(&term, file!(), line!(), &["    ", "    "]);
+    term.print("\x1b[?47l");
+
+    // 1048 saves and restores the cursor without switching screens
+    term.cup(1, 1);
+    term.print("\x1b[?1048h");
+    term.cup(3, 0);
+    term.print("\x1b[?1048l");
+    term.assert_cursor_pos(1, 1, None);
+}
+
+#[test]
+fn test_alt_screen_resize() {
+    let mut term = TestTerm::new(3, 10, 10);
+    term.print("hello");
+    term.print("\x1b[?1049habcdefghijklmnop");
+
+    term.resize(3, 4, 3 * 16, 4 * 8);
+    assert_eq!(
+        term.screen().lines.len(),
+        3,
+        "the alternate screen has no scrollback"
+    );
+
+    // The saved cursor follows the rewrapped primary screen content
+    term.print("\x1b[?1049l");
+    assert_visible_contents(&term, file!(), line!(), &["hell", "o   ", "    "]);
+    term.assert_cursor_pos(1, 1, None);
+}
+
+/// Test that printing in the last column defers the wrap until the
+/// next printable character, and that only an actual wrap marks the
+/// line as continuing on the next row.
+#[test]
+fn test_deferred_wrap() {
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("abcd");
+    term.assert_cursor_pos(3, 0, Some("the wrap is pending"));
+    assert!(!term.screen().visible_lines()[0].last_cell_was_wrapped());
+
+    // CR cancels the pending wrap, so this overwrites the first column
+    term.print("\re");
+    assert_visible_contents(&term, file!(), line!(), &["ebcd", "    ", "    "]);
+
+    term.print("\r\nfghij");
+    assert_visible_contents(&term, file!(), line!(), &["ebcd", "fghi", "j   "]);
+    let lines = term.screen().visible_lines();
+    assert!(!lines[0].last_cell_was_wrapped(), "hard newline");
+    assert!(lines[1].last_cell_was_wrapped(), "soft wrap");
+}
+
+/// Test that a double width character that doesn't fit before the
+/// right margin is moved to the next line rather than being split.
+#[test]
+fn test_wrap_double_width() {
+    let mut term = TestTerm::new(2, 5, 0);
+    term.print("abcd\u{4e2d}");
+    assert_visible_contents(&term, file!(), line!(), &["abcd ", "\u{4e2d}   "]);
+    term.assert_cursor_pos(2, 1, None);
+    assert!(term.screen().visible_lines()[0].last_cell_was_wrapped());
+}
+
+/// Test that content to the right of the cursor is discarded rather
+/// than being left behind as stale wrapped fragments when narrowing.
+#[test]
+fn test_resize_truncates_after_cursor() {
+    let mut term = TestTerm::new(4, 10, 0);
+    term.print("$ hello");
+    term.cup(2, 0);
+
+    term.resize(4, 4, 0, 0);
+    assert_visible_contents(&term, file!(), line!(), &["$ ", "    ", "    ", "    "]);
+    term.assert_cursor_pos(2, 0, None);
+}
+
 #[test]
 fn test_scrollup() {
     let mut term = TestTerm::new(2, 1, 4);
@@ -666,6 +947,244 @@ fn test_emoji_with_modifier() {
     );
 }
 
+#[test]
+fn test_combining_marks() {
+    let mut term = TestTerm::new(2, 4, 0);
+
+    // The combining mark arrives separately from its base character
+    term.print("e");
+    term.print("\u{301}x");
+    term.assert_cursor_pos(2, 0, None);
+
+    // and following a double width character
+    term.print("\r\n\u{4e2d}");
+    term.print("\u{301}");
+    term.assert_cursor_pos(2, 1, None);
+
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["e\u{301}x  ", "\u{4e2d}\u{301}  "],
+    );
+}
+
+#[test]
+fn test_split_emoji_presentation() {
+    let mut term = TestTerm::new(1, 4, 0);
+
+    // VS16 arrives separately from the heart, which it widens to
+    // a double width emoji
+    term.print("\u{2764}");
+    term.assert_cursor_pos(1, 0, None);
+    term.print("\u{fe0f}");
+    term.assert_cursor_pos(2, 0, None);
+    term.print("x");
+
+    assert_visible_contents(&term, file!(), line!(), &["\u{2764}\u{fe0f}x "]);
+}
+
+#[test]
+fn test_progress() {
+    let mut term = TestTerm::new(2, 4, 0);
+    assert_eq!(term.get_progress(), Progress::None);
+
+    term.print("\x1b]9;4;1;25\x1b\\");
+    assert_eq!(term.get_progress(), Progress::SetPercentage(25));
+
+    term.print("\x1b]9;4;2\x07");
+    assert_eq!(term.get_progress(), Progress::SetError(0));
+
+    term.print("\x1b]9;4;0\x1b\\");
+    assert_eq!(term.get_progress(), Progress::None);
+
+    // Nothing is printed to the screen
+    assert_visible_contents(&term, file!(), line!(), &["    ", "    "]);
+}
+
+#[test]
+fn test_status_text() {
+    let mut term = TestTerm::new(2, 4, 0);
+    assert_eq!(term.get_status_text(), None);
+
+    term.print("\x1b]7777;12:30 | 80%\x1b\\");
+    assert_eq!(term.get_status_text(), Some("12:30 | 80%"));
+
+    term.print("\x1b]7777;a;b\x07");
+    assert_eq!(term.get_status_text(), Some("a;b"));
+
+    term.print("\x1b]7777;\x1b\\");
+    assert_eq!(term.get_status_text(), None);
+
+    assert_visible_contents(&term, file!(), line!(), &["    ", "    "]);
+}
+
+#[test]
+fn test_unicode_version() {
+    let mut term = TestTerm::new(2, 8, 0);
+    assert_eq!(term.get_unicode_version(), UnicodeVersion::LATEST);
+
+    // Emoji are double width from Unicode 9 onwards
+    term.print("\u{1f600}");
+    term.assert_cursor_pos(2, 0, None);
+
+    term.print("\x1b]1337;UnicodeVersion=push outer\x07");
+    term.print("\x1b]1337;UnicodeVersion=8\x07");
+    assert_eq!(term.get_unicode_version(), UnicodeVersion(8));
+    term.print("\u{1f600}");
+    term.assert_cursor_pos(3, 0, Some("single width in Unicode 8"));
+
+    term.print("\x1b]1337;UnicodeVersion=push\x07");
+    term.print("\x1b]1337;UnicodeVersion=12\x07");
+    term.print("\x1b]1337;UnicodeVersion=pop outer\x07");
+    assert_eq!(term.get_unicode_version(), UnicodeVersion::LATEST);
+
+    // Popping an empty stack has no effect
+    term.print("\x1b]1337;UnicodeVersion=pop\x07");
+    assert_eq!(term.get_unicode_version(), UnicodeVersion::LATEST);
+}
+
+#[test]
+fn test_notifications() {
+    let mut term = TestTerm::new(2, 4, 0);
+
+    term.print("\x1b]9;hello\x07");
+    term.print("\x1b]777;notify;Build;done; 3 warnings\x1b\\");
+    assert_eq!(
+        term.take_notifications(),
+        vec![
+            ToastNotification {
+                title: None,
+                body: "hello".to_string(),
+            },
+            ToastNotification {
+                title: Some("Build".to_string()),
+                body: "done; 3 warnings".to_string(),
+            },
+        ]
+    );
+    assert_eq!(term.take_notifications(), vec![]);
+
+    // Progress reports are not notifications
+    term.print("\x1b]9;4;1;50\x07");
+    assert_eq!(term.take_notifications(), vec![]);
+}
+
+#[test]
+fn test_bell() {
+    let mut term = TestTerm::new(2, 4, 0);
+    assert!(!term.take_bell());
+
+    term.print("a\x07\x07b\x07");
+    assert!(term.take_bell());
+    assert!(!term.take_bell());
+    assert_visible_contents(&term, file!(), line!(), &["ab  ", "    "]);
+
+    // The BEL that terminates an OSC doesn't ring the bell
+    term.print("\x1b]2;title\x07");
+    assert!(!term.take_bell());
+}
+
+#[test]
+fn test_decrqm() {
+    let mut term = TestTerm::new(2, 4, 0);
+
+    term.print("\x1b[?2004$p");
+    assert_eq!(term.take_host_output(), "\x1b[?2004;2$y");
+    term.set_mode("?2004", true);
+    term.print("\x1b[?2004$p");
+    assert_eq!(term.take_host_output(), "\x1b[?2004;1$y");
+
+    // Modes whose state isn't just a flag are reported too
+    term.print("\x1b[?1049h\x1b[?1049$p");
+    assert_eq!(term.take_host_output(), "\x1b[?1049;1$y");
+    // Accepted but without effect
+    term.print("\x1b[?3$p");
+    assert_eq!(term.take_host_output(), "\x1b[?3;4$y");
+    // Not recognized at all
+    term.print("\x1b[?2026$p");
+    assert_eq!(term.take_host_output(), "\x1b[?2026;0$y");
+
+    term.print("\x1b[4$p\x1b[4h\x1b[4$p");
+    assert_eq!(term.take_host_output(), "\x1b[4;2$y\x1b[4;1$y");
+}
+
+#[test]
+fn test_user_vars() {
+    let mut term = TestTerm::new(2, 4, 0);
+    assert!(term.take_user_vars().is_empty());
+
+    // The values are base64 encoded: "18", "Iosevka" and then "20"
+    term.print("\x1b]1337;SetUserVar=WEZTERM_FONT_SIZE=MTg=\x07");
+    term.print("\x1b]1337;SetUserVar=WEZTERM_FONT=SW9zZXZrYQ==\x07");
+    term.print("\x1b]1337;SetUserVar=WEZTERM_FONT_SIZE=MjA=\x07");
+    assert_eq!(
+        term.take_user_vars(),
+        vec![
+            ("WEZTERM_FONT".to_string(), "Iosevka".to_string()),
+            ("WEZTERM_FONT_SIZE".to_string(), "20".to_string()),
+        ]
+    );
+    assert!(term.take_user_vars().is_empty());
+}
+
+#[test]
+fn test_tab_info() {
+    let mut term = TestTerm::new(2, 4, 0);
+    assert!(!term.take_tab_info_request());
+    term.print("\x1b]1337;ReportTabInfo\x07");
+    assert!(term.take_tab_info_request());
+    assert!(!term.take_tab_info_request());
+
+    term.report_tab_info(3, 1);
+    assert_eq!(term.take_host_output(), "\x1b]1337;ReportTabInfo=3;1\x1b\\");
+}
+
+#[test]
+fn test_title_stack() {
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("\x1b]2;shell\x07");
+    term.print("\x1b[22;0t\x1b]2;VIM\x07");
+    assert_eq!(term.get_title(), "VIM");
+    term.print("\x1b[23;0t");
+    assert_eq!(term.get_title(), "shell");
+
+    // Popping an empty stack leaves the title alone
+    term.print("\x1b[23;0t");
+    assert_eq!(term.get_title(), "shell");
+
+    // Popping only the icon title leaves the window title alone
+    term.print("\x1b[22;0t\x1b]0;VIM\x07\x1b[23;1t");
+    assert_eq!(term.get_title(), "VIM");
+
+    // Pushing beyond the limit discards the oldest entries
+    for n in 0..12 {
+        term.print(format!("\x1b]2;{}\x07\x1b[22;2t", n));
+    }
+    for _ in 0..12 {
+        term.print("\x1b[23;2t");
+    }
+    assert_eq!(term.get_title(), "2");
+}
+
+#[test]
+fn test_semantic_prompt() {
+    let mut term = TestTerm::new(4, 4, 0);
+
+    // The prompt starts on a fresh line
+    term.print("\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07a");
+    term.print("\x1b]133;D;0\x07\x1b]133;A\x07$ ");
+    assert_visible_contents(&term, file!(), line!(), &["$ ls", "a   ", "$   ", "    "]);
+
+    let prompts: Vec<bool> = term
+        .screen()
+        .visible_lines()
+        .iter()
+        .map(|line| line.is_prompt())
+        .collect();
+    assert_eq!(prompts, vec![true, false, true, false]);
+}
+
 #[test]
 fn test_hyperlinks() {
     let mut term = TestTerm::new(3, 5, 0);
@@ -749,3 +1268,19 @@ fn test_hyperlinks() {
         Compare::TEXT | Compare::ATTRS,
     );
 }
+
+#[test]
+fn test_cp437() {
+    let mut term = TestTerm::new(1, 4, 0);
+    term.set_encoding(Encoding::Cp437);
+    term.print(b"\xc9\xcd\xbb");
+    assert_visible_contents(&term, file!(), line!(), &["\u{2554}\u{2550}\u{2557} "]);
+
+    // Typing and pasting are encoded in the same way, and characters
+    // that CP437 lacks are replaced
+    term.key_down(KeyCode::Char('\u{e9}'), KeyModifiers::NONE)
+        .unwrap();
+    term.send_paste("\u{bd}\u{20ac}").unwrap();
+    term.send_text("\u{fc}").unwrap();
+    assert_eq!(*term.host.0.lock().unwrap(), b"\x82\xab?\x81");
+}
@@ -0,0 +1,241 @@
+//! Tests for extracting the text of a selection
+use super::*;
+use crate::selection::{selection_text, SelectionTextOptions};
+use std::ops::Range;
+
+/// Returns the text selected from the visible rows in `rows`,
+/// using `cols` to compute the selected columns for each row
+fn select_with_options<F: Fn(usize) -> Range<usize>>(
+    term: &TestTerm,
+    rows: Range<usize>,
+    cols: F,
+    rectangular: bool,
+    options: &SelectionTextOptions,
+) -> String {
+    let lines = term.screen().visible_lines();
+    selection_text(
+        lines[rows.clone()]
+            .iter()
+            .zip(rows)
+            .map(|(line, row)| (line, cols(row))),
+        rectangular,
+        options,
+    )
+}
+
+fn select<F: Fn(usize) -> Range<usize>>(
+    term: &TestTerm,
+    rows: Range<usize>,
+    cols: F,
+    rectangular: bool,
+) -> String {
+    select_with_options(
+        term,
+        rows,
+        cols,
+        rectangular,
+        &SelectionTextOptions::default(),
+    )
+}
+
+#[test]
+fn test_selection_snaps_to_double_width() {
+    let mut term = TestTerm::new(2, 8, 0);
+    term.print("a\u{4e2d}\u{6587}b\r\n\u{1F480}skull");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["a\u{4e2d}\u{6587}b  ", "\u{1F480}skull "],
+    );
+
+    // Starting in the second half of the first character and ending
+    // in the first half of the second includes both of them
+    assert_eq!(select(&term, 0..1, |_| 2..4, false), "\u{4e2d}\u{6587}");
+
+    // Starting in the middle of the emoji includes it
+    assert_eq!(select(&term, 1..2, |_| 1..6, false), "\u{1F480}skul");
+    assert_eq!(select(&term, 1..2, |_| 1..2, false), "\u{1F480}");
+}
+
+#[test]
+fn test_selection_trims_trailing_spaces() {
+    let mut term = TestTerm::new(3, 8, 0);
+    term.print("foo   \r\n\u{4e2d}  \r\nbar");
+
+    assert_eq!(
+        select(&term, 0..3, |_| 0..usize::max_value(), false),
+        "foo\n\u{4e2d}\nbar"
+    );
+    assert_eq!(select(&term, 0..1, |_| 2..6, false), "o");
+}
+
+#[test]
+fn test_selection_across_soft_wrap() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("hello     world\r\nnext");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["hello     ", "world     ", "next      "],
+    );
+
+    // The soft wrap doesn't introduce a newline, and the spaces
+    // before the wrap are part of the logical line
+    assert_eq!(
+        select(
+            &term,
+            0..3,
+            |row| match row {
+                2 => 0..2,
+                _ => 0..usize::max_value(),
+            },
+            false
+        ),
+        "hello     world\nne"
+    );
+
+    // A selection that ends on the wrapped row is trimmed as usual
+    assert_eq!(
+        select(&term, 0..1, |_| 0..usize::max_value(), false),
+        "hello"
+    );
+}
+
+#[test]
+fn test_selection_full_line_hard_newline() {
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("abcd\r\nefgh\r\nij");
+
+    // Lines that exactly fill the width but end with an explicit
+    // newline are not joined
+    assert_eq!(
+        select(&term, 0..3, |_| 0..usize::max_value(), false),
+        "abcd\nefgh\nij"
+    );
+}
+
+#[test]
+fn test_selection_rectangular() {
+    let mut term = TestTerm::new(4, 8, 0);
+    term.print("abcdef\r\n\u{4e2d}\u{6587}xyz\r\n\u{1F480}\r\nab");
+
+    assert_eq!(
+        select(&term, 0..4, |_| 1..4, true),
+        "bcd\n\u{4e2d}\u{6587}\n\u{1F480}  \nb  "
+    );
+
+    // Rows that are soft-wrapped still produce one line per row
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("abcdefgh");
+    assert_eq!(select(&term, 0..2, |_| 0..2, true), "ab\nef");
+}
+
+#[test]
+fn test_selection_preserves_tabs() {
+    let mut term = TestTerm::new(3, 12, 0);
+    term.print("a\tb\r\nabcdefghij\r\tX");
+    assert_visible_contents(&term, file!(), line!(), &["a       b   ", "abcdefghXj  "]);
+
+    assert_eq!(
+        select(&term, 0..2, |_| 0..usize::max_value(), false),
+        "a\tb\nabcdefghXj"
+    );
+
+    // Selecting part of the tab still yields the tab
+    assert_eq!(select(&term, 0..1, |_| 1..9, false), "\tb");
+
+    let options = SelectionTextOptions {
+        preserve_tabs: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        select_with_options(&term, 0..1, |_| 0..usize::max_value(), false, &options),
+        "a       b"
+    );
+
+    // Rectangular selections keep the spaces so that columns line up
+    assert_eq!(select(&term, 0..2, |_| 0..4, true), "a   \nabcd");
+}
+
+#[test]
+fn test_selection_without_trimming() {
+    let mut term = TestTerm::new(4, 10, 0);
+    // Print some spaces explicitly, and erase the tail of the second line
+    term.print("foo   \r\nbarbaz\r\x1b[3C\x1b[K\r\nhello     world");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["foo       ", "bar       ", "hello     ", "world     "],
+    );
+
+    let options = SelectionTextOptions {
+        trim_trailing_whitespace: false,
+        ..Default::default()
+    };
+    // The spaces written by the application are kept, but neither the
+    // padding nor the erased cells are included, and nothing is removed
+    // at the soft wrap
+    assert_eq!(
+        select_with_options(&term, 0..4, |_| 0..usize::max_value(), false, &options),
+        "foo   \nbar\nhello     world"
+    );
+}
+
+#[test]
+fn test_selection_emoji_sequences() {
+    let technologist = "\u{1F469}\u{1F3FD}\u{200D}\u{1F4BB}";
+    let flag = "\u{1F1EF}\u{1F1F5}";
+    let heart = "\u{2764}\u{FE0F}";
+
+    let mut term = TestTerm::new(1, 8, 0);
+    term.print(format!("{}{}{}x", technologist, flag, heart));
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &[&format!("{}{}{}x ", technologist, flag, heart)],
+    );
+
+    // Each sequence occupies a single double width cell
+    {
+        let lines = term.screen().visible_lines();
+        let cells = lines[0].cells();
+        assert_eq!(cells[0].str(), technologist);
+        assert_eq!(cells[2].str(), flag);
+        assert_eq!(cells[4].str(), heart);
+        assert_eq!(cells[6].str(), "x");
+    }
+
+    // Copying yields the original sequences
+    assert_eq!(
+        select(&term, 0..1, |_| 0..8, false),
+        format!("{}{}{}x", technologist, flag, heart)
+    );
+    assert_eq!(select(&term, 0..1, |_| 3..4, false), flag);
+}
+
+#[test]
+fn test_selection_with_escapes() {
+    let mut term = TestTerm::new(2, 10, 0);
+    term.print("a\x1b[1;31mbold\x1b[0m c\r\n\x1b[38;2;1;2;3;4mx");
+
+    let options = SelectionTextOptions {
+        escapes: true,
+        ..Default::default()
+    };
+    // The rendition is only emitted when it changes, and the
+    // text ends in the default rendition
+    assert_eq!(
+        select_with_options(&term, 0..2, |_| 0..usize::max_value(), false, &options),
+        "a\x1b[0;1;31mbold\x1b[0m c\n\x1b[0;4;38;2;1;2;3mx\x1b[0m"
+    );
+
+    // Without escapes, only the text is returned
+    assert_eq!(
+        select(&term, 0..2, |_| 0..usize::max_value(), false),
+        "abold c\nx"
+    );
+}
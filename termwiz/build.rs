@@ -0,0 +1,43 @@
+use std::fmt::Write;
+use std::path::PathBuf;
+
+/// Turns data/wide-since.txt, which is generated from the Unicode
+/// Character Database by data/gen-wide-since.pl, into the table that
+/// grapheme_column_width_for_version searches
+fn generate_wide_since_table() {
+    let source = "data/wide-since.txt";
+    println!("cargo:rerun-if-changed={}", source);
+    let data = std::fs::read_to_string(source).unwrap();
+
+    let mut table = String::new();
+    table.push_str("/// Ranges of wide codepoints and the major Unicode version\n");
+    table.push_str("/// from which they are wide, sorted by codepoint\n");
+    table.push_str("static WIDE_SINCE: &[(u32, u32, u8)] = &[\n");
+    for line in data.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line
+            .split(|c| c == ';' || c == '.')
+            .filter(|s| !s.is_empty());
+        let mut next = || {
+            fields
+                .next()
+                .unwrap_or_else(|| panic!("bad line {:?}", line))
+        };
+        let first = u32::from_str_radix(next(), 16).unwrap();
+        let last = u32::from_str_radix(next(), 16).unwrap();
+        let since: u8 = next().parse().unwrap();
+        writeln!(table, "    (0x{:X}, 0x{:X}, {}),", first, last, since).unwrap();
+    }
+    table.push_str("];\n");
+
+    let dest = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("wide_since.rs");
+    std::fs::write(dest, table).unwrap();
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    generate_wide_since_table();
+}
@@ -0,0 +1,85 @@
+#!/usr/bin/env perl
+# Generates wide-since.txt from the Unicode Character Database that
+# ships with perl:
+#
+#   perl gen-wide-since.pl > wide-since.txt
+#
+# Each line is a range of codepoints that are wide (East_Asian_Width
+# W or F) along with the major Unicode version from which they are
+# wide: the version in which they were assigned, or 9 for the emoji
+# that became wide when Unicode 9 gave Emoji_Presentation characters
+# a wide East_Asian_Width.  The build script turns the ranges into the
+# tables used by grapheme_column_width_for_version.
+use strict;
+use warnings;
+use Unicode::UCD qw(prop_invmap prop_invlist);
+
+# Returns the value of an inversion map for $cp
+sub lookup {
+    my ($list, $map, $cp) = @_;
+    my ($lo, $hi) = (0, scalar(@$list) - 1);
+    while ($lo < $hi) {
+        my $mid = int(($lo + $hi + 1) / 2);
+        if ($list->[$mid] <= $cp) {
+            $lo = $mid;
+        } else {
+            $hi = $mid - 1;
+        }
+    }
+    return $map->[$lo];
+}
+
+sub in_invlist {
+    my ($list, $cp) = @_;
+    my $idx = -1;
+    for my $i (0 .. $#$list) {
+        last if $list->[$i] > $cp;
+        $idx = $i;
+    }
+    # Even indices start ranges that are in the list
+    return $idx >= 0 && $idx % 2 == 0;
+}
+
+my ($eaw_list, $eaw_map) = prop_invmap("East_Asian_Width");
+my ($age_list, $age_map) = prop_invmap("Age");
+my @emoji_presentation = prop_invlist("Emoji_Presentation");
+
+print "# Generated by gen-wide-since.pl from the Unicode Character Database "
+  . Unicode::UCD::UnicodeVersion() . "\n";
+print "# <first>..<last>;<major Unicode version from which they are wide>\n";
+
+my ($start, $prev, $prev_since);
+sub flush {
+    if (defined $start) {
+        printf("%04X..%04X;%d\n", $start, $prev, $prev_since);
+    }
+    $start = undef;
+}
+
+for my $cp (0 .. 0x10FFFF) {
+    my $eaw = lookup($eaw_list, $eaw_map, $cp);
+    if ($eaw ne 'W' && $eaw ne 'F') {
+        flush();
+        next;
+    }
+
+    my $age = lookup($age_list, $age_map, $cp);
+    # Unassigned codepoints in the CJK blocks and planes default to
+    # wide in every version
+    my $since = $age eq 'Unassigned' ? 1 : int($age);
+    # The Enclosed Ideographic Supplement was already wide
+    if ($since < 9
+        && ($cp < 0x1F200 || $cp > 0x1F2FF)
+        && in_invlist(\@emoji_presentation, $cp))
+    {
+        $since = 9;
+    }
+
+    if (defined $start && $prev == $cp - 1 && $prev_since == $since) {
+        $prev = $cp;
+    } else {
+        flush();
+        ($start, $prev, $prev_since) = ($cp, $cp, $since);
+    }
+}
+flush();
@@ -1,14 +1,15 @@
 //! Model a cell in the terminal display
 use crate::color::ColorAttribute;
 pub use crate::escape::osc::Hyperlink;
-use crate::image::ImageCell;
+use crate::image::{ImageCell, ImageData};
 #[cfg(feature = "use_serde")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use smallvec::SmallVec;
 use std;
+use std::hash::{Hash, Hasher};
 use std::mem;
 use std::sync::Arc;
-use unicode_width::UnicodeWidthStr;
+use unicode_width::UnicodeWidthChar;
 
 /// Holds the attributes for a cell.
 /// Most style attributes are stored internally as part of a bitfield
@@ -16,7 +17,7 @@ use unicode_width::UnicodeWidthStr;
 /// The setter methods return a mutable self reference so that they can
 /// be chained together.
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Eq)]
 pub struct CellAttributes {
     attributes: u16,
     /// The foreground color
@@ -29,6 +30,10 @@ pub struct CellAttributes {
     pub image: Option<Box<ImageCell>>,
 }
 
+/// The bits of the attributes bitfield that describe how a cell
+/// was produced; see `CellAttributes::attribute_bits_equal`.
+const CONTENT_BITS: u16 = (1 << 12) | (1 << 13);
+
 /// Define getter and setter for the attributes bitfield.
 /// The first form is for a simple boolean value stored in
 /// a single bit.  The $bitnum parameter specifies which bit.
@@ -159,12 +164,41 @@ impl CellAttributes {
     bitfield!(invisible, set_invisible, 9);
     bitfield!(wrapped, set_wrapped, 10);
     bitfield!(overline, set_overline, 11);
+    // Set on cells that were explicitly printed, as opposed to the
+    // blank cells that pad out the rest of a line
+    bitfield!(written, set_written, 12);
+    // Set on the first of a run of blank cells that the cursor was
+    // moved over by a horizontal tab
+    bitfield!(tab, set_tab, 13);
 
     /// Returns true if the attribute bits in both objects are equal.
     /// This can be used to cheaply test whether the styles of the two
     /// cells are the same, and is used by some `Renderer` implementations.
+    /// The `written` and `tab` bits record how the cell was produced
+    /// rather than how it looks, so they are not considered.
     pub fn attribute_bits_equal(&self, other: &Self) -> bool {
-        self.attributes == other.attributes
+        (self.attributes & !CONTENT_BITS) == (other.attributes & !CONTENT_BITS)
+    }
+
+    /// Feeds the attributes that affect rendering into `hasher`.
+    /// Hyperlinks and images are hashed by identity rather than by
+    /// value; that is cheaper, and a renderer caching data derived
+    /// from the attributes needs to notice when a cell refers to a
+    /// different hyperlink, even if it has the same URI.
+    /// This is why this isn't an implementation of `Hash`.
+    pub fn hash_identity<H: Hasher>(&self, hasher: &mut H) {
+        (self.attributes & !CONTENT_BITS).hash(hasher);
+        self.foreground.hash(hasher);
+        self.background.hash(hasher);
+        self.hyperlink
+            .as_ref()
+            .map(|link| &**link as *const Hyperlink as usize)
+            .hash(hasher);
+        if let Some(image) = self.image.as_ref() {
+            (&**image.image_data() as *const ImageData as usize).hash(hasher);
+            image.top_left().hash(hasher);
+            image.bottom_right().hash(hasher);
+        }
     }
 
     /// Set the foreground color for the cell to that specified
@@ -201,6 +235,16 @@ impl CellAttributes {
     }
 }
 
+impl PartialEq for CellAttributes {
+    fn eq(&self, other: &Self) -> bool {
+        self.attribute_bits_equal(other)
+            && self.foreground == other.foreground
+            && self.background == other.background
+            && self.hyperlink == other.hyperlink
+            && self.image == other.image
+    }
+}
+
 #[cfg(feature = "use_serde")]
 fn deserialize_smallvec<'de, D>(deserializer: D) -> Result<SmallVec<[u8; 4]>, D::Error>
 where
@@ -233,6 +277,9 @@ pub struct Cell {
         )
     )]
     text: SmallVec<[u8; 4]>,
+    /// The number of cells occupied by the text, which depends on the
+    /// Unicode version that was in effect when it was assigned
+    width: u8,
     attrs: CellAttributes,
 }
 
@@ -288,8 +335,22 @@ impl Cell {
         text.encode_utf8(&mut storage);
         Self::nerf_control_char(&mut storage);
 
+        Self::with_storage(storage, UnicodeVersion::LATEST, attrs)
+    }
+
+    fn with_storage(
+        text: SmallVec<[u8; 4]>,
+        version: UnicodeVersion,
+        attrs: CellAttributes,
+    ) -> Self {
+        // unsafety: the storage was copied from a str
+        let width = grapheme_column_width_for_version(
+            unsafe { std::str::from_utf8_unchecked(&text) },
+            version,
+        );
         Self {
-            text: storage,
+            text,
+            width: width.min(u8::max_value() as usize) as u8,
             attrs,
         }
     }
@@ -301,14 +362,40 @@ impl Cell {
     /// over.  This function technically allows for an arbitrary string to
     /// be passed but it should not be used to hold strings other than
     /// graphemes.
+    /// Graphemes with more than `MAX_CELL_CODEPOINTS` codepoints are
+    /// truncated.
     pub fn new_grapheme(text: &str, attrs: CellAttributes) -> Self {
-        let mut storage = SmallVec::from_slice(text.as_bytes());
+        Self::new_grapheme_for_version(text, UnicodeVersion::LATEST, attrs)
+    }
+
+    /// Like `new_grapheme`, but the width of the grapheme is that
+    /// given to it by the specified Unicode version
+    pub fn new_grapheme_for_version(
+        text: &str,
+        version: UnicodeVersion,
+        attrs: CellAttributes,
+    ) -> Self {
+        let mut storage = SmallVec::from_slice(clamp_codepoints(text).as_bytes());
         Self::nerf_control_char(&mut storage);
 
-        Self {
-            text: storage,
-            attrs,
-        }
+        Self::with_storage(storage, version, attrs)
+    }
+
+    /// Appends text to the grapheme held by this cell.  This is used to
+    /// attach combining marks that arrive separately from their base
+    /// character.  The resulting grapheme is truncated if it has more
+    /// than `MAX_CELL_CODEPOINTS` codepoints.
+    pub fn append_grapheme(&mut self, text: &str) {
+        self.append_grapheme_for_version(text, UnicodeVersion::LATEST)
+    }
+
+    /// Like `append_grapheme`, but the width of the resulting grapheme
+    /// is that given to it by the specified Unicode version
+    pub fn append_grapheme_for_version(&mut self, text: &str, version: UnicodeVersion) {
+        let mut combined = self.str().to_string();
+        combined.push_str(text);
+        let text = SmallVec::from_slice(clamp_codepoints(&combined).as_bytes());
+        *self = Self::with_storage(text, version, self.attrs.clone());
     }
 
     /// Returns the textual content of the cell
@@ -320,7 +407,7 @@ impl Cell {
 
     /// Returns the number of cells visually occupied by this grapheme
     pub fn width(&self) -> usize {
-        grapheme_column_width(self.str())
+        self.width as usize
     }
 
     /// Returns the attributes of the cell
@@ -333,29 +420,140 @@ impl Cell {
     }
 }
 
+/// The maximum number of codepoints retained in a single cell.
+/// This is generous enough for the longest emoji sequences, while
+/// preventing pathological input, such as "zalgo" text with dozens
+/// of stacked combining marks, from growing cells without bound.
+pub const MAX_CELL_CODEPOINTS: usize = 16;
+
+/// Returns the longest prefix of `text` that has at most
+/// `MAX_CELL_CODEPOINTS` codepoints
+fn clamp_codepoints(text: &str) -> &str {
+    match text.char_indices().nth(MAX_CELL_CODEPOINTS) {
+        Some((idx, _)) => &text[..idx],
+        None => text,
+    }
+}
+
 /// Returns the number of cells visually occupied by a sequence
 /// of graphemes
 pub fn unicode_column_width(s: &str) -> usize {
+    unicode_column_width_for_version(s, UnicodeVersion::LATEST)
+}
+
+/// Like `unicode_column_width`, but using the widths of the
+/// specified Unicode version
+pub fn unicode_column_width_for_version(s: &str, version: UnicodeVersion) -> usize {
     use unicode_segmentation::UnicodeSegmentation;
-    s.graphemes(true).map(grapheme_column_width).sum()
+    s.graphemes(true)
+        .map(|g| grapheme_column_width_for_version(g, version))
+        .sum()
+}
+
+/// The major version of the Unicode standard whose character widths
+/// are used.  Characters are sometimes wide in one version and narrow
+/// in another; most notably, Unicode 9 made emoji wide.  Matching the
+/// version that is used by the `wcwidth` of the application avoids
+/// misaligned output.
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnicodeVersion(pub u8);
+
+impl UnicodeVersion {
+    /// The version of the Unicode Character Database from which the
+    /// width tables were generated
+    pub const LATEST: UnicodeVersion = UnicodeVersion(14);
+}
+
+impl Default for UnicodeVersion {
+    fn default() -> Self {
+        Self::LATEST
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/wide_since.rs"));
+
+/// Returns true if `c` is wide in the specified Unicode version
+fn is_wide(c: char, version: UnicodeVersion) -> bool {
+    let c = c as u32;
+    match WIDE_SINCE.binary_search_by(|&(first, last, _)| {
+        if last < c {
+            std::cmp::Ordering::Less
+        } else if first > c {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }) {
+        Ok(idx) => WIDE_SINCE[idx].2 <= version.0,
+        Err(_) => false,
+    }
 }
 
 /// Returns the number of cells visually occupied by a grapheme.
 /// The input string must be a single grapheme.
 pub fn grapheme_column_width(s: &str) -> usize {
+    grapheme_column_width_for_version(s, UnicodeVersion::LATEST)
+}
+
+/// Like `grapheme_column_width`, but using the widths of the
+/// specified Unicode version
+pub fn grapheme_column_width_for_version(s: &str, version: UnicodeVersion) -> usize {
+    if version >= UnicodeVersion(9) {
+        if let Some(width) = emoji_sequence_width(s) {
+            return width;
+        }
+    }
+
+    // The unicode-width crate tells us which characters are zero width;
+    // the others are wide if the table for the version says so
+    s.chars()
+        .map(|c| match UnicodeWidthChar::width(c) {
+            None | Some(0) => 0,
+            Some(_) if is_wide(c, version) => 2,
+            Some(_) => 1,
+        })
+        .sum()
+}
+
+/// Returns the width of a grapheme that is presented as a single emoji
+/// in Unicode 9 and later, or None if it isn't one of those
+fn emoji_sequence_width(s: &str) -> Option<usize> {
     // Due to this issue:
     // https://github.com/unicode-rs/unicode-width/issues/4
     // we cannot simply use the unicode-width crate to compute
     // the desired value.
     // Let's check for emoji-ness for ourselves first
     use xi_unicode::EmojiExt;
-    for c in s.chars() {
+    let mut regional_indicators = 0;
+    let mut has_zwj = false;
+    for (idx, c) in s.chars().enumerate() {
         if c.is_emoji_modifier_base() || c.is_emoji_modifier() {
             // treat modifier sequences as double wide
-            return 2;
+            return Some(2);
         }
+        if c == '\u{fe0f}' && idx > 0 {
+            // VS16 requests emoji presentation of the preceding character;
+            // a lone VS16 has no character to present and is zero width
+            return Some(2);
+        }
+        if c.is_regional_indicator_symbol() {
+            regional_indicators += 1;
+        }
+        if c == '\u{200d}' {
+            has_zwj = true;
+        }
+    }
+    if regional_indicators == 2 {
+        // A pair of regional indicators is a flag
+        return Some(2);
     }
-    UnicodeWidthStr::width(s)
+    if has_zwj && s.chars().next().map(|c| c.is_emoji()).unwrap_or(false) {
+        // An emoji ZWJ sequence is presented as a single emoji; this is
+        // restricted to emoji because other scripts also make use of ZWJ
+        return Some(2);
+    }
+    None
 }
 
 /// Models a change in the attributes of a cell in a stream of changes.
@@ -426,4 +624,96 @@ mod test {
             women_holding_hands_dark_skin_tone_medium_light_skin_tone
         );
     }
+
+    #[test]
+    fn combining_marks() {
+        let mut cell = Cell::new('e', CellAttributes::default());
+        cell.append_grapheme("\u{301}");
+        assert_eq!(cell.str(), "e\u{301}");
+        assert_eq!(cell.width(), 1);
+
+        // Stacked marks are clamped
+        let zalgo: String = std::iter::once('a')
+            .chain(std::iter::repeat('\u{300}').take(50))
+            .collect();
+        let cell = Cell::new_grapheme(&zalgo, CellAttributes::default());
+        assert_eq!(cell.str().chars().count(), MAX_CELL_CODEPOINTS);
+        assert!(cell.str().starts_with('a'));
+
+        let mut cell = Cell::new('a', CellAttributes::default());
+        for _ in 0..50 {
+            cell.append_grapheme("\u{300}");
+        }
+        assert_eq!(cell.str().chars().count(), MAX_CELL_CODEPOINTS);
+    }
+
+    #[test]
+    fn emoji_sequence_width() {
+        // Each of these is a single grapheme that is presented as
+        // a single double width emoji
+        for g in &[
+            // woman technologist: medium skin tone
+            "\u{1F469}\u{1F3FD}\u{200D}\u{1F4BB}",
+            // woman technologist
+            "\u{1F469}\u{200D}\u{1F4BB}",
+            // rainbow flag, which includes a variation selector
+            "\u{1F3F3}\u{FE0F}\u{200D}\u{1F308}",
+            // red heart, with emoji presentation
+            "\u{2764}\u{FE0F}",
+            // flag: Japan
+            "\u{1F1EF}\u{1F1F5}",
+        ] {
+            use unicode_segmentation::UnicodeSegmentation;
+            assert_eq!(g.graphemes(true).count(), 1, "{:?} is one grapheme", g);
+            assert_eq!(grapheme_column_width(g), 2, "width of {:?}", g);
+
+            let cell = Cell::new_grapheme(g, CellAttributes::default());
+            assert_eq!(cell.str(), *g);
+            assert_eq!(cell.width(), 2);
+        }
+
+        // Text presentation and non-emoji uses of ZWJ are unaffected
+        assert_eq!(grapheme_column_width("\u{2764}"), 1);
+        assert_eq!(grapheme_column_width("\u{2764}\u{FE0E}"), 1);
+        assert_eq!(grapheme_column_width("\u{915}\u{94D}\u{200D}"), 1);
+        // A lone regional indicator is not a flag
+        assert_eq!(grapheme_column_width("\u{1F1EF}"), 1);
+        // A lone variation selector, such as one that arrives separately
+        // from its base character, has no width of its own
+        assert_eq!(grapheme_column_width("\u{FE0F}"), 0);
+        assert_eq!(grapheme_column_width("\u{FE0E}"), 0);
+    }
+
+    #[test]
+    fn unicode_version_widths() {
+        let width = grapheme_column_width_for_version;
+        let v8 = UnicodeVersion(8);
+        let v9 = UnicodeVersion(9);
+        let v12 = UnicodeVersion(12);
+
+        // WATCH and GRINNING FACE became wide in Unicode 9
+        assert_eq!(width("\u{231A}", v8), 1);
+        assert_eq!(width("\u{231A}", v9), 2);
+        assert_eq!(width("\u{1F600}", v8), 1);
+        assert_eq!(width("\u{1F600}", v9), 2);
+        // ELEVATOR was added in Unicode 13
+        assert_eq!(width("\u{1F6D7}", v12), 1);
+        assert_eq!(width("\u{1F6D7}", UnicodeVersion::LATEST), 2);
+        assert_eq!(grapheme_column_width("\u{1F6D7}"), 2);
+        // The enclosed ideographs were wide before Unicode 9
+        assert_eq!(width("\u{1F232}", v8), 2);
+        // CJK ideographs are wide in every version
+        assert_eq!(width("\u{4E2D}", v8), 2);
+        // Emoji presentation sequences are only wide from Unicode 9
+        assert_eq!(width("\u{2764}\u{FE0F}", v8), 1);
+        assert_eq!(width("\u{2764}\u{FE0F}", v9), 2);
+        // Combining marks are zero width in every version
+        assert_eq!(width("e\u{301}", v8), 1);
+
+        let cell = Cell::new_grapheme_for_version("\u{1F600}", v8, CellAttributes::default());
+        assert_eq!(cell.width(), 1);
+        let mut cell = Cell::new_grapheme_for_version("\u{2764}", v9, CellAttributes::default());
+        cell.append_grapheme_for_version("\u{FE0F}", v9);
+        assert_eq!(cell.width(), 2);
+    }
 }
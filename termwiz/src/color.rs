@@ -332,7 +332,7 @@ impl From<RgbColor> for ColorSpec {
 /// TrueColor value, allowing a fallback to a more traditional palette
 /// index if TrueColor is not available.
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum ColorAttribute {
     /// Use RgbColor when supported, falling back to the specified PaletteIndex.
     TrueColorWithPaletteFallback(RgbColor, PaletteIndex),
@@ -268,6 +268,10 @@ pub enum Window {
     },
     ReportTextAreaSizeCells,
     ReportScreenSizeCells,
+    ReportScreenSizeCellsResponse {
+        width: Option<i64>,
+        height: Option<i64>,
+    },
     ReportIconLabel,
     ReportWindowTitle,
     PushIconAndWindowTitle,
@@ -337,6 +341,12 @@ impl Display for Window {
             ),
             Window::ReportTextAreaSizeCells => write!(f, "18t"),
             Window::ReportScreenSizeCells => write!(f, "19t"),
+            Window::ReportScreenSizeCellsResponse { width, height } => write!(
+                f,
+                "9;{};{}t",
+                numstr_or_empty(height),
+                numstr_or_empty(width),
+            ),
             Window::ReportIconLabel => write!(f, "20t"),
             Window::ReportWindowTitle => write!(f, "21t"),
             Window::PushIconAndWindowTitle => write!(f, "22;0t"),
@@ -445,8 +455,12 @@ pub enum Mode {
     ResetDecPrivateMode(DecPrivateMode),
     SaveDecPrivateMode(DecPrivateMode),
     RestoreDecPrivateMode(DecPrivateMode),
+    /// DECRQM: requests a DECRPM report of the state of the mode
+    QueryDecPrivateMode(DecPrivateMode),
     SetMode(TerminalMode),
     ResetMode(TerminalMode),
+    /// DECRQM: requests a DECRPM report of the state of the mode
+    QueryMode(TerminalMode),
     XtermKeyMode {
         resource: XtermKeyModifierResource,
         value: Option<i64>,
@@ -478,8 +492,16 @@ impl Display for Mode {
             Mode::ResetDecPrivateMode(mode) => emit!("l", mode),
             Mode::SaveDecPrivateMode(mode) => emit!("s", mode),
             Mode::RestoreDecPrivateMode(mode) => emit!("r", mode),
+            Mode::QueryDecPrivateMode(mode) => emit!("$p", mode),
             Mode::SetMode(mode) => emit_mode!("h", mode),
             Mode::ResetMode(mode) => emit_mode!("l", mode),
+            Mode::QueryMode(mode) => {
+                let value = match mode {
+                    TerminalMode::Code(mode) => mode.to_u16().ok_or_else(|| FmtError)?,
+                    TerminalMode::Unspecified(mode) => *mode,
+                };
+                write!(f, "{}$p", value)
+            }
             Mode::XtermKeyMode { resource, value } => {
                 write!(
                     f,
@@ -715,6 +737,9 @@ pub enum Cursor {
     /// equals the value of Pn.
     LineTabulation(u32),
 
+    /// DECST8C - Reset the tab stops to every 8 columns
+    SetDefaultTabStops,
+
     /// DECSTBM - Set top and bottom margins.
     SetTopAndBottomMargins {
         top: OneBased,
@@ -906,6 +931,7 @@ impl Display for Cursor {
             Cursor::LineTabulation(n) => n.write_csi(f, "Y")?,
             Cursor::TabulationControl(n) => n.write_csi(f, "W")?,
             Cursor::TabulationClear(n) => n.write_csi(f, "g")?,
+            Cursor::SetDefaultTabStops => write!(f, "?5W")?,
             Cursor::CharacterPositionAbsolute(n) => n.write_csi(f, "`")?,
             Cursor::CharacterPositionBackward(n) => n.write_csi(f, "j")?,
             Cursor::CharacterPositionForward(n) => n.write_csi(f, "a")?,
@@ -1391,6 +1417,9 @@ impl<'a> CSIParser<'a> {
             }
 
             ('p', &[b'!']) => Ok(CSI::Device(Box::new(Device::SoftReset))),
+            ('p', &[b'$']) => self
+                .terminal_mode(params)
+                .map(|mode| CSI::Mode(Mode::QueryMode(mode))),
 
             ('h', &[b'?']) => self
                 .dec(params)
@@ -1407,6 +1436,16 @@ impl<'a> CSIParser<'a> {
             ('s', &[b'?']) => self
                 .dec(params)
                 .map(|mode| CSI::Mode(Mode::SaveDecPrivateMode(mode))),
+            ('p', &[b'?', b'$']) => self
+                .dec(params)
+                .map(|mode| CSI::Mode(Mode::QueryDecPrivateMode(mode))),
+            ('W', &[b'?']) => {
+                if params == [5] {
+                    Ok(CSI::Cursor(Cursor::SetDefaultTabStops))
+                } else {
+                    Err(())
+                }
+            }
 
             ('m', &[b'<']) | ('M', &[b'<']) => self.mouse_sgr1006(params).map(CSI::Mouse),
             ('m', &[b'>']) => self.xterm_key_modifier(params),
@@ -1746,11 +1785,15 @@ impl<'a> CSIParser<'a> {
                     height: arg1,
                     width: arg2,
                 }),
-                9 => match arg1 {
-                    Some(0) => Ok(Window::RestoreMaximizedWindow),
-                    Some(1) => Ok(Window::MaximizeWindow),
-                    Some(2) => Ok(Window::MaximizeWindowVertically),
-                    Some(3) => Ok(Window::MaximizeWindowHorizontally),
+                9 => match (params.len(), arg1) {
+                    (3, _) => Ok(Window::ReportScreenSizeCellsResponse {
+                        height: arg1,
+                        width: arg2,
+                    }),
+                    (_, Some(0)) => Ok(Window::RestoreMaximizedWindow),
+                    (_, Some(1)) => Ok(Window::MaximizeWindow),
+                    (_, Some(2)) => Ok(Window::MaximizeWindowVertically),
+                    (_, Some(3)) => Ok(Window::MaximizeWindowHorizontally),
                     _ => Err(()),
                 },
                 10 => match arg1 {
@@ -2140,6 +2183,24 @@ mod test {
                 height: Some(15)
             })]
         );
+        assert_eq!(
+            parse('t', &[9, 1], "\x1b[9;1t"),
+            vec![CSI::Window(Window::MaximizeWindow)]
+        );
+        assert_eq!(
+            parse('t', &[9, 24, 80], "\x1b[9;24;80t"),
+            vec![CSI::Window(Window::ReportScreenSizeCellsResponse {
+                width: Some(80),
+                height: Some(24)
+            })]
+        );
+        assert_eq!(
+            parse('t', &[8, 24, 80], "\x1b[8;24;80t"),
+            vec![CSI::Window(Window::ResizeWindowCells {
+                width: Some(80),
+                height: Some(24)
+            })]
+        );
     }
 
     #[test]
@@ -2234,6 +2295,25 @@ mod test {
         );
     }
 
+    #[test]
+    fn decrqm() {
+        let res: Vec<CSI> = CSI::parse(&[2004], &[b'?', b'$'], false, 'p').collect();
+        assert_eq!(encode(&res), "\x1b[?2004$p");
+        assert_eq!(
+            res,
+            vec![CSI::Mode(Mode::QueryDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::BracketedPaste,
+            )))]
+        );
+
+        assert_eq!(
+            parse_int('p', &[4], b'$', "\x1b[4$p"),
+            vec![CSI::Mode(Mode::QueryMode(TerminalMode::Code(
+                TerminalModeCode::Insert,
+            )))]
+        );
+    }
+
     #[test]
     fn mouse() {
         assert_eq!(
@@ -2247,6 +2327,26 @@ mod test {
         );
     }
 
+    #[test]
+    fn tab_stops() {
+        assert_eq!(
+            parse('W', &[5], "\x1b[5W"),
+            vec![CSI::Cursor(Cursor::TabulationControl(
+                CursorTabulationControl::ClearAllCharacterTabStops
+            ))]
+        );
+        assert_eq!(
+            parse('g', &[3], "\x1b[3g"),
+            vec![CSI::Cursor(Cursor::TabulationClear(
+                TabulationClear::ClearAllCharacterTabStops
+            ))]
+        );
+        assert_eq!(
+            parse_int('W', &[5], b'?', "\x1b[?5W"),
+            vec![CSI::Cursor(Cursor::SetDefaultTabStops)]
+        );
+    }
+
     #[test]
     fn device_attr() {
         assert_eq!(
@@ -68,14 +68,28 @@ pub enum EscCode {
     /// DECPNM - Normal Keypad
     DecNormalKeyPad = esc!('>'),
 
-    /// Designate Character Set – DEC Line Drawing
+    /// Designate G0 Character Set – DEC Line Drawing
     DecLineDrawing = esc!('(', '0'),
-    /// Designate Character Set – US ASCII
+    /// Designate G0 Character Set – UK
+    UkCharacterSet = esc!('(', 'A'),
+    /// Designate G0 Character Set – US ASCII
     AsciiCharacterSet = esc!('(', 'B'),
+    /// Designate G1 Character Set – DEC Line Drawing
+    DecLineDrawingG1 = esc!(')', '0'),
+    /// Designate G1 Character Set – UK
+    UkCharacterSetG1 = esc!(')', 'A'),
+    /// Designate G1 Character Set – US ASCII
+    AsciiCharacterSetG1 = esc!(')', 'B'),
 
     /// https://vt100.net/docs/vt510-rm/DECALN.html
     DecScreenAlignmentDisplay = esc!('#', '8'),
 
+    /// DOCS - Select the default character set, which is ISO 8859-1.
+    /// The parser switches encodings itself when it sees this.
+    SelectLatin1Encoding = esc!('%', '@'),
+    /// DOCS - Select UTF-8
+    SelectUtf8Encoding = esc!('%', 'G'),
+
     /// These are typically sent by the terminal when keys are pressed
     ApplicationModeArrowUpPress = esc!('O', 'A'),
     ApplicationModeArrowDownPress = esc!('O', 'B'),
@@ -172,5 +186,7 @@ mod test {
     fn test() {
         assert_eq!(parse("(0"), Esc::Code(EscCode::DecLineDrawing));
         assert_eq!(parse("(B"), Esc::Code(EscCode::AsciiCharacterSet));
+        assert_eq!(parse(")0"), Esc::Code(EscCode::DecLineDrawingG1));
+        assert_eq!(parse(")A"), Esc::Code(EscCode::UkCharacterSetG1));
     }
 }
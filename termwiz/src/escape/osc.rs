@@ -37,16 +37,87 @@ pub enum OperatingSystemCommand {
     QuerySelection(Selection),
     SetSelection(Selection, String),
     SystemNotification(String),
+    ConEmuProgress(Progress),
+    RxvtExtension(Vec<String>),
     ITermProprietary(ITermProprietary),
     ChangeColorNumber(Vec<ChangeColorPair>),
     ChangeDynamicColors(DynamicColorNumber, Vec<ColorOrQuery>),
     ResetDynamicColor(DynamicColorNumber),
     CurrentWorkingDirectory(String),
     ResetColors(Vec<u8>),
+    FinalTermSemanticPrompt(FinalTermSemanticPrompt),
+    /// Sets the text that wezterm shows in the status area of its
+    /// tab bar; empty text clears it
+    SetStatusText(String),
 
     Unspecified(Vec<Vec<u8>>),
 }
 
+/// The state of a long running operation, as reported by the
+/// ConEmu `OSC 9;4;state;progress` sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// No operation is in progress; clears any prior state
+    None,
+    /// The operation is the specified percentage complete
+    SetPercentage(u8),
+    /// The operation failed after reaching the specified percentage
+    SetError(u8),
+    /// The operation is in progress but its extent is not known
+    SetIndeterminate,
+    /// The operation is paused at the specified percentage
+    Paused(u8),
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress::None
+    }
+}
+
+impl Display for Progress {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            Progress::None => write!(f, "9;4;0"),
+            Progress::SetPercentage(pct) => write!(f, "9;4;1;{}", pct),
+            Progress::SetError(pct) => write!(f, "9;4;2;{}", pct),
+            Progress::SetIndeterminate => write!(f, "9;4;3"),
+            Progress::Paused(pct) => write!(f, "9;4;4;{}", pct),
+        }
+    }
+}
+
+/// The markers of the FinalTerm shell integration protocol,
+/// `OSC 133;...`, with which a shell reports where its prompt, the
+/// command line that was entered and the output of the command are.
+/// Additional parameters that some shells send are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinalTermSemanticPrompt {
+    /// `133;A`: a prompt is about to be drawn, starting on a fresh line
+    FreshLineAndStartPrompt,
+    /// `133;B`: the prompt has ended and the command line starts
+    StartOfInput,
+    /// `133;C`: the command line was entered and its output starts
+    StartOfOutput,
+    /// `133;D;status`: the command finished, with its exit status
+    /// if the shell knows it
+    CommandStatus(Option<i32>),
+}
+
+impl Display for FinalTermSemanticPrompt {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            FinalTermSemanticPrompt::FreshLineAndStartPrompt => write!(f, "133;A"),
+            FinalTermSemanticPrompt::StartOfInput => write!(f, "133;B"),
+            FinalTermSemanticPrompt::StartOfOutput => write!(f, "133;C"),
+            FinalTermSemanticPrompt::CommandStatus(None) => write!(f, "133;D"),
+            FinalTermSemanticPrompt::CommandStatus(Some(status)) => {
+                write!(f, "133;D;{}", status)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
 #[repr(u8)]
 pub enum DynamicColorNumber {
@@ -245,6 +316,44 @@ impl OperatingSystemCommand {
         ))
     }
 
+    fn parse_conemu_progress(osc: &[&[u8]]) -> anyhow::Result<Self> {
+        ensure!(osc.len() <= 4, "wrong param count");
+        let state = str::from_utf8(osc[2])?;
+        // The percentage is optional for some of the states, and
+        // values outside of the 0-100 range are clamped
+        let pct = match osc.get(3) {
+            Some(pct) if !pct.is_empty() => str::from_utf8(pct)?.parse::<u32>()?.min(100) as u8,
+            _ => 0,
+        };
+        let progress = match state {
+            "0" => Progress::None,
+            "1" => Progress::SetPercentage(pct),
+            "2" => Progress::SetError(pct),
+            "3" => Progress::SetIndeterminate,
+            "4" => Progress::Paused(pct),
+            _ => bail!("invalid progress state {:?}", state),
+        };
+        Ok(OperatingSystemCommand::ConEmuProgress(progress))
+    }
+
+    fn parse_final_term_semantic_prompt(osc: &[&[u8]]) -> anyhow::Result<Self> {
+        ensure!(osc.len() >= 2, "wrong param count");
+        let marker = match osc[1] {
+            b"A" => FinalTermSemanticPrompt::FreshLineAndStartPrompt,
+            b"B" => FinalTermSemanticPrompt::StartOfInput,
+            b"C" => FinalTermSemanticPrompt::StartOfOutput,
+            b"D" => FinalTermSemanticPrompt::CommandStatus(match osc.get(2) {
+                Some(status) if !status.is_empty() => Some(str::from_utf8(status)?.parse()?),
+                _ => None,
+            }),
+            marker => bail!(
+                "invalid semantic prompt marker {:?}",
+                String::from_utf8_lossy(marker)
+            ),
+        };
+        Ok(OperatingSystemCommand::FinalTermSemanticPrompt(marker))
+    }
+
     fn internal_parse(osc: &[&[u8]]) -> anyhow::Result<Self> {
         ensure!(!osc.is_empty(), "no params");
         let p1str = String::from_utf8_lossy(osc[0]);
@@ -292,13 +401,32 @@ impl OperatingSystemCommand {
             )),
             SetHyperlink => Ok(OperatingSystemCommand::SetHyperlink(Hyperlink::parse(osc)?)),
             ManipulateSelectionData => Self::parse_selection(osc),
+            SystemNotification if osc.len() >= 3 && osc[1] == b"4" => {
+                Self::parse_conemu_progress(osc)
+            }
             SystemNotification => single_string!(SystemNotification),
+            RxvtProprietary => {
+                let mut params = vec![];
+                for param in osc.iter().skip(1) {
+                    params.push(String::from_utf8(param.to_vec())?);
+                }
+                Ok(OperatingSystemCommand::RxvtExtension(params))
+            }
             SetCurrentWorkingDirectory => single_string!(CurrentWorkingDirectory),
             ITermProprietary => {
                 self::ITermProprietary::parse(osc).map(OperatingSystemCommand::ITermProprietary)
             }
             ChangeColorNumber => Self::parse_change_color_number(osc),
             ResetColors => Self::parse_reset_colors(osc),
+            FinalTermSemanticPrompt => Self::parse_final_term_semantic_prompt(osc),
+            // The text may itself contain semicolons
+            WezTermStatusText => Ok(OperatingSystemCommand::SetStatusText(
+                osc[1..]
+                    .iter()
+                    .map(|s| String::from_utf8(s.to_vec()))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(";"),
+            )),
 
             ResetSpecialColor
             | ResetTextForegroundColor
@@ -407,8 +535,12 @@ osc_entries!(
     ResetHighlightColor = "117",
     ResetTektronixCursorColor = "118",
     ResetHighlightForegroundColor = "119",
+    /// See https://gitlab.freedesktop.org/Per_Bothner/specifications/blob/master/proposals/semantic-prompts.md
+    FinalTermSemanticPrompt = "133",
     RxvtProprietary = "777",
     ITermProprietary = "1337",
+    /// Sets the text of the wezterm status area
+    WezTermStatusText = "7777",
     /// Here the "Sun" suffix comes from the table in
     /// <https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Miscellaneous>
     /// that lays out various window related escape sequences.
@@ -479,6 +611,14 @@ impl Display for OperatingSystemCommand {
             QuerySelection(s) => write!(f, "52;{};?", s)?,
             SetSelection(s, val) => write!(f, "52;{};{}", s, base64::encode(val))?,
             SystemNotification(s) => write!(f, "9;{}", s)?,
+            ConEmuProgress(progress) => progress.fmt(f)?,
+            SetStatusText(text) => write!(f, "7777;{}", text)?,
+            RxvtExtension(params) => {
+                write!(f, "777")?;
+                for param in params {
+                    write!(f, ";{}", param)?;
+                }
+            }
             ITermProprietary(i) => i.fmt(f)?,
             ResetColors(colors) => {
                 write!(f, "104")?;
@@ -502,6 +642,7 @@ impl Display for OperatingSystemCommand {
                 write!(f, "{}", 100 + *color as u8)?;
             }
             CurrentWorkingDirectory(s) => write!(f, "7;{}", s)?,
+            FinalTermSemanticPrompt(marker) => marker.fmt(f)?,
         };
         // Use the longer form ST as neovim doesn't like the BEL version
         write!(f, "\x1b\\")?;
@@ -550,6 +691,29 @@ pub enum ITermProprietary {
     SetBadgeFormat(String),
     /// Download file data from the application.
     File(Box<ITermFileData>),
+    /// Selects the Unicode version whose character widths are used
+    UnicodeVersion(ITermUnicodeVersionOp),
+    /// Request that the terminal send a ReportTabInfo response.
+    /// This is a wezterm extension.
+    RequestTabInfo,
+    /// The response to RequestTabInfo: the ids of the tab and of the
+    /// window that contains it
+    ReportTabInfo {
+        tab_id: usize,
+        window_id: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ITermUnicodeVersionOp {
+    /// Use the widths of this major Unicode version
+    Set(u8),
+    /// Save the current version, optionally with a label
+    Push(Option<String>),
+    /// Restore the most recently saved version.  With a label, the
+    /// versions that were saved after the one with that label are
+    /// discarded too.
+    Pop(Option<String>),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -808,6 +972,7 @@ impl ITermProprietary {
         single!(ClearScrollback, "ClearScrollback");
         single!(EndCopy, "EndCopy");
         single!(RequestCellSize, "ReportCellSize");
+        single!(RequestTabInfo, "ReportTabInfo");
         const_arg!(HighlightCursorLine, "HighlightCursorLine", "yes", true);
         const_arg!(HighlightCursorLine, "HighlightCursorLine", "no", false);
         one_str!(CurrentDir, "CurrentDir");
@@ -840,6 +1005,15 @@ impl ITermProprietary {
             }
         }
 
+        if osc.len() == 3 && keyword == "ReportTabInfo" {
+            if let Some(p1) = p1 {
+                return Ok(ITermProprietary::ReportTabInfo {
+                    tab_id: p1.parse()?,
+                    window_id: String::from_utf8_lossy(osc[2]).parse()?,
+                });
+            }
+        }
+
         if osc.len() == 2 && keyword == "SetUserVar" {
             if let Some(p1) = p1 {
                 let mut iter = p1.splitn(2, '=');
@@ -855,6 +1029,24 @@ impl ITermProprietary {
             }
         }
 
+        if osc.len() == 2 && keyword == "UnicodeVersion" {
+            if let Some(p1) = p1 {
+                let mut iter = p1.splitn(2, ' ');
+                let op = iter.next().unwrap_or("");
+                let label = iter
+                    .next()
+                    .map(str::trim)
+                    .filter(|label| !label.is_empty())
+                    .map(str::to_string);
+                let op = match op {
+                    "push" => ITermUnicodeVersionOp::Push(label),
+                    "pop" => ITermUnicodeVersionOp::Pop(label),
+                    version => ITermUnicodeVersionOp::Set(version.parse()?),
+                };
+                return Ok(ITermProprietary::UnicodeVersion(op));
+            }
+        }
+
         if keyword == "File" {
             return Ok(ITermProprietary::File(Box::new(ITermFileData::parse(osc)?)));
         }
@@ -883,6 +1075,10 @@ impl Display for ITermProprietary {
                 height_points,
                 width_points,
             } => write!(f, "ReportCellSize={};{}", height_points, width_points)?,
+            RequestTabInfo => write!(f, "ReportTabInfo")?,
+            ReportTabInfo { tab_id, window_id } => {
+                write!(f, "ReportTabInfo={};{}", tab_id, window_id)?
+            }
             Copy(s) => write!(f, "Copy=;{}", base64::encode(s))?,
             ReportVariable(s) => write!(f, "ReportVariable={}", base64::encode(s))?,
             SetUserVar { name, value } => {
@@ -890,6 +1086,15 @@ impl Display for ITermProprietary {
             }
             SetBadgeFormat(s) => write!(f, "SetBadgeFormat={}", base64::encode(s))?,
             File(file) => file.fmt(f)?,
+            UnicodeVersion(ITermUnicodeVersionOp::Set(n)) => write!(f, "UnicodeVersion={}", n)?,
+            UnicodeVersion(ITermUnicodeVersionOp::Push(label)) => match label {
+                Some(label) => write!(f, "UnicodeVersion=push {}", label)?,
+                None => write!(f, "UnicodeVersion=push")?,
+            },
+            UnicodeVersion(ITermUnicodeVersionOp::Pop(label)) => match label {
+                Some(label) => write!(f, "UnicodeVersion=pop {}", label)?,
+                None => write!(f, "UnicodeVersion=pop")?,
+            },
         }
         Ok(())
     }
@@ -967,6 +1172,89 @@ mod test {
         );
     }
 
+    #[test]
+    fn conemu_progress() {
+        assert_eq!(
+            parse(&["9", "4", "1", "42"], "\x1b]9;4;1;42\x1b\\"),
+            OperatingSystemCommand::ConEmuProgress(Progress::SetPercentage(42))
+        );
+        assert_eq!(
+            parse(&["9", "4", "1", "250"], "\x1b]9;4;1;100\x1b\\"),
+            OperatingSystemCommand::ConEmuProgress(Progress::SetPercentage(100))
+        );
+        assert_eq!(
+            parse(&["9", "4", "2"], "\x1b]9;4;2;0\x1b\\"),
+            OperatingSystemCommand::ConEmuProgress(Progress::SetError(0))
+        );
+        assert_eq!(
+            parse(&["9", "4", "3", ""], "\x1b]9;4;3\x1b\\"),
+            OperatingSystemCommand::ConEmuProgress(Progress::SetIndeterminate)
+        );
+        assert_eq!(
+            parse(&["9", "4", "4", "7"], "\x1b]9;4;4;7\x1b\\"),
+            OperatingSystemCommand::ConEmuProgress(Progress::Paused(7))
+        );
+        assert_eq!(
+            parse(&["9", "4", "0"], "\x1b]9;4;0\x1b\\"),
+            OperatingSystemCommand::ConEmuProgress(Progress::None)
+        );
+
+        // A plain notification is still recognized
+        assert_eq!(
+            parse(&["9", "hello"], "\x1b]9;hello\x1b\\"),
+            OperatingSystemCommand::SystemNotification("hello".into())
+        );
+
+        // unknown state
+        assert_eq!(
+            parse(&["9", "4", "5"], "\x1b]9;4;5\x1b\\"),
+            OperatingSystemCommand::Unspecified(vec![b"9".to_vec(), b"4".to_vec(), b"5".to_vec()])
+        );
+    }
+
+    #[test]
+    fn semantic_prompt() {
+        assert_eq!(
+            parse(&["133", "A"], "\x1b]133;A\x1b\\"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::FreshLineAndStartPrompt
+            )
+        );
+        // Extra parameters are ignored
+        assert_eq!(
+            parse(&["133", "B", "aid=1"], "\x1b]133;B\x1b\\"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(FinalTermSemanticPrompt::StartOfInput)
+        );
+        assert_eq!(
+            parse(&["133", "D", "1"], "\x1b]133;D;1\x1b\\"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::CommandStatus(Some(1))
+            )
+        );
+        assert_eq!(
+            parse(&["133", "D"], "\x1b]133;D\x1b\\"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::CommandStatus(None)
+            )
+        );
+    }
+
+    #[test]
+    fn status_text() {
+        assert_eq!(
+            parse(&["7777", "12:30"], "\x1b]7777;12:30\x1b\\"),
+            OperatingSystemCommand::SetStatusText("12:30".into())
+        );
+        assert_eq!(
+            parse(&["7777", "load", "0.5"], "\x1b]7777;load;0.5\x1b\\"),
+            OperatingSystemCommand::SetStatusText("load;0.5".into())
+        );
+        assert_eq!(
+            parse(&["7777"], "\x1b]7777;\x1b\\"),
+            OperatingSystemCommand::SetStatusText("".into())
+        );
+    }
+
     #[test]
     fn hyperlink() {
         assert_eq!(
@@ -997,6 +1285,21 @@ mod test {
         );
     }
 
+    #[test]
+    fn rxvt_extension() {
+        assert_eq!(
+            parse(
+                &["777", "notify", "Build", "finished"],
+                "\x1b]777;notify;Build;finished\x1b\\"
+            ),
+            OperatingSystemCommand::RxvtExtension(vec![
+                "notify".into(),
+                "Build".into(),
+                "finished".into()
+            ])
+        );
+    }
+
     #[test]
     fn iterm() {
         assert_eq!(
@@ -1060,6 +1363,21 @@ mod test {
             })
         );
 
+        assert_eq!(
+            parse(&["1337", "ReportTabInfo"], "\x1b]1337;ReportTabInfo\x1b\\"),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::RequestTabInfo)
+        );
+        assert_eq!(
+            parse(
+                &["1337", "ReportTabInfo=3", "1"],
+                "\x1b]1337;ReportTabInfo=3;1\x1b\\"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::ReportTabInfo {
+                tab_id: 3,
+                window_id: 1
+            })
+        );
+
         assert_eq!(
             parse(
                 &["1337", "File=:aGVsbG8="],
@@ -1215,4 +1533,35 @@ mod test {
             )))
         );
     }
+
+    #[test]
+    fn iterm_unicode_version() {
+        assert_eq!(
+            parse(
+                &["1337", "UnicodeVersion=8"],
+                "\x1b]1337;UnicodeVersion=8\x1b\\"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::UnicodeVersion(
+                ITermUnicodeVersionOp::Set(8)
+            ))
+        );
+        assert_eq!(
+            parse(
+                &["1337", "UnicodeVersion=push vim"],
+                "\x1b]1337;UnicodeVersion=push vim\x1b\\"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::UnicodeVersion(
+                ITermUnicodeVersionOp::Push(Some("vim".into()))
+            ))
+        );
+        assert_eq!(
+            parse(
+                &["1337", "UnicodeVersion=pop"],
+                "\x1b]1337;UnicodeVersion=pop\x1b\\"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::UnicodeVersion(
+                ITermUnicodeVersionOp::Pop(None)
+            ))
+        );
+    }
 }
@@ -7,6 +7,7 @@ use log::error;
 use num_traits::FromPrimitive;
 use regex::bytes::Regex;
 use std::cell::RefCell;
+pub use vtparse::Encoding;
 use vtparse::{VTActor, VTParser};
 
 struct SixelBuilder {
@@ -33,8 +34,15 @@ struct ParseState {
 pub struct Parser {
     state_machine: VTParser,
     state: RefCell<ParseState>,
+    /// The raw bytes of a sequence that spans the chunks passed to
+    /// `parse_with_raw`
+    pending_raw: Vec<u8>,
 }
 
+/// The most bytes of a single sequence that `parse_with_raw` will
+/// retain; longer sequences, such as sixel images, are truncated.
+const MAX_RAW_LEN: usize = 1024;
+
 impl Default for Parser {
     fn default() -> Self {
         Self::new()
@@ -46,9 +54,27 @@ impl Parser {
         Self {
             state_machine: VTParser::new(),
             state: RefCell::new(Default::default()),
+            pending_raw: vec![],
         }
     }
 
+    /// Returns the encoding that the bytes are decoded from
+    pub fn encoding(&self) -> Encoding {
+        self.state_machine.encoding()
+    }
+
+    /// Selects the encoding that the bytes are decoded from, until the
+    /// application selects another using `ESC % G` or `ESC % @`
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.state_machine.set_encoding(encoding);
+    }
+
+    /// Controls whether bytes from 0x80 to 0x9f that aren't part of a
+    /// UTF-8 sequence are taken to be 8-bit C1 controls
+    pub fn set_c1_controls(&mut self, enabled: bool) {
+        self.state_machine.set_c1_controls(enabled);
+    }
+
     pub fn parse<F: FnMut(Action)>(&mut self, bytes: &[u8], mut callback: F) {
         let mut perform = Performer {
             callback: &mut callback,
@@ -57,6 +83,50 @@ impl Parser {
         self.state_machine.parse(bytes, &mut perform);
     }
 
+    /// Similar to `parse`, but the callback is also passed the raw bytes
+    /// that the action was decoded from, which is useful for reporting
+    /// sequences that weren't understood.  When several actions are
+    /// decoded from a single sequence, each of them is passed all of
+    /// its bytes.  The bytes are truncated to `MAX_RAW_LEN`.
+    pub fn parse_with_raw<F: FnMut(Action, &[u8])>(&mut self, bytes: &[u8], mut callback: F) {
+        let mut actions = vec![];
+        let mut start = 0;
+        for (idx, b) in bytes.iter().enumerate() {
+            self.state_machine.parse_byte(
+                *b,
+                &mut Performer {
+                    callback: &mut |action| actions.push(action),
+                    state: &mut self.state.borrow_mut(),
+                },
+            );
+            if actions.is_empty() {
+                continue;
+            }
+
+            let raw = &bytes[start..=idx];
+            let raw = if self.pending_raw.is_empty() {
+                &raw[..raw.len().min(MAX_RAW_LEN)]
+            } else {
+                let room = MAX_RAW_LEN.saturating_sub(self.pending_raw.len());
+                self.pending_raw
+                    .extend_from_slice(&raw[..raw.len().min(room)]);
+                &self.pending_raw
+            };
+            for action in actions.drain(..) {
+                callback(action, raw);
+            }
+            self.pending_raw.clear();
+            start = idx + 1;
+        }
+
+        // Retain the start of a sequence that is continued in the
+        // next chunk
+        let rest = &bytes[start..];
+        let room = MAX_RAW_LEN.saturating_sub(self.pending_raw.len());
+        self.pending_raw
+            .extend_from_slice(&rest[..rest.len().min(room)]);
+    }
+
     /// A specialized version of the parser that halts after recognizing the
     /// first action from the stream of bytes.  The return value is the action
     /// that was recognized and the length of the byte stream that was fed in
@@ -418,7 +488,8 @@ impl SixelBuilder {
 mod test {
     use super::*;
     use crate::cell::Intensity;
-    use crate::escape::csi::Sgr;
+    use crate::color::AnsiColor;
+    use crate::escape::csi::{DecPrivateMode, Mode, Sgr};
     use crate::escape::EscCode;
     use std::io::Write;
 
@@ -430,6 +501,39 @@ mod test {
         String::from_utf8(res).unwrap()
     }
 
+    #[test]
+    fn parse_with_raw() {
+        let mut p = Parser::new();
+        let mut actions = vec![];
+        p.parse_with_raw(b"a\x1b[1;3", |action, raw| {
+            actions.push((action, raw.to_vec()))
+        });
+        p.parse_with_raw(b"1mb\x1b[?9999h", |action, raw| {
+            actions.push((action, raw.to_vec()))
+        });
+        assert_eq!(
+            actions,
+            vec![
+                (Action::Print('a'), b"a".to_vec()),
+                (
+                    Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))),
+                    b"\x1b[1;31m".to_vec()
+                ),
+                (
+                    Action::CSI(CSI::Sgr(Sgr::Foreground(AnsiColor::Maroon.into()))),
+                    b"\x1b[1;31m".to_vec()
+                ),
+                (Action::Print('b'), b"b".to_vec()),
+                (
+                    Action::CSI(CSI::Mode(Mode::SetDecPrivateMode(
+                        DecPrivateMode::Unspecified(9999)
+                    ))),
+                    b"\x1b[?9999h".to_vec()
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn basic_parse() {
         let mut p = Parser::new();
@@ -140,7 +140,8 @@ pub struct Rule {
     /// The replacements are carried out in reverse order, starting
     /// with the highest numbered capture first.  This avoids issues
     /// with ambiguous replacement of `$11` vs `$1` in the case of
-    /// more complex regexes.
+    /// more complex regexes.  A capture group that didn't take part
+    /// in the match is replaced by the empty string.
     format: String,
 }
 
@@ -190,7 +191,10 @@ impl<'t> Match<'t> {
         // This avoids ambiguity when replacing $11 vs $1.
         for n in (0..self.captures.len()).rev() {
             let search = format!("${}", n);
-            result = result.replace(&search, self.captures.get(n).unwrap().as_str());
+            // A group that didn't participate in the match expands
+            // to nothing
+            let capture = self.captures.get(n).map_or("", |m| m.as_str());
+            result = result.replace(&search, capture);
         }
         result
     }
@@ -267,4 +271,17 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn optional_capture() {
+        let rules = vec![Rule::new(r"\b(\w+\.rs):(\d+)(?::(\d+))?\b", "$1#$2,$3").unwrap()];
+
+        assert_eq!(
+            Rule::match_hyperlinks("main.rs:42", &rules),
+            vec![RuleMatch {
+                range: 0..10,
+                link: Arc::new(Hyperlink::new_implicit("main.rs#42,")),
+            }]
+        );
+    }
 }
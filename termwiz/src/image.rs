@@ -35,7 +35,7 @@ where
 }
 
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TextureCoordinate {
     #[cfg_attr(
         feature = "use_serde",
@@ -109,6 +109,161 @@ impl ImageCell {
     pub fn image_data(&self) -> &Arc<ImageData> {
         &self.data
     }
+
+    /// Returns the portion of this cell that is covered by the image.
+    /// The texture coordinates of a cell may extend outside of the
+    /// 0..1 range when the image is letterboxed within the cells that
+    /// it spans; this clips them back to the image and computes the
+    /// corresponding portion of the cell.
+    /// Returns None if the cell is entirely letterboxing.
+    pub fn visible_region(&self) -> Option<ImageCellRegion> {
+        fn clip(start: f32, end: f32) -> Option<(f32, f32, f32, f32)> {
+            let clipped_start = start.max(0.);
+            let clipped_end = end.min(1.);
+            if clipped_end <= clipped_start {
+                return None;
+            }
+            let span = end - start;
+            Some((
+                clipped_start,
+                clipped_end,
+                (clipped_start - start) / span,
+                (clipped_end - start) / span,
+            ))
+        }
+
+        let (tex_left, tex_right, cell_left, cell_right) =
+            clip(*self.top_left.x, *self.bottom_right.x)?;
+        let (tex_top, tex_bottom, cell_top, cell_bottom) =
+            clip(*self.top_left.y, *self.bottom_right.y)?;
+
+        Some(ImageCellRegion {
+            top_left: TextureCoordinate::new_f32(tex_left, tex_top),
+            bottom_right: TextureCoordinate::new_f32(tex_right, tex_bottom),
+            cell_top_left: (cell_left, cell_top),
+            cell_bottom_right: (cell_right, cell_bottom),
+        })
+    }
+}
+
+/// The portion of a cell that is covered by its image, as computed
+/// by `ImageCell::visible_region`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageCellRegion {
+    /// Texture coordinates of the visible portion of the image
+    pub top_left: TextureCoordinate,
+    pub bottom_right: TextureCoordinate,
+    /// The extent of the visible portion, expressed as fractions of
+    /// the cell size, where (0, 0) is the top left of the cell and
+    /// (1, 1) is the bottom right.
+    pub cell_top_left: (f32, f32),
+    pub cell_bottom_right: (f32, f32),
+}
+
+/// Describes how an image is laid out over the cells that it spans.
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImagePlacement {
+    /// The number of cells spanned by the image
+    pub cols: usize,
+    pub rows: usize,
+    /// The size of a cell, in pixels, at the time the image was placed
+    pub cell_width: usize,
+    pub cell_height: usize,
+    /// The offset, in pixels, of the image from the top left of the span
+    pub x: usize,
+    pub y: usize,
+    /// The size, in pixels, at which the image will be displayed
+    pub width: usize,
+    pub height: usize,
+    /// Whether the image was fitted into the span honoring its aspect ratio
+    pub preserve_aspect_ratio: bool,
+}
+
+impl ImagePlacement {
+    /// Computes the placement of an image whose native size is
+    /// `image_size` (width, height) pixels, in cells of `cell_size` pixels.
+    /// `requested` holds the requested width and height in pixels, with
+    /// None to have that dimension computed from the image: from the
+    /// other dimension, keeping the aspect ratio of the image, if that
+    /// was requested, or else from the native size of the image.
+    /// The span is limited to `max_width` pixels; there is no limit on
+    /// the height, so tall images scroll along with their cells.
+    /// When `preserve_aspect_ratio` is true the image is scaled to fit
+    /// within the requested dimensions and is centered within them.
+    pub fn compute(
+        image_size: (usize, usize),
+        requested: (Option<usize>, Option<usize>),
+        preserve_aspect_ratio: bool,
+        cell_size: (usize, usize),
+        max_width: usize,
+    ) -> Self {
+        let (image_width, image_height) = image_size;
+        let (width, height) = requested;
+        let (cell_width, cell_height) = cell_size;
+        let image_width = image_width.max(1) as f32;
+        let image_height = image_height.max(1) as f32;
+
+        let (span_width, mut span_height) = match (width, height) {
+            (None, None) => (image_width, image_height),
+            (Some(w), None) => (w as f32, image_height * w as f32 / image_width),
+            (None, Some(h)) => (image_width * h as f32 / image_height, h as f32),
+            (Some(w), Some(h)) => (w as f32, h as f32),
+        };
+
+        let max_width = max_width.max(1) as f32;
+        let span_width = if span_width > max_width {
+            if height.is_none() {
+                // The height was derived from the width, so scale it down
+                // to match rather than letterboxing
+                span_height = span_height * max_width / span_width;
+            }
+            max_width
+        } else {
+            span_width
+        };
+        let span_width = span_width.max(1.);
+        let span_height = span_height.max(1.);
+
+        let (display_width, display_height) = if preserve_aspect_ratio {
+            let scale = (span_width / image_width).min(span_height / image_height);
+            (image_width * scale, image_height * scale)
+        } else {
+            (span_width, span_height)
+        };
+
+        let cell_width = cell_width.max(1);
+        let cell_height = cell_height.max(1);
+
+        Self {
+            cols: (span_width / cell_width as f32).ceil() as usize,
+            rows: (span_height / cell_height as f32).ceil() as usize,
+            cell_width,
+            cell_height,
+            x: ((span_width - display_width) / 2.) as usize,
+            y: ((span_height - display_height) / 2.) as usize,
+            width: (display_width as usize).max(1),
+            height: (display_height as usize).max(1),
+            preserve_aspect_ratio,
+        }
+    }
+
+    /// Returns the texture coordinates of the top left and bottom right
+    /// of the cell at `col`, `row` within the span.  Cells that fall
+    /// outside of the displayed image have coordinates outside of the
+    /// 0..1 range; see `ImageCell::visible_region`.
+    pub fn cell_texture_coords(
+        &self,
+        col: usize,
+        row: usize,
+    ) -> (TextureCoordinate, TextureCoordinate) {
+        let x = |col: usize| ((col * self.cell_width) as f32 - self.x as f32) / self.width as f32;
+        let y = |row: usize| ((row * self.cell_height) as f32 - self.y as f32) / self.height as f32;
+        (
+            TextureCoordinate::new_f32(x(col), y(row)),
+            TextureCoordinate::new_f32(x(col + 1), y(row + 1)),
+        )
+    }
 }
 
 static IMAGE_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
@@ -119,13 +274,33 @@ pub struct ImageData {
     id: usize,
     /// The image data bytes.  Data is the native image file format
     data: Vec<u8>,
+    /// How the image was laid out when it was added to the terminal
+    placement: Option<ImagePlacement>,
 }
 
 impl ImageData {
     /// Create a new ImageData struct with the provided raw data.
     pub fn with_raw_data(data: Vec<u8>) -> Self {
         let id = IMAGE_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed);
-        Self { id, data }
+        Self {
+            id,
+            data,
+            placement: None,
+        }
+    }
+
+    /// Create a new ImageData struct with the provided raw data and
+    /// the placement that was computed for it.
+    pub fn with_placement(data: Vec<u8>, placement: ImagePlacement) -> Self {
+        Self {
+            placement: Some(placement),
+            ..Self::with_raw_data(data)
+        }
+    }
+
+    #[inline]
+    pub fn placement(&self) -> Option<&ImagePlacement> {
+        self.placement.as_ref()
     }
 
     #[inline]
@@ -138,3 +313,73 @@ impl ImageData {
         self.id
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn placement_letterboxes() {
+        // A 256x128 image in an 8x13 span of 16x16 pixel cells is
+        // scaled to 128x64 and centered vertically
+        let placement =
+            ImagePlacement::compute((256, 128), (Some(128), Some(208)), true, (16, 16), 800);
+        assert_eq!((placement.cols, placement.rows), (8, 13));
+        assert_eq!((placement.x, placement.y), (0, 72));
+        assert_eq!((placement.width, placement.height), (128, 64));
+
+        let data = Arc::new(ImageData::with_placement(vec![], placement));
+        let cell = |col, row| {
+            let (top_left, bottom_right) = placement.cell_texture_coords(col, row);
+            ImageCell::new(top_left, bottom_right, Arc::clone(&data))
+        };
+
+        // Entirely within the letterboxing above the image
+        assert_eq!(cell(0, 0).visible_region(), None);
+        // Straddles the top edge of the image
+        let region = cell(0, 4).visible_region().unwrap();
+        assert_eq!(region.top_left, TextureCoordinate::new_f32(0., 0.));
+        assert_eq!(
+            region.bottom_right,
+            TextureCoordinate::new_f32(0.125, 0.125)
+        );
+        assert_eq!(region.cell_top_left, (0., 0.5));
+        assert_eq!(region.cell_bottom_right, (1., 1.));
+        // Entirely within the image
+        let region = cell(7, 5).visible_region().unwrap();
+        assert_eq!(region.top_left, TextureCoordinate::new_f32(0.875, 0.125));
+        assert_eq!(region.cell_top_left, (0., 0.));
+        assert_eq!(region.cell_bottom_right, (1., 1.));
+        // Entirely within the letterboxing below the image
+        assert_eq!(cell(7, 12).visible_region(), None);
+    }
+
+    #[test]
+    fn placement_automatic_dimension() {
+        // Auto height follows the aspect ratio of the requested width
+        let placement = ImagePlacement::compute((200, 100), (Some(100), None), true, (10, 10), 800);
+        assert_eq!((placement.cols, placement.rows), (10, 5));
+        assert_eq!((placement.width, placement.height), (100, 50));
+
+        // Too wide for the screen: scaled down, but the height is not clamped
+        let placement = ImagePlacement::compute((100, 1000), (None, None), true, (10, 10), 50);
+        assert_eq!((placement.cols, placement.rows), (5, 50));
+
+        // A single dimension determines the other from the aspect
+        // ratio, even when the image may be stretched
+        let placement =
+            ImagePlacement::compute((200, 100), (Some(100), None), false, (10, 10), 800);
+        assert_eq!((placement.width, placement.height), (100, 50));
+        let placement = ImagePlacement::compute((200, 100), (None, Some(20)), false, (10, 10), 800);
+        assert_eq!((placement.width, placement.height), (40, 20));
+        let placement =
+            ImagePlacement::compute((200, 100), (Some(400), None), false, (10, 10), 100);
+        assert_eq!((placement.width, placement.height), (100, 50));
+
+        // Stretched when not preserving the aspect ratio
+        let placement =
+            ImagePlacement::compute((200, 100), (Some(100), Some(200)), false, (10, 20), 800);
+        assert_eq!((placement.x, placement.y), (0, 0));
+        assert_eq!((placement.width, placement.height), (100, 200));
+    }
+}
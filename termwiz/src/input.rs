@@ -25,6 +25,9 @@ bitflags! {
         const ALT = 1<<2;
         const CTRL = 1<<3;
         const SUPER = 1<<4;
+        /// A virtual modifier that wezterm uses for the bindings
+        /// that follow its leader key; it is never encoded
+        const LEADER = 1<<5;
     }
 }
 bitflags! {
@@ -83,6 +83,9 @@ pub struct LineEditor<'term> {
     move_to_editor_start: Option<Change>,
     move_to_editor_end: Option<Change>,
 
+    /// Characters, in addition to whitespace, that separate words
+    word_delimiters: String,
+
     state: EditorState,
 }
 
@@ -161,6 +164,7 @@ impl<'term> LineEditor<'term> {
             completion: None,
             move_to_editor_start: None,
             move_to_editor_end: None,
+            word_delimiters: String::new(),
             state: EditorState::Inactive,
         }
     }
@@ -281,6 +285,17 @@ impl<'term> LineEditor<'term> {
         self.prompt = prompt.to_owned();
     }
 
+    /// Specifies the characters that separate words for the purposes
+    /// of the word movement and deletion actions.  Whitespace always
+    /// separates words; by default it is the only separator.
+    pub fn set_word_delimiters(&mut self, delimiters: &str) {
+        self.word_delimiters = delimiters.to_owned();
+    }
+
+    fn is_word_delimiter(&self, c: char) -> bool {
+        c.is_whitespace() || self.word_delimiters.contains(c)
+    }
+
     /// Enter line editing mode.
     /// Control is not returned to the caller until a line has been
     /// accepted, or until an error is detected.
@@ -528,7 +543,7 @@ impl<'term> LineEditor<'term> {
 
                     let mut found = None;
                     for prev in (0..char_position - 1).rev() {
-                        if char_indices[prev].1.is_whitespace() {
+                        if self.is_word_delimiter(char_indices[prev].1) {
                             found = Some(prev + 1);
                             break;
                         }
@@ -551,14 +566,14 @@ impl<'term> LineEditor<'term> {
                 for _ in 0..rep {
                     // Skip any non-whitespace characters
                     while char_position < char_indices.len()
-                        && !char_indices[char_position].1.is_whitespace()
+                        && !self.is_word_delimiter(char_indices[char_position].1)
                     {
                         char_position += 1;
                     }
 
                     // Skip any whitespace characters
                     while char_position < char_indices.len()
-                        && char_indices[char_position].1.is_whitespace()
+                        && self.is_word_delimiter(char_indices[char_position].1)
                     {
                         char_position += 1;
                     }
@@ -5,6 +5,8 @@ use crate::surface::Change;
 use bitflags::bitflags;
 #[cfg(feature = "use_serde")]
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::ops::Range;
 use std::sync::Arc;
 use unicode_segmentation::UnicodeSegmentation;
@@ -22,6 +24,8 @@ bitflags! {
         const SCANNED_IMPLICIT_HYPERLINKS = 1<<2;
         /// true if we found implicit hyperlinks in the last scan
         const HAS_IMPLICIT_HYPERLINKS = 1<<3;
+        /// The application reported that a prompt starts on this line
+        const PROMPT = 1<<4;
     }
 }
 
@@ -86,6 +90,7 @@ impl Line {
     /// Wrap the line so that it fits within the provided width.
     /// Returns the list of resultant line(s)
     pub fn wrap(mut self, width: usize) -> Vec<Self> {
+        let prompt = self.is_prompt();
         if let Some(end_idx) = self.cells.iter().rposition(|c| c.str() != " ") {
             self.cells.resize(end_idx + 1, Cell::default());
 
@@ -108,6 +113,10 @@ impl Line {
             lines
                 .last_mut()
                 .map(|line| line.set_last_cell_was_wrapped(false));
+            // The prompt starts on the first of them
+            if let Some(line) = lines.first_mut() {
+                line.set_prompt(prompt);
+            }
             lines
         } else {
             vec![self]
@@ -135,6 +144,22 @@ impl Line {
         self.bits &= !LineBits::DIRTY;
     }
 
+    /// Returns true if the application reported that a prompt starts
+    /// on this line, using the FinalTerm `OSC 133;A` sequence
+    #[inline]
+    pub fn is_prompt(&self) -> bool {
+        (self.bits & LineBits::PROMPT) == LineBits::PROMPT
+    }
+
+    #[inline]
+    pub fn set_prompt(&mut self, prompt: bool) {
+        if prompt {
+            self.bits |= LineBits::PROMPT;
+        } else {
+            self.bits &= !LineBits::PROMPT;
+        }
+    }
+
     /// If we have any cells with an implicit hyperlink, remove the hyperlink
     /// from the cell attributes but leave the remainder of the attributes alone.
     pub fn invalidate_implicit_hyperlinks(&mut self) {
@@ -416,6 +441,19 @@ impl Line {
         CellCluster::make_cluster(self.visible_cells())
     }
 
+    /// Computes a hash of the text and attributes of the cells in the
+    /// line.  Unlike the dirty bit, this doesn't change when a line is
+    /// rewritten with the same content, so it can be used as the key
+    /// for caching data derived from the line, such as shaped text.
+    pub fn compute_shape_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for cell in &self.cells {
+            cell.str().hash(&mut hasher);
+            cell.attrs().hash_identity(&mut hasher);
+        }
+        hasher.finish()
+    }
+
     pub fn cells(&self) -> &[Cell] {
         &self.cells
     }
@@ -438,6 +476,33 @@ impl Line {
             .unwrap_or(false)
     }
 
+    /// Returns the number of cells up to and including the last one
+    /// that has explicit content; the cells beyond that are blank
+    /// padding that the application never wrote.
+    pub fn written_len(&self) -> usize {
+        self.cells
+            .iter()
+            .rposition(|c| c.attrs().written() || c.attrs().tab() || c.str() != " ")
+            .map(|idx| idx + 1)
+            .unwrap_or(0)
+    }
+
+    /// Records that the cursor was moved over the cells in `cols` by
+    /// a horizontal tab, so that the tab can be reproduced when the text
+    /// is copied.  Nothing is recorded unless all of those cells are
+    /// blank padding.
+    pub fn mark_tab(&mut self, cols: Range<usize>) {
+        if cols.start >= cols.end || cols.end > self.cells.len() {
+            return;
+        }
+        let blank = self.cells[cols.clone()]
+            .iter()
+            .all(|c| c.str() == " " && !c.attrs().written() && !c.attrs().tab());
+        if blank {
+            self.cells[cols.start].attrs_mut().set_tab(true);
+        }
+    }
+
     /// Adjust the value of the wrapped attribute on the last cell of this
     /// line.
     pub fn set_last_cell_was_wrapped(&mut self, wrapped: bool) {
@@ -540,9 +605,52 @@ impl<'a> From<&'a str> for Line {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::cell::Intensity;
     use crate::hyperlink::*;
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn shape_hash() {
+        let line: Line = "hello".into();
+        let mut same: Line = "hello".into();
+        same.clear_dirty();
+        assert_eq!(line.compute_shape_hash(), same.compute_shape_hash());
+
+        let other: Line = "hellp".into();
+        assert_ne!(line.compute_shape_hash(), other.compute_shape_hash());
+
+        let mut bold = line.clone();
+        let attr = CellAttributes::default()
+            .set_intensity(Intensity::Bold)
+            .clone();
+        bold.set_cell(0, Cell::new('h', attr));
+        assert_ne!(line.compute_shape_hash(), bold.compute_shape_hash());
+
+        // Hyperlinks are distinguished by identity
+        let link = Arc::new(Hyperlink::new("http://example.com"));
+        let attr = CellAttributes::default()
+            .set_hyperlink(Some(Arc::clone(&link)))
+            .clone();
+        let mut linked = line.clone();
+        linked.set_cell(0, Cell::new('h', attr.clone()));
+        let mut linked_again = line.clone();
+        linked_again.set_cell(0, Cell::new('h', attr));
+        assert_eq!(
+            linked.compute_shape_hash(),
+            linked_again.compute_shape_hash()
+        );
+
+        let attr = CellAttributes::default()
+            .set_hyperlink(Some(Arc::new(Hyperlink::new("http://example.com"))))
+            .clone();
+        let mut linked_elsewhere = line.clone();
+        linked_elsewhere.set_cell(0, Cell::new('h', attr));
+        assert_ne!(
+            linked.compute_shape_hash(),
+            linked_elsewhere.compute_shape_hash()
+        );
+    }
+
     #[test]
     fn hyperlinks() {
         let text =
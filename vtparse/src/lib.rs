@@ -10,7 +10,9 @@
 //! You may wish to use `termwiz::escape::parser::Parser` in the
 //! [termwiz](https://docs.rs/termwiz/) crate if you don't want to have to research
 //! all those possible escape sequences for yourself.
+use std::borrow::Cow;
 use utf8parse::Parser as Utf8Parser;
+mod cp437;
 mod enums;
 use crate::enums::*;
 mod transitions;
@@ -323,6 +325,63 @@ impl OscState {
     }
 }
 
+/// How the parser decodes the bytes from 0x80 upwards into characters.
+/// An application can switch between these using the ISO 2022
+/// sequences `ESC % G`, which selects UTF-8, and `ESC % @`, which
+/// selects Latin-1.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Encoding {
+    /// Characters are encoded as UTF-8.  This is the default.
+    Utf8,
+    /// Each byte from 0xa0 upwards is the ISO 8859-1 character of the
+    /// same value.
+    Latin1,
+    /// Each byte from 0x80 upwards is a character of code page 437,
+    /// such as a box drawing character.  There are no C1 controls.
+    Cp437,
+}
+
+impl Encoding {
+    /// Returns the character that `byte` stands for by itself, or None
+    /// if it is part of a UTF-8 sequence or is a C1 control
+    pub fn decode(self, byte: u8) -> Option<char> {
+        match (self, byte) {
+            (_, 0..=0x7f) => Some(byte as char),
+            (Encoding::Utf8, _) | (Encoding::Latin1, 0x80..=0x9f) => None,
+            (Encoding::Latin1, _) => Some(byte as char),
+            (Encoding::Cp437, _) => Some(cp437::CP437[byte as usize - 0x80]),
+        }
+    }
+
+    /// Encodes `text` for a program that expects this encoding.  The
+    /// characters that can't be represented become `?`.
+    pub fn encode(self, text: &str) -> Cow<[u8]> {
+        if self == Encoding::Utf8 || text.is_ascii() {
+            return Cow::Borrowed(text.as_bytes());
+        }
+        Cow::Owned(
+            text.chars()
+                .map(|c| match (self, c as u32) {
+                    (_, 0..=0x7f) => c as u8,
+                    (Encoding::Latin1, 0x80..=0xff) => c as u8,
+                    (Encoding::Cp437, _) => cp437::CP437
+                        .iter()
+                        .position(|&p| p == c)
+                        .map(|idx| (idx + 0x80) as u8)
+                        .unwrap_or(b'?'),
+                    _ => b'?',
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Self::Utf8
+    }
+}
+
 /// The virtual terminal parser.  It works together with an implementation of `VTActor`.
 pub struct VTParser {
     state: State,
@@ -340,6 +399,9 @@ pub struct VTParser {
 
     utf8_parser: Utf8Parser,
     utf8_return_state: State,
+
+    encoding: Encoding,
+    c1_controls: bool,
 }
 
 impl VTParser {
@@ -369,9 +431,33 @@ impl VTParser {
             current_param: None,
 
             utf8_parser: Utf8Parser::new(),
+
+            encoding: Encoding::Utf8,
+            c1_controls: true,
         }
     }
 
+    /// Returns the encoding that the bytes are decoded from
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Selects the encoding that the bytes are decoded from, until the
+    /// application selects another
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Controls whether the bytes from 0x80 to 0x9f are the 8-bit forms
+    /// of the C1 controls, such as 0x9b for CSI, when they aren't part
+    /// of a UTF-8 sequence.  This is enabled by default.  When disabled,
+    /// such a byte is invalid UTF-8, which prints as U+FFFD, and in
+    /// Latin-1 it is ignored.  Either way, the bytes that continue a
+    /// UTF-8 sequence are never taken to be controls.
+    pub fn set_c1_controls(&mut self, enabled: bool) {
+        self.c1_controls = enabled;
+    }
+
     fn finish_param(&mut self) {
         if let Some(val) = self.current_param.take() {
             if self.num_params < MAX_PARAMS {
@@ -436,6 +522,13 @@ impl VTParser {
             Action::Put => actor.dcs_put(param),
             Action::EscDispatch => {
                 self.finish_param();
+                if self.num_intermediates == 1 && self.intermediates[0] == b'%' {
+                    match param {
+                        b'G' => self.encoding = Encoding::Utf8,
+                        b'@' => self.encoding = Encoding::Latin1,
+                        _ => {}
+                    }
+                }
                 actor.esc_dispatch(
                     &self.params[0..self.num_params],
                     &self.intermediates[0..self.num_intermediates],
@@ -507,6 +600,17 @@ impl VTParser {
             }
         }
 
+        // A byte that can't continue the sequence ends it, and is then
+        // processed in its own right.  Otherwise the ESC that starts a
+        // sequence following a truncated character would be swallowed.
+        if self.state == State::Utf8Sequence && (byte < 0x80 || byte > 0xbf) {
+            self.utf8_parser = Utf8Parser::new();
+            self.state = self.utf8_return_state;
+            self.print_char(std::char::REPLACEMENT_CHARACTER, actor);
+            self.parse_byte(byte, actor);
+            return;
+        }
+
         let mut decoder = Decoder { codepoint: None };
 
         self.utf8_parser.advance(&mut decoder, byte);
@@ -540,6 +644,35 @@ impl VTParser {
         }
     }
 
+    /// Handles a character that was decoded from a byte above 0x7f,
+    /// which is text in the ground and OSC string states, and ignored
+    /// in the others
+    fn print_char(&mut self, c: char, actor: &mut dyn VTActor) {
+        match self.state {
+            State::Ground => actor.print(c),
+            State::OscString => self.osc.put(c),
+            _ => {}
+        }
+    }
+
+    /// Decodes a byte above 0x7f that doesn't start a UTF-8 sequence
+    /// or a C1 control, returning false if it does
+    fn decode_high_byte(&mut self, byte: u8, actor: &mut dyn VTActor) -> bool {
+        match (self.encoding, byte) {
+            (Encoding::Cp437, _) => {}
+            (_, 0x80..=0x9f) if self.c1_controls => return false,
+            (Encoding::Utf8, 0xc2..=0xf4) => return false,
+            _ => {}
+        }
+        match self.encoding.decode(byte) {
+            Some(c) => self.print_char(c, actor),
+            // A C1 control that is disabled
+            None if self.encoding == Encoding::Latin1 => {}
+            None => self.print_char(std::char::REPLACEMENT_CHARACTER, actor),
+        }
+        true
+    }
+
     /// Parse a single byte.  This may result in a call to one of the
     /// methods on the provided `actor`.
     #[inline(always)]
@@ -553,6 +686,10 @@ impl VTParser {
             return;
         }
 
+        if byte >= 0x80 && self.decode_high_byte(byte, actor) {
+            return;
+        }
+
         let (action, state) = lookup(self.state, byte);
 
         if state != self.state {
@@ -880,4 +1017,182 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn c1_controls() {
+        let mut parser = VTParser::new();
+        parser.set_c1_controls(false);
+        let mut actor = CollectingVTActor::default();
+        parser.parse(b"\x9b1m\x1b[1m", &mut actor);
+        assert_eq!(
+            actor.into_vec(),
+            vec![
+                VTAction::Print(std::char::REPLACEMENT_CHARACTER),
+                VTAction::Print('1'),
+                VTAction::Print('m'),
+                VTAction::CsiDispatch {
+                    params: vec![1],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                    byte: b'm',
+                },
+            ]
+        );
+
+        // The continuation bytes of a sequence are never controls
+        let mut actor = CollectingVTActor::default();
+        parser.set_c1_controls(true);
+        parser.parse("\u{49b}".as_bytes(), &mut actor);
+        assert_eq!(actor.into_vec(), vec![VTAction::Print('\u{49b}')]);
+    }
+
+    #[test]
+    fn truncated_utf8() {
+        assert_eq!(
+            parse_as_vec(b"\xe2\x82\x1b[m\xff"),
+            vec![
+                VTAction::Print(std::char::REPLACEMENT_CHARACTER),
+                VTAction::CsiDispatch {
+                    params: vec![],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                    byte: b'm',
+                },
+                VTAction::Print(std::char::REPLACEMENT_CHARACTER),
+            ]
+        );
+    }
+
+    #[test]
+    fn latin1() {
+        let docs = |byte| VTAction::EscDispatch {
+            params: vec![],
+            intermediates: vec![b'%'],
+            ignored_excess_intermediates: false,
+            byte,
+        };
+        let mut parser = VTParser::new();
+        let mut actor = CollectingVTActor::default();
+        parser.parse(b"\x1b%@\xe9\x1b]0;\xe9\x07\x9b1m", &mut actor);
+        assert_eq!(parser.encoding(), Encoding::Latin1);
+        parser.parse(b"\x1b%G\xc3\xa9", &mut actor);
+        assert_eq!(parser.encoding(), Encoding::Utf8);
+        assert_eq!(
+            actor.into_vec(),
+            vec![
+                docs(b'@'),
+                VTAction::Print('é'),
+                VTAction::OscDispatch(vec![b"0".to_vec(), "é".as_bytes().to_vec()]),
+                VTAction::CsiDispatch {
+                    params: vec![1],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                    byte: b'm',
+                },
+                docs(b'G'),
+                VTAction::Print('é'),
+            ]
+        );
+    }
+
+    #[test]
+    fn cp437() {
+        let mut parser = VTParser::new();
+        parser.set_encoding(Encoding::Cp437);
+        let mut actor = CollectingVTActor::default();
+        // A box, a C0 control, a byte that would otherwise be a C1
+        // control, and UTF-8 that is decoded byte by byte
+        parser.parse(b"\xc9\xcd\xbb\x07\x9b\xc3\xa9", &mut actor);
+        assert_eq!(
+            actor.into_vec(),
+            vec![
+                VTAction::Print('╔'),
+                VTAction::Print('═'),
+                VTAction::Print('╗'),
+                VTAction::ExecuteC0orC1(0x07),
+                VTAction::Print('¢'),
+                VTAction::Print('├'),
+                VTAction::Print('⌐'),
+            ]
+        );
+    }
+
+    #[test]
+    fn encode() {
+        for &encoding in &[Encoding::Latin1, Encoding::Cp437] {
+            for byte in 0u8..=0xff {
+                if let Some(c) = encoding.decode(byte) {
+                    let mut buf = [0u8; 4];
+                    assert_eq!(&*encoding.encode(c.encode_utf8(&mut buf)), &[byte]);
+                }
+            }
+        }
+        assert_eq!(&*Encoding::Cp437.encode("é ╬ €"), b"\x82 \xce ?");
+        assert_eq!(&*Encoding::Latin1.encode("é ╬"), b"\xe9 ?");
+        assert_eq!(&*Encoding::Utf8.encode("é"), "é".as_bytes());
+    }
+
+    /// Feeds streams of random fragments of valid and invalid sequences
+    /// through the parser in each of its modes, checking that it doesn't
+    /// panic and that text in between the fragments still gets through
+    #[test]
+    fn fuzz() {
+        const FRAGMENTS: &[&[u8]] = &[
+            b"\x1b",
+            b"\x1b[",
+            b"\x1b]",
+            b"\x1bP",
+            b"\x1b%@",
+            b"\x1b%G",
+            b"\x07",
+            b";",
+            b"1",
+            b"\x9b",
+            b"\x9c",
+            b"\x9d",
+            b"\x90",
+            b"\x98",
+            b"\xc2",
+            b"\xe2\x82",
+            b"\xf0\x9f\x98",
+            b"\x80",
+            b"\xbf",
+            b"\xff",
+            "\u{9b}".as_bytes(),
+            "\u{20ac}".as_bytes(),
+            "\u{1f600}".as_bytes(),
+        ];
+        // xorshift, which is good enough to pick fragments with
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for &encoding in &[Encoding::Utf8, Encoding::Latin1, Encoding::Cp437] {
+            for &c1_controls in &[true, false] {
+                for _ in 0..200 {
+                    let mut parser = VTParser::new();
+                    parser.set_encoding(encoding);
+                    parser.set_c1_controls(c1_controls);
+                    let mut actor = CollectingVTActor::default();
+                    for _ in 0..64 {
+                        let idx = next() as usize % FRAGMENTS.len();
+                        parser.parse(FRAGMENTS[idx], &mut actor);
+                    }
+                    // Whatever came before, CAN and ST return the parser
+                    // to the ground state, where text is printed
+                    parser.parse(b"\x18\x1b\\", &mut actor);
+                    parser.set_encoding(encoding);
+                    let mut actor = CollectingVTActor::default();
+                    parser.parse(b"ok", &mut actor);
+                    assert_eq!(
+                        actor.into_vec(),
+                        vec![VTAction::Print('o'), VTAction::Print('k')]
+                    );
+                }
+            }
+        }
+    }
 }
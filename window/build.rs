@@ -1,6 +1,21 @@
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
 
+    // wp_fractional_scale_v1 is newer than the protocols that are
+    // bundled with wayland-protocols, so we generate its bindings
+    #[cfg(feature = "wayland")]
+    {
+        use std::path::PathBuf;
+        let protocol = "protocols/fractional-scale-v1.xml";
+        println!("cargo:rerun-if-changed={}", protocol);
+        let dest = PathBuf::from(&std::env::var("OUT_DIR").unwrap());
+        wayland_scanner::generate_code(
+            protocol,
+            dest.join("fractional_scale_v1.rs"),
+            wayland_scanner::Side::Client,
+        );
+    }
+
     #[cfg(feature = "opengl")]
     {
         use gl_generator::{Api, Fallbacks, Profile, Registry};
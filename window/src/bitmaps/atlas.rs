@@ -1,7 +1,10 @@
-use crate::bitmaps::{BitmapImage, Texture2d, TextureRect};
-use crate::{Point, Rect, Size};
+use crate::bitmaps::{BitmapImage, Image, Texture2d, TextureRect};
+use crate::{Operator, Point, Rect, Size};
 use anyhow::{ensure, Result as Fallible};
+use std::collections::HashMap;
+use std::ops::Range;
 use std::rc::Rc;
+use std::time::Instant;
 use thiserror::*;
 
 pub const TEX_SIZE: u32 = 4096;
@@ -13,11 +16,60 @@ pub struct OutOfTextureSpace {
     pub size: usize,
 }
 
+/// Shelf heights are rounded up to a multiple of this value, so that
+/// sprites of similar height share a shelf.
+const SHELF_QUANTUM: usize = 4;
+
+/// A horizontal band of the atlas that holds sprites of up to
+/// `height` pixels tall.
+struct Shelf {
+    y: usize,
+    height: usize,
+    /// The unused spans of this shelf, sorted by x and coalesced
+    free: Vec<Range<usize>>,
+}
+
+impl Shelf {
+    fn is_empty(&self, side: usize) -> bool {
+        self.free.len() == 1 && self.free[0] == (0..side)
+    }
+
+    fn release(&mut self, span: Range<usize>) {
+        let idx = self
+            .free
+            .iter()
+            .position(|f| f.start > span.start)
+            .unwrap_or_else(|| self.free.len());
+        self.free.insert(idx, span);
+
+        // Coalesce with the neighbors
+        if idx + 1 < self.free.len() && self.free[idx].end == self.free[idx + 1].start {
+            self.free[idx].end = self.free[idx + 1].end;
+            self.free.remove(idx + 1);
+        }
+        if idx > 0 && self.free[idx - 1].end == self.free[idx].start {
+            self.free[idx - 1].end = self.free[idx].end;
+            self.free.remove(idx);
+        }
+    }
+}
+
+/// Describes a sprite that is allocated from an atlas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Allocation {
+    /// The coordinates of the sprite, excluding its padding
+    pub coords: Rect,
+    /// When the sprite was allocated
+    pub allocated: Instant,
+}
+
 /// Atlases are bitmaps of srgba data that are sized as a power of 2.
-/// We allocate sprites out of the available space, starting from the
-/// bottom left corner and working to the right until we run out of
-/// space, then we move up to the logical row above.  Since sprites can
-/// have varying height the height of the rows can also vary.
+/// Space is divided into horizontal shelves whose heights are rounded
+/// up to a multiple of `SHELF_QUANTUM`.  A sprite is placed on the
+/// best fitting shelf of its height class, and a new shelf is opened
+/// above the others when there is none with room.  Once there is no
+/// room for new shelves, the best fitting shelf of any height is used.
+/// Sprites can be released, which returns their space to their shelf.
 pub struct Atlas<T>
 where
     T: Texture2d,
@@ -27,14 +79,16 @@ where
     /// Dimensions of the texture
     side: usize,
 
-    /// The bottom of the available space.
-    bottom: usize,
+    shelves: Vec<Shelf>,
+
+    /// The top of the space used by shelves
+    next_y: usize,
 
-    /// The height of the tallest sprite allocated on the current row
-    tallest: usize,
+    /// The number of pixels reserved by allocated sprites
+    used: usize,
 
-    /// How far along the current row we've progressed
-    left: usize,
+    /// The allocated sprites, keyed by the origin of their coords
+    allocations: HashMap<(isize, isize), Allocation>,
 }
 
 impl<T> Atlas<T>
@@ -49,9 +103,10 @@ where
         Ok(Self {
             texture: Rc::clone(texture),
             side: texture.width(),
-            bottom: 0,
-            tallest: 0,
-            left: 0,
+            shelves: vec![],
+            next_y: 0,
+            used: 0,
+            allocations: HashMap::new(),
         })
     }
 
@@ -60,6 +115,29 @@ where
         Rc::clone(&self.texture)
     }
 
+    /// Finds the shelf and free span with the least wasted space that
+    /// can hold a reservation of the given size.  Returns the indices
+    /// of the shelf and span along with the vertical waste.
+    fn best_fit(&self, width: usize, height: usize) -> Option<(usize, usize, usize)> {
+        let mut best: Option<((usize, usize), usize, usize)> = None;
+        for (shelf_idx, shelf) in self.shelves.iter().enumerate() {
+            if shelf.height < height {
+                continue;
+            }
+            for (span_idx, span) in shelf.free.iter().enumerate() {
+                let span_width = span.end - span.start;
+                if span_width < width {
+                    continue;
+                }
+                let key = (shelf.height - height, span_width);
+                if best.as_ref().map(|(k, _, _)| key < *k).unwrap_or(true) {
+                    best = Some((key, shelf_idx, span_idx));
+                }
+            }
+        }
+        best.map(|((waste, _), shelf_idx, span_idx)| (shelf_idx, span_idx, waste))
+    }
+
     /// Reserve space for a sprite of the given size
     pub fn allocate(&mut self, im: &dyn BitmapImage) -> Result<Sprite<T>, OutOfTextureSpace> {
         let (width, height) = im.image_dimensions();
@@ -76,35 +154,71 @@ where
                 size: reserve_width.max(reserve_height).next_power_of_two(),
             });
         }
-        let x_left = self.side - self.left;
-        if x_left < reserve_width {
-            // Bump up to next row
-            self.bottom += self.tallest;
-            self.left = 0;
-            self.tallest = 0;
-        }
 
-        // Do we have vertical space?
-        let y_left = self.side - self.bottom;
-        if y_left < reserve_height {
-            // No room at the inn.
-            return Err(OutOfTextureSpace {
-                size: (self.side + reserve_width.max(reserve_height)).next_power_of_two(),
-            });
+        let shelf_height =
+            (((reserve_height + SHELF_QUANTUM - 1) / SHELF_QUANTUM) * SHELF_QUANTUM).min(self.side);
+
+        let (shelf_idx, span_idx) = match self.best_fit(reserve_width, reserve_height) {
+            Some((shelf_idx, span_idx, waste)) if waste < SHELF_QUANTUM => (shelf_idx, span_idx),
+            best => {
+                if self.next_y + shelf_height <= self.side {
+                    self.shelves.push(Shelf {
+                        y: self.next_y,
+                        height: shelf_height,
+                        free: vec![0..self.side],
+                    });
+                    self.next_y += shelf_height;
+                    (self.shelves.len() - 1, 0)
+                } else if let Some((shelf_idx, span_idx, _)) = best {
+                    (shelf_idx, span_idx)
+                } else {
+                    // No room at the inn.
+                    return Err(OutOfTextureSpace {
+                        size: (self.side + reserve_width.max(reserve_height)).next_power_of_two(),
+                    });
+                }
+            }
+        };
+
+        let shelf = &mut self.shelves[shelf_idx];
+        let left = shelf.free[span_idx].start;
+        shelf.free[span_idx].start += reserve_width;
+        if shelf.free[span_idx].start == shelf.free[span_idx].end {
+            shelf.free.remove(span_idx);
         }
 
         let rect = Rect::new(
-            Point::new(
-                (self.left + PADDING) as isize,
-                (self.bottom + PADDING) as isize,
-            ),
+            Point::new((left + PADDING) as isize, (shelf.y + PADDING) as isize),
             Size::new(width as isize, height as isize),
         );
 
-        self.texture.write(rect, im);
-
-        self.left += reserve_width;
-        self.tallest = self.tallest.max(reserve_height);
+        // Write the image surrounded by a transparent border, so that
+        // sampling at the edges of the sprite when the texture is scaled
+        // cannot pick up pixels from a neighboring sprite, or stale pixels
+        // from a sprite that used to be here.
+        let mut padded = Image::new(reserve_width, reserve_height);
+        padded.draw_image(
+            Point::new(PADDING as isize, PADDING as isize),
+            None,
+            im,
+            Operator::Source,
+        );
+        self.texture.write(
+            Rect::new(
+                Point::new(left as isize, shelf.y as isize),
+                Size::new(reserve_width as isize, reserve_height as isize),
+            ),
+            &padded,
+        );
+        self.used += reserve_width * reserve_height;
+        self.allocations.insert(
+            (rect.origin.x, rect.origin.y),
+            Allocation {
+                coords: rect,
+                allocated: Instant::now(),
+            },
+        );
+        self.record_fragmentation();
 
         Ok(Sprite {
             texture: Rc::clone(&self.texture),
@@ -112,9 +226,69 @@ where
         })
     }
 
+    /// Returns the space used by a sprite to the atlas so that it
+    /// can be used by subsequent allocations.  The sprite must have
+    /// been allocated from this atlas and must no longer be used.
+    pub fn release(&mut self, sprite: &Sprite<T>) {
+        let left = sprite.coords.origin.x as usize - PADDING;
+        let y = sprite.coords.origin.y as usize - PADDING;
+        let reserve_width = sprite.coords.size.width as usize + PADDING * 2;
+        let reserve_height = sprite.coords.size.height as usize + PADDING * 2;
+
+        let shelf = match self.shelves.iter_mut().find(|shelf| shelf.y == y) {
+            Some(shelf) => shelf,
+            None => return,
+        };
+        shelf.release(left..left + reserve_width);
+        self.used = self.used.saturating_sub(reserve_width * reserve_height);
+        self.allocations
+            .remove(&(sprite.coords.origin.x, sprite.coords.origin.y));
+
+        // Reclaim empty shelves at the top so that their space can be
+        // used for shelves of a different height
+        while let Some(shelf) = self.shelves.last() {
+            if !shelf.is_empty(self.side) {
+                break;
+            }
+            self.next_y = shelf.y;
+            self.shelves.pop();
+        }
+        self.record_fragmentation();
+    }
+
+    /// Returns the fraction of the space occupied by shelves that
+    /// is not used by sprites.  This increases as sprites of mixed
+    /// heights are packed together, and as sprites are released.
+    pub fn fragmentation(&self) -> f64 {
+        let shelf_area = self.side * self.next_y;
+        if shelf_area == 0 {
+            0.0
+        } else {
+            1.0 - (self.used as f64 / shelf_area as f64)
+        }
+    }
+
+    /// Returns the fraction of the whole atlas that is used by sprites
+    pub fn utilization(&self) -> f64 {
+        self.used as f64 / (self.side * self.side) as f64
+    }
+
+    fn record_fragmentation(&self) {
+        metrics::value!(
+            "atlas.fragmentation.pct",
+            (self.fragmentation() * 100.) as u64
+        );
+    }
+
     pub fn size(&self) -> usize {
         self.side
     }
+
+    /// Returns the sprites that are currently allocated, in no
+    /// particular order
+    pub fn allocations(&self) -> impl Iterator<Item = &Allocation> {
+        self.allocations.values()
+    }
 }
 
 pub struct Sprite<T>
@@ -233,3 +407,185 @@ impl SpriteSlice {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bitmaps::ImageTexture;
+    use crate::color::Color;
+
+    /// A minimal xorshift generator so that the test is deterministic
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn range(&mut self, lo: usize, hi: usize) -> usize {
+            lo + (self.next() % (hi - lo + 1) as u64) as usize
+        }
+    }
+
+    /// Produces a mix of glyph sizes resembling a terminal session:
+    /// mostly latin text of varying heights, with some CJK and emoji
+    fn glyph_sizes() -> impl Iterator<Item = (usize, usize)> {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        std::iter::from_fn(move || {
+            let kind = rng.next() % 100;
+            Some(if kind < 75 {
+                (rng.range(5, 9), rng.range(8, 15))
+            } else if kind < 90 {
+                (rng.range(14, 16), rng.range(14, 16))
+            } else {
+                (rng.range(18, 22), rng.range(18, 22))
+            })
+        })
+    }
+
+    /// The row allocator that was used prior to the shelf allocator,
+    /// used as a baseline for comparison
+    struct RowAllocator {
+        side: usize,
+        bottom: usize,
+        tallest: usize,
+        left: usize,
+        used: usize,
+    }
+
+    impl RowAllocator {
+        fn allocate(&mut self, width: usize, height: usize) -> bool {
+            let reserve_width = width + PADDING * 2;
+            let reserve_height = height + PADDING * 2;
+            if self.side - self.left < reserve_width {
+                self.bottom += self.tallest;
+                self.left = 0;
+                self.tallest = 0;
+            }
+            if self.side - self.bottom < reserve_height {
+                return false;
+            }
+            self.left += reserve_width;
+            self.tallest = self.tallest.max(reserve_height);
+            self.used += reserve_width * reserve_height;
+            true
+        }
+    }
+
+    #[test]
+    fn shelf_utilization() {
+        let side = 512;
+
+        let mut rows = RowAllocator {
+            side,
+            bottom: 0,
+            tallest: 0,
+            left: 0,
+            used: 0,
+        };
+        for (width, height) in glyph_sizes() {
+            if !rows.allocate(width, height) {
+                break;
+            }
+        }
+        let row_utilization = rows.used as f64 / (side * side) as f64;
+
+        let texture = Rc::new(ImageTexture::new(side, side));
+        let mut atlas = Atlas::new(&texture).unwrap();
+        for (width, height) in glyph_sizes() {
+            if atlas.allocate(&Image::new(width, height)).is_err() {
+                break;
+            }
+        }
+
+        assert!(
+            atlas.utilization() >= row_utilization * 1.2,
+            "shelf utilization {} should be 20% better than row utilization {}",
+            atlas.utilization(),
+            row_utilization
+        );
+        assert!(atlas.fragmentation() <= 1.0 - atlas.utilization());
+    }
+
+    #[test]
+    fn release_and_reuse() {
+        let texture = Rc::new(ImageTexture::new(64, 64));
+        let mut atlas = Atlas::new(&texture).unwrap();
+
+        let a = atlas.allocate(&Image::new(10, 10)).unwrap();
+        let b = atlas.allocate(&Image::new(10, 10)).unwrap();
+        let c = atlas.allocate(&Image::new(10, 10)).unwrap();
+        assert_eq!(a.coords.origin.y, c.coords.origin.y);
+
+        // The space from a released sprite is used again
+        atlas.release(&b);
+        let d = atlas.allocate(&Image::new(10, 10)).unwrap();
+        assert_eq!(b.coords, d.coords);
+
+        // Releasing everything returns the atlas to its initial state
+        atlas.release(&a);
+        atlas.release(&c);
+        atlas.release(&d);
+        assert_eq!(atlas.used, 0);
+        assert_eq!(atlas.next_y, 0);
+        let tall = atlas.allocate(&Image::new(62, 62)).unwrap();
+        assert_eq!(tall.coords.origin, Point::new(1, 1));
+    }
+
+    #[test]
+    fn allocations_are_tracked() {
+        let texture = Rc::new(ImageTexture::new(64, 64));
+        let mut atlas = Atlas::new(&texture).unwrap();
+
+        let a = atlas.allocate(&Image::new(10, 10)).unwrap();
+        let b = atlas.allocate(&Image::new(10, 10)).unwrap();
+        let c = atlas.allocate(&Image::new(10, 10)).unwrap();
+        assert_eq!(atlas.allocations().count(), 3);
+
+        // A released sprite is no longer listed, and the sprite that
+        // reuses its space is
+        atlas.release(&b);
+        assert!(!atlas.allocations().any(|alloc| alloc.coords == b.coords));
+        let d = atlas.allocate(&Image::new(10, 10)).unwrap();
+        assert!(atlas.allocations().any(|alloc| alloc.coords == d.coords));
+        assert_eq!(atlas.allocations().count(), 3);
+
+        atlas.release(&a);
+        atlas.release(&c);
+        atlas.release(&d);
+        assert_eq!(atlas.allocations().count(), 0);
+    }
+
+    #[test]
+    fn sprites_have_transparent_border() {
+        let texture = Rc::new(ImageTexture::new(16, 16));
+        // Simulate stale data left in the texture
+        texture
+            .image
+            .borrow_mut()
+            .clear(Color::rgb(0xff, 0xff, 0xff));
+        let mut atlas = Atlas::new(&texture).unwrap();
+
+        let mut im = Image::new(4, 4);
+        im.clear(Color::rgb(0xff, 0, 0));
+        let sprite = atlas.allocate(&im).unwrap();
+        assert_eq!(sprite.coords, Rect::new(Point::new(1, 1), Size::new(4, 4)));
+
+        let image = texture.image.borrow();
+        let pixels = image.pixels();
+        for y in 0..6 {
+            for x in 0..6 {
+                let inside = (1..5).contains(&x) && (1..5).contains(&y);
+                let pixel = pixels[y * 16 + x];
+                if inside {
+                    assert_ne!(pixel, 0, "sprite pixel {},{}", x, y);
+                } else {
+                    assert_eq!(pixel, 0, "border pixel {},{}", x, y);
+                }
+            }
+        }
+    }
+}
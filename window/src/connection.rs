@@ -37,6 +37,21 @@ pub trait ConnectionOps {
     /// focus away from it.
     fn hide_application(&self) {}
 
+    /// Sounds the system bell, at `volume` from 0.0 to 1.0 where the
+    /// system supports that.  Returns false if the system has no bell,
+    /// so that the caller can alert the user in some other way.
+    fn beep(&self, _volume: f32) -> bool {
+        false
+    }
+
+    /// Returns true if the system has a primary selection that is
+    /// separate from the clipboard.  Where it doesn't, setting the
+    /// primary selection has no effect and getting it returns the
+    /// contents of the clipboard.
+    fn has_primary_selection(&self) -> bool {
+        false
+    }
+
     // TODO: return a handle that can be used to cancel the timer
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F);
 }
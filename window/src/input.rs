@@ -83,6 +83,24 @@ pub enum KeyCode {
     ApplicationDownArrow,
 }
 
+impl KeyCode {
+    /// Returns the modifier that this key toggles, if it is a
+    /// modifier key
+    pub fn modifier(&self) -> Option<Modifiers> {
+        match self {
+            Self::Shift | Self::LeftShift | Self::RightShift => Some(Modifiers::SHIFT),
+            Self::Control | Self::LeftControl | Self::RightControl => Some(Modifiers::CTRL),
+            Self::Alt | Self::Meta => Some(Modifiers::ALT),
+            Self::LeftAlt => Some(Modifiers::ALT | Modifiers::LEFT_ALT),
+            Self::RightAlt => Some(Modifiers::ALT | Modifiers::RIGHT_ALT),
+            Self::Super | Self::Hyper | Self::LeftWindows | Self::RightWindows => {
+                Some(Modifiers::SUPER)
+            }
+            _ => None,
+        }
+    }
+}
+
 bitflags! {
     #[derive(Default)]
     pub struct Modifiers: u8 {
@@ -115,16 +133,23 @@ pub enum MousePress {
     Middle,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum MouseEventKind {
     Move,
     Press(MousePress),
     Release(MousePress),
+    /// A number of discrete wheel ticks; positive values are up
     VertWheel(i16),
+    /// A number of discrete wheel ticks; positive values are left
     HorzWheel(i16),
+    /// A pixel-precise scroll delta, such as those produced by a
+    /// trackpad; positive values are up
+    VertPixelWheel(f64),
+    /// A pixel-precise scroll delta; positive values are left
+    HorzPixelWheel(f64),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MouseEvent {
     pub kind: MouseEventKind,
     /// Coordinates of the mouse relative to the top left of the window
@@ -108,6 +108,23 @@ pub enum MouseCursor {
     Arrow,
     Hand,
     Text,
+    /// For resizing something vertically, such as a horizontal divider
+    SizeUpDown,
+    /// For resizing something horizontally, such as a vertical divider
+    SizeLeftRight,
+}
+
+/// The progress of a long running operation associated with a window.
+/// Where the system supports it, this is reflected in the taskbar
+/// entry or dock icon for the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskbarProgress {
+    None,
+    /// Percentage complete, 0-100
+    Normal(u8),
+    Error(u8),
+    Indeterminate,
+    Paused(u8),
 }
 
 #[allow(unused_variables)]
@@ -169,6 +186,18 @@ pub trait WindowCallbacks: Any {
         false
     }
 
+    /// Called when the input method changes the text that it is
+    /// composing, known as the preedit, on systems where the embedding
+    /// app is responsible for showing it.  `None` means that nothing is
+    /// being composed.  The composed text is delivered as a key event
+    /// with `KeyCode::Composed`.
+    fn ime_preedit(&mut self, preedit: Option<String>) {}
+
+    /// Called when files are dragged from another application and
+    /// dropped onto the window.  `paths` are the local paths of the
+    /// files, in the order that the other application listed them.
+    fn dropped_files(&mut self, paths: Vec<std::path::PathBuf>) {}
+
     fn mouse_event(&mut self, event: &MouseEvent, context: &dyn WindowOps) {
         context.set_cursor(Some(MouseCursor::Arrow));
     }
@@ -192,6 +221,12 @@ pub trait WindowOps {
     /// Hide a visible window
     fn hide(&self) -> Future<()>;
 
+    /// Raise the window and give it the keyboard focus, if the
+    /// system allows an application to do that
+    fn focus(&self) -> Future<()> {
+        Future::ok(())
+    }
+
     /// Schedule the window to be closed
     fn close(&self) -> Future<()>;
 
@@ -243,8 +278,10 @@ pub trait WindowOps {
     /// Initiate textual transfer from the clipboard
     fn get_clipboard(&self, clipboard: Clipboard) -> Future<String>;
 
-    /// Set some text in the clipboard
-    fn set_clipboard(&self, text: String) -> Future<()>;
+    /// Set some text in the clipboard.
+    /// On systems that don't have a primary selection, setting it
+    /// has no effect.
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()>;
 
     /// Set the icon for the window.
     /// Depending on the system this may be shown in its titlebar
@@ -252,6 +289,12 @@ pub trait WindowOps {
     fn set_icon(&self, _image: Image) -> Future<()> {
         Future::ok(())
     }
+
+    /// Show the progress of a long running operation in the
+    /// taskbar/dock, if supported by the system
+    fn set_progress(&self, _progress: TaskbarProgress) -> Future<()> {
+        Future::ok(())
+    }
 }
 
 pub trait WindowOpsMut {
@@ -261,6 +304,10 @@ pub trait WindowOpsMut {
     /// Hide a visible window
     fn hide(&mut self);
 
+    /// Raise the window and give it the keyboard focus, if the
+    /// system allows an application to do that
+    fn focus(&mut self) {}
+
     /// Schedule the window to be closed
     fn close(&mut self);
 
@@ -291,4 +338,8 @@ pub trait WindowOpsMut {
     /// Depending on the system this may be shown in its titlebar
     /// and/or in the task manager/task switcher
     fn set_icon(&mut self, _image: &dyn BitmapImage) {}
+
+    /// Show the progress of a long running operation in the
+    /// taskbar/dock, if supported by the system
+    fn set_progress(&mut self, _progress: TaskbarProgress) {}
 }
@@ -2,18 +2,98 @@
 #![allow(clippy::let_unit_value)]
 
 use super::window::WindowInner;
+use super::{nsstring, nsstring_to_str};
 use crate::connection::ConnectionOps;
 use crate::spawn::*;
 use cocoa::appkit::{NSApp, NSApplication, NSApplicationActivationPolicyRegular};
-use cocoa::base::{id, nil};
+use cocoa::base::{id, nil, BOOL, NO, YES};
 use core_foundation::date::CFAbsoluteTimeGetCurrent;
 use core_foundation::runloop::*;
+use objc::declare::ClassDecl;
+use objc::runtime::{Class, Object, Sel};
 use objc::*;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::atomic::AtomicUsize;
 
+thread_local! {
+    static REOPEN_HANDLER: RefCell<Option<Box<dyn Fn()>>> = RefCell::new(None);
+    static APPEARANCE_HANDLER: RefCell<Option<Box<dyn Fn(bool)>>> = RefCell::new(None);
+}
+
+/// Sets the function that is called when the application is reopened,
+/// such as by clicking its icon in the Dock, while it has no visible
+/// windows.  It is expected to open a window.
+pub fn set_reopen_handler<F: Fn() + 'static>(handler: F) {
+    REOPEN_HANDLER.with(|h| h.borrow_mut().replace(Box::new(handler)));
+}
+
+extern "C" fn application_should_handle_reopen(
+    _this: &mut Object,
+    _sel: Sel,
+    _app: id,
+    has_visible_windows: BOOL,
+) -> BOOL {
+    if has_visible_windows == NO {
+        REOPEN_HANDLER.with(|h| {
+            if let Some(handler) = h.borrow().as_ref() {
+                handler();
+            }
+        });
+    }
+    YES
+}
+
+/// Sets the function that is called when the system appearance
+/// changes; it is passed true if the dark appearance is now used
+pub fn set_appearance_handler<F: Fn(bool) + 'static>(handler: F) {
+    APPEARANCE_HANDLER.with(|h| h.borrow_mut().replace(Box::new(handler)));
+}
+
+/// Returns true if the system uses the dark appearance.
+/// AppleInterfaceStyle is only set while that is the case.
+pub fn is_dark_appearance() -> bool {
+    unsafe {
+        let defaults: id = msg_send![class!(NSUserDefaults), standardUserDefaults];
+        let style: id = msg_send![defaults, stringForKey: *nsstring("AppleInterfaceStyle")];
+        !style.is_null() && nsstring_to_str(style) == "Dark"
+    }
+}
+
+extern "C" fn appearance_changed(_this: &mut Object, _sel: Sel, _notification: id) {
+    let dark = is_dark_appearance();
+    APPEARANCE_HANDLER.with(|h| {
+        if let Some(handler) = h.borrow().as_ref() {
+            handler(dark);
+        }
+    });
+}
+
+/// Returns a new instance of the application delegate, which
+/// handles the application being reopened and observes changes
+/// to the system appearance
+fn app_delegate() -> id {
+    const CLS_NAME: &str = "WezTermAppDelegate";
+    let cls = Class::get(CLS_NAME).unwrap_or_else(|| {
+        let mut cls = ClassDecl::new(CLS_NAME, class!(NSObject))
+            .expect("Unable to register WezTermAppDelegate class");
+        unsafe {
+            cls.add_method(
+                sel!(applicationShouldHandleReopen:hasVisibleWindows:),
+                application_should_handle_reopen
+                    as extern "C" fn(&mut Object, Sel, id, BOOL) -> BOOL,
+            );
+            cls.add_method(
+                sel!(appearanceChanged:),
+                appearance_changed as extern "C" fn(&mut Object, Sel, id),
+            );
+        }
+        cls.register()
+    });
+    unsafe { msg_send![cls, new] }
+}
+
 pub struct Connection {
     ns_app: id,
     pub(crate) windows: RefCell<HashMap<usize, Rc<RefCell<WindowInner>>>>,
@@ -29,6 +109,16 @@ impl Connection {
         unsafe {
             let ns_app = NSApp();
             ns_app.setActivationPolicy_(NSApplicationActivationPolicyRegular);
+            // The application holds a weak reference to its delegate,
+            // which therefore lives for the rest of the process
+            let delegate = app_delegate();
+            let () = msg_send![ns_app, setDelegate: delegate];
+            let center: id = msg_send![class!(NSDistributedNotificationCenter), defaultCenter];
+            let () = msg_send![center,
+                addObserver: delegate
+                selector: sel!(appearanceChanged:)
+                name: *nsstring("AppleInterfaceThemeChangedNotification")
+                object: nil];
             let conn = Self {
                 ns_app,
                 windows: RefCell::new(HashMap::new()),
@@ -91,6 +181,18 @@ impl ConnectionOps for Connection {
         }
     }
 
+    /// NSBeep plays the alert sound chosen in the system preferences,
+    /// at the alert volume that is also set there
+    fn beep(&self, _volume: f32) -> bool {
+        extern "C" {
+            fn NSBeep();
+        }
+        unsafe {
+            NSBeep();
+        }
+        true
+    }
+
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F) {
         let secs_f64 =
             (interval.as_secs() as f64) + (f64::from(interval.subsec_nanos()) / 1_000_000_000_f64);
@@ -8,13 +8,13 @@ use crate::os::macos::bitmap::BitmapRef;
 use crate::{
     BitmapImage, Clipboard, Color, Connection, Dimensions, KeyCode, KeyEvent, Modifiers,
     MouseButtons, MouseCursor, MouseEvent, MouseEventKind, MousePress, Operator, PaintContext,
-    Point, Rect, ScreenPoint, Size, WindowCallbacks, WindowOps, WindowOpsMut,
+    Point, Rect, ScreenPoint, Size, TaskbarProgress, WindowCallbacks, WindowOps, WindowOpsMut,
 };
 use anyhow::{anyhow, bail, ensure};
 use cocoa::appkit::{
-    NSApplicationActivateIgnoringOtherApps, NSBackingStoreBuffered, NSEvent, NSEventModifierFlags,
-    NSRunningApplication, NSScreen, NSView, NSViewHeightSizable, NSViewWidthSizable, NSWindow,
-    NSWindowStyleMask,
+    NSApp, NSApplicationActivateIgnoringOtherApps, NSBackingStoreBuffered, NSEvent,
+    NSEventModifierFlags, NSRunningApplication, NSScreen, NSView, NSViewHeightSizable,
+    NSViewWidthSizable, NSWindow, NSWindowStyleMask,
 };
 use cocoa::base::*;
 use cocoa::foundation::{NSArray, NSNotFound, NSPoint, NSRect, NSSize, NSUInteger};
@@ -27,6 +27,7 @@ use promise::Future;
 use std::any::Any;
 use std::cell::RefCell;
 use std::ffi::c_void;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -36,6 +37,25 @@ pub fn use_ime(enable: bool) {
     USE_IME.store(enable, Ordering::Relaxed);
 }
 
+static LEFT_OPTION_COMPOSES: AtomicBool = AtomicBool::new(false);
+static RIGHT_OPTION_COMPOSES: AtomicBool = AtomicBool::new(true);
+
+/// Sets whether the left and right Option keys compose characters,
+/// or can act as the Alt modifier.  This decides whether a dead key
+/// pressed with Option is composed by the system or sent as Alt.
+pub fn set_option_composes(left: bool, right: bool) {
+    LEFT_OPTION_COMPOSES.store(left, Ordering::Relaxed);
+    RIGHT_OPTION_COMPOSES.store(right, Ordering::Relaxed);
+}
+
+/// Returns true if Option is held and composes for the side(s)
+/// that are held
+fn option_composes(modifiers: Modifiers) -> bool {
+    (modifiers.contains(Modifiers::LEFT_ALT) && LEFT_OPTION_COMPOSES.load(Ordering::Relaxed))
+        || (modifiers.contains(Modifiers::RIGHT_ALT)
+            && RIGHT_OPTION_COMPOSES.load(Ordering::Relaxed))
+}
+
 fn round_away_from_zero(value: f64) -> i16 {
     if value > 0. {
         value.max(1.).round() as i16
@@ -301,6 +321,7 @@ impl Window {
                 #[cfg(feature = "opengl")]
                 gl_context_pair: None,
                 text_cursor_position: Rect::new(Point::new(0, 0), Size::new(0, 0)),
+                dead_key_pending: false,
             }));
 
             let window = StrongPtr::new(
@@ -326,6 +347,9 @@ impl Window {
             window.setContentView_(*view);
             window.setDelegate_(*view);
 
+            let dragged_types = NSArray::arrayWithObjects(nil, &[*nsstring(FILENAMES_PBOARD_TYPE)]);
+            let () = msg_send![*view, registerForDraggedTypes: dragged_types];
+
             let frame = NSView::frame(*view);
             let backing_frame = NSView::convertRectToBacking(*view, frame);
             let width = backing_frame.size.width;
@@ -379,6 +403,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn focus(&self) -> Future<()> {
+        Connection::with_window_inner(self.0, |inner| {
+            inner.focus();
+            Ok(())
+        })
+    }
+
     fn set_cursor(&self, cursor: Option<MouseCursor>) -> Future<()> {
         Connection::with_window_inner(self.0, move |inner| {
             let _ = inner.set_cursor(cursor);
@@ -422,6 +453,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn set_progress(&self, progress: TaskbarProgress) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_progress(progress);
+            Ok(())
+        })
+    }
+
     fn apply<R, F: Send + 'static + FnMut(&mut dyn Any, &dyn WindowOps) -> anyhow::Result<R>>(
         &self,
         mut func: F,
@@ -461,8 +499,11 @@ impl WindowOps for Window {
         )
     }
 
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         use clipboard::ClipboardProvider;
+        if clipboard == Clipboard::PrimarySelection {
+            return Future::ok(());
+        }
         Future::result(
             clipboard::ClipboardContext::new()
                 .and_then(|mut ctx| ctx.set_contents(text))
@@ -512,6 +553,11 @@ impl WindowOpsMut for WindowInner {
         }
     }
 
+    fn focus(&mut self) {
+        // Showing the window already brings it to the front
+        self.show();
+    }
+
     fn close(&mut self) {
         unsafe {
             self.window.close();
@@ -535,6 +581,10 @@ impl WindowOpsMut for WindowInner {
                     MouseCursor::Arrow => msg_send![ns_cursor_cls, arrowCursor],
                     MouseCursor::Text => msg_send![ns_cursor_cls, IBeamCursor],
                     MouseCursor::Hand => msg_send![ns_cursor_cls, pointingHandCursor],
+                    MouseCursor::SizeUpDown => msg_send![ns_cursor_cls, resizeUpDownCursor],
+                    MouseCursor::SizeLeftRight => {
+                        msg_send![ns_cursor_cls, resizeLeftRightCursor]
+                    }
                 };
                 let () = msg_send![instance, set];
             }
@@ -592,6 +642,32 @@ impl WindowOpsMut for WindowInner {
             }
         }
     }
+
+    fn set_progress(&mut self, progress: TaskbarProgress) {
+        // The dock tile belongs to the application rather than the
+        // window, so the most recently reported progress wins.
+        // We show it as a badge on the dock icon.
+        let label = match progress {
+            TaskbarProgress::None => None,
+            TaskbarProgress::Normal(pct) | TaskbarProgress::Paused(pct) => {
+                Some(format!("{}%", pct))
+            }
+            TaskbarProgress::Error(_) => Some("!".to_string()),
+            TaskbarProgress::Indeterminate => Some("…".to_string()),
+        };
+        unsafe {
+            let dock_tile: id = msg_send![NSApp(), dockTile];
+            match label {
+                Some(label) => {
+                    let label = nsstring(&label);
+                    let () = msg_send![dock_tile, setBadgeLabel: *label];
+                }
+                None => {
+                    let () = msg_send![dock_tile, setBadgeLabel: nil];
+                }
+            }
+        }
+    }
 }
 
 struct Inner {
@@ -601,6 +677,9 @@ struct Inner {
     #[cfg(feature = "opengl")]
     gl_context_pair: Option<opengl::GlContextPair>,
     text_cursor_position: Rect,
+    /// Set when a dead key was handed to the input context, so that
+    /// the key that follows it is also composed there
+    dead_key_pending: bool,
 }
 
 impl Inner {
@@ -619,6 +698,10 @@ impl Inner {
 }
 
 const CLS_NAME: &str = "WezTermWindowView";
+/// The value of NSFilenamesPboardType, under which the paths of the
+/// files that are dragged from the Finder are found
+const FILENAMES_PBOARD_TYPE: &str = "NSFilenamesPboardType";
+const NS_DRAG_OPERATION_COPY: NSUInteger = 1;
 
 struct WindowView {
     inner: Rc<RefCell<Inner>>,
@@ -952,6 +1035,31 @@ impl WindowView {
         YES
     }
 
+    extern "C" fn dragging_entered(_this: &mut Object, _sel: Sel, _sender: id) -> NSUInteger {
+        NS_DRAG_OPERATION_COPY
+    }
+
+    extern "C" fn perform_drag_operation(this: &mut Object, _sel: Sel, sender: id) -> BOOL {
+        let paths: Vec<PathBuf> = unsafe {
+            let pasteboard: id = msg_send![sender, draggingPasteboard];
+            let filenames: id =
+                msg_send![pasteboard, propertyListForType: *nsstring(FILENAMES_PBOARD_TYPE)];
+            if filenames.is_null() {
+                return NO;
+            }
+            (0..filenames.count())
+                .map(|idx| PathBuf::from(nsstring_to_str(filenames.objectAtIndex(idx))))
+                .collect()
+        };
+        if paths.is_empty() {
+            return NO;
+        }
+        if let Some(this) = Self::get_this(this) {
+            this.inner.borrow_mut().callbacks.dropped_files(paths);
+        }
+        YES
+    }
+
     extern "C" fn window_will_close(this: &mut Object, _sel: Sel, _id: id) {
         if let Some(this) = Self::get_this(this) {
             // Advise the window of its impending death
@@ -1006,7 +1114,20 @@ impl WindowView {
     extern "C" fn scroll_wheel(this: &mut Object, _sel: Sel, nsevent: id) {
         let vert_delta = unsafe { nsevent.scrollingDeltaY() };
         let horz_delta = unsafe { nsevent.scrollingDeltaX() };
-        let kind = if vert_delta.abs() > horz_delta.abs() {
+        let precise = unsafe { nsevent.hasPreciseScrollingDeltas() } == YES;
+        let kind = if precise {
+            // Trackpads and magic mice report deltas in points;
+            // convert them to pixels
+            let view = this as id;
+            let unit = NSRect::new(NSPoint::new(0., 0.), NSSize::new(1., 1.));
+            let backing = unsafe { NSView::convertRectToBacking(view, unit) };
+            let scale = backing.size.height;
+            if vert_delta.abs() > horz_delta.abs() {
+                MouseEventKind::VertPixelWheel(vert_delta * scale)
+            } else {
+                MouseEventKind::HorzPixelWheel(horz_delta * scale)
+            }
+        } else if vert_delta.abs() > horz_delta.abs() {
             MouseEventKind::VertWheel(round_away_from_zero(vert_delta))
         } else {
             MouseEventKind::HorzWheel(round_away_from_zero(horz_delta))
@@ -1066,7 +1187,37 @@ impl WindowView {
             modifiers
         };
 
-        if USE_IME.load(Ordering::Relaxed) && modifiers.is_empty() {
+        // A dead key, such as Option-e on a US keymap, produces no chars.
+        // When Option acts as Alt we want to send Alt with the unmodified
+        // key instead, eg: ESC e.
+        let chars = if chars.is_empty() && modifiers.contains(Modifiers::ALT) {
+            if option_composes(modifiers) {
+                chars
+            } else {
+                unmod
+            }
+        } else {
+            chars
+        };
+
+        // Otherwise the dead key and the key that follows it are given
+        // to the input context, which composes them and passes the result
+        // to insert_text_replacement_range, even when the IME is disabled.
+        let dead_key = key_is_down && chars.is_empty() && !unmod.is_empty();
+        let dead_key_pending = match Self::get_this(this) {
+            Some(myself) => {
+                let mut inner = myself.inner.borrow_mut();
+                let pending = inner.dead_key_pending;
+                if key_is_down {
+                    inner.dead_key_pending = dead_key;
+                }
+                pending
+            }
+            None => false,
+        };
+
+        if (USE_IME.load(Ordering::Relaxed) && modifiers.is_empty()) || dead_key || dead_key_pending
+        {
             unsafe {
                 let input_context: id = msg_send![this, inputContext];
                 let res: BOOL = msg_send![input_context, handleEvent: nsevent];
@@ -1137,6 +1288,41 @@ impl WindowView {
         Self::key_common(this, nsevent, true);
     }
 
+    /// Pressing or releasing a modifier key on its own produces neither
+    /// keyDown nor keyUp, but rather this, so that we can tell the
+    /// callbacks which modifiers are held
+    extern "C" fn flags_changed(this: &mut Object, _sel: Sel, nsevent: id) {
+        use super::keycodes::*;
+        let modifiers = unsafe { key_modifiers(nsevent.modifierFlags()) };
+        let key = match unsafe { nsevent.keyCode() } {
+            kVK_Shift | kVK_RightShift => KeyCode::Shift,
+            kVK_Control | kVK_RightControl => KeyCode::Control,
+            kVK_Option => KeyCode::LeftAlt,
+            kVK_RightOption => KeyCode::RightAlt,
+            kVK_Command | kVK_RightCommand => KeyCode::Super,
+            _ => return,
+        };
+        // The flags are those that are held after the change
+        let key_is_down = key
+            .modifier()
+            .map(|modifier| modifiers.contains(modifier))
+            .unwrap_or(false);
+        let event = KeyEvent {
+            key,
+            raw_key: None,
+            modifiers,
+            raw_modifiers: Modifiers::NONE,
+            repeat_count: 1,
+            key_is_down,
+        };
+
+        if let Some(myself) = Self::get_this(this) {
+            let mut inner = myself.inner.borrow_mut();
+            let window = Window(inner.window_id);
+            inner.callbacks.key_event(&event, &window);
+        }
+    }
+
     /*
     extern "C" fn key_up(this: &mut Object, _sel: Sel, nsevent: id) {
         Self::key_common(this, nsevent, false);
@@ -1297,6 +1483,19 @@ impl WindowView {
                 Self::window_should_close as extern "C" fn(&mut Object, Sel, id) -> BOOL,
             );
 
+            cls.add_method(
+                sel!(draggingEntered:),
+                Self::dragging_entered as extern "C" fn(&mut Object, Sel, id) -> NSUInteger,
+            );
+            cls.add_method(
+                sel!(draggingUpdated:),
+                Self::dragging_entered as extern "C" fn(&mut Object, Sel, id) -> NSUInteger,
+            );
+            cls.add_method(
+                sel!(performDragOperation:),
+                Self::perform_drag_operation as extern "C" fn(&mut Object, Sel, id) -> BOOL,
+            );
+
             cls.add_method(
                 sel!(drawRect:),
                 Self::draw_rect as extern "C" fn(&mut Object, Sel, NSRect),
@@ -1358,6 +1557,10 @@ impl WindowView {
                 sel!(keyDown:),
                 Self::key_down as extern "C" fn(&mut Object, Sel, id),
             );
+            cls.add_method(
+                sel!(flagsChanged:),
+                Self::flags_changed as extern "C" fn(&mut Object, Sel, id),
+            );
             /* keyUp events mess up the IME and we generally only care
              * about the down events anyway.  Leaving this un-plumbed
              * means that we'll fall back to the default behavior for
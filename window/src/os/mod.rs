@@ -3,6 +3,7 @@ pub mod windows;
 #[cfg(windows)]
 pub use windows::*;
 
+pub mod uri_list;
 pub mod wayland;
 pub mod x11;
 pub mod x_and_wayland;
@@ -0,0 +1,133 @@
+#![cfg(all(unix, not(target_os = "macos")))]
+//! Decodes the `text/uri-list` that applications offer when files are
+//! dragged from them on X11 and Wayland.  The list has one URI per
+//! line, with lines that begin with `#` being comments; see RFC 2483.
+//! Only `file:` URIs that refer to this host can be dropped onto a
+//! terminal, so anything else in the list is ignored.
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+
+pub const URI_LIST_MIME_TYPE: &str = "text/uri-list";
+
+/// Returns the value of the hex digit `c`
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes the %xx escapes in `s`.  The result is bytes rather than a
+/// string because file names needn't be UTF-8.
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' && idx + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex_value(bytes[idx + 1]), hex_value(bytes[idx + 2])) {
+                decoded.push(hi << 4 | lo);
+                idx += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[idx]);
+        idx += 1;
+    }
+    decoded
+}
+
+/// Returns true if `host`, from a `file:` URI, refers to this machine
+fn is_local_host(host: &str) -> bool {
+    if host.is_empty() || host.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    let mut name = [0u8; 256];
+    if unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) } != 0 {
+        return false;
+    }
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    name[..len].eq_ignore_ascii_case(host.as_bytes())
+}
+
+/// Returns the local path named by `uri`, or None if it isn't a
+/// `file:` URI for this host
+pub fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let scheme_len = "file:".len();
+    match uri.get(..scheme_len) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("file:") => {}
+        _ => return None,
+    }
+    let rest = &uri[scheme_len..];
+    let path = if rest.starts_with("//") {
+        let authority = &rest[2..];
+        let slash = authority.find('/')?;
+        if !is_local_host(&authority[..slash]) {
+            return None;
+        }
+        &authority[slash..]
+    } else if rest.starts_with('/') {
+        rest
+    } else {
+        return None;
+    };
+    Some(OsString::from_vec(percent_decode(path)).into())
+}
+
+/// Returns the local paths of the files in `list`, a `text/uri-list`
+pub fn parse_uri_list(list: &[u8]) -> Vec<PathBuf> {
+    String::from_utf8_lossy(list)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(file_uri_to_path)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn file_uris() {
+        assert_eq!(
+            file_uri_to_path("file:///home/wez/My%20Documents/it's.txt"),
+            Some(PathBuf::from("/home/wez/My Documents/it's.txt"))
+        );
+        assert_eq!(
+            file_uri_to_path("file://localhost/tmp"),
+            Some(PathBuf::from("/tmp"))
+        );
+        assert_eq!(file_uri_to_path("file:/tmp"), Some(PathBuf::from("/tmp")));
+        // Malformed escapes are left alone
+        assert_eq!(
+            file_uri_to_path("file:///100%"),
+            Some(PathBuf::from("/100%"))
+        );
+        assert_eq!(file_uri_to_path("https://wezfurlong.org/"), None);
+        assert_eq!(file_uri_to_path("file://elsewhere.example.com/tmp"), None);
+    }
+
+    #[test]
+    fn non_utf8_names() {
+        assert_eq!(
+            file_uri_to_path("file:///tmp/%FF"),
+            Some(PathBuf::from(OsString::from_vec(b"/tmp/\xff".to_vec())))
+        );
+    }
+
+    #[test]
+    fn lists() {
+        let list = b"# dragged from the file manager\r\n\
+                     file:///tmp/a\r\n\
+                     http://example.com/b\r\n\
+                     file:///tmp/c%20d\r\n";
+        assert_eq!(
+            parse_uri_list(list),
+            vec![PathBuf::from("/tmp/a"), PathBuf::from("/tmp/c d")]
+        );
+    }
+}
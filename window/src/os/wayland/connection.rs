@@ -1,6 +1,8 @@
 #![allow(dead_code)]
+use super::fractional_scale::wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1;
 use super::keyboard::KeyboardDispatcher;
 use super::pointer::*;
+use super::text_input::TextInputDispatcher;
 use super::window::*;
 use crate::connection::ConnectionOps;
 use crate::spawn::*;
@@ -13,12 +15,25 @@ use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::atomic::AtomicUsize;
 use std::time::{Duration, Instant};
-use toolkit::environment::Environment;
+use toolkit::environment::{Environment, SimpleGlobal};
 use toolkit::reexports::calloop::{EventLoop, EventSource, Interest, Mode, Poll, Readiness, Token};
 use toolkit::reexports::client::Display;
 use toolkit::WaylandSource;
+use wayland_protocols::unstable::text_input::v3::client::zwp_text_input_manager_v3::ZwpTextInputManagerV3;
+use wayland_protocols::viewporter::client::wp_viewporter::WpViewporter;
 
-toolkit::default_environment!(MyEnvironment, desktop);
+toolkit::default_environment!(MyEnvironment, desktop,
+    fields = [
+        fractional_scale_manager: SimpleGlobal<WpFractionalScaleManagerV1>,
+        viewporter: SimpleGlobal<WpViewporter>,
+        text_input_manager: SimpleGlobal<ZwpTextInputManagerV3>,
+    ],
+    singles = [
+        WpFractionalScaleManagerV1 => fractional_scale_manager,
+        WpViewporter => viewporter,
+        ZwpTextInputManagerV3 => text_input_manager,
+    ]
+);
 
 pub struct WaylandConnection {
     should_terminate: RefCell<bool>,
@@ -35,6 +50,7 @@ pub struct WaylandConnection {
     // bottom of this list.
     pub(crate) pointer: PointerDispatcher,
     pub(crate) keyboard: KeyboardDispatcher,
+    pub(crate) text_input: TextInputDispatcher,
     pub(crate) environment: RefCell<Environment<MyEnvironment>>,
     event_q: RefCell<EventLoop<()>>,
     pub(crate) display: RefCell<Display>,
@@ -42,11 +58,20 @@ pub struct WaylandConnection {
 
 impl WaylandConnection {
     pub fn create_new() -> anyhow::Result<Self> {
-        let (environment, display, event_q) =
-            toolkit::init_default_environment!(MyEnvironment, desktop)?;
+        let (environment, display, event_q) = toolkit::init_default_environment!(
+            MyEnvironment,
+            desktop,
+            fields = [
+                fractional_scale_manager: SimpleGlobal::new(),
+                viewporter: SimpleGlobal::new(),
+                text_input_manager: SimpleGlobal::new(),
+            ]
+        )?;
         let event_loop = toolkit::reexports::calloop::EventLoop::<()>::new()?;
 
         let keyboard = KeyboardDispatcher::new();
+        let text_input = TextInputDispatcher::new();
+        let text_input_manager = environment.get_global::<ZwpTextInputManagerV3>();
         let mut pointer = None;
 
         for seat in environment.get_all_seats() {
@@ -58,6 +83,9 @@ impl WaylandConnection {
             }) {
                 if has_kbd {
                     keyboard.register(event_loop.handle(), &seat)?;
+                    if let Some(manager) = text_input_manager.as_ref() {
+                        text_input.register(manager, &seat);
+                    }
                 }
                 if has_ptr {
                     pointer.replace(PointerDispatcher::register(
@@ -83,6 +111,7 @@ impl WaylandConnection {
             next_window_id: AtomicUsize::new(1),
             windows: RefCell::new(HashMap::new()),
             keyboard,
+            text_input,
             pointer: pointer.unwrap(),
         })
     }
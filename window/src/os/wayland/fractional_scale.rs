@@ -0,0 +1,22 @@
+//! Bindings for the wp_fractional_scale_v1 protocol, which are
+//! generated by build.rs from protocols/fractional-scale-v1.xml.
+//! The compositor uses it to tell us the preferred scale of a surface,
+//! in 120ths, so that we can render at eg: 1.25x rather than rendering
+//! at 1x or 2x and having the compositor scale the result.
+#![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#![allow(non_upper_case_globals, non_snake_case, unused_imports)]
+#![allow(clippy::all)]
+
+// These are used by the generated code
+pub(crate) use wayland_client::protocol::wl_surface;
+pub(crate) use wayland_client::sys;
+pub(crate) use wayland_client::{AnonymousObject, Attached, Main, Proxy, ProxyMap};
+pub(crate) use wayland_commons::map::{Object, ObjectMetadata};
+pub(crate) use wayland_commons::smallvec;
+pub(crate) use wayland_commons::wire::{Argument, ArgumentType, Message, MessageDesc};
+pub(crate) use wayland_commons::{Interface, MessageGroup};
+
+include!(concat!(env!("OUT_DIR"), "/fractional_scale_v1.rs"));
+
+/// The denominator of the scale reported by `preferred_scale`
+pub const SCALE_DENOMINATOR: f64 = 120.;
@@ -5,5 +5,7 @@ pub mod window;
 pub use self::window::*;
 pub use connection::*;
 mod copy_and_paste;
+mod fractional_scale;
 mod keyboard;
 mod pointer;
+mod text_input;
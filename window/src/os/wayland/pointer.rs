@@ -1,8 +1,12 @@
 use super::copy_and_paste::*;
+use super::window::read_pipe_with_timeout;
 use crate::input::*;
+use crate::os::uri_list::{parse_uri_list, URI_LIST_MIME_TYPE};
 use crate::os::wayland::connection::WaylandConnection;
+use filedescriptor::Pipe;
 use smithay_client_toolkit as toolkit;
 use std::collections::HashMap;
+use std::os::unix::io::AsRawFd;
 use std::sync::{Arc, Mutex};
 use toolkit::reexports::client::protocol::wl_data_device::{
     Event as DataDeviceEvent, WlDataDevice,
@@ -14,16 +18,26 @@ use toolkit::reexports::client::protocol::wl_pointer::{
 use toolkit::reexports::client::protocol::wl_surface::WlSurface;
 use toolkit::seat::pointer::{ThemeManager, ThemeSpec, ThemedPointer};
 use wayland_client::protocol::wl_compositor::WlCompositor;
-use wayland_client::protocol::wl_data_device_manager::WlDataDeviceManager;
+use wayland_client::protocol::wl_data_device_manager::{DndAction, WlDataDeviceManager};
 use wayland_client::protocol::wl_seat::WlSeat;
 use wayland_client::protocol::wl_shm::WlShm;
 use wayland_client::{Attached, Main};
 
+/// A drag that offers a list of files and that is over one of our windows
+struct Drag {
+    offer: WlDataOffer,
+    window_id: usize,
+}
+
 #[derive(Default)]
 struct Inner {
     active_surface_id: u32,
     surface_to_pending: HashMap<u32, Arc<Mutex<PendingMouse>>>,
     serial: u32,
+    /// The id of the most recent data offer that offered a list of
+    /// files; a drag announces the offer just before it enters a window
+    uri_list_offer: Option<u32>,
+    drag: Option<Drag>,
 }
 
 impl Inner {
@@ -55,6 +69,11 @@ impl Inner {
     }
 
     fn route_data_offer(&mut self, event: DataOfferEvent, offer: WlDataOffer) {
+        if let DataOfferEvent::Offer { mime_type } = &event {
+            if mime_type == URI_LIST_MIME_TYPE {
+                self.uri_list_offer = Some(offer.as_ref().id());
+            }
+        }
         if let Some(copy_and_paste) = self.resolve_copy_and_paste() {
             copy_and_paste
                 .lock()
@@ -74,10 +93,37 @@ impl Inner {
                     }
                 });
             }
-            DataDeviceEvent::Enter { .. }
-            | DataDeviceEvent::Leave { .. }
-            | DataDeviceEvent::Motion { .. }
-            | DataDeviceEvent::Drop => {}
+            DataDeviceEvent::Enter {
+                serial,
+                surface,
+                id: Some(offer),
+                ..
+            } => {
+                self.drag.take();
+                let window_id = self
+                    .surface_to_pending
+                    .get(&surface.as_ref().id())
+                    .map(|pending| pending.lock().unwrap().window_id);
+                match window_id {
+                    Some(window_id) if self.uri_list_offer == Some(offer.as_ref().id()) => {
+                        offer.accept(serial, Some(URI_LIST_MIME_TYPE.to_string()));
+                        if offer.as_ref().version() >= 3 {
+                            offer.set_actions(DndAction::Copy, DndAction::Copy);
+                        }
+                        self.drag = Some(Drag { offer, window_id });
+                    }
+                    _ => offer.accept(serial, None),
+                }
+            }
+            DataDeviceEvent::Leave { .. } => {
+                self.drag.take();
+            }
+            DataDeviceEvent::Drop => {
+                if let Some(drag) = self.drag.take() {
+                    drop_files(drag);
+                }
+            }
+            DataDeviceEvent::Enter { .. } | DataDeviceEvent::Motion { .. } => {}
 
             DataDeviceEvent::Selection { id } => {
                 if let Some(offer) = id {
@@ -91,6 +137,39 @@ impl Inner {
     }
 }
 
+/// Receives the list of files that were dropped, and passes them to
+/// the window once it has been read
+fn drop_files(drag: Drag) {
+    let pipe = match Pipe::new() {
+        Ok(pipe) => pipe,
+        Err(err) => {
+            log::error!("while receiving dropped files: {}", err);
+            return;
+        }
+    };
+    drag.offer
+        .receive(URI_LIST_MIME_TYPE.to_string(), pipe.write.as_raw_fd());
+    // Our end of the pipe must be closed for the read to see EOF
+    drop(pipe.write);
+    drag.offer.finish();
+    drag.offer.destroy();
+
+    let window_id = drag.window_id;
+    let read = pipe.read;
+    std::thread::spawn(move || match read_pipe_with_timeout(read) {
+        Ok(list) => {
+            let paths = parse_uri_list(list.as_bytes());
+            if !paths.is_empty() {
+                WaylandConnection::with_window_inner(window_id, move |inner| {
+                    inner.dropped_files(paths.clone());
+                    Ok(())
+                });
+            }
+        }
+        Err(err) => log::error!("while reading dropped files: {}", err),
+    });
+}
+
 pub struct PointerDispatcher {
     inner: Arc<Mutex<Inner>>,
     pub(crate) data_device: Main<WlDataDevice>,
@@ -106,6 +185,7 @@ pub struct PendingMouse {
     surface_coords: Option<(f64, f64)>,
     button: Vec<(MousePress, DebuggableButtonState)>,
     scroll: Option<(f64, f64)>,
+    discrete_scroll: Option<(i32, i32)>,
 }
 
 impl PendingMouse {
@@ -115,6 +195,7 @@ impl PendingMouse {
             copy_and_paste: Arc::clone(copy_and_paste),
             button: vec![],
             scroll: None,
+            discrete_scroll: None,
             surface_coords: None,
         }))
     }
@@ -186,6 +267,15 @@ impl PendingMouse {
                 self.scroll.replace((x + value, y));
                 changed
             }
+            SendablePointerEvent::AxisDiscrete { axis, discrete } => {
+                let changed = self.scroll.is_none() && self.discrete_scroll.is_none();
+                let (x, y) = self.discrete_scroll.take().unwrap_or((0, 0));
+                self.discrete_scroll.replace(match axis {
+                    Axis::VerticalScroll => (x, y + discrete),
+                    _ => (x + discrete, y),
+                });
+                changed
+            }
             _ => false,
         }
     }
@@ -206,6 +296,13 @@ impl PendingMouse {
     pub fn scroll(pending: &Arc<Mutex<Self>>) -> Option<(f64, f64)> {
         pending.lock().unwrap().scroll.take()
     }
+
+    /// Returns the accumulated wheel ticks.  These accompany the
+    /// continuous scroll values when the scrolling is performed by a
+    /// wheel rather than a trackpad, and are preferred over them.
+    pub fn discrete_scroll(pending: &Arc<Mutex<Self>>) -> Option<(i32, i32)> {
+        pending.lock().unwrap().discrete_scroll.take()
+    }
 }
 
 impl PointerDispatcher {
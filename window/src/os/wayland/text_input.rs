@@ -0,0 +1,121 @@
+//! Input method support through the zwp_text_input_v3 protocol.
+//! The input method sends the text that is being composed, known as
+//! the preedit, and the text that is eventually committed; we pass the
+//! former to the window so that it can be shown at the cursor, and the
+//! latter as a composed key event.
+use crate::os::wayland::connection::WaylandConnection;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::Attached;
+use wayland_protocols::unstable::text_input::v3::client::zwp_text_input_manager_v3::ZwpTextInputManagerV3;
+use wayland_protocols::unstable::text_input::v3::client::zwp_text_input_v3::{
+    ContentHint, ContentPurpose, Event, ZwpTextInputV3,
+};
+
+/// The changes sent by the input method, which take effect together
+/// when it sends `done`
+#[derive(Default, Clone, Debug)]
+pub struct TextInputEvent {
+    /// The text being composed; None if there is none
+    pub preedit: Option<String>,
+    /// Text that has been composed and should be sent to the window
+    pub commit: Option<String>,
+}
+
+#[derive(Default)]
+struct Inner {
+    surface_to_window_id: HashMap<u32, usize>,
+    /// The window that has the text input focus, along with the text
+    /// input object of the seat through which it has it
+    active: Option<(usize, ZwpTextInputV3)>,
+    pending: TextInputEvent,
+}
+
+impl Inner {
+    fn handle_event(&mut self, input: &ZwpTextInputV3, event: Event) {
+        match event {
+            Event::Enter { surface } => {
+                if let Some(&window_id) = self.surface_to_window_id.get(&surface.as_ref().id()) {
+                    input.enable();
+                    input.set_content_type(ContentHint::None, ContentPurpose::Terminal);
+                    input.commit();
+                    self.active = Some((window_id, input.clone()));
+                }
+            }
+            Event::Leave { surface } => {
+                let window_id = self.surface_to_window_id.get(&surface.as_ref().id());
+                if let Some(&window_id) = window_id {
+                    input.disable();
+                    input.commit();
+                    self.pending = TextInputEvent::default();
+                    if self.active.as_ref().map(|(id, _)| *id) == Some(window_id) {
+                        self.active = None;
+                    }
+                    // Remove any preedit that was being shown
+                    Self::dispatch_to_window(window_id, TextInputEvent::default());
+                }
+            }
+            Event::PreeditString { text, .. } => self.pending.preedit = text,
+            Event::CommitString { text } => self.pending.commit = text,
+            Event::Done { .. } => {
+                // Any state that wasn't sent since the last `done` is
+                // reset, so an absent preedit means that it is cleared
+                let event = std::mem::take(&mut self.pending);
+                if let Some((window_id, _)) = self.active.as_ref() {
+                    Self::dispatch_to_window(*window_id, event);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch_to_window(window_id: usize, event: TextInputEvent) {
+        let mut event = Some(event);
+        WaylandConnection::with_window_inner(window_id, move |inner| {
+            inner.handle_text_input_event(event.take().unwrap());
+            Ok(())
+        });
+    }
+}
+
+#[derive(Clone)]
+pub struct TextInputDispatcher {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl TextInputDispatcher {
+    pub fn new() -> Self {
+        let inner = Arc::new(Mutex::new(Inner::default()));
+        Self { inner }
+    }
+
+    pub fn register(&self, manager: &Attached<ZwpTextInputManagerV3>, seat: &Attached<WlSeat>) {
+        let input = manager.get_text_input(seat);
+        let inner = Arc::clone(&self.inner);
+        input.quick_assign(move |input, event, _| {
+            inner.lock().unwrap().handle_event(&input, event);
+        });
+    }
+
+    pub fn add_window(&self, window_id: usize, surface: &WlSurface) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .surface_to_window_id
+            .insert(surface.as_ref().id(), window_id);
+    }
+
+    /// Tells the input method where the cursor of `window_id` is, in
+    /// surface coordinates, so that it can place its candidate window
+    /// next to it
+    pub fn set_cursor_rectangle(&self, window_id: usize, x: i32, y: i32, width: i32, height: i32) {
+        let inner = self.inner.lock().unwrap();
+        if let Some((active_id, input)) = inner.active.as_ref() {
+            if *active_id == window_id {
+                input.set_cursor_rectangle(x, y, width, height);
+                input.commit();
+            }
+        }
+    }
+}
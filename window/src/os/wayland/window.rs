@@ -1,6 +1,10 @@
 use super::copy_and_paste::*;
+use super::fractional_scale::wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1;
+use super::fractional_scale::wp_fractional_scale_v1::{self, WpFractionalScaleV1};
+use super::fractional_scale::SCALE_DENOMINATOR;
 use super::keyboard::KeyboardEvent;
 use super::pointer::*;
+use super::text_input::TextInputEvent;
 use crate::bitmaps::BitmapImage;
 use crate::color::Color;
 use crate::connection::ConnectionOps;
@@ -28,8 +32,11 @@ use toolkit::reexports::client::protocol::wl_surface::WlSurface;
 use toolkit::shm::MemPool;
 use toolkit::window::{ButtonColorSpec, ColorSpec, ConceptConfig, ConceptFrame, Event};
 use wayland_client::protocol::wl_data_device_manager::WlDataDeviceManager;
+use wayland_client::Main;
 #[cfg(feature = "opengl")]
 use wayland_egl::{is_available as egl_is_available, WlEglSurface};
+use wayland_protocols::viewporter::client::wp_viewport::WpViewport;
+use wayland_protocols::viewporter::client::wp_viewporter::WpViewporter;
 
 const DARK_GRAY: [u8; 4] = [0xff, 0x35, 0x35, 0x35];
 const DARK_PURPLE: [u8; 4] = [0xff, 0x2b, 0x20, 0x42];
@@ -81,6 +88,24 @@ fn frame_config() -> ConceptConfig {
     }
 }
 
+/// Present when the compositor supports wp_fractional_scale_v1 and
+/// wp_viewporter.  The buffer is then allocated at the preferred scale
+/// of the surface, which may be fractional, and the viewport maps it
+/// onto the surface, whose buffer scale remains at 1.
+struct FractionalScale {
+    fractional_scale: Main<WpFractionalScaleV1>,
+    viewport: Main<WpViewport>,
+    /// The most recently reported preferred scale
+    scale: Option<f64>,
+}
+
+impl Drop for FractionalScale {
+    fn drop(&mut self) {
+        self.viewport.destroy();
+        self.fractional_scale.destroy();
+    }
+}
+
 pub struct WaylandWindowInner {
     window_id: usize,
     callbacks: Box<dyn WindowCallbacks>,
@@ -95,6 +120,7 @@ pub struct WaylandWindowInner {
     modifiers: Modifiers,
     pending_event: Arc<Mutex<PendingEvent>>,
     pending_mouse: Arc<Mutex<PendingMouse>>,
+    fractional_scale: Option<FractionalScale>,
     // wegl_surface is listed before gl_state because it
     // must be dropped before gl_state otherwise the underlying
     // libraries will segfault on shutdown
@@ -110,6 +136,7 @@ struct PendingEvent {
     refresh_decorations: bool,
     configure: Option<(u32, u32)>,
     dpi: Option<i32>,
+    fractional_scale: Option<f64>,
 }
 
 impl PendingEvent {
@@ -187,6 +214,42 @@ impl WaylandWindow {
                 }
             });
 
+        let fractional_scale = {
+            let env = conn.environment.borrow();
+            match (
+                env.get_global::<WpFractionalScaleManagerV1>(),
+                env.get_global::<WpViewporter>(),
+            ) {
+                (Some(manager), Some(viewporter)) => {
+                    let fractional_scale = manager.get_fractional_scale(&surface);
+                    fractional_scale.quick_assign({
+                        let pending_event = Arc::clone(&pending_event);
+                        move |_, event, _| {
+                            if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+                                let scale = scale as f64 / SCALE_DENOMINATOR;
+                                log::debug!("preferred fractional scale is {}", scale);
+                                pending_event
+                                    .lock()
+                                    .unwrap()
+                                    .fractional_scale
+                                    .replace(scale);
+                                WaylandConnection::with_window_inner(window_id, move |inner| {
+                                    inner.dispatch_pending_event();
+                                    Ok(())
+                                });
+                            }
+                        }
+                    });
+                    Some(FractionalScale {
+                        fractional_scale,
+                        viewport: viewporter.get_viewport(&surface),
+                        scale: None,
+                    })
+                }
+                _ => None,
+            }
+        };
+
         let dimensions = Dimensions {
             pixel_width: width,
             pixel_height: height,
@@ -225,6 +288,7 @@ impl WaylandWindow {
 
         // window.new_seat(&conn.seat);
         conn.keyboard.add_window(window_id, &surface);
+        conn.text_input.add_window(window_id, &surface);
 
         let copy_and_paste = CopyAndPaste::create();
         let pending_mouse = PendingMouse::create(window_id, &copy_and_paste);
@@ -245,6 +309,7 @@ impl WaylandWindow {
             modifiers: Modifiers::NONE,
             pending_event,
             pending_mouse,
+            fractional_scale,
             #[cfg(feature = "opengl")]
             gl_state: None,
             #[cfg(feature = "opengl")]
@@ -317,6 +382,26 @@ impl WaylandWindowInner {
         }
     }
 
+    pub(crate) fn handle_text_input_event(&mut self, event: TextInputEvent) {
+        self.callbacks.ime_preedit(event.preedit);
+        if let Some(text) = event.commit {
+            let key_event = KeyEvent {
+                key_is_down: true,
+                key: KeyCode::Composed(text),
+                raw_key: None,
+                modifiers: Modifiers::NONE,
+                raw_modifiers: Modifiers::NONE,
+                repeat_count: 1,
+            };
+            self.callbacks
+                .key_event(&key_event, &Window::Wayland(WaylandWindow(self.window_id)));
+        }
+    }
+
+    pub(crate) fn dropped_files(&mut self, paths: Vec<std::path::PathBuf>) {
+        self.callbacks.dropped_files(paths);
+    }
+
     pub(crate) fn dispatch_pending_mouse(&mut self) {
         // Dancing around the borrow checker and the call to self.refresh_frame()
         let pending_mouse = Arc::clone(&self.pending_mouse);
@@ -372,12 +457,31 @@ impl WaylandWindowInner {
                 .mouse_event(&event, &Window::Wayland(WaylandWindow(self.window_id)));
         }
 
+        let discrete = PendingMouse::discrete_scroll(&pending_mouse);
         if let Some((value_x, value_y)) = PendingMouse::scroll(&pending_mouse) {
-            let factor = self.get_dpi_factor() as f64;
-            let discrete_x = value_x.trunc() * factor;
-            if discrete_x != 0. {
+            // Wheels report discrete ticks alongside the continuous
+            // value; trackpads only report the latter, in surface
+            // coordinates, which we scale to pixels
+            let factor = self.get_dpi_factor();
+            let (kind_x, kind_y) = match discrete {
+                Some((discrete_x, discrete_y)) => (
+                    MouseEventKind::HorzWheel(-discrete_x as i16),
+                    MouseEventKind::VertWheel(-discrete_y as i16),
+                ),
+                None => (
+                    MouseEventKind::HorzPixelWheel(-value_x * factor),
+                    MouseEventKind::VertPixelWheel(-value_y * factor),
+                ),
+            };
+            let is_zero = |kind: &MouseEventKind| match kind {
+                MouseEventKind::HorzWheel(n) | MouseEventKind::VertWheel(n) => *n == 0,
+                MouseEventKind::HorzPixelWheel(n) | MouseEventKind::VertPixelWheel(n) => *n == 0.,
+                _ => true,
+            };
+
+            if !is_zero(&kind_x) {
                 let event = MouseEvent {
-                    kind: MouseEventKind::HorzWheel(-discrete_x as i16),
+                    kind: kind_x,
                     coords: self.last_mouse_coords,
                     screen_coords: ScreenPoint::new(
                         self.last_mouse_coords.x + self.dimensions.pixel_width as isize,
@@ -390,10 +494,9 @@ impl WaylandWindowInner {
                     .mouse_event(&event, &Window::Wayland(WaylandWindow(self.window_id)));
             }
 
-            let discrete_y = value_y.trunc() * factor;
-            if discrete_y != 0. {
+            if !is_zero(&kind_y) {
                 let event = MouseEvent {
-                    kind: MouseEventKind::VertWheel(-discrete_y as i16),
+                    kind: kind_y,
                     coords: self.last_mouse_coords,
                     screen_coords: ScreenPoint::new(
                         self.last_mouse_coords.x + self.dimensions.pixel_width as isize,
@@ -408,8 +511,17 @@ impl WaylandWindowInner {
         }
     }
 
-    fn get_dpi_factor(&self) -> i32 {
-        self.dimensions.dpi as i32 / 96
+    fn get_dpi_factor(&self) -> f64 {
+        self.dimensions.dpi as f64 / 96.
+    }
+
+    /// The scale at which to render: the preferred fractional scale if
+    /// the compositor supports that, otherwise its integer scale
+    fn scale_factor(&self) -> f64 {
+        match self.fractional_scale.as_ref().and_then(|f| f.scale) {
+            Some(scale) => scale,
+            None => get_surface_scale_factor(&self.surface) as f64,
+        }
     }
 
     fn get_dpi(&self) -> usize {
@@ -417,14 +529,14 @@ impl WaylandWindowInner {
     }
 
     fn surface_to_pixels(&self, surface: i32) -> i32 {
-        surface * self.get_dpi_factor()
+        (surface as f64 * self.get_dpi_factor()).round() as i32
     }
 
     fn pixels_to_surface(&self, pixels: i32) -> i32 {
         // Take care to round up, otherwise we can lose a pixel
         // and that can effectively lose the final row of the
         // terminal
-        ((pixels as f64) / (self.get_dpi_factor() as f64)).ceil() as i32
+        ((pixels as f64) / self.get_dpi_factor()).ceil() as i32
     }
 
     fn dispatch_pending_event(&mut self) {
@@ -439,7 +551,15 @@ impl WaylandWindowInner {
             self.window.take();
         }
 
-        if pending.configure.is_none() && pending.dpi.is_some() {
+        if let Some(scale) = pending.fractional_scale {
+            if let Some(fractional) = self.fractional_scale.as_mut() {
+                fractional.scale.replace(scale);
+            }
+        }
+
+        if pending.configure.is_none()
+            && (pending.dpi.is_some() || pending.fractional_scale.is_some())
+        {
             // Synthesize a pending configure event for the dpi change
             pending.configure.replace((
                 self.pixels_to_surface(self.dimensions.pixel_width as i32) as u32,
@@ -450,16 +570,27 @@ impl WaylandWindowInner {
 
         if let Some((w, h)) = pending.configure.take() {
             if self.window.is_some() {
-                let factor = get_surface_scale_factor(&self.surface);
+                let factor = self.scale_factor();
 
-                let pixel_width = self.surface_to_pixels(w.try_into().unwrap());
-                let pixel_height = self.surface_to_pixels(h.try_into().unwrap());
+                // Sizes are rounded halfway away from zero, as the
+                // compositor does for fractionally scaled surfaces
+                let pixel_width = (w as f64 * factor).round() as i32;
+                let pixel_height = (h as f64 * factor).round() as i32;
 
                 // Avoid blurring by matching the scaling factor of the
                 // compositor; if it is going to double the size then
                 // we render at double the size anyway and tell it that
-                // the buffer is already doubled
-                self.surface.set_buffer_scale(factor);
+                // the buffer is already doubled.  A fractional scale is
+                // instead applied by the viewport.
+                match self.fractional_scale.as_ref() {
+                    Some(fractional) if fractional.scale.is_some() => {
+                        self.surface.set_buffer_scale(1);
+                        fractional
+                            .viewport
+                            .set_destination(w.try_into().unwrap(), h.try_into().unwrap());
+                    }
+                    _ => self.surface.set_buffer_scale(factor as i32),
+                }
 
                 // Update the window decoration size
                 self.window.as_mut().unwrap().resize(w, h);
@@ -468,7 +599,7 @@ impl WaylandWindowInner {
                 let new_dimensions = Dimensions {
                     pixel_width: pixel_width.try_into().unwrap(),
                     pixel_height: pixel_height.try_into().unwrap(),
-                    dpi: factor as usize * 96,
+                    dpi: (factor * 96.).round() as usize,
                 };
                 // Only trigger a resize if the new dimensions are different;
                 // this makes things more efficient and a little more smooth
@@ -741,6 +872,13 @@ impl WindowOps for WaylandWindow {
         })
     }
 
+    fn set_text_cursor_position(&self, cursor: Rect) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.set_text_cursor_position(cursor);
+            Ok(())
+        })
+    }
+
     fn apply<R, F: Send + 'static + FnMut(&mut dyn Any, &dyn WindowOps) -> anyhow::Result<R>>(
         &self,
         mut func: F,
@@ -765,6 +903,8 @@ impl WindowOps for WaylandWindow {
         let future = promise.get_future().unwrap();
         let promise = Arc::new(Mutex::new(promise));
         WaylandConnection::with_window_inner(self.0, move |inner| {
+            // We don't implement the primary selection protocol, so
+            // the clipboard is used in place of the primary selection
             let read = inner.copy_and_paste.lock().unwrap().get_clipboard_data()?;
             let promise = Arc::clone(&promise);
             std::thread::spawn(move || {
@@ -787,8 +927,12 @@ impl WindowOps for WaylandWindow {
         future
     }
 
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         WaylandConnection::with_window_inner(self.0, move |inner| {
+            if clipboard == Clipboard::PrimarySelection {
+                // We don't implement the primary selection protocol
+                return Ok(());
+            }
             let text = text.clone();
             let conn = Connection::get().unwrap().wayland();
 
@@ -842,7 +986,7 @@ fn write_pipe_with_timeout(mut file: FileDescriptor, data: &[u8]) -> anyhow::Res
     Ok(())
 }
 
-fn read_pipe_with_timeout(mut file: FileDescriptor) -> anyhow::Result<String> {
+pub(super) fn read_pipe_with_timeout(mut file: FileDescriptor) -> anyhow::Result<String> {
     let mut result = Vec::new();
 
     file.set_non_blocking(true)?;
@@ -909,6 +1053,8 @@ impl WindowOpsMut for WaylandWindowInner {
             Some(MouseCursor::Arrow) => "arrow",
             Some(MouseCursor::Hand) => "hand",
             Some(MouseCursor::Text) => "text",
+            Some(MouseCursor::SizeUpDown) => "sb_v_double_arrow",
+            Some(MouseCursor::SizeLeftRight) => "sb_h_double_arrow",
             None => return,
         };
         let conn = Connection::get().unwrap().wayland();
@@ -950,6 +1096,17 @@ impl WindowOpsMut for WaylandWindowInner {
 
     fn set_window_position(&self, _coords: ScreenPoint) {}
 
+    fn set_text_cursor_position(&mut self, cursor: Rect) {
+        let conn = Connection::get().unwrap().wayland();
+        conn.text_input.set_cursor_rectangle(
+            self.window_id,
+            self.pixels_to_surface(cursor.origin.x as i32),
+            self.pixels_to_surface(cursor.origin.y as i32),
+            self.pixels_to_surface(cursor.size.width as i32),
+            self.pixels_to_surface(cursor.size.height as i32),
+        );
+    }
+
     /// Change the title for the window manager
     fn set_title(&mut self, title: &str) {
         if let Some(window) = self.window.as_ref() {
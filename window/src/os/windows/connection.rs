@@ -10,6 +10,8 @@ use std::rc::Rc;
 use winapi::shared::basetsd::UINT_PTR;
 use winapi::shared::minwindef::*;
 use winapi::shared::windef::*;
+use winapi::um::combaseapi::CoInitializeEx;
+use winapi::um::objbase::COINIT_APARTMENTTHREADED;
 use winapi::um::winbase::INFINITE;
 use winapi::um::winnt::HANDLE;
 use winapi::um::winuser::*;
@@ -27,6 +29,11 @@ impl ConnectionOps for Connection {
         }
     }
 
+    /// Plays the default sound; its volume is that of the system
+    fn beep(&self, _volume: f32) -> bool {
+        unsafe { MessageBeep(MB_OK) != 0 }
+    }
+
     fn run_message_loop(&self) -> anyhow::Result<()> {
         let mut msg: MSG = unsafe { std::mem::zeroed() };
         loop {
@@ -92,6 +99,10 @@ impl ConnectionOps for Connection {
 
 impl Connection {
     pub(crate) fn create_new() -> anyhow::Result<Self> {
+        // COM is required by the taskbar progress APIs
+        unsafe {
+            CoInitializeEx(null_mut(), COINIT_APARTMENTTHREADED);
+        }
         let event_handle = SPAWN_QUEUE.event_handle.0;
         Ok(Self {
             event_handle,
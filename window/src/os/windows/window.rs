@@ -5,8 +5,8 @@ use crate::color::Color;
 use crate::connection::ConnectionOps;
 use crate::{
     Clipboard, Dimensions, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseCursor, MouseEvent,
-    MouseEventKind, MousePress, Operator, PaintContext, Point, Rect, ScreenPoint, WindowCallbacks,
-    WindowOps, WindowOpsMut,
+    MouseEventKind, MousePress, Operator, PaintContext, Point, Rect, ScreenPoint, TaskbarProgress,
+    WindowCallbacks, WindowOps, WindowOpsMut,
 };
 use anyhow::{bail, Context};
 use lazy_static::lazy_static;
@@ -14,17 +14,28 @@ use promise::Future;
 use std::any::Any;
 use std::cell::RefCell;
 use std::convert::TryInto;
+use std::ffi::OsString;
 use std::io::{self, Error as IoError};
 use std::os::windows::ffi::OsStringExt;
+use std::path::PathBuf;
 use std::ptr::{null, null_mut};
 use std::rc::Rc;
 use winapi::shared::minwindef::*;
 use winapi::shared::ntdef::*;
 use winapi::shared::windef::*;
+use winapi::shared::winerror::S_OK;
+use winapi::shared::wtypesbase::CLSCTX_INPROC_SERVER;
+use winapi::um::combaseapi::CoCreateInstance;
 use winapi::um::imm::*;
 use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::shellapi::{DragFinish, DragQueryFileW, HDROP};
+use winapi::um::shobjidl_core::{
+    CLSID_TaskbarList, ITaskbarList3, TBPF_ERROR, TBPF_INDETERMINATE, TBPF_NOPROGRESS, TBPF_NORMAL,
+    TBPF_PAUSED,
+};
 use winapi::um::wingdi::*;
 use winapi::um::winuser::*;
+use winapi::Interface;
 use winreg::{enums::HKEY_CURRENT_USER, RegKey};
 
 const GCS_RESULTSTR: DWORD = 0x800;
@@ -49,6 +60,9 @@ pub(crate) struct WindowInner {
     vscroll_remainder: i16,
 
     keyboard_info: KeyboardLayoutInfo,
+    /// The most recently requested mouse cursor; re-applied in
+    /// response to WM_SETCURSOR
+    cursor: Option<MouseCursor>,
 }
 
 #[derive(Debug, Clone)]
@@ -188,7 +202,7 @@ impl Window {
         let name = wide_string(name);
         let hwnd = unsafe {
             CreateWindowExW(
-                0,
+                WS_EX_ACCEPTFILES,
                 class_name.as_ptr(),
                 name.as_ptr(),
                 WS_OVERLAPPEDWINDOW,
@@ -227,6 +241,7 @@ impl Window {
             vscroll_remainder: 0,
             hscroll_remainder: 0,
             keyboard_info: KeyboardLayoutInfo::new(),
+            cursor: None,
         }));
 
         // Careful: `raw` owns a ref to inner, but there is no Drop impl
@@ -280,11 +295,25 @@ impl WindowOpsMut for WindowInner {
         schedule_show_window(self.hwnd, true);
     }
 
+    fn focus(&mut self) {
+        let hwnd = self.hwnd;
+        // As with ShowWindow, these may call to the window proc
+        promise::spawn::spawn(async move {
+            unsafe {
+                if IsIconic(hwnd.0) != 0 {
+                    ShowWindow(hwnd.0, SW_RESTORE);
+                }
+                SetForegroundWindow(hwnd.0);
+            }
+        });
+    }
+
     fn hide(&mut self) {
         schedule_show_window(self.hwnd, false);
     }
 
     fn set_cursor(&mut self, cursor: Option<MouseCursor>) {
+        self.cursor = cursor;
         apply_mouse_cursor(cursor);
     }
 
@@ -350,6 +379,41 @@ impl WindowOpsMut for WindowInner {
         let imc = ImmContext::get(self.hwnd.0);
         imc.set_position(cursor.origin.x.max(0) as i32, cursor.origin.y.max(0) as i32);
     }
+
+    fn set_progress(&mut self, progress: TaskbarProgress) {
+        let (state, value) = match progress {
+            TaskbarProgress::None => (TBPF_NOPROGRESS, None),
+            TaskbarProgress::Normal(pct) => (TBPF_NORMAL, Some(pct)),
+            TaskbarProgress::Error(pct) => (TBPF_ERROR, Some(pct)),
+            TaskbarProgress::Indeterminate => (TBPF_INDETERMINATE, None),
+            TaskbarProgress::Paused(pct) => (TBPF_PAUSED, Some(pct)),
+        };
+
+        unsafe {
+            let mut taskbar: *mut ITaskbarList3 = null_mut();
+            if CoCreateInstance(
+                &CLSID_TaskbarList,
+                null_mut(),
+                CLSCTX_INPROC_SERVER,
+                &ITaskbarList3::uuidof(),
+                &mut taskbar as *mut _ as *mut _,
+            ) != S_OK
+                || taskbar.is_null()
+            {
+                return;
+            }
+            let taskbar = &*taskbar;
+            if taskbar.HrInit() == S_OK {
+                // The value must be set before the state, as setting
+                // the value implicitly switches to the normal state
+                if let Some(pct) = value {
+                    taskbar.SetProgressValue(self.hwnd.0, pct as u64, 100);
+                }
+                taskbar.SetProgressState(self.hwnd.0, state);
+            }
+            taskbar.Release();
+        }
+    }
 }
 
 impl WindowOps for Window {
@@ -370,6 +434,13 @@ impl WindowOps for Window {
         Future::ok(()) // FIXME: this is a lie!
     }
 
+    fn focus(&self) -> Future<()> {
+        Connection::with_window_inner(self.0, |inner| {
+            inner.focus();
+            Ok(())
+        })
+    }
+
     fn set_cursor(&self, cursor: Option<MouseCursor>) -> Future<()> {
         Connection::with_window_inner(self.0, move |inner| {
             inner.set_cursor(cursor);
@@ -413,6 +484,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn set_progress(&self, progress: TaskbarProgress) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_progress(progress);
+            Ok(())
+        })
+    }
+
     fn apply<R, F: Send + 'static + FnMut(&mut dyn Any, &dyn WindowOps) -> anyhow::Result<R>>(
         &self,
         mut func: F,
@@ -440,7 +518,10 @@ impl WindowOps for Window {
         )
     }
 
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
+        if clipboard == Clipboard::PrimarySelection {
+            return Future::ok(());
+        }
         Future::result(
             clipboard_win::set_clipboard_string(&text).context("Error setting clipboard"),
         )
@@ -578,6 +659,29 @@ unsafe fn wm_set_focus(
     None
 }
 
+/// Windows asks us to set the cursor each time the mouse moves; we
+/// only get to decide it for the client area, and the rest is left
+/// to the default window procedure so that the resize borders show
+/// the appropriate cursors.
+unsafe fn wm_set_cursor(
+    hwnd: HWND,
+    _msg: UINT,
+    _wparam: WPARAM,
+    lparam: LPARAM,
+) -> Option<LRESULT> {
+    if LOWORD(lparam as u32) as isize != HTCLIENT {
+        return None;
+    }
+    if let Some(inner) = rc_from_hwnd(hwnd) {
+        let inner = inner.borrow();
+        if let Some(cursor) = inner.cursor {
+            apply_mouse_cursor(Some(cursor));
+            return Some(1);
+        }
+    }
+    None
+}
+
 unsafe fn wm_kill_focus(
     hwnd: HWND,
     _msg: UINT,
@@ -591,6 +695,36 @@ unsafe fn wm_kill_focus(
     None
 }
 
+/// Files were dropped onto the window from Explorer
+unsafe fn wm_drop_files(
+    hwnd: HWND,
+    _msg: UINT,
+    wparam: WPARAM,
+    _lparam: LPARAM,
+) -> Option<LRESULT> {
+    let drop = wparam as HDROP;
+    // Passing an index of 0xFFFFFFFF returns the number of files
+    let count = DragQueryFileW(drop, 0xFFFF_FFFF, null_mut(), 0);
+    let mut paths = vec![];
+    for idx in 0..count {
+        // The length doesn't include the terminating NUL
+        let len = DragQueryFileW(drop, idx, null_mut(), 0);
+        let mut name = vec![0u16; len as usize + 1];
+        let copied = DragQueryFileW(drop, idx, name.as_mut_ptr(), name.len() as u32);
+        name.truncate(copied as usize);
+        paths.push(PathBuf::from(OsString::from_wide(&name)));
+    }
+    DragFinish(drop);
+
+    if !paths.is_empty() {
+        if let Some(inner) = rc_from_hwnd(hwnd) {
+            let inner = inner.borrow();
+            inner.callbacks.borrow_mut().dropped_files(paths);
+        }
+    }
+    Some(0)
+}
+
 unsafe fn wm_paint(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
     if let Some(inner) = rc_from_hwnd(hwnd) {
         let mut inner = inner.borrow_mut();
@@ -736,6 +870,8 @@ fn apply_mouse_cursor(cursor: Option<MouseCursor>) {
                     MouseCursor::Arrow => IDC_ARROW,
                     MouseCursor::Hand => IDC_HAND,
                     MouseCursor::Text => IDC_IBEAM,
+                    MouseCursor::SizeUpDown => IDC_SIZENS,
+                    MouseCursor::SizeLeftRight => IDC_SIZEWE,
                 },
             ));
         },
@@ -1237,6 +1373,8 @@ unsafe fn do_wnd_proc(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
         WM_SIZE => wm_size(hwnd, msg, wparam, lparam),
         WM_SETFOCUS => wm_set_focus(hwnd, msg, wparam, lparam),
         WM_KILLFOCUS => wm_kill_focus(hwnd, msg, wparam, lparam),
+        WM_SETCURSOR => wm_set_cursor(hwnd, msg, wparam, lparam),
+        WM_DROPFILES => wm_drop_files(hwnd, msg, wparam, lparam),
         WM_KEYDOWN | WM_CHAR | WM_IME_CHAR | WM_KEYUP | WM_SYSKEYUP | WM_SYSKEYDOWN => {
             key(hwnd, msg, wparam, lparam)
         }
@@ -1,3 +1,4 @@
+use super::dnd::XdndAtoms;
 use super::keyboard::Keyboard;
 use crate::connection::ConnectionOps;
 use crate::os::x11::window::XWindowInner;
@@ -26,10 +27,17 @@ pub struct XConnection {
     pub atom_xsel_data: xcb::Atom,
     pub atom_targets: xcb::Atom,
     pub atom_clipboard: xcb::Atom,
+    pub atom_net_wm_ping: xcb::Atom,
+    pub atom_net_wm_sync_request: xcb::Atom,
+    pub atom_net_wm_sync_request_counter: xcb::Atom,
+    pub atom_xdnd: XdndAtoms,
     keysyms: *mut xcb_key_symbols_t,
     pub(crate) windows: RefCell<HashMap<xcb::xproto::Window, Arc<Mutex<XWindowInner>>>>,
     should_terminate: RefCell<bool>,
     pub(crate) shm_available: bool,
+    /// Whether the SYNC extension is available, which is needed in
+    /// order to support the _NET_WM_SYNC_REQUEST protocol
+    pub(crate) sync_available: bool,
     timers: RefCell<TimerList>,
     pub(crate) visual: xcb::xproto::Visualtype,
     pub(crate) depth: u8,
@@ -168,11 +176,42 @@ fn server_supports_shm() -> bool {
     }
 }
 
+/// Determine whether the server supports the SYNC extension, and
+/// initialize it if so
+fn server_supports_sync(conn: &xcb::Connection) -> bool {
+    // Using an extension that the server doesn't provide kills the
+    // connection, so check for it with a core request first
+    match xcb::query_extension(conn, "SYNC").get_reply() {
+        Ok(reply) if reply.present() => {}
+        _ => return false,
+    }
+    match xcb::sync::initialize(conn, 3, 1).get_reply() {
+        Ok(_) => true,
+        Err(err) => {
+            log::error!("While initializing the X SYNC extension: {}", err);
+            false
+        }
+    }
+}
+
 impl ConnectionOps for XConnection {
     fn terminate_message_loop(&self) {
         *self.should_terminate.borrow_mut() = true;
     }
 
+    fn beep(&self, volume: f32) -> bool {
+        // The percentage is relative to the base volume of the bell;
+        // 0 is the base volume and -100 is silent
+        let percent = ((volume.max(0.).min(1.) - 1.) * 100.) as i8;
+        xcb::bell(self.conn(), percent);
+        self.conn.flush();
+        true
+    }
+
+    fn has_primary_selection(&self) -> bool {
+        true
+    }
+
     fn run_message_loop(&self) -> anyhow::Result<()> {
         self.conn.flush();
 
@@ -336,10 +375,22 @@ impl XConnection {
         let atom_clipboard = xcb::intern_atom(&conn, false, "CLIPBOARD")
             .get_reply()?
             .atom();
+        let atom_net_wm_ping = xcb::intern_atom(&conn, false, "_NET_WM_PING")
+            .get_reply()?
+            .atom();
+        let atom_net_wm_sync_request = xcb::intern_atom(&conn, false, "_NET_WM_SYNC_REQUEST")
+            .get_reply()?
+            .atom();
+        let atom_net_wm_sync_request_counter =
+            xcb::intern_atom(&conn, false, "_NET_WM_SYNC_REQUEST_COUNTER")
+                .get_reply()?
+                .atom();
+        let atom_xdnd = XdndAtoms::new(&conn)?;
 
         let keysyms = unsafe { xcb_key_symbols_alloc((*conn).get_raw_conn()) };
 
         let shm_available = server_supports_shm();
+        let sync_available = server_supports_sync(&conn);
 
         let screen = conn
             .get_setup()
@@ -399,9 +450,14 @@ impl XConnection {
             atom_utf8_string,
             atom_xsel_data,
             atom_targets,
+            atom_net_wm_ping,
+            atom_net_wm_sync_request,
+            atom_net_wm_sync_request_counter,
+            atom_xdnd,
             windows: RefCell::new(HashMap::new()),
             should_terminate: RefCell::new(false),
             shm_available,
+            sync_available,
             timers: RefCell::new(TimerList::new()),
             depth,
             visual,
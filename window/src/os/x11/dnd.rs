@@ -0,0 +1,220 @@
+//! Accepts files that are dropped onto our windows by other
+//! applications, using the XDND protocol that is described at
+//! <https://freedesktop.org/wiki/Specifications/XDND/>.
+//! Only drags that offer a `text/uri-list` are accepted.  When one is
+//! dropped, the list is requested from the XdndSelection and arrives
+//! later as a SelectionNotify event, after which the source is told
+//! that the drop has finished.
+use super::connection::XConnection;
+use crate::os::uri_list::{parse_uri_list, URI_LIST_MIME_TYPE};
+use std::path::PathBuf;
+
+/// The version of the protocol that we implement
+pub const XDND_VERSION: u32 = 5;
+
+pub struct XdndAtoms {
+    pub aware: xcb::Atom,
+    pub enter: xcb::Atom,
+    pub position: xcb::Atom,
+    pub status: xcb::Atom,
+    pub leave: xcb::Atom,
+    pub drop: xcb::Atom,
+    pub finished: xcb::Atom,
+    pub selection: xcb::Atom,
+    pub type_list: xcb::Atom,
+    pub action_copy: xcb::Atom,
+    pub uri_list: xcb::Atom,
+}
+
+impl XdndAtoms {
+    pub fn new(conn: &xcb::Connection) -> anyhow::Result<Self> {
+        let intern = |name: &str| -> anyhow::Result<xcb::Atom> {
+            Ok(xcb::intern_atom(conn, false, name).get_reply()?.atom())
+        };
+        Ok(Self {
+            aware: intern("XdndAware")?,
+            enter: intern("XdndEnter")?,
+            position: intern("XdndPosition")?,
+            status: intern("XdndStatus")?,
+            leave: intern("XdndLeave")?,
+            drop: intern("XdndDrop")?,
+            finished: intern("XdndFinished")?,
+            selection: intern("XdndSelection")?,
+            type_list: intern("XdndTypeList")?,
+            action_copy: intern("XdndActionCopy")?,
+            uri_list: intern(URI_LIST_MIME_TYPE)?,
+        })
+    }
+}
+
+/// Tells the world that `window_id` accepts drops
+pub fn set_aware(conn: &XConnection, window_id: xcb::xproto::Window) {
+    xcb::change_property(
+        conn.conn(),
+        xcb::PROP_MODE_REPLACE as u8,
+        window_id,
+        conn.atom_xdnd.aware,
+        xcb::ATOM_ATOM,
+        32,
+        &[XDND_VERSION],
+    );
+}
+
+/// The state of a drag that is over one of our windows
+#[derive(Default)]
+pub struct DragState {
+    /// The window of the application that the drag comes from
+    source: Option<xcb::xproto::Window>,
+    /// Whether the source offers a `text/uri-list`
+    acceptable: bool,
+    /// The source of a drop whose list we have requested, and which
+    /// must be told once it has been received
+    dropped_by: Option<xcb::xproto::Window>,
+}
+
+fn send_to_source(
+    conn: &XConnection,
+    source: xcb::xproto::Window,
+    type_: xcb::Atom,
+    data: [u32; 5],
+) {
+    let event =
+        xcb::ClientMessageEvent::new(32, source, type_, xcb::ClientMessageData::from_data32(data));
+    xcb::send_event(conn.conn(), false, source, 0, &event);
+}
+
+/// Returns the types that are offered by the XdndEnter message `data`
+fn offered_types(conn: &XConnection, data: &[u32]) -> Vec<xcb::Atom> {
+    const MORE_THAN_THREE_TYPES: u32 = 1;
+    if data[1] & MORE_THAN_THREE_TYPES == 0 {
+        return data[2..5].iter().copied().filter(|&t| t != 0).collect();
+    }
+    match xcb::get_property(
+        conn.conn(),
+        false,
+        data[0],
+        conn.atom_xdnd.type_list,
+        xcb::ATOM_ATOM,
+        0,
+        1024,
+    )
+    .get_reply()
+    {
+        Ok(reply) => reply.value::<u32>().to_vec(),
+        Err(err) => {
+            log::error!("reading XdndTypeList: {}", err);
+            vec![]
+        }
+    }
+}
+
+impl DragState {
+    /// Handles `msg` if it is part of the protocol, returning false if
+    /// it is something else
+    pub fn client_message(
+        &mut self,
+        conn: &XConnection,
+        window_id: xcb::xproto::Window,
+        msg: &xcb::ClientMessageEvent,
+    ) -> bool {
+        let atoms = &conn.atom_xdnd;
+        let data = msg.data().data32();
+        let type_ = msg.type_();
+        if type_ == atoms.enter {
+            self.source = Some(data[0]);
+            self.acceptable = offered_types(conn, data).contains(&atoms.uri_list);
+        } else if type_ == atoms.position {
+            let source = data[0];
+            let (accept, action) = if self.acceptable && self.source == Some(source) {
+                (1, atoms.action_copy)
+            } else {
+                (0, xcb::NONE)
+            };
+            // An empty rectangle asks for a position message every time
+            // that the pointer moves
+            send_to_source(
+                conn,
+                source,
+                atoms.status,
+                [window_id, accept, 0, 0, action],
+            );
+        } else if type_ == atoms.leave {
+            self.source = None;
+            self.acceptable = false;
+        } else if type_ == atoms.drop {
+            let source = data[0];
+            let time = data[2];
+            if self.acceptable && self.source == Some(source) {
+                // The list is stored in a property of the same name
+                xcb::convert_selection(
+                    conn.conn(),
+                    window_id,
+                    atoms.selection,
+                    atoms.uri_list,
+                    atoms.selection,
+                    time,
+                );
+                self.dropped_by = Some(source);
+            } else {
+                send_to_source(conn, source, atoms.finished, [window_id, 0, 0, 0, 0]);
+            }
+            self.source = None;
+            self.acceptable = false;
+        } else {
+            return false;
+        }
+        conn.flush();
+        true
+    }
+
+    /// Handles the SelectionNotify for a drop, returning the paths of
+    /// the files that were dropped
+    pub fn selection_notify(
+        &mut self,
+        conn: &XConnection,
+        window_id: xcb::xproto::Window,
+        selection: &xcb::SelectionNotifyEvent,
+    ) -> Vec<PathBuf> {
+        let source = match self.dropped_by.take() {
+            Some(source) => source,
+            None => return vec![],
+        };
+        let atoms = &conn.atom_xdnd;
+
+        let paths = if selection.property() == xcb::NONE {
+            vec![]
+        } else {
+            match xcb::get_property(
+                conn.conn(),
+                true,
+                window_id,
+                selection.property(),
+                xcb::xproto::GET_PROPERTY_TYPE_ANY,
+                0,
+                u32::max_value() / 4,
+            )
+            .get_reply()
+            {
+                Ok(reply) => parse_uri_list(reply.value::<u8>()),
+                Err(err) => {
+                    log::error!("reading the dropped {}: {}", URI_LIST_MIME_TYPE, err);
+                    vec![]
+                }
+            }
+        };
+
+        let (accepted, action) = if paths.is_empty() {
+            (0, xcb::NONE)
+        } else {
+            (1, atoms.action_copy)
+        };
+        send_to_source(
+            conn,
+            source,
+            atoms.finished,
+            [window_id, accepted, action, 0, 0],
+        );
+        conn.flush();
+        paths
+    }
+}
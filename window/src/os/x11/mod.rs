@@ -1,6 +1,7 @@
 #![cfg(all(unix, not(target_os = "macos")))]
 pub mod bitmap;
 pub mod connection;
+pub mod dnd;
 pub mod keyboard;
 pub mod window;
 
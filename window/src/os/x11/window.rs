@@ -1,3 +1,4 @@
+use super::dnd::DragState;
 use super::*;
 use crate::bitmaps::*;
 use crate::connection::ConnectionOps;
@@ -32,11 +33,87 @@ impl Drop for XcbCursor {
 
 #[derive(Default)]
 struct CopyAndPaste {
-    owned: Option<String>,
+    /// The text that we own in the CLIPBOARD selection
+    owned_clipboard: Option<String>,
+    /// The text that we own in the PRIMARY selection
+    owned_primary: Option<String>,
     request: Option<Promise<String>>,
     time: u32,
 }
 
+impl CopyAndPaste {
+    fn owned(&self, clipboard: Clipboard) -> Option<&String> {
+        match clipboard {
+            Clipboard::Clipboard => self.owned_clipboard.as_ref(),
+            Clipboard::PrimarySelection => self.owned_primary.as_ref(),
+        }
+    }
+
+    fn owned_mut(&mut self, clipboard: Clipboard) -> &mut Option<String> {
+        match clipboard {
+            Clipboard::Clipboard => &mut self.owned_clipboard,
+            Clipboard::PrimarySelection => &mut self.owned_primary,
+        }
+    }
+}
+
+/// The state of the _NET_WM_SYNC_REQUEST protocol, through which the
+/// window manager learns when we have painted the frame that follows
+/// a resize, so that it can show the window at its new size without
+/// first showing a stale or empty frame
+struct SyncCounter {
+    counter: xcb::sync::Counter,
+    /// The value sent with the most recent sync request; it applies
+    /// to the ConfigureNotify that follows it
+    requested: Option<i64>,
+    /// The value to set the counter to once the frame for the new
+    /// size has been painted
+    after_paint: Option<i64>,
+}
+
+impl SyncCounter {
+    fn new(conn: &XConnection, window_id: xcb::xproto::Window) -> Self {
+        let counter = conn.conn().generate_id();
+        xcb::sync::create_counter(conn.conn(), counter, xcb::sync::Int64::new(0, 0));
+        xcb::change_property(
+            conn.conn(),
+            xcb::PROP_MODE_REPLACE as u8,
+            window_id,
+            conn.atom_net_wm_sync_request_counter,
+            xcb::ATOM_CARDINAL,
+            32,
+            &[counter],
+        );
+        Self {
+            counter,
+            requested: None,
+            after_paint: None,
+        }
+    }
+
+    /// Called when the window has been resized.  Returns true if the
+    /// resize was requested with a sync request, in which case the
+    /// counter is updated after the next paint.
+    fn configured(&mut self) -> bool {
+        match self.requested.take() {
+            Some(value) => {
+                self.after_paint = Some(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set(&self, conn: &XConnection, value: i64) {
+        xcb::sync::set_counter(
+            conn.conn(),
+            self.counter,
+            xcb::sync::Int64::new((value >> 32) as i32, value as u32),
+        );
+        conn.conn().flush();
+    }
+}
+
 pub(crate) struct XWindowInner {
     window_id: xcb::xproto::Window,
     conn: Weak<XConnection>,
@@ -50,6 +127,8 @@ pub(crate) struct XWindowInner {
     cursor: Option<MouseCursor>,
     cursors: HashMap<Option<MouseCursor>, XcbCursor>,
     copy_and_paste: CopyAndPaste,
+    sync_counter: Option<SyncCounter>,
+    drag: DragState,
     #[cfg(feature = "opengl")]
     gl_state: Option<Rc<glium::backend::Context>>,
 }
@@ -67,6 +146,9 @@ fn enclosing_boundary_with(a: &Rect, b: &Rect) -> Rect {
 impl Drop for XWindowInner {
     fn drop(&mut self) {
         if let Some(conn) = self.conn.upgrade() {
+            if let Some(sync) = self.sync_counter.take() {
+                xcb::sync::destroy_counter(conn.conn(), sync.counter);
+            }
             xcb::destroy_window(conn.conn(), self.window_id);
         }
     }
@@ -138,6 +220,19 @@ impl XWindowInner {
     }
 
     pub fn paint(&mut self) -> anyhow::Result<()> {
+        self.paint_impl()?;
+        // The frame for the new size has been presented; let the
+        // window manager know that it can show it
+        let conn = self.conn();
+        if let Some(sync) = self.sync_counter.as_mut() {
+            if let Some(value) = sync.after_paint.take() {
+                sync.set(&conn, value);
+            }
+        }
+        Ok(())
+    }
+
+    fn paint_impl(&mut self) -> anyhow::Result<()> {
         let window_dimensions =
             Rect::from_size(Size::new(self.width as isize, self.height as isize));
 
@@ -156,7 +251,7 @@ impl XWindowInner {
                     log::error!("opengl context was lost; should reinit");
                     drop(self.gl_state.take());
                     self.enable_opengl()?;
-                    return self.paint();
+                    return self.paint_impl();
                 }
 
                 self.expose.clear();
@@ -278,6 +373,8 @@ impl XWindowInner {
                     MouseCursor::Arrow => 132,
                     MouseCursor::Hand => 58,
                     MouseCursor::Text => 152,
+                    MouseCursor::SizeUpDown => 116,
+                    MouseCursor::SizeLeftRight => 108,
                 };
 
                 let cursor_id: xcb::ffi::xcb_cursor_t = conn.generate_id();
@@ -335,7 +432,19 @@ impl XWindowInner {
                     pixel_width: self.width as usize,
                     pixel_height: self.height as usize,
                     dpi: 96,
-                })
+                });
+                let awaiting_paint = self
+                    .sync_counter
+                    .as_mut()
+                    .map(SyncCounter::configured)
+                    .unwrap_or(false);
+                if awaiting_paint {
+                    // Paint the new size straight away, rather than on
+                    // the next paint interval, as the window manager is
+                    // waiting for us
+                    self.paint_all = true;
+                    self.paint()?;
+                }
             }
             xcb::KEY_PRESS | xcb::KEY_RELEASE => {
                 let key_press: &xcb::KeyPressEvent = unsafe { xcb::cast_event(event) };
@@ -429,8 +538,48 @@ impl XWindowInner {
             }
             xcb::CLIENT_MESSAGE => {
                 let msg: &xcb::ClientMessageEvent = unsafe { xcb::cast_event(event) };
-                if msg.data().data32()[0] == conn.atom_delete() && self.callbacks.can_close() {
-                    xcb::destroy_window(conn.conn(), self.window_id);
+                let data = msg.data().data32();
+                if self.drag.client_message(&conn, self.window_id, msg) {
+                    // Part of a drag and drop; the files that are dropped
+                    // arrive later in a SELECTION_NOTIFY
+                } else if msg.type_() != conn.atom_protocols {
+                    // Not a WM_PROTOCOLS message
+                } else if data[0] == conn.atom_delete() {
+                    if self.callbacks.can_close() {
+                        xcb::destroy_window(conn.conn(), self.window_id);
+                    }
+                } else if data[0] == conn.atom_net_wm_ping {
+                    // Bounce the message back to the root window to show
+                    // the window manager that we're still responsive
+                    let root = conn
+                        .conn()
+                        .get_setup()
+                        .roots()
+                        .nth(conn.screen_num() as usize)
+                        .map(|screen| screen.root());
+                    if let Some(root) = root {
+                        let reply = xcb::ClientMessageEvent::new(
+                            32,
+                            root,
+                            msg.type_(),
+                            xcb::ClientMessageData::from_data32([
+                                data[0], data[1], data[2], data[3], data[4],
+                            ]),
+                        );
+                        xcb::send_event(
+                            conn.conn(),
+                            false,
+                            root,
+                            xcb::EVENT_MASK_SUBSTRUCTURE_NOTIFY
+                                | xcb::EVENT_MASK_SUBSTRUCTURE_REDIRECT,
+                            &reply,
+                        );
+                        conn.conn().flush();
+                    }
+                } else if data[0] == conn.atom_net_wm_sync_request {
+                    if let Some(sync) = self.sync_counter.as_mut() {
+                        sync.requested = Some(i64::from(data[2]) | (i64::from(data[3]) << 32));
+                    }
                 }
             }
             xcb::DESTROY_NOTIFY => {
@@ -438,7 +587,7 @@ impl XWindowInner {
                 conn.windows.borrow_mut().remove(&self.window_id);
             }
             xcb::SELECTION_CLEAR => {
-                self.selection_clear()?;
+                self.selection_clear(unsafe { xcb::cast_event(event) })?;
             }
             xcb::SELECTION_REQUEST => {
                 self.selection_request(unsafe { xcb::cast_event(event) })?;
@@ -474,15 +623,19 @@ impl XWindowInner {
     /// that and vice versa.
     fn update_selection_owner(&mut self) {
         let conn = self.conn();
-        for &selection in &[xcb::ATOM_PRIMARY, conn.atom_clipboard] {
+        for &(selection, clipboard) in &[
+            (xcb::ATOM_PRIMARY, Clipboard::PrimarySelection),
+            (conn.atom_clipboard, Clipboard::Clipboard),
+        ] {
+            let owned = self.copy_and_paste.owned(clipboard).is_some();
             let current_owner = xcb::get_selection_owner(&conn, selection)
                 .get_reply()
                 .unwrap()
                 .owner();
-            if self.copy_and_paste.owned.is_none() && current_owner == self.window_id {
+            if !owned && current_owner == self.window_id {
                 // We don't have a selection but X thinks we do; disown it!
                 xcb::set_selection_owner(&conn, xcb::NONE, selection, self.copy_and_paste.time);
-            } else if self.copy_and_paste.owned.is_some() && current_owner != self.window_id {
+            } else if owned && current_owner != self.window_id {
                 // We have the selection but X doesn't think we do; assert it!
                 xcb::set_selection_owner(
                     &conn,
@@ -495,8 +648,22 @@ impl XWindowInner {
         conn.flush();
     }
 
-    fn selection_clear(&mut self) -> anyhow::Result<()> {
-        self.copy_and_paste.owned.take();
+    /// Maps a selection atom to the corresponding Clipboard
+    fn selection_clipboard(&self, selection: xcb::Atom) -> Option<Clipboard> {
+        if selection == xcb::ATOM_PRIMARY {
+            Some(Clipboard::PrimarySelection)
+        } else if selection == self.conn().atom_clipboard {
+            Some(Clipboard::Clipboard)
+        } else {
+            None
+        }
+    }
+
+    fn selection_clear(&mut self, clear: &xcb::SelectionClearEvent) -> anyhow::Result<()> {
+        // Another client now owns this selection
+        if let Some(clipboard) = self.selection_clipboard(clear.selection()) {
+            self.copy_and_paste.owned_mut(clipboard).take();
+        }
         self.copy_and_paste.request.take();
         self.update_selection_owner();
         Ok(())
@@ -545,7 +712,10 @@ impl XWindowInner {
             // We don't and won't do any conversion from UTF-8 to
             // whatever STRING represents; let's just assume that
             // the other end is going to handle it correctly.
-            if let Some(text) = self.copy_and_paste.owned.as_ref() {
+            let owned = self
+                .selection_clipboard(request.selection())
+                .and_then(|clipboard| self.copy_and_paste.owned(clipboard));
+            if let Some(text) = owned {
                 xcb::xproto::change_property(
                     &conn,
                     xcb::xproto::PROP_MODE_REPLACE as u8,
@@ -597,6 +767,14 @@ impl XWindowInner {
             selection.property()
         );
 
+        if selection.selection() == conn.atom_xdnd.selection {
+            let paths = self.drag.selection_notify(&conn, self.window_id, selection);
+            if !paths.is_empty() {
+                self.callbacks.dropped_files(paths);
+            }
+            return Ok(());
+        }
+
         if (selection.selection() == xcb::ATOM_PRIMARY
             || selection.selection() == conn.atom_clipboard)
             && selection.property() != xcb::NONE
@@ -782,6 +960,12 @@ impl XWindow {
                 buffer_image,
                 cursor: None,
                 cursors: HashMap::new(),
+                sync_counter: if conn.sync_available {
+                    Some(SyncCounter::new(&conn, window_id))
+                } else {
+                    None
+                },
+                drag: DragState::default(),
                 #[cfg(feature = "opengl")]
                 gl_state: None,
             }))
@@ -792,6 +976,10 @@ impl XWindow {
             libc::getpid() as u32
         });
 
+        let mut protocols = vec![conn.atom_delete, conn.atom_net_wm_ping];
+        if conn.sync_available {
+            protocols.push(conn.atom_net_wm_sync_request);
+        }
         xcb::change_property(
             &*conn,
             xcb::PROP_MODE_REPLACE as u8,
@@ -799,8 +987,9 @@ impl XWindow {
             conn.atom_protocols,
             4,
             32,
-            &[conn.atom_delete],
+            &protocols,
         );
+        dnd::set_aware(&conn, window_id);
 
         // window.lock().unwrap().disable_decorations()?;
 
@@ -831,6 +1020,21 @@ impl WindowOpsMut for XWindowInner {
     fn show(&mut self) {
         xcb::map_window(self.conn().conn(), self.window_id);
     }
+    fn focus(&mut self) {
+        // Ask the window manager to activate the window.  We identify
+        // ourselves as a pager, as this is only done in response to the
+        // user picking the window, and window managers are less likely
+        // to treat that as stealing the focus.
+        let conn = self.conn();
+        xcb_util::ewmh::request_change_active_window(
+            conn.ewmh_conn(),
+            conn.screen_num,
+            self.window_id,
+            2, // pager
+            xcb::CURRENT_TIME,
+            xcb::NONE,
+        );
+    }
     fn set_cursor(&mut self, cursor: Option<MouseCursor>) {
         XWindowInner::set_cursor(self, cursor).unwrap();
     }
@@ -935,6 +1139,13 @@ impl WindowOps for XWindow {
         })
     }
 
+    fn focus(&self) -> Future<()> {
+        XConnection::with_window_inner(self.0, |inner| {
+            inner.focus();
+            Ok(())
+        })
+    }
+
     fn set_cursor(&self, cursor: Option<MouseCursor>) -> Future<()> {
         XConnection::with_window_inner(self.0, move |inner| {
             let _ = inner.set_cursor(cursor);
@@ -1004,7 +1215,7 @@ impl WindowOps for XWindow {
         let mut promise = Some(promise);
         XConnection::with_window_inner(self.0, move |inner| {
             let mut promise = promise.take().unwrap();
-            if let Some(text) = inner.copy_and_paste.owned.as_ref() {
+            if let Some(text) = inner.copy_and_paste.owned(clipboard) {
                 promise.ok(text.to_owned());
 
                 // Cancel any outstanding promise from the other branch
@@ -1036,9 +1247,12 @@ impl WindowOps for XWindow {
     }
 
     /// Set some text in the clipboard
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         XConnection::with_window_inner(self.0, move |inner| {
-            inner.copy_and_paste.owned.replace(text.clone());
+            inner
+                .copy_and_paste
+                .owned_mut(clipboard)
+                .replace(text.clone());
             inner.update_selection_owner();
             Ok(())
         })
@@ -105,6 +105,22 @@ impl ConnectionOps for Connection {
             Self::Wayland(w) => w.run_message_loop(),
         }
     }
+    fn beep(&self, volume: f32) -> bool {
+        match self {
+            Self::X11(x) => x.beep(volume),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.beep(volume),
+        }
+    }
+
+    fn has_primary_selection(&self) -> bool {
+        match self {
+            Self::X11(x) => x.has_primary_selection(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.has_primary_selection(),
+        }
+    }
+
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F) {
         match self {
             Self::X11(x) => x.schedule_timer(interval, callback),
@@ -153,6 +169,14 @@ impl WindowOps for Window {
         }
     }
 
+    fn focus(&self) -> Future<()> {
+        match self {
+            Self::X11(x) => x.focus(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.focus(),
+        }
+    }
+
     fn set_cursor(&self, cursor: Option<MouseCursor>) -> Future<()> {
         match self {
             Self::X11(x) => x.set_cursor(cursor),
@@ -232,11 +256,11 @@ impl WindowOps for Window {
             Self::Wayland(w) => w.get_clipboard(clipboard),
         }
     }
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         match self {
-            Self::X11(x) => x.set_clipboard(text),
+            Self::X11(x) => x.set_clipboard(clipboard, text),
             #[cfg(feature = "wayland")]
-            Self::Wayland(w) => w.set_clipboard(text),
+            Self::Wayland(w) => w.set_clipboard(clipboard, text),
         }
     }
 }